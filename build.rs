@@ -0,0 +1,8 @@
+// Rebuild when migrations change so sqlx::migrate! embeds the latest files
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    // The gRPC service and its messages are generated from the protobuf definition
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/aibot/v1/aibot.proto").expect("Failed to compile the gRPC protos");
+}
@@ -0,0 +1,11 @@
+-- Knowledge bases a session retrieves from
+CREATE TABLE IF NOT EXISTS session_knowledge_bases (
+    session_id TEXT NOT NULL,
+    knowledge_base_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (session_id, knowledge_base_id),
+    FOREIGN KEY (session_id) REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_knowledge_bases_kb_id ON session_knowledge_bases(knowledge_base_id);
@@ -0,0 +1,34 @@
+-- OAuth-backed connectors that sync external documents into a knowledge base
+CREATE TABLE IF NOT EXISTS connectors (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    knowledge_base_id TEXT NOT NULL,
+    provider TEXT NOT NULL CHECK (provider IN ('notion', 'google_drive')),
+    -- JSON array of Notion page ids or Drive folder ids to sync
+    resource_ids TEXT NOT NULL DEFAULT '[]',
+    sync_interval_minutes BIGINT NOT NULL DEFAULT 60,
+    -- Sealed with SECRETS_KEY
+    access_token TEXT,
+    refresh_token TEXT,
+    token_expires_at TIMESTAMPTZ,
+    last_synced_at TIMESTAMPTZ,
+    last_sync_status TEXT,
+    last_sync_error TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+-- Which knowledge base document each external item was last ingested as
+CREATE TABLE IF NOT EXISTS connector_documents (
+    connector_id TEXT NOT NULL,
+    external_id TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    external_modified_at TEXT NOT NULL,
+    PRIMARY KEY (connector_id, external_id),
+    FOREIGN KEY (connector_id) REFERENCES connectors(id) ON DELETE CASCADE,
+    FOREIGN KEY (document_id) REFERENCES kb_documents(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_connectors_knowledge_base_id ON connectors(knowledge_base_id);
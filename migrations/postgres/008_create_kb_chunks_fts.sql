@@ -0,0 +1,3 @@
+-- Keyword index over knowledge base chunks for hybrid retrieval. Queries must use
+-- the same to_tsvector expression for the index to apply.
+CREATE INDEX IF NOT EXISTS idx_kb_chunks_fts ON kb_chunks USING GIN (to_tsvector('english', content));
@@ -0,0 +1,2 @@
+-- Sources the assistant was given for a reply, stored as a JSON array of citations
+ALTER TABLE messages ADD COLUMN citations TEXT;
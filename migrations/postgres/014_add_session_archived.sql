@@ -0,0 +1,2 @@
+-- Archived sessions are hidden from the session list but keep all of their data
+ALTER TABLE chat_sessions ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Keyword index over message content for full-text conversation search. Queries must
+-- use the same to_tsvector expression for the index to apply.
+CREATE INDEX IF NOT EXISTS idx_messages_fts ON messages USING GIN (to_tsvector('english', content));
@@ -0,0 +1,7 @@
+-- Deleted sessions and messages go to the trash first; NULL means not deleted.
+-- Trashed items are purged for good once they are older than the retention period.
+ALTER TABLE chat_sessions ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE messages ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_chat_sessions_deleted_at ON chat_sessions(deleted_at);
+CREATE INDEX IF NOT EXISTS idx_messages_deleted_at ON messages(deleted_at);
@@ -0,0 +1,2 @@
+-- Start of the newest message, shown under the title in the session list
+ALTER TABLE chat_sessions ADD COLUMN last_message_preview TEXT;
@@ -0,0 +1,10 @@
+-- Per-user retention settings; they can only tighten the instance-wide policy
+CREATE TABLE IF NOT EXISTS retention_policies (
+    user_id TEXT PRIMARY KEY,
+    -- Messages older than this many days are moved to the trash
+    message_retention_days BIGINT,
+    -- Only this many sessions are kept, most recently active first; pinned sessions are never removed
+    max_sessions BIGINT,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
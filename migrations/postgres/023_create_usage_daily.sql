@@ -0,0 +1,16 @@
+-- Daily usage rollups per user and model, rebuilt from messages by a background job
+-- so dashboards don't have to scan every message
+CREATE TABLE IF NOT EXISTS usage_daily (
+    day DATE NOT NULL,
+    user_id TEXT NOT NULL,
+    model_provider TEXT NOT NULL,
+    model_name TEXT NOT NULL,
+    user_messages BIGINT NOT NULL DEFAULT 0,
+    assistant_messages BIGINT NOT NULL DEFAULT 0,
+    tokens BIGINT NOT NULL DEFAULT 0,
+    -- USD estimate; NULL when the model has no known price
+    estimated_cost DOUBLE PRECISION,
+    PRIMARY KEY (day, user_id, model_provider, model_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_daily_user_id ON usage_daily(user_id, day);
@@ -0,0 +1,34 @@
+-- Tenants. Every user belongs to one organization; sessions and knowledge bases are
+-- owned by the organization of the user who created them.
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- OrganizationSettings as JSON
+    settings TEXT NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Provider API keys that replace the instance-wide ones for an organization's chats
+CREATE TABLE IF NOT EXISTS organization_api_keys (
+    org_id TEXT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    api_key TEXT NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (org_id, provider)
+);
+
+-- Everything that already exists moves into the default organization
+INSERT INTO organizations (id, name) VALUES ('default_org', 'Default');
+
+ALTER TABLE users ADD COLUMN org_id TEXT REFERENCES organizations(id);
+ALTER TABLE chat_sessions ADD COLUMN org_id TEXT REFERENCES organizations(id);
+ALTER TABLE knowledge_bases ADD COLUMN org_id TEXT REFERENCES organizations(id);
+
+UPDATE users SET org_id = 'default_org';
+UPDATE chat_sessions SET org_id = 'default_org';
+UPDATE knowledge_bases SET org_id = 'default_org';
+
+CREATE INDEX IF NOT EXISTS idx_users_org_id ON users(org_id);
+CREATE INDEX IF NOT EXISTS idx_chat_sessions_org_id ON chat_sessions(org_id);
+CREATE INDEX IF NOT EXISTS idx_knowledge_bases_org_id ON knowledge_bases(org_id);
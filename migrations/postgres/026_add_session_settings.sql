@@ -0,0 +1,2 @@
+-- Per-session generation settings as JSON (SessionSettings); NULL means the defaults
+ALTER TABLE chat_sessions ADD COLUMN settings TEXT;
@@ -0,0 +1,12 @@
+-- Provider API keys users bring themselves, used instead of their organization's or the
+-- instance's key. Keys are sealed with the instance's SECRETS_KEY.
+CREATE TABLE IF NOT EXISTS user_api_keys (
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    encrypted_key TEXT NOT NULL,
+    -- Identifies the key in the UI without revealing it
+    fingerprint TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, provider)
+);
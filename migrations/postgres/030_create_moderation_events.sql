@@ -0,0 +1,16 @@
+-- Messages and replies the content filter flagged. The flagged text itself isn't kept.
+CREATE TABLE IF NOT EXISTS moderation_events (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    -- "input" for the user's message, "output" for the model's reply
+    direction TEXT NOT NULL,
+    -- What was done about it: "block", "warn" or "log"
+    action TEXT NOT NULL,
+    -- JSON array of the categories that were flagged
+    categories TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_moderation_events_created_at ON moderation_events(created_at);
+CREATE INDEX IF NOT EXISTS idx_moderation_events_user_id ON moderation_events(user_id, created_at);
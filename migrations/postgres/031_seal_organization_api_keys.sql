@@ -0,0 +1,5 @@
+-- Organization keys are sealed with SECRETS_KEY, like users' own keys. Keys saved before
+-- this stay readable as they are and are sealed the next time the server starts with
+-- SECRETS_KEY set.
+ALTER TABLE organization_api_keys ADD COLUMN sealed BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE organization_api_keys ADD COLUMN fingerprint TEXT;
@@ -0,0 +1,6 @@
+-- Roles replace the admin flag: admin, member (the default) or guest
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'member';
+
+UPDATE users SET role = 'admin' WHERE is_admin = TRUE;
+
+ALTER TABLE users DROP COLUMN is_admin;
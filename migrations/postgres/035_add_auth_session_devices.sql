@@ -0,0 +1,5 @@
+-- What the security page shows about each signed-in browser. The address and last seen time
+-- are refreshed as the session is used.
+ALTER TABLE auth_sessions ADD COLUMN user_agent TEXT;
+ALTER TABLE auth_sessions ADD COLUMN ip_address TEXT;
+ALTER TABLE auth_sessions ADD COLUMN last_seen_at TIMESTAMPTZ;
@@ -0,0 +1,12 @@
+-- Failed sign-in attempts, counted to lock out an email address or a client address that
+-- guesses too often. Rows are removed once they are too old to count.
+CREATE TABLE IF NOT EXISTS login_failures (
+    id TEXT PRIMARY KEY,
+    -- The email that was tried, lowercased, whether or not an account has it
+    email TEXT NOT NULL,
+    ip_address TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_failures_email ON login_failures(email, created_at);
+CREATE INDEX IF NOT EXISTS idx_login_failures_ip_address ON login_failures(ip_address, created_at);
@@ -0,0 +1,18 @@
+-- Personal access tokens for scripts and other clients. Like login sessions, only the
+-- SHA-256 digest of a token is stored.
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    -- The start of the token, so users can tell their tokens apart
+    token_prefix TEXT NOT NULL,
+    -- "read" or "write"
+    scope TEXT NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    -- NULL for tokens that don't expire
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user_id ON api_tokens(user_id);
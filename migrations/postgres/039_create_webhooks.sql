@@ -0,0 +1,31 @@
+-- Outbound webhooks registered by admins, and every delivery made to them
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    -- Comma-separated event names, like "message.created,session.created"
+    events TEXT NOT NULL,
+    -- Signing secret, sealed with SECRETS_KEY
+    secret TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id TEXT PRIMARY KEY,
+    webhook_id TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    -- The JSON body, so retries send exactly what the first attempt did
+    payload TEXT NOT NULL,
+    -- "pending", "delivered" or "failed"
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- Of the last attempt
+    response_status INTEGER,
+    error TEXT,
+    -- NULL once the delivery has succeeded or run out of attempts
+    next_attempt_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_next_attempt_at ON webhook_deliveries(next_attempt_at);
@@ -0,0 +1,20 @@
+-- Prompts sent on a schedule, each reply added to a session of the user's
+CREATE TABLE IF NOT EXISTS schedules (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    prompt TEXT NOT NULL,
+    -- A page whose text is attached to the prompt each time it runs
+    fetch_url TEXT,
+    interval_minutes BIGINT NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    -- Email the reply to the user
+    notify BOOLEAN NOT NULL DEFAULT FALSE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_run_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_schedules_next_run_at ON schedules(enabled, next_run_at);
+CREATE INDEX IF NOT EXISTS idx_schedules_session_id ON schedules(session_id);
@@ -0,0 +1,20 @@
+-- Users' own automations: when something happens in their chats and it matches the
+-- condition, the action runs
+CREATE TABLE IF NOT EXISTS rules (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- "message_created" or "session_created"
+    trigger_event TEXT NOT NULL,
+    -- JSON; see RuleCondition
+    condition_json TEXT NOT NULL,
+    -- JSON; see RuleAction
+    action_json TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_fired_at TIMESTAMPTZ,
+    -- Why the action failed the last time it ran, if it did
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rules_user_id ON rules(user_id, trigger_event);
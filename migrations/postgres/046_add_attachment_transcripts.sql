@@ -0,0 +1,3 @@
+-- What was said in a voice note, kept so the note reads the same each time it is sent as
+-- context or re-attached; NULL when it wasn't transcribed
+ALTER TABLE file_attachments ADD COLUMN transcript TEXT;
@@ -0,0 +1,19 @@
+-- Maps application ids onto rows of the sqlite-vec index. The vec0 virtual table
+-- itself is created at startup because its column width depends on the embedding model.
+CREATE TABLE IF NOT EXISTS vector_entries (
+    rowid INTEGER PRIMARY KEY AUTOINCREMENT,
+    collection TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    metadata TEXT NOT NULL DEFAULT '{}',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(collection, item_id)
+);
+
+-- Records the dimension the index was built with so a model change is detected
+CREATE TABLE IF NOT EXISTS vector_store_meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_vector_entries_collection ON vector_entries(collection);
@@ -0,0 +1,2 @@
+-- Incognito sessions neither read nor write user memory
+ALTER TABLE chat_sessions ADD COLUMN incognito BOOLEAN NOT NULL DEFAULT FALSE;
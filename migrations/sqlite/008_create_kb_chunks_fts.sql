@@ -0,0 +1,24 @@
+-- Keyword index over knowledge base chunks for hybrid retrieval. `_` is kept inside
+-- tokens so identifiers like snake_case_names match as a whole.
+CREATE VIRTUAL TABLE IF NOT EXISTS kb_chunks_fts USING fts5(
+    content,
+    content='kb_chunks',
+    content_rowid='rowid',
+    tokenize="unicode61 tokenchars '_'"
+);
+
+CREATE TRIGGER IF NOT EXISTS kb_chunks_fts_insert AFTER INSERT ON kb_chunks BEGIN
+    INSERT INTO kb_chunks_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS kb_chunks_fts_delete AFTER DELETE ON kb_chunks BEGIN
+    INSERT INTO kb_chunks_fts(kb_chunks_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS kb_chunks_fts_update AFTER UPDATE ON kb_chunks BEGIN
+    INSERT INTO kb_chunks_fts(kb_chunks_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+    INSERT INTO kb_chunks_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
+
+-- Index chunks ingested before this migration
+INSERT INTO kb_chunks_fts(kb_chunks_fts) VALUES ('rebuild');
@@ -0,0 +1,20 @@
+-- Websites crawled into a knowledge base, kept so they can be re-crawled on a schedule
+CREATE TABLE IF NOT EXISTS crawl_sources (
+    id TEXT PRIMARY KEY,
+    knowledge_base_id TEXT NOT NULL,
+    start_url TEXT NOT NULL,
+    max_depth INTEGER NOT NULL,
+    max_pages INTEGER NOT NULL,
+    same_domain_only BOOLEAN NOT NULL DEFAULT TRUE,
+    -- NULL means the site is only crawled on demand
+    sync_interval_minutes INTEGER,
+    last_synced_at DATETIME,
+    last_sync_status TEXT,
+    last_sync_error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_crawl_sources_knowledge_base_id ON crawl_sources(knowledge_base_id);
+CREATE INDEX IF NOT EXISTS idx_kb_documents_source_uri ON kb_documents(knowledge_base_id, source_uri);
@@ -0,0 +1,9 @@
+-- When a message was added to the conversation search index; NULL means not yet indexed
+ALTER TABLE messages ADD COLUMN embedded_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_messages_embedded_at ON messages(embedded_at);
+
+-- Failed attempts to add a message to the index, and when the last one failed; messages that
+-- keep failing are retried later and eventually left out
+ALTER TABLE messages ADD COLUMN embed_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN embed_failed_at DATETIME;
@@ -0,0 +1,14 @@
+-- Values a memory held before a conflicting fact replaced them
+CREATE TABLE IF NOT EXISTS memory_history (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    memory_key TEXT NOT NULL,
+    memory_value TEXT NOT NULL,
+    confidence REAL NOT NULL,
+    -- When the superseded value was first recorded
+    recorded_at DATETIME NOT NULL,
+    superseded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_memory_history_user_key ON memory_history(user_id, memory_key);
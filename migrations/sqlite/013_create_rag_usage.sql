@@ -0,0 +1,9 @@
+-- Running per-user counters for retrieval-augmented generation; stored vector
+-- counts are computed from kb_chunks instead of being tracked here
+CREATE TABLE IF NOT EXISTS rag_usage (
+    user_id TEXT PRIMARY KEY,
+    embedded_tokens INTEGER NOT NULL DEFAULT 0,
+    retrievals INTEGER NOT NULL DEFAULT 0,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Folders group a user's sessions in the sidebar
+CREATE TABLE IF NOT EXISTS session_folders (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_folders_user_id ON session_folders(user_id);
+
+-- Deleting a folder moves its sessions back to the top level
+ALTER TABLE chat_sessions ADD COLUMN folder_id TEXT REFERENCES session_folders(id) ON DELETE SET NULL;
+-- Pinned sessions are listed before all others
+ALTER TABLE chat_sessions ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
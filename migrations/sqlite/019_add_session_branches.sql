@@ -0,0 +1,7 @@
+-- A branch is a session forked from a message of another session. Purging the
+-- parent turns its branches into standalone sessions.
+ALTER TABLE chat_sessions ADD COLUMN parent_session_id TEXT REFERENCES chat_sessions(id) ON DELETE SET NULL;
+-- The message in the parent session the branch continues from
+ALTER TABLE chat_sessions ADD COLUMN branch_message_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_chat_sessions_parent_session_id ON chat_sessions(parent_session_id);
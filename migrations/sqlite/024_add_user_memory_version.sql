@@ -0,0 +1,2 @@
+-- Bumped each time a memory's value changes, so an edit made from a stale copy can be rejected
+ALTER TABLE user_memory ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,14 @@
+-- Argon2 hash in PHC string format; NULL for users that can't sign in with a password
+ALTER TABLE users ADD COLUMN password_hash TEXT;
+
+-- Signed-in browsers. The session cookie carries a random token and only its SHA-256
+-- digest is stored, so a leaked table can't be used to sign in.
+CREATE TABLE IF NOT EXISTS auth_sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_user_id ON auth_sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_expires_at ON auth_sessions(expires_at);
@@ -0,0 +1,5 @@
+-- Admins can manage users and see instance-wide usage, provider health and configuration
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The first account registered on an instance takes over the default user and runs it
+UPDATE users SET is_admin = TRUE WHERE id = 'default_user' AND password_hash IS NOT NULL;
@@ -0,0 +1,22 @@
+-- TOTP two-factor authentication. The secret is sealed with the instance's SECRETS_KEY and
+-- only checked at login once `enabled` is set by confirming a first code.
+CREATE TABLE IF NOT EXISTS user_two_factor (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    encrypted_secret TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- The last 30-second time step a code was accepted for, so a code can't be used twice
+    last_used_step BIGINT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    enabled_at DATETIME
+);
+
+-- One-time codes for signing in without the authenticator app, stored as digests
+CREATE TABLE IF NOT EXISTS two_factor_backup_codes (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_two_factor_backup_codes_user_id ON two_factor_backup_codes(user_id);
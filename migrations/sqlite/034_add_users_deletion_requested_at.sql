@@ -0,0 +1,3 @@
+-- Set when a user asks for their account to be deleted; it is purged once the grace period
+-- has passed unless they cancel
+ALTER TABLE users ADD COLUMN deletion_requested_at DATETIME;
@@ -0,0 +1,11 @@
+-- The session each Matrix user is chatting in, per room. Everyone in a room gets their own
+-- session, so members of a group room don't share a conversation history with the bot.
+CREATE TABLE IF NOT EXISTS matrix_rooms (
+    room_id TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    updated_at DATETIME NOT NULL,
+    PRIMARY KEY (room_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_matrix_rooms_session_id ON matrix_rooms(session_id);
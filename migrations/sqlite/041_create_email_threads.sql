@@ -0,0 +1,10 @@
+-- The session each email of a conversation by email belongs to, by the email's Message-ID.
+-- Both the emails received and the replies sent are recorded, so a follow-up is threaded
+-- into its session whichever of them it answers.
+CREATE TABLE IF NOT EXISTS email_threads (
+    message_id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_threads_session_id ON email_threads(session_id);
@@ -0,0 +1,33 @@
+-- Batches of prompts run in the background against one model, and each prompt's result
+CREATE TABLE IF NOT EXISTS batch_jobs (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    model_provider TEXT NOT NULL,
+    model_name TEXT NOT NULL,
+    -- How many prompts are sent to the model at once
+    concurrency INTEGER NOT NULL,
+    -- "pending", "running", "completed" or "failed"
+    status TEXT NOT NULL,
+    -- Why the whole job failed; prompts that fail on their own are recorded with the prompt
+    error TEXT,
+    created_at DATETIME NOT NULL,
+    finished_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_batch_jobs_user_id ON batch_jobs(user_id, created_at);
+
+CREATE TABLE IF NOT EXISTS batch_items (
+    job_id TEXT NOT NULL REFERENCES batch_jobs(id) ON DELETE CASCADE,
+    -- Position in the submitted file, from 0
+    line INTEGER NOT NULL,
+    -- The caller's own ID for the prompt, given back with its result
+    custom_id TEXT NOT NULL,
+    prompt TEXT NOT NULL,
+    system_prompt TEXT,
+    -- "pending", "completed" or "failed"
+    status TEXT NOT NULL,
+    output TEXT,
+    error TEXT,
+    tokens_used INTEGER,
+    PRIMARY KEY (job_id, line)
+);
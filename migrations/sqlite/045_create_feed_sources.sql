@@ -0,0 +1,34 @@
+-- RSS and Atom feeds polled into a knowledge base
+CREATE TABLE IF NOT EXISTS feed_sources (
+    id TEXT PRIMARY KEY,
+    knowledge_base_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    title TEXT,
+    sync_interval_minutes INTEGER NOT NULL,
+    -- The model that writes the daily digest; NULL means no digest
+    digest_model_provider TEXT,
+    digest_model_name TEXT,
+    last_digest_at DATETIME,
+    last_synced_at DATETIME,
+    last_sync_status TEXT,
+    last_sync_error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+-- Items already indexed from each feed, so polling only picks up new ones
+CREATE TABLE IF NOT EXISTS feed_items (
+    feed_source_id TEXT NOT NULL,
+    guid TEXT NOT NULL,
+    title TEXT NOT NULL,
+    link TEXT,
+    summary TEXT NOT NULL,
+    published_at DATETIME,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (feed_source_id, guid),
+    FOREIGN KEY (feed_source_id) REFERENCES feed_sources(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_feed_sources_knowledge_base_id ON feed_sources(knowledge_base_id);
+CREATE INDEX IF NOT EXISTS idx_feed_items_created_at ON feed_items(feed_source_id, created_at);
@@ -0,0 +1,33 @@
+#!/bin/sh
+# Copies the browser libraries the app loads into public/vendor, so they are served from
+# its own origin rather than a CDN. npm checks each package against the registry's
+# integrity hash. Run it once before building, and again after changing a version here.
+set -eu
+
+cd "$(dirname "$0")/.."
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+# Unpacks the npm package `$1` into $work/package
+unpack() {
+    rm -rf "$work/package"
+    tarball=$(npm pack --silent --pack-destination "$work" "$1")
+    tar -xzf "$work/$tarball" -C "$work"
+}
+
+rm -rf public/vendor
+
+# KaTeX typesets math in replies
+unpack katex@0.16.11
+mkdir -p public/vendor/katex
+cp -R "$work/package/dist/katex.min.js" "$work/package/dist/katex.min.css" "$work/package/dist/fonts" public/vendor/katex/
+
+# Mermaid draws `mermaid` code blocks as diagrams
+unpack mermaid@11.4.1
+mkdir -p public/vendor/mermaid
+cp "$work/package/dist/mermaid.min.js" public/vendor/mermaid/
+
+# PDF.js draws the pages of PDF attachments in their preview
+unpack pdfjs-dist@3.11.174
+mkdir -p public/vendor/pdfjs
+cp "$work/package/build/pdf.min.js" "$work/package/build/pdf.worker.min.js" public/vendor/pdfjs/
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use crate::{api::AppState, export::EXPORT_VERSION, knowledge_base, models::*, trash};
+
+// Builds the "download my data" zip: the profile and knowledge bases in account.json, one
+// JSON export per session (archived and trashed ones too), memory.json and every attachment
+// file under attachments/
+pub async fn export_archive(state: &AppState, user_id: &str) -> Result<Vec<u8>> {
+    let user = state.db.get_user(user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let account = AccountExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        user,
+        knowledge_bases: state.db.get_user_knowledge_bases(user_id).await?,
+    };
+    zip.start_file("account.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&account)?)?;
+
+    for session in state.db.get_all_user_sessions(user_id).await? {
+        let name = format!("sessions/{}.json", session.id);
+        let export = crate::export::session_export(state, session).await?;
+        zip.start_file(name, options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&export)?)?;
+    }
+
+    let memories = state.db.get_user_memory(user_id).await?;
+    zip.start_file("memory.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&crate::memory::export_memories(&memories))?)?;
+
+    let mut archived_paths = HashSet::new();
+    for attachment in state.db.get_all_user_attachments(user_id).await? {
+        // Files that were re-attached share a path; only archive them once
+        if !archived_paths.insert(attachment.file_path.clone()) {
+            continue;
+        }
+        let data = match tokio::fs::read(&attachment.file_path).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Skipping missing attachment {}: {}", attachment.file_path, e);
+                continue;
+            }
+        };
+        zip.start_file(format!("attachments/{}", crate::export::archive_entry_name(&attachment)), options)?;
+        zip.write_all(&data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+// When the account will be purged, if its owner asked for it to be deleted
+pub async fn deletion_scheduled_at(state: &AppState, user_id: &str) -> Result<Option<DateTime<Utc>>> {
+    Ok(state.db.get_deletion_requested_at(user_id).await?
+        .map(|requested_at| requested_at + Duration::days(state.account_deletion_grace_days)))
+}
+
+// Schedules the account for deletion once the grace period is over. Takes the password so a
+// stolen login session can't do it. Returns when it will be purged.
+pub async fn request_deletion(state: &AppState, user: &User, password: &str) -> Result<DateTime<Utc>> {
+    let password_hash = state.db.get_password_hash(&user.id).await?
+        .ok_or_else(|| anyhow::anyhow!("Only accounts with a password can be deleted this way"))?;
+    if !crate::auth::verify_password(password, &password_hash) {
+        return Err(anyhow::anyhow!("Incorrect password"));
+    }
+    if user.role == Role::Admin && state.db.count_admins().await? <= 1 {
+        return Err(anyhow::anyhow!("Make someone else an admin before deleting the last admin account"));
+    }
+
+    if state.db.get_deletion_requested_at(&user.id).await?.is_none() {
+        state.db.set_deletion_requested_at(&user.id, Some(Utc::now())).await?;
+    }
+    deletion_scheduled_at(state, &user.id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))
+}
+
+pub async fn cancel_deletion(state: &AppState, user_id: &str) -> Result<()> {
+    if state.db.get_deletion_requested_at(user_id).await?.is_none() {
+        return Err(anyhow::anyhow!("This account isn't scheduled for deletion"));
+    }
+    state.db.set_deletion_requested_at(user_id, None).await?;
+    Ok(())
+}
+
+// Permanently deletes a user: their sessions with messages, search index entries and files,
+// their knowledge bases with documents and vectors, then the user row, which takes memory,
+// keys, connectors, tags, folders and login sessions with it
+pub async fn purge(state: &AppState, user_id: &str) -> Result<()> {
+    for session in state.db.get_all_user_sessions(user_id).await? {
+        trash::purge_session(state, &session).await?;
+    }
+    for kb in state.db.get_user_knowledge_bases(user_id).await? {
+        knowledge_base::delete_knowledge_base(state, &kb.id).await?;
+    }
+    state.db.delete_user(user_id).await?;
+    crate::audit::record(state, user_id, AuditAction::AccountDeleted, None, None).await
+}
+
+// Purges every account whose grace period is over. Returns how many were purged.
+pub async fn purge_due(state: &AppState) -> Result<usize> {
+    let cutoff = Utc::now() - Duration::days(state.account_deletion_grace_days);
+    let user_ids = state.db.get_users_due_for_deletion(cutoff).await?;
+    for user_id in &user_ids {
+        purge(state, user_id).await?;
+    }
+    Ok(user_ids.len())
+}
@@ -2,13 +2,18 @@ use anyhow::Result;
 use async_stream::stream;
 use futures::Stream;
 use serde_json::{json, Value};
-use std::collections::HashMap;
 use tokio::sync::RwLock;
 use crate::models::*;
+use crate::rag::RetrievedChunk;
+use crate::redaction::{redact_secrets, Redactor};
+
+const MAX_TITLE_CHARS: usize = 60;
 
 pub struct AIService {
-    clients: RwLock<HashMap<AIProvider, String>>, // Store API keys/URLs
-    config: AIServiceConfig,
+    // Replaced whole when the configuration is reloaded. Requests take a copy when they
+    // start, so those in flight finish with the keys they began with.
+    config: RwLock<AIServiceConfig>,
+    http: reqwest::Client,
 }
 
 #[derive(Clone)]
@@ -18,6 +23,23 @@ pub struct AIServiceConfig {
     pub gemini_api_key: Option<String>,
     pub openrouter_api_key: Option<String>,
     pub ollama_base_url: String,
+    // Providers whose prompts have personal data and secrets replaced with placeholders,
+    // by name (e.g. "openai")
+    pub redact_providers: Vec<String>,
+}
+
+// Per-request settings that override the service's defaults
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    // Replaces the configured key, e.g. with an organization's own key
+    pub api_key: Option<String>,
+    // None uses the provider's default
+    pub temperature: Option<f32>,
+    // Replaces the default assistant instructions
+    pub system_prompt: Option<String>,
+    // Receives the reply a piece at a time as the provider writes it. The whole reply is
+    // still returned at the end.
+    pub deltas: Option<tokio::sync::mpsc::UnboundedSender<String>>,
 }
 
 impl Default for AIServiceConfig {
@@ -28,36 +50,43 @@ impl Default for AIServiceConfig {
             gemini_api_key: None,
             openrouter_api_key: None,
             ollama_base_url: "http://localhost:11434".to_string(),
+            redact_providers: ["openai", "anthropic", "gemini", "openrouter"].map(String::from).to_vec(),
         }
     }
 }
 
-impl AIService {
-    pub async fn new(config: AIServiceConfig) -> Result<Self> {
-        let mut clients = HashMap::new();
-        
-        // Store configuration for each provider
-        if config.openai_api_key.is_some() {
-            clients.insert(AIProvider::OpenAI, config.openai_api_key.clone().unwrap());
-        }
-        if config.anthropic_api_key.is_some() {
-            clients.insert(AIProvider::Anthropic, config.anthropic_api_key.clone().unwrap());
-        }
-        if config.gemini_api_key.is_some() {
-            clients.insert(AIProvider::Gemini, config.gemini_api_key.clone().unwrap());
-        }
-        if config.openrouter_api_key.is_some() {
-            clients.insert(AIProvider::OpenRouter, config.openrouter_api_key.clone().unwrap());
+impl AIServiceConfig {
+    // The instance's API key for a provider, or Ollama's URL, as it doesn't need a key
+    fn credential(&self, provider: &AIProvider) -> Option<&str> {
+        match provider {
+            AIProvider::OpenAI => self.openai_api_key.as_deref(),
+            AIProvider::Anthropic => self.anthropic_api_key.as_deref(),
+            AIProvider::Gemini => self.gemini_api_key.as_deref(),
+            AIProvider::OpenRouter => self.openrouter_api_key.as_deref(),
+            AIProvider::Ollama => Some(&self.ollama_base_url),
         }
-        // Ollama doesn't need an API key
-        clients.insert(AIProvider::Ollama, config.ollama_base_url.clone());
+    }
+}
 
+impl AIService {
+    pub async fn new(config: AIServiceConfig) -> Result<Self> {
         Ok(Self {
-            clients: RwLock::new(clients),
-            config,
+            config: RwLock::new(config),
+            http: reqwest::Client::new(),
         })
     }
 
+    async fn config(&self) -> AIServiceConfig {
+        self.config.read().await.clone()
+    }
+
+    // Swaps in new provider keys and URLs; chats already under way aren't affected
+    pub async fn reconfigure(&self, config: AIServiceConfig) {
+        *self.config.write().await = config;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, fields(provider = %provider, model = model_name))]
     pub async fn chat(
         &self,
         provider: AIProvider,
@@ -65,16 +94,26 @@ impl AIService {
         messages: Vec<Message>,
         user_memory: &[UserMemory],
         files: &[FileUpload],
+        knowledge: &[RetrievedChunk],
+        options: &ChatOptions,
     ) -> Result<ChatResponse> {
-        let clients = self.clients.read().await;
+        let config = self.config().await;
         
         // Check if provider is available
-        if !clients.contains_key(&provider) {
+        if options.api_key.is_none() && config.credential(&provider).is_none() {
             return Err(anyhow::anyhow!("Provider {:?} not available", provider));
         }
 
+        // Personal data and secrets are swapped for placeholders before the provider sees
+        // them, and swapped back in the reply
+        let mut redactor = config.redact_providers.contains(&provider.to_string()).then(Redactor::default);
+        let mut redact = |text: &str| match redactor.as_mut() {
+            Some(redactor) => redactor.redact(text),
+            None => text.to_string(),
+        };
+
         // Build system prompt with user memory
-        let system_prompt = self.build_system_prompt(user_memory);
+        let system_prompt = redact(&self.build_system_prompt(options.system_prompt.as_deref(), user_memory, knowledge));
         
         // Convert messages to the format expected by the provider
         let mut formatted_messages = vec![];
@@ -91,9 +130,9 @@ impl AIService {
             };
             
             let content = if !files.is_empty() && msg.role == MessageRole::User {
-                self.build_content_with_files(&msg.content, files)?
+                redact(&self.build_content_with_files(&msg.content, files)?)
             } else {
-                msg.content.clone()
+                redact(&msg.content)
             };
             
             formatted_messages.push(json!({
@@ -102,23 +141,89 @@ impl AIService {
             }));
         }
 
+        // The body a real provider request would send
+        let request = json!({
+            "model": model_name,
+            "messages": formatted_messages,
+            "temperature": options.temperature,
+        });
+        // Keys never reach the logs, whether or not the provider gets redacted prompts
+        let credential = options.api_key.as_deref().or_else(|| config.credential(&provider)).unwrap_or_default();
+        tracing::debug!(request = %redact_secrets(&request.to_string(), &[credential]), "Provider request");
+        if let Some(redactor) = redactor.as_ref().filter(|redactor| !redactor.is_empty()) {
+            tracing::debug!("Redacted {} values from the request to {}", redactor.len(), provider);
+        }
+
         // For now, return a mock response
         // In a real implementation, you'd make HTTP requests to the respective APIs
         let mock_response = format!("This is a mock response from {} using model {}. You said: {}", 
             provider.to_string(), model_name, 
-            messages.last().map(|m| &m.content).unwrap_or(&"".to_string()));
+            formatted_messages.last().and_then(|m| m["content"].as_str()).unwrap_or_default());
+        tracing::debug!(response = %redact_secrets(&mock_response, &[credential]), "Provider response");
 
+        let restore = |text: &str| match redactor.as_ref() {
+            Some(redactor) => redactor.restore(text),
+            None => text.to_string(),
+        };
+        // The mock writes its reply a word at a time. Placeholders hold no spaces, so each
+        // piece can be restored on its own.
+        if let Some(deltas) = &options.deltas {
+            for piece in mock_response.split_inclusive(' ') {
+                // Nobody listening any more isn't a reason to stop the reply
+                let _ = deltas.send(restore(piece));
+                tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+            }
+        }
+        let suggested_questions = self.generate_suggested_questions(&mock_response, &messages, user_memory).await?;
         Ok(ChatResponse {
             message_id: uuid::Uuid::new_v4().to_string(),
-            content: mock_response,
+            content: restore(&mock_response),
             reasoning: Some("This is a mock reasoning process.".to_string()),
-            suggested_questions: self.generate_suggested_questions(&mock_response, &messages, user_memory).await?,
+            suggested_questions: suggested_questions.iter().map(|question| restore(question)).collect(),
             model_provider: provider.to_string(),
             model_name: model_name.to_string(),
             tokens_used: Some(150),
+            citations: knowledge.iter().map(|k| k.citation.clone()).collect(),
+            moderation_warning: None,
         })
     }
 
+    // Asks the model for a short title summarising the opening exchange of a conversation
+    pub async fn generate_title(
+        &self,
+        provider: AIProvider,
+        api_key: Option<&str>,
+        model_name: &str,
+        user_message: &str,
+        assistant_reply: &str,
+    ) -> Result<String> {
+        let prompt = format!(
+            "Write a title of at most six words for a conversation that starts like this. Reply with the title only.\n\nUser: {}\n\nAssistant: {}",
+            user_message, assistant_reply
+        );
+        let request = Message::new(String::new(), MessageRole::User, prompt);
+        let options = ChatOptions { api_key: api_key.map(str::to_string), ..Default::default() };
+        let response = self.chat(provider, model_name, vec![request], &[], &[], &[], &options).await?;
+
+        let title: String = response.content
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'' || c == '#' || c == '*')
+            .trim()
+            .chars()
+            .take(MAX_TITLE_CHARS)
+            .collect();
+
+        // Fall back to the start of the user's message if the model gave nothing usable
+        if title.is_empty() {
+            Ok(user_message.chars().take(MAX_TITLE_CHARS).collect::<String>().trim().to_string())
+        } else {
+            Ok(title)
+        }
+    }
+
     pub async fn chat_stream(
         &self,
         provider: AIProvider,
@@ -138,8 +243,10 @@ impl AIService {
         })
     }
 
-    fn build_system_prompt(&self, user_memory: &[UserMemory]) -> String {
-        let mut prompt = String::from("You are a helpful AI assistant. ");
+    fn build_system_prompt(&self, instructions: Option<&str>, user_memory: &[UserMemory], knowledge: &[RetrievedChunk]) -> String {
+        let mut prompt = instructions
+            .map(|instructions| format!("{}\n", instructions.trim()))
+            .unwrap_or_else(|| String::from("You are a helpful AI assistant. "));
         
         if !user_memory.is_empty() {
             prompt.push_str("\n\nUser context and preferences:\n");
@@ -149,9 +256,29 @@ impl AIService {
             prompt.push_str("\nPlease remember and use this information in our conversation.\n");
         }
         
-        prompt.push_str("\nAlways provide helpful, accurate, and engaging responses. ");
-        prompt.push_str("If you're not sure about something, say so. ");
-        prompt.push_str("You can process images, PDFs, and other files when provided.");
+        if !knowledge.is_empty() {
+            prompt.push_str("\n\nRelevant excerpts from the user's knowledge base:\n");
+            prompt.push_str(crate::injection_guard::GUARD_INSTRUCTIONS);
+            prompt.push('\n');
+            for chunk in knowledge {
+                // Excerpts may come from crawled pages or connected services, so they're
+                // fenced off and stripped of anything addressed to the model
+                prompt.push_str(&format!(
+                    "\n[{}] {} ({})\n{}\n",
+                    chunk.citation.index, chunk.citation.title, chunk.citation.source_uri,
+                    crate::injection_guard::guard(&chunk.citation.source_uri, &chunk.content)
+                ));
+            }
+            prompt.push_str("\nWhen you use information from an excerpt, cite it with its marker, e.g. [1]. ");
+            prompt.push_str("If the excerpts don't answer the question, say so rather than guessing.\n");
+        }
+
+        // Custom instructions stand on their own
+        if instructions.is_none() {
+            prompt.push_str("\nAlways provide helpful, accurate, and engaging responses. ");
+            prompt.push_str("If you're not sure about something, say so. ");
+            prompt.push_str("You can process images, PDFs, and other files when provided.");
+        }
         
         prompt
     }
@@ -176,7 +303,14 @@ impl AIService {
                         full_content.push_str(&text);
                     }
                 }
-                "text/" => {
+                audio if audio.starts_with("audio/") => {
+                    // Voice notes are passed on as their transcript when one is available
+                    full_content.push_str(&format!("\n\n[Voice note: {}]\n", file.name));
+                    if let Some(transcript) = &file.transcript {
+                        full_content.push_str(transcript);
+                    }
+                }
+                text if text.starts_with("text/") => {
                     // For text files, add content directly
                     if let Ok(text) = String::from_utf8(file.data.clone()) {
                         full_content.push_str(&format!("\n\n[Text from {}]\n", file.name));
@@ -192,6 +326,134 @@ impl AIService {
         Ok(full_content)
     }
 
+    // Whether prompts to the provider have personal data and secrets replaced
+    pub async fn redacts(&self, provider: &AIProvider) -> bool {
+        self.config().await.redact_providers.contains(&provider.to_string())
+    }
+
+    // Transcribes recorded audio using OpenAI's Whisper endpoint, with `api_key` in place
+    // of the configured key when given
+    #[tracing::instrument(skip_all, fields(bytes = audio_data.len()))]
+    pub async fn transcribe(&self, audio_data: &[u8], content_type: &str, api_key: Option<&str>) -> Result<String> {
+        let api_key = api_key.map(str::to_string)
+            .or(self.config().await.openai_api_key)
+            .ok_or_else(|| anyhow::anyhow!("Transcription requires an OpenAI API key"))?;
+
+        let extension = mime_guess::get_mime_extensions_str(content_type)
+            .and_then(|exts| exts.first())
+            .copied()
+            .unwrap_or("webm");
+        let part = reqwest::multipart::Part::bytes(audio_data.to_vec())
+            .file_name(format!("audio.{}", extension))
+            .mime_str(content_type)?;
+        let form = reqwest::multipart::Form::new()
+            .text("model", "whisper-1")
+            .part("file", part);
+
+        let response: Value = self.http
+            .post("https://api.openai.com/v1/audio/transcriptions")
+            .bearer_auth(api_key)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        response["text"]
+            .as_str()
+            .map(|text| text.trim().to_string())
+            .ok_or_else(|| anyhow::anyhow!("Transcription response did not contain text"))
+    }
+
+    // Checks an API key by listing models with it, which every provider allows for free
+    pub async fn validate_api_key(&self, provider: AIProvider, api_key: &str) -> Result<()> {
+        let request = match provider {
+            AIProvider::OpenAI => self.http.get("https://api.openai.com/v1/models").bearer_auth(api_key),
+            AIProvider::Anthropic => self.http
+                .get("https://api.anthropic.com/v1/models")
+                .header("x-api-key", api_key)
+                .header("anthropic-version", "2023-06-01"),
+            AIProvider::Gemini => self.http
+                .get("https://generativelanguage.googleapis.com/v1beta/models")
+                .query(&[("key", api_key)]),
+            AIProvider::OpenRouter => self.http.get("https://openrouter.ai/api/v1/auth/key").bearer_auth(api_key),
+            AIProvider::Ollama => return Ok(()),
+        };
+
+        let response = request.send().await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::BAD_REQUEST => {
+                Err(anyhow::anyhow!("{} rejected this API key", provider))
+            }
+            status => Err(anyhow::anyhow!("Couldn't check the key with {}: {}", provider, status)),
+        }
+    }
+
+    // Calls every provider the instance has credentials for, one after another
+    pub async fn provider_health(&self) -> Vec<ProviderHealth> {
+        let config = self.config().await;
+        let providers = [
+            (AIProvider::Ollama, Some(&config.ollama_base_url)),
+            (AIProvider::OpenAI, config.openai_api_key.as_ref()),
+            (AIProvider::Anthropic, config.anthropic_api_key.as_ref()),
+            (AIProvider::Gemini, config.gemini_api_key.as_ref()),
+            (AIProvider::OpenRouter, config.openrouter_api_key.as_ref()),
+        ];
+
+        let mut health = Vec::new();
+        for (provider, credential) in providers {
+            let Some(credential) = credential else {
+                health.push(ProviderHealth {
+                    provider: provider.to_string(),
+                    configured: false,
+                    healthy: false,
+                    latency_ms: None,
+                    error: None,
+                });
+                continue;
+            };
+
+            let started = std::time::Instant::now();
+            let result = match provider {
+                AIProvider::Ollama => self.http
+                    .get(format!("{}/api/tags", credential))
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(|_| ())
+                    .map_err(anyhow::Error::from),
+                _ => self.validate_api_key(provider.clone(), credential).await,
+            };
+            health.push(ProviderHealth {
+                provider: provider.to_string(),
+                configured: true,
+                healthy: result.is_ok(),
+                latency_ms: Some(started.elapsed().as_secs_f64() * 1000.0),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        health
+    }
+
+    // Whether chats can be answered with the instance's own credentials: a hosted provider
+    // has a key, or Ollama answers at its URL, since it needs no key to count as set up
+    pub async fn has_provider(&self) -> bool {
+        let config = self.config().await;
+        if config.openai_api_key.is_some() || config.anthropic_api_key.is_some()
+            || config.gemini_api_key.is_some() || config.openrouter_api_key.is_some()
+        {
+            return true;
+        }
+        self.http
+            .get(format!("{}/api/tags", config.ollama_base_url))
+            .timeout(std::time::Duration::from_secs(2))
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success())
+    }
+
     fn extract_pdf_text(&self, data: &[u8]) -> Result<String> {
         // Simple PDF text extraction using lopdf
         // This is a basic implementation - you might want to use a more robust library
@@ -1,16 +1,223 @@
 use leptos::*;
 use anyhow::Result;
+use std::sync::Arc;
+#[cfg(feature = "ssr")]
+use crate::database::OwnedResource;
+#[cfg(feature = "ssr")]
+use crate::moderation::ModerationDirection;
 use crate::{
     models::*,
     database::Database,
     ai_service::{AIService, AIServiceConfig},
+    embeddings_service::EmbeddingsService,
+    vector_store::VectorStore,
+    reranker::Reranker,
+    memory::MemoryPolicy,
+    usage::UsageLimits,
 };
 
+// Page sizes for paginated listings
+#[cfg(feature = "ssr")]
+const DEFAULT_PAGE_SIZE: i64 = 50;
+#[cfg(feature = "ssr")]
+const MAX_PAGE_SIZE: i64 = 200;
+// Larger code blocks are shown without highlighting
+#[cfg(feature = "ssr")]
+const MAX_HIGHLIGHT_BYTES: usize = 256 * 1024;
+
 // Server state
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
-    pub ai_service: AIService,
+    pub ai_service: Arc<AIService>,
+    pub embeddings: EmbeddingsService,
+    pub vectors: VectorStore,
+    // Optional second retrieval stage; None when no reranker is configured
+    pub reranker: Option<Reranker>,
+    // Optional content filter on messages and replies; None when none is configured
+    pub moderator: Option<crate::moderation::Moderator>,
+    pub memory_policy: MemoryPolicy,
+    pub usage_limits: UsageLimits,
+    // Days a trashed session or message is kept before it is purged for good
+    pub trash_retention_days: i64,
+    // Days between a user asking for their account to be deleted and it being purged
+    pub account_deletion_grace_days: i64,
+    // Instance-wide retention limits; users can only tighten them
+    pub retention_policy: RetentionPolicy,
+    // Report what retention would remove without removing it
+    pub retention_dry_run: bool,
+    // Bearer token for the admin endpoints; they are disabled when unset
+    pub admin_token: Option<String>,
+    // Master key for secrets stored in the database; users can't save their own
+    // provider keys or set up two-factor when unset
+    pub secrets: Option<crate::secrets::SecretBox>,
+    // Admins can't use admin capabilities until they turn on two-factor authentication
+    pub require_admin_two_factor: bool,
+    // The Ollama model anonymous guests chat with; guest mode is off when unset. Changes
+    // when the configuration is reloaded.
+    pub guest_model: Arc<std::sync::RwLock<Option<String>>>,
+    // Sends password reset links; resetting by email is off when unset
+    pub mailer: Option<crate::mailer::Mailer>,
+    // Single sign-on with an OpenID Connect provider; off when unset
+    pub oidc: Option<crate::oidc::Oidc>,
+    // Answers questions emailed to the instance; off when unset
+    pub email_gateway: Option<crate::email_gateway::EmailGateway>,
+    // Live updates for clients subscribed to a session
+    pub session_events: crate::session_events::SessionEvents,
+    // Tells live update streams and background tasks the server is stopping
+    pub shutdown: crate::shutdown::Shutdown,
+    // Where users reach the app, for links in emails and OAuth redirects
+    pub public_base_url: String,
+    // Signs password reset tokens
+    pub reset_token_key: Vec<u8>,
+    // Signs attachment links, which work without signing in until they expire
+    pub attachment_url_key: Vec<u8>,
+    pub attachment_url_ttl_hours: i64,
+}
+
+// Server function to create an account with a password and sign in to it
+#[server(Register, "/api")]
+pub async fn register(name: Option<String>, email: String, password: String) -> Result<User> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let name = name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty());
+    // Signing up as a guest keeps the guest's conversations
+    let guest = match crate::auth::signed_in_user_id() {
+        Some(user_id) => state.db.get_user(&user_id).await?.filter(|user| user.is_anonymous_guest()),
+        None => None,
+    };
+    let user = crate::auth::register(&state, name, email.trim(), &password, guest).await?;
+    crate::auth::sign_in(&state, &user.id).await?;
+    Ok(user)
+}
+
+// Server function to sign in with an email and password. Accounts with two-factor
+// authentication also need a code from their app or a backup code; without one the
+// password is checked and `CodeRequired` returned.
+#[server(Login, "/api")]
+pub async fn login(email: String, password: String, code: Option<String>) -> Result<LoginResult> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let email = email.trim();
+    let ip = crate::auth::client_ip();
+    crate::login_throttle::check(&state, email, ip.as_deref()).await?;
+
+    let user = match crate::auth::authenticate(&state, email, &password).await {
+        Ok(user) => user,
+        Err(e) => {
+            crate::login_throttle::record_failure(&state, email, ip.as_deref()).await?;
+            return Err(e);
+        }
+    };
+    if crate::two_factor::is_enabled(&state, &user.id).await? {
+        let Some(code) = code.filter(|code| !code.trim().is_empty()) else {
+            return Ok(LoginResult::CodeRequired);
+        };
+        if !crate::two_factor::verify(&state, &user.id, &code).await? {
+            crate::login_throttle::record_failure(&state, email, ip.as_deref()).await?;
+            return Err(anyhow::anyhow!("Incorrect code"));
+        }
+    }
+    crate::login_throttle::record_success(&state, email).await?;
+    crate::auth::sign_in(&state, &user.id).await?;
+    Ok(LoginResult::SignedIn(user))
+}
+
+// Server function to finish a single sign-on for an account with two-factor authentication,
+// with a code from their app or a backup code
+#[server(CompleteSingleSignOn, "/api")]
+pub async fn complete_single_sign_on(code: String) -> Result<User> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let pending = crate::auth::request_cookie(crate::oidc::PENDING_COOKIE);
+    let user_id = crate::oidc::pending_user_id(&state, pending.as_deref())?;
+    let user = state.db.get_user(&user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    // Throttled like password sign-ins, by email where the account has one
+    let login = user.email.clone().unwrap_or_else(|| user.id.clone());
+    let ip = crate::auth::client_ip();
+    crate::login_throttle::check(&state, &login, ip.as_deref()).await?;
+
+    if !crate::two_factor::verify(&state, &user.id, &code).await? {
+        crate::login_throttle::record_failure(&state, &login, ip.as_deref()).await?;
+        return Err(anyhow::anyhow!("Incorrect code"));
+    }
+    crate::login_throttle::record_success(&state, &login).await?;
+    crate::auth::set_cookie(&crate::oidc::clear_pending_cookie())?;
+    crate::auth::sign_in(&state, &user.id).await?;
+    Ok(user)
+}
+
+// Server function to email a password reset link. It succeeds whether or not there is an
+// account with the address, so addresses can't be probed.
+#[server(RequestPasswordReset, "/api")]
+pub async fn request_password_reset(email: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    crate::password_reset::request(&state, email.trim()).await
+}
+
+// Server function to choose a new password with the token from a reset link
+#[server(ResetPassword, "/api")]
+pub async fn reset_password(token: String, password: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::password_reset::reset(&state, &token, &password).await?;
+    crate::audit::record(&state, &user_id, AuditAction::PasswordReset, Some(&user_id), None).await
+}
+
+// Server function to get whether visitors can chat as a guest without an account
+#[server(GuestAccessEnabled, "/api")]
+pub async fn guest_access_enabled() -> Result<bool> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    Ok(crate::guests::guest_model(&state).is_ok())
+}
+
+// Server function to get the name of the single sign-on provider users can sign in with, if
+// one is configured
+#[server(SingleSignOnProvider, "/api")]
+pub async fn single_sign_on_provider() -> Result<Option<String>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    Ok(state.oidc.as_ref().map(|oidc| oidc.display_name().to_string()))
+}
+
+// Server function to start chatting as a guest in this browser
+#[server(StartGuestSession, "/api")]
+pub async fn start_guest_session() -> Result<User> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    crate::guests::start(&state).await
+}
+
+// Server function to sign out of the current browser
+#[server(Logout, "/api")]
+pub async fn logout() -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    crate::auth::sign_out(&state).await
+}
+
+// Server function to get the signed-in user; None when nobody is signed in
+#[server(GetCurrentUser, "/api")]
+pub async fn get_current_user() -> Result<Option<User>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    match crate::auth::signed_in_user_id() {
+        Some(user_id) => state.db.get_user(&user_id).await,
+        None => Ok(None),
+    }
 }
 
 // Server function to create a new chat session
@@ -19,20 +226,53 @@ pub async fn create_session(
     title: Option<String>,
     model_provider: AIProvider,
     model_name: String,
+    incognito: bool,
 ) -> Result<String> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
     
-    // For now, we'll use a default user ID
-    // In a real app, you'd get this from authentication
-    let user_id = "default_user".to_string();
+    let user_id = crate::auth::current_user_id()?;
+    let session = create_session_as(&state, &user_id, title, model_provider, model_name, incognito).await?;
+    Ok(session.id)
+}
+
+// Creates a session for the user. Shared by the server function, the REST and gRPC APIs and
+// the Matrix bridge.
+#[cfg(feature = "ssr")]
+pub(crate) async fn create_session_as(
+    state: &AppState,
+    user_id: &str,
+    title: Option<String>,
+    model_provider: AIProvider,
+    model_name: String,
+    incognito: bool,
+) -> Result<ChatSession> {
+    // Guests always get the guest model, whatever was picked
+    let (model_provider, model_name) = match state.db.get_user(user_id).await? {
+        Some(user) if user.is_anonymous_guest() => {
+            (crate::guests::GUEST_PROVIDER, crate::guests::guest_model(state)?)
+        }
+        _ => (model_provider, model_name),
+    };
     
-    let session = ChatSession::new(user_id, model_provider, model_name);
+    let mut session = ChatSession::new(user_id.to_string(), model_provider, model_name);
     session.title = title;
+    session.incognito = incognito;
     
     state.db.create_session(&session).await?;
+    sessions_changed(state, user_id);
+    crate::webhooks::emit(state, WebhookEvent::SessionCreated, serde_json::json!({ "session": session })).await;
+    if !session.incognito {
+        crate::rules::fire(state, crate::rules::RuleEvent { trigger: RuleTrigger::SessionCreated, session: &session, message: None }).await;
+    }
     
-    Ok(session.id)
+    Ok(session)
+}
+
+// Tells the user's other tabs and devices to reload their session list
+#[cfg(feature = "ssr")]
+pub(crate) fn sessions_changed(state: &AppState, user_id: &str) {
+    state.session_events.publish_to_user(user_id, SyncEvent::SessionsChanged);
 }
 
 // Server function to send a chat message
@@ -41,53 +281,230 @@ pub async fn send_message(
     session_id: String,
     message: String,
     files: Vec<FileUpload>,
+    attachment_ids: Vec<String>,
+    context_attachment_ids: Vec<String>,
+    transcribe_audio: bool,
 ) -> Result<ChatResponse> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
+
+    let user_id = crate::auth::current_user_id()?;
+    send_message_as(&state, &user_id, session_id, message, files, attachment_ids, context_attachment_ids, transcribe_audio).await
+}
+
+// Sends a message in one of the user's sessions and saves the exchange. Shared by the
+// server function, the REST and gRPC APIs and the Matrix bridge.
+#[cfg(feature = "ssr")]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, fields(session_id = %session_id))]
+pub(crate) async fn send_message_as(
+    state: &AppState,
+    user_id: &str,
+    session_id: String,
+    message: String,
+    files: Vec<FileUpload>,
+    attachment_ids: Vec<String>,
+    context_attachment_ids: Vec<String>,
+    transcribe_audio: bool,
+) -> Result<ChatResponse> {
+    crate::auth::authorize(state, user_id, OwnedResource::Session, &session_id).await?;
+
+    // Subscribers see the session as responding until the exchange is saved or fails
+    state.session_events.publish(&session_id, SessionEvent::Status { status: SessionStatus::Responding });
+    let result = exchange(state, user_id, session_id.clone(), message, files, attachment_ids, context_attachment_ids, transcribe_audio).await;
+    state.session_events.publish(&session_id, SessionEvent::Status { status: SessionStatus::Idle });
+    if result.is_err() {
+        // Tabs showing the reply as it was written drop what they have of it
+        state.session_events.publish_to_user(user_id, SyncEvent::ReplyAbandoned { session_id });
+    }
+    result
+}
+
+#[cfg(feature = "ssr")]
+#[allow(clippy::too_many_arguments)]
+async fn exchange(
+    state: &AppState,
+    user_id: &str,
+    session_id: String,
+    message: String,
+    files: Vec<FileUpload>,
+    attachment_ids: Vec<String>,
+    context_attachment_ids: Vec<String>,
+    transcribe_audio: bool,
+) -> Result<ChatResponse> {
     // Get the session
     let session = state.db.get_session(&session_id).await?
         .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    crate::guests::check_session(state, user_id, &session).await?;
     
-    // Get user memory
-    let user_memory = state.db.get_user_memory(&session.user_id).await?;
+    let settings = &session.settings;
+
+    // Incognito sessions, and sessions with memory turned off, skip memory entirely:
+    // nothing is read into the prompt and nothing said here reinforces what is remembered
+    let now = chrono::Utc::now();
+    let (user_memory, reinforced_memories) = if session.incognito || !settings.memory_enabled {
+        (Vec::new(), Vec::new())
+    } else {
+        // Memories the user restates get their confidence topped back up. The boost is
+        // applied here for the prompt and saved with the rest of the exchange.
+        let mut stored_memory = state.db.get_user_memory(&session.user_id).await?;
+        let reinforced = state.memory_policy.reinforced(&stored_memory, &message, now);
+        for memory in stored_memory.iter_mut() {
+            if let Some((_, confidence)) = reinforced.iter().find(|(m, _)| m.id == memory.id) {
+                memory.confidence = *confidence;
+                memory.updated_at = now;
+            }
+        }
+
+        // Get user memory, leaving out anything that has decayed below the threshold
+        (state.memory_policy.active_memories(stored_memory, now), reinforced)
+    };
     
-    // Get session messages
-    let messages = state.db.get_session_messages(&session_id).await?;
+    // The session's organization may restrict providers or bring its own key
+    let api_key = crate::api_keys::provider_key(state, &session).await?;
+
+    // Get session messages, as many as the session's context strategy keeps
+    let messages = settings.context_strategy.select(state.db.get_session_messages(&session_id).await?);
+
+    // Earlier session attachments the user chose to keep in context for this message.
+    // Only attachments that belong to this session can be selected.
+    let context_attachments: Vec<FileAttachment> = state.db.get_session_attachments(&session_id).await?
+        .into_iter()
+        .filter(|attachment| context_attachment_ids.contains(&attachment.id))
+        .collect();
     
-    // Create user message
+    // Create user message. Nothing is written to the database until the reply is back;
+    // the whole exchange is then saved in one transaction.
     let user_message = Message::new(session_id.clone(), MessageRole::User, message.clone());
-    state.db.create_message(&user_message).await?;
+
+    // The content filter sees the message before any provider does
+    let input_warning = crate::moderation::moderate(state, user_id, &session_id, ModerationDirection::Input, &message).await?;
+
+    // Uploads are written to disk before the model is asked, and removed again if the
+    // exchange isn't saved
+    let mut stored_paths: Vec<String> = Vec::new();
+    let result: Result<(ChatResponse, Vec<FileAttachment>)> = async {
+        let mut files = files;
+        let mut attachments = Vec::new();
+
+        // Store file attachments if any
+        for file in files.iter_mut() {
+            // Voice notes are transcribed so the model can read what was said. When that
+            // can't be done the note is still sent, just without a transcript.
+            if transcribe_audio && file.content_type.starts_with("audio/") {
+                match transcribe_voice_note(state, &session, file).await {
+                    Ok(transcript) => file.transcript = Some(transcript),
+                    Err(e) => tracing::warn!("Sending voice note {} without a transcript: {}", file.name, e),
+                }
+            }
+
+            let file_path = store_upload(file).await?;
+            stored_paths.push(file_path.clone());
+            attachments.push(FileAttachment {
+                id: uuid::Uuid::new_v4().to_string(),
+                message_id: user_message.id.clone(),
+                file_name: file.name.clone(),
+                file_path,
+                file_type: file.content_type.clone(),
+                file_size: file.data.len() as i64,
+                content_hash: None,
+                created_at: chrono::Utc::now(),
+                transcript: file.transcript.clone(),
+                url: None,
+            });
+        }
+
+        // Re-attach files from the user's library without uploading them again
+        for attachment_id in &attachment_ids {
+            crate::auth::authorize(state, user_id, OwnedResource::Attachment, attachment_id).await?;
+            let original = state.db.get_attachment(attachment_id).await?
+                .ok_or_else(|| anyhow::anyhow!("Attachment not found"))?;
+
+            attachments.push(FileAttachment {
+                id: uuid::Uuid::new_v4().to_string(),
+                message_id: user_message.id.clone(),
+                created_at: chrono::Utc::now(),
+                ..original.clone()
+            });
+
+            files.push(load_attachment(&original).await?);
+        }
+
+        for attachment in &context_attachments {
+            files.push(load_attachment(attachment).await?);
+        }
     
-    // Save file attachments if any
-    for file in &files {
-        let attachment = FileAttachment {
-            id: uuid::Uuid::new_v4().to_string(),
-            message_id: user_message.id.clone(),
-            file_name: file.name.clone(),
-            file_path: format!("uploads/{}", file.name),
-            file_type: file.content_type.clone(),
-            file_size: file.data.len() as i64,
-            content_hash: None,
-            created_at: chrono::Utc::now(),
+        // Retrieve knowledge base excerpts for linked sessions, unless turned off for this one
+        let kb_ids = if settings.knowledge_enabled {
+            state.db.get_session_knowledge_base_ids(&session.id).await?
+        } else {
+            Vec::new()
         };
-        state.db.save_file_attachment(&attachment).await?;
-    }
-    
-    // Get AI provider and model
-    let provider = AIProvider::from(session.model_provider.clone());
-    let model_name = session.model_name.clone();
+        let knowledge = crate::rag::retrieve(state, &kb_ids, &user_message.content, crate::rag::TOP_K).await?;
+        if !kb_ids.is_empty() {
+            state.db.record_retrieval(&session.user_id).await?;
+        }
+
+        // Get AI provider and model
+        let provider = AIProvider::from(session.model_provider.clone());
     
-    // Send to AI service
-    let ai_response = state.ai_service.chat(
-        provider,
-        &model_name,
-        messages,
-        &user_memory,
-        &files,
-    ).await?;
+        // The reply is shown in the user's tabs as it is written, unless the content filter
+        // could still withhold it
+        let (sender, mut deltas) = tokio::sync::mpsc::unbounded_channel();
+        let options = crate::ai_service::ChatOptions {
+            api_key: api_key.clone(),
+            temperature: settings.temperature,
+            system_prompt: settings.system_prompt.clone(),
+            deltas: (!crate::moderation::may_withhold_replies(state)).then_some(sender),
+        };
+        let publish_delta = |text: String| {
+            state.session_events.publish_to_user(user_id, SyncEvent::Delta { session_id: session_id.clone(), text });
+        };
+
+        // Send to AI service
+        let reply = state.ai_service.chat(
+            provider,
+            &session.model_name,
+            messages,
+            &user_memory,
+            &files,
+            &knowledge,
+            &options,
+        );
+        tokio::pin!(reply);
+        // Every piece is published before the reply returns, so none arrives after the saved message
+        let ai_response = loop {
+            tokio::select! {
+                biased;
+                Some(text) = deltas.recv() => publish_delta(text),
+                response = &mut reply => break response?,
+            }
+        };
+        while let Ok(text) = deltas.try_recv() {
+            publish_delta(text);
+        }
+
+        Ok((ai_response, attachments))
+    }.await;
+    let (mut ai_response, attachments) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            discard_uploads(&stored_paths).await;
+            return Err(e);
+        }
+    };
+
+    // A blocked reply isn't saved, and neither is the message that prompted it
+    let output_warning = match crate::moderation::moderate(state, user_id, &session_id, ModerationDirection::Output, &ai_response.content).await {
+        Ok(warning) => warning,
+        Err(e) => {
+            discard_uploads(&stored_paths).await;
+            return Err(e);
+        }
+    };
+    ai_response.moderation_warning = input_warning.or(output_warning);
     
-    // Save AI response
+    // AI response
     let ai_message = Message {
         id: ai_response.message_id.clone(),
         session_id: session_id.clone(),
@@ -97,11 +514,12 @@ pub async fn send_message(
         model_provider: Some(ai_response.model_provider.clone()),
         model_name: Some(ai_response.model_name.clone()),
         tokens_used: ai_response.tokens_used,
+        citations: ai_response.citations.clone(),
+        deleted_at: None,
         created_at: chrono::Utc::now(),
     };
-    state.db.create_message(&ai_message).await?;
     
-    // Save suggested questions
+    // Suggested questions
     let suggested_questions: Vec<SuggestedQuestion> = ai_response.suggested_questions
         .iter()
         .enumerate()
@@ -114,89 +532,1718 @@ pub async fn send_message(
             created_at: chrono::Utc::now(),
         })
         .collect();
-    
-    if !suggested_questions.is_empty() {
-        state.db.save_suggested_questions(&suggested_questions).await?;
+
+    let exchange = crate::database::ChatExchange {
+        user_message,
+        attachments,
+        ai_message,
+        suggested_questions,
+        reinforced_memories,
+    };
+    if let Err(e) = state.db.save_exchange(&exchange).await {
+        discard_uploads(&stored_paths).await;
+        return Err(e);
+    }
+
+    for message in [&exchange.user_message, &exchange.ai_message] {
+        state.session_events.publish(&session_id, SessionEvent::Message { message: message.clone() });
+        state.session_events.publish_to_user(&session.user_id, SyncEvent::Message { message: message.clone() });
+    }
+    // The session moves to the top of the list with the new message as its preview
+    sessions_changed(state, &session.user_id);
+
+    // What is said in incognito sessions isn't sent anywhere else
+    if !session.incognito {
+        for message in [&exchange.user_message, &exchange.ai_message] {
+            let data = serde_json::json!({ "user_id": user_id, "message": message });
+            crate::webhooks::emit(state, WebhookEvent::MessageCreated, data).await;
+            crate::rules::fire(state, crate::rules::RuleEvent { trigger: RuleTrigger::MessageCreated, session: &session, message: Some(message) }).await;
+        }
+    }
+
+    // Name the session after its first exchange, in the background so the reply isn't held
+    // up by another model call and a failed title can't fail it
+    if session.title.is_none() {
+        let state = state.clone();
+        let reply = ai_response.content.clone();
+        state.shutdown.clone().spawn(async move {
+            let provider = AIProvider::from(session.model_provider.clone());
+            let title = match state.ai_service.generate_title(provider, api_key.as_deref(), &session.model_name, &message, &reply).await {
+                Ok(title) if !title.is_empty() => title,
+                Ok(_) => return,
+                Err(e) => {
+                    tracing::warn!("Failed to generate a title for session {}: {}", session_id, e);
+                    return;
+                }
+            };
+            if let Err(e) = state.db.update_session_title(&session_id, Some(&title)).await {
+                tracing::warn!("Failed to save the title of session {}: {}", session_id, e);
+                return;
+            }
+            state.session_events.publish(&session_id, SessionEvent::Renamed { title: Some(title) });
+            sessions_changed(&state, &session.user_id);
+        });
     }
     
     Ok(ai_response)
 }
 
-// Server function to get chat history
+// Removes uploads written for an exchange that was never saved
+#[cfg(feature = "ssr")]
+async fn discard_uploads(paths: &[String]) {
+    for path in paths {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            tracing::warn!("Failed to remove unsaved upload {}: {}", path, e);
+        }
+    }
+}
+
+// Writes an uploaded file to the uploads directory and returns its path. The file is
+// named by a fresh uuid; the client's file name is only ever stored in the database, since
+// it can contain separators or `..`.
+#[cfg(feature = "ssr")]
+async fn store_upload(file: &FileUpload) -> Result<String> {
+    tokio::fs::create_dir_all("uploads").await?;
+    let extension = std::path::Path::new(&file.name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.len() <= 10 && extension.bytes().all(|b| b.is_ascii_alphanumeric()))
+        .map(|extension| format!(".{}", extension.to_ascii_lowercase()))
+        .unwrap_or_default();
+    let file_path = format!("uploads/{}{}", uuid::Uuid::new_v4(), extension);
+    tokio::fs::write(&file_path, &file.data).await?;
+    Ok(file_path)
+}
+
+// Reads a stored attachment back into an upload so it can be sent to the model
+#[cfg(feature = "ssr")]
+async fn load_attachment(attachment: &FileAttachment) -> Result<FileUpload> {
+    let data = tokio::fs::read(&attachment.file_path).await?;
+    Ok(FileUpload {
+        name: attachment.file_name.clone(),
+        content_type: attachment.file_type.clone(),
+        data,
+        transcript: attachment.transcript.clone(),
+    })
+}
+
+// Transcribes a voice note with OpenAI, using the key the session's chats would use with
+// it. Audio can't have personal data taken out, so it isn't sent while OpenAI's prompts
+// are redacted.
+#[cfg(feature = "ssr")]
+async fn transcribe_voice_note(state: &AppState, session: &ChatSession, file: &FileUpload) -> Result<String> {
+    if state.ai_service.redacts(&AIProvider::OpenAI).await {
+        return Err(anyhow::anyhow!("OpenAI's prompts are redacted"));
+    }
+    let whisper = ChatSession { model_provider: AIProvider::OpenAI.to_string(), ..session.clone() };
+    let api_key = crate::api_keys::provider_key(state, &whisper).await?;
+    state.ai_service.transcribe(&file.data, &file.content_type, api_key.as_deref()).await
+}
+
+// Server function to get chat history, newest page first; pass the returned cursor as
+// `before` to load earlier messages
 #[server(GetChatHistory, "/api")]
-pub async fn get_chat_history(session_id: String) -> Result<Vec<Message>> {
+pub async fn get_chat_history(
+    session_id: String,
+    before: Option<MessageCursor>,
+    limit: Option<i64>,
+) -> Result<Page<Message, MessageCursor>> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
     
-    state.db.get_session_messages(&session_id).await
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    state.db.get_session_messages_page(&session_id, before.as_ref(), limit).await
+}
+
+// Server function to search the user's past conversations by meaning
+#[server(SearchConversations, "/api")]
+pub async fn search_conversations(query: String) -> Result<Vec<ConversationSearchResult>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::conversation_search::search(&state, &user_id, &query, 10).await
+}
+
+// Server function to find past messages containing every word of `query`
+#[server(SearchMessages, "/api")]
+pub async fn search_messages(query: String, filters: MessageSearchFilters) -> Result<Vec<ConversationSearchResult>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let terms = crate::rag::search_terms(&query);
+    state.db.search_messages(&user_id, &terms, &filters, 20).await
 }
 
 // Server function to get user sessions
 #[server(GetUserSessions, "/api")]
-pub async fn get_user_sessions() -> Result<Vec<ChatSession>> {
+pub async fn get_user_sessions(
+    include_archived: bool,
+    tag_id: Option<String>,
+    after: Option<SessionCursor>,
+    limit: Option<i64>,
+) -> Result<Page<ChatSession, SessionCursor>> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
     
-    // For now, use default user
-    let user_id = "default_user".to_string();
-    state.db.get_user_sessions(&user_id).await
+    let user_id = crate::auth::current_user_id()?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    state.db.get_user_sessions(&user_id, include_archived, tag_id.as_deref(), after.as_ref(), limit).await
 }
 
-// Server function to get suggested questions
-#[server(GetSuggestedQuestions, "/api")]
-pub async fn get_suggested_questions(session_id: String) -> Result<Vec<SuggestedQuestion>> {
+// Server function to tag a session, creating the tag if the user doesn't have it yet
+#[server(AddSessionTag, "/api")]
+pub async fn add_session_tag(session_id: String, tag_name: String) -> Result<Tag> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
-    state.db.get_session_suggested_questions(&session_id, 5).await
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let tag_name = tag_name.trim().to_lowercase();
+    if tag_name.is_empty() {
+        return Err(anyhow::anyhow!("Tag name can't be empty"));
+    }
+
+    let tag = state.db.get_or_create_tag(&user_id, &tag_name).await?;
+    state.db.add_session_tag(&session_id, &tag.id).await?;
+    Ok(tag)
 }
 
-// Server function to save user memory
-#[server(SaveMemory, "/api")]
-pub async fn save_memory(memory_key: String, memory_value: String) -> Result<()> {
+// Server function to remove a tag from a session
+#[server(RemoveSessionTag, "/api")]
+pub async fn remove_session_tag(session_id: String, tag_id: String) -> Result<()> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
-    // For now, use default user
-    let user_id = "default_user".to_string();
-    
-    let memory = UserMemory::new(user_id, memory_key, memory_value);
-    state.db.save_memory(&memory).await
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.remove_session_tag(&session_id, &tag_id).await
 }
 
-// Server function to get user memory
-#[server(GetUserMemory, "/api")]
-pub async fn get_user_memory() -> Result<Vec<UserMemory>> {
+// Server function to get the tags on a session
+#[server(GetSessionTags, "/api")]
+pub async fn get_session_tags(session_id: String) -> Result<Vec<Tag>> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
-    // For now, use default user
-    let user_id = "default_user".to_string();
-    state.db.get_user_memory(&user_id).await
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.get_session_tags(&session_id).await
 }
 
-// Server function to get available models
-#[server(GetAvailableModels, "/api")]
-pub async fn get_available_models(provider: AIProvider) -> Result<Vec<String>> {
+// Server function to list every tag the user has created
+#[server(ListTags, "/api")]
+pub async fn list_tags() -> Result<Vec<Tag>> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
-    state.ai_service.get_available_models(provider).await
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_tags(&user_id).await
 }
 
-// Server function to handle voice input
-#[server(ProcessVoiceInput, "/api")]
-pub async fn process_voice_input(audio_data: Vec<u8>) -> Result<String> {
-    // This would use whisper-rs to transcribe audio
-    // For now, return a placeholder
-    Ok("Voice input processed".to_string())
+// Server function to pin a session to the top of the session list, or unpin it
+#[server(PinSession, "/api")]
+pub async fn pin_session(session_id: String, pinned: bool) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.set_session_pinned(&session_id, pinned).await?;
+    sessions_changed(&state, &user_id);
+    Ok(())
 }
 
-// Server function to mark suggested question as used
-#[server(MarkQuestionUsed, "/api")]
-pub async fn mark_question_used(question_id: String) -> Result<()> {
+// Server function to move a session into a folder; None moves it back to the top level
+#[server(MoveSessionToFolder, "/api")]
+pub async fn move_session_to_folder(session_id: String, folder_id: Option<String>) -> Result<()> {
     let state = use_context::<AppState>()
         .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
-    
-    state.db.mark_question_used(&question_id).await
-} 
\ No newline at end of file
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+    if let Some(folder_id) = &folder_id {
+        crate::auth::authorize(&state, &user_id, OwnedResource::Folder, folder_id).await?;
+    }
+
+    state.db.move_session_to_folder(&session_id, folder_id.as_deref()).await?;
+    sessions_changed(&state, &user_id);
+    Ok(())
+}
+
+// Server function to create a session folder
+#[server(CreateFolder, "/api")]
+pub async fn create_folder(name: String) -> Result<SessionFolder> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Folder name can't be empty"));
+    }
+
+    let user_id = crate::auth::current_user_id()?;
+    let folder = SessionFolder::new(user_id.clone(), name.to_string());
+    state.db.create_folder(&folder).await?;
+    sessions_changed(&state, &user_id);
+    Ok(folder)
+}
+
+// Server function to list the user's session folders
+#[server(ListFolders, "/api")]
+pub async fn list_folders() -> Result<Vec<SessionFolder>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_folders(&user_id).await
+}
+
+// Server function to rename a session folder
+#[server(RenameFolder, "/api")]
+pub async fn rename_folder(folder_id: String, name: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Folder, &folder_id).await?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Folder name can't be empty"));
+    }
+    state.db.rename_folder(&folder_id, name).await?;
+    sessions_changed(&state, &user_id);
+    Ok(())
+}
+
+// Server function to delete a folder; its sessions move back to the top level
+#[server(DeleteFolder, "/api")]
+pub async fn delete_folder(folder_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Folder, &folder_id).await?;
+
+    state.db.delete_folder(&folder_id).await?;
+    sessions_changed(&state, &user_id);
+    Ok(())
+}
+
+// Server function to rename a session; an empty title clears it
+#[server(RenameSession, "/api")]
+pub async fn rename_session(session_id: String, title: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    rename_session_as(&state, &user_id, &session_id, &title).await
+}
+
+// Renames one of the user's sessions. Shared by the server function and the gRPC and GraphQL APIs.
+#[cfg(feature = "ssr")]
+pub(crate) async fn rename_session_as(state: &AppState, user_id: &str, session_id: &str, title: &str) -> Result<()> {
+    crate::auth::authorize(state, user_id, OwnedResource::Session, session_id).await?;
+
+    let title = title.trim();
+    state.db.update_session_title(session_id, (!title.is_empty()).then_some(title)).await?;
+    state.session_events.publish(session_id, SessionEvent::Renamed { title: (!title.is_empty()).then(|| title.to_string()) });
+    sessions_changed(state, user_id);
+    Ok(())
+}
+
+// Server function to hide a session from the session list, or bring it back
+#[server(ArchiveSession, "/api")]
+pub async fn archive_session(session_id: String, archived: bool) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.set_session_archived(&session_id, archived).await?;
+    sessions_changed(&state, &user_id);
+    Ok(())
+}
+
+// Server function to move a session to the trash
+#[server(DeleteSession, "/api")]
+pub async fn delete_session(session_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    delete_session_as(&state, &user_id, &session_id).await
+}
+
+// Moves a session to the trash: one of the user's, or anyone's for users who may delete any
+// session. Shared by the server function and the REST, gRPC and GraphQL APIs.
+#[cfg(feature = "ssr")]
+pub(crate) async fn delete_session_as(state: &AppState, user_id: &str, session_id: &str) -> Result<()> {
+    crate::permissions::authorize_any(state, user_id, Capability::DeleteAnySession, OwnedResource::Session, session_id).await?;
+    let session = state.db.get_session(session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+    state.db.set_session_deleted(session_id, Some(chrono::Utc::now())).await?;
+    sessions_changed(state, &session.user_id);
+    crate::audit::record(state, user_id, AuditAction::SessionDeleted, Some(session_id), None).await
+}
+
+// Server function to move a single message to the trash
+#[server(DeleteMessage, "/api")]
+pub async fn delete_message(message_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    state.db.set_message_deleted(&message_id, Some(chrono::Utc::now())).await?;
+    crate::audit::record(&state, &user_id, AuditAction::MessageDeleted, Some(&message_id), None).await
+}
+
+// Server function to list the user's trashed sessions and messages
+#[server(GetTrash, "/api")]
+pub async fn get_trash() -> Result<Trash> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_trash(&user_id).await
+}
+
+// Server function to bring a session back from the trash
+#[server(RestoreSession, "/api")]
+pub async fn restore_session(session_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.set_session_deleted(&session_id, None).await?;
+    sessions_changed(&state, &user_id);
+    crate::audit::record(&state, &user_id, AuditAction::SessionRestored, Some(&session_id), None).await
+}
+
+// Server function to bring a message back from the trash
+#[server(RestoreMessage, "/api")]
+pub async fn restore_message(message_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    state.db.set_message_deleted(&message_id, None).await?;
+    crate::audit::record(&state, &user_id, AuditAction::MessageRestored, Some(&message_id), None).await
+}
+
+// Server function to permanently delete a session with its messages and stored files
+#[server(PurgeSession, "/api")]
+pub async fn purge_session(session_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let session = state.db.get_session(&session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    crate::trash::purge_session(&state, &session).await?;
+    sessions_changed(&state, &session.user_id);
+    crate::audit::record(&state, &user_id, AuditAction::SessionPurged, Some(&session_id), Some(serde_json::json!({ "title": session.title }))).await
+}
+
+// Server function to permanently delete a message and its stored files
+#[server(PurgeMessage, "/api")]
+pub async fn purge_message(message_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    let message = state.db.get_message(&message_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    let session = state.db.get_session(&message.session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    crate::trash::purge_message(&state, &session.user_id, &message).await?;
+    crate::audit::record(&state, &session.user_id, AuditAction::MessagePurged, Some(&message_id), Some(serde_json::json!({ "session_id": session.id }))).await
+}
+
+// Server function to fork a session at one of its messages. The branch starts with copies
+// of the messages up to and including that one; returns the new session's id.
+#[server(BranchSession, "/api")]
+pub async fn branch_session(message_id: String) -> Result<String> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    let message = state.db.get_message(&message_id).await?
+        .filter(|message| message.deleted_at.is_none())
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    let session = state.db.get_session(&message.session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+    let branch = fork_session(&state, &session, Some(&message)).await?;
+    sessions_changed(&state, &branch.user_id);
+    Ok(branch.id)
+}
+
+// Server function to start resubmitting one of the user's messages with new text. The
+// session is forked just before the message, so the edited text is sent into a branch
+// that ends there and the original exchange stays in the session it came from. Returns the
+// branch's id.
+#[server(BranchForEdit, "/api")]
+pub async fn branch_for_edit(message_id: String) -> Result<String> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    let message = state.db.get_message(&message_id).await?
+        .filter(|message| message.deleted_at.is_none())
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    if !matches!(message.role, MessageRole::User) {
+        return Err(anyhow::anyhow!("Only your own messages can be edited"));
+    }
+    let session = state.db.get_session(&message.session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+    let messages = state.db.get_session_messages(&session.id).await?;
+    let previous = messages.iter()
+        .take_while(|earlier| earlier.id != message.id)
+        .last();
+    let branch = fork_session(&state, &session, previous).await?;
+    sessions_changed(&state, &branch.user_id);
+    Ok(branch.id)
+}
+
+// Creates a branch of `session` holding copies of its messages up to and including
+// `through`, or none of them without it. Knowledge bases and settings carry over.
+#[cfg(feature = "ssr")]
+async fn fork_session(state: &AppState, session: &ChatSession, through: Option<&Message>) -> Result<ChatSession> {
+    let mut branch = ChatSession::new(
+        session.user_id.clone(),
+        AIProvider::from(session.model_provider.clone()),
+        session.model_name.clone(),
+    );
+    branch.title = session.title.as_ref().map(|title| format!("{} (branch)", title));
+    branch.incognito = session.incognito;
+    branch.settings = session.settings.clone();
+    branch.folder_id = session.folder_id.clone();
+    branch.parent_session_id = Some(session.id.clone());
+    branch.branch_message_id = through.map(|message| message.id.clone());
+    branch.last_message_preview = through.map(|message| message_preview(&message.content));
+    state.db.create_session(&branch).await?;
+
+    for kb_id in state.db.get_session_knowledge_base_ids(&session.id).await? {
+        state.db.link_session_knowledge_base(&branch.id, &kb_id).await?;
+    }
+
+    let Some(through) = through else {
+        return Ok(branch);
+    };
+    for original in state.db.get_session_messages(&session.id).await? {
+        let mut copy = original.clone();
+        copy.id = uuid::Uuid::new_v4().to_string();
+        copy.session_id = branch.id.clone();
+        state.db.create_message(&copy).await?;
+
+        // Copies point at the same stored files, which stay until nothing references them
+        for attachment in state.db.get_message_attachments(&original.id).await? {
+            state.db.save_file_attachment(&FileAttachment {
+                id: uuid::Uuid::new_v4().to_string(),
+                message_id: copy.id.clone(),
+                ..attachment
+            }).await?;
+        }
+
+        if original.id == through.id {
+            break;
+        }
+    }
+    Ok(branch)
+}
+
+// Server function to list every branch related to a session: the session it was
+// originally forked from and all branches below that, oldest first
+#[server(GetSessionBranches, "/api")]
+pub async fn get_session_branches(session_id: String) -> Result<Vec<ChatSession>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let mut root_id = session_id;
+    while let Some(parent_id) = state.db.get_session(&root_id).await?.and_then(|session| session.parent_session_id) {
+        root_id = parent_id;
+    }
+    state.db.get_session_branches(&root_id).await
+}
+
+// Server function to get a session's generation settings
+#[server(GetSessionSettings, "/api")]
+pub async fn get_session_settings(session_id: String) -> Result<SessionSettings> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let session = state.db.get_session(&session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    Ok(session.settings)
+}
+
+// Server function to change a session's generation settings; they apply from the next message
+#[server(UpdateSessionSettings, "/api")]
+pub async fn update_session_settings(session_id: String, settings: SessionSettings) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    if let Some(temperature) = settings.temperature {
+        if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+            return Err(anyhow::anyhow!("Temperature must be between 0 and {}", MAX_TEMPERATURE));
+        }
+    }
+    match settings.context_strategy {
+        ContextStrategy::RecentMessages { count: 0 } | ContextStrategy::TokenBudget { tokens: 0 } => {
+            return Err(anyhow::anyhow!("The context window must keep at least one message"));
+        }
+        _ => {}
+    }
+
+    let settings = SessionSettings {
+        system_prompt: settings.system_prompt
+            .map(|prompt| prompt.trim().to_string())
+            .filter(|prompt| !prompt.is_empty()),
+        ..settings
+    };
+    state.db.update_session_settings(&session_id, &settings).await
+}
+
+// Server function to list the provider keys the user has saved, by fingerprint
+#[server(ListApiKeys, "/api")]
+pub async fn list_api_keys() -> Result<Vec<StoredApiKey>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_api_keys(&user_id).await
+}
+
+// Server function to save the user's own key for a provider after checking it works.
+// Their chats with that provider use it instead of the organization or instance key.
+#[server(SetApiKey, "/api")]
+pub async fn set_api_key(provider: AIProvider, api_key: String) -> Result<StoredApiKey> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKeys).await?;
+    let key = crate::api_keys::save(&state, &user_id, provider, &api_key).await?;
+    crate::audit::record(&state, &user_id, AuditAction::UserApiKeyChanged, None, Some(serde_json::json!({ "provider": key.provider, "fingerprint": key.fingerprint }))).await?;
+    Ok(key)
+}
+
+// Server function to remove the user's own key for a provider
+#[server(DeleteApiKey, "/api")]
+pub async fn delete_api_key(provider: AIProvider) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKeys).await?;
+    state.db.delete_user_api_key(&user_id, &provider.to_string()).await?;
+    crate::audit::record(&state, &user_id, AuditAction::UserApiKeyChanged, None, Some(serde_json::json!({ "provider": provider.to_string(), "removed": true }))).await
+}
+
+// Server function to save the user's GitHub token after checking it works. Repositories
+// they ingest are read with it, private ones included.
+#[server(SetGitHubToken, "/api")]
+pub async fn set_github_token(token: String) -> Result<StoredApiKey> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKeys).await?;
+    let key = crate::api_keys::save_github_token(&state, &user_id, &token).await?;
+    crate::audit::record(&state, &user_id, AuditAction::UserApiKeyChanged, None, Some(serde_json::json!({ "provider": key.provider, "fingerprint": key.fingerprint }))).await?;
+    Ok(key)
+}
+
+// Server function to remove the user's GitHub token
+#[server(DeleteGitHubToken, "/api")]
+pub async fn delete_github_token() -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKeys).await?;
+    state.db.delete_user_api_key(&user_id, crate::api_keys::GITHUB).await?;
+    crate::audit::record(&state, &user_id, AuditAction::UserApiKeyChanged, None, Some(serde_json::json!({ "provider": crate::api_keys::GITHUB, "removed": true }))).await
+}
+
+// Server function to get whether the user has two-factor authentication on
+#[server(GetTwoFactorStatus, "/api")]
+pub async fn get_two_factor_status() -> Result<TwoFactorStatus> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let user = state.db.get_user(&user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    crate::two_factor::status(&state, &user).await
+}
+
+// Server function to start setting up two-factor authentication; returns the QR code and
+// secret for the user's authenticator app
+#[server(StartTwoFactorSetup, "/api")]
+pub async fn start_two_factor_setup() -> Result<TwoFactorSetup> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let user = state.db.get_user(&user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    crate::two_factor::start_setup(&state, &user).await
+}
+
+// Server function to turn two-factor authentication on with a first code from the app;
+// returns the backup codes
+#[server(ConfirmTwoFactorSetup, "/api")]
+pub async fn confirm_two_factor_setup(code: String) -> Result<Vec<String>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let backup_codes = crate::two_factor::confirm_setup(&state, &user_id, &code).await?;
+    crate::audit::record(&state, &user_id, AuditAction::TwoFactorChanged, Some(&user_id), Some(serde_json::json!({ "enabled": true }))).await?;
+    Ok(backup_codes)
+}
+
+// Server function to turn two-factor authentication off, given a current code
+#[server(DisableTwoFactor, "/api")]
+pub async fn disable_two_factor(code: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::two_factor::disable(&state, &user_id, &code).await?;
+    crate::audit::record(&state, &user_id, AuditAction::TwoFactorChanged, Some(&user_id), Some(serde_json::json!({ "enabled": false }))).await
+}
+
+// Server function to replace the user's backup codes, given a current code
+#[server(RegenerateBackupCodes, "/api")]
+pub async fn regenerate_backup_codes(code: String) -> Result<Vec<String>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let backup_codes = crate::two_factor::regenerate_backup_codes(&state, &user_id, &code).await?;
+    crate::audit::record(&state, &user_id, AuditAction::TwoFactorChanged, Some(&user_id), Some(serde_json::json!({ "backup_codes_regenerated": true }))).await?;
+    Ok(backup_codes)
+}
+
+// Server function to list the browsers the user is signed in on, marking this one
+#[server(GetLoginSessions, "/api")]
+pub async fn get_login_sessions() -> Result<Vec<LoginSession>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let current_id = crate::auth::current_session_id();
+    Ok(state.db.get_user_auth_sessions(&user_id, chrono::Utc::now()).await?
+        .into_iter()
+        .map(|session| LoginSession { current: Some(&session.id) == current_id.as_ref(), ..session })
+        .collect())
+}
+
+// Server function to sign the user out on one device; revoking this one signs out here
+#[server(RevokeLoginSession, "/api")]
+pub async fn revoke_login_session(session_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    if crate::auth::current_session_id().as_ref() == Some(&session_id) {
+        crate::auth::sign_out(&state).await?;
+    } else if !state.db.delete_user_auth_session(&user_id, &session_id).await? {
+        return Err(anyhow::anyhow!("Login session not found"));
+    }
+    crate::audit::record(&state, &user_id, AuditAction::LoginSessionRevoked, Some(&session_id), None).await
+}
+
+// Server function to sign the user out everywhere except this browser
+#[server(RevokeOtherLoginSessions, "/api")]
+pub async fn revoke_other_login_sessions() -> Result<u64> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let current_id = crate::auth::current_session_id()
+        .ok_or_else(|| anyhow::anyhow!("Not signed in"))?;
+    let revoked = state.db.delete_other_auth_sessions(&user_id, &current_id).await?;
+    crate::audit::record(&state, &user_id, AuditAction::LoginSessionRevoked, None, Some(serde_json::json!({ "revoked": revoked }))).await?;
+    Ok(revoked)
+}
+
+// Server function to list the user's personal access tokens
+#[server(GetApiTokens, "/api")]
+pub async fn get_api_tokens() -> Result<Vec<ApiToken>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_api_tokens(&user_id).await
+}
+
+// Server function to create a personal access token; its secret is only returned this once
+#[server(CreateApiToken, "/api")]
+pub async fn create_api_token(name: String, scope: TokenScope, expires_in_days: Option<i64>) -> Result<NewApiToken> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::UseApiTokens).await?;
+    let created = crate::api_tokens::create(&state, &user_id, &name, scope, expires_in_days).await?;
+    crate::audit::record(&state, &user_id, AuditAction::ApiTokenCreated, Some(&created.token.id), Some(serde_json::json!({ "scope": scope.to_string() }))).await?;
+    Ok(created)
+}
+
+// Server function to revoke one of the user's personal access tokens
+#[server(DeleteApiToken, "/api")]
+pub async fn delete_api_token(token_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    if !state.db.delete_api_token(&user_id, &token_id).await? {
+        return Err(anyhow::anyhow!("API token not found"));
+    }
+    crate::audit::record(&state, &user_id, AuditAction::ApiTokenRevoked, Some(&token_id), None).await
+}
+
+// Server function to get when the user's account will be deleted, if they asked for it
+#[server(GetAccountDeletion, "/api")]
+pub async fn get_account_deletion() -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::account::deletion_scheduled_at(&state, &user_id).await
+}
+
+// Server function to schedule the user's account for deletion after the grace period, given
+// their password; returns when it will be deleted
+#[server(RequestAccountDeletion, "/api")]
+pub async fn request_account_deletion(password: String) -> Result<chrono::DateTime<chrono::Utc>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let user = state.db.get_user(&user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    let scheduled_at = crate::account::request_deletion(&state, &user, &password).await?;
+    crate::audit::record(&state, &user_id, AuditAction::AccountDeletionRequested, Some(&user_id), Some(serde_json::json!({ "scheduled_at": scheduled_at }))).await?;
+    Ok(scheduled_at)
+}
+
+// Server function to keep the user's account after asking for it to be deleted
+#[server(CancelAccountDeletion, "/api")]
+pub async fn cancel_account_deletion() -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::account::cancel_deletion(&state, &user_id).await?;
+    crate::audit::record(&state, &user_id, AuditAction::AccountDeletionCancelled, Some(&user_id), None).await
+}
+
+// Server function to get the user's own retention settings; unset fields follow the instance policy
+#[server(GetRetentionPolicy, "/api")]
+pub async fn get_retention_policy() -> Result<RetentionPolicy> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+
+    Ok(state.db.get_retention_policy(&user_id).await?.unwrap_or_default())
+}
+
+// Server function to change the user's retention settings. They can only make the
+// instance policy stricter; the effective policy is returned.
+#[server(SetRetentionPolicy, "/api")]
+pub async fn set_retention_policy(policy: RetentionPolicy) -> Result<RetentionPolicy> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+
+    if policy.message_retention_days.is_some_and(|days| days < 1) || policy.max_sessions.is_some_and(|max| max < 1) {
+        return Err(anyhow::anyhow!("Retention limits must be at least 1"));
+    }
+    state.db.save_retention_policy(&user_id, &policy).await?;
+    crate::audit::record(&state, &user_id, AuditAction::RetentionPolicyChanged, None, Some(serde_json::to_value(policy)?)).await?;
+    crate::retention::effective_policy(&state, &user_id).await
+}
+
+// Server function to list what the user's retention policy would move to the trash right now
+#[server(PreviewRetention, "/api")]
+pub async fn preview_retention() -> Result<RetentionReport> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+
+    crate::retention::apply(&state, &user_id, true).await
+}
+
+// Server function to export a session as a JSON document, Markdown transcript or PDF
+#[server(ExportSession, "/api")]
+pub async fn export_session(session_id: String, format: ExportFormat) -> Result<ExportedFile> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let file = crate::export::export_session(&state, &session_id, format).await?;
+    crate::audit::record(&state, &user_id, AuditAction::SessionExported, Some(&session_id), Some(serde_json::json!({ "format": format.to_string() }))).await?;
+    Ok(file)
+}
+
+// Server function to import conversations from a ChatGPT or Claude data export
+#[server(ImportConversations, "/api")]
+pub async fn import_conversations(data: Vec<u8>) -> Result<ImportReport> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ImportData).await?;
+
+    let report = crate::import::import_conversations(&state, &user_id, &data).await?;
+    sessions_changed(&state, &user_id);
+    crate::audit::record(&state, &user_id, AuditAction::ConversationsImported, None, Some(serde_json::json!({
+        "sessions": report.sessions_imported,
+        "messages": report.messages_imported,
+    }))).await?;
+    Ok(report)
+}
+
+// Server function to get suggested questions
+#[server(GetSuggestedQuestions, "/api")]
+pub async fn get_suggested_questions(session_id: String) -> Result<Vec<SuggestedQuestion>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+    
+    state.db.get_session_suggested_questions(&session_id, 5).await
+}
+
+// Server function to save user memory. An edit of an existing memory passes the version
+// it was made from, and is rejected if the memory has changed since; new memories pass None.
+// Returns the memory as stored.
+#[server(SaveMemory, "/api")]
+pub async fn save_memory(memory_key: String, memory_value: String, expected_version: Option<i64>) -> Result<UserMemory> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+    
+    let user_id = crate::auth::current_user_id()?;
+    
+    let memory = UserMemory::new(user_id.clone(), memory_key.clone(), memory_value);
+    remember(&state, memory, expected_version).await?;
+    crate::audit::record(&state, &user_id, AuditAction::MemorySaved, None, Some(serde_json::json!({ "key": memory_key }))).await?;
+    state.db.get_memory_by_key(&user_id, &memory_key).await?
+        .ok_or_else(|| anyhow::anyhow!("Memory not found"))
+}
+
+// Stores a learned fact, resolving any conflict with the value already kept under its key.
+// With `expected_version`, the stored memory must still be at that version.
+#[cfg(feature = "ssr")]
+async fn remember(state: &AppState, memory: UserMemory, expected_version: Option<i64>) -> Result<()> {
+    use crate::memory::MemoryResolution;
+
+    let existing = state.db.get_memory_by_key(&memory.user_id, &memory.memory_key).await?;
+    if let Some(expected_version) = expected_version {
+        if existing.as_ref().map(|existing| existing.version) != Some(expected_version) {
+            return Err(crate::database::memory_conflict(&memory.memory_key));
+        }
+    }
+    let now = chrono::Utc::now();
+
+    match (state.memory_policy.resolve(existing.as_ref(), &memory, now), existing) {
+        (MemoryResolution::Reinforce, Some(existing)) => {
+            let confidence = state.memory_policy.effective_confidence(&existing, now).max(memory.confidence);
+            state.db.reinforce_memory(&existing.id, confidence, now).await
+        }
+        // Checks the version again as it writes, in case of an edit since the read above
+        (MemoryResolution::Supersede, Some(existing)) => state.db.supersede_memory(&existing, &memory).await,
+        (MemoryResolution::Keep, Some(existing)) => {
+            tracing::info!(
+                "Ignoring low-confidence value for memory '{}' that conflicts with '{}'",
+                existing.memory_key,
+                existing.memory_value
+            );
+            Ok(())
+        }
+        _ => state.db.save_memory(&memory).await,
+    }
+}
+
+// Server function to report the user's knowledge base usage and quota
+#[server(GetRagUsage, "/api")]
+pub async fn get_rag_usage() -> Result<RagUsage> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let mut usage = state.db.get_rag_usage(&user_id).await?;
+    usage.max_vectors = state.usage_limits.max_kb_chunks_per_user;
+    Ok(usage)
+}
+
+// Server function to get the user's daily usage per model between two days, inclusive.
+// Figures come from the hourly rollup, so today's are up to an hour behind.
+#[server(GetUsageStats, "/api")]
+pub async fn get_usage_stats(from: chrono::NaiveDate, to: chrono::NaiveDate) -> Result<Vec<DailyUsage>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_daily_usage(Some(&user_id), from, to).await
+}
+
+// Server function to list the values a memory held before being superseded, newest first
+#[server(GetMemoryHistory, "/api")]
+pub async fn get_memory_history(memory_key: String) -> Result<Vec<MemoryHistoryEntry>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_memory_history(&user_id, &memory_key).await
+}
+
+// Server function to get user memory
+#[server(GetUserMemory, "/api")]
+pub async fn get_user_memory() -> Result<Vec<UserMemory>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+    
+    let user_id = crate::auth::current_user_id()?;
+    let memories = state.db.get_user_memory(&user_id).await?;
+
+    // Report decayed confidence so the UI shows what the model actually sees
+    let now = chrono::Utc::now();
+    Ok(memories
+        .into_iter()
+        .map(|mut memory| {
+            memory.confidence = state.memory_policy.effective_confidence(&memory, now);
+            memory
+        })
+        .collect())
+}
+
+// Server function to get available models
+#[server(GetAvailableModels, "/api")]
+pub async fn get_available_models(provider: AIProvider) -> Result<Vec<String>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+    
+    state.ai_service.get_available_models(provider).await
+}
+
+// Server function to handle voice input
+#[server(ProcessVoiceInput, "/api")]
+pub async fn process_voice_input(audio_data: Vec<u8>) -> Result<String> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    state.ai_service.transcribe(&audio_data, "audio/webm", None).await
+}
+
+// Server function to mark suggested question as used
+#[server(MarkQuestionUsed, "/api")]
+pub async fn mark_question_used(question_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::SuggestedQuestion, &question_id).await?;
+    
+    state.db.mark_question_used(&question_id).await
+}
+
+// Server function to list the user's previously uploaded files
+#[server(GetFileLibrary, "/api")]
+pub async fn get_file_library() -> Result<Vec<FileAttachment>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::attachment_urls::sign_all(&state, state.db.get_user_attachments(&user_id).await?)
+}
+
+// Server function to get the files attached to a message
+#[server(GetMessageAttachments, "/api")]
+pub async fn get_message_attachments(message_id: String) -> Result<Vec<FileAttachment>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Message, &message_id).await?;
+
+    crate::attachment_urls::sign_all(&state, state.db.get_message_attachments(&message_id).await?)
+}
+
+// Server function to list every file attached anywhere in a session
+#[server(GetSessionAttachments, "/api")]
+pub async fn get_session_attachments(session_id: String) -> Result<Vec<FileAttachment>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    crate::attachment_urls::sign_all(&state, state.db.get_session_attachments(&session_id).await?)
+}
+
+// Server function to render a text attachment as syntax-highlighted HTML for the preview modal
+#[server(GetAttachmentPreviewHtml, "/api")]
+pub async fn get_attachment_preview_html(attachment_id: String) -> Result<String> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Attachment, &attachment_id).await?;
+
+    let attachment = state.db.get_attachment(&attachment_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Attachment not found"))?;
+    let data = tokio::fs::read(&attachment.file_path).await?;
+    let text = String::from_utf8_lossy(&data);
+
+    let extension = std::path::Path::new(&attachment.file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("txt");
+
+    Ok(crate::highlight::highlight_to_html(&text, extension))
+}
+
+// Server function to highlight a code block from a message. Returns one line of HTML per
+// line of code.
+#[server(HighlightCode, "/api")]
+pub async fn highlight_code(code: String, language: String) -> Result<Vec<String>> {
+    crate::auth::current_user_id()?;
+    if code.len() > MAX_HIGHLIGHT_BYTES {
+        return Err(anyhow::anyhow!("Code blocks over {} KB aren't highlighted", MAX_HIGHLIGHT_BYTES / 1024));
+    }
+    Ok(crate::highlight::highlight_lines(&code, &language))
+}
+
+// Server function to create a knowledge base
+#[server(CreateKnowledgeBase, "/api")]
+pub async fn create_knowledge_base(name: String, description: Option<String>) -> Result<KnowledgeBase> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+
+    let kb = KnowledgeBase::new(user_id, name, description);
+    state.db.create_knowledge_base(&kb).await?;
+    Ok(kb)
+}
+
+// Server function to list the user's knowledge bases
+#[server(ListKnowledgeBases, "/api")]
+pub async fn list_knowledge_bases() -> Result<Vec<KnowledgeBase>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_knowledge_bases(&user_id).await
+}
+
+// Server function to chunk, embed and store documents in a knowledge base
+#[server(AddKnowledgeDocuments, "/api")]
+pub async fn add_knowledge_documents(kb_id: String, files: Vec<FileUpload>) -> Result<Vec<KnowledgeDocument>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    let mut documents = Vec::new();
+    for file in &files {
+        let text = crate::knowledge_base::extract_text(file)?;
+        let document = crate::knowledge_base::ingest_text(
+            &state,
+            &kb_id,
+            "upload",
+            &file.name,
+            &file.name,
+            &text,
+        ).await?;
+        documents.push(document);
+    }
+    Ok(documents)
+}
+
+// Server function to list the documents in a knowledge base
+#[server(ListKnowledgeDocuments, "/api")]
+pub async fn list_knowledge_documents(kb_id: String) -> Result<Vec<KnowledgeDocument>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    state.db.get_kb_documents(&kb_id).await
+}
+
+// Server function to remove a single document and its chunks from a knowledge base
+#[server(DeleteKnowledgeDocument, "/api")]
+pub async fn delete_knowledge_document(document_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeDocument, &document_id).await?;
+
+    let document = state.db.get_kb_document(&document_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Document not found"))?;
+    crate::knowledge_base::delete_document(&state, &document).await
+}
+
+// Server function to delete a knowledge base with all of its documents
+#[server(DeleteKnowledgeBase, "/api")]
+pub async fn delete_knowledge_base(kb_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    crate::knowledge_base::delete_knowledge_base(&state, &kb_id).await
+}
+
+// Server function to link a knowledge base to a session for retrieval
+#[server(LinkSessionKnowledgeBase, "/api")]
+pub async fn link_session_knowledge_base(session_id: String, kb_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    let session = state.db.get_session(&session_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    // Knowledge bases of other organizations are treated as if they didn't exist
+    state.db.get_knowledge_base(&kb_id).await?
+        .filter(|kb| kb.org_id.as_deref().unwrap_or(DEFAULT_ORG_ID) == crate::organizations::session_org_id(&session))
+        .ok_or_else(|| anyhow::anyhow!("Knowledge base not found"))?;
+    state.db.link_session_knowledge_base(&session_id, &kb_id).await
+}
+
+// Server function to stop a session retrieving from a knowledge base
+#[server(UnlinkSessionKnowledgeBase, "/api")]
+pub async fn unlink_session_knowledge_base(session_id: String, kb_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.unlink_session_knowledge_base(&session_id, &kb_id).await
+}
+
+// Server function to get the knowledge bases linked to a session
+#[server(GetSessionKnowledgeBases, "/api")]
+pub async fn get_session_knowledge_bases(session_id: String) -> Result<Vec<String>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+
+    state.db.get_session_knowledge_base_ids(&session_id).await
+}
+
+// Server function to crawl a website into a knowledge base as a background job.
+// With `sync_interval_minutes` set, the site is re-crawled on that schedule.
+#[server(CrawlWebsite, "/api")]
+pub async fn crawl_website(
+    kb_id: String,
+    start_url: String,
+    max_depth: Option<usize>,
+    max_pages: Option<usize>,
+    same_domain_only: Option<bool>,
+    sync_interval_minutes: Option<i64>,
+) -> Result<IngestionJob> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    let start_url = start_url.trim().to_string();
+    let parsed = reqwest::Url::parse(&start_url).map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?;
+    crate::outbound::check_url(&parsed).await?;
+
+    let defaults = crate::crawler::CrawlOptions::default();
+    let source = CrawlSource::new(
+        kb_id,
+        start_url,
+        max_depth.unwrap_or(defaults.max_depth) as i64,
+        max_pages.unwrap_or(defaults.max_pages) as i64,
+        same_domain_only.unwrap_or(defaults.same_domain_only),
+        sync_interval_minutes.map(|minutes| minutes.max(5)),
+    );
+    state.db.create_crawl_source(&source).await?;
+
+    crate::crawler::start_crawl(&state, source).await
+}
+
+// Server function to list the websites crawled into a knowledge base and their last sync status
+#[server(ListCrawlSources, "/api")]
+pub async fn list_crawl_sources(kb_id: String) -> Result<Vec<CrawlSource>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    state.db.get_kb_crawl_sources(&kb_id).await
+}
+
+// Server function to change or clear (None) a crawled website's re-crawl interval
+#[server(SetCrawlSourceInterval, "/api")]
+pub async fn set_crawl_source_interval(source_id: String, sync_interval_minutes: Option<i64>) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::CrawlSource, &source_id).await?;
+
+    state.db.update_crawl_source_interval(&source_id, sync_interval_minutes.map(|minutes| minutes.max(5))).await
+}
+
+// Server function to re-crawl a website immediately instead of waiting for the schedule
+#[server(RecrawlSourceNow, "/api")]
+pub async fn recrawl_source_now(source_id: String) -> Result<IngestionJob> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::CrawlSource, &source_id).await?;
+
+    let source = state.db.get_crawl_source(&source_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Crawl source not found"))?;
+    crate::crawler::start_crawl(&state, source).await
+}
+
+// Server function to stop tracking a crawled website; pages already ingested stay in the knowledge base
+#[server(DeleteCrawlSource, "/api")]
+pub async fn delete_crawl_source(source_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::CrawlSource, &source_id).await?;
+
+    state.db.delete_crawl_source(&source_id).await
+}
+
+// Server function to add an RSS or Atom feed to a knowledge base. New items are indexed
+// every `sync_interval_minutes`; with a digest model set, that model also writes a daily
+// digest of them in a new session. Returns the feed and the job of its first poll.
+#[server(AddFeedSource, "/api")]
+pub async fn add_feed_source(
+    kb_id: String,
+    url: String,
+    sync_interval_minutes: Option<i64>,
+    digest_model_provider: Option<String>,
+    digest_model_name: Option<String>,
+) -> Result<(FeedSource, IngestionJob)> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    crate::feeds::create(&state, kb_id, url, sync_interval_minutes, digest_model_provider, digest_model_name).await
+}
+
+// Server function to list the feeds of a knowledge base and their last poll status
+#[server(ListFeedSources, "/api")]
+pub async fn list_feed_sources(kb_id: String) -> Result<Vec<FeedSource>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    state.db.get_kb_feed_sources(&kb_id).await
+}
+
+// Server function to change how often a feed is polled and which model, if any (None),
+// writes its daily digest
+#[server(UpdateFeedSource, "/api")]
+pub async fn update_feed_source(
+    source_id: String,
+    sync_interval_minutes: i64,
+    digest_model_provider: Option<String>,
+    digest_model_name: Option<String>,
+) -> Result<FeedSource> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::FeedSource, &source_id).await?;
+
+    let mut source = state.db.get_feed_source(&source_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Feed source not found"))?;
+    crate::feeds::update(&state, &mut source, sync_interval_minutes, digest_model_provider, digest_model_name).await?;
+    Ok(source)
+}
+
+// Server function to poll a feed immediately instead of waiting for the schedule
+#[server(PollFeedNow, "/api")]
+pub async fn poll_feed_now(source_id: String) -> Result<IngestionJob> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::FeedSource, &source_id).await?;
+
+    let source = state.db.get_feed_source(&source_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Feed source not found"))?;
+    crate::feeds::start_poll(&state, source).await
+}
+
+// Server function to stop polling a feed; items already indexed stay in the knowledge base
+#[server(DeleteFeedSource, "/api")]
+pub async fn delete_feed_source(source_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::FeedSource, &source_id).await?;
+
+    state.db.delete_feed_source(&source_id).await
+}
+
+// Server function to check on a background ingestion job
+#[server(GetIngestionJob, "/api")]
+pub async fn get_ingestion_job(job_id: String) -> Result<Option<IngestionJob>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    if !state.db.is_owner(OwnedResource::IngestionJob, &job_id, &user_id).await? {
+        return Ok(None);
+    }
+    state.db.get_ingestion_job(&job_id).await
+}
+
+// Server function to list a knowledge base's ingestion jobs
+#[server(ListIngestionJobs, "/api")]
+pub async fn list_ingestion_jobs(kb_id: String) -> Result<Vec<IngestionJob>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+
+    state.db.get_kb_ingestion_jobs(&kb_id).await
+}
+
+// Server function to ingest a GitHub repository into a knowledge base as a background job
+#[server(IngestGithubRepository, "/api")]
+pub async fn ingest_github_repository(
+    kb_id: String,
+    repo: String,
+    branch: Option<String>,
+    path_prefix: Option<String>,
+    extensions: Vec<String>,
+) -> Result<IngestionJob> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+    // Validate up front so a typo fails the request instead of the job
+    crate::github::parse_repo(&repo)?;
+    if let Some(branch) = &branch {
+        crate::github::check_branch(branch)?;
+    }
+
+    let options = crate::github::RepoOptions { branch, path_prefix, extensions };
+    let job = IngestionJob::new(kb_id.clone(), "github", repo.clone());
+    state.db.create_ingestion_job(&job).await?;
+
+    let work = crate::github::ingest_repository(state.clone(), job.id.clone(), user_id, kb_id, repo, options);
+    crate::knowledge_base::run_in_background(state, job.id.clone(), work);
+
+    Ok(job)
+}
+
+// Server function to set up a Notion or Google Drive connector. Returns the connector and
+// the OAuth URL the user must visit to authorize it; syncing starts after the callback.
+#[server(CreateConnector, "/api")]
+pub async fn create_connector(
+    kb_id: String,
+    provider: ConnectorProvider,
+    resource_ids: Vec<String>,
+    sync_interval_minutes: i64,
+) -> Result<(Connector, String)> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ManageKnowledge).await?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+    if resource_ids.is_empty() {
+        return Err(anyhow::anyhow!("Select at least one page or folder to sync"));
+    }
+
+    let connector = Connector::new(user_id, kb_id, provider, resource_ids, sync_interval_minutes.max(5));
+    let (authorize_url, flow_cookie) = crate::connectors::authorize_url(&state, &connector)?;
+    state.db.create_connector(&connector).await?;
+    crate::auth::set_cookie(&flow_cookie)?;
+    crate::audit::record(&state, &connector.user_id, AuditAction::ConnectorCreated, Some(&connector.id), Some(serde_json::json!({ "provider": connector.provider.to_string() }))).await?;
+
+    Ok((connector, authorize_url))
+}
+
+// Server function to list the user's connectors and their last sync status
+#[server(ListConnectors, "/api")]
+pub async fn list_connectors() -> Result<Vec<Connector>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    state.db.get_user_connectors(&user_id).await
+}
+
+// Server function to sync a connector immediately instead of waiting for the schedule
+#[server(SyncConnectorNow, "/api")]
+pub async fn sync_connector_now(connector_id: String) -> Result<IngestionJob> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Connector, &connector_id).await?;
+
+    let connector = state.db.get_connector(&connector_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Connector not found"))?;
+    if !connector.connected {
+        return Err(anyhow::anyhow!("Connector has not been authorized yet"));
+    }
+    crate::connectors::start_sync(&state, connector).await
+}
+
+// Server function to remove a connector; documents it already synced stay in the knowledge base
+#[server(DeleteConnector, "/api")]
+pub async fn delete_connector(connector_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Connector, &connector_id).await?;
+
+    state.db.delete_connector(&connector_id).await?;
+    crate::audit::record(&state, &user_id, AuditAction::ConnectorDeleted, Some(&connector_id), None).await
+}
+
+// Server function to list the prompts scheduled in a session
+#[server(ListSchedules, "/api")]
+pub async fn list_schedules(session_id: String) -> Result<Vec<Schedule>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+    state.db.get_session_schedules(&session_id).await
+}
+
+// Server function to send a prompt to a session every `interval_minutes` from `first_run_at`,
+// optionally with the text of `fetch_url` attached and each reply emailed to the user
+#[server(CreateSchedule, "/api")]
+pub async fn create_schedule(
+    session_id: String,
+    prompt: String,
+    fetch_url: Option<String>,
+    interval_minutes: i64,
+    first_run_at: chrono::DateTime<chrono::Utc>,
+    notify: bool,
+) -> Result<Schedule> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Session, &session_id).await?;
+    crate::schedules::create(&state, &user_id, session_id, prompt, fetch_url, interval_minutes, first_run_at, notify).await
+}
+
+// Server function to pause or resume a schedule. A resumed schedule skips the runs it
+// missed while paused.
+#[server(SetScheduleEnabled, "/api")]
+pub async fn set_schedule_enabled(schedule_id: String, enabled: bool) -> Result<Schedule> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Schedule, &schedule_id).await?;
+
+    let mut schedule = state.db.get_schedule(&schedule_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Schedule not found"))?;
+    if enabled && !schedule.enabled {
+        schedule.next_run_at = crate::schedules::next_run(&schedule, chrono::Utc::now());
+    }
+    schedule.enabled = enabled;
+    state.db.set_schedule_enabled(&schedule_id, enabled, schedule.next_run_at).await?;
+    Ok(schedule)
+}
+
+// Server function to delete a schedule; replies it already added stay in the session
+#[server(DeleteSchedule, "/api")]
+pub async fn delete_schedule(schedule_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    crate::auth::authorize(&state, &user_id, OwnedResource::Schedule, &schedule_id).await?;
+    state.db.delete_schedule(&schedule_id).await
+}
+
+// Server function to export the user's memory as JSON
+#[server(ExportMemory, "/api")]
+pub async fn export_memory() -> Result<MemoryExport> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::auth::current_user_id()?;
+    let memories = state.db.get_user_memory(&user_id).await?;
+    crate::audit::record(&state, &user_id, AuditAction::MemoryExported, None, None).await?;
+    Ok(crate::memory::export_memories(&memories))
+}
+
+// Server function to import a memory export; returns how many memories were added or updated
+#[server(ImportMemory, "/api")]
+pub async fn import_memory(export_json: String) -> Result<usize> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let user_id = crate::permissions::require(&state, Capability::ImportData).await?;
+
+    let export: MemoryExport = serde_json::from_str(&export_json)
+        .map_err(|e| anyhow::anyhow!("Invalid memory export: {}", e))?;
+    let existing = state.db.get_user_memory(&user_id).await?;
+    let merged = crate::memory::merge_import(&user_id, &existing, export)?;
+
+    for memory in &merged {
+        // Imported values are already known to be newer; keep what they replace in history
+        match existing.iter().find(|current| current.memory_key == memory.memory_key) {
+            Some(current) if !crate::memory::same_value(&current.memory_value, &memory.memory_value) => {
+                state.db.supersede_memory(current, memory).await?
+            }
+            _ => state.db.save_memory(memory).await?,
+        }
+    }
+    crate::audit::record(&state, &user_id, AuditAction::MemoryImported, None, Some(serde_json::json!({ "memories": merged.len() }))).await?;
+    Ok(merged.len())
+}
+
+// Server function to get everything the admin panel shows apart from usage: users,
+// provider health and the instance's configuration
+#[server(GetAdminOverview, "/api")]
+pub async fn get_admin_overview() -> Result<AdminOverview> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    crate::permissions::require(&state, Capability::ViewUsage).await?;
+    Ok(AdminOverview {
+        users: state.db.list_admin_users().await?,
+        providers: state.ai_service.provider_health().await,
+        config: InstanceConfig {
+            database_backend: state.db.backend_name().to_string(),
+            reranking_enabled: state.reranker.is_some(),
+            moderation_action: state.moderator.as_ref().map(|moderator| moderator.action().to_string()),
+            memory_half_life_days: state.memory_policy.half_life_days,
+            memory_min_confidence: state.memory_policy.min_confidence,
+            max_kb_chunks_per_user: state.usage_limits.max_kb_chunks_per_user,
+            trash_retention_days: state.trash_retention_days,
+            account_deletion_grace_days: state.account_deletion_grace_days,
+            retention_policy: state.retention_policy,
+            retention_dry_run: state.retention_dry_run,
+            admin_token_set: state.admin_token.is_some(),
+            secrets_key_set: state.secrets.is_some(),
+        },
+    })
+}
+
+// Server function to get every user's daily usage per model between two days, inclusive
+#[server(GetInstanceUsage, "/api")]
+pub async fn get_instance_usage(from: chrono::NaiveDate, to: chrono::NaiveDate) -> Result<Vec<DailyUsage>> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    crate::permissions::require(&state, Capability::ViewUsage).await?;
+    state.db.get_daily_usage(None, from, to).await
+}
+
+// Server function to change a user's role. The last admin can't be given another role, so
+// the instance always has someone who can manage it.
+#[server(SetUserRole, "/api")]
+pub async fn set_user_role(user_id: String, role: Role) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let admin_id = crate::permissions::require(&state, Capability::ManageUsers).await?;
+    let user = state.db.get_user(&user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    if user.role == Role::Admin && role != Role::Admin && state.db.count_admins().await? <= 1 {
+        return Err(anyhow::anyhow!("The last admin can't be given another role"));
+    }
+
+    state.db.set_user_role(&user_id, role).await?;
+    crate::audit::record(&state, &admin_id, AuditAction::UserRoleChanged, Some(&user_id), Some(serde_json::json!({ "from": user.role.to_string(), "to": role.to_string() }))).await
+}
+
+// Server function to turn off a user's two-factor authentication, for someone who lost
+// both their authenticator app and their backup codes
+#[server(ResetUserTwoFactor, "/api")]
+pub async fn reset_user_two_factor(user_id: String) -> Result<()> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let admin_id = crate::permissions::require(&state, Capability::ManageUsers).await?;
+    state.db.delete_two_factor(&user_id).await?;
+    crate::audit::record(&state, &admin_id, AuditAction::TwoFactorChanged, Some(&user_id), Some(serde_json::json!({ "enabled": false, "reset": true }))).await
+}
+
+// Server function to end every login session of a user
+#[server(SignOutUser, "/api")]
+pub async fn sign_out_user(user_id: String) -> Result<u64> {
+    let state = use_context::<AppState>()
+        .ok_or_else(|| anyhow::anyhow!("AppState not found"))?;
+
+    let admin_id = crate::permissions::require(&state, Capability::ManageUsers).await?;
+    let ended = state.db.delete_user_auth_sessions(&user_id).await?;
+    crate::audit::record(&state, &admin_id, AuditAction::UserSignedOut, Some(&user_id), Some(serde_json::json!({ "sessions": ended }))).await?;
+    Ok(ended)
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use crate::{api::AppState, models::*, secrets::secret_box};
+
+// Where a user's GitHub token is kept among their provider keys
+pub const GITHUB: &str = "github";
+
+// The key a session's chats are sent with: the user's own key, then their organization's,
+// and None to fall back to the instance key. Fails if the organization doesn't allow
+// the session's provider, whoever's key it would be.
+pub async fn provider_key(state: &AppState, session: &ChatSession) -> Result<Option<String>> {
+    let org_key = crate::organizations::provider_key(state, session).await?;
+    match state.db.get_user_api_key_sealed(&session.user_id, &session.model_provider).await? {
+        Some(sealed) => Ok(Some(secret_box(state)?.open(&sealed)?)),
+        None => Ok(org_key),
+    }
+}
+
+// Checks the key with the provider, then stores it sealed
+pub async fn save(state: &AppState, user_id: &str, provider: AIProvider, api_key: &str) -> Result<StoredApiKey> {
+    let secret_box = secret_box(state)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return Err(anyhow::anyhow!("API key can't be empty"));
+    }
+    if let AIProvider::Ollama = provider {
+        return Err(anyhow::anyhow!("Ollama doesn't use API keys"));
+    }
+    state.ai_service.validate_api_key(provider.clone(), api_key).await?;
+
+    let fingerprint = crate::secrets::fingerprint(api_key);
+    state.db.set_user_api_key(user_id, &provider.to_string(), &secret_box.seal(api_key)?, &fingerprint).await?;
+    Ok(StoredApiKey {
+        provider: provider.to_string(),
+        fingerprint,
+        updated_at: chrono::Utc::now(),
+    })
+}
+
+// The user's own GitHub token, if they saved one
+pub async fn github_token(state: &AppState, user_id: &str) -> Result<Option<String>> {
+    match state.db.get_user_api_key_sealed(user_id, GITHUB).await? {
+        Some(sealed) => Ok(Some(secret_box(state)?.open(&sealed)?)),
+        None => Ok(None),
+    }
+}
+
+// Checks the token with GitHub, then stores it sealed like a provider key
+pub async fn save_github_token(state: &AppState, user_id: &str, token: &str) -> Result<StoredApiKey> {
+    let secret_box = secret_box(state)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(anyhow::anyhow!("Token can't be empty"));
+    }
+    crate::github::check_token(token).await?;
+
+    let fingerprint = crate::secrets::fingerprint(token);
+    state.db.set_user_api_key(user_id, GITHUB, &secret_box.seal(token)?, &fingerprint).await?;
+    Ok(StoredApiKey {
+        provider: GITHUB.to_string(),
+        fingerprint,
+        updated_at: chrono::Utc::now(),
+    })
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use chrono::{Duration, Utc};
+use leptos::server_fn::ServerFn;
+use sha2::{Digest, Sha256};
+use crate::{api::{self, AppState}, models::*};
+
+// Tokens are this prefix and 64 hex digits; the prefix makes leaked ones easy to search for
+const TOKEN_PREFIX: &str = "aibot_pat_";
+// How much of a token is kept in the clear to tell tokens apart
+const SHOWN_PREFIX_LENGTH: usize = TOKEN_PREFIX.len() + 6;
+const MAX_NAME_LENGTH: usize = 100;
+// A token's last use is only written when it is this much out of date
+const LAST_USED_RESOLUTION_MINUTES: i64 = 5;
+const INVALID_TOKEN: &str = "Invalid or expired API token";
+
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// Server functions that could take over or lock up the account, and the admin endpoints
+// that read or replace the whole database. They need a signed-in browser, so a leaked
+// token can't be used to make more tokens, turn off two-factor or take every password hash.
+fn browser_only(path: &str) -> bool {
+    [
+        api::CreateApiToken::PATH,
+        api::DeleteApiToken::PATH,
+        api::StartTwoFactorSetup::PATH,
+        api::ConfirmTwoFactorSetup::PATH,
+        api::DisableTwoFactor::PATH,
+        api::RegenerateBackupCodes::PATH,
+        api::RevokeLoginSession::PATH,
+        api::RevokeOtherLoginSessions::PATH,
+        api::RequestAccountDeletion::PATH,
+        api::CancelAccountDeletion::PATH,
+        "/api/admin/backup",
+        "/api/admin/restore",
+    ].contains(&path)
+}
+
+// The token in an `Authorization: Bearer` header, if there is one
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// Creates a token for the user. Its secret is returned only this once.
+pub async fn create(state: &AppState, user_id: &str, name: &str, scope: TokenScope, expires_in_days: Option<i64>) -> Result<NewApiToken> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(anyhow::anyhow!("Give the token a name of up to {} characters", MAX_NAME_LENGTH));
+    }
+    if expires_in_days.is_some_and(|days| days < 1) {
+        return Err(anyhow::anyhow!("Tokens must last at least a day"));
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let secret = format!("{}{}", TOKEN_PREFIX, bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let now = Utc::now();
+    let token = ApiToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        token_prefix: secret[..SHOWN_PREFIX_LENGTH].to_string(),
+        scope,
+        last_used_at: None,
+        expires_at: expires_in_days.map(|days| now + Duration::days(days)),
+        created_at: now,
+    };
+    state.db.create_api_token(user_id, &token, &token_hash(&secret)).await?;
+    Ok(NewApiToken { token, secret })
+}
+
+// The user a bearer token acts for on this request. Fails with the response to send when
+// the token is unknown or expired, or its scope doesn't allow the request.
+pub async fn authenticate(state: &AppState, token: &str, method: &Method, path: &str) -> Result<String, (StatusCode, String)> {
+    let now = Utc::now();
+    let (user_id, id, scope, last_used_at) = state.db.get_api_token_by_hash(&token_hash(token), now).await
+        .map_err(|e| {
+            tracing::error!("Failed to look up API token: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
+        })?
+        .ok_or((StatusCode::UNAUTHORIZED, INVALID_TOKEN.to_string()))?;
+
+    let read_only = matches!(*method, Method::GET | Method::HEAD);
+    if TokenScope::from(scope) == TokenScope::Read && !read_only {
+        return Err((StatusCode::FORBIDDEN, "This API token can only make GET requests".to_string()));
+    }
+    if browser_only(path) {
+        return Err((StatusCode::FORBIDDEN, "API tokens can't do this; sign in instead".to_string()));
+    }
+
+    if last_used_at.map_or(true, |at| at + Duration::minutes(LAST_USED_RESOLUTION_MINUTES) <= now) {
+        if let Err(e) = state.db.touch_api_token(&id, now).await {
+            tracing::error!("Failed to update API token: {}", e);
+        }
+    }
+    Ok(user_id)
+}
@@ -0,0 +1,126 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use crate::rest_api::ErrorBody;
+
+// The REST API is served under `/api/v{n}`. A released version's requests and responses
+// never change in ways that break callers. When a model has to, the new shape goes in the
+// next version, and the old version's handlers stay as shims converting to and from the
+// current models, like the `V1` structs in `rest_api`, until the version's sunset. Until
+// then, responses from a deprecated version carry `Deprecation`, `Sunset` and a `Link` to
+// its successor, so integrations can notice before anything breaks.
+pub const VERSIONS_PATH: &str = "/api/versions";
+
+pub struct ApiVersion {
+    pub number: u32,
+    // Days, as YYYY-MM-DD in UTC; a version is deprecated before its sunset is announced
+    pub deprecated_on: Option<&'static str>,
+    // Requests to the version are answered 410 Gone from this day
+    pub sunset_on: Option<&'static str>,
+}
+
+// Oldest first; the last one is the current version
+pub const VERSIONS: &[ApiVersion] = &[
+    ApiVersion { number: 1, deprecated_on: None, sunset_on: None },
+];
+
+fn latest() -> u32 {
+    VERSIONS.last().map(|version| version.number).unwrap_or(1)
+}
+
+fn day(date: Option<&str>) -> Option<DateTime<Utc>> {
+    date.and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|at| at.and_utc())
+}
+
+// The version number a path asks for: 2 for `/api/v2/sessions`
+fn requested_version(path: &str) -> Option<u32> {
+    let rest = path.strip_prefix("/api/v")?;
+    let number = rest.split('/').next()?;
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+fn successor_link() -> String {
+    format!("</api/v{}/openapi.json>; rel=\"successor-version\"", latest())
+}
+
+// Answers unknown versions with 404 and retired ones with 410, and marks responses from
+// deprecated versions. Everything outside `/api/v{n}` passes through untouched.
+pub async fn versioning(request: Request, next: Next) -> Response {
+    let Some(number) = requested_version(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let Some(version) = VERSIONS.iter().find(|version| version.number == number) else {
+        let error = format!("API version v{} not found; the current version is v{}", number, latest());
+        return (StatusCode::NOT_FOUND, Json(ErrorBody { error })).into_response();
+    };
+
+    let sunset = day(version.sunset_on);
+    if sunset.is_some_and(|sunset| sunset <= Utc::now()) {
+        let error = format!("API version v{} was retired; use v{}", number, latest());
+        let mut response = (StatusCode::GONE, Json(ErrorBody { error })).into_response();
+        if let Ok(link) = HeaderValue::from_str(&successor_link()) {
+            response.headers_mut().insert(axum::http::header::LINK, link);
+        }
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(HeaderName::from_static("api-version"), HeaderValue::from(number));
+    if let Some(deprecated) = day(version.deprecated_on) {
+        // RFC 9745 gives the date as a Unix timestamp, RFC 8594 the sunset as an HTTP date
+        if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecated.timestamp())) {
+            headers.insert(HeaderName::from_static("deprecation"), value);
+        }
+        if let Some(value) = sunset.and_then(|sunset| HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).ok()) {
+            headers.insert(HeaderName::from_static("sunset"), value);
+        }
+        if let Ok(link) = HeaderValue::from_str(&successor_link()) {
+            headers.append(axum::http::header::LINK, link);
+        }
+    }
+    response
+}
+
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub version: String,
+    // current, supported, deprecated or retired
+    pub status: &'static str,
+    pub deprecated_on: Option<&'static str>,
+    pub sunset_on: Option<&'static str>,
+    pub openapi: String,
+}
+
+// Lists the API's versions and where each stands, for integrations checking before they
+// upgrade. Needs no token.
+pub async fn list_versions() -> Json<Vec<VersionInfo>> {
+    let now = Utc::now();
+    let current = latest();
+    Json(VERSIONS.iter().map(|version| VersionInfo {
+        version: format!("v{}", version.number),
+        status: if day(version.sunset_on).is_some_and(|sunset| sunset <= now) {
+            "retired"
+        } else if version.deprecated_on.is_some() {
+            "deprecated"
+        } else if version.number == current {
+            "current"
+        } else {
+            "supported"
+        },
+        deprecated_on: version.deprecated_on,
+        sunset_on: version.sunset_on,
+        openapi: format!("/api/v{}/openapi.json", version.number),
+    }).collect())
+}
@@ -4,7 +4,12 @@ use leptos_router::{
     components::{Route, Router, Routes},
     StaticSegment,
 };
-use crate::components::chat_box::ChatBox;
+use crate::components::{
+    admin::AdminPage,
+    auth::{ForgotPasswordPage, LoginPage, RegisterPage, ResetPasswordPage},
+    chat_box::ChatBox,
+    security::SecurityPage,
+};
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
     view! {
@@ -16,6 +21,15 @@ pub fn shell(options: LeptosOptions) -> impl IntoView {
                 <AutoReload options=options.clone() />
                 <HydrationScripts options/>
                 <MetaTags/>
+                // KaTeX typesets math in replies; see components::markdown. It is served from
+                // public/vendor, filled by scripts/vendor-assets.sh, so no other origin runs
+                // scripts on this one.
+                <link rel="stylesheet" href="/vendor/katex/katex.min.css"/>
+                <script defer src="/vendor/katex/katex.min.js"></script>
+                // Mermaid draws `mermaid` code blocks as diagrams; vendored like KaTeX
+                <script defer src="/vendor/mermaid/mermaid.min.js"></script>
+                // PDF.js draws the pages of PDF attachments in their preview
+                <script defer src="/vendor/pdfjs/pdf.min.js"></script>
             </head>
             <body>
                 <App/>
@@ -42,6 +56,12 @@ pub fn App() -> impl IntoView {
             <main>
                 <Routes fallback=|| "Page not found.".into_view()>
                     <Route path=StaticSegment("") view=HomePage/>
+                    <Route path=StaticSegment("login") view=LoginPage/>
+                    <Route path=StaticSegment("register") view=RegisterPage/>
+                    <Route path=StaticSegment("forgot-password") view=ForgotPasswordPage/>
+                    <Route path=StaticSegment("reset-password") view=ResetPasswordPage/>
+                    <Route path=StaticSegment("admin") view=AdminPage/>
+                    <Route path=StaticSegment("security") view=SecurityPage/>
                 </Routes>
             </main>
         </Router>
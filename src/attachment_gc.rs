@@ -0,0 +1,58 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+use crate::api::AppState;
+
+const UPLOADS_DIR: &str = "uploads";
+// Uploads are written before their attachment row is saved at the end of a chat turn,
+// so recent files without a row may just be in flight
+const STORED_FILE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub orphaned_attachments: usize,
+    pub removed_files: usize,
+    pub reclaimed_bytes: u64,
+}
+
+// Deletes attachment rows whose message or session is gone, then removes stored files
+// that no attachment row points at
+pub async fn collect(state: &AppState) -> Result<GcReport> {
+    let mut report = GcReport::default();
+
+    for attachment in state.db.get_orphaned_attachments().await? {
+        state.db.delete_attachment(&attachment.id).await?;
+        report.orphaned_attachments += 1;
+    }
+
+    let referenced: HashSet<String> = state.db.get_attachment_file_paths().await?.into_iter().collect();
+    let cutoff = SystemTime::now() - STORED_FILE_GRACE;
+
+    let mut entries = match tokio::fs::read_dir(UPLOADS_DIR).await {
+        Ok(entries) => entries,
+        // Nothing has been uploaded yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() || metadata.modified()? > cutoff {
+            continue;
+        }
+        // Stored paths are relative, e.g. "uploads/<uuid>.pdf"
+        let path = format!("{}/{}", UPLOADS_DIR, entry.file_name().to_string_lossy());
+        if referenced.contains(&path) {
+            continue;
+        }
+
+        match tokio::fs::remove_file(entry.path()).await {
+            Ok(()) => {
+                report.removed_files += 1;
+                report.reclaimed_bytes += metadata.len();
+            }
+            Err(e) => tracing::warn!("Failed to remove unreferenced upload {}: {}", path, e),
+        }
+    }
+
+    Ok(report)
+}
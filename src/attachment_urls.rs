@@ -0,0 +1,84 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::{api::AppState, models::*};
+
+// Attachments are served from `/api/attachments/<id>?expires=<unix time>&sig=<signature>`.
+// The signature is the authorization, so a link works for anyone it is shared with until it
+// expires, and a bare attachment id gets nobody anything.
+fn mac(key: &[u8], attachment_id: &str, expires_at: i64) -> Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| anyhow::anyhow!("Invalid attachment URL key: {}", e))?;
+    mac.update(format!("{}.{}", attachment_id, expires_at).as_bytes());
+    Ok(mac)
+}
+
+fn signed_url(key: &[u8], attachment_id: &str, expires_at: i64) -> Result<String> {
+    let signature = mac(key, attachment_id, expires_at)?.finalize().into_bytes();
+    Ok(format!("/api/attachments/{}?expires={}&sig={}", attachment_id, expires_at, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+fn signature_valid(key: &[u8], attachment_id: &str, expires_at: i64, signature: &str, now: DateTime<Utc>) -> bool {
+    if expires_at < now.timestamp() {
+        return false;
+    }
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else { return false };
+    mac(key, attachment_id, expires_at)
+        .map(|mac| mac.verify_slice(&signature).is_ok())
+        .unwrap_or(false)
+}
+
+// A link to the attachment that works for `ATTACHMENT_URL_TTL_HOURS` from `now`
+pub fn sign(state: &AppState, attachment_id: &str, now: DateTime<Utc>) -> Result<String> {
+    let expires_at = (now + Duration::hours(state.attachment_url_ttl_hours)).timestamp();
+    signed_url(&state.attachment_url_key, attachment_id, expires_at)
+}
+
+// Fills in `url` on attachments about to be sent to the browser
+pub fn sign_all(state: &AppState, attachments: Vec<FileAttachment>) -> Result<Vec<FileAttachment>> {
+    let now = Utc::now();
+    attachments.into_iter()
+        .map(|attachment| Ok(FileAttachment { url: Some(sign(state, &attachment.id, now)?), ..attachment }))
+        .collect()
+}
+
+// True when the link was signed by this server for this attachment and hasn't expired
+pub fn verify(state: &AppState, attachment_id: &str, expires_at: i64, signature: &str, now: DateTime<Utc>) -> bool {
+    signature_valid(&state.attachment_url_key, attachment_id, expires_at, signature, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"attachment url test key";
+
+    // The `sig` parameter of a link signed for `attachment_id`
+    fn signature(attachment_id: &str, expires_at: i64) -> String {
+        let url = signed_url(KEY, attachment_id, expires_at).unwrap();
+        url.split_once("&sig=").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn accepts_its_own_links_until_they_expire() {
+        let now = Utc::now();
+        let expires_at = now.timestamp() + 60;
+        let sig = signature("attachment-1", expires_at);
+        assert!(signature_valid(KEY, "attachment-1", expires_at, &sig, now));
+        assert!(!signature_valid(KEY, "attachment-1", expires_at, &sig, now + Duration::seconds(61)));
+    }
+
+    #[test]
+    fn rejects_links_changed_or_signed_elsewhere() {
+        let now = Utc::now();
+        let expires_at = now.timestamp() + 60;
+        let sig = signature("attachment-1", expires_at);
+        assert!(!signature_valid(KEY, "attachment-2", expires_at, &sig, now));
+        assert!(!signature_valid(KEY, "attachment-1", expires_at + 3600, &sig, now));
+        assert!(!signature_valid(b"another key", "attachment-1", expires_at, &sig, now));
+        assert!(!signature_valid(KEY, "attachment-1", expires_at, "not base64!", now));
+        assert!(!signature_valid(KEY, "attachment-1", expires_at, "", now));
+    }
+}
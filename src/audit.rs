@@ -0,0 +1,28 @@
+use anyhow::Result;
+use crate::{api::AppState, models::*};
+
+// Actor recorded for requests made with the admin token
+pub const ADMIN_ACTOR: &str = "admin";
+// Actor recorded for signed-out clients, such as failed sign-ins
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+// Actor recorded for roles synced from the groups a single sign-on provider reports
+pub const SSO_ACTOR: &str = "sso";
+
+// Appends an event to the audit log. Called after the action succeeded, with the
+// signed-in user as the actor.
+pub async fn record(
+    state: &AppState,
+    user_id: &str,
+    action: AuditAction,
+    target_id: Option<&str>,
+    details: Option<serde_json::Value>,
+) -> Result<()> {
+    state.db.record_audit_entry(&AuditEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        action: action.to_string(),
+        target_id: target_id.map(str::to_string),
+        details,
+        created_at: chrono::Utc::now(),
+    }).await
+}
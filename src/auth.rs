@@ -0,0 +1,304 @@
+use anyhow::Result;
+use argon2::{
+    password_hash::{rand_core::{OsRng, RngCore}, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{Duration, Utc};
+use leptos::{prelude::use_context, server_fn::ServerFn};
+use sha2::{Digest, Sha256};
+use crate::{api::{self, AppState}, database::OwnedResource, models::*, rate_limit::ClientIp};
+
+pub const SESSION_COOKIE: &str = "aibot_session";
+const SESSION_TTL_DAYS: i64 = 30;
+// User agents are kept to show which device a session is on; longer ones are cut off
+const MAX_USER_AGENT_LENGTH: usize = 512;
+// A session's last seen time and address are only written when they are this much out of
+// date, not on every request
+const LAST_SEEN_RESOLUTION_MINUTES: i64 = 5;
+pub(crate) const NOT_SIGNED_IN: &str = "Not signed in";
+
+// The signed-in user, added to the request extensions by `session_middleware`. Handlers
+// take it as an argument to require a signed-in user.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuthUser>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, NOT_SIGNED_IN.to_string()))
+    }
+}
+
+// For handlers that also serve callers who aren't signed in, like the admin endpoints
+impl<S: Send + Sync> OptionalFromRequestParts<S> for AuthUser {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<AuthUser>().cloned())
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+// Compares a secret someone sent with the real one in constant time, so how long a wrong
+// guess takes says nothing about how much of it was right
+pub fn secrets_match(provided: &str, expected: &str) -> bool {
+    let (provided, expected) = (Sha256::digest(provided.as_bytes()), Sha256::digest(expected.as_bytes()));
+    provided.iter().zip(expected.iter()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+// Login sessions are stored under the digest of their token, never the token itself
+fn session_id(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn session_cookie(token: &str, max_age_secs: i64) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}", SESSION_COOKIE, token, max_age_secs)
+}
+
+// The value of the request's cookie called `name`
+pub fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value.to_string())
+}
+
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    cookie_value(headers, SESSION_COOKIE)
+}
+
+// Resolves the session cookie of every request to the signed-in user. Requests without a
+// valid session pass through unchanged. API requests with a bearer token are authenticated
+// by the token alone, never the cookie; ADMIN_TOKEN is left for the admin endpoints to check.
+pub async fn session_middleware(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if path.starts_with("/api/") {
+        if let Some(token) = crate::api_tokens::bearer_token(request.headers()) {
+            let admin_token = path.starts_with("/api/admin/")
+                && state.admin_token.as_deref().is_some_and(|expected| secrets_match(token, expected));
+            if !admin_token {
+                match crate::api_tokens::authenticate(&state, token, request.method(), path).await {
+                    Ok(user_id) => {
+                        request.extensions_mut().insert(AuthUser { user_id });
+                    }
+                    Err(rejection) => return rejection.into_response(),
+                }
+            }
+            return next.run(request).await;
+        }
+    }
+
+    if let Some(token) = session_token(request.headers()) {
+        let id = session_id(&token);
+        let now = Utc::now();
+        match state.db.get_auth_session_user(&id, now).await {
+            Ok(Some((user_id, last_seen_at))) => {
+                if last_seen_at.map_or(true, |at| at + Duration::minutes(LAST_SEEN_RESOLUTION_MINUTES) <= now) {
+                    let ip = request.extensions().get::<ClientIp>().map(|ClientIp(ip)| ip.to_string());
+                    if let Err(e) = state.db.touch_auth_session(&id, now, ip.as_deref()).await {
+                        tracing::error!("Failed to update login session: {}", e);
+                    }
+                }
+                request.extensions_mut().insert(AuthUser { user_id });
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to look up login session: {}", e),
+        }
+    }
+    next.run(request).await
+}
+
+// Rejects calls to the API from anyone who isn't signed in, apart from the server functions
+// used to sign in, single sign-on, the REST API's OpenAPI document and version list, the
+// admin endpoints, which also take ADMIN_TOKEN, inbound emails, which check their own secret, and attachment
+// links, which carry their own signature
+pub async fn require_sign_in(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let public = !path.starts_with("/api/")
+        || path.starts_with("/api/admin/")
+        || path.starts_with("/api/attachments/")
+        || path.starts_with("/api/auth/oidc/")
+        || path == crate::rest_api::OPENAPI_PATH
+        || path == crate::api_versions::VERSIONS_PATH
+        || path == crate::email_gateway::INBOUND_PATH
+        || [
+            api::Register::PATH,
+            api::Login::PATH,
+            api::Logout::PATH,
+            api::GetCurrentUser::PATH,
+            api::GuestAccessEnabled::PATH,
+            api::SingleSignOnProvider::PATH,
+            api::CompleteSingleSignOn::PATH,
+            api::StartGuestSession::PATH,
+            api::RequestPasswordReset::PATH,
+            api::ResetPassword::PATH,
+        ].contains(&path);
+    if !public && request.extensions().get::<AuthUser>().is_none() {
+        return (StatusCode::UNAUTHORIZED, NOT_SIGNED_IN).into_response();
+    }
+    next.run(request).await
+}
+
+// The user signed in on the request a server function is handling, if any
+pub fn signed_in_user_id() -> Option<String> {
+    use_context::<Parts>()?
+        .extensions
+        .get::<AuthUser>()
+        .map(|user| user.user_id.clone())
+}
+
+// The user a server function acts for. Every server function that touches user data
+// gets its user from here.
+pub fn current_user_id() -> Result<String> {
+    signed_in_user_id().ok_or_else(|| anyhow::anyhow!(NOT_SIGNED_IN))
+}
+
+// Fails unless `user_id` owns the record. Someone else's record gets the same "not found"
+// error as a missing one, so ids can't be probed.
+pub async fn authorize(state: &AppState, user_id: &str, resource: OwnedResource, id: &str) -> Result<()> {
+    if !state.db.is_owner(resource, id, user_id).await? {
+        return Err(anyhow::anyhow!("{} not found", resource));
+    }
+    Ok(())
+}
+
+// Starts a login session for `user_id` on the browser that sent `headers`, and returns the
+// Set-Cookie value that signs the browser in
+pub async fn create_login_session(state: &AppState, user_id: &str, headers: &HeaderMap, ip: Option<&str>) -> Result<String> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    let expires_at = Utc::now() + Duration::days(SESSION_TTL_DAYS);
+    let user_agent = headers.get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect::<String>());
+    state.db.create_auth_session(&session_id(&token), user_id, expires_at, user_agent.as_deref(), ip).await?;
+    Ok(session_cookie(&token, SESSION_TTL_DAYS * 24 * 60 * 60))
+}
+
+// Starts a login session for `user_id` and sets its cookie on the server function's response
+pub async fn sign_in(state: &AppState, user_id: &str) -> Result<()> {
+    let headers = use_context::<Parts>().map(|parts| parts.headers).unwrap_or_default();
+    let cookie = create_login_session(state, user_id, &headers, client_ip().as_deref()).await?;
+    set_cookie(&cookie)
+}
+
+// The value of the cookie called `name` on the request a server function is handling
+pub fn request_cookie(name: &str) -> Option<String> {
+    use_context::<Parts>().and_then(|parts| cookie_value(&parts.headers, name))
+}
+
+// The address the current request came from, if known
+pub fn client_ip() -> Option<String> {
+    use_context::<Parts>()?
+        .extensions
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| ip.to_string())
+}
+
+// The id of the login session the current request is signed in with
+pub fn current_session_id() -> Option<String> {
+    use_context::<Parts>()
+        .and_then(|parts| session_token(&parts.headers))
+        .map(|token| session_id(&token))
+}
+
+// Ends the login session of the current request and clears its cookie
+pub async fn sign_out(state: &AppState) -> Result<()> {
+    if let Some(id) = current_session_id() {
+        state.db.delete_auth_session(&id).await?;
+    }
+    set_cookie(&session_cookie("", 0))
+}
+
+pub(crate) fn set_cookie(cookie: &str) -> Result<()> {
+    let response = use_context::<leptos_axum::ResponseOptions>()
+        .ok_or_else(|| anyhow::anyhow!("ResponseOptions not found"))?;
+    response.append_header(header::SET_COOKIE, HeaderValue::from_str(cookie)?);
+    Ok(())
+}
+
+// Creates an account with a password. The first account registered on an instance takes
+// over the default user, so conversations from before accounts existed stay with it, and
+// is its admin. Otherwise a guest signing up becomes the account, keeping their chats.
+pub async fn register(state: &AppState, name: Option<String>, email: &str, password: &str, guest: Option<User>) -> Result<User> {
+    if !email.contains('@') {
+        return Err(anyhow::anyhow!("Enter a valid email address"));
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(anyhow::anyhow!("Passwords must be at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+    if state.db.get_user_by_email(email).await?.is_some() {
+        return Err(anyhow::anyhow!("An account with this email already exists"));
+    }
+
+    let password_hash = hash_password(password)?;
+    let first_account = state.db.count_password_users().await? == 0;
+    if first_account {
+        if let Some(mut user) = state.db.get_user(DEFAULT_USER_ID).await? {
+            state.db.claim_user(&user.id, name.as_deref(), email, &password_hash).await?;
+            state.db.set_user_role(&user.id, Role::Admin).await?;
+            user.name = name.or(user.name);
+            user.email = Some(email.to_string());
+            user.role = Role::Admin;
+            return Ok(user);
+        }
+    }
+
+    if let Some(mut user) = guest.filter(|_| !first_account) {
+        state.db.claim_user(&user.id, name.as_deref(), email, &password_hash).await?;
+        state.db.set_user_role(&user.id, Role::Member).await?;
+        user.name = name.or(user.name);
+        user.email = Some(email.to_string());
+        user.role = Role::Member;
+        return Ok(user);
+    }
+
+    let mut user = User::new(name, Some(email.to_string()));
+    if first_account {
+        user.role = Role::Admin;
+    }
+    state.db.create_user(&user).await?;
+    state.db.set_password_hash(&user.id, &password_hash).await?;
+    Ok(user)
+}
+
+// Checks an email and password, giving the same error whether the account is missing or
+// the password is wrong
+pub async fn authenticate(state: &AppState, email: &str, password: &str) -> Result<User> {
+    let invalid = || anyhow::anyhow!("Incorrect email or password");
+    let user = state.db.get_user_by_email(email).await?.ok_or_else(invalid)?;
+    let password_hash = state.db.get_password_hash(&user.id).await?.ok_or_else(invalid)?;
+    if !verify_password(password, &password_hash) {
+        return Err(invalid());
+    }
+    Ok(user)
+}
@@ -0,0 +1,258 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::{api::AppState, database::DbPool};
+
+pub const BACKUP_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SQLITE_ENTRY: &str = "database.sqlite";
+// pg_dump custom format, restored with pg_restore
+const POSTGRES_ENTRY: &str = "database.dump";
+const UPLOADS_DIR: &str = "uploads";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupBackend {
+    Sqlite,
+    Postgres,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub backend: BackupBackend,
+    // Newest migration applied when the backup was taken
+    pub schema_version: i64,
+    pub upload_count: usize,
+}
+
+fn backend(state: &AppState) -> BackupBackend {
+    match state.db.pool() {
+        DbPool::Sqlite(_) => BackupBackend::Sqlite,
+        DbPool::Postgres(_) => BackupBackend::Postgres,
+    }
+}
+
+// Removes the scratch file when dropped so failed backups and restores don't leave it behind
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("aibot-backup-{}.{}", uuid::Uuid::new_v4(), extension)))
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap_or_default()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Builds a zip archive with a manifest, a consistent snapshot of the database and
+// every file in the uploads directory
+pub async fn create_backup(state: &AppState) -> Result<(BackupManifest, Vec<u8>)> {
+    let backend = backend(state);
+    let snapshot = TempFile::new("db");
+    match backend {
+        BackupBackend::Sqlite => state.db.vacuum_into(snapshot.path()).await?,
+        BackupBackend::Postgres => {
+            let (url, password) = pg_connection(state)?;
+            run_tool("pg_dump", &["--format=custom", "--no-owner", "--no-acl", "--file", snapshot.path(), "--dbname", &url], password.as_deref()).await?
+        }
+    }
+    let database = tokio::fs::read(&snapshot.0).await?;
+    let uploads = list_uploads(Path::new(UPLOADS_DIR)).await?;
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        backend,
+        schema_version: state.db.schema_version().await?,
+        upload_count: uploads.len(),
+    };
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file(MANIFEST_ENTRY, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+
+    zip.start_file(database_entry(backend), options)?;
+    zip.write_all(&database)?;
+
+    for path in &uploads {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(e) => {
+                // Deleted between listing and reading
+                tracing::warn!("Skipping upload {} in backup: {}", path.display(), e);
+                continue;
+            }
+        };
+        zip.start_file(path.to_string_lossy().replace('\\', "/"), options)?;
+        zip.write_all(&data)?;
+    }
+
+    Ok((manifest, zip.finish()?.into_inner()))
+}
+
+// Validates a backup archive and replaces the database and uploads with its contents.
+// Nothing is changed unless the manifest, database snapshot and file paths all check out.
+pub async fn restore_backup(state: &AppState, archive: &[u8]) -> Result<BackupManifest> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))
+        .map_err(|e| anyhow::anyhow!("Not a valid backup archive: {}", e))?;
+
+    let manifest: BackupManifest = serde_json::from_slice(&read_entry(&mut zip, MANIFEST_ENTRY)?)
+        .map_err(|e| anyhow::anyhow!("Invalid backup manifest: {}", e))?;
+    if manifest.version > BACKUP_VERSION {
+        return Err(anyhow::anyhow!("Unsupported backup version {}", manifest.version));
+    }
+    if manifest.backend != backend(state) {
+        return Err(anyhow::anyhow!("Backup was taken from a {:?} database and can't be restored into this one", manifest.backend));
+    }
+    if manifest.schema_version > state.db.latest_schema_version() {
+        return Err(anyhow::anyhow!("Backup was taken by a newer version of the app (schema {})", manifest.schema_version));
+    }
+
+    let snapshot = TempFile::new("db");
+    tokio::fs::write(&snapshot.0, read_entry(&mut zip, database_entry(manifest.backend))?).await?;
+
+    // Collect uploads up front so a bad path rejects the whole archive
+    let mut uploads = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_dir() || !file.name().starts_with(&format!("{}/", UPLOADS_DIR)) {
+            continue;
+        }
+        let path = file.enclosed_name()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow::anyhow!("Backup contains an unsafe path: {}", file.name()))?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        uploads.push((path, data));
+    }
+
+    match manifest.backend {
+        BackupBackend::Sqlite => {
+            let target = state.db.sqlite_path()
+                .ok_or_else(|| anyhow::anyhow!("Only file-backed SQLite databases can be restored"))?;
+            let source = snapshot.0.clone();
+            let key = state.db.sqlite_key().map(str::to_string);
+            tokio::task::spawn_blocking(move || restore_sqlite(&source, &target, key.as_deref())).await??;
+        }
+        BackupBackend::Postgres => {
+            let (url, password) = pg_connection(state)?;
+            run_tool("pg_restore", &["--list", snapshot.path()], None).await?;
+            run_tool("pg_restore", &["--clean", "--if-exists", "--no-owner", "--no-acl", "--single-transaction", "--dbname", &url, snapshot.path()], password.as_deref()).await?;
+        }
+    }
+
+    // An older backup is brought up to the current schema
+    state.db.run_migrations().await?;
+    state.db.rebuild_search_index().await?;
+
+    for (path, data) in uploads {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, data).await?;
+    }
+
+    Ok(manifest)
+}
+
+fn database_entry(backend: BackupBackend) -> &'static str {
+    match backend {
+        BackupBackend::Sqlite => SQLITE_ENTRY,
+        BackupBackend::Postgres => POSTGRES_ENTRY,
+    }
+}
+
+fn read_entry(zip: &mut zip::ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>> {
+    let mut file = zip.by_name(name)
+        .map_err(|_| anyhow::anyhow!("Backup is missing {}", name))?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// Checks the snapshot, then copies it page by page over the live database with SQLite's
+// online backup API, so open pool connections see the restored data without reconnecting.
+// Snapshots of an encrypted database are encrypted with the same key.
+fn restore_sqlite(source: &Path, target: &Path, key: Option<&str>) -> Result<()> {
+    let snapshot = rusqlite::Connection::open_with_flags(source, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if let Some(key) = key {
+        snapshot.pragma_update(None, "key", key)?;
+    }
+    let integrity: String = snapshot.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(anyhow::anyhow!("Backup database failed its integrity check: {}", integrity));
+    }
+    drop(snapshot);
+
+    let mut live = rusqlite::Connection::open(target)?;
+    if let Some(key) = key {
+        live.pragma_update(None, "key", key)?;
+    }
+    live.restore(rusqlite::DatabaseName::Main, source, None::<fn(rusqlite::backup::Progress)>)?;
+    Ok(())
+}
+
+// The database URL without its password, and the password. Arguments can be read by anyone
+// who can list the host's processes, so the password goes to the tools in PGPASSWORD instead.
+fn pg_connection(state: &AppState) -> Result<(String, Option<String>)> {
+    let mut url = reqwest::Url::parse(state.db.url())?;
+    let password = url.password()
+        .map(|password| percent_encoding::percent_decode_str(password).decode_utf8().map(|password| password.into_owned()))
+        .transpose()?;
+    url.set_password(None).map_err(|_| anyhow::anyhow!("DATABASE_URL can't hold a password"))?;
+    Ok((url.to_string(), password))
+}
+
+// Runs one of the PostgreSQL client tools, which must be on the PATH
+async fn run_tool(program: &str, args: &[&str], password: Option<&str>) -> Result<()> {
+    let mut command = tokio::process::Command::new(program);
+    if let Some(password) = password {
+        command.env("PGPASSWORD", password);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+// Every file under `dir`, recursively; a missing directory just means nothing was uploaded yet
+async fn list_uploads(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use futures::StreamExt;
+use serde::Deserialize;
+use crate::{ai_service::ChatOptions, api::AppState, models::*};
+
+// Prompts one batch may hold
+pub const MAX_BATCH_PROMPTS: usize = 10_000;
+// The most prompts one job sends to its model at once
+pub const MAX_CONCURRENCY: i32 = 16;
+pub const DEFAULT_CONCURRENCY: i32 = 4;
+
+// A line of a submitted batch file
+#[derive(Deserialize)]
+struct BatchLine {
+    // Numbered from 1 by line when not given
+    custom_id: Option<String>,
+    prompt: String,
+    system_prompt: Option<String>,
+}
+
+// Reads a JSONL file of prompts, one `{"custom_id": ..., "prompt": ..., "system_prompt": ...}`
+// per line. Blank lines are skipped.
+fn parse_items(job_id: &str, jsonl: &str) -> Result<Vec<BatchItem>> {
+    let mut items = Vec::new();
+    for (number, text) in jsonl.lines().enumerate().filter(|(_, text)| !text.trim().is_empty()) {
+        let line: BatchLine = serde_json::from_str(text)
+            .map_err(|e| anyhow::anyhow!("Line {} isn't a valid prompt: {}", number + 1, e))?;
+        if line.prompt.trim().is_empty() {
+            return Err(anyhow::anyhow!("Line {} has an empty prompt", number + 1));
+        }
+        items.push(BatchItem {
+            job_id: job_id.to_string(),
+            line: items.len() as i32,
+            custom_id: line.custom_id.unwrap_or_else(|| (number + 1).to_string()),
+            prompt: line.prompt,
+            system_prompt: line.system_prompt,
+            status: JobStatus::Pending,
+            output: None,
+            error: None,
+            tokens_used: None,
+        });
+    }
+    if items.is_empty() {
+        return Err(anyhow::anyhow!("The batch has no prompts"));
+    }
+    if items.len() > MAX_BATCH_PROMPTS {
+        return Err(anyhow::anyhow!("A batch can hold at most {} prompts", MAX_BATCH_PROMPTS));
+    }
+    Ok(items)
+}
+
+// The key the job's prompts are sent with, found the same way as for a session of the
+// user's with the job's model. Fails if their organization doesn't allow the provider.
+async fn provider_key(state: &AppState, job: &BatchJob) -> Result<Option<String>> {
+    let user = state.db.get_user(&job.user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    let mut session = ChatSession::new(job.user_id.clone(), AIProvider::from(job.model_provider.clone()), job.model_name.clone());
+    session.org_id = user.org_id;
+    crate::api_keys::provider_key(state, &session).await
+}
+
+// Saves the batch as a job and starts running it
+pub async fn submit(
+    state: &AppState,
+    user_id: &str,
+    model_provider: AIProvider,
+    model_name: String,
+    concurrency: Option<i32>,
+    jsonl: &str,
+) -> Result<BatchJob> {
+    let user = state.db.get_user(user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    if user.is_anonymous_guest() {
+        return Err(anyhow::anyhow!("Guests can't run batches; create an account first"));
+    }
+    let concurrency = concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+    if !(1..=MAX_CONCURRENCY).contains(&concurrency) {
+        return Err(anyhow::anyhow!("Concurrency must be between 1 and {}", MAX_CONCURRENCY));
+    }
+
+    let mut job = BatchJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        model_provider: model_provider.to_string(),
+        model_name,
+        concurrency,
+        status: JobStatus::Pending,
+        error: None,
+        total: 0,
+        completed: 0,
+        failed: 0,
+        created_at: chrono::Utc::now(),
+        finished_at: None,
+    };
+    let items = parse_items(&job.id, jsonl)?;
+    job.total = items.len() as i64;
+    // A provider the organization doesn't allow is turned down now rather than failing
+    // every prompt
+    provider_key(state, &job).await?;
+
+    state.db.create_batch_job(&job, &items).await?;
+    start(state, job.clone());
+    Ok(job)
+}
+
+// Runs the job's remaining prompts in the background
+fn start(state: &AppState, job: BatchJob) {
+    let state = state.clone();
+    state.shutdown.clone().spawn(async move {
+        if let Err(e) = run(&state, &job).await {
+            tracing::error!("Batch job {} failed: {}", job.id, e);
+            if let Err(e) = state.db.update_batch_job(&job.id, JobStatus::Failed, Some(&e.to_string()), Some(chrono::Utc::now())).await {
+                tracing::error!("Failed to record outcome of batch job {}: {}", job.id, e);
+            }
+        }
+    });
+}
+
+// Starts again the jobs a restart interrupted, from the prompts they hadn't got to
+pub async fn resume(state: &AppState) -> Result<()> {
+    for job in state.db.get_unfinished_batch_jobs().await? {
+        start(state, job);
+    }
+    Ok(())
+}
+
+// Sends the prompts to the model, up to the job's concurrency at a time, recording each
+// result as it arrives. On shutdown no more are sent; the job stays running and the
+// rest are sent after the restart.
+async fn run(state: &AppState, job: &BatchJob) -> Result<()> {
+    state.db.update_batch_job(&job.id, JobStatus::Running, None, None).await?;
+    let options = ChatOptions {
+        api_key: provider_key(state, job).await?,
+        ..Default::default()
+    };
+    let provider = AIProvider::from(job.model_provider.clone());
+
+    let items = state.db.get_batch_items(&job.id, true).await?;
+    futures::stream::iter(items)
+        .take_until(state.shutdown.triggered())
+        .map(|mut item| {
+            let (provider, options) = (provider.clone(), options.clone());
+            async move {
+                let prompt = Message::new(String::new(), MessageRole::User, item.prompt.clone());
+                let options = ChatOptions { system_prompt: item.system_prompt.clone(), ..options };
+                match state.ai_service.chat(provider, &job.model_name, vec![prompt], &[], &[], &[], &options).await {
+                    Ok(response) => {
+                        item.status = JobStatus::Completed;
+                        item.output = Some(response.content);
+                        item.tokens_used = response.tokens_used;
+                    }
+                    Err(e) => {
+                        item.status = JobStatus::Failed;
+                        item.error = Some(e.to_string());
+                    }
+                }
+                if let Err(e) = state.db.update_batch_item(&item).await {
+                    tracing::error!("Failed to record result of batch job {} line {}: {}", job.id, item.line, e);
+                }
+            }
+        })
+        .buffer_unordered(job.concurrency.max(1) as usize)
+        .for_each(|()| async {})
+        .await;
+
+    if !state.shutdown.is_triggered() {
+        state.db.update_batch_job(&job.id, JobStatus::Completed, None, Some(chrono::Utc::now())).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use leptos::*;
+
+#[component]
+pub fn AccountDataSettings() -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    // When the account will be deleted, if its owner asked for it
+    let (scheduled_at, set_scheduled_at) = create_signal(None::<chrono::DateTime<chrono::Utc>>);
+    let (password, set_password) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+
+    spawn_local(async move {
+        match crate::api::get_account_deletion().await {
+            Ok(found) => set_scheduled_at.set(found),
+            Err(e) => tracing::error!("Failed to load account deletion status: {}", e),
+        }
+    });
+
+    let toggle_panel = move |_| {
+        set_error.set(None);
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    let request_deletion = move |_| {
+        let entered = password.get_untracked();
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::request_account_deletion(entered).await {
+                Ok(at) => {
+                    set_password.set(String::new());
+                    set_scheduled_at.set(Some(at));
+                }
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let cancel_deletion = move |_| {
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::cancel_account_deletion().await {
+                Ok(()) => set_scheduled_at.set(None),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    view! {
+        <div class="relative mr-3">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="Your data"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 7v10c0 2.21 3.582 4 8 4s8-1.79 8-4V7M4 7c0 2.21 3.582 4 8 4s8-1.79 8-4M4 7c0-2.21 3.582-4 8-4s8 1.79 8 4"></path>
+                </svg>
+            </button>
+            // Shown outside the panel so a pending deletion can't go unnoticed
+            {move || scheduled_at.get().map(|_| view! {
+                <span class="absolute top-1 right-1 w-2 h-2 bg-red-500 rounded-full" title="Account scheduled for deletion"></span>
+            })}
+
+            {move || show_panel.get().then(|| view! {
+                <div class="absolute top-12 right-0 w-80 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                    <div class="p-3 space-y-3 text-sm">
+                        <div class="space-y-1">
+                            <a href="/api/account/export" download class="block w-full py-1 text-center text-white bg-blue-600 rounded hover:bg-blue-700">
+                                "Download my data"
+                            </a>
+                            <div class="text-xs text-gray-500">
+                                "A zip of your chats, memory and attachments."
+                            </div>
+                        </div>
+                        <div class="pt-3 border-t border-gray-100">
+                            {move || match scheduled_at.get() {
+                                Some(at) => view! {
+                                    <div class="space-y-2">
+                                        <div class="text-xs text-red-700">
+                                            {format!("Your account and everything in it will be deleted on {}.", at.format("%Y-%m-%d %H:%M UTC"))}
+                                        </div>
+                                        <button
+                                            type="button"
+                                            on:click=cancel_deletion
+                                            class="w-full py-1 text-gray-800 bg-gray-100 rounded hover:bg-gray-200"
+                                        >
+                                            "Keep my account"
+                                        </button>
+                                    </div>
+                                }.into_view(),
+                                None => view! {
+                                    <div class="space-y-2">
+                                        <div class="text-xs text-gray-500">
+                                            "Delete your account with all its chats, memory, knowledge bases and files. You can cancel until the grace period is over."
+                                        </div>
+                                        <input
+                                            type="password"
+                                            autocomplete="current-password"
+                                            placeholder="Password"
+                                            class="w-full px-2 py-1 text-sm border border-gray-300 rounded focus:outline-none focus:ring-1 focus:ring-blue-500"
+                                            prop:value=password
+                                            on:input=move |ev| set_password.set(event_target_value(&ev))
+                                        />
+                                        <button
+                                            type="button"
+                                            on:click=request_deletion
+                                            class="w-full py-1 text-white bg-red-600 rounded hover:bg-red-700"
+                                        >
+                                            "Delete my account"
+                                        </button>
+                                    </div>
+                                }.into_view(),
+                            }}
+                        </div>
+                        {move || error.get().map(|message| view! {
+                            <div class="text-xs text-red-600">{message}</div>
+                        })}
+                    </div>
+                </div>
+            })}
+        </div>
+    }
+}
@@ -0,0 +1,286 @@
+use leptos::*;
+use crate::models::*;
+
+const USAGE_DAYS: i64 = 30;
+
+// The /admin area: users, instance-wide usage, provider health and configuration. Every
+// server function behind it checks the caller's role allows it.
+#[component]
+pub fn AdminPage() -> impl IntoView {
+    let (overview, set_overview) = create_signal(None::<AdminOverview>);
+    let (usage, set_usage) = create_signal(Vec::<DailyUsage>::new());
+    let (error, set_error) = create_signal(None::<String>);
+
+    let load_overview = move || {
+        spawn_local(async move {
+            match crate::api::get_admin_overview().await {
+                Ok(found) => set_overview.set(Some(found)),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    load_overview();
+    spawn_local(async move {
+        let to = chrono::Utc::now().date_naive();
+        let from = to - chrono::Duration::days(USAGE_DAYS - 1);
+        match crate::api::get_instance_usage(from, to).await {
+            Ok(rows) => set_usage.set(rows),
+            Err(e) => tracing::error!("Failed to load usage: {}", e),
+        }
+    });
+
+    view! {
+        <div class="max-w-5xl mx-auto p-6 space-y-8">
+            <div class="flex items-center justify-between">
+                <h1 class="text-2xl font-semibold text-gray-900">"Admin"</h1>
+                <a href="/" class="text-sm text-indigo-600 hover:underline">"Back to chat"</a>
+            </div>
+            {move || error.get().map(|message| view! {
+                <div class="p-3 text-sm text-red-700 bg-red-50 rounded-lg">{message}</div>
+            })}
+            {move || overview.get().map(|overview| {
+                let AdminOverview { users, providers, config } = overview;
+                view! {
+                    <AdminSection title="Users">
+                        <UserTable users=users on_change=move |_| load_overview() set_error=set_error />
+                    </AdminSection>
+                    <AdminSection title="Usage, last 30 days">
+                        <UsageTable usage=usage />
+                    </AdminSection>
+                    <AdminSection title="Providers">
+                        <ProviderTable providers=providers />
+                    </AdminSection>
+                    <AdminSection title="Configuration">
+                        <ConfigTable config=config />
+                    </AdminSection>
+                }
+            })}
+        </div>
+    }
+}
+
+#[component]
+fn AdminSection(title: &'static str, children: Children) -> impl IntoView {
+    view! {
+        <section>
+            <h2 class="mb-3 text-lg font-medium text-gray-800">{title}</h2>
+            <div class="overflow-x-auto bg-white border border-gray-200 rounded-lg">
+                {children()}
+            </div>
+        </section>
+    }
+}
+
+#[component]
+fn UserTable(
+    users: Vec<AdminUser>,
+    // Called after a user is changed so the overview can reload
+    on_change: Callback<()>,
+    set_error: WriteSignal<Option<String>>,
+) -> impl IntoView {
+    let rows = users.into_iter().map(|user| {
+        let role_id = user.id.clone();
+        let sign_out_id = user.id.clone();
+        let reset_id = user.id.clone();
+        let current_role = user.role;
+
+        view! {
+            <tr class="border-t border-gray-100">
+                <td class="px-3 py-2">
+                    <div class="text-gray-900">{user.name.clone().or(user.email.clone()).unwrap_or_else(|| user.id.clone())}</div>
+                    <div class="text-xs text-gray-500">
+                        {user.email.clone().unwrap_or_default()}
+                        {(!user.has_password).then(|| " (no password)")}
+                    </div>
+                </td>
+                <td class="px-3 py-2 text-gray-600">{user.org_id}</td>
+                <td class="px-3 py-2 text-right text-gray-600">{user.session_count}</td>
+                <td class="px-3 py-2 text-gray-600">
+                    {user.last_active_at.map(|at| at.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "Never".to_string())}
+                </td>
+                <td class="px-3 py-2">
+                    <select
+                        class="px-2 py-1 text-sm text-gray-600 bg-gray-100 rounded outline-none"
+                        on:change=move |ev| {
+                            let user_id = role_id.clone();
+                            let role = Role::from(event_target_value(&ev));
+                            spawn_local(async move {
+                                if let Err(e) = crate::api::set_user_role(user_id, role).await {
+                                    set_error.set(Some(e.to_string()));
+                                }
+                                // Reloading also puts the select back if the change failed
+                                on_change.call(());
+                            });
+                        }
+                    >
+                        {Role::ALL.into_iter().map(|role| view! {
+                            <option value=role.to_string() selected=role == current_role>{role.to_string()}</option>
+                        }).collect::<Vec<_>>()}
+                    </select>
+                </td>
+                <td class="px-3 py-2 text-right whitespace-nowrap">
+                    <button
+                        type="button"
+                        on:click=move |_| {
+                            let user_id = reset_id.clone();
+                            spawn_local(async move {
+                                if let Err(e) = crate::api::reset_user_two_factor(user_id).await {
+                                    set_error.set(Some(e.to_string()));
+                                }
+                            });
+                        }
+                        class="mr-3 text-xs text-gray-600 hover:text-gray-800"
+                        title="Turn off this user's two-factor authentication, for when they lost their app and backup codes"
+                    >
+                        "Reset 2FA"
+                    </button>
+                    <button
+                        type="button"
+                        on:click=move |_| {
+                            let user_id = sign_out_id.clone();
+                            spawn_local(async move {
+                                if let Err(e) = crate::api::sign_out_user(user_id).await {
+                                    set_error.set(Some(e.to_string()));
+                                }
+                            });
+                        }
+                        class="text-xs text-red-600 hover:text-red-800"
+                        title="End all of this user's login sessions"
+                    >
+                        "Sign out"
+                    </button>
+                </td>
+            </tr>
+        }
+    }).collect::<Vec<_>>();
+
+    view! {
+        <table class="w-full text-sm">
+            <thead class="text-left text-xs text-gray-500 uppercase">
+                <tr>
+                    <th class="px-3 py-2">"User"</th>
+                    <th class="px-3 py-2">"Organization"</th>
+                    <th class="px-3 py-2 text-right">"Chats"</th>
+                    <th class="px-3 py-2">"Last active"</th>
+                    <th class="px-3 py-2">"Role"</th>
+                    <th class="px-3 py-2"></th>
+                </tr>
+            </thead>
+            <tbody>{rows}</tbody>
+        </table>
+    }
+}
+
+// Totals per provider and model over the whole period
+#[component]
+fn UsageTable(usage: ReadSignal<Vec<DailyUsage>>) -> impl IntoView {
+    let totals = move || {
+        let mut totals: Vec<(String, i64, i64, Option<f64>)> = Vec::new();
+        for row in usage.get() {
+            let model = format!("{}/{}", row.model_provider, row.model_name);
+            let messages = row.user_messages + row.assistant_messages;
+            match totals.iter_mut().find(|(name, ..)| *name == model) {
+                Some((_, total_messages, total_tokens, total_cost)) => {
+                    *total_messages += messages;
+                    *total_tokens += row.tokens;
+                    *total_cost = match (*total_cost, row.estimated_cost) {
+                        (Some(total), Some(cost)) => Some(total + cost),
+                        (total, cost) => total.or(cost),
+                    };
+                }
+                None => totals.push((model, messages, row.tokens, row.estimated_cost)),
+            }
+        }
+        totals
+    };
+
+    view! {
+        <table class="w-full text-sm">
+            <thead class="text-left text-xs text-gray-500 uppercase">
+                <tr>
+                    <th class="px-3 py-2">"Model"</th>
+                    <th class="px-3 py-2 text-right">"Messages"</th>
+                    <th class="px-3 py-2 text-right">"Tokens"</th>
+                    <th class="px-3 py-2 text-right">"Estimated cost"</th>
+                </tr>
+            </thead>
+            <tbody>
+                {move || totals().into_iter().map(|(model, messages, tokens, cost)| view! {
+                    <tr class="border-t border-gray-100">
+                        <td class="px-3 py-2 text-gray-900">{model}</td>
+                        <td class="px-3 py-2 text-right text-gray-600">{messages}</td>
+                        <td class="px-3 py-2 text-right text-gray-600">{tokens}</td>
+                        <td class="px-3 py-2 text-right text-gray-600">
+                            {cost.map(|cost| format!("${:.2}", cost)).unwrap_or_else(|| "-".to_string())}
+                        </td>
+                    </tr>
+                }).collect::<Vec<_>>()}
+            </tbody>
+        </table>
+    }
+}
+
+#[component]
+fn ProviderTable(providers: Vec<ProviderHealth>) -> impl IntoView {
+    let rows = providers.into_iter().map(|provider| {
+        let status = if !provider.configured {
+            "No instance key"
+        } else if provider.healthy {
+            "Healthy"
+        } else {
+            "Failing"
+        };
+
+        view! {
+            <tr class="border-t border-gray-100">
+                <td class="px-3 py-2 text-gray-900">{provider.provider}</td>
+                <td class="px-3 py-2 text-gray-600">{status}</td>
+                <td class="px-3 py-2 text-right text-gray-600">
+                    {provider.latency_ms.map(|ms| format!("{:.0} ms", ms))}
+                </td>
+                <td class="px-3 py-2 text-xs text-red-600">{provider.error}</td>
+            </tr>
+        }
+    }).collect::<Vec<_>>();
+
+    view! {
+        <table class="w-full text-sm">
+            <tbody>{rows}</tbody>
+        </table>
+    }
+}
+
+#[component]
+fn ConfigTable(config: InstanceConfig) -> impl IntoView {
+    let limit = |value: Option<i64>| value.map(|value| value.to_string()).unwrap_or_else(|| "Unlimited".to_string());
+    let set = |value: bool| if value { "Set" } else { "Not set" }.to_string();
+    let settings = [
+        ("Database", config.database_backend),
+        ("Reranking", if config.reranking_enabled { "On" } else { "Off" }.to_string()),
+        ("Content filter", config.moderation_action.unwrap_or_else(|| "Off".to_string())),
+        ("Memory half-life (days)", config.memory_half_life_days.to_string()),
+        ("Memory minimum confidence", config.memory_min_confidence.to_string()),
+        ("Knowledge base chunks per user", limit(config.max_kb_chunks_per_user)),
+        ("Trash retention (days)", config.trash_retention_days.to_string()),
+        ("Account deletion grace period (days)", config.account_deletion_grace_days.to_string()),
+        ("Message retention (days)", limit(config.retention_policy.message_retention_days)),
+        ("Sessions kept per user", limit(config.retention_policy.max_sessions)),
+        ("Retention dry run", if config.retention_dry_run { "On" } else { "Off" }.to_string()),
+        ("ADMIN_TOKEN", set(config.admin_token_set)),
+        ("SECRETS_KEY", set(config.secrets_key_set)),
+    ];
+
+    view! {
+        <table class="w-full text-sm">
+            <tbody>
+                {settings.into_iter().map(|(name, value)| view! {
+                    <tr class="border-t border-gray-100">
+                        <td class="px-3 py-2 text-gray-600">{name}</td>
+                        <td class="px-3 py-2 text-gray-900">{value}</td>
+                    </tr>
+                }).collect::<Vec<_>>()}
+            </tbody>
+        </table>
+    }
+}
@@ -0,0 +1,197 @@
+use leptos::*;
+use crate::models::*;
+
+const KEYED_PROVIDERS: [AIProvider; 4] = [
+    AIProvider::OpenAI,
+    AIProvider::Anthropic,
+    AIProvider::Gemini,
+    AIProvider::OpenRouter,
+];
+
+// What a row's key is for: chats with a provider, or reading GitHub repositories
+#[derive(Clone)]
+enum KeyTarget {
+    Provider(AIProvider),
+    GitHub,
+}
+
+impl KeyTarget {
+    // How the saved key is named in the list of keys
+    fn name(&self) -> String {
+        match self {
+            KeyTarget::Provider(provider) => provider.to_string(),
+            KeyTarget::GitHub => "github".to_string(),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            KeyTarget::Provider(provider) => provider.to_string(),
+            KeyTarget::GitHub => "GitHub".to_string(),
+        }
+    }
+}
+
+#[component]
+pub fn ApiKeySettings() -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (keys, set_keys) = create_signal(Vec::<StoredApiKey>::new());
+
+    let load_keys = move || {
+        spawn_local(async move {
+            match crate::api::list_api_keys().await {
+                Ok(found) => set_keys.set(found),
+                Err(e) => tracing::error!("Failed to load API keys: {}", e),
+            }
+        });
+    };
+
+    // Reload the keys every time the panel is opened
+    create_effect(move |_| {
+        if show_panel.get() {
+            load_keys();
+        }
+    });
+
+    let toggle_panel = move |_| {
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    view! {
+        <div class="relative mr-3">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="Your API keys"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M15 7a2 2 0 012 2m4 0a6 6 0 01-7.743 5.743L11 17H9v2H7v2H4a1 1 0 01-1-1v-2.586a1 1 0 01.293-.707l5.964-5.964A6 6 0 1121 9z"></path>
+                </svg>
+            </button>
+
+            {move || {
+                if show_panel.get() {
+                    view! {
+                        <div class="absolute top-12 right-0 w-96 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                            <div class="p-3 space-y-3">
+                                <div class="text-xs text-gray-500">
+                                    "Chats with a provider use your own key when you've saved one, otherwise the key set up for this server."
+                                </div>
+                                {KEYED_PROVIDERS.iter().cloned().map(KeyTarget::Provider).chain([KeyTarget::GitHub]).map(|target| {
+                                    let name = target.name();
+                                    let saved = Signal::derive(move || {
+                                        keys.get().into_iter().find(|key| key.provider == name)
+                                    });
+                                    view! { <ApiKeyRow target=target saved=saved on_change=move |_| load_keys() /> }
+                                }).collect::<Vec<_>>()}
+                                <div class="text-xs text-gray-500">
+                                    "A GitHub token lets you ingest your private repositories; without one, only public ones."
+                                </div>
+                            </div>
+                        </div>
+                    }
+                } else {
+                    view! { <div></div> }
+                }
+            }}
+        </div>
+    }
+}
+
+#[component]
+fn ApiKeyRow(
+    target: KeyTarget,
+    saved: Signal<Option<StoredApiKey>>,
+    // Called after the key is saved or removed so the panel can reload
+    on_change: Callback<()>,
+) -> impl IntoView {
+    let (api_key, set_api_key) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+    let (saving, set_saving) = create_signal(false);
+
+    let save_target = target.clone();
+    let save = move |_| {
+        let target = save_target.clone();
+        let key = api_key.get();
+        set_saving.set(true);
+        set_error.set(None);
+        spawn_local(async move {
+            let saved = match target {
+                KeyTarget::Provider(provider) => crate::api::set_api_key(provider, key).await,
+                KeyTarget::GitHub => crate::api::set_github_token(key).await,
+            };
+            match saved {
+                Ok(_) => {
+                    set_api_key.set(String::new());
+                    on_change.call(());
+                }
+                // Shown inline so the user knows why the provider rejected the key
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            set_saving.set(false);
+        });
+    };
+
+    let remove_target = target.clone();
+    let remove = move |_| {
+        let target = remove_target.clone();
+        spawn_local(async move {
+            let removed = match target {
+                KeyTarget::Provider(provider) => crate::api::delete_api_key(provider).await,
+                KeyTarget::GitHub => crate::api::delete_github_token().await,
+            };
+            if let Err(e) = removed {
+                tracing::error!("Failed to remove API key: {}", e);
+            }
+            on_change.call(());
+        });
+    };
+
+    view! {
+        <div class="space-y-1">
+            <div class="flex items-center justify-between">
+                <span class="text-sm font-medium text-gray-800">{target.label()}</span>
+                {move || match saved.get() {
+                    Some(key) => view! {
+                        <span class="text-xs text-gray-500">
+                            {format!("Key {}", key.fingerprint)}
+                            <button
+                                type="button"
+                                on:click=remove.clone()
+                                class="ml-2 text-red-600 hover:text-red-800"
+                            >
+                                "Remove"
+                            </button>
+                        </span>
+                    }.into_view(),
+                    None => view! {
+                        <span class="text-xs text-gray-400">
+                            {if matches!(target, KeyTarget::GitHub) { "Public repositories only" } else { "Using server key" }}
+                        </span>
+                    }.into_view(),
+                }}
+            </div>
+            <div class="flex">
+                <input
+                    type="password"
+                    placeholder="Paste an API key"
+                    class="flex-1 min-w-0 px-2 py-1 text-sm border border-gray-300 rounded-l focus:outline-none focus:ring-1 focus:ring-blue-500"
+                    prop:value=api_key
+                    on:input=move |ev| set_api_key.set(event_target_value(&ev))
+                />
+                <button
+                    type="button"
+                    on:click=save
+                    disabled=move || saving.get() || api_key.get().trim().is_empty()
+                    class="px-3 py-1 text-sm text-white bg-blue-600 rounded-r hover:bg-blue-700 disabled:opacity-50"
+                >
+                    {move || if saving.get() { "Checking…" } else { "Save" }}
+                </button>
+            </div>
+            {move || error.get().map(|message| view! {
+                <div class="text-xs text-red-600">{message}</div>
+            })}
+        </div>
+    }
+}
@@ -0,0 +1,36 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn AttachmentContext(
+    attachments: Vec<FileAttachment>,
+    // Nothing earlier goes into context unless the user ticks it
+    included: ReadSignal<Vec<String>>,
+    on_toggle: Callback<String>,
+) -> impl IntoView {
+    view! {
+        <div class="mt-2 bg-white rounded-lg shadow-lg p-3">
+            <div class="text-sm text-gray-600 mb-2">"Files in context for the next message:"</div>
+            <div class="space-y-1 max-h-32 overflow-y-auto">
+                {attachments.into_iter().map(|attachment| {
+                    let id = attachment.id.clone();
+                    let toggle_id = attachment.id.clone();
+                    let is_included = move || included.get().contains(&id);
+
+                    view! {
+                        <label class="flex items-center text-sm">
+                            <input
+                                type="checkbox"
+                                class="mr-2"
+                                prop:checked=is_included
+                                on:change=move |_| on_toggle.call(toggle_id.clone())
+                            />
+                            <span class="text-gray-800">{attachment.file_name}</span>
+                            <span class="text-gray-500 ml-2">{format!("({} bytes)", attachment.file_size)}</span>
+                        </label>
+                    }
+                }).collect::<Vec<_>>()}
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,374 @@
+use leptos::*;
+use leptos_router::*;
+use crate::models::*;
+
+#[component]
+pub fn LoginPage() -> impl IntoView {
+    // The single sign-on callback comes back here when signing in failed, or with `sso_code`
+    // when the account also needs a two-factor code
+    let query = use_query_map();
+    let sso_pending = query.with_untracked(|q| q.get("sso_code").is_some());
+
+    let (email, set_email) = create_signal(String::new());
+    let (password, set_password) = create_signal(String::new());
+    // Asked for once the password is accepted, for accounts with two-factor authentication
+    let (code, set_code) = create_signal(String::new());
+    let (needs_code, set_needs_code) = create_signal(sso_pending);
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_submitting, set_is_submitting) = create_signal(false);
+    let (guest_access, set_guest_access) = create_signal(false);
+    // Name of the single sign-on provider, when one is configured
+    let (sso_provider, set_sso_provider) = create_signal(None::<String>);
+    let navigate = use_navigate();
+
+    if query.with_untracked(|q| q.get("sso_error").is_some()) {
+        set_error.set(Some("Single sign-on failed. Try again, or ask your administrator for access.".to_string()));
+    }
+
+    spawn_local(async move {
+        match crate::api::guest_access_enabled().await {
+            Ok(enabled) => set_guest_access.set(enabled),
+            Err(e) => tracing::error!("Failed to check for guest access: {}", e),
+        }
+    });
+
+    spawn_local(async move {
+        match crate::api::single_sign_on_provider().await {
+            Ok(provider) => set_sso_provider.set(provider),
+            Err(e) => tracing::error!("Failed to check for single sign-on: {}", e),
+        }
+    });
+
+    let continue_as_guest = {
+        let navigate = navigate.clone();
+        move |_| {
+            let navigate = navigate.clone();
+            spawn_local(async move {
+                match crate::api::start_guest_session().await {
+                    Ok(_) => navigate("/", Default::default()),
+                    Err(e) => set_error.set(Some(e.to_string())),
+                }
+            });
+        }
+    };
+
+    let handle_submit = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        set_is_submitting.set(true);
+        set_error.set(None);
+        let navigate = navigate.clone();
+        spawn_local(async move {
+            if sso_pending {
+                match crate::api::complete_single_sign_on(code.get_untracked()).await {
+                    Ok(_) => navigate("/", Default::default()),
+                    Err(e) => set_error.set(Some(e.to_string())),
+                }
+                set_is_submitting.set(false);
+                return;
+            }
+            let code = needs_code.get_untracked().then(|| code.get_untracked());
+            match crate::api::login(email.get_untracked(), password.get_untracked(), code).await {
+                Ok(LoginResult::SignedIn(_)) => navigate("/", Default::default()),
+                Ok(LoginResult::CodeRequired) => set_needs_code.set(true),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            set_is_submitting.set(false);
+        });
+    };
+
+    view! {
+        <AuthCard title="Sign in">
+            <form on:submit=handle_submit class="space-y-4">
+                {(!sso_pending).then(|| view! {
+                    <AuthField label="Email" input_type="email" autocomplete="email" value=email set_value=set_email />
+                    <AuthField label="Password" input_type="password" autocomplete="current-password" value=password set_value=set_password />
+                })}
+                {move || needs_code.get().then(|| view! {
+                    <AuthField label="Code from your authenticator app, or a backup code" input_type="text" autocomplete="one-time-code" value=code set_value=set_code />
+                })}
+                <AuthError error=error />
+                <button
+                    type="submit"
+                    disabled=move || is_submitting.get()
+                    class="w-full py-2 text-white bg-indigo-600 rounded-lg hover:bg-indigo-700 disabled:opacity-50"
+                >
+                    {move || if needs_code.get() { "Verify" } else { "Sign in" }}
+                </button>
+            </form>
+            {move || sso_provider.get().map(|provider| view! {
+                // A full page load, since the server redirects to the provider
+                <a
+                    href="/api/auth/oidc/login"
+                    rel="external"
+                    class="block w-full mt-3 py-2 text-sm text-center text-gray-700 bg-gray-100 rounded-lg hover:bg-gray-200"
+                >
+                    {format!("Sign in with {}", provider)}
+                </a>
+            })}
+            <p class="mt-4 text-sm text-center">
+                <a href="/forgot-password" class="text-indigo-600 hover:underline">"Forgot your password?"</a>
+            </p>
+            <p class="mt-4 text-sm text-center text-gray-600">
+                "No account yet? "
+                <a href="/register" class="text-indigo-600 hover:underline">"Create one"</a>
+            </p>
+            {move || guest_access.get().then(|| {
+                let continue_as_guest = continue_as_guest.clone();
+                view! {
+                    <button
+                        type="button"
+                        on:click=continue_as_guest
+                        class="w-full mt-3 py-2 text-sm text-gray-700 bg-gray-100 rounded-lg hover:bg-gray-200"
+                    >
+                        "Continue as a guest"
+                    </button>
+                }
+            })}
+        </AuthCard>
+    }
+}
+
+#[component]
+pub fn RegisterPage() -> impl IntoView {
+    let (name, set_name) = create_signal(String::new());
+    let (email, set_email) = create_signal(String::new());
+    let (password, set_password) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_submitting, set_is_submitting) = create_signal(false);
+    let navigate = use_navigate();
+
+    let handle_submit = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        if password.get_untracked().chars().count() < MIN_PASSWORD_LENGTH {
+            set_error.set(Some(format!("Passwords must be at least {} characters", MIN_PASSWORD_LENGTH)));
+            return;
+        }
+        set_is_submitting.set(true);
+        set_error.set(None);
+        let navigate = navigate.clone();
+        spawn_local(async move {
+            let name = Some(name.get_untracked()).filter(|name| !name.trim().is_empty());
+            match crate::api::register(name, email.get_untracked(), password.get_untracked()).await {
+                Ok(_) => navigate("/", Default::default()),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            set_is_submitting.set(false);
+        });
+    };
+
+    view! {
+        <AuthCard title="Create an account">
+            <form on:submit=handle_submit class="space-y-4">
+                <AuthField label="Name (optional)" input_type="text" autocomplete="name" value=name set_value=set_name />
+                <AuthField label="Email" input_type="email" autocomplete="email" value=email set_value=set_email />
+                <AuthField label="Password" input_type="password" autocomplete="new-password" value=password set_value=set_password />
+                <AuthError error=error />
+                <button
+                    type="submit"
+                    disabled=move || is_submitting.get()
+                    class="w-full py-2 text-white bg-indigo-600 rounded-lg hover:bg-indigo-700 disabled:opacity-50"
+                >
+                    "Create account"
+                </button>
+            </form>
+            <p class="mt-4 text-sm text-center text-gray-600">
+                "Already have an account? "
+                <a href="/login" class="text-indigo-600 hover:underline">"Sign in"</a>
+            </p>
+        </AuthCard>
+    }
+}
+
+// Asks for the email address to send a password reset link to
+#[component]
+pub fn ForgotPasswordPage() -> impl IntoView {
+    let (email, set_email) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+    let (sent, set_sent) = create_signal(false);
+    let (is_submitting, set_is_submitting) = create_signal(false);
+
+    let handle_submit = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        set_is_submitting.set(true);
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::request_password_reset(email.get_untracked()).await {
+                Ok(()) => set_sent.set(true),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            set_is_submitting.set(false);
+        });
+    };
+
+    view! {
+        <AuthCard title="Reset your password">
+            {move || if sent.get() {
+                view! {
+                    <p class="text-sm text-gray-600">
+                        "If there is an account with that address, we've emailed it a link to choose a new password. The link works for an hour."
+                    </p>
+                }.into_view()
+            } else {
+                view! {
+                    <form on:submit=handle_submit class="space-y-4">
+                        <AuthField label="Email" input_type="email" autocomplete="email" value=email set_value=set_email />
+                        <AuthError error=error />
+                        <button
+                            type="submit"
+                            disabled=move || is_submitting.get()
+                            class="w-full py-2 text-white bg-indigo-600 rounded-lg hover:bg-indigo-700 disabled:opacity-50"
+                        >
+                            "Email me a link"
+                        </button>
+                    </form>
+                }.into_view()
+            }}
+            <p class="mt-4 text-sm text-center">
+                <a href="/login" class="text-indigo-600 hover:underline">"Back to sign in"</a>
+            </p>
+        </AuthCard>
+    }
+}
+
+// Where reset links lead: chooses a new password with the link's token
+#[component]
+pub fn ResetPasswordPage() -> impl IntoView {
+    let query = use_query_map();
+    let (password, set_password) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_submitting, set_is_submitting) = create_signal(false);
+    let navigate = use_navigate();
+
+    let handle_submit = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        if password.get_untracked().chars().count() < MIN_PASSWORD_LENGTH {
+            set_error.set(Some(format!("Passwords must be at least {} characters", MIN_PASSWORD_LENGTH)));
+            return;
+        }
+        let token = query.with_untracked(|q| q.get("token").cloned()).unwrap_or_default();
+        set_is_submitting.set(true);
+        set_error.set(None);
+        let navigate = navigate.clone();
+        spawn_local(async move {
+            match crate::api::reset_password(token, password.get_untracked()).await {
+                // Signing in again applies two-factor authentication, if the account has it
+                Ok(()) => navigate("/login", Default::default()),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            set_is_submitting.set(false);
+        });
+    };
+
+    view! {
+        <AuthCard title="Choose a new password">
+            <form on:submit=handle_submit class="space-y-4">
+                <AuthField label="New password" input_type="password" autocomplete="new-password" value=password set_value=set_password />
+                <AuthError error=error />
+                <button
+                    type="submit"
+                    disabled=move || is_submitting.get()
+                    class="w-full py-2 text-white bg-indigo-600 rounded-lg hover:bg-indigo-700 disabled:opacity-50"
+                >
+                    "Set password"
+                </button>
+            </form>
+        </AuthCard>
+    }
+}
+
+// Shows who is signed in with a sign-out button, and a link to the admin panel for roles
+// that can see it or to sign up for guests, or a sign-in link
+#[component]
+pub fn AccountMenu() -> impl IntoView {
+    let (user, set_user) = create_signal(None::<User>);
+
+    spawn_local(async move {
+        match crate::api::get_current_user().await {
+            Ok(found) => set_user.set(found),
+            Err(e) => tracing::error!("Failed to load the signed-in user: {}", e),
+        }
+    });
+
+    let handle_logout = move |_| {
+        spawn_local(async move {
+            match crate::api::logout().await {
+                Ok(()) => {
+                    if let Some(window) = web_sys::window() {
+                        let _ = window.location().set_href("/login");
+                    }
+                }
+                Err(e) => tracing::error!("Failed to sign out: {}", e),
+            }
+        });
+    };
+
+    view! {
+        <div class="flex items-center mr-3 text-sm text-gray-600">
+            {move || match user.get() {
+                Some(user) => view! {
+                    {user.role.can(Capability::ViewUsage).then(|| view! {
+                        <a href="/admin" class="mr-3 text-indigo-600 hover:underline">"Admin"</a>
+                    })}
+                    {user.is_anonymous_guest().then(|| view! {
+                        <a href="/register" class="mr-3 text-indigo-600 hover:underline" title="Keep your conversations in an account">
+                            "Create account"
+                        </a>
+                    })}
+                    {(!user.is_anonymous_guest()).then(|| view! {
+                        <a href="/security" class="mr-3 text-indigo-600 hover:underline" title="Devices you're signed in on">"Security"</a>
+                    })}
+                    <span class="mr-2 truncate max-w-[12rem]">{user.name.or(user.email).unwrap_or_default()}</span>
+                    <button type="button" on:click=handle_logout class="text-indigo-600 hover:underline">
+                        "Sign out"
+                    </button>
+                }.into_view(),
+                None => view! {
+                    <a href="/login" class="text-indigo-600 hover:underline">"Sign in"</a>
+                }.into_view(),
+            }}
+        </div>
+    }
+}
+
+#[component]
+fn AuthCard(title: &'static str, children: Children) -> impl IntoView {
+    view! {
+        <div class="flex items-center justify-center min-h-screen bg-gray-50">
+            <div class="w-full max-w-sm p-8 bg-white rounded-xl shadow-lg border border-gray-200">
+                <h1 class="mb-6 text-2xl font-semibold text-center text-gray-900">{title}</h1>
+                {children()}
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn AuthField(
+    label: &'static str,
+    input_type: &'static str,
+    autocomplete: &'static str,
+    value: ReadSignal<String>,
+    set_value: WriteSignal<String>,
+) -> impl IntoView {
+    view! {
+        <label class="block text-sm text-gray-700">
+            <span class="block mb-1 font-medium">{label}</span>
+            <input
+                type=input_type
+                autocomplete=autocomplete
+                class="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-indigo-500"
+                prop:value=move || value.get()
+                on:input=move |ev| set_value.set(event_target_value(&ev))
+            />
+        </label>
+    }
+}
+
+#[component]
+fn AuthError(error: ReadSignal<Option<String>>) -> impl IntoView {
+    view! {
+        {move || error.get().map(|error| view! {
+            <p class="text-sm text-red-600">{error}</p>
+        })}
+    }
+}
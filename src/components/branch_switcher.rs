@@ -0,0 +1,53 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn BranchSwitcher(
+    current_session: ReadSignal<Option<String>>,
+    // Bumped by the chat box when a new branch is created
+    refresh: ReadSignal<u32>,
+    on_select: Callback<String>,
+) -> impl IntoView {
+    let (branches, set_branches) = create_signal(Vec::<ChatSession>::new());
+
+    create_effect(move |_| {
+        let _ = refresh.get();
+        let Some(session_id) = current_session.get() else {
+            set_branches.set(Vec::new());
+            return;
+        };
+        spawn_local(async move {
+            match crate::api::get_session_branches(session_id).await {
+                Ok(found) => set_branches.set(found),
+                Err(e) => tracing::error!("Failed to load branches: {}", e),
+            }
+        });
+    });
+
+    view! {
+        {move || {
+            let found = branches.get();
+            // Only worth showing once the conversation has actually been branched
+            (found.len() > 1).then(|| {
+                let current = current_session.get().unwrap_or_default();
+                view! {
+                    <select
+                        class="mr-3 px-2 py-1 text-sm text-gray-600 bg-gray-100 rounded-full outline-none"
+                        title="Switch branch"
+                        on:change=move |ev| on_select.call(event_target_value(&ev))
+                    >
+                        {found.into_iter().enumerate().map(|(i, branch)| {
+                            let selected = branch.id == current;
+                            let label = if branch.parent_session_id.is_none() {
+                                "Original".to_string()
+                            } else {
+                                format!("Branch {}", i)
+                            };
+                            view! { <option value=branch.id selected=selected>{label}</option> }
+                        }).collect::<Vec<_>>()}
+                    </select>
+                }
+            })
+        }}
+    }
+}
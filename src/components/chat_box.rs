@@ -1,50 +1,250 @@
 use leptos::*;
 use leptos_router::*;
+use wasm_bindgen::{closure::Closure, JsCast};
 use crate::{
     models::*,
     api::*,
     components::{
         message::MessageComponent,
+        streaming_message::StreamingMessage,
         suggested_questions::SuggestedQuestions,
         model_switcher::ModelSwitcher,
         file_upload::FileUpload,
+        file_library::FileLibrary,
+        attachment_context::AttachmentContext,
+        conversation_search::ConversationSearch,
+        session_sidebar::SessionSidebar,
+        session_tags::SessionTags,
+        trash::TrashPanel,
+        branch_switcher::BranchSwitcher,
+        session_settings::SessionSettingsPanel,
+        session_schedules::SessionSchedulesPanel,
+        api_keys::ApiKeySettings,
+        two_factor::TwoFactorSettings,
+        account::AccountDataSettings,
+        auth::AccountMenu,
         voice_input::VoiceInput,
         thinking_animation::ThinkingAnimation,
     },
 };
 
+// How close to the bottom, in pixels, the messages box counts as scrolled to the end
+const FOLLOW_THRESHOLD_PX: i32 = 48;
+
 #[component]
 pub fn ChatBox() -> impl IntoView {
     let (current_session, set_current_session) = create_signal(None::<String>);
     let (messages, set_messages) = create_signal(Vec::<Message>::new());
     let (input_value, set_input_value) = create_signal(String::new());
     let (is_loading, set_is_loading) = create_signal(false);
+    // The reply being streamed in, until the saved message replaces it
+    let (in_progress, set_in_progress) = create_signal(None::<Message>);
+    let is_streaming = create_memo(move |_| in_progress.with(Option::is_some));
     let (suggested_questions, set_suggested_questions) = create_signal(Vec::<SuggestedQuestion>::new());
     let (selected_model, set_selected_model) = create_signal(AIProvider::Ollama);
     let (selected_model_name, set_selected_model_name) = create_signal("llama3.2".to_string());
+    let (incognito, set_incognito) = create_signal(false);
     let (uploaded_files, set_uploaded_files) = create_signal(Vec::<FileUpload>::new());
+    let (reattached_files, set_reattached_files) = create_signal(Vec::<FileAttachment>::new());
+    let (auto_transcribe, set_auto_transcribe) = create_signal(true);
+    let (session_attachments, set_session_attachments) = create_signal(Vec::<FileAttachment>::new());
+    // Earlier attachments the user chose to send along; none are unless chosen
+    let (included_attachments, set_included_attachments) = create_signal(Vec::<String>::new());
+    let (sessions_changed, set_sessions_changed) = create_signal(0u32);
+    // Set while the session has messages older than the ones loaded
+    let (earlier_cursor, set_earlier_cursor) = create_signal(None::<MessageCursor>);
+    // Seconds until the rate limiter lets the next message through, and what was typed
+    // so it can be put back when a message is turned away
+    let (retry_in, set_retry_in) = create_signal(0u64);
+    let (last_sent, set_last_sent) = create_signal(String::new());
+    // Shown above the input: a content filter warning on the last exchange, or why the
+    // last message couldn't be sent
+    let (send_notice, set_send_notice) = create_signal(None::<String>);
+    let (role, set_role) = create_signal(None::<Role>);
+    // Guests chat with the one model the server picks and have no password to protect
+    let (is_guest, set_is_guest) = create_signal(false);
+    // The message being edited; sending resubmits it in a branch forked just before it
+    let (editing, set_editing) = create_signal(None::<String>);
+    let input_ref = create_node_ref::<html::Input>();
+    // Whether the messages box keeps itself scrolled to the newest message. Scrolling up
+    // turns it off until the user scrolls back down or jumps to the latest message.
+    let (following, set_following) = create_signal(true);
+    let messages_ref = create_node_ref::<html::Div>();
+
+    // Search results link to `/?session=<id>#message-<id>`
+    let query = use_query_map();
+    let location = use_location();
+    let navigate = use_navigate();
 
-    // Create a new session when component mounts
+    // The chat needs a signed-in user; send everyone else to the login page
+    spawn_local(async move {
+        match get_current_user().await {
+            Ok(Some(user)) => {
+                set_is_guest.set(user.is_anonymous_guest());
+                set_role.set(Some(user.role));
+            }
+            Ok(None) => navigate("/login", Default::default()),
+            Err(e) => tracing::error!("Failed to load the signed-in user: {}", e),
+        }
+    });
+
+    // Open the linked session if there is one, otherwise create a new session when component mounts
     create_effect(move |_| {
+        if let Some(session_id) = query.with(|q| q.get("session").cloned()) {
+            set_current_session.set(Some(session_id));
+            return;
+        }
         spawn_local(async move {
-            match create_session(None, selected_model.get(), selected_model_name.get()).await {
+            match create_session(None, selected_model.get(), selected_model_name.get(), incognito.get()).await {
                 Ok(session_id) => {
                     set_current_session.set(Some(session_id));
                 }
                 Err(e) => {
-                    log::error!("Failed to create session: {}", e);
+                    tracing::error!("Failed to create session: {}", e);
                 }
             }
         });
     });
 
+    // Loads the latest page of the current session, replacing what is shown
+    let load_latest_messages = move || {
+        if let Some(session_id) = current_session.get_untracked() {
+            spawn_local(async move {
+                match get_chat_history(session_id, None, None).await {
+                    Ok(page) => {
+                        set_messages.set(page.items);
+                        set_earlier_cursor.set(page.next_cursor);
+                    }
+                    Err(e) => tracing::error!("Failed to load messages: {}", e),
+                }
+            });
+        }
+    };
+
+    // Prepends the page before the oldest loaded message
+    let load_earlier_messages = move || {
+        let (Some(session_id), Some(cursor)) = (current_session.get_untracked(), earlier_cursor.get_untracked()) else {
+            return;
+        };
+        spawn_local(async move {
+            match get_chat_history(session_id, Some(cursor), None).await {
+                Ok(page) => {
+                    set_messages.update(|loaded| {
+                        let mut earlier = page.items;
+                        earlier.append(loaded);
+                        *loaded = earlier;
+                    });
+                    set_earlier_cursor.set(page.next_cursor);
+                }
+                Err(e) => tracing::error!("Failed to load earlier messages: {}", e),
+            }
+        });
+    };
+
     // Load messages when session changes
     create_effect(move |_| {
+        let _ = current_session.get();
+        set_in_progress.set(None);
+        set_included_attachments.set(Vec::new());
+        set_editing.set(None);
+        // A search result's link opens the session at its message rather than the end
+        set_following.set(!location.hash.get_untracked().starts_with("#message-"));
+        load_latest_messages();
+    });
+
+    // Stay in step with the user's other tabs and devices, so messages and session changes
+    // made there, or through the API, show up here too
+    create_effect(move |_| {
+        connect_sync(Callback::new(move |event: SyncEvent| match event {
+            // This tab's own messages are loaded once its reply lands
+            SyncEvent::Message { message } => {
+                let shown = current_session.get_untracked().as_deref() == Some(message.session_id.as_str());
+                let known = messages.with_untracked(|loaded| loaded.iter().any(|m| m.id == message.id));
+                if shown && matches!(message.role, MessageRole::Assistant) && in_progress.with_untracked(Option::is_some) {
+                    // The finished reply takes the place of the streamed one
+                    set_in_progress.set(None);
+                    if !known {
+                        set_messages.update(|loaded| loaded.push(message));
+                    }
+                } else if shown && !known && !is_loading.get_untracked() {
+                    load_latest_messages();
+                }
+            }
+            SyncEvent::Delta { session_id, text } => {
+                if current_session.get_untracked().as_deref() == Some(session_id.as_str()) {
+                    set_in_progress.update(|reply| match reply {
+                        Some(reply) => reply.content.push_str(&text),
+                        None => *reply = Some(Message::new(session_id, MessageRole::Assistant, text)),
+                    });
+                }
+            }
+            SyncEvent::ReplyAbandoned { session_id } => {
+                if current_session.get_untracked().as_deref() == Some(session_id.as_str()) {
+                    set_in_progress.set(None);
+                }
+            }
+            SyncEvent::SessionsChanged => set_sessions_changed.update(|n| *n += 1),
+            // Events were missed, so catch up from the server. Pieces of a reply being
+            // written may be among them, so it waits for the saved one instead.
+            SyncEvent::Lagged => {
+                set_in_progress.set(None);
+                set_sessions_changed.update(|n| *n += 1);
+                load_latest_messages();
+            }
+        }), false);
+    });
+
+    let scroll_to_latest = move |behavior: web_sys::ScrollBehavior| {
+        if let Some(container) = messages_ref.get_untracked() {
+            let options = web_sys::ScrollToOptions::new();
+            options.set_top(container.scroll_height() as f64);
+            options.set_behavior(behavior);
+            container.scroll_to_with_scroll_to_options(&options);
+        }
+    };
+
+    // Keep the newest message in view as messages arrive and replies stream in, unless the
+    // user has scrolled up to read something earlier
+    create_effect(move |_| {
+        messages.with(|_| ());
+        in_progress.with(|_| ());
+        is_loading.with(|_| ());
+        if following.get_untracked() {
+            request_animation_frame(move || scroll_to_latest(web_sys::ScrollBehavior::Instant));
+        }
+    });
+
+    let handle_messages_scroll = move |_| {
+        if let Some(container) = messages_ref.get_untracked() {
+            let from_bottom = container.scroll_height() - container.scroll_top() - container.client_height();
+            set_following.set(from_bottom <= FOLLOW_THRESHOLD_PX);
+        }
+    };
+
+    // Scroll to the message a search result linked to once it has rendered, paging
+    // back through older messages until it is loaded
+    create_effect(move |_| {
+        let _ = messages.get();
+        let hash = location.hash.get_untracked();
+        if let Some(element_id) = hash.strip_prefix('#').map(str::to_string) {
+            request_animation_frame(move || {
+                if let Some(element) = document().get_element_by_id(&element_id) {
+                    element.scroll_into_view();
+                } else if element_id.starts_with("message-") {
+                    load_earlier_messages();
+                }
+            });
+        }
+    });
+
+    // Load the files attached so far in this session; reloads whenever the message list changes
+    create_effect(move |_| {
+        let _ = messages.get();
         if let Some(session_id) = current_session.get() {
             spawn_local(async move {
-                match get_chat_history(session_id).await {
-                    Ok(msgs) => set_messages.set(msgs),
-                    Err(e) => log::error!("Failed to load messages: {}", e),
+                match get_session_attachments(session_id).await {
+                    Ok(attachments) => set_session_attachments.set(attachments),
+                    Err(e) => tracing::error!("Failed to load session attachments: {}", e),
                 }
             });
         }
@@ -56,18 +256,31 @@ pub fn ChatBox() -> impl IntoView {
             spawn_local(async move {
                 match get_suggested_questions(session_id).await {
                     Ok(questions) => set_suggested_questions.set(questions),
-                    Err(e) => log::error!("Failed to load suggested questions: {}", e),
+                    Err(e) => tracing::error!("Failed to load suggested questions: {}", e),
                 }
             });
         }
     });
 
-    let send_message = create_action(|input: &(String, Vec<FileUpload>)| {
-        let (message, files) = input.clone();
+    let send_message = create_action(|input: &(String, Vec<FileUpload>, Vec<String>)| {
+        let (message, files, attachment_ids) = input.clone();
         async move {
             if let Some(session_id) = current_session.get() {
                 set_is_loading.set(true);
-                let result = send_message(session_id, message, files).await;
+                let included = included_attachments.get_untracked();
+                let context_attachment_ids = session_attachments.get_untracked()
+                    .into_iter()
+                    .map(|a| a.id)
+                    .filter(|id| included.contains(id))
+                    .collect();
+                let result = send_message(
+                    session_id,
+                    message,
+                    files,
+                    attachment_ids,
+                    context_attachment_ids,
+                    auto_transcribe.get_untracked(),
+                ).await;
                 set_is_loading.set(false);
                 result
             } else {
@@ -76,14 +289,129 @@ pub fn ChatBox() -> impl IntoView {
         }
     });
 
+    // Reload the conversation once a reply lands so the stored message (with its
+    // citations and attachments) replaces what was typed. The first reply also names the session.
+    create_effect(move |_| {
+        let result = send_message.value().get();
+        if result.is_some() {
+            set_in_progress.set(None);
+        }
+        match result {
+            Some(Ok(response)) => {
+                set_send_notice.set(response.moderation_warning);
+                set_sessions_changed.update(|n| *n += 1);
+                load_latest_messages();
+            }
+            Some(Err(e)) => match RateLimited::from_error(&e.to_string()) {
+                Some(limited) => {
+                    set_retry_in.set(limited.retry_after_secs);
+                    set_input_value.set(last_sent.get_untracked());
+                }
+                None => {
+                    tracing::error!("Failed to send message: {}", e);
+                    set_send_notice.set(Some(e.to_string()));
+                    set_input_value.set(last_sent.get_untracked());
+                }
+            },
+            None => {}
+        }
+    });
+
+    // Counts the rate limit down a second at a time
+    create_effect(move |_| {
+        if retry_in.get() > 0 {
+            set_timeout(
+                move || set_retry_in.update(|secs| *secs = secs.saturating_sub(1)),
+                std::time::Duration::from_secs(1),
+            );
+        }
+    });
+
+    let handle_delete_message = Callback::new(move |message_id: String| {
+        spawn_local(async move {
+            match crate::api::delete_message(message_id.clone()).await {
+                Ok(()) => set_messages.update(|loaded| loaded.retain(|m| m.id != message_id)),
+                Err(e) => tracing::error!("Failed to delete message: {}", e),
+            }
+        });
+    });
+
+    // Switches to the new branch; the switcher in the header leads back to the original
+    let handle_branch = Callback::new(move |message_id: String| {
+        spawn_local(async move {
+            match crate::api::branch_session(message_id).await {
+                Ok(session_id) => {
+                    set_current_session.set(Some(session_id));
+                    set_sessions_changed.update(|n| *n += 1);
+                }
+                Err(e) => tracing::error!("Failed to branch session: {}", e),
+            }
+        });
+    });
+
+    // Puts one of the user's messages back in the input, with its attachments, to be changed
+    // and sent again
+    let handle_edit = Callback::new(move |message_id: String| {
+        let Some(content) = messages.with_untracked(|loaded| {
+            loaded.iter().find(|m| m.id == message_id).map(|m| m.content.clone())
+        }) else {
+            return;
+        };
+        set_input_value.set(content);
+        set_editing.set(Some(message_id.clone()));
+        set_send_notice.set(None);
+        if let Some(input) = input_ref.get_untracked() {
+            let _ = input.focus();
+        }
+        spawn_local(async move {
+            match get_message_attachments(message_id).await {
+                Ok(attachments) => set_reattached_files.set(attachments),
+                Err(e) => tracing::error!("Failed to load the message's attachments: {}", e),
+            }
+        });
+    });
+
+    let cancel_edit = move |_| {
+        set_editing.set(None);
+        set_input_value.set(String::new());
+        set_reattached_files.set(Vec::new());
+    };
+
     let handle_send = move |ev: web_sys::SubmitEvent| {
         ev.prevent_default();
         let message = input_value.get();
-        if !message.trim().is_empty() {
+        let has_voice_note = uploaded_files.get().iter().any(|f| f.content_type.starts_with("audio/"));
+        if !message.trim().is_empty() || has_voice_note {
             let files = uploaded_files.get();
-            send_message.dispatch((message, files));
+            let attachment_ids = reattached_files.get().into_iter().map(|f| f.id).collect();
+            set_last_sent.set(message.clone());
+            set_send_notice.set(None);
+            set_following.set(true);
+            match editing.get() {
+                // The conversation continues from just before the edited message, in a new
+                // branch; the switcher in the header leads back to the original
+                Some(message_id) => {
+                    set_editing.set(None);
+                    spawn_local(async move {
+                        match crate::api::branch_for_edit(message_id).await {
+                            Ok(session_id) => {
+                                set_current_session.set(Some(session_id));
+                                set_sessions_changed.update(|n| *n += 1);
+                                send_message.dispatch((message, files, attachment_ids));
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to resubmit message: {}", e);
+                                set_send_notice.set(Some(e.to_string()));
+                                set_input_value.set(message);
+                            }
+                        }
+                    });
+                }
+                None => send_message.dispatch((message, files, attachment_ids)),
+            }
             set_input_value.set(String::new());
             set_uploaded_files.set(Vec::new());
+            set_reattached_files.set(Vec::new());
         }
     };
 
@@ -95,18 +423,44 @@ pub fn ChatBox() -> impl IntoView {
         set_uploaded_files.set(files);
     };
 
+    let handle_context_toggle = move |attachment_id: String| {
+        set_included_attachments.update(|included| {
+            if let Some(pos) = included.iter().position(|id| *id == attachment_id) {
+                included.remove(pos);
+            } else {
+                included.push(attachment_id);
+            }
+        });
+    };
+
+    let handle_voice_note = move |note: FileUpload| {
+        set_uploaded_files.update(|files| files.push(note));
+    };
+
+    let handle_library_select = move |file: FileAttachment| {
+        set_reattached_files.update(|files| {
+            if !files.iter().any(|f| f.id == file.id) {
+                files.push(file);
+            }
+        });
+    };
+
+    let handle_session_select = move |session_id: String| {
+        set_current_session.set(Some(session_id));
+    };
+
     let handle_model_change = move |provider: AIProvider, model_name: String| {
         set_selected_model.set(provider);
         set_selected_model_name.set(model_name);
         // Create new session with new model
         spawn_local(async move {
-            match create_session(None, provider, model_name).await {
+            match create_session(None, provider, model_name, incognito.get_untracked()).await {
                 Ok(session_id) => {
                     set_current_session.set(Some(session_id));
                     set_messages.set(Vec::new());
                 }
                 Err(e) => {
-                    log::error!("Failed to create session: {}", e);
+                    tracing::error!("Failed to create session: {}", e);
                 }
             }
         });
@@ -114,39 +468,161 @@ pub fn ChatBox() -> impl IntoView {
 
     view! {
         <div class="min-h-screen bg-gradient-to-br from-blue-50 to-indigo-100 p-4">
+            <SessionSidebar
+                current_session=current_session
+                refresh=sessions_changed
+                on_select=handle_session_select
+            />
             <div class="max-w-4xl mx-auto">
                 // Header with model switcher
                 <div class="bg-white rounded-lg shadow-lg p-4 mb-6">
                     <div class="flex items-center justify-between">
                         <h1 class="text-2xl font-bold text-gray-800">"AI Chat"</h1>
-                        <ModelSwitcher
-                            selected_provider=selected_model
-                            selected_model=selected_model_name
-                            on_change=handle_model_change
+                        {move || current_session.get().map(|session_id| {
+                            view! {
+                                <a
+                                    href=format!("/api/sessions/{}/attachments.zip", session_id)
+                                    class="ml-auto mr-3 p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                                    title="Download all attachments"
+                                >
+                                    <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-4l-4 4m0 0l-4-4m4 4V4"></path>
+                                    </svg>
+                                </a>
+                                <details class="relative mr-3">
+                                    <summary
+                                        class="list-none p-2 text-gray-500 hover:text-gray-700 transition-colors cursor-pointer"
+                                        title="Export conversation"
+                                    >
+                                        <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z"></path>
+                                        </svg>
+                                    </summary>
+                                    <div class="absolute right-0 top-10 w-48 py-1 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                                        <a
+                                            href=format!("/api/sessions/{}/export?format=markdown", session_id)
+                                            class="block px-3 py-1 text-sm text-gray-700 hover:bg-gray-100"
+                                        >
+                                            "Export as Markdown"
+                                        </a>
+                                        <a
+                                            href=format!("/api/sessions/{}/export?format=json", session_id)
+                                            class="block px-3 py-1 text-sm text-gray-700 hover:bg-gray-100"
+                                        >
+                                            "Export as JSON"
+                                        </a>
+                                        <a
+                                            href=format!("/api/sessions/{}/export?format=pdf", session_id)
+                                            class="block px-3 py-1 text-sm text-gray-700 hover:bg-gray-100"
+                                        >
+                                            "Export as PDF"
+                                        </a>
+                                    </div>
+                                </details>
+                            }
+                        })}
+                        <BranchSwitcher
+                            current_session=current_session
+                            refresh=sessions_changed
+                            on_select=handle_session_select
+                        />
+                        <SessionSchedulesPanel current_session=current_session />
+                        <SessionSettingsPanel current_session=current_session />
+                        <ConversationSearch />
+                        <TrashPanel
+                            on_restore=move |_| {
+                                set_sessions_changed.update(|n| *n += 1);
+                                load_latest_messages();
+                            }
                         />
+                        <button
+                            on:click=move |_| {
+                                // Starts a fresh session, so the effect above creates it with the new flag
+                                set_incognito.update(|on| *on = !*on);
+                                set_messages.set(Vec::new());
+                            }
+                            class=move || {
+                                if incognito.get() {
+                                    "mr-3 px-3 py-1 text-sm rounded-full bg-gray-800 text-white"
+                                } else {
+                                    "mr-3 px-3 py-1 text-sm rounded-full bg-gray-100 text-gray-600 hover:bg-gray-200"
+                                }
+                            }
+                            title="Incognito chats don't use or update memory"
+                        >
+                            {move || if incognito.get() { "Incognito on" } else { "Incognito" }}
+                        </button>
+                        {move || (!is_guest.get()).then(|| view! {
+                            <ModelSwitcher
+                                selected_provider=selected_model
+                                selected_model=selected_model_name
+                                on_change=handle_model_change
+                            />
+                        })}
+                        {move || role.get().filter(|role| role.can(Capability::ManageKeys)).map(|_| view! { <ApiKeySettings /> })}
+                        {move || (!is_guest.get()).then(|| view! { <TwoFactorSettings /> })}
+                        {move || (!is_guest.get()).then(|| view! { <AccountDataSettings /> })}
+                        <AccountMenu />
                     </div>
+                    <SessionTags
+                        current_session=current_session
+                        on_change=move |_| set_sessions_changed.update(|n| *n += 1)
+                    />
                 </div>
 
-                // Messages area
-                <div class="bg-white rounded-lg shadow-lg p-6 mb-6 min-h-96 max-h-96 overflow-y-auto">
-                    <div class="space-y-4">
-                        {move || {
-                            messages.get().into_iter().map(|msg| {
-                                view! {
-                                    <MessageComponent message=msg />
-                                }
-                            }).collect::<Vec<_>>()
-                        }}
-                        {move || {
-                            if is_loading.get() {
-                                view! {
-                                    <ThinkingAnimation />
+                // Messages area, filling most of the window so long chats have room
+                <div class="relative mb-6">
+                    <div
+                        node_ref=messages_ref
+                        on:scroll=handle_messages_scroll
+                        class="bg-white rounded-lg shadow-lg p-6 min-h-96 h-[65vh] overflow-y-auto"
+                    >
+                        <div class="space-y-4">
+                            {move || earlier_cursor.get().is_some().then(|| view! {
+                                <div class="text-center">
+                                    <button
+                                        type="button"
+                                        on:click=move |_| load_earlier_messages()
+                                        class="px-3 py-1 text-sm text-gray-600 bg-gray-100 rounded-full hover:bg-gray-200"
+                                    >
+                                        "Load earlier messages"
+                                    </button>
+                                </div>
+                            })}
+                            {move || {
+                                messages.get().into_iter().map(|msg| {
+                                    view! {
+                                        <MessageComponent message=msg on_delete=handle_delete_message on_branch=handle_branch on_edit=handle_edit />
+                                    }
+                                }).collect::<Vec<_>>()
+                            }}
+                            {move || {
+                                if is_streaming.get() {
+                                    view! {
+                                        <StreamingMessage message=in_progress />
+                                    }
+                                } else if is_loading.get() {
+                                    view! {
+                                        <ThinkingAnimation />
+                                    }
+                                } else {
+                                    view! { <div></div> }
                                 }
-                            } else {
-                                view! { <div></div> }
-                            }
-                        }}
+                            }}
+                        </div>
                     </div>
+                    {move || (!following.get()).then(|| view! {
+                        <button
+                            type="button"
+                            on:click=move |_| {
+                                set_following.set(true);
+                                scroll_to_latest(web_sys::ScrollBehavior::Smooth);
+                            }
+                            class="absolute bottom-4 left-1/2 transform -translate-x-1/2 px-3 py-1 text-sm text-white bg-gray-800/80 rounded-full shadow hover:bg-gray-800"
+                        >
+                            "Jump to latest ↓"
+                        </button>
+                    })}
                 </div>
 
                 // Suggested questions
@@ -166,16 +642,36 @@ pub fn ChatBox() -> impl IntoView {
 
                 // Floating input box
                 <div class="fixed bottom-6 left-1/2 transform -translate-x-1/2 w-full max-w-2xl">
+                    {move || send_notice.get().map(|notice| view! {
+                        <div class="mb-2 text-center text-sm text-amber-700">{notice}</div>
+                    })}
+                    {move || editing.get().is_some().then(|| view! {
+                        <div class="mb-2 flex items-center justify-center gap-2 text-sm text-gray-600">
+                            <span>"Editing a message. Sending it continues the conversation from there in a new branch."</span>
+                            <button type="button" on:click=cancel_edit class="underline hover:text-gray-800">
+                                "Cancel"
+                            </button>
+                        </div>
+                    })}
+                    {move || (retry_in.get() > 0).then(|| view! {
+                        <div class="mb-2 text-center text-sm text-amber-700">
+                            {format!("You're sending messages too quickly. Try again in {}s.", retry_in.get())}
+                        </div>
+                    })}
                     <div class="bg-white rounded-full shadow-2xl border border-gray-200">
                         <form on:submit=handle_send class="flex items-center p-2">
                             // File upload button
                             <FileUpload on_upload=handle_file_upload />
+
+                            // Re-attach a previous upload
+                            <FileLibrary on_select=handle_library_select />
                             
                             // Voice input button
-                            <VoiceInput />
+                            <VoiceInput on_record=handle_voice_note />
                             
                             // Text input
                             <input
+                                node_ref=input_ref
                                 type="text"
                                 placeholder="Ask me anything..."
                                 class="flex-1 px-4 py-2 text-gray-700 bg-transparent border-none outline-none"
@@ -188,7 +684,10 @@ pub fn ChatBox() -> impl IntoView {
                             // Send button
                             <button
                                 type="submit"
-                                disabled=move || is_loading.get() || input_value.get().trim().is_empty()
+                                disabled=move || {
+                                    let has_voice_note = uploaded_files.get().iter().any(|f| f.content_type.starts_with("audio/"));
+                                    is_loading.get() || retry_in.get() > 0 || (input_value.get().trim().is_empty() && !has_voice_note)
+                                }
                                 class="ml-2 p-2 bg-blue-600 text-white rounded-full hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
                             >
                                 <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
@@ -198,10 +697,28 @@ pub fn ChatBox() -> impl IntoView {
                         </form>
                     </div>
                     
+                    // Earlier session attachments to include in context
+                    {move || {
+                        let attachments = session_attachments.get();
+                        if !attachments.is_empty() {
+                            view! {
+                                <AttachmentContext
+                                    attachments=attachments
+                                    included=included_attachments
+                                    on_toggle=handle_context_toggle
+                                />
+                            }
+                        } else {
+                            view! { <div></div> }
+                        }
+                    }}
+
                     // File preview
                     {move || {
                         let files = uploaded_files.get();
-                        if !files.is_empty() {
+                        let reattached = reattached_files.get();
+                        let files_have_audio = files.iter().any(|f| f.content_type.starts_with("audio/"));
+                        if !files.is_empty() || !reattached.is_empty() {
                             view! {
                                 <div class="mt-2 bg-white rounded-lg shadow-lg p-3">
                                     <div class="text-sm text-gray-600 mb-2">"Attached files:"</div>
@@ -214,6 +731,25 @@ pub fn ChatBox() -> impl IntoView {
                                                 </div>
                                             }
                                         }).collect::<Vec<_>>()}
+                                        {files_have_audio.then(|| view! {
+                                            <label class="flex items-center text-sm text-gray-600 mt-1">
+                                                <input
+                                                    type="checkbox"
+                                                    class="mr-2"
+                                                    prop:checked=move || auto_transcribe.get()
+                                                    on:change=move |ev| set_auto_transcribe.set(event_target_checked(&ev))
+                                                />
+                                                "Transcribe voice notes for the AI"
+                                            </label>
+                                        })}
+                                        {reattached.into_iter().map(|file| {
+                                            view! {
+                                                <div class="flex items-center text-sm">
+                                                    <span class="text-gray-800">{file.file_name}</span>
+                                                    <span class="text-gray-500 ml-2">"(from library)"</span>
+                                                </div>
+                                            }
+                                        }).collect::<Vec<_>>()}
                                     </div>
                                 </div>
                             }
@@ -225,4 +761,42 @@ pub fn ChatBox() -> impl IntoView {
             </div>
         </div>
     }
-} 
\ No newline at end of file
+} 
+
+// How long to wait before reconnecting a sync socket that closed
+const SYNC_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(3);
+
+// Opens the sync WebSocket, passing each event it brings to `on_event`, and opens it again
+// whenever it closes. A reconnected socket starts with a `Lagged` event, since anything sent
+// while it was closed was missed.
+fn connect_sync(on_event: Callback<SyncEvent>, reconnecting: bool) {
+    let Some(location) = web_sys::window().map(|window| window.location()) else { return };
+    let scheme = if location.protocol().ok().as_deref() == Some("https:") { "wss" } else { "ws" };
+    let url = format!("{}://{}/api/sync", scheme, location.host().unwrap_or_default());
+    let Ok(socket) = web_sys::WebSocket::new(&url) else {
+        tracing::error!("Failed to connect to sync");
+        return;
+    };
+
+    if reconnecting {
+        let on_open = Closure::<dyn FnMut()>::new(move || on_event.call(SyncEvent::Lagged));
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        on_open.forget();
+    }
+
+    let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |ev: web_sys::MessageEvent| {
+        let Some(data) = ev.data().as_string() else { return };
+        match serde_json::from_str::<SyncEvent>(&data) {
+            Ok(event) => on_event.call(event),
+            Err(e) => tracing::error!("Failed to read sync event: {}", e),
+        }
+    });
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    let on_close = Closure::<dyn FnMut()>::new(move || {
+        set_timeout(move || connect_sync(on_event, true), SYNC_RECONNECT_DELAY);
+    });
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+}
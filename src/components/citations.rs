@@ -0,0 +1,38 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn Citations(citations: Vec<Citation>) -> impl IntoView {
+    view! {
+        <div class="mt-3 pt-3 border-t border-gray-200">
+            <div class="text-xs font-medium text-gray-500 mb-1">"Sources"</div>
+            <ol class="space-y-1">
+                {citations.into_iter().map(|citation| {
+                    // Only web sources get a clickable link; uploads just show their name
+                    let href = citation.source_uri.starts_with("http").then(|| citation.source_uri.clone());
+
+                    view! {
+                        <li class="relative group text-xs text-gray-600 flex items-start">
+                            <span class="font-mono mr-1">{format!("[{}]", citation.index)}</span>
+                            {match href {
+                                Some(href) => view! {
+                                    <a href=href target="_blank" rel="noopener noreferrer" class="underline hover:text-gray-800 truncate">
+                                        {citation.title.clone()}
+                                    </a>
+                                }.into_view(),
+                                None => view! { <span class="truncate">{citation.title.clone()}</span> }.into_view(),
+                            }}
+
+                            // Source preview on hover
+                            <div class="hidden group-hover:block absolute left-0 bottom-full mb-1 w-80 p-3 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                                <div class="text-xs font-medium text-gray-800 mb-1">{citation.title}</div>
+                                <div class="text-xs text-gray-600 whitespace-pre-wrap">{format!("{}…", citation.snippet)}</div>
+                                <div class="text-xs text-gray-400 mt-1 truncate">{citation.source_uri}</div>
+                            </div>
+                        </li>
+                    }
+                }).collect::<Vec<_>>()}
+            </ol>
+        </div>
+    }
+}
@@ -0,0 +1,137 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn ConversationSearch() -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (query, set_query) = create_signal(String::new());
+    let (results, set_results) = create_signal(Vec::<ConversationSearchResult>::new());
+    let (is_searching, set_is_searching) = create_signal(false);
+    // Keyword mode finds exact words (e.g. a code identifier); the default matches by meaning
+    let (keyword_mode, set_keyword_mode) = create_signal(false);
+
+    let toggle_panel = move |_| {
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    let handle_search = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        let query = query.get();
+        if query.trim().is_empty() {
+            return;
+        }
+        set_is_searching.set(true);
+        let keyword = keyword_mode.get();
+        spawn_local(async move {
+            let found = if keyword {
+                crate::api::search_messages(query, MessageSearchFilters::default()).await
+            } else {
+                crate::api::search_conversations(query).await
+            };
+            match found {
+                Ok(found) => set_results.set(found),
+                Err(e) => tracing::error!("Failed to search conversations: {}", e),
+            }
+            set_is_searching.set(false);
+        });
+    };
+
+    view! {
+        <div class="relative mr-3">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="Search past conversations"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M21 21l-6-6m2-5a7 7 0 11-14 0 7 7 0 0114 0z"></path>
+                </svg>
+            </button>
+
+            {move || {
+                if show_panel.get() {
+                    view! {
+                        <div class="absolute top-12 right-0 w-96 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                            <form on:submit=handle_search class="p-2 border-b border-gray-200">
+                                <input
+                                    type="text"
+                                    placeholder=move || if keyword_mode.get() { "Exact words..." } else { "What did we discuss about..." }
+                                    class="w-full px-3 py-2 text-sm text-gray-700 border border-gray-200 rounded outline-none focus:border-blue-400"
+                                    prop:value=move || query.get()
+                                    on:input=move |ev| set_query.set(event_target_value(&ev))
+                                />
+                                <label class="flex items-center mt-1 text-xs text-gray-600">
+                                    <input
+                                        type="checkbox"
+                                        class="mr-1"
+                                        prop:checked=move || keyword_mode.get()
+                                        on:change=move |ev| set_keyword_mode.set(event_target_checked(&ev))
+                                    />
+                                    "Match exact words"
+                                </label>
+                            </form>
+                            <div class="p-2 space-y-1 max-h-80 overflow-y-auto">
+                                {move || {
+                                    let found = results.get();
+                                    if is_searching.get() {
+                                        view! {
+                                            <div class="px-2 py-1 text-sm text-gray-500">"Searching..."</div>
+                                        }.into_view()
+                                    } else if found.is_empty() {
+                                        view! {
+                                            <div class="px-2 py-1 text-sm text-gray-500">"No matching messages"</div>
+                                        }.into_view()
+                                    } else {
+                                        found.into_iter().map(|result| {
+                                            let href = format!("/?session={}#message-{}", result.session_id, result.message_id);
+                                            let speaker = match result.role {
+                                                MessageRole::User => "You",
+                                                _ => "AI",
+                                            };
+
+                                            view! {
+                                                <a
+                                                    href=href
+                                                    on:click=move |_| set_show_panel.set(false)
+                                                    class="block px-2 py-2 rounded hover:bg-gray-100"
+                                                >
+                                                    <div class="flex items-center justify-between text-xs text-gray-500">
+                                                        <span class="font-medium text-gray-700 truncate">{result.session_title}</span>
+                                                        <span class="ml-2">{result.created_at.format("%Y-%m-%d").to_string()}</span>
+                                                    </div>
+                                                    <div class="text-sm text-gray-800 line-clamp-2">
+                                                        <span class="text-gray-500">{format!("{}: ", speaker)}</span>
+                                                        {highlighted(&result.snippet)}
+                                                    </div>
+                                                </a>
+                                            }
+                                        }).collect::<Vec<_>>().into_view()
+                                    }
+                                }}
+                            </div>
+                        </div>
+                    }
+                } else {
+                    view! { <div></div> }
+                }
+            }}
+        </div>
+    }
+}
+
+// Renders a snippet with the words between HIGHLIGHT_START and HIGHLIGHT_END marked
+fn highlighted(snippet: &str) -> Vec<View> {
+    let mut parts = Vec::new();
+    for (i, segment) in snippet.split(HIGHLIGHT_START).enumerate() {
+        // Every segment after the first opens with a highlighted run
+        match segment.split_once(HIGHLIGHT_END) {
+            Some((matched, rest)) if i > 0 => {
+                parts.push(view! { <mark class="bg-yellow-200 rounded">{matched.to_string()}</mark> }.into_view());
+                parts.push(rest.to_string().into_view());
+            }
+            _ => parts.push(segment.replace(HIGHLIGHT_END, "").into_view()),
+        }
+    }
+    parts
+}
@@ -0,0 +1,82 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn FileLibrary(on_select: Callback<FileAttachment>) -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (library, set_library) = create_signal(Vec::<FileAttachment>::new());
+
+    // Reload the library every time the panel is opened
+    create_effect(move |_| {
+        if show_panel.get() {
+            spawn_local(async move {
+                match crate::api::get_file_library().await {
+                    Ok(files) => set_library.set(files),
+                    Err(e) => tracing::error!("Failed to load file library: {}", e),
+                }
+            });
+        }
+    });
+
+    let toggle_panel = move |_| {
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    view! {
+        <div class="relative">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="File library"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M3 7v10a2 2 0 002 2h14a2 2 0 002-2V9a2 2 0 00-2-2h-6l-2-2H5a2 2 0 00-2 2z"></path>
+                </svg>
+            </button>
+
+            {move || {
+                if show_panel.get() {
+                    view! {
+                        <div class="absolute bottom-12 left-0 w-72 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                            <div class="p-2">
+                                <div class="text-xs font-medium text-gray-500 mb-2">"Previous uploads"</div>
+                                <div class="space-y-1 max-h-64 overflow-y-auto">
+                                    {move || {
+                                        let files = library.get();
+                                        if files.is_empty() {
+                                            view! {
+                                                <div class="px-2 py-1 text-sm text-gray-500">"No files uploaded yet"</div>
+                                            }.into_view()
+                                        } else {
+                                            files.into_iter().map(|file| {
+                                                let file_clone = file.clone();
+                                                let click_handler = move |_| {
+                                                    on_select.call(file_clone.clone());
+                                                    set_show_panel.set(false);
+                                                };
+
+                                                view! {
+                                                    <button
+                                                        type="button"
+                                                        on:click=click_handler
+                                                        class="w-full text-left px-2 py-1 text-sm hover:bg-gray-100 rounded flex items-center justify-between"
+                                                    >
+                                                        <span class="text-gray-800 truncate">{file.file_name}</span>
+                                                        <span class="text-xs text-gray-500 ml-2">{file.created_at.format("%Y-%m-%d").to_string()}</span>
+                                                    </button>
+                                                }
+                                            }).collect::<Vec<_>>().into_view()
+                                        }
+                                    }}
+                                </div>
+                            </div>
+                        </div>
+                    }
+                } else {
+                    view! { <div></div> }
+                }
+            }}
+        </div>
+    }
+}
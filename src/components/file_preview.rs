@@ -0,0 +1,237 @@
+use leptos::*;
+use wasm_bindgen::{JsCast, JsValue};
+use crate::models::*;
+
+// PDF.js is loaded deferred from public/vendor, like KaTeX; see scripts/vendor-assets.sh
+const PDFJS_WORKER: &str = "/vendor/pdfjs/pdf.worker.min.js";
+const SCRIPT_RETRIES: u32 = 20;
+const SCRIPT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[component]
+pub fn FilePreview(attachment: FileAttachment, on_close: Callback<()>) -> impl IntoView {
+    let src = attachment.url.clone().unwrap_or_default();
+    let file_type = attachment.file_type.clone();
+
+    let body = if file_type.starts_with("image/") {
+        view! { <ImagePreview src=src.clone() alt=attachment.file_name.clone() /> }.into_view()
+    } else if file_type == "application/pdf" {
+        view! { <PdfPreview src=src.clone() /> }.into_view()
+    } else if file_type.starts_with("text/") || file_type == "application/json" {
+        view! { <TextPreview attachment_id=attachment.id.clone() /> }.into_view()
+    } else {
+        view! {
+            <div class="p-6 text-center text-gray-600">
+                <p class="mb-3">"No preview available for this file type."</p>
+                <a href=src.clone() class="text-blue-600 underline" download=attachment.file_name.clone()>"Download"</a>
+            </div>
+        }.into_view()
+    };
+
+    view! {
+        <div
+            class="fixed inset-0 bg-black bg-opacity-60 flex items-center justify-center z-50"
+            on:click=move |_| on_close.call(())
+        >
+            <div
+                class="bg-white rounded-lg shadow-2xl w-full max-w-4xl max-h-[90vh] overflow-hidden flex flex-col"
+                on:click=|ev| ev.stop_propagation()
+            >
+                <div class="flex items-center justify-between px-4 py-2 border-b border-gray-200">
+                    <span class="text-sm font-medium text-gray-800 truncate">{attachment.file_name.clone()}</span>
+                    <div class="flex items-center space-x-3">
+                        <a href=src.clone() download=attachment.file_name.clone() class="text-sm text-gray-500 hover:text-gray-700">"Download"</a>
+                        <button
+                            on:click=move |_| on_close.call(())
+                            class="text-gray-500 hover:text-gray-700"
+                            title="Close"
+                        >
+                            <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                            </svg>
+                        </button>
+                    </div>
+                </div>
+                <div class="flex-1 overflow-auto p-4">
+                    {body}
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn ImagePreview(src: String, alt: String) -> impl IntoView {
+    let (zoom, set_zoom) = create_signal(1.0_f64);
+
+    let zoom_in = move |_| set_zoom.update(|z| *z = (*z * 1.25).min(8.0));
+    let zoom_out = move |_| set_zoom.update(|z| *z = (*z / 1.25).max(0.25));
+    let reset_zoom = move |_| set_zoom.set(1.0);
+
+    view! {
+        <div class="flex flex-col items-center">
+            <div class="flex items-center space-x-2 mb-3">
+                <button on:click=zoom_out class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded">"-"</button>
+                <button on:click=reset_zoom class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded">
+                    {move || format!("{:.0}%", zoom.get() * 100.0)}
+                </button>
+                <button on:click=zoom_in class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded">"+"</button>
+            </div>
+            <div class="overflow-auto max-h-[70vh] w-full text-center">
+                <img
+                    src=src
+                    alt=alt
+                    class="inline-block origin-top transition-transform"
+                    style=move || format!("transform: scale({});", zoom.get())
+                />
+            </div>
+        </div>
+    }
+}
+
+fn property(target: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    js_sys::Reflect::get(target, &JsValue::from_str(key))
+}
+
+// Calls one of `target`'s methods
+fn call(target: &JsValue, method: &str, args: &[JsValue]) -> Result<JsValue, JsValue> {
+    let function: js_sys::Function = property(target, method)?.dyn_into()?;
+    function.apply(target, &args.iter().collect::<js_sys::Array>())
+}
+
+async fn resolve(promise: JsValue) -> Result<JsValue, JsValue> {
+    wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(promise)).await
+}
+
+// Opens the PDF at `src` with PDF.js, waiting for its script if it hasn't loaded yet. The
+// file is fetched as data and drawn onto a canvas, so it never runs as a document.
+fn open_pdf(src: String, set_document: WriteSignal<Option<JsValue>>, set_failed: WriteSignal<bool>, attempt: u32) {
+    let library = web_sys::window()
+        .and_then(|window| property(&window, "pdfjsLib").ok())
+        .filter(|library| !library.is_undefined());
+    let Some(library) = library else {
+        if attempt < SCRIPT_RETRIES {
+            set_timeout(move || open_pdf(src, set_document, set_failed, attempt + 1), SCRIPT_RETRY_DELAY);
+        } else {
+            set_failed.set(true);
+        }
+        return;
+    };
+    spawn_local(async move {
+        let opened = async {
+            let options = property(&library, "GlobalWorkerOptions")?;
+            js_sys::Reflect::set(&options, &JsValue::from_str("workerSrc"), &JsValue::from_str(PDFJS_WORKER))?;
+            let task = call(&library, "getDocument", &[JsValue::from_str(&src)])?;
+            resolve(property(&task, "promise")?).await
+        }.await;
+        match opened {
+            Ok(document) => set_document.set(Some(document)),
+            Err(e) => {
+                tracing::error!("Failed to open PDF: {:?}", e);
+                set_failed.set(true);
+            }
+        }
+    });
+}
+
+// Draws one page of an open PDF onto `canvas`, `scale` times its natural size
+fn draw_page(document: JsValue, number: u32, scale: f64, canvas: web_sys::HtmlCanvasElement) {
+    spawn_local(async move {
+        let drawn = async {
+            let page = resolve(call(&document, "getPage", &[JsValue::from(number)])?).await?;
+            let options = js_sys::Object::new();
+            js_sys::Reflect::set(&options, &JsValue::from_str("scale"), &JsValue::from_f64(scale))?;
+            let viewport = call(&page, "getViewport", &[options.into()])?;
+            canvas.set_width(property(&viewport, "width")?.as_f64().unwrap_or_default() as u32);
+            canvas.set_height(property(&viewport, "height")?.as_f64().unwrap_or_default() as u32);
+
+            let context = canvas.get_context("2d")?.ok_or_else(|| JsValue::from_str("No 2D canvas context"))?;
+            let parameters = js_sys::Object::new();
+            js_sys::Reflect::set(&parameters, &JsValue::from_str("canvasContext"), &context)?;
+            js_sys::Reflect::set(&parameters, &JsValue::from_str("viewport"), &viewport)?;
+            let task = call(&page, "render", &[parameters.into()])?;
+            resolve(property(&task, "promise")?).await
+        }.await;
+        if let Err(e) = drawn {
+            tracing::error!("Failed to draw PDF page {}: {:?}", number, e);
+        }
+    });
+}
+
+// A PDF shown a page at a time, with page and zoom controls
+#[component]
+fn PdfPreview(src: String) -> impl IntoView {
+    let (document, set_document) = create_signal(None::<JsValue>);
+    let (failed, set_failed) = create_signal(false);
+    let (page, set_page) = create_signal(1_u32);
+    let (zoom, set_zoom) = create_signal(1.25_f64);
+    let canvas = create_node_ref::<html::Canvas>();
+
+    open_pdf(src.clone(), set_document, set_failed, 0);
+
+    let page_count = move || {
+        document.with(|document| document.as_ref()
+            .and_then(|document| property(document, "numPages").ok())
+            .and_then(|count| count.as_f64())
+            .unwrap_or(1.0) as u32)
+    };
+
+    // Redraw whenever the page or zoom changes
+    create_effect(move |_| {
+        let (Some(document), Some(canvas)) = (document.get(), canvas.get()) else { return };
+        draw_page(document, page.get(), zoom.get(), (*canvas).clone());
+    });
+
+    let previous_page = move |_| set_page.update(|page| *page = page.saturating_sub(1).max(1));
+    let next_page = move |_| {
+        let count = page_count();
+        set_page.update(|page| *page = (*page + 1).min(count));
+    };
+    let zoom_in = move |_| set_zoom.update(|z| *z = (*z * 1.25).min(5.0));
+    let zoom_out = move |_| set_zoom.update(|z| *z = (*z / 1.25).max(0.25));
+
+    view! {
+        <div class="flex flex-col items-center">
+            {move || if failed.get() {
+                view! {
+                    <div class="p-6 text-center text-gray-600">
+                        <p class="mb-3">"This PDF couldn't be shown."</p>
+                        <a href=src.clone() class="text-blue-600 underline">"Download"</a>
+                    </div>
+                }.into_view()
+            } else if document.with(Option::is_none) {
+                view! { <div class="text-sm text-gray-500">"Loading preview..."</div> }.into_view()
+            } else {
+                view! {
+                    <div class="flex items-center space-x-2 mb-3">
+                        <button on:click=previous_page disabled=move || page.get() <= 1 class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded disabled:opacity-50">"Previous"</button>
+                        <span class="text-sm text-gray-700">{move || format!("Page {} of {}", page.get(), page_count())}</span>
+                        <button on:click=next_page disabled=move || page.get() >= page_count() class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded disabled:opacity-50">"Next"</button>
+                        <button on:click=zoom_out class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded">"-"</button>
+                        <span class="text-sm text-gray-700">{move || format!("{:.0}%", zoom.get() * 100.0)}</span>
+                        <button on:click=zoom_in class="px-2 py-1 text-sm bg-gray-100 hover:bg-gray-200 rounded">"+"</button>
+                    </div>
+                }.into_view()
+            }}
+            <div class="overflow-auto max-h-[70vh] w-full text-center">
+                <canvas node_ref=canvas class=move || if document.with(Option::is_some) { "inline-block shadow" } else { "hidden" }></canvas>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn TextPreview(attachment_id: String) -> impl IntoView {
+    let highlighted = create_resource(
+        move || attachment_id.clone(),
+        |attachment_id| async move { crate::api::get_attachment_preview_html(attachment_id).await },
+    );
+
+    view! {
+        <Suspense fallback=|| view! { <div class="text-sm text-gray-500">"Loading preview..."</div> }>
+            {move || highlighted.get().map(|result| match result {
+                Ok(html) => view! { <div class="text-sm overflow-x-auto" inner_html=html></div> }.into_view(),
+                Err(e) => view! { <div class="text-sm text-red-600">{format!("Failed to load preview: {}", e)}</div> }.into_view(),
+            })}
+        </Suspense>
+    }
+}
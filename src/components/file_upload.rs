@@ -20,6 +20,7 @@ pub fn FileUpload(on_upload: Callback<Vec<FileUpload>>) -> impl IntoView {
                                         name: file.name(),
                                         content_type: file.type_(),
                                         data: bytes,
+                                        transcript: None,
                                     };
                                     uploaded_files.push(file_upload);
                                 }
@@ -0,0 +1,602 @@
+use leptos::*;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use wasm_bindgen::{JsCast, JsValue};
+use crate::components::message::copy_to_clipboard;
+
+// Math is swapped for `MATH_START <index> MATH_END` before the Markdown is parsed, so TeX
+// like `a_1 * b_2` isn't read as emphasis. Both are private-use characters.
+const MATH_START: char = '\u{E000}';
+const MATH_END: char = '\u{E001}';
+// The KaTeX and Mermaid scripts are deferred, so the first messages can render before
+// they have loaded
+const SCRIPT_RETRIES: u32 = 20;
+const SCRIPT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+// Mermaid needs an id per diagram it renders
+static NEXT_DIAGRAM: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+static MERMAID_CONFIGURED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+struct MathSpan {
+    tex: String,
+    // `$$...$$`, set on a line of its own
+    display: bool,
+}
+
+// An element being built: what has been rendered inside it so far
+struct Frame<'a> {
+    // None for the top level
+    tag: Option<Tag<'a>>,
+    children: Vec<View>,
+    // The plain text inside, used for an image's alt text and a code block's code
+    text: String,
+}
+
+impl<'a> Frame<'a> {
+    fn new(tag: Option<Tag<'a>>) -> Self {
+        Self { tag, children: Vec::new(), text: String::new() }
+    }
+}
+
+// Columns of the table being rendered
+#[derive(Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    in_head: bool,
+    column: usize,
+}
+
+// Links and images may only point at the web, an email address or somewhere on this site.
+// Anything else, like a `javascript:` URL, is dropped.
+fn safe_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let lower = url.to_ascii_lowercase();
+    let relative = match (lower.find(':'), lower.find(['/', '?', '#'])) {
+        (None, _) => true,
+        (Some(colon), Some(path)) => path < colon,
+        (Some(_), None) => false,
+    };
+    let allowed = relative || ["http://", "https://", "mailto:"].iter().any(|scheme| lower.starts_with(scheme));
+    allowed.then(|| url.to_string())
+}
+
+fn alignment_style(alignment: Option<&Alignment>) -> &'static str {
+    match alignment {
+        Some(Alignment::Left) => "text-align: left",
+        Some(Alignment::Center) => "text-align: center",
+        Some(Alignment::Right) => "text-align: right",
+        _ => "",
+    }
+}
+
+fn heading(level: HeadingLevel, children: Vec<View>) -> View {
+    match level {
+        HeadingLevel::H1 => view! { <h1 class="text-2xl font-bold mt-4 mb-2">{children}</h1> }.into_view(),
+        HeadingLevel::H2 => view! { <h2 class="text-xl font-bold mt-4 mb-2">{children}</h2> }.into_view(),
+        HeadingLevel::H3 => view! { <h3 class="text-lg font-semibold mt-3 mb-2">{children}</h3> }.into_view(),
+        HeadingLevel::H4 => view! { <h4 class="font-semibold mt-3 mb-1">{children}</h4> }.into_view(),
+        HeadingLevel::H5 => view! { <h5 class="text-sm font-semibold mt-2 mb-1">{children}</h5> }.into_view(),
+        HeadingLevel::H6 => view! { <h6 class="text-sm font-semibold text-gray-600 mt-2 mb-1">{children}</h6> }.into_view(),
+    }
+}
+
+// Takes `$...$` and `$$...$$` out of the Markdown, leaving code and `\$` alone. Like most
+// renderers, `$` only opens inline math when followed by a non-space character and only
+// closes it when preceded by a non-space character and not followed by a digit, so prices
+// like "$5 and $10" stay text.
+fn extract_math(content: &str) -> (String, Vec<MathSpan>) {
+    let mut markdown = String::with_capacity(content.len());
+    let mut spans = Vec::new();
+    let mut in_fence = false;
+    let mut at_line_start = true;
+    let mut i = 0;
+
+    let mut placeholder = |markdown: &mut String, tex: &str, display: bool| {
+        markdown.push(MATH_START);
+        markdown.push_str(&spans.len().to_string());
+        markdown.push(MATH_END);
+        spans.push(MathSpan { tex: tex.trim().to_string(), display });
+    };
+
+    while i < content.len() {
+        let rest = &content[i..];
+        if at_line_start {
+            let trimmed = rest.trim_start_matches([' ', '\t']);
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        at_line_start = c == '\n';
+
+        if in_fence {
+            markdown.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+
+        if c == '`' {
+            // Inline code runs to the next run of as many backticks
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let fence = &rest[..ticks];
+            let end = rest[ticks..].find(fence).map(|at| ticks + at + ticks).unwrap_or(ticks);
+            markdown.push_str(&rest[..end]);
+            i += end;
+        } else if rest.starts_with("\\$") {
+            markdown.push_str("\\$");
+            i += 2;
+        } else if let Some(inner) = rest.strip_prefix("$$") {
+            match inner.find("$$").filter(|&end| !inner[..end].trim().is_empty()) {
+                Some(end) => {
+                    placeholder(&mut markdown, &inner[..end], true);
+                    i += 2 + end + 2;
+                }
+                None => {
+                    markdown.push_str("$$");
+                    i += 2;
+                }
+            }
+        } else if c == '$' {
+            let inner = &rest[1..];
+            let line = &inner[..inner.find('\n').unwrap_or(inner.len())];
+            let opens = line.chars().next().is_some_and(|next| !next.is_whitespace());
+            let close = line.char_indices().skip(1).filter(|_| opens).find(|&(at, ch)| {
+                let before = line[..at].chars().next_back().unwrap_or(' ');
+                let after = line[at + 1..].chars().next().unwrap_or(' ');
+                ch == '$' && !before.is_whitespace() && before != '\\' && !after.is_ascii_digit()
+            }).map(|(at, _)| at);
+            match close {
+                Some(end) => {
+                    placeholder(&mut markdown, &line[..end], false);
+                    i += 1 + end + 1;
+                }
+                None => {
+                    markdown.push('$');
+                    i += 1;
+                }
+            }
+        } else {
+            markdown.push(c);
+            i += c.len_utf8();
+        }
+    }
+    (markdown, spans)
+}
+
+// Text with the math placeholders in it turned back into math, or into its TeX where math
+// can't be shown, like an image's alt text
+fn with_math(text: &str, math: &[MathSpan]) -> Vec<View> {
+    let mut views = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(MATH_START) {
+        let Some(end) = rest[start..].find(MATH_END).map(|end| start + end) else { break };
+        let Some(span) = rest[start + MATH_START.len_utf8()..end].parse::<usize>().ok().and_then(|index| math.get(index)) else { break };
+        if start > 0 {
+            views.push(rest[..start].to_string().into_view());
+        }
+        views.push(view! { <Math tex=span.tex.clone() display=span.display /> }.into_view());
+        rest = &rest[end + MATH_END.len_utf8()..];
+    }
+    if !rest.is_empty() {
+        views.push(rest.to_string().into_view());
+    }
+    views
+}
+
+fn restore_math(text: &str, math: &[MathSpan]) -> String {
+    let mut restored = text.to_string();
+    for (index, span) in math.iter().enumerate() {
+        let delimiter = if span.display { "$$" } else { "$" };
+        restored = restored.replace(
+            &format!("{}{}{}", MATH_START, index, MATH_END),
+            &format!("{}{}{}", delimiter, span.tex, delimiter),
+        );
+    }
+    restored
+}
+
+// A script library's object on `window` and one of its functions, once it has loaded
+fn library_function(library: &str, function: &str) -> Option<(JsValue, js_sys::Function)> {
+    let window = web_sys::window()?;
+    let library = js_sys::Reflect::get(&window, &JsValue::from_str(library)).ok()?;
+    let function = js_sys::Reflect::get(&library, &JsValue::from_str(function)).ok()?;
+    Some((library, function.dyn_into().ok()?))
+}
+
+// Typesets TeX into `element` with KaTeX, waiting for its script if it hasn't loaded yet.
+// Invalid TeX is shown in red rather than failing.
+fn typeset(element: JsValue, tex: String, display: bool, attempt: u32) {
+    let Some((katex, render)) = library_function("katex", "render") else {
+        if attempt < SCRIPT_RETRIES {
+            set_timeout(move || typeset(element, tex, display, attempt + 1), SCRIPT_RETRY_DELAY);
+        }
+        return;
+    };
+    let options = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&options, &JsValue::from_str("displayMode"), &JsValue::from_bool(display));
+    let _ = js_sys::Reflect::set(&options, &JsValue::from_str("throwOnError"), &JsValue::FALSE);
+    if let Err(e) = render.call3(&katex, &JsValue::from_str(&tex), &element, &options) {
+        tracing::error!("Failed to render math: {:?}", e);
+    }
+}
+
+// Math typeset by KaTeX once the page is running; until then, and without JavaScript, the
+// TeX is shown as it was written
+#[component]
+fn Math(tex: String, display: bool) -> impl IntoView {
+    let node = create_node_ref::<html::Span>();
+    let source = tex.clone();
+    create_effect(move |_| {
+        if let Some(element) = node.get() {
+            let element: JsValue = (*element).clone().into();
+            typeset(element, source.clone(), display, 0);
+        }
+    });
+
+    let class = if display { "block my-2 overflow-x-auto text-center" } else { "inline" };
+    let delimiter = if display { "$$" } else { "$" };
+    view! {
+        <span node_ref=node class=class>{format!("{}{}{}", delimiter, tex, delimiter)}</span>
+    }
+}
+
+// The text a message reads as once rendered, for copying: no Markdown syntax, a line per
+// paragraph, heading or list item, and table cells separated by tabs. Math keeps its TeX.
+pub fn plain_text(content: &str, math: bool) -> String {
+    let (content, math) = if math { extract_math(content) } else { (content.to_string(), Vec::new()) };
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let mut text = String::new();
+    for event in Parser::new_ext(&content, options) {
+        match event {
+            Event::Text(part) | Event::Code(part) | Event::Html(part) => text.push_str(&part),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::TaskListMarker(done) => text.push_str(if done { "[x] " } else { "[ ] " }),
+            Event::FootnoteReference(label) => text.push_str(&format!("[{}]", label)),
+            Event::End(Tag::TableCell) => text.push('\t'),
+            Event::End(Tag::TableHead | Tag::TableRow) => {
+                text.pop();
+                text.push('\n');
+            }
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item | Tag::BlockQuote | Tag::FootnoteDefinition(_)) => {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            // A code block's text already ends in a newline
+            Event::Start(Tag::CodeBlock(_) | Tag::List(_) | Tag::Table(_)) if !text.is_empty() && !text.ends_with('\n') => text.push('\n'),
+            _ => {}
+        }
+    }
+    restore_math(&text, &math).trim_end().to_string()
+}
+
+// Builds the element for a tag once everything inside it has been rendered
+fn close(frame: Frame<'_>, table: &mut TableState) -> View {
+    let Frame { tag, children, text } = frame;
+    let Some(tag) = tag else {
+        return children.into_view();
+    };
+    match tag {
+        Tag::Paragraph => view! { <p class="mb-2">{children}</p> }.into_view(),
+        Tag::Heading(level, _, _) => heading(level, children),
+        Tag::BlockQuote => view! {
+            <blockquote class="border-l-4 border-gray-300 pl-3 my-2 text-gray-600">{children}</blockquote>
+        }.into_view(),
+        Tag::CodeBlock(kind) => {
+            let language = match kind {
+                CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_string(),
+                CodeBlockKind::Indented => String::new(),
+            };
+            if language.eq_ignore_ascii_case("mermaid") {
+                view! { <MermaidDiagram source=text /> }.into_view()
+            } else {
+                view! { <CodeBlock language=language content=text /> }.into_view()
+            }
+        }
+        Tag::List(Some(start)) => view! {
+            <ol class="list-decimal pl-6 mb-2" start=start.to_string()>{children}</ol>
+        }.into_view(),
+        Tag::List(None) => view! { <ul class="list-disc pl-6 mb-2">{children}</ul> }.into_view(),
+        Tag::Item => view! { <li class="mb-1">{children}</li> }.into_view(),
+        Tag::FootnoteDefinition(name) => view! {
+            <div class="flex gap-1 mt-2 text-sm text-gray-600">
+                <sup>{name.to_string()}</sup>
+                <div>{children}</div>
+            </div>
+        }.into_view(),
+        Tag::Table(_) => {
+            *table = TableState::default();
+            view! {
+                <div class="overflow-x-auto mb-2">
+                    <table class="min-w-full border-collapse text-sm">{children}</table>
+                </div>
+            }.into_view()
+        }
+        Tag::TableHead => {
+            table.in_head = false;
+            view! { <thead class="bg-gray-200"><tr>{children}</tr></thead> }.into_view()
+        }
+        Tag::TableRow => view! { <tr class="border-t border-gray-300">{children}</tr> }.into_view(),
+        Tag::TableCell => {
+            let style = alignment_style(table.alignments.get(table.column));
+            table.column += 1;
+            if table.in_head {
+                view! { <th class="px-2 py-1 font-semibold" style=style>{children}</th> }.into_view()
+            } else {
+                view! { <td class="px-2 py-1" style=style>{children}</td> }.into_view()
+            }
+        }
+        Tag::Emphasis => view! { <em>{children}</em> }.into_view(),
+        Tag::Strong => view! { <strong>{children}</strong> }.into_view(),
+        Tag::Strikethrough => view! { <del>{children}</del> }.into_view(),
+        Tag::Link(_, url, title) => match safe_url(&url) {
+            Some(href) => view! {
+                <a
+                    href=href
+                    title=title.to_string()
+                    target="_blank"
+                    rel="noopener noreferrer"
+                    class="text-blue-600 underline hover:text-blue-800"
+                >
+                    {children}
+                </a>
+            }.into_view(),
+            None => children.into_view(),
+        },
+        Tag::Image(_, url, title) => match safe_url(&url) {
+            Some(src) => view! {
+                <img src=src alt=text title=title.to_string() loading="lazy" class="max-w-full rounded my-2" />
+            }.into_view(),
+            None => text.into_view(),
+        },
+    }
+}
+
+// Renders a message's Markdown: CommonMark plus tables, strikethrough, task lists and
+// footnotes, and with `math` set, `$...$` and `$$...$$` as TeX. Raw HTML in the message is
+// shown as text rather than interpreted, so a reply can't inject markup.
+pub fn render_markdown(content: &str, math: bool) -> Vec<View> {
+    let (content, math) = if math { extract_math(content) } else { (content.to_string(), Vec::new()) };
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let mut stack = vec![Frame::new(None)];
+    let mut table = TableState::default();
+
+    for event in Parser::new_ext(&content, options) {
+        match event {
+            Event::Start(tag) => {
+                match &tag {
+                    Tag::Table(alignments) => table.alignments = alignments.clone(),
+                    Tag::TableHead => {
+                        table.in_head = true;
+                        table.column = 0;
+                    }
+                    Tag::TableRow => table.column = 0,
+                    _ => {}
+                }
+                stack.push(Frame::new(Some(tag)));
+            }
+            Event::End(_) => {
+                if stack.len() > 1 {
+                    let frame = stack.pop().unwrap();
+                    let view = close(frame, &mut table);
+                    stack.last_mut().unwrap().children.push(view);
+                }
+            }
+            Event::Text(text) => {
+                let frame = stack.last_mut().unwrap();
+                match frame.tag {
+                    Some(Tag::CodeBlock(_)) => frame.text.push_str(&text),
+                    Some(Tag::Image(..)) => frame.text.push_str(&restore_math(&text, &math)),
+                    _ => frame.children.extend(with_math(&text, &math)),
+                }
+            }
+            Event::Code(code) => {
+                let frame = stack.last_mut().unwrap();
+                if matches!(frame.tag, Some(Tag::Image(..))) {
+                    frame.text.push_str(&code);
+                } else {
+                    frame.children.push(view! {
+                        <code class="bg-gray-200 text-gray-800 px-1 rounded">{code.to_string()}</code>
+                    }.into_view());
+                }
+            }
+            Event::Html(html) => {
+                let frame = stack.last_mut().unwrap();
+                match frame.tag {
+                    Some(Tag::CodeBlock(_)) => frame.text.push_str(&html),
+                    _ => frame.children.push(html.to_string().into_view()),
+                }
+            }
+            Event::SoftBreak => {
+                let frame = stack.last_mut().unwrap();
+                match frame.tag {
+                    Some(Tag::Image(..)) => frame.text.push(' '),
+                    _ => frame.children.push(" ".into_view()),
+                }
+            }
+            Event::HardBreak => stack.last_mut().unwrap().children.push(view! { <br /> }.into_view()),
+            Event::Rule => stack.last_mut().unwrap().children.push(view! { <hr class="my-3 border-gray-300" /> }.into_view()),
+            Event::TaskListMarker(checked) => stack.last_mut().unwrap().children.push(view! {
+                <input type="checkbox" class="mr-1" disabled=true checked=checked />
+            }.into_view()),
+            Event::FootnoteReference(name) => stack.last_mut().unwrap().children.push(view! {
+                <sup class="text-gray-500">{format!("[{}]", name)}</sup>
+            }.into_view()),
+        }
+    }
+
+    // Unclosed tags can't happen with a complete document, but are closed just in case
+    while stack.len() > 1 {
+        let frame = stack.pop().unwrap();
+        let view = close(frame, &mut table);
+        stack.last_mut().unwrap().children.push(view);
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+// A code block, highlighted on the server for its fenced language, with line numbers and
+// a toggle between scrolling and wrapping long lines. Until the highlighting arrives, or
+// when there is no language to go by, the code is shown plain.
+#[component]
+pub fn CodeBlock(language: String, content: String) -> impl IntoView {
+    let (wrap, set_wrap) = create_signal(false);
+    let plain: Vec<String> = content.lines().map(str::to_string).collect();
+    let highlighted = create_resource(
+        {
+            let (content, language) = (content.clone(), language.clone());
+            move || (content.clone(), language.clone())
+        },
+        |(content, language)| async move {
+            if language.is_empty() {
+                return None;
+            }
+            match crate::api::highlight_code(content, language).await {
+                Ok(lines) => Some(lines),
+                Err(e) => {
+                    tracing::error!("Failed to highlight code: {}", e);
+                    None
+                }
+            }
+        },
+    );
+
+    let copy_code = move |_| copy_to_clipboard(content.clone(), || {});
+
+    let line_class = move || if wrap.get() { "flex-1 whitespace-pre-wrap break-words" } else { "flex-1 whitespace-pre" };
+
+    view! {
+        <div class="relative bg-gray-900 rounded-lg p-4 mb-4">
+            <div class="flex items-center justify-between mb-2">
+                <span class="text-sm text-gray-400">{language}</span>
+                <div class="flex items-center gap-3">
+                    <button
+                        type="button"
+                        on:click=move |_| set_wrap.update(|wrap| *wrap = !*wrap)
+                        class=move || if wrap.get() { "text-xs text-white" } else { "text-xs text-gray-400 hover:text-white transition-colors" }
+                        title="Wrap long lines"
+                    >
+                        "Wrap"
+                    </button>
+                    <button
+                        type="button"
+                        on:click=copy_code
+                        class="text-gray-400 hover:text-white transition-colors"
+                        title="Copy code"
+                    >
+                        <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M8 16H6a2 2 0 01-2-2V6a2 2 0 012-2h8a2 2 0 012 2v2m-6 12h8a2 2 0 002-2v-8a2 2 0 00-2-2h-8a2 2 0 00-2 2v8a2 2 0 002 2z"></path>
+                        </svg>
+                    </button>
+                </div>
+            </div>
+            <pre class=move || if wrap.get() { "text-sm text-gray-100" } else { "text-sm text-gray-100 overflow-x-auto" }>
+                <code class="block">
+                    {move || match highlighted.get().flatten() {
+                        Some(lines) => lines.into_iter().enumerate().map(|(index, html)| view! {
+                            <div class="flex">
+                                <span class="select-none w-8 shrink-0 pr-3 text-right text-gray-500">{index + 1}</span>
+                                <span class=line_class inner_html=html></span>
+                            </div>
+                        }).collect_view(),
+                        None => plain.iter().enumerate().map(|(index, line)| view! {
+                            <div class="flex">
+                                <span class="select-none w-8 shrink-0 pr-3 text-right text-gray-500">{index + 1}</span>
+                                <span class=line_class>{line.clone()}</span>
+                            </div>
+                        }).collect_view(),
+                    }}
+                </code>
+            </pre>
+        </div>
+    }
+}
+
+// Draws a diagram from its Mermaid definition into `element`. Mermaid's strict security
+// level strips scripts and click handlers from the definition, since it comes from a reply.
+fn draw_diagram(element: web_sys::Element, source: String, set_drawn: WriteSignal<bool>, attempt: u32) {
+    let Some((mermaid, render)) = library_function("mermaid", "render") else {
+        if attempt < SCRIPT_RETRIES {
+            set_timeout(move || draw_diagram(element, source, set_drawn, attempt + 1), SCRIPT_RETRY_DELAY);
+        }
+        return;
+    };
+    if !MERMAID_CONFIGURED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+        if let Some((_, initialize)) = library_function("mermaid", "initialize") {
+            let config = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&config, &JsValue::from_str("startOnLoad"), &JsValue::FALSE);
+            let _ = js_sys::Reflect::set(&config, &JsValue::from_str("securityLevel"), &JsValue::from_str("strict"));
+            let _ = initialize.call1(&mermaid, &config);
+        }
+    }
+    let id = format!("mermaid-{}", NEXT_DIAGRAM.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
+    let promise = match render.call2(&mermaid, &JsValue::from_str(&id), &JsValue::from_str(&source)) {
+        Ok(promise) => promise,
+        Err(e) => {
+            tracing::error!("Failed to render diagram: {:?}", e);
+            return;
+        }
+    };
+    spawn_local(async move {
+        match wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(promise)).await {
+            Ok(result) => {
+                if let Some(svg) = js_sys::Reflect::get(&result, &JsValue::from_str("svg")).ok().and_then(|svg| svg.as_string()) {
+                    element.set_inner_html(&svg);
+                    set_drawn.set(true);
+                }
+            }
+            // Usually a definition with a syntax error, including one still streaming in
+            Err(e) => tracing::error!("Failed to render diagram: {:?}", e),
+        }
+    });
+}
+
+// A `mermaid` code block drawn as its diagram, with a toggle back to the definition. The
+// definition is shown until the diagram is drawn, and instead of it if it can't be.
+#[component]
+pub fn MermaidDiagram(source: String) -> impl IntoView {
+    let (show_source, set_show_source) = create_signal(false);
+    let (drawn, set_drawn) = create_signal(false);
+    let diagram = create_node_ref::<html::Div>();
+    {
+        let source = source.clone();
+        create_effect(move |_| {
+            if let Some(element) = diagram.get() {
+                let element: web_sys::Element = (*element).clone().into();
+                draw_diagram(element, source.clone(), set_drawn, 0);
+            }
+        });
+    }
+    let source_shown = move || show_source.get() || !drawn.get();
+
+    view! {
+        <div class="relative bg-gray-900 rounded-lg p-4 mb-4">
+            <div class="flex items-center justify-between mb-2">
+                <span class="text-sm text-gray-400">"mermaid"</span>
+                {move || drawn.get().then(|| view! {
+                    <button
+                        type="button"
+                        on:click=move |_| set_show_source.update(|show| *show = !*show)
+                        class="text-xs text-gray-400 hover:text-white transition-colors"
+                        title="Switch between the diagram and its source"
+                    >
+                        {move || if show_source.get() { "Diagram" } else { "Source" }}
+                    </button>
+                })}
+            </div>
+            <div
+                node_ref=diagram
+                class=move || if source_shown() { "hidden" } else { "flex justify-center overflow-x-auto bg-white rounded p-2" }
+            ></div>
+            <pre class=move || if source_shown() { "text-sm text-gray-100 overflow-x-auto" } else { "hidden" }>
+                <code>{source}</code>
+            </pre>
+        </div>
+    }
+}
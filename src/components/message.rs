@@ -1,29 +1,70 @@
 use leptos::*;
 use crate::models::*;
+use crate::components::{citations::Citations, file_preview::FilePreview, markdown::{plain_text, render_markdown}};
+
+// How long a copy button reads "Copied" after it's clicked
+const COPIED_FEEDBACK: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Puts `text` on the clipboard, calling `on_copied` once it's there. Browsers only allow
+// this from a user action, like a click, on a secure page.
+pub fn copy_to_clipboard(text: String, on_copied: impl FnOnce() + 'static) {
+    let Some(window) = web_sys::window() else { return };
+    let promise = window.navigator().clipboard().write_text(&text);
+    spawn_local(async move {
+        match wasm_bindgen_futures::JsFuture::from(promise).await {
+            Ok(_) => on_copied(),
+            Err(e) => tracing::error!("Failed to copy to the clipboard: {:?}", e),
+        }
+    });
+}
 
 #[component]
-pub fn MessageComponent(message: Message) -> impl IntoView {
+pub fn MessageComponent(
+    message: Message,
+    // Moves the message to the trash; no delete button is shown without it
+    #[prop(optional)] on_delete: Option<Callback<String>>,
+    // Forks the conversation at this message; no branch button is shown without it
+    #[prop(optional)] on_branch: Option<Callback<String>>,
+    // Puts the user's own message back in the input to resubmit; ignored for replies
+    #[prop(optional)] on_edit: Option<Callback<String>>,
+) -> impl IntoView {
     let (show_reasoning, set_show_reasoning) = create_signal(false);
 
+    let message_id = message.id.clone();
+    let attachments = create_resource(
+        move || message_id.clone(),
+        |message_id| async move {
+            crate::api::get_message_attachments(message_id).await.unwrap_or_default()
+        },
+    );
+
     let is_user = move || matches!(message.role, MessageRole::User);
     let is_assistant = move || matches!(message.role, MessageRole::Assistant);
+    // Only replies are typeset; users' own `$` signs are left as typed
+    let with_math = is_assistant();
 
     let toggle_reasoning = move |_| {
         set_show_reasoning.update(|show| *show = !*show);
     };
 
-    let copy_to_clipboard = move |text: String| {
-        spawn_local(async move {
-            if let Some(window) = web_sys::window() {
-                if let Some(navigator) = window.navigator().clipboard() {
-                    let _ = navigator.write_text(&text).await;
-                }
-            }
-        });
+    // Which copy button was last used, so it can say "Copied" for a moment
+    let (copied, set_copied) = create_signal(None::<&'static str>);
+    let copy = {
+        let content = message.content.clone();
+        move |format: &'static str| {
+            let text = if format == "markdown" { content.clone() } else { plain_text(&content, with_math) };
+            copy_to_clipboard(text, move || {
+                set_copied.set(Some(format));
+                set_timeout(move || set_copied.set(None), COPIED_FEEDBACK);
+            });
+        }
+    };
+    let copy_label = move |format: &'static str, label: &'static str| {
+        move || if copied.get() == Some(format) { "Copied" } else { label }
     };
 
     view! {
-        <div class=move || {
+        <div id=format!("message-{}", message.id) class=move || {
             if is_user() {
                 "flex justify-end"
             } else {
@@ -40,8 +81,15 @@ pub fn MessageComponent(message: Message) -> impl IntoView {
             }>
                 // Message content with markdown rendering
                 <div class="prose prose-sm max-w-none">
-                    {move || render_markdown(&message.content)}
+                    {move || render_markdown(&message.content, with_math)}
                 </div>
+
+                // Attachments (voice notes get an inline player)
+                {move || attachments.get().map(|files| {
+                    files.into_iter().map(|attachment| {
+                        view! { <AttachmentView attachment=attachment /> }
+                    }).collect::<Vec<_>>()
+                })}
                 
                 // Reasoning dropdown (only for assistant messages)
                 {move || {
@@ -87,9 +135,72 @@ pub fn MessageComponent(message: Message) -> impl IntoView {
                     }
                 }}
                 
+                // Numbered sources for retrieval-augmented replies
+                {(!message.citations.is_empty()).then(|| view! {
+                    <Citations citations=message.citations.clone() />
+                })}
+
                 // Message metadata
                 <div class="mt-2 text-xs text-gray-500 flex items-center justify-between">
                     <span>{format!("{}", message.created_at.format("%H:%M"))}</span>
+                    <button
+                        type="button"
+                        on:click={
+                            let copy = copy.clone();
+                            move |_| copy("text")
+                        }
+                        class="ml-2 opacity-60 hover:opacity-100"
+                        title="Copy the message as plain text"
+                    >
+                        {copy_label("text", "Copy")}
+                    </button>
+                    <button
+                        type="button"
+                        on:click=move |_| copy("markdown")
+                        class="ml-2 opacity-60 hover:opacity-100"
+                        title="Copy the message's Markdown"
+                    >
+                        {copy_label("markdown", "Copy Markdown")}
+                    </button>
+                    {on_edit.filter(|_| matches!(message.role, MessageRole::User)).map(|on_edit| {
+                        let edit_id = message.id.clone();
+                        view! {
+                            <button
+                                type="button"
+                                on:click=move |_| on_edit.call(edit_id.clone())
+                                class="ml-2 opacity-60 hover:opacity-100"
+                                title="Edit and resend this message"
+                            >
+                                "Edit"
+                            </button>
+                        }
+                    })}
+                    {on_branch.map(|on_branch| {
+                        let branch_id = message.id.clone();
+                        view! {
+                            <button
+                                type="button"
+                                on:click=move |_| on_branch.call(branch_id.clone())
+                                class="ml-2 opacity-60 hover:opacity-100"
+                                title="Continue in a new branch from this message"
+                            >
+                                "Branch"
+                            </button>
+                        }
+                    })}
+                    {on_delete.map(|on_delete| {
+                        let delete_id = message.id.clone();
+                        view! {
+                            <button
+                                type="button"
+                                on:click=move |_| on_delete.call(delete_id.clone())
+                                class="ml-2 opacity-60 hover:opacity-100"
+                                title="Move to trash"
+                            >
+                                "Delete"
+                            </button>
+                        }
+                    })}
                     {move || {
                         if let Some(tokens) = message.tokens_used {
                             view! {
@@ -105,124 +216,36 @@ pub fn MessageComponent(message: Message) -> impl IntoView {
     }
 }
 
-fn render_markdown(content: &str) -> Vec<View> {
-    use pulldown_cmark::{Parser, Event, Tag, CodeBlockKind};
-    
-    let parser = Parser::new(content);
-    let mut elements = Vec::new();
-    let mut current_text = String::new();
-    let mut in_code_block = false;
-    let mut code_lang = String::new();
-    let mut code_content = String::new();
-    
-    for event in parser {
-        match event {
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
-                if !current_text.is_empty() {
-                    elements.push(view! {
-                        <p class="mb-2">{current_text.clone()}</p>
-                    });
-                    current_text.clear();
-                }
-                in_code_block = true;
-                code_lang = lang.to_string();
-            }
-            Event::End(Tag::CodeBlock(_)) => {
-                if in_code_block {
-                    elements.push(view! {
-                        <CodeBlock
-                            language=code_lang.clone()
-                            content=code_content.clone()
-                        />
-                    });
-                    in_code_block = false;
-                    code_content.clear();
-                    code_lang.clear();
-                }
-            }
-            Event::Text(text) => {
-                if in_code_block {
-                    code_content.push_str(&text);
-                } else {
-                    current_text.push_str(&text);
-                }
-            }
-            Event::Start(Tag::Paragraph) => {
-                if !current_text.is_empty() {
-                    elements.push(view! {
-                        <p class="mb-2">{current_text.clone()}</p>
-                    });
-                    current_text.clear();
-                }
-            }
-            Event::End(Tag::Paragraph) => {
-                if !current_text.is_empty() {
-                    elements.push(view! {
-                        <p class="mb-2">{current_text.clone()}</p>
-                    });
-                    current_text.clear();
-                }
-            }
-            Event::Start(Tag::Strong) => {
-                current_text.push_str("<strong>");
-            }
-            Event::End(Tag::Strong) => {
-                current_text.push_str("</strong>");
-            }
-            Event::Start(Tag::Emphasis) => {
-                current_text.push_str("<em>");
-            }
-            Event::End(Tag::Emphasis) => {
-                current_text.push_str("</em>");
-            }
-            Event::Start(Tag::Code) => {
-                current_text.push_str("<code class=\"bg-gray-200 px-1 rounded\">");
-            }
-            Event::End(Tag::Code) => {
-                current_text.push_str("</code>");
-            }
-            _ => {}
-        }
-    }
-    
-    if !current_text.is_empty() {
-        elements.push(view! {
-            <p class="mb-2">{current_text}</p>
-        });
-    }
-    
-    elements
-}
-
 #[component]
-fn CodeBlock(language: String, content: String) -> impl IntoView {
-    let copy_code = move |_| {
-        spawn_local(async move {
-            if let Some(window) = web_sys::window() {
-                if let Some(navigator) = window.navigator().clipboard() {
-                    let _ = navigator.write_text(&content).await;
-                }
-            }
-        });
-    };
+fn AttachmentView(attachment: FileAttachment) -> impl IntoView {
+    if attachment.file_type.starts_with("audio/") {
+        let src = attachment.url.clone().unwrap_or_default();
+        view! {
+            <div class="mt-2">
+                <audio controls=true preload="metadata" src=src class="w-64"></audio>
+            </div>
+        }
+    } else {
+        let (show_preview, set_show_preview) = create_signal(false);
+        let file_name = attachment.file_name.clone();
 
-    view! {
-        <div class="relative bg-gray-900 rounded-lg p-4 mb-4">
-            <div class="flex items-center justify-between mb-2">
-                <span class="text-sm text-gray-400">{language}</span>
+        view! {
+            <div class="mt-2">
                 <button
-                    on:click=copy_code
-                    class="text-gray-400 hover:text-white transition-colors"
-                    title="Copy code"
+                    type="button"
+                    on:click=move |_| set_show_preview.set(true)
+                    class="inline-flex items-center text-sm underline"
+                    title="Preview"
                 >
-                    <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M8 16H6a2 2 0 01-2-2V6a2 2 0 012-2h8a2 2 0 012 2v2m-6 12h8a2 2 0 002-2v-8a2 2 0 00-2-2h-8a2 2 0 00-2 2v8a2 2 0 002 2z"></path>
-                    </svg>
+                    {file_name}
                 </button>
+                {move || show_preview.get().then(|| view! {
+                    <FilePreview
+                        attachment=attachment.clone()
+                        on_close=move |_| set_show_preview.set(false)
+                    />
+                })}
             </div>
-            <pre class="text-sm text-gray-100 overflow-x-auto">
-                <code>{content}</code>
-            </pre>
-        </div>
+        }
     }
-} 
\ No newline at end of file
+}
@@ -1,7 +1,26 @@
 pub mod chat_box;
 pub mod message;
+pub mod markdown;
+pub mod streaming_message;
 pub mod suggested_questions;
 pub mod model_switcher;
 pub mod file_upload;
 pub mod voice_input;
-pub mod thinking_animation; 
\ No newline at end of file
+pub mod thinking_animation;
+pub mod file_library;
+pub mod attachment_context;
+pub mod file_preview;
+pub mod citations;
+pub mod conversation_search;
+pub mod session_sidebar;
+pub mod session_tags;
+pub mod trash;
+pub mod branch_switcher;
+pub mod session_settings;
+pub mod session_schedules;
+pub mod auth;
+pub mod api_keys;
+pub mod admin;
+pub mod two_factor;
+pub mod account;
+pub mod security;
\ No newline at end of file
@@ -16,7 +16,7 @@ pub fn ModelSwitcher(
         spawn_local(async move {
             match crate::api::get_available_models(provider).await {
                 Ok(models) => set_available_models.set(models),
-                Err(e) => log::error!("Failed to load models: {}", e),
+                Err(e) => tracing::error!("Failed to load models: {}", e),
             }
         });
     });
@@ -0,0 +1,245 @@
+use leptos::*;
+use crate::models::*;
+
+// The /security page: every browser the user is signed in on, with a way to sign each out,
+// and the user's personal access tokens
+#[component]
+pub fn SecurityPage() -> impl IntoView {
+    let (sessions, set_sessions) = create_signal(Vec::<LoginSession>::new());
+    let (error, set_error) = create_signal(None::<String>);
+
+    let load_sessions = move || {
+        spawn_local(async move {
+            match crate::api::get_login_sessions().await {
+                Ok(found) => set_sessions.set(found),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    load_sessions();
+
+    let revoke = move |session: LoginSession| {
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::revoke_login_session(session.id).await {
+                // Signing out this browser leaves nothing here to manage
+                Ok(()) if session.current => {
+                    if let Some(window) = web_sys::window() {
+                        let _ = window.location().set_href("/login");
+                    }
+                }
+                Ok(()) => load_sessions(),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let revoke_others = move |_| {
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::revoke_other_login_sessions().await {
+                Ok(_) => load_sessions(),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    view! {
+        <div class="max-w-3xl mx-auto p-6 space-y-6">
+            <div class="flex items-center justify-between">
+                <h1 class="text-2xl font-semibold text-gray-900">"Security"</h1>
+                <a href="/" class="text-sm text-indigo-600 hover:underline">"Back to chat"</a>
+            </div>
+            {move || error.get().map(|message| view! {
+                <div class="p-3 text-sm text-red-700 bg-red-50 rounded-lg">{message}</div>
+            })}
+            <section>
+                <div class="flex items-center justify-between mb-3">
+                    <h2 class="text-lg font-medium text-gray-800">"Signed-in devices"</h2>
+                    {move || (sessions.get().len() > 1).then(|| view! {
+                        <button type="button" on:click=revoke_others class="text-sm text-red-600 hover:text-red-800">
+                            "Sign out everywhere else"
+                        </button>
+                    })}
+                </div>
+                <div class="overflow-x-auto bg-white border border-gray-200 rounded-lg">
+                    <table class="w-full text-sm">
+                        <thead class="text-left text-xs text-gray-500 uppercase">
+                            <tr>
+                                <th class="px-3 py-2">"Device"</th>
+                                <th class="px-3 py-2">"Address"</th>
+                                <th class="px-3 py-2">"Signed in"</th>
+                                <th class="px-3 py-2">"Last seen"</th>
+                                <th class="px-3 py-2"></th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            {move || sessions.get().into_iter().map(|session| {
+                                let last_seen = session.last_seen_at.unwrap_or(session.created_at);
+                                let current = session.current;
+                                view! {
+                                    <tr class="border-t border-gray-100">
+                                        <td class="px-3 py-2">
+                                            <div class="text-gray-900" title=session.user_agent.clone().unwrap_or_default()>{session.device()}</div>
+                                            {current.then(|| view! { <div class="text-xs text-green-700">"This browser"</div> })}
+                                        </td>
+                                        <td class="px-3 py-2 text-gray-600">{session.ip_address.clone().unwrap_or_else(|| "-".to_string())}</td>
+                                        <td class="px-3 py-2 text-gray-600">{session.created_at.format("%Y-%m-%d").to_string()}</td>
+                                        <td class="px-3 py-2 text-gray-600">{last_seen.format("%Y-%m-%d %H:%M UTC").to_string()}</td>
+                                        <td class="px-3 py-2 text-right">
+                                            <button
+                                                type="button"
+                                                on:click=move |_| revoke(session.clone())
+                                                class="text-xs text-red-600 hover:text-red-800"
+                                            >
+                                                "Sign out"
+                                            </button>
+                                        </td>
+                                    </tr>
+                                }
+                            }).collect::<Vec<_>>()}
+                        </tbody>
+                    </table>
+                </div>
+            </section>
+            <ApiTokenSection set_error=set_error />
+        </div>
+    }
+}
+
+#[component]
+fn ApiTokenSection(set_error: WriteSignal<Option<String>>) -> impl IntoView {
+    let (tokens, set_tokens) = create_signal(Vec::<ApiToken>::new());
+    let (name, set_name) = create_signal(String::new());
+    let (scope, set_scope) = create_signal(TokenScope::Read);
+    // Empty for a token that doesn't expire
+    let (expires_in_days, set_expires_in_days) = create_signal("90".to_string());
+    // Shown once, right after the token is created
+    let (new_secret, set_new_secret) = create_signal(None::<String>);
+
+    let load_tokens = move || {
+        spawn_local(async move {
+            match crate::api::get_api_tokens().await {
+                Ok(found) => set_tokens.set(found),
+                Err(e) => tracing::error!("Failed to load API tokens: {}", e),
+            }
+        });
+    };
+
+    load_tokens();
+
+    let create_token = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        set_error.set(None);
+        let days = expires_in_days.get_untracked().trim().parse::<i64>().ok();
+        spawn_local(async move {
+            match crate::api::create_api_token(name.get_untracked(), scope.get_untracked(), days).await {
+                Ok(created) => {
+                    set_name.set(String::new());
+                    set_new_secret.set(Some(created.secret));
+                    load_tokens();
+                }
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let revoke = move |token_id: String| {
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::delete_api_token(token_id).await {
+                Ok(()) => load_tokens(),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    view! {
+        <section>
+            <h2 class="mb-3 text-lg font-medium text-gray-800">"API tokens"</h2>
+            <p class="mb-3 text-sm text-gray-500">
+                "Scripts and other apps can call the API with "
+                <code>"Authorization: Bearer <token>"</code>
+                ". Read tokens can only download; write tokens can also chat and change things."
+            </p>
+            {move || new_secret.get().map(|secret| view! {
+                <div class="p-3 mb-3 text-sm bg-green-50 rounded-lg">
+                    <div class="mb-1 text-green-800">"Copy your new token now. It won't be shown again."</div>
+                    <code class="block font-mono text-xs text-gray-900 break-all">{secret}</code>
+                </div>
+            })}
+            <form on:submit=create_token class="flex flex-wrap items-end gap-2 mb-3 text-sm">
+                <input
+                    type="text"
+                    placeholder="Token name"
+                    class="flex-1 px-2 py-1 border border-gray-300 rounded focus:outline-none focus:ring-1 focus:ring-blue-500"
+                    prop:value=name
+                    on:input=move |ev| set_name.set(event_target_value(&ev))
+                />
+                <select
+                    class="px-2 py-1 text-gray-600 bg-gray-100 rounded outline-none"
+                    on:change=move |ev| set_scope.set(TokenScope::from(event_target_value(&ev)))
+                >
+                    {TokenScope::ALL.into_iter().map(|option| view! {
+                        <option value=option.to_string() selected=move || scope.get() == option>{option.to_string()}</option>
+                    }).collect::<Vec<_>>()}
+                </select>
+                <select
+                    class="px-2 py-1 text-gray-600 bg-gray-100 rounded outline-none"
+                    on:change=move |ev| set_expires_in_days.set(event_target_value(&ev))
+                >
+                    <option value="30">"30 days"</option>
+                    <option value="90" selected=true>"90 days"</option>
+                    <option value="365">"1 year"</option>
+                    <option value="">"Never expires"</option>
+                </select>
+                <button type="submit" class="px-3 py-1 text-white bg-blue-600 rounded hover:bg-blue-700">
+                    "Create"
+                </button>
+            </form>
+            <div class="overflow-x-auto bg-white border border-gray-200 rounded-lg">
+                <table class="w-full text-sm">
+                    <thead class="text-left text-xs text-gray-500 uppercase">
+                        <tr>
+                            <th class="px-3 py-2">"Name"</th>
+                            <th class="px-3 py-2">"Scope"</th>
+                            <th class="px-3 py-2">"Last used"</th>
+                            <th class="px-3 py-2">"Expires"</th>
+                            <th class="px-3 py-2"></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {move || tokens.get().into_iter().map(|token| {
+                            let token_id = token.id.clone();
+                            view! {
+                                <tr class="border-t border-gray-100">
+                                    <td class="px-3 py-2">
+                                        <div class="text-gray-900">{token.name}</div>
+                                        <div class="font-mono text-xs text-gray-500">{format!("{}…", token.token_prefix)}</div>
+                                    </td>
+                                    <td class="px-3 py-2 text-gray-600">{token.scope.to_string()}</td>
+                                    <td class="px-3 py-2 text-gray-600">
+                                        {token.last_used_at.map(|at| at.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "Never".to_string())}
+                                    </td>
+                                    <td class="px-3 py-2 text-gray-600">
+                                        {token.expires_at.map(|at| at.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "Never".to_string())}
+                                    </td>
+                                    <td class="px-3 py-2 text-right">
+                                        <button
+                                            type="button"
+                                            on:click=move |_| revoke(token_id.clone())
+                                            class="text-xs text-red-600 hover:text-red-800"
+                                        >
+                                            "Revoke"
+                                        </button>
+                                    </td>
+                                </tr>
+                            }
+                        }).collect::<Vec<_>>()}
+                    </tbody>
+                </table>
+            </div>
+        </section>
+    }
+}
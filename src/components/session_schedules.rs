@@ -0,0 +1,224 @@
+use leptos::*;
+use crate::models::*;
+
+// How often a new schedule can repeat, in minutes
+const REPEAT_OPTIONS: [(i64, &str); 4] = [(60, "Every hour"), (6 * 60, "Every 6 hours"), (24 * 60, "Every day"), (7 * 24 * 60, "Every week")];
+const DEFAULT_INTERVAL_MINUTES: i64 = 24 * 60;
+
+fn repeat_label(interval_minutes: i64) -> String {
+    REPEAT_OPTIONS.iter()
+        .find(|(minutes, _)| *minutes == interval_minutes)
+        .map(|(_, label)| label.to_string())
+        .unwrap_or_else(|| format!("Every {} minutes", interval_minutes))
+}
+
+#[component]
+pub fn SessionSchedulesPanel(current_session: ReadSignal<Option<String>>) -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (schedules, set_schedules) = create_signal(Vec::<Schedule>::new());
+    let (prompt, set_prompt) = create_signal(String::new());
+    let (fetch_url, set_fetch_url) = create_signal(String::new());
+    let (interval_minutes, set_interval_minutes) = create_signal(DEFAULT_INTERVAL_MINUTES);
+    let (first_run_at, set_first_run_at) = create_signal(String::new());
+    let (notify, set_notify) = create_signal(false);
+    let (status, set_status) = create_signal(None::<String>);
+
+    let load_schedules = move || {
+        let Some(session_id) = current_session.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            match crate::api::list_schedules(session_id).await {
+                Ok(found) => set_schedules.set(found),
+                Err(e) => tracing::error!("Failed to load schedules: {}", e),
+            }
+        });
+    };
+
+    // Reload the schedules every time the panel is opened or the session changes
+    create_effect(move |_| {
+        current_session.track();
+        if show_panel.get() {
+            set_status.set(None);
+            load_schedules();
+        }
+    });
+
+    let handle_create = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        let Some(session_id) = current_session.get_untracked() else {
+            return;
+        };
+        // Times are entered and shown in UTC; an empty time means now
+        let first_run_at = match first_run_at.get_untracked() {
+            value if value.is_empty() => chrono::Utc::now(),
+            value => match chrono::NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M") {
+                Ok(at) => at.and_utc(),
+                Err(_) => {
+                    set_status.set(Some("Enter a valid first run time".to_string()));
+                    return;
+                }
+            },
+        };
+        let url = fetch_url.get_untracked();
+        let url = (!url.trim().is_empty()).then_some(url);
+        let (prompt_text, interval, notify) = (prompt.get_untracked(), interval_minutes.get_untracked(), notify.get_untracked());
+        spawn_local(async move {
+            match crate::api::create_schedule(session_id, prompt_text, url, interval, first_run_at, notify).await {
+                Ok(schedule) => {
+                    set_schedules.update(|schedules| {
+                        schedules.push(schedule);
+                        schedules.sort_by_key(|schedule| schedule.next_run_at);
+                    });
+                    set_prompt.set(String::new());
+                    set_fetch_url.set(String::new());
+                    set_status.set(Some("Scheduled".to_string()));
+                }
+                Err(e) => set_status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let toggle_schedule = move |schedule_id: String, enabled: bool| {
+        spawn_local(async move {
+            match crate::api::set_schedule_enabled(schedule_id, enabled).await {
+                Ok(updated) => set_schedules.update(|schedules| {
+                    if let Some(schedule) = schedules.iter_mut().find(|schedule| schedule.id == updated.id) {
+                        *schedule = updated;
+                    }
+                }),
+                Err(e) => set_status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let remove_schedule = move |schedule_id: String| {
+        spawn_local(async move {
+            match crate::api::delete_schedule(schedule_id.clone()).await {
+                Ok(()) => set_schedules.update(|schedules| schedules.retain(|schedule| schedule.id != schedule_id)),
+                Err(e) => set_status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    view! {
+        {move || current_session.get().is_some().then(|| view! {
+            <div class="relative mr-1">
+                <button
+                    type="button"
+                    on:click=move |_| set_show_panel.update(|show| *show = !*show)
+                    class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                    title="Scheduled prompts"
+                >
+                    <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 8v4l3 3m6-3a9 9 0 11-18 0 9 9 0 0118 0z"></path>
+                    </svg>
+                </button>
+
+                {move || show_panel.get().then(|| view! {
+                    <div class="absolute top-12 right-0 w-96 p-3 space-y-3 bg-white rounded-lg shadow-xl border border-gray-200 z-50 text-sm text-gray-700">
+                        <ul class="space-y-2 max-h-60 overflow-y-auto">
+                            {move || {
+                                let schedules = schedules.get();
+                                if schedules.is_empty() {
+                                    return view! { <li class="text-gray-500">"No prompts are scheduled in this chat"</li> }.into_view();
+                                }
+                                schedules.into_iter().map(|schedule| {
+                                    let (toggle_id, remove_id) = (schedule.id.clone(), schedule.id.clone());
+                                    let enabled = schedule.enabled;
+                                    view! {
+                                        <li class="p-2 border border-gray-200 rounded">
+                                            <p class="truncate font-medium" title=schedule.prompt.clone()>{schedule.prompt.clone()}</p>
+                                            <p class="text-xs text-gray-500">
+                                                {repeat_label(schedule.interval_minutes)}
+                                                {if enabled {
+                                                    format!(", next {}", schedule.next_run_at.format("%Y-%m-%d %H:%M UTC"))
+                                                } else {
+                                                    ", paused".to_string()
+                                                }}
+                                            </p>
+                                            {schedule.fetch_url.clone().map(|url| view! {
+                                                <p class="text-xs text-gray-500 truncate">"Reads " {url}</p>
+                                            })}
+                                            {schedule.last_error.clone().map(|error| view! {
+                                                <p class="text-xs text-red-600">"Last run failed: " {error}</p>
+                                            })}
+                                            <div class="flex gap-3 mt-1 text-xs">
+                                                <button
+                                                    type="button"
+                                                    class="text-indigo-600 hover:underline"
+                                                    on:click=move |_| toggle_schedule(toggle_id.clone(), !enabled)
+                                                >
+                                                    {if enabled { "Pause" } else { "Resume" }}
+                                                </button>
+                                                <button
+                                                    type="button"
+                                                    class="text-red-600 hover:underline"
+                                                    on:click=move |_| remove_schedule(remove_id.clone())
+                                                >
+                                                    "Delete"
+                                                </button>
+                                            </div>
+                                        </li>
+                                    }
+                                }).collect_view()
+                            }}
+                        </ul>
+
+                        <form on:submit=handle_create class="space-y-2 pt-2 border-t border-gray-200">
+                            <textarea
+                                rows="2"
+                                placeholder="Prompt to send, e.g. Summarize today's front page"
+                                class="w-full px-2 py-1 border border-gray-300 rounded"
+                                prop:value=move || prompt.get()
+                                on:input=move |ev| set_prompt.set(event_target_value(&ev))
+                            ></textarea>
+                            <input
+                                type="url"
+                                placeholder="Page to read each time (optional)"
+                                class="w-full px-2 py-1 border border-gray-300 rounded"
+                                prop:value=move || fetch_url.get()
+                                on:input=move |ev| set_fetch_url.set(event_target_value(&ev))
+                            />
+                            <div class="flex gap-2">
+                                <select
+                                    class="flex-1 px-2 py-1 border border-gray-300 rounded"
+                                    prop:value=move || interval_minutes.get().to_string()
+                                    on:change=move |ev| {
+                                        let minutes = event_target_value(&ev).parse().unwrap_or(DEFAULT_INTERVAL_MINUTES);
+                                        set_interval_minutes.set(minutes);
+                                    }
+                                >
+                                    {REPEAT_OPTIONS.iter().map(|(minutes, label)| view! {
+                                        <option value=minutes.to_string()>{*label}</option>
+                                    }).collect_view()}
+                                </select>
+                                <input
+                                    type="datetime-local"
+                                    title="First run (UTC); leave empty to start now"
+                                    class="flex-1 px-2 py-1 border border-gray-300 rounded"
+                                    prop:value=move || first_run_at.get()
+                                    on:input=move |ev| set_first_run_at.set(event_target_value(&ev))
+                                />
+                            </div>
+                            <label class="flex items-center gap-2">
+                                <input
+                                    type="checkbox"
+                                    prop:checked=move || notify.get()
+                                    on:change=move |ev| set_notify.set(event_target_checked(&ev))
+                                />
+                                "Email me each reply"
+                            </label>
+                            <div class="flex items-center justify-between">
+                                <span class="text-xs text-gray-500">{move || status.get()}</span>
+                                <button type="submit" class="px-3 py-1 text-white bg-indigo-600 rounded hover:bg-indigo-700">
+                                    "Schedule"
+                                </button>
+                            </div>
+                        </form>
+                    </div>
+                })}
+            </div>
+        })}
+    }
+}
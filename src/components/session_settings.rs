@@ -0,0 +1,196 @@
+use leptos::*;
+use crate::models::*;
+
+// Default sizes offered when switching to a limited context window
+const DEFAULT_RECENT_MESSAGES: usize = 20;
+const DEFAULT_TOKEN_BUDGET: usize = 4000;
+
+#[component]
+pub fn SessionSettingsPanel(current_session: ReadSignal<Option<String>>) -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (settings, set_settings) = create_signal(SessionSettings::default());
+    let (status, set_status) = create_signal(None::<String>);
+
+    // Reload the settings every time the panel is opened or the session changes
+    create_effect(move |_| {
+        let Some(session_id) = current_session.get() else {
+            return;
+        };
+        if !show_panel.get() {
+            return;
+        }
+        set_status.set(None);
+        spawn_local(async move {
+            match crate::api::get_session_settings(session_id).await {
+                Ok(found) => set_settings.set(found),
+                Err(e) => tracing::error!("Failed to load session settings: {}", e),
+            }
+        });
+    });
+
+    let handle_save = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        let Some(session_id) = current_session.get_untracked() else {
+            return;
+        };
+        let updated = settings.get_untracked();
+        spawn_local(async move {
+            match crate::api::update_session_settings(session_id, updated).await {
+                Ok(()) => set_status.set(Some("Saved".to_string())),
+                Err(e) => set_status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let strategy_name = move || match settings.get().context_strategy {
+        ContextStrategy::Full => "full",
+        ContextStrategy::RecentMessages { .. } => "recent",
+        ContextStrategy::TokenBudget { .. } => "tokens",
+    };
+
+    view! {
+        {move || current_session.get().is_some().then(|| view! {
+            <div class="relative mr-3">
+                <button
+                    type="button"
+                    on:click=move |_| set_show_panel.update(|show| *show = !*show)
+                    class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                    title="Session settings"
+                >
+                    <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 6V4m0 2a2 2 0 100 4m0-4a2 2 0 110 4m-6 8a2 2 0 100-4m0 4a2 2 0 110-4m0 4v2m0-6V4m6 6v10m6-2a2 2 0 100-4m0 4a2 2 0 110-4m0 4v2m0-6V4"></path>
+                    </svg>
+                </button>
+
+                {move || show_panel.get().then(|| view! {
+                    <form
+                        on:submit=handle_save
+                        class="absolute top-12 right-0 w-80 p-3 space-y-3 bg-white rounded-lg shadow-xl border border-gray-200 z-50 text-sm text-gray-700"
+                    >
+                        <label class="block">
+                            <span class="block mb-1 font-medium">"Instructions"</span>
+                            <textarea
+                                rows="3"
+                                placeholder="Default assistant instructions"
+                                class="w-full px-2 py-1 border border-gray-300 rounded"
+                                prop:value=move || settings.get().system_prompt.unwrap_or_default()
+                                on:input=move |ev| {
+                                    let prompt = event_target_value(&ev);
+                                    set_settings.update(|s| s.system_prompt = (!prompt.trim().is_empty()).then_some(prompt));
+                                }
+                            ></textarea>
+                        </label>
+
+                        <label class="block">
+                            <span class="block mb-1 font-medium">
+                                {move || match settings.get().temperature {
+                                    Some(temperature) => format!("Temperature: {:.1}", temperature),
+                                    None => "Temperature: provider default".to_string(),
+                                }}
+                            </span>
+                            <input
+                                type="range"
+                                min="0"
+                                max=MAX_TEMPERATURE.to_string()
+                                step="0.1"
+                                class="w-full"
+                                prop:value=move || settings.get().temperature.unwrap_or(1.0).to_string()
+                                on:input=move |ev| {
+                                    let temperature = event_target_value(&ev).parse().ok();
+                                    set_settings.update(|s| s.temperature = temperature);
+                                }
+                            />
+                            <button
+                                type="button"
+                                class="text-xs text-indigo-600 hover:underline"
+                                on:click=move |_| set_settings.update(|s| s.temperature = None)
+                            >
+                                "Use provider default"
+                            </button>
+                        </label>
+
+                        <label class="block">
+                            <span class="block mb-1 font-medium">"Context"</span>
+                            <select
+                                class="w-full px-2 py-1 border border-gray-300 rounded"
+                                prop:value=strategy_name
+                                on:change=move |ev| {
+                                    let strategy = match event_target_value(&ev).as_str() {
+                                        "recent" => ContextStrategy::RecentMessages { count: DEFAULT_RECENT_MESSAGES },
+                                        "tokens" => ContextStrategy::TokenBudget { tokens: DEFAULT_TOKEN_BUDGET },
+                                        _ => ContextStrategy::Full,
+                                    };
+                                    set_settings.update(|s| s.context_strategy = strategy);
+                                }
+                            >
+                                <option value="full">"Whole conversation"</option>
+                                <option value="recent">"Recent messages"</option>
+                                <option value="tokens">"Token budget"</option>
+                            </select>
+                            {move || match settings.get().context_strategy {
+                                ContextStrategy::Full => None,
+                                ContextStrategy::RecentMessages { count } => Some(view! {
+                                    <input
+                                        type="number"
+                                        min="1"
+                                        class="w-full mt-1 px-2 py-1 border border-gray-300 rounded"
+                                        title="Messages to keep"
+                                        prop:value=count.to_string()
+                                        on:change=move |ev| {
+                                            let count = event_target_value(&ev).parse().unwrap_or(DEFAULT_RECENT_MESSAGES);
+                                            set_settings.update(|s| s.context_strategy = ContextStrategy::RecentMessages { count });
+                                        }
+                                    />
+                                }),
+                                ContextStrategy::TokenBudget { tokens } => Some(view! {
+                                    <input
+                                        type="number"
+                                        min="1"
+                                        step="500"
+                                        class="w-full mt-1 px-2 py-1 border border-gray-300 rounded"
+                                        title="Approximate tokens to keep"
+                                        prop:value=tokens.to_string()
+                                        on:change=move |ev| {
+                                            let tokens = event_target_value(&ev).parse().unwrap_or(DEFAULT_TOKEN_BUDGET);
+                                            set_settings.update(|s| s.context_strategy = ContextStrategy::TokenBudget { tokens });
+                                        }
+                                    />
+                                }),
+                            }}
+                        </label>
+
+                        <label class="flex items-center gap-2">
+                            <input
+                                type="checkbox"
+                                prop:checked=move || settings.get().memory_enabled
+                                on:change=move |ev| {
+                                    let enabled = event_target_checked(&ev);
+                                    set_settings.update(|s| s.memory_enabled = enabled);
+                                }
+                            />
+                            "Use memory"
+                        </label>
+                        <label class="flex items-center gap-2">
+                            <input
+                                type="checkbox"
+                                prop:checked=move || settings.get().knowledge_enabled
+                                on:change=move |ev| {
+                                    let enabled = event_target_checked(&ev);
+                                    set_settings.update(|s| s.knowledge_enabled = enabled);
+                                }
+                            />
+                            "Search linked knowledge bases"
+                        </label>
+
+                        <div class="flex items-center justify-between">
+                            <span class="text-xs text-gray-500">{move || status.get()}</span>
+                            <button type="submit" class="px-3 py-1 text-white bg-indigo-600 rounded hover:bg-indigo-700">
+                                "Save"
+                            </button>
+                        </div>
+                    </form>
+                })}
+            </div>
+        })}
+    }
+}
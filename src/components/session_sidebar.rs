@@ -0,0 +1,368 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn SessionSidebar(
+    current_session: ReadSignal<Option<String>>,
+    // Bumped by the chat box whenever session titles may have changed
+    refresh: ReadSignal<u32>,
+    on_select: Callback<String>,
+) -> impl IntoView {
+    let (sessions, set_sessions) = create_signal(Vec::<ChatSession>::new());
+    let (folders, set_folders) = create_signal(Vec::<SessionFolder>::new());
+    let (editing, set_editing) = create_signal(None::<String>);
+    let (draft_title, set_draft_title) = create_signal(String::new());
+    let (new_folder_name, set_new_folder_name) = create_signal(String::new());
+    let (tags, set_tags) = create_signal(Vec::<Tag>::new());
+    let (tag_filter, set_tag_filter) = create_signal(None::<String>);
+    let (more_cursor, set_more_cursor) = create_signal(None::<SessionCursor>);
+    let (import_report, set_import_report) = create_signal(None::<ImportReport>);
+    let import_input_ref = create_node_ref::<html::Input>();
+
+    // Reloads from the first page; older pages are fetched again on demand
+    let load_sessions = move || {
+        spawn_local(async move {
+            match crate::api::get_user_sessions(false, tag_filter.get_untracked(), None, None).await {
+                Ok(page) => {
+                    set_sessions.set(page.items);
+                    set_more_cursor.set(page.next_cursor);
+                }
+                Err(e) => tracing::error!("Failed to load sessions: {}", e),
+            }
+            match crate::api::list_folders().await {
+                Ok(found) => set_folders.set(found),
+                Err(e) => tracing::error!("Failed to load folders: {}", e),
+            }
+            match crate::api::list_tags().await {
+                Ok(found) => set_tags.set(found),
+                Err(e) => tracing::error!("Failed to load tags: {}", e),
+            }
+        });
+    };
+
+    create_effect(move |_| {
+        let _ = current_session.get();
+        let _ = refresh.get();
+        let _ = tag_filter.get();
+        load_sessions();
+    });
+
+    let load_more_sessions = move |_| {
+        let Some(cursor) = more_cursor.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            match crate::api::get_user_sessions(false, tag_filter.get_untracked(), Some(cursor), None).await {
+                Ok(page) => {
+                    set_sessions.update(|loaded| loaded.extend(page.items));
+                    set_more_cursor.set(page.next_cursor);
+                }
+                Err(e) => tracing::error!("Failed to load more sessions: {}", e),
+            }
+        });
+    };
+
+    let commit_rename = move |session_id: String| {
+        // Enter and the blur that follows it both land here; only the first one saves
+        if editing.get_untracked().as_deref() != Some(session_id.as_str()) {
+            return;
+        }
+        set_editing.set(None);
+        let title = draft_title.get_untracked();
+        spawn_local(async move {
+            if let Err(e) = crate::api::rename_session(session_id, title).await {
+                tracing::error!("Failed to rename session: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let toggle_pin = move |session_id: String, pinned: bool| {
+        spawn_local(async move {
+            if let Err(e) = crate::api::pin_session(session_id, pinned).await {
+                tracing::error!("Failed to pin session: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let move_to_folder = move |session_id: String, folder_id: Option<String>| {
+        spawn_local(async move {
+            if let Err(e) = crate::api::move_session_to_folder(session_id, folder_id).await {
+                tracing::error!("Failed to move session: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let handle_create_folder = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        let name = new_folder_name.get();
+        if name.trim().is_empty() {
+            return;
+        }
+        set_new_folder_name.set(String::new());
+        spawn_local(async move {
+            if let Err(e) = crate::api::create_folder(name).await {
+                tracing::error!("Failed to create folder: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let delete_folder = move |folder_id: String| {
+        spawn_local(async move {
+            if let Err(e) = crate::api::delete_folder(folder_id).await {
+                tracing::error!("Failed to delete folder: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let delete_session = move |session_id: String| {
+        spawn_local(async move {
+            if let Err(e) = crate::api::delete_session(session_id).await {
+                tracing::error!("Failed to delete session: {}", e);
+            }
+            load_sessions();
+        });
+    };
+
+    let handle_import = move |_| {
+        let Some(file) = import_input_ref.get().and_then(|input| input.files()).and_then(|files| files.get(0)) else {
+            return;
+        };
+        spawn_local(async move {
+            let Ok(array_buffer) = file.array_buffer().await else {
+                return;
+            };
+            let data = js_sys::Uint8Array::new(&array_buffer).to_vec();
+            match crate::api::import_conversations(data).await {
+                Ok(report) => set_import_report.set(Some(report)),
+                Err(e) => tracing::error!("Failed to import conversations: {}", e),
+            }
+            load_sessions();
+        });
+    };
+
+    let session_row = move |session: ChatSession| {
+        let id = session.id.clone();
+        let select_id = session.id.clone();
+        let edit_id = session.id.clone();
+        let blur_id = session.id.clone();
+        let key_id = session.id.clone();
+        let pin_id = session.id.clone();
+        let move_id = session.id.clone();
+        let delete_id = session.id.clone();
+        let title = session.title.clone().unwrap_or_else(|| "New chat".to_string());
+        let edit_title = session.title.clone().unwrap_or_default();
+        let preview = session.last_message_preview.clone();
+        let is_current = move || current_session.get().as_deref() == Some(id.as_str());
+        let is_editing = editing.get().as_deref() == Some(session.id.as_str());
+        let pinned = session.pinned;
+        let folder_id = session.folder_id.clone().unwrap_or_default();
+
+        if is_editing {
+            let input_ref = create_node_ref::<html::Input>();
+            input_ref.on_load(|input| {
+                let _ = input.focus();
+            });
+
+            view! {
+                <input
+                    node_ref=input_ref
+                    type="text"
+                    class="w-full px-2 py-1 text-sm text-gray-800 border border-blue-400 rounded outline-none"
+                    prop:value=move || draft_title.get()
+                    on:input=move |ev| set_draft_title.set(event_target_value(&ev))
+                    on:blur=move |_| commit_rename(blur_id.clone())
+                    on:keydown=move |ev: web_sys::KeyboardEvent| {
+                        match ev.key().as_str() {
+                            "Enter" => commit_rename(key_id.clone()),
+                            "Escape" => set_editing.set(None),
+                            _ => {}
+                        }
+                    }
+                />
+            }.into_view()
+        } else {
+            view! {
+                <div class=move || {
+                    if is_current() {
+                        "group flex items-center rounded bg-blue-50"
+                    } else {
+                        "group flex items-center rounded hover:bg-gray-100"
+                    }
+                }>
+                    <button
+                        type="button"
+                        on:click=move |_| on_select.call(select_id.clone())
+                        on:dblclick=move |_| {
+                            set_draft_title.set(edit_title.clone());
+                            set_editing.set(Some(edit_id.clone()));
+                        }
+                        class="flex-1 min-w-0 text-left px-2 py-1"
+                        title="Double-click to rename"
+                    >
+                        <div class="text-sm text-gray-800 truncate">{title}</div>
+                        {preview.map(|preview| view! {
+                            <div class="text-xs text-gray-500 truncate">{preview}</div>
+                        })}
+                    </button>
+
+                    // Move to folder; "" is the top level
+                    <select
+                        class="hidden group-hover:block w-5 text-xs text-gray-500 bg-transparent outline-none"
+                        title="Move to folder"
+                        on:change=move |ev| {
+                            let value = event_target_value(&ev);
+                            move_to_folder(move_id.clone(), (!value.is_empty()).then_some(value));
+                        }
+                    >
+                        <option value="" selected=folder_id.is_empty()>"No folder"</option>
+                        {folders.get_untracked().into_iter().map(|folder| {
+                            let selected = folder.id == folder_id;
+                            view! { <option value=folder.id selected=selected>{folder.name}</option> }
+                        }).collect::<Vec<_>>()}
+                    </select>
+
+                    <button
+                        type="button"
+                        on:click=move |_| toggle_pin(pin_id.clone(), !pinned)
+                        class=if pinned {
+                            "px-1 text-xs text-blue-600"
+                        } else {
+                            "hidden group-hover:block px-1 text-xs text-gray-400 hover:text-gray-600"
+                        }
+                        title=if pinned { "Unpin" } else { "Pin" }
+                    >
+                        "📌"
+                    </button>
+
+                    <button
+                        type="button"
+                        on:click=move |_| delete_session(delete_id.clone())
+                        class="hidden group-hover:block px-1 text-xs text-gray-400 hover:text-red-600"
+                        title="Move to trash"
+                    >
+                        "×"
+                    </button>
+                </div>
+            }.into_view()
+        }
+    };
+
+    view! {
+        <div class="fixed left-0 top-0 h-full w-64 bg-white shadow-lg p-3 overflow-y-auto hidden lg:block">
+            <div class="flex items-center justify-between mb-2">
+                <span class="text-xs font-medium text-gray-500">"Chats"</span>
+                {move || {
+                    let available = tags.get();
+                    (!available.is_empty()).then(|| view! {
+                        <select
+                            class="text-xs text-gray-600 bg-transparent outline-none"
+                            title="Filter by tag"
+                            on:change=move |ev| {
+                                let value = event_target_value(&ev);
+                                set_tag_filter.set((!value.is_empty()).then_some(value));
+                            }
+                        >
+                            <option value="" selected=tag_filter.get_untracked().is_none()>"All tags"</option>
+                            {available.into_iter().map(|tag| {
+                                let selected = tag_filter.get_untracked().as_deref() == Some(tag.id.as_str());
+                                view! { <option value=tag.id selected=selected>{format!("#{}", tag.name)}</option> }
+                            }).collect::<Vec<_>>()}
+                        </select>
+                    })
+                }}
+            </div>
+            <div class="space-y-1">
+                {move || sessions.get().into_iter()
+                    .filter(|session| session.folder_id.is_none())
+                    .map(session_row)
+                    .collect::<Vec<_>>()}
+            </div>
+
+            {move || folders.get().into_iter().map(|folder| {
+                let folder_id = folder.id.clone();
+                let delete_id = folder.id.clone();
+
+                view! {
+                    <div class="mt-4">
+                        <div class="group flex items-center justify-between text-xs font-medium text-gray-500 mb-1">
+                            <span class="truncate">{folder.name}</span>
+                            <button
+                                type="button"
+                                on:click=move |_| delete_folder(delete_id.clone())
+                                class="hidden group-hover:block text-gray-400 hover:text-red-600"
+                                title="Delete folder (its chats are kept)"
+                            >
+                                "×"
+                            </button>
+                        </div>
+                        <div class="space-y-1">
+                            {move || sessions.get().into_iter()
+                                .filter(|session| session.folder_id.as_deref() == Some(folder_id.as_str()))
+                                .map(session_row)
+                                .collect::<Vec<_>>()}
+                        </div>
+                    </div>
+                }
+            }).collect::<Vec<_>>()}
+
+            {move || more_cursor.get().is_some().then(|| view! {
+                <button
+                    type="button"
+                    on:click=load_more_sessions
+                    class="w-full mt-2 px-2 py-1 text-xs text-gray-500 hover:text-gray-700"
+                >
+                    "Show more"
+                </button>
+            })}
+
+            <form on:submit=handle_create_folder class="mt-4">
+                <input
+                    type="text"
+                    placeholder="New folder..."
+                    class="w-full px-2 py-1 text-sm text-gray-700 border border-gray-200 rounded outline-none focus:border-blue-400"
+                    prop:value=move || new_folder_name.get()
+                    on:input=move |ev| set_new_folder_name.set(event_target_value(&ev))
+                />
+            </form>
+
+            // ChatGPT or Claude data export: the zip or its conversations.json
+            <input
+                ref=import_input_ref
+                type="file"
+                accept=".zip,.json,application/zip,application/json"
+                class="hidden"
+                on:change=handle_import
+            />
+            <button
+                type="button"
+                on:click=move |_| {
+                    if let Some(input) = import_input_ref.get() {
+                        input.click();
+                    }
+                }
+                class="w-full mt-2 px-2 py-1 text-xs text-gray-500 hover:text-gray-700 text-left"
+                title="Import a ChatGPT or Claude data export"
+            >
+                "Import chats..."
+            </button>
+            {move || import_report.get().map(|report| view! {
+                <div class="mt-1 px-2 text-xs text-gray-500">
+                    <div>{format!("Imported {} chats ({} messages)", report.sessions_imported, report.messages_imported)}</div>
+                    {(!report.skipped.is_empty()).then(|| view! {
+                        <details>
+                            <summary class="cursor-pointer">{format!("{} skipped", report.skipped.len())}</summary>
+                            <ul class="mt-1 space-y-1">
+                                {report.skipped.into_iter().map(|line| view! { <li>{line}</li> }).collect::<Vec<_>>()}
+                            </ul>
+                        </details>
+                    })}
+                </div>
+            })}
+        </div>
+    }
+}
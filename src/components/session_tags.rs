@@ -0,0 +1,86 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn SessionTags(
+    current_session: ReadSignal<Option<String>>,
+    // Called after tags change so the session list can refresh its tag filter
+    on_change: Callback<()>,
+) -> impl IntoView {
+    let (tags, set_tags) = create_signal(Vec::<Tag>::new());
+    let (new_tag, set_new_tag) = create_signal(String::new());
+
+    let load_tags = move || {
+        if let Some(session_id) = current_session.get_untracked() {
+            spawn_local(async move {
+                match crate::api::get_session_tags(session_id).await {
+                    Ok(found) => set_tags.set(found),
+                    Err(e) => tracing::error!("Failed to load session tags: {}", e),
+                }
+            });
+        }
+    };
+
+    create_effect(move |_| {
+        let _ = current_session.get();
+        load_tags();
+    });
+
+    let handle_add = move |ev: web_sys::SubmitEvent| {
+        ev.prevent_default();
+        let tag_name = new_tag.get();
+        let Some(session_id) = current_session.get_untracked() else {
+            return;
+        };
+        if tag_name.trim().is_empty() {
+            return;
+        }
+        set_new_tag.set(String::new());
+        spawn_local(async move {
+            if let Err(e) = crate::api::add_session_tag(session_id, tag_name).await {
+                tracing::error!("Failed to tag session: {}", e);
+            }
+            load_tags();
+            on_change.call(());
+        });
+    };
+
+    view! {
+        <div class="flex flex-wrap items-center gap-1 mt-2">
+            {move || tags.get().into_iter().map(|tag| {
+                let tag_id = tag.id.clone();
+                let remove = move |_| {
+                    let Some(session_id) = current_session.get_untracked() else {
+                        return;
+                    };
+                    let tag_id = tag_id.clone();
+                    spawn_local(async move {
+                        if let Err(e) = crate::api::remove_session_tag(session_id, tag_id).await {
+                            tracing::error!("Failed to remove tag: {}", e);
+                        }
+                        load_tags();
+                        on_change.call(());
+                    });
+                };
+
+                view! {
+                    <span class="inline-flex items-center px-2 py-0.5 text-xs rounded-full bg-indigo-100 text-indigo-700">
+                        {format!("#{}", tag.name)}
+                        <button type="button" on:click=remove class="ml-1 text-indigo-400 hover:text-indigo-700" title="Remove tag">
+                            "×"
+                        </button>
+                    </span>
+                }
+            }).collect::<Vec<_>>()}
+            <form on:submit=handle_add>
+                <input
+                    type="text"
+                    placeholder="+ tag"
+                    class="w-20 px-2 py-0.5 text-xs text-gray-700 bg-transparent border-none outline-none"
+                    prop:value=move || new_tag.get()
+                    on:input=move |ev| set_new_tag.set(event_target_value(&ev))
+                />
+            </form>
+        </div>
+    }
+}
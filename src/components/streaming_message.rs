@@ -0,0 +1,48 @@
+use leptos::*;
+use crate::models::*;
+use crate::components::markdown::render_markdown;
+
+// Where the finished blocks of a reply being written end: after the last blank line that
+// isn't inside a code fence. Everything before it won't change as more text arrives.
+fn settled_len(content: &str) -> usize {
+    let mut in_fence = false;
+    let mut settled = 0;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if trimmed.is_empty() && !in_fence && line.ends_with('\n') {
+            settled = offset;
+        }
+    }
+    settled
+}
+
+// A reply as it is streamed in. The finished blocks are rendered once, and only the block
+// still being written is rendered again as each piece of text arrives.
+#[component]
+pub fn StreamingMessage(message: ReadSignal<Option<Message>>) -> impl IntoView {
+    let content = move || message.with(|message| message.as_ref().map(|m| m.content.clone()).unwrap_or_default());
+    let settled = create_memo(move |_| {
+        let content = content();
+        content[..settled_len(&content)].to_string()
+    });
+    let pending = move || {
+        let content = content();
+        content[settled_len(&content)..].to_string()
+    };
+
+    view! {
+        <div class="flex justify-start">
+            <div class="max-w-3xl rounded-lg p-4 bg-gray-100 text-gray-800">
+                <div class="prose prose-sm max-w-none">
+                    {move || settled.with(|settled| render_markdown(settled, true))}
+                    {move || render_markdown(&pending(), true)}
+                    <span class="inline-block w-2 h-4 align-text-bottom bg-gray-400 animate-pulse"></span>
+                </div>
+            </div>
+        </div>
+    }
+}
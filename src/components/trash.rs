@@ -0,0 +1,167 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn TrashPanel(
+    // Called after anything is restored so the session list and messages can reload
+    on_restore: Callback<()>,
+) -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (trash, set_trash) = create_signal(Trash::default());
+
+    let load_trash = move || {
+        spawn_local(async move {
+            match crate::api::get_trash().await {
+                Ok(found) => set_trash.set(found),
+                Err(e) => tracing::error!("Failed to load trash: {}", e),
+            }
+        });
+    };
+
+    // Reload the trash every time the panel is opened
+    create_effect(move |_| {
+        if show_panel.get() {
+            load_trash();
+        }
+    });
+
+    let toggle_panel = move |_| {
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    view! {
+        <div class="relative mr-3">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="Trash"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16"></path>
+                </svg>
+            </button>
+
+            {move || {
+                if show_panel.get() {
+                    view! {
+                        <div class="absolute top-12 right-0 w-96 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                            <div class="p-2 space-y-1 max-h-80 overflow-y-auto">
+                                {move || {
+                                    let Trash { sessions, messages } = trash.get();
+                                    if sessions.is_empty() && messages.is_empty() {
+                                        return view! {
+                                            <div class="px-2 py-1 text-sm text-gray-500">"Trash is empty"</div>
+                                        }.into_view();
+                                    }
+
+                                    let session_rows = sessions.into_iter().map(|session| {
+                                        let restore_id = session.id.clone();
+                                        let purge_id = session.id.clone();
+                                        let title = session.title.unwrap_or_else(|| "Untitled chat".to_string());
+
+                                        view! {
+                                            <TrashRow
+                                                label=title
+                                                deleted_at=session.deleted_at
+                                                on_restore=move |_| {
+                                                    let session_id = restore_id.clone();
+                                                    spawn_local(async move {
+                                                        if let Err(e) = crate::api::restore_session(session_id).await {
+                                                            tracing::error!("Failed to restore session: {}", e);
+                                                        }
+                                                        load_trash();
+                                                        on_restore.call(());
+                                                    });
+                                                }
+                                                on_purge=move |_| {
+                                                    let session_id = purge_id.clone();
+                                                    spawn_local(async move {
+                                                        if let Err(e) = crate::api::purge_session(session_id).await {
+                                                            tracing::error!("Failed to purge session: {}", e);
+                                                        }
+                                                        load_trash();
+                                                    });
+                                                }
+                                            />
+                                        }
+                                    }).collect::<Vec<_>>();
+
+                                    let message_rows = messages.into_iter().map(|message| {
+                                        let restore_id = message.id.clone();
+                                        let purge_id = message.id.clone();
+                                        let snippet: String = message.content.chars().take(80).collect();
+
+                                        view! {
+                                            <TrashRow
+                                                label=format!("Message: {}", snippet)
+                                                deleted_at=message.deleted_at
+                                                on_restore=move |_| {
+                                                    let message_id = restore_id.clone();
+                                                    spawn_local(async move {
+                                                        if let Err(e) = crate::api::restore_message(message_id).await {
+                                                            tracing::error!("Failed to restore message: {}", e);
+                                                        }
+                                                        load_trash();
+                                                        on_restore.call(());
+                                                    });
+                                                }
+                                                on_purge=move |_| {
+                                                    let message_id = purge_id.clone();
+                                                    spawn_local(async move {
+                                                        if let Err(e) = crate::api::purge_message(message_id).await {
+                                                            tracing::error!("Failed to purge message: {}", e);
+                                                        }
+                                                        load_trash();
+                                                    });
+                                                }
+                                            />
+                                        }
+                                    }).collect::<Vec<_>>();
+
+                                    view! { {session_rows} {message_rows} }.into_view()
+                                }}
+                            </div>
+                        </div>
+                    }
+                } else {
+                    view! { <div></div> }
+                }
+            }}
+        </div>
+    }
+}
+
+#[component]
+fn TrashRow(
+    label: String,
+    deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    on_restore: Callback<()>,
+    on_purge: Callback<()>,
+) -> impl IntoView {
+    view! {
+        <div class="flex items-center px-2 py-1 rounded hover:bg-gray-100">
+            <div class="flex-1 min-w-0">
+                <div class="text-sm text-gray-800 truncate">{label}</div>
+                <div class="text-xs text-gray-500">
+                    {deleted_at.map(|at| format!("Deleted {}", at.format("%Y-%m-%d")))}
+                </div>
+            </div>
+            <button
+                type="button"
+                on:click=move |_| on_restore.call(())
+                class="ml-2 text-xs text-blue-600 hover:text-blue-800"
+            >
+                "Restore"
+            </button>
+            <button
+                type="button"
+                on:click=move |_| on_purge.call(())
+                class="ml-2 text-xs text-red-600 hover:text-red-800"
+                title="Delete permanently"
+            >
+                "Delete"
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,192 @@
+use leptos::*;
+use crate::models::*;
+
+#[component]
+pub fn TwoFactorSettings() -> impl IntoView {
+    let (show_panel, set_show_panel) = create_signal(false);
+    let (status, set_status) = create_signal(None::<TwoFactorStatus>);
+    // Set while the user is adding the secret to their app
+    let (setup, set_setup) = create_signal(None::<TwoFactorSetup>);
+    // Shown once, right after they are generated
+    let (backup_codes, set_backup_codes) = create_signal(Vec::<String>::new());
+    let (code, set_code) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+
+    let load_status = move || {
+        spawn_local(async move {
+            match crate::api::get_two_factor_status().await {
+                Ok(found) => set_status.set(Some(found)),
+                Err(e) => tracing::error!("Failed to load two-factor status: {}", e),
+            }
+        });
+    };
+
+    // Reload the status every time the panel is opened
+    create_effect(move |_| {
+        if show_panel.get() {
+            set_error.set(None);
+            load_status();
+        }
+    });
+
+    let toggle_panel = move |_| {
+        set_show_panel.update(|show| *show = !*show);
+    };
+
+    let start_setup = move |_| {
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::start_two_factor_setup().await {
+                Ok(found) => set_setup.set(Some(found)),
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    // Turns two-factor on, or replaces the backup codes, and shows the new codes
+    let submit_for_codes = move |enabling: bool| {
+        let entered = code.get_untracked();
+        set_error.set(None);
+        spawn_local(async move {
+            let result = if enabling {
+                crate::api::confirm_two_factor_setup(entered).await
+            } else {
+                crate::api::regenerate_backup_codes(entered).await
+            };
+            match result {
+                Ok(codes) => {
+                    set_setup.set(None);
+                    set_code.set(String::new());
+                    set_backup_codes.set(codes);
+                    load_status();
+                }
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let disable = move |_| {
+        let entered = code.get_untracked();
+        set_error.set(None);
+        spawn_local(async move {
+            match crate::api::disable_two_factor(entered).await {
+                Ok(()) => {
+                    set_code.set(String::new());
+                    set_backup_codes.set(Vec::new());
+                    load_status();
+                }
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let code_input = move || view! {
+        <input
+            type="text"
+            inputmode="numeric"
+            autocomplete="one-time-code"
+            placeholder="Code from your app"
+            class="w-full px-2 py-1 text-sm border border-gray-300 rounded focus:outline-none focus:ring-1 focus:ring-blue-500"
+            prop:value=code
+            on:input=move |ev| set_code.set(event_target_value(&ev))
+        />
+    };
+
+    view! {
+        <div class="relative mr-3">
+            <button
+                type="button"
+                on:click=toggle_panel
+                class="p-2 text-gray-500 hover:text-gray-700 transition-colors"
+                title="Two-factor authentication"
+            >
+                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 12l2 2 4-4m5.618-4.016A11.955 11.955 0 0112 2.944a11.955 11.955 0 01-8.618 3.04A12.02 12.02 0 003 9c0 5.591 3.824 10.29 9 11.622 5.176-1.332 9-6.03 9-11.622 0-1.042-.133-2.052-.382-3.016z"></path>
+                </svg>
+            </button>
+
+            {move || show_panel.get().then(|| view! {
+                <div class="absolute top-12 right-0 w-80 bg-white rounded-lg shadow-xl border border-gray-200 z-50">
+                    <div class="p-3 space-y-3 text-sm">
+                        {move || status.get().map(|status| {
+                            if status.enabled {
+                                view! {
+                                    <div class="space-y-2">
+                                        <div class="text-gray-800">"Two-factor authentication is on."</div>
+                                        <div class="text-xs text-gray-500">{format!("{} backup codes left", status.backup_codes_left)}</div>
+                                        {code_input}
+                                        <div class="flex justify-between">
+                                            <button type="button" on:click=move |_| submit_for_codes(false) class="text-xs text-indigo-600 hover:text-indigo-800">
+                                                "New backup codes"
+                                            </button>
+                                            <button type="button" on:click=disable class="text-xs text-red-600 hover:text-red-800">
+                                                "Turn off"
+                                            </button>
+                                        </div>
+                                    </div>
+                                }.into_view()
+                            } else if let Some(setup) = setup.get() {
+                                view! {
+                                    <div class="space-y-2">
+                                        <div class="text-xs text-gray-500">
+                                            "Scan this with your authenticator app, then enter the code it shows."
+                                        </div>
+                                        <img
+                                            src=format!("data:image/png;base64,{}", setup.qr_code_png)
+                                            alt="QR code for your authenticator app"
+                                            class="w-40 h-40 mx-auto"
+                                        />
+                                        <div class="text-xs text-gray-500 break-all" title=setup.otpauth_url>
+                                            {format!("Or enter this key: {}", setup.secret)}
+                                        </div>
+                                        {code_input}
+                                        <button
+                                            type="button"
+                                            on:click=move |_| submit_for_codes(true)
+                                            class="w-full py-1 text-white bg-blue-600 rounded hover:bg-blue-700"
+                                        >
+                                            "Turn on"
+                                        </button>
+                                    </div>
+                                }.into_view()
+                            } else {
+                                view! {
+                                    <div class="space-y-2">
+                                        {status.required.then(|| view! {
+                                            <div class="text-xs text-amber-700">
+                                                "Admin features stay off until you turn on two-factor authentication."
+                                            </div>
+                                        })}
+                                        <div class="text-xs text-gray-500">
+                                            "Ask for a code from an authenticator app as well as your password when signing in."
+                                        </div>
+                                        <button
+                                            type="button"
+                                            on:click=start_setup
+                                            class="w-full py-1 text-white bg-blue-600 rounded hover:bg-blue-700"
+                                        >
+                                            "Set up"
+                                        </button>
+                                    </div>
+                                }.into_view()
+                            }
+                        })}
+                        {move || (!backup_codes.get().is_empty()).then(|| view! {
+                            <div class="p-2 bg-gray-50 rounded">
+                                <div class="mb-1 text-xs text-gray-600">
+                                    "Backup codes. Each works once if you lose your app; they won't be shown again."
+                                </div>
+                                <div class="grid grid-cols-2 gap-1 font-mono text-xs text-gray-900">
+                                    {backup_codes.get().into_iter().map(|code| view! { <span>{code}</span> }).collect::<Vec<_>>()}
+                                </div>
+                            </div>
+                        })}
+                        {move || error.get().map(|message| view! {
+                            <div class="text-xs text-red-600">{message}</div>
+                        })}
+                    </div>
+                </div>
+            })}
+        </div>
+    }
+}
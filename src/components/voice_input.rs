@@ -1,33 +1,90 @@
 use leptos::*;
-use wasm_bindgen::JsCast;
-use web_sys::{MediaRecorder, MediaRecorderOptions, Blob};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobEvent, MediaRecorder, MediaRecorderOptions, MediaStream, MediaStreamConstraints, MediaStreamTrack};
+use crate::models::*;
 
 #[component]
-pub fn VoiceInput() -> impl IntoView {
+pub fn VoiceInput(on_record: Callback<FileUpload>) -> impl IntoView {
     let (is_recording, set_is_recording) = create_signal(false);
-    let (transcript, set_transcript) = create_signal(String::new());
+    let recorder = store_value(None::<MediaRecorder>);
+    let chunks = store_value(Vec::<Blob>::new());
 
-    let start_recording = move |_| {
-        set_is_recording.set(true);
-        set_transcript.set(String::new());
-        
+    let start_recording = move || {
         spawn_local(async move {
-            if let Some(window) = web_sys::window() {
-                if let Some(navigator) = window.navigator().media_devices() {
-                    if let Ok(stream) = navigator.get_user_media_with_constraints(&js_sys::Object::new()).await {
-                        // For now, we'll just show a placeholder
-                        // In a real implementation, you'd use the MediaRecorder API
-                        set_transcript.set("Voice recording started...".to_string());
-                    }
+            let Some(window) = web_sys::window() else { return };
+            let Ok(media_devices) = window.navigator().media_devices() else { return };
+
+            let constraints = MediaStreamConstraints::new();
+            constraints.set_audio(&JsValue::TRUE);
+            let Ok(promise) = media_devices.get_user_media_with_constraints(&constraints) else { return };
+            let stream: MediaStream = match JsFuture::from(promise).await {
+                Ok(stream) => stream.unchecked_into(),
+                Err(e) => {
+                    tracing::error!("Microphone access denied: {:?}", e);
+                    return;
+                }
+            };
+
+            let options = MediaRecorderOptions::new();
+            options.set_mime_type("audio/webm");
+            let Ok(media_recorder) = MediaRecorder::new_with_media_stream_and_media_recorder_options(&stream, &options) else {
+                tracing::error!("Failed to create MediaRecorder");
+                return;
+            };
+
+            let on_data = Closure::<dyn FnMut(BlobEvent)>::new(move |ev: BlobEvent| {
+                if let Some(blob) = ev.data() {
+                    chunks.update_value(|chunks| chunks.push(blob));
+                }
+            });
+            media_recorder.set_ondataavailable(Some(on_data.as_ref().unchecked_ref()));
+            on_data.forget();
+
+            // Once recording stops, release the microphone and hand the note to the chat box
+            let on_stop = Closure::<dyn FnMut()>::new(move || {
+                for track in stream.get_tracks().iter() {
+                    track.unchecked_into::<MediaStreamTrack>().stop();
                 }
+
+                let parts = js_sys::Array::new();
+                for blob in chunks.get_value() {
+                    parts.push(&blob);
+                }
+                chunks.set_value(Vec::new());
+
+                spawn_local(async move {
+                    let Ok(blob) = Blob::new_with_blob_sequence(&parts) else { return };
+                    let Ok(buffer) = JsFuture::from(blob.array_buffer()).await else { return };
+                    let data = js_sys::Uint8Array::new(&buffer).to_vec();
+                    if data.is_empty() {
+                        return;
+                    }
+
+                    on_record.call(FileUpload {
+                        name: format!("voice-note-{}.webm", chrono::Utc::now().format("%Y%m%d-%H%M%S")),
+                        content_type: "audio/webm".to_string(),
+                        data,
+                        transcript: None,
+                    });
+                });
+            });
+            media_recorder.set_onstop(Some(on_stop.as_ref().unchecked_ref()));
+            on_stop.forget();
+
+            if media_recorder.start().is_ok() {
+                recorder.set_value(Some(media_recorder));
+                set_is_recording.set(true);
             }
         });
     };
 
-    let stop_recording = move |_| {
+    let stop_recording = move || {
+        if let Some(media_recorder) = recorder.get_value() {
+            let _ = media_recorder.stop();
+        }
+        recorder.set_value(None);
         set_is_recording.set(false);
-        // In a real implementation, you'd stop the recording and process the audio
-        set_transcript.set("Voice recording stopped".to_string());
     };
 
     view! {
@@ -36,9 +93,9 @@ pub fn VoiceInput() -> impl IntoView {
                 type="button"
                 on:click=move |_| {
                     if is_recording.get() {
-                        stop_recording(());
+                        stop_recording();
                     } else {
-                        start_recording(());
+                        start_recording();
                     }
                 }
                 class=move || {
@@ -48,13 +105,13 @@ pub fn VoiceInput() -> impl IntoView {
                         "p-2 text-gray-500 hover:text-gray-700 transition-colors"
                     }
                 }
-                title="Voice input"
+                title=move || if is_recording.get() { "Stop recording" } else { "Record voice note" }
             >
                 <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
                     <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 11a7 7 0 01-7 7m0 0a7 7 0 01-7-7m7 7v4m0 0H8m4 0h4m-4-8a3 3 0 01-3-3V5a3 3 0 116 0v6a3 3 0 01-3 3z"></path>
                 </svg>
             </button>
-            
+
             // Recording indicator
             {move || {
                 if is_recording.get() {
@@ -67,4 +124,4 @@ pub fn VoiceInput() -> impl IntoView {
             }}
         </div>
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,408 @@
+use anyhow::Result;
+use serde::{Deserialize, Deserializer};
+use crate::{
+    ai_service::AIServiceConfig,
+    api::AppState,
+    rate_limit::{RateLimiter, RateLimits},
+};
+
+// Read from the working directory when AIBOT_CONFIG doesn't name another file
+const DEFAULT_CONFIG_FILE: &str = "aibot.toml";
+
+// Each setting's environment variable, which takes precedence over the file. Empty
+// variables count as unset, except for lists, where an empty value means none.
+const ENV_VARS: &[(&str, &str)] = &[
+    ("server.public_base_url", "PUBLIC_BASE_URL"),
+    ("server.trusted_origins", "TRUSTED_ORIGINS"),
+    ("server.cors_origins", "CORS_ORIGINS"),
+    ("server.admin_token", "ADMIN_TOKEN"),
+    ("server.grpc_addr", "GRPC_ADDR"),
+    ("server.shutdown_grace_secs", "SHUTDOWN_GRACE_SECS"),
+    ("logging.format", "LOG_FORMAT"),
+    ("logging.otlp_endpoint", "OTEL_EXPORTER_OTLP_ENDPOINT"),
+    ("logging.service_name", "OTEL_SERVICE_NAME"),
+    ("storage.database_url", "DATABASE_URL"),
+    ("storage.max_connections", "DATABASE_MAX_CONNECTIONS"),
+    ("storage.acquire_timeout_secs", "DATABASE_ACQUIRE_TIMEOUT_SECS"),
+    ("storage.sqlite_wal", "SQLITE_WAL"),
+    ("storage.sqlite_busy_timeout_ms", "SQLITE_BUSY_TIMEOUT_MS"),
+    ("storage.sqlite_foreign_keys", "SQLITE_FOREIGN_KEYS"),
+    ("storage.sqlcipher_key", "SQLCIPHER_KEY"),
+    ("storage.sqlcipher_key_file", "SQLCIPHER_KEY_FILE"),
+    ("storage.vector_dimensions", "VECTOR_DIMENSIONS"),
+    ("providers.openai_api_key", "OPENAI_API_KEY"),
+    ("providers.anthropic_api_key", "ANTHROPIC_API_KEY"),
+    ("providers.gemini_api_key", "GEMINI_API_KEY"),
+    ("providers.openrouter_api_key", "OPENROUTER_API_KEY"),
+    ("providers.ollama_base_url", "OLLAMA_BASE_URL"),
+    ("providers.redact", "PII_REDACTION_PROVIDERS"),
+    ("providers.default_provider", "DEFAULT_AI_PROVIDER"),
+    ("providers.default_model", "DEFAULT_MODEL"),
+    ("embeddings.provider", "EMBEDDINGS_PROVIDER"),
+    ("embeddings.model", "EMBEDDINGS_MODEL"),
+    ("embeddings.local_url", "LOCAL_EMBEDDINGS_URL"),
+    ("embeddings.batch_size", "EMBEDDINGS_BATCH_SIZE"),
+    ("rerank.provider", "RERANK_PROVIDER"),
+    ("rerank.model", "RERANK_MODEL"),
+    ("rerank.cohere_api_key", "COHERE_API_KEY"),
+    ("rerank.local_url", "LOCAL_RERANK_URL"),
+    ("moderation.provider", "MODERATION_PROVIDER"),
+    ("moderation.model", "MODERATION_MODEL"),
+    ("moderation.local_url", "LOCAL_MODERATION_URL"),
+    ("moderation.action", "MODERATION_ACTION"),
+    ("moderation.threshold", "MODERATION_THRESHOLD"),
+    ("memory.half_life_days", "MEMORY_HALF_LIFE_DAYS"),
+    ("memory.min_confidence", "MEMORY_MIN_CONFIDENCE"),
+    ("limits.messages_per_minute", "RATE_LIMIT_MESSAGES_PER_MINUTE"),
+    ("limits.uploads_per_hour", "RATE_LIMIT_UPLOADS_PER_HOUR"),
+    ("limits.trust_forwarded_for", "RATE_LIMIT_TRUST_FORWARDED_FOR"),
+    ("limits.kb_max_chunks_per_user", "KB_MAX_CHUNKS_PER_USER"),
+    ("retention.trash_days", "TRASH_RETENTION_DAYS"),
+    ("retention.account_deletion_grace_days", "ACCOUNT_DELETION_GRACE_DAYS"),
+    ("retention.message_days", "RETENTION_MESSAGE_DAYS"),
+    ("retention.max_sessions", "RETENTION_MAX_SESSIONS"),
+    ("retention.dry_run", "RETENTION_DRY_RUN"),
+    ("security.secrets_key", "SECRETS_KEY"),
+    ("security.secrets_key_file", "SECRETS_KEY_FILE"),
+    ("security.require_admin_2fa", "REQUIRE_ADMIN_2FA"),
+    ("security.password_reset_secret", "PASSWORD_RESET_SECRET"),
+    ("security.attachment_url_secret", "ATTACHMENT_URL_SECRET"),
+    ("security.attachment_url_ttl_hours", "ATTACHMENT_URL_TTL_HOURS"),
+    ("features.guest_mode", "GUEST_MODE"),
+    ("features.guest_model", "GUEST_MODEL"),
+    ("features.seed_demo", "SEED_DEMO"),
+    ("smtp.host", "SMTP_HOST"),
+    ("smtp.port", "SMTP_PORT"),
+    ("smtp.security", "SMTP_SECURITY"),
+    ("smtp.username", "SMTP_USERNAME"),
+    ("smtp.password", "SMTP_PASSWORD"),
+    ("smtp.from", "SMTP_FROM"),
+    ("oidc.issuer_url", "OIDC_ISSUER_URL"),
+    ("oidc.client_id", "OIDC_CLIENT_ID"),
+    ("oidc.client_secret", "OIDC_CLIENT_SECRET"),
+    ("oidc.scopes", "OIDC_SCOPES"),
+    ("oidc.groups_claim", "OIDC_GROUPS_CLAIM"),
+    ("oidc.role_mapping", "OIDC_ROLE_MAPPING"),
+    ("oidc.default_role", "OIDC_DEFAULT_ROLE"),
+    ("oidc.display_name", "OIDC_DISPLAY_NAME"),
+    ("email.inbound_secret", "INBOUND_EMAIL_SECRET"),
+    ("email.model_provider", "EMAIL_MODEL_PROVIDER"),
+    ("email.model", "EMAIL_MODEL"),
+    ("matrix.homeserver_url", "MATRIX_HOMESERVER_URL"),
+    ("matrix.user_id", "MATRIX_USER_ID"),
+    ("matrix.password", "MATRIX_PASSWORD"),
+    ("matrix.store_path", "MATRIX_STORE_PATH"),
+    ("matrix.store_passphrase", "MATRIX_STORE_PASSPHRASE"),
+    ("matrix.allowed_users", "MATRIX_ALLOWED_USERS"),
+    ("matrix.model_provider", "MATRIX_MODEL_PROVIDER"),
+    ("matrix.model", "MATRIX_MODEL"),
+];
+
+// Settings read as lists, where an empty environment variable is an empty list
+const LIST_KEYS: &[&str] = &[
+    "server.trusted_origins",
+    "server.cors_origins",
+    "providers.redact",
+    "matrix.allowed_users",
+];
+
+// The server's configuration: aibot.toml, with environment variables layered on top. Every
+// setting is optional; what is left unset falls back to the defaults of the part it
+// configures, so an empty file, or none at all, runs a local instance.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub logging: LoggingSettings,
+    pub storage: StorageSettings,
+    pub providers: ProviderSettings,
+    pub embeddings: EmbeddingsSettings,
+    pub rerank: RerankSettings,
+    pub moderation: ModerationSettings,
+    pub memory: MemorySettings,
+    pub limits: LimitSettings,
+    pub retention: RetentionSettings,
+    pub security: SecuritySettings,
+    pub features: FeatureSettings,
+    pub smtp: SmtpSettings,
+    pub oidc: OidcSettings,
+    pub email: EmailSettings,
+    pub matrix: MatrixSettings,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    // Where users reach the app, for links in emails and OAuth redirects
+    pub public_base_url: Option<String>,
+    // Origins allowed to make cookie-authenticated API calls besides the server's own
+    #[serde(deserialize_with = "list")]
+    pub trusted_origins: Vec<String>,
+    // Other frontends allowed to call the API from the browser with a personal access token
+    #[serde(deserialize_with = "list")]
+    pub cors_origins: Vec<String>,
+    // Enables the admin endpoints, authenticated with this bearer token
+    pub admin_token: Option<String>,
+    // Serves the gRPC API on this address; needs a build with the grpc feature
+    pub grpc_addr: Option<String>,
+    // How long requests in progress and background jobs get to finish on shutdown
+    pub shutdown_grace_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    // pretty or json
+    pub format: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StorageSettings {
+    pub database_url: Option<String>,
+    pub max_connections: Option<u32>,
+    pub acquire_timeout_secs: Option<u64>,
+    pub sqlite_wal: Option<bool>,
+    pub sqlite_busy_timeout_ms: Option<u64>,
+    pub sqlite_foreign_keys: Option<bool>,
+    // SQLCipher key, given directly or as a file holding it
+    pub sqlcipher_key: Option<String>,
+    pub sqlcipher_key_file: Option<String>,
+    // Must match the embedding model's output size
+    pub vector_dimensions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProviderSettings {
+    pub openai_api_key: Option<String>,
+    pub anthropic_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openrouter_api_key: Option<String>,
+    pub ollama_base_url: Option<String>,
+    // Providers whose prompts have personal data and secrets replaced; unset means the
+    // hosted ones, an empty list none
+    #[serde(deserialize_with = "optional_list")]
+    pub redact: Option<Vec<String>>,
+    // The model the email gateway, the Matrix bot and guests use when they name none
+    pub default_provider: Option<String>,
+    pub default_model: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingsSettings {
+    // openai, ollama or local
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub local_url: Option<String>,
+    pub batch_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RerankSettings {
+    // cohere or local; reranking is off when unset
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub cohere_api_key: Option<String>,
+    pub local_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModerationSettings {
+    // openai or local; the content filter is off when unset
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub local_url: Option<String>,
+    // block, warn or log
+    pub action: Option<String>,
+    pub threshold: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MemorySettings {
+    pub half_life_days: Option<f64>,
+    pub min_confidence: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LimitSettings {
+    // 0 turns a rate limit off
+    pub messages_per_minute: Option<u32>,
+    pub uploads_per_hour: Option<u32>,
+    pub trust_forwarded_for: Option<bool>,
+    // Unset is unlimited
+    pub kb_max_chunks_per_user: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub trash_days: Option<i64>,
+    pub account_deletion_grace_days: Option<i64>,
+    // Unset limits keep history forever
+    pub message_days: Option<i64>,
+    pub max_sessions: Option<i64>,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    // Master key sealing stored secrets, given directly or as a file holding it
+    pub secrets_key: Option<String>,
+    pub secrets_key_file: Option<String>,
+    pub require_admin_2fa: Option<bool>,
+    pub password_reset_secret: Option<String>,
+    pub attachment_url_secret: Option<String>,
+    pub attachment_url_ttl_hours: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FeatureSettings {
+    pub guest_mode: Option<bool>,
+    pub guest_model: Option<String>,
+    pub seed_demo: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SmtpSettings {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub security: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OidcSettings {
+    pub issuer_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scopes: Option<String>,
+    pub groups_claim: Option<String>,
+    pub role_mapping: Option<String>,
+    pub default_role: Option<String>,
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub inbound_secret: Option<String>,
+    pub model_provider: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MatrixSettings {
+    pub homeserver_url: Option<String>,
+    pub user_id: Option<String>,
+    pub password: Option<String>,
+    pub store_path: Option<String>,
+    pub store_passphrase: Option<String>,
+    #[serde(deserialize_with = "list")]
+    pub allowed_users: Vec<String>,
+    pub model_provider: Option<String>,
+    pub model: Option<String>,
+}
+
+// Lists are TOML arrays in the file and comma-separated in environment variables
+fn list<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Items(Vec<String>),
+        Joined(String),
+    }
+
+    let items = match List::deserialize(deserializer)? {
+        List::Items(items) => items,
+        List::Joined(joined) => joined.split(',').map(str::to_string).collect(),
+    };
+    Ok(items.into_iter().map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect())
+}
+
+fn optional_list<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Vec<String>>, D::Error> {
+    list(deserializer).map(Some)
+}
+
+impl Settings {
+    // Reads the file AIBOT_CONFIG names, or aibot.toml if there is one, and applies the
+    // environment variables over it
+    pub fn load() -> Result<Self> {
+        let path = std::env::var("AIBOT_CONFIG").ok().filter(|path| !path.is_empty());
+        // A file named explicitly has to be there; the default one is optional
+        let file = ::config::File::new(path.as_deref().unwrap_or(DEFAULT_CONFIG_FILE), ::config::FileFormat::Toml)
+            .required(path.is_some());
+
+        let mut builder = ::config::Config::builder().add_source(file);
+        for (key, var) in ENV_VARS {
+            let Ok(value) = std::env::var(var) else { continue };
+            if value.is_empty() && !LIST_KEYS.contains(key) {
+                continue;
+            }
+            builder = builder.set_override(*key, value)?;
+        }
+        Ok(builder.build()?.try_deserialize()?)
+    }
+
+    pub fn ai_service_config(&self) -> AIServiceConfig {
+        let defaults = AIServiceConfig::default();
+        let providers = &self.providers;
+        AIServiceConfig {
+            openai_api_key: providers.openai_api_key.clone(),
+            anthropic_api_key: providers.anthropic_api_key.clone(),
+            gemini_api_key: providers.gemini_api_key.clone(),
+            openrouter_api_key: providers.openrouter_api_key.clone(),
+            ollama_base_url: providers.ollama_base_url.clone().unwrap_or(defaults.ollama_base_url),
+            // Hosted providers by default; set to an empty list to send prompts unredacted
+            redact_providers: providers.redact.as_ref()
+                .map(|providers| providers.iter().map(|p| p.to_lowercase()).collect())
+                .unwrap_or(defaults.redact_providers),
+        }
+    }
+
+    // Per-user message and upload limits; 0 turns one off
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            messages_per_minute: self.limits.messages_per_minute.or(Some(20)).filter(|n| *n > 0),
+            uploads_per_hour: self.limits.uploads_per_hour.or(Some(60)).filter(|n| *n > 0),
+            trust_forwarded_for: self.limits.trust_forwarded_for.unwrap_or(false),
+        }
+    }
+
+    pub fn default_model(&self) -> String {
+        self.providers.default_model.clone().unwrap_or_else(|| "llama3.2".to_string())
+    }
+
+    // The model guests chat with, or None when guest mode is off
+    pub fn guest_model(&self) -> Option<String> {
+        self.features.guest_mode.unwrap_or(false).then(|| {
+            self.features.guest_model.clone().unwrap_or_else(|| self.default_model())
+        })
+    }
+}
+
+// Reads the configuration again and swaps in the provider keys, rate limits and guest model.
+// Everything else, like the database, SMTP, single sign-on and the Matrix bot, keeps the
+// settings it started with until the server restarts. Environment variables are only read
+// from the process, so changes to them or to .env need a restart too.
+pub async fn reload(state: &AppState, limiter: &RateLimiter) -> Result<()> {
+    let settings = Settings::load()?;
+    state.ai_service.reconfigure(settings.ai_service_config()).await;
+    limiter.set_limits(settings.rate_limits());
+    *state.guest_model.write().unwrap() = settings.guest_model();
+    Ok(())
+}
@@ -0,0 +1,445 @@
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use std::{collections::HashSet, sync::OnceLock, time::Duration as StdDuration};
+use crate::{api::AppState, knowledge_base, models::*, secrets::secret_box};
+
+// Holds the nonce sent as the OAuth `state` and the connector being authorized, so the
+// callback only completes an authorization this browser started
+pub const FLOW_COOKIE: &str = "aibot_connector";
+// How long the user has to finish authorizing at the provider
+const FLOW_TTL_SECS: i64 = 10 * 60;
+const NOTION_VERSION: &str = "2022-06-28";
+const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive.readonly";
+// How deep to follow nested Notion blocks (toggles, columns, sub-lists)
+const NOTION_MAX_DEPTH: usize = 3;
+// How long a request to Notion or Google may take before the sync gives up on it
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+// Shared by every authorization and sync, so connections to the providers are reused
+fn http() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(StdDuration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .expect("the connector HTTP client settings are valid")
+    })
+}
+
+struct OAuthClient {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+}
+
+fn oauth_client(state: &AppState, provider: ConnectorProvider) -> Result<OAuthClient> {
+    let prefix = match provider {
+        ConnectorProvider::Notion => "NOTION",
+        ConnectorProvider::GoogleDrive => "GOOGLE",
+    };
+    let client_id = std::env::var(format!("{}_CLIENT_ID", prefix))
+        .map_err(|_| anyhow::anyhow!("{}_CLIENT_ID is not set", prefix))?;
+    let client_secret = std::env::var(format!("{}_CLIENT_SECRET", prefix))
+        .map_err(|_| anyhow::anyhow!("{}_CLIENT_SECRET is not set", prefix))?;
+    let base_url = &state.public_base_url;
+
+    Ok(OAuthClient {
+        client_id,
+        client_secret,
+        redirect_uri: format!("{}/api/connectors/{}/callback", base_url.trim_end_matches('/'), provider),
+    })
+}
+
+fn flow_cookie(value: &str, max_age_secs: i64) -> String {
+    format!("{}={}; Path=/api/connectors/; HttpOnly; SameSite=Lax; Max-Age={}", FLOW_COOKIE, value, max_age_secs)
+}
+
+// Clears the cookie of an authorization once it is finished
+pub fn clear_flow_cookie() -> String {
+    flow_cookie("", 0)
+}
+
+// URL to send the user to, and the flow cookie to set before they go
+pub fn authorize_url(state: &AppState, connector: &Connector) -> Result<(String, String)> {
+    let oauth = oauth_client(state, connector.provider)?;
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let nonce: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut url = match connector.provider {
+        ConnectorProvider::Notion => reqwest::Url::parse("https://api.notion.com/v1/oauth/authorize")?,
+        ConnectorProvider::GoogleDrive => reqwest::Url::parse("https://accounts.google.com/o/oauth2/v2/auth")?,
+    };
+
+    {
+        let mut query = url.query_pairs_mut();
+        query
+            .append_pair("client_id", &oauth.client_id)
+            .append_pair("redirect_uri", &oauth.redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("state", &nonce);
+        match connector.provider {
+            ConnectorProvider::Notion => {
+                query.append_pair("owner", "user");
+            }
+            ConnectorProvider::GoogleDrive => {
+                query
+                    .append_pair("scope", DRIVE_SCOPE)
+                    .append_pair("access_type", "offline")
+                    .append_pair("prompt", "consent");
+            }
+        }
+    }
+
+    let cookie = flow_cookie(&format!("{}.{}", nonce, connector.id), FLOW_TTL_SECS);
+    Ok((url.to_string(), cookie))
+}
+
+// The id of the connector a callback is for, once its `state` matches the flow cookie
+pub fn flow_connector_id(returned_state: &str, flow: Option<&str>) -> Result<String> {
+    let flow = flow.ok_or_else(|| anyhow::anyhow!("Authorization cookie missing or expired"))?;
+    let (nonce, connector_id) = flow.split_once('.')
+        .ok_or_else(|| anyhow::anyhow!("Malformed authorization cookie"))?;
+    if !crate::auth::secrets_match(returned_state, nonce) {
+        return Err(anyhow::anyhow!("Authorization state doesn't match"));
+    }
+    Ok(connector_id.to_string())
+}
+
+// Exchanges an OAuth authorization code for tokens and stores them on the connector
+pub async fn complete_authorization(state: &AppState, connector: &Connector, code: &str) -> Result<()> {
+    let oauth = oauth_client(state, connector.provider)?;
+    let http = http();
+
+    let response: Value = match connector.provider {
+        ConnectorProvider::Notion => http
+            .post("https://api.notion.com/v1/oauth/token")
+            .basic_auth(&oauth.client_id, Some(&oauth.client_secret))
+            .json(&json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "redirect_uri": oauth.redirect_uri,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?,
+        ConnectorProvider::GoogleDrive => http
+            .post("https://oauth2.googleapis.com/token")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("client_id", &oauth.client_id),
+                ("client_secret", &oauth.client_secret),
+                ("redirect_uri", &oauth.redirect_uri),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?,
+    };
+
+    store_tokens(state, &connector.id, &response).await
+}
+
+// Tokens are sealed with the instance's SecretBox, as they read the user's Notion or Drive
+async fn store_tokens(state: &AppState, connector_id: &str, response: &Value) -> Result<()> {
+    let access_token = response["access_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Token response did not contain an access token"))?;
+    let expires_at = response["expires_in"].as_i64().map(|secs| Utc::now() + Duration::seconds(secs));
+
+    let secret_box = secret_box(state)?;
+    let access_token = secret_box.seal(access_token)?;
+    let refresh_token = response["refresh_token"].as_str().map(|token| secret_box.seal(token)).transpose()?;
+    state.db.set_connector_tokens(connector_id, &access_token, refresh_token.as_deref(), expires_at).await
+}
+
+// Returns a usable access token, refreshing Google tokens that are about to expire
+async fn access_token(state: &AppState, connector: &Connector) -> Result<String> {
+    let (token, refresh_token, expires_at) = state.db.get_connector_tokens(&connector.id).await?
+        .ok_or_else(|| anyhow::anyhow!("Connector has not been authorized"))?;
+
+    let expiring = expires_at.map(|at| at - Duration::minutes(2) <= Utc::now()).unwrap_or(false);
+    if !expiring {
+        return secret_box(state)?.open(&token);
+    }
+
+    let refresh_token = refresh_token.map(|token| secret_box(state)?.open(&token)).transpose()?;
+    let refresh_token = refresh_token.ok_or_else(|| anyhow::anyhow!("Access token expired and no refresh token is stored"))?;
+    let oauth = oauth_client(state, connector.provider)?;
+    let response: Value = http()
+        .post("https://oauth2.googleapis.com/token")
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", &oauth.client_id),
+            ("client_secret", &oauth.client_secret),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    store_tokens(state, &connector.id, &response).await?;
+    response["access_token"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Refresh response did not contain an access token"))
+}
+
+struct ExternalItem {
+    external_id: String,
+    title: String,
+    url: String,
+    // Provider's last-modified stamp, compared verbatim to detect changes
+    modified_at: String,
+    mime_type: Option<String>,
+}
+
+// Syncs a connector's selected resources into its knowledge base, re-ingesting only
+// items whose modification stamp changed and removing items that disappeared.
+// Returns the number of documents (re)ingested.
+pub async fn sync_connector(state: AppState, job_id: String, connector: Connector) -> Result<i64> {
+    // Stamp the sync as started so the scheduler doesn't queue it again meanwhile
+    state.db.record_connector_sync(&connector.id, JobStatus::Running, None).await?;
+
+    let result = run_sync(&state, &job_id, &connector).await;
+    match &result {
+        Ok(_) => state.db.record_connector_sync(&connector.id, JobStatus::Completed, None).await?,
+        Err(e) => state.db.record_connector_sync(&connector.id, JobStatus::Failed, Some(&e.to_string())).await?,
+    }
+    result
+}
+
+async fn run_sync(state: &AppState, job_id: &str, connector: &Connector) -> Result<i64> {
+    let token = access_token(state, connector).await?;
+    let http = http();
+
+    let items = match connector.provider {
+        ConnectorProvider::Notion => notion_items(http, &token, &connector.resource_ids).await?,
+        ConnectorProvider::GoogleDrive => drive_items(http, &token, &connector.resource_ids).await?,
+    };
+    let known = state.db.get_connector_documents(&connector.id).await?;
+    let mut seen = HashSet::new();
+    let mut ingested = 0i64;
+
+    for item in &items {
+        seen.insert(item.external_id.clone());
+        let previous = known.get(&item.external_id);
+        if previous.map(|(_, modified)| modified == &item.modified_at).unwrap_or(false) {
+            continue;
+        }
+
+        let text = match connector.provider {
+            ConnectorProvider::Notion => notion_page_text(http, &token, &item.external_id, 0).await?,
+            ConnectorProvider::GoogleDrive => match drive_file_text(http, &token, item).await? {
+                Some(text) => text,
+                None => continue,
+            },
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let previous_document = match previous {
+            Some((document_id, _)) => state.db.get_kb_document(document_id).await?,
+            None => None,
+        };
+
+        let source_type = connector.provider.to_string();
+        let document = knowledge_base::replace_text(
+            state,
+            previous_document.as_ref(),
+            &connector.knowledge_base_id,
+            &source_type,
+            &item.url,
+            &item.title,
+            &text,
+        ).await?;
+        state.db.upsert_connector_document(&connector.id, &item.external_id, &document.id, &item.modified_at).await?;
+
+        ingested += 1;
+        state.db.update_ingestion_job(job_id, JobStatus::Running, ingested, None).await?;
+    }
+
+    // Drop documents whose source was deleted or moved out of the synced folders
+    for (external_id, (document_id, _)) in &known {
+        if !seen.contains(external_id) {
+            if let Some(document) = state.db.get_kb_document(document_id).await? {
+                knowledge_base::delete_document(state, &document).await?;
+            }
+        }
+    }
+
+    Ok(ingested)
+}
+
+async fn notion_items(http: &reqwest::Client, token: &str, page_ids: &[String]) -> Result<Vec<ExternalItem>> {
+    let mut items = Vec::new();
+    for page_id in page_ids {
+        let page: Value = http
+            .get(format!("https://api.notion.com/v1/pages/{}", page_id))
+            .bearer_auth(token)
+            .header("Notion-Version", NOTION_VERSION)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // The title lives in whichever property has type "title"
+        let title = page["properties"]
+            .as_object()
+            .and_then(|props| props.values().find(|p| p["type"] == "title"))
+            .map(|p| rich_text(&p["title"]))
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "Untitled".to_string());
+
+        items.push(ExternalItem {
+            external_id: page_id.clone(),
+            title,
+            url: page["url"].as_str().unwrap_or_default().to_string(),
+            modified_at: page["last_edited_time"].as_str().unwrap_or_default().to_string(),
+            mime_type: None,
+        });
+    }
+    Ok(items)
+}
+
+async fn notion_page_text(http: &reqwest::Client, token: &str, block_id: &str, depth: usize) -> Result<String> {
+    let mut text = String::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut request = http
+            .get(format!("https://api.notion.com/v1/blocks/{}/children", block_id))
+            .bearer_auth(token)
+            .header("Notion-Version", NOTION_VERSION)
+            .query(&[("page_size", "100")]);
+        if let Some(cursor) = &cursor {
+            request = request.query(&[("start_cursor", cursor.as_str())]);
+        }
+        let response: Value = request.send().await?.error_for_status()?.json().await?;
+
+        for block in response["results"].as_array().into_iter().flatten() {
+            let block_type = block["type"].as_str().unwrap_or_default();
+            let line = rich_text(&block[block_type]["rich_text"]);
+            if !line.is_empty() {
+                text.push_str(&line);
+                text.push_str("\n\n");
+            }
+            // Child pages are synced separately if selected, so don't inline them
+            if block["has_children"].as_bool().unwrap_or(false) && block_type != "child_page" && depth < NOTION_MAX_DEPTH {
+                let block_id = block["id"].as_str().unwrap_or_default();
+                text.push_str(&Box::pin(notion_page_text(http, token, block_id, depth + 1)).await?);
+            }
+        }
+
+        match response["next_cursor"].as_str() {
+            Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+            _ => break,
+        }
+    }
+
+    Ok(text)
+}
+
+fn rich_text(value: &Value) -> String {
+    value
+        .as_array()
+        .map(|parts| parts.iter().filter_map(|part| part["plain_text"].as_str()).collect())
+        .unwrap_or_default()
+}
+
+async fn drive_items(http: &reqwest::Client, token: &str, folder_ids: &[String]) -> Result<Vec<ExternalItem>> {
+    let mut items = Vec::new();
+    for folder_id in folder_ids {
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = http
+                .get("https://www.googleapis.com/drive/v3/files")
+                .bearer_auth(token)
+                .query(&[
+                    ("q", format!("'{}' in parents and trashed = false", folder_id)),
+                    ("fields", "nextPageToken, files(id, name, mimeType, modifiedTime, webViewLink)".to_string()),
+                    ("pageSize", "100".to_string()),
+                ]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let response: Value = request.send().await?.error_for_status()?.json().await?;
+
+            for file in response["files"].as_array().into_iter().flatten() {
+                items.push(ExternalItem {
+                    external_id: file["id"].as_str().unwrap_or_default().to_string(),
+                    title: file["name"].as_str().unwrap_or("Untitled").to_string(),
+                    url: file["webViewLink"].as_str().unwrap_or_default().to_string(),
+                    modified_at: file["modifiedTime"].as_str().unwrap_or_default().to_string(),
+                    mime_type: file["mimeType"].as_str().map(str::to_string),
+                });
+            }
+
+            match response["nextPageToken"].as_str() {
+                Some(next) => page_token = Some(next.to_string()),
+                None => break,
+            }
+        }
+    }
+    Ok(items)
+}
+
+// Google Docs are exported as plain text; PDFs and text files are downloaded as-is.
+// Returns None for file types we can't index.
+async fn drive_file_text(http: &reqwest::Client, token: &str, item: &ExternalItem) -> Result<Option<String>> {
+    let mime_type = item.mime_type.clone().unwrap_or_default();
+    let base = format!("https://www.googleapis.com/drive/v3/files/{}", item.external_id);
+
+    if mime_type == "application/vnd.google-apps.document" {
+        let text = http
+            .get(format!("{}/export", base))
+            .bearer_auth(token)
+            .query(&[("mimeType", "text/plain")])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        return Ok(Some(text));
+    }
+
+    if mime_type != "application/pdf" && !mime_type.starts_with("text/") {
+        return Ok(None);
+    }
+
+    let data = http
+        .get(&base)
+        .bearer_auth(token)
+        .query(&[("alt", "media")])
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let file = FileUpload {
+        name: item.title.clone(),
+        content_type: mime_type,
+        data: data.to_vec(),
+        transcript: None,
+    };
+    Ok(Some(knowledge_base::extract_text(&file)?))
+}
+
+// Queues a background sync for a connector and returns the job tracking it
+pub async fn start_sync(state: &AppState, connector: Connector) -> Result<IngestionJob> {
+    let job = IngestionJob::new(connector.knowledge_base_id.clone(), &connector.provider.to_string(), connector.id.clone());
+    state.db.create_ingestion_job(&job).await?;
+
+    let work = sync_connector(state.clone(), job.id.clone(), connector);
+    knowledge_base::run_in_background(state.clone(), job.id.clone(), work);
+    Ok(job)
+}
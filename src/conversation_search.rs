@@ -0,0 +1,95 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use crate::{api::AppState, models::*, usage::estimate_tokens, vector_store::MESSAGES};
+
+// Messages embedded per provider call while working through the backlog
+const INDEX_BATCH: i64 = 64;
+// A message the provider fails to embed is tried again after RETRY_DELAY_MINUTES, and left
+// out of the index after MAX_INDEX_ATTEMPTS failures
+const MAX_INDEX_ATTEMPTS: i64 = 5;
+const RETRY_DELAY_MINUTES: i64 = 60;
+const SNIPPET_CHARS: usize = 240;
+
+// Each user's messages live in their own partition so searches never cross users
+pub fn message_collection(user_id: &str) -> String {
+    format!("{}:{}", MESSAGES, user_id)
+}
+
+// Embeds every stored message that isn't indexed yet, oldest first. Returns how many were indexed.
+pub async fn index_pending_messages(state: &AppState) -> Result<usize> {
+    let mut indexed = 0;
+    loop {
+        let retry_before = Utc::now() - Duration::minutes(RETRY_DELAY_MINUTES);
+        let pending = state.db.get_unembedded_messages(INDEX_BATCH, MAX_INDEX_ATTEMPTS, retry_before).await?;
+        if pending.is_empty() {
+            return Ok(indexed);
+        }
+
+        let texts: Vec<String> = pending.iter().map(|(_, message)| message.content.clone()).collect();
+        let embeddings: Vec<Option<Vec<f32>>> = match state.embeddings.embed(&texts).await {
+            Ok(embeddings) => embeddings.into_iter().map(Some).collect(),
+            // One at a time, so a message the provider rejects doesn't hold up the rest
+            Err(e) => {
+                tracing::warn!("Failed to embed {} messages together, trying one at a time: {}", texts.len(), e);
+                let mut embeddings = Vec::with_capacity(texts.len());
+                for text in &texts {
+                    embeddings.push(state.embeddings.embed_one(text).await.ok());
+                }
+                embeddings
+            }
+        };
+
+        for ((user_id, message), (embedding, text)) in pending.iter().zip(embeddings.iter().zip(&texts)) {
+            let Some(embedding) = embedding else {
+                tracing::warn!("Failed to embed message {} for search", message.id);
+                state.db.record_message_embed_failure(&message.id).await?;
+                continue;
+            };
+            let metadata = json!({
+                "session_id": message.session_id,
+                "role": message.role.to_string(),
+            });
+            state.vectors.upsert(&message_collection(user_id), &message.id, embedding, &metadata).await?;
+            state.db.mark_message_embedded(&message.id).await?;
+            state.db.record_embedded_tokens(user_id, estimate_tokens(std::slice::from_ref(text))).await?;
+            indexed += 1;
+        }
+    }
+}
+
+// Finds the user's past messages closest in meaning to `query`, best match first
+pub async fn search(state: &AppState, user_id: &str, query: &str, k: usize) -> Result<Vec<ConversationSearchResult>> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let embedding = state.embeddings.embed_one(query).await?;
+    let matches = state.vectors.query(&message_collection(user_id), &embedding, k).await?;
+
+    let mut results = Vec::with_capacity(matches.len());
+    for m in matches {
+        // The session may have been deleted since the message was indexed
+        let Some(message) = state.db.get_message(&m.item_id).await? else {
+            continue;
+        };
+        let Some(session) = state.db.get_session(&message.session_id).await? else {
+            continue;
+        };
+        if message.deleted_at.is_some() || session.deleted_at.is_some() {
+            continue;
+        }
+
+        results.push(ConversationSearchResult {
+            session_id: session.id,
+            session_title: session.title.unwrap_or_else(|| "Untitled chat".to_string()),
+            message_id: message.id,
+            role: message.role,
+            snippet: message.content.chars().take(SNIPPET_CHARS).collect(),
+            created_at: message.created_at,
+            score: 1.0 - m.distance,
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,274 @@
+use anyhow::Result;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::{api::AppState, knowledge_base, models::*, outbound};
+
+const USER_AGENT: &str = "aibot-crawler/0.1";
+const REQUEST_DELAY_MS: u64 = 250;
+// Pages and robots.txt files larger than these aren't read
+const MAX_PAGE_BYTES: usize = 5_000_000;
+const MAX_ROBOTS_BYTES: usize = 500_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlOptions {
+    pub max_depth: usize,
+    pub max_pages: usize,
+    // Stay on the start URL's host
+    pub same_domain_only: bool,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 2,
+            max_pages: 50,
+            same_domain_only: true,
+        }
+    }
+}
+
+impl From<&CrawlSource> for CrawlOptions {
+    fn from(source: &CrawlSource) -> Self {
+        Self {
+            max_depth: source.max_depth.max(0) as usize,
+            max_pages: source.max_pages.max(0) as usize,
+            same_domain_only: source.same_domain_only,
+        }
+    }
+}
+
+// Crawls a source and records the outcome on it. Returns the number of pages (re)ingested.
+pub async fn sync_crawl_source(state: AppState, job_id: String, source: CrawlSource) -> Result<i64> {
+    // Stamp the crawl as started so the scheduler doesn't queue it again meanwhile
+    state.db.record_crawl_sync(&source.id, JobStatus::Running, None).await?;
+
+    let result = crawl_site(&state, &job_id, &source.knowledge_base_id, &source.start_url, CrawlOptions::from(&source)).await;
+    match &result {
+        Ok(_) => state.db.record_crawl_sync(&source.id, JobStatus::Completed, None).await?,
+        Err(e) => state.db.record_crawl_sync(&source.id, JobStatus::Failed, Some(&e.to_string())).await?,
+    }
+    result
+}
+
+// Queues a background crawl of a source and returns the job tracking it
+pub async fn start_crawl(state: &AppState, source: CrawlSource) -> Result<IngestionJob> {
+    let job = IngestionJob::new(source.knowledge_base_id.clone(), "web", source.start_url.clone());
+    state.db.create_ingestion_job(&job).await?;
+
+    let work = sync_crawl_source(state.clone(), job.id.clone(), source);
+    knowledge_base::run_in_background(state.clone(), job.id.clone(), work);
+    Ok(job)
+}
+
+// Breadth-first crawl from `start_url`, ingesting every HTML page into the knowledge base.
+// Pages already ingested with identical text are skipped, and changed pages replace their
+// previous document, so re-crawling only re-embeds what changed.
+// Returns the number of pages ingested.
+async fn crawl_site(
+    state: &AppState,
+    job_id: &str,
+    kb_id: &str,
+    start_url: &str,
+    options: CrawlOptions,
+) -> Result<i64> {
+    let start = Url::parse(start_url)?;
+    let client = outbound::client();
+    // robots.txt applies per origin, so links to other hosts are checked against their own
+    let mut robots: HashMap<String, RobotsRules> = HashMap::new();
+
+    let mut queue = VecDeque::from([(start.clone(), 0usize)]);
+    let mut visited = HashSet::new();
+    let mut ingested = 0i64;
+    let mut crawled = 0usize;
+
+    while let Some((url, depth)) = queue.pop_front() {
+        if crawled >= options.max_pages {
+            break;
+        }
+        if !visited.insert(url.to_string()) {
+            continue;
+        }
+        let origin = url.origin().ascii_serialization();
+        if !robots.contains_key(&origin) {
+            let rules = RobotsRules::fetch(client, &url).await;
+            robots.insert(origin.clone(), rules);
+        }
+        if !robots[&origin].is_allowed(url.path()) {
+            continue;
+        }
+
+        let page = match fetch_page(client, &url).await {
+            Ok(Some(page)) => page,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Crawler skipped {}: {}", url, e);
+                continue;
+            }
+        };
+
+        if depth < options.max_depth {
+            for link in page.links {
+                let same_host = link.host_str() == start.host_str();
+                // Links to an IP address skip the client's check of where names resolve
+                let allowed = (!options.same_domain_only || same_host) && outbound::literal_host_is_public(&link);
+                if allowed && !visited.contains(link.as_str()) {
+                    queue.push_back((link, depth + 1));
+                }
+            }
+        }
+
+        if !page.text.trim().is_empty() {
+            crawled += 1;
+            let previous = state.db.find_kb_document_by_source(kb_id, url.as_str()).await?;
+            let hash = knowledge_base::content_hash(&page.text);
+
+            if previous.as_ref().and_then(|d| d.content_hash.as_deref()) != Some(hash.as_str()) {
+                knowledge_base::replace_text(state, previous.as_ref(), kb_id, "web", url.as_str(), &page.title, &page.text).await?;
+                ingested += 1;
+                state.db.update_ingestion_job(job_id, JobStatus::Running, ingested, None).await?;
+            }
+        }
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(REQUEST_DELAY_MS)).await;
+    }
+
+    Ok(ingested)
+}
+
+struct Page {
+    title: String,
+    text: String,
+    links: Vec<Url>,
+}
+
+async fn fetch_page(client: &reqwest::Client, url: &Url) -> Result<Option<Page>> {
+    if !outbound::literal_host_is_public(url) {
+        return Err(anyhow::anyhow!("{} is a private address", url));
+    }
+    let response = client.get(url.clone())
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?
+        .error_for_status()?;
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("text/html"))
+        .unwrap_or(false);
+    if !is_html {
+        return Ok(None);
+    }
+
+    let body = outbound::read_text(response, MAX_PAGE_BYTES).await?;
+    let document = Html::parse_document(&body);
+
+    let title_selector = Selector::parse("title").unwrap();
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|t| t.text().collect::<String>().trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| url.to_string());
+
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let links = document
+        .select(&link_selector)
+        .filter_map(|a| a.value().attr("href"))
+        .filter_map(|href| url.join(href).ok())
+        .filter(|link| matches!(link.scheme(), "http" | "https"))
+        .map(|mut link| {
+            link.set_fragment(None);
+            link
+        })
+        .collect();
+
+    Ok(Some(Page {
+        title,
+        text: page_text(&document),
+        links,
+    }))
+}
+
+// Fetches a single page and returns its title and visible text
+pub(crate) async fn fetch_page_text(url: &str) -> Result<(String, String)> {
+    let url = Url::parse(url)?;
+    let page = fetch_page(outbound::client(), &url).await?
+        .ok_or_else(|| anyhow::anyhow!("{} isn't an HTML page", url))?;
+    Ok((page.title, page.text))
+}
+
+// Visible text of a page, one paragraph per text node, skipping scripts and chrome
+pub(crate) fn page_text(document: &Html) -> String {
+    const SKIPPED: [&str; 6] = ["script", "style", "noscript", "head", "nav", "footer"];
+
+    let mut text = String::new();
+    for node in document.root_element().descendants() {
+        let Some(fragment) = node.value().as_text() else { continue };
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor
+                .value()
+                .as_element()
+                .map(|element| SKIPPED.contains(&element.name()))
+                .unwrap_or(false)
+        });
+        let fragment = fragment.trim();
+        if !hidden && !fragment.is_empty() {
+            text.push_str(fragment);
+            text.push_str("\n\n");
+        }
+    }
+    text
+}
+
+// The subset of robots.txt we honour: Allow/Disallow prefixes for `*` or our agent
+struct RobotsRules {
+    allow: Vec<String>,
+    disallow: Vec<String>,
+}
+
+impl RobotsRules {
+    async fn fetch(client: &reqwest::Client, page: &Url) -> Self {
+        let mut rules = Self { allow: Vec::new(), disallow: Vec::new() };
+        let Ok(robots_url) = page.join("/robots.txt") else { return rules };
+
+        let body = match client.get(robots_url).header(reqwest::header::USER_AGENT, USER_AGENT).send().await {
+            Ok(response) if response.status().is_success() => outbound::read_text(response, MAX_ROBOTS_BYTES).await.unwrap_or_default(),
+            _ => return rules,
+        };
+
+        let mut applies = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => applies = value == "*" || USER_AGENT.starts_with(value),
+                "allow" if applies && !value.is_empty() => rules.allow.push(value.to_string()),
+                "disallow" if applies && !value.is_empty() => rules.disallow.push(value.to_string()),
+                _ => {}
+            }
+        }
+        rules
+    }
+
+    // Longest matching prefix wins; Allow wins ties
+    fn is_allowed(&self, path: &str) -> bool {
+        let longest = |prefixes: &[String]| {
+            prefixes
+                .iter()
+                .filter(|prefix| path.starts_with(prefix.as_str()))
+                .map(|prefix| prefix.len())
+                .max()
+        };
+
+        match (longest(&self.allow), longest(&self.disallow)) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(allow), Some(disallow)) => allow >= disallow,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+// Origins besides the server's own that may call the API with the user's cookie, such as
+// a frontend served from another domain. Written as `scheme://host[:port]`.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedOrigins(Arc<Vec<String>>);
+
+impl TrustedOrigins {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self(Arc::new(origins.into_iter().map(|origin| origin.trim_end_matches('/').to_string()).collect()))
+    }
+
+    fn allows(&self, origin: &str, headers: &HeaderMap) -> bool {
+        // The server's own origin, whatever name it was reached by
+        let host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+        let same_host = origin.split_once("://").map(|(_, rest)| rest) == host;
+        same_host || self.0.iter().any(|trusted| trusted == origin)
+    }
+}
+
+// Rejects cross-site requests that change something, now that the API is authenticated by
+// a cookie the browser attaches to every request. Browsers say where a request comes from
+// in Sec-Fetch-Site, and older ones in Origin; requests with neither don't come from a web
+// page. Requests with a bearer token, the admin token or a personal access token, are
+// left alone: browsers never attach one on their own, and those requests ignore the
+// cookie. The admin endpoints also take the cookie, so they are checked like the rest.
+pub async fn require_same_origin(State(trusted): State<TrustedOrigins>, request: Request, next: Next) -> Response {
+    if !allowed(&trusted, request.method(), request.uri().path(), request.headers()) {
+        return (StatusCode::FORBIDDEN, "Cross-site request blocked").into_response();
+    }
+    next.run(request).await
+}
+
+fn allowed(trusted: &TrustedOrigins, method: &Method, path: &str, headers: &HeaderMap) -> bool {
+    let safe_method = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let bearer = crate::api_tokens::bearer_token(headers).is_some();
+    if safe_method || bearer || !path.starts_with("/api/") {
+        return true;
+    }
+
+    let origin = headers.get(header::ORIGIN).and_then(|value| value.to_str().ok());
+    let fetch_site = headers.get("sec-fetch-site").and_then(|value| value.to_str().ok());
+    match (origin, fetch_site) {
+        (Some(origin), _) if trusted.allows(origin, headers) => true,
+        (_, Some("same-origin" | "none")) => true,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "chat.example.com".parse().unwrap());
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn post(path: &str, pairs: &[(&'static str, &str)]) -> bool {
+        let trusted = TrustedOrigins::new(vec!["https://app.example.org/".to_string()]);
+        allowed(&trusted, &Method::POST, path, &headers(pairs))
+    }
+
+    #[test]
+    fn blocks_cross_site_writes() {
+        assert!(!post("/api/send_message", &[("origin", "https://evil.example.net")]));
+        assert!(!post("/api/send_message", &[("sec-fetch-site", "cross-site")]));
+        assert!(!post("/api/send_message", &[("sec-fetch-site", "same-site")]));
+    }
+
+    #[test]
+    fn checks_admin_endpoints_like_the_rest() {
+        assert!(!post("/api/admin/restore", &[("origin", "https://evil.example.net")]));
+        assert!(post("/api/admin/restore", &[("authorization", "Bearer admin-token"), ("origin", "https://evil.example.net")]));
+    }
+
+    #[test]
+    fn allows_own_and_trusted_origins() {
+        assert!(post("/api/send_message", &[("origin", "https://chat.example.com")]));
+        assert!(post("/api/send_message", &[("origin", "https://app.example.org")]));
+        assert!(post("/api/send_message", &[("sec-fetch-site", "same-origin")]));
+        assert!(post("/api/send_message", &[("sec-fetch-site", "none")]));
+    }
+
+    #[test]
+    fn allows_requests_not_from_a_page() {
+        assert!(post("/api/send_message", &[]));
+    }
+
+    #[test]
+    fn allows_reads_and_non_api_paths() {
+        let trusted = TrustedOrigins::default();
+        let cross_site = headers(&[("origin", "https://evil.example.net")]);
+        assert!(allowed(&trusted, &Method::GET, "/api/attachments/1", &cross_site));
+        assert!(allowed(&trusted, &Method::POST, "/login", &cross_site));
+    }
+}
@@ -1,304 +1,3849 @@
-use sqlx::{sqlite::SqlitePool, Row};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    postgres::{PgPool, PgPoolOptions, PgRow},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow},
+    Executor, FromRow, Row,
+};
 use anyhow::Result;
+use serde::Serialize;
+use std::{str::FromStr, time::{Duration, Instant}};
 use crate::models::*;
 
+// The backend is chosen from the DATABASE_URL scheme. Queries are written once with
+// `$N` placeholders, which both SQLite and Postgres accept, and run through `on_pool!`.
+#[derive(Clone)]
+pub enum DbPool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+// Expands `$body` once per backend with `$pool` bound to the concrete pool, so each
+// copy is type-checked against its own driver
+macro_rules! on_pool {
+    ($db_pool:expr, $pool:ident => $body:expr) => {
+        match $db_pool {
+            $crate::database::DbPool::Sqlite($pool) => $body,
+            $crate::database::DbPool::Postgres($pool) => $body,
+        }
+    };
+}
+pub(crate) use on_pool;
+
+// Implements FromRow for both backends from a single column mapping
+macro_rules! impl_from_row {
+    ($ty:ty, |$row:ident| $body:expr) => {
+        impl<'r> FromRow<'r, SqliteRow> for $ty {
+            fn from_row($row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+                Ok($body)
+            }
+        }
+
+        impl<'r> FromRow<'r, PgRow> for $ty {
+            fn from_row($row: &'r PgRow) -> Result<Self, sqlx::Error> {
+                Ok($body)
+            }
+        }
+    };
+}
+
+impl_from_row!(User, |r| User {
+    id: r.try_get("id")?,
+    name: r.try_get("name")?,
+    email: r.try_get("email")?,
+    org_id: r.try_get("org_id")?,
+    role: Role::from(r.try_get::<String, _>("role")?),
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(ApiToken, |r| ApiToken {
+    id: r.try_get("id")?,
+    name: r.try_get("name")?,
+    token_prefix: r.try_get("token_prefix")?,
+    scope: TokenScope::from(r.try_get::<String, _>("scope")?),
+    last_used_at: r.try_get("last_used_at")?,
+    expires_at: r.try_get("expires_at")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(Webhook, |r| Webhook {
+    id: r.try_get("id")?,
+    url: r.try_get("url")?,
+    events: r.try_get::<String, _>("events")?.split(',').filter_map(WebhookEvent::parse).collect(),
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(WebhookDelivery, |r| WebhookDelivery {
+    id: r.try_get("id")?,
+    webhook_id: r.try_get("webhook_id")?,
+    event: r.try_get("event")?,
+    payload: r.try_get("payload")?,
+    status: DeliveryStatus::from(r.try_get::<String, _>("status")?),
+    attempts: r.try_get("attempts")?,
+    response_status: r.try_get("response_status")?,
+    error: r.try_get("error")?,
+    next_attempt_at: r.try_get("next_attempt_at")?,
+    created_at: r.try_get("created_at")?,
+    delivered_at: r.try_get("delivered_at")?,
+});
+
+impl_from_row!(BatchJob, |r| BatchJob {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    model_provider: r.try_get("model_provider")?,
+    model_name: r.try_get("model_name")?,
+    concurrency: r.try_get("concurrency")?,
+    status: JobStatus::from(r.try_get::<String, _>("status")?),
+    error: r.try_get("error")?,
+    total: r.try_get("total")?,
+    completed: r.try_get("completed")?,
+    failed: r.try_get("failed")?,
+    created_at: r.try_get("created_at")?,
+    finished_at: r.try_get("finished_at")?,
+});
+
+impl_from_row!(Schedule, |r| Schedule {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    session_id: r.try_get("session_id")?,
+    prompt: r.try_get("prompt")?,
+    fetch_url: r.try_get("fetch_url")?,
+    interval_minutes: r.try_get("interval_minutes")?,
+    next_run_at: r.try_get("next_run_at")?,
+    notify: r.try_get("notify")?,
+    enabled: r.try_get("enabled")?,
+    last_run_at: r.try_get("last_run_at")?,
+    last_error: r.try_get("last_error")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(Rule, |r| Rule {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    name: r.try_get("name")?,
+    trigger: RuleTrigger::parse(&r.try_get::<String, _>("trigger_event")?)
+        .ok_or_else(|| sqlx::Error::Decode("Unknown rule trigger".into()))?,
+    condition: serde_json::from_str(&r.try_get::<String, _>("condition_json")?).unwrap_or_default(),
+    action: serde_json::from_str(&r.try_get::<String, _>("action_json")?)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+    enabled: r.try_get("enabled")?,
+    last_fired_at: r.try_get("last_fired_at")?,
+    last_error: r.try_get("last_error")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(BatchItem, |r| BatchItem {
+    job_id: r.try_get("job_id")?,
+    line: r.try_get("line")?,
+    custom_id: r.try_get("custom_id")?,
+    prompt: r.try_get("prompt")?,
+    system_prompt: r.try_get("system_prompt")?,
+    status: JobStatus::from(r.try_get::<String, _>("status")?),
+    output: r.try_get("output")?,
+    error: r.try_get("error")?,
+    tokens_used: r.try_get("tokens_used")?,
+});
+
+// `current` is filled in by the caller, which knows the request's session
+impl_from_row!(LoginSession, |r| LoginSession {
+    id: r.try_get("id")?,
+    user_agent: r.try_get("user_agent")?,
+    ip_address: r.try_get("ip_address")?,
+    created_at: r.try_get("created_at")?,
+    last_seen_at: r.try_get("last_seen_at")?,
+    expires_at: r.try_get("expires_at")?,
+    current: false,
+});
+
+impl_from_row!(AdminUser, |r| AdminUser {
+    id: r.try_get("id")?,
+    name: r.try_get("name")?,
+    email: r.try_get("email")?,
+    org_id: r.try_get("org_id")?,
+    role: Role::from(r.try_get::<String, _>("role")?),
+    has_password: r.try_get("has_password")?,
+    session_count: r.try_get("session_count")?,
+    last_active_at: r.try_get("last_active_at")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(StoredApiKey, |r| StoredApiKey {
+    provider: r.try_get("provider")?,
+    fingerprint: r.try_get("fingerprint")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(ChatSession, |r| ChatSession {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    title: r.try_get("title")?,
+    model_provider: r.try_get("model_provider")?,
+    model_name: r.try_get("model_name")?,
+    incognito: r.try_get("incognito")?,
+    archived: r.try_get("archived")?,
+    folder_id: r.try_get("folder_id")?,
+    pinned: r.try_get("pinned")?,
+    parent_session_id: r.try_get("parent_session_id")?,
+    branch_message_id: r.try_get("branch_message_id")?,
+    last_message_preview: r.try_get("last_message_preview")?,
+    org_id: r.try_get("org_id")?,
+    settings: r.try_get::<Option<String>, _>("settings")?
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default(),
+    deleted_at: r.try_get("deleted_at")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(Organization, |r| Organization {
+    id: r.try_get("id")?,
+    name: r.try_get("name")?,
+    settings: serde_json::from_str(&r.try_get::<String, _>("settings")?).unwrap_or_default(),
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(Tag, |r| Tag {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    name: r.try_get("name")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(RetentionPolicy, |r| RetentionPolicy {
+    message_retention_days: r.try_get("message_retention_days")?,
+    max_sessions: r.try_get("max_sessions")?,
+});
+
+impl_from_row!(AuditEntry, |r| AuditEntry {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    action: r.try_get("action")?,
+    target_id: r.try_get("target_id")?,
+    details: r.try_get::<Option<String>, _>("details")?
+        .and_then(|d| serde_json::from_str(&d).ok()),
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(ModerationEvent, |r| ModerationEvent {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    session_id: r.try_get("session_id")?,
+    direction: r.try_get("direction")?,
+    action: r.try_get("action")?,
+    categories: serde_json::from_str(&r.try_get::<String, _>("categories")?).unwrap_or_default(),
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(DailyUsage, |r| DailyUsage {
+    day: r.try_get("day")?,
+    user_id: r.try_get("user_id")?,
+    model_provider: r.try_get("model_provider")?,
+    model_name: r.try_get("model_name")?,
+    user_messages: r.try_get("user_messages")?,
+    assistant_messages: r.try_get("assistant_messages")?,
+    tokens: r.try_get("tokens")?,
+    estimated_cost: r.try_get("estimated_cost")?,
+});
+
+impl_from_row!(SessionFolder, |r| SessionFolder {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    name: r.try_get("name")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(Message, |r| Message {
+    id: r.try_get("id")?,
+    session_id: r.try_get("session_id")?,
+    role: MessageRole::from(r.try_get::<String, _>("role")?),
+    content: r.try_get("content")?,
+    reasoning: r.try_get("reasoning")?,
+    model_provider: r.try_get("model_provider")?,
+    model_name: r.try_get("model_name")?,
+    tokens_used: r.try_get("tokens_used")?,
+    citations: r.try_get::<Option<String>, _>("citations")?
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default(),
+    deleted_at: r.try_get("deleted_at")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(UserMemory, |r| UserMemory {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    memory_key: r.try_get("memory_key")?,
+    memory_value: r.try_get("memory_value")?,
+    confidence: r.try_get("confidence")?,
+    version: r.try_get("version")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(MemoryHistoryEntry, |r| MemoryHistoryEntry {
+    id: r.try_get("id")?,
+    memory_key: r.try_get("memory_key")?,
+    memory_value: r.try_get("memory_value")?,
+    confidence: r.try_get("confidence")?,
+    recorded_at: r.try_get("recorded_at")?,
+    superseded_at: r.try_get("superseded_at")?,
+});
+
+impl_from_row!(FileAttachment, |r| FileAttachment {
+    id: r.try_get("id")?,
+    message_id: r.try_get("message_id")?,
+    file_name: r.try_get("file_name")?,
+    file_path: r.try_get("file_path")?,
+    file_type: r.try_get("file_type")?,
+    file_size: r.try_get("file_size")?,
+    content_hash: r.try_get("content_hash")?,
+    created_at: r.try_get("created_at")?,
+    transcript: r.try_get("transcript")?,
+    url: None,
+});
+
+impl_from_row!(SuggestedQuestion, |r| SuggestedQuestion {
+    id: r.try_get("id")?,
+    session_id: r.try_get("session_id")?,
+    question: r.try_get("question")?,
+    relevance_score: r.try_get("relevance_score")?,
+    used: r.try_get("used")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(KnowledgeBase, |r| KnowledgeBase {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    org_id: r.try_get("org_id")?,
+    name: r.try_get("name")?,
+    description: r.try_get("description")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(KnowledgeDocument, |r| KnowledgeDocument {
+    id: r.try_get("id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    source_type: r.try_get("source_type")?,
+    source_uri: r.try_get("source_uri")?,
+    title: r.try_get("title")?,
+    content_hash: r.try_get("content_hash")?,
+    chunk_count: r.try_get("chunk_count")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(KnowledgeChunk, |r| KnowledgeChunk {
+    id: r.try_get("id")?,
+    document_id: r.try_get("document_id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    chunk_index: r.try_get("chunk_index")?,
+    content: r.try_get("content")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(IngestionJob, |r| IngestionJob {
+    id: r.try_get("id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    source_type: r.try_get("source_type")?,
+    source_uri: r.try_get("source_uri")?,
+    status: JobStatus::from(r.try_get::<String, _>("status")?),
+    documents_processed: r.try_get("documents_processed")?,
+    error: r.try_get("error")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(CrawlSource, |r| CrawlSource {
+    id: r.try_get("id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    start_url: r.try_get("start_url")?,
+    max_depth: r.try_get("max_depth")?,
+    max_pages: r.try_get("max_pages")?,
+    same_domain_only: r.try_get("same_domain_only")?,
+    sync_interval_minutes: r.try_get("sync_interval_minutes")?,
+    last_synced_at: r.try_get("last_synced_at")?,
+    last_sync_status: r.try_get("last_sync_status")?,
+    last_sync_error: r.try_get("last_sync_error")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(FeedSource, |r| FeedSource {
+    id: r.try_get("id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    url: r.try_get("url")?,
+    title: r.try_get("title")?,
+    sync_interval_minutes: r.try_get("sync_interval_minutes")?,
+    digest_model_provider: r.try_get("digest_model_provider")?,
+    digest_model_name: r.try_get("digest_model_name")?,
+    last_digest_at: r.try_get("last_digest_at")?,
+    last_synced_at: r.try_get("last_synced_at")?,
+    last_sync_status: r.try_get("last_sync_status")?,
+    last_sync_error: r.try_get("last_sync_error")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+impl_from_row!(FeedItem, |r| FeedItem {
+    feed_source_id: r.try_get("feed_source_id")?,
+    guid: r.try_get("guid")?,
+    title: r.try_get("title")?,
+    link: r.try_get("link")?,
+    summary: r.try_get("summary")?,
+    published_at: r.try_get("published_at")?,
+    created_at: r.try_get("created_at")?,
+});
+
+impl_from_row!(Connector, |r| Connector {
+    id: r.try_get("id")?,
+    user_id: r.try_get("user_id")?,
+    knowledge_base_id: r.try_get("knowledge_base_id")?,
+    provider: ConnectorProvider::from(r.try_get::<String, _>("provider")?),
+    resource_ids: serde_json::from_str(&r.try_get::<String, _>("resource_ids")?).unwrap_or_default(),
+    sync_interval_minutes: r.try_get("sync_interval_minutes")?,
+    connected: r.try_get::<Option<String>, _>("access_token")?.is_some(),
+    last_synced_at: r.try_get("last_synced_at")?,
+    last_sync_status: r.try_get("last_sync_status")?,
+    last_sync_error: r.try_get("last_sync_error")?,
+    created_at: r.try_get("created_at")?,
+    updated_at: r.try_get("updated_at")?,
+});
+
+// INSERT statements shared by the single-row methods and `save_exchange`, which runs
+// them inside a transaction; each expands to the query with every value bound
+macro_rules! insert_message {
+    ($message:expr, $citations:expr) => {
+        sqlx::query("INSERT INTO messages (id, session_id, role, content, reasoning, model_provider, model_name, tokens_used, citations, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)")
+            .bind(&$message.id)
+            .bind(&$message.session_id)
+            .bind($message.role.to_string())
+            .bind(&$message.content)
+            .bind(&$message.reasoning)
+            .bind(&$message.model_provider)
+            .bind(&$message.model_name)
+            .bind($message.tokens_used)
+            .bind($citations)
+            .bind($message.created_at)
+    };
+}
+
+macro_rules! insert_attachment {
+    ($attachment:expr) => {
+        sqlx::query("INSERT INTO file_attachments (id, message_id, file_name, file_path, file_type, file_size, content_hash, created_at, transcript) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+            .bind(&$attachment.id)
+            .bind(&$attachment.message_id)
+            .bind(&$attachment.file_name)
+            .bind(&$attachment.file_path)
+            .bind(&$attachment.file_type)
+            .bind($attachment.file_size)
+            .bind(&$attachment.content_hash)
+            .bind($attachment.created_at)
+            .bind(&$attachment.transcript)
+    };
+}
+
+macro_rules! insert_suggested_question {
+    ($question:expr) => {
+        sqlx::query("INSERT INTO suggested_questions (id, session_id, question, relevance_score, used, created_at) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(&$question.id)
+            .bind(&$question.session_id)
+            .bind(&$question.question)
+            .bind($question.relevance_score)
+            .bind($question.used)
+            .bind($question.created_at)
+    };
+}
+
+pub(crate) fn memory_conflict(memory_key: &str) -> anyhow::Error {
+    anyhow::anyhow!("Memory '{}' was changed elsewhere; reload it and try again", memory_key)
+}
+
+fn citations_json(message: &Message) -> Result<Option<String>> {
+    Ok(if message.citations.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&message.citations)?)
+    })
+}
+
+// Records only their owner may see or change. Messages, attachments and suggested
+// questions belong to the owner of their session; documents, crawl and feed sources and
+// ingestion jobs to the owner of their knowledge base.
+#[derive(Debug, Clone, Copy)]
+pub enum OwnedResource {
+    Session,
+    Message,
+    Attachment,
+    SuggestedQuestion,
+    Folder,
+    Tag,
+    KnowledgeBase,
+    KnowledgeDocument,
+    CrawlSource,
+    FeedSource,
+    IngestionJob,
+    Connector,
+    Schedule,
+    Rule,
+}
+
+impl OwnedResource {
+    // Selects the user_id owning the record with id $1
+    fn owner_sql(self) -> &'static str {
+        match self {
+            OwnedResource::Session => "SELECT user_id FROM chat_sessions WHERE id = $1",
+            OwnedResource::Message => "SELECT s.user_id FROM messages m JOIN chat_sessions s ON s.id = m.session_id WHERE m.id = $1",
+            OwnedResource::Attachment => "SELECT s.user_id FROM file_attachments a JOIN messages m ON m.id = a.message_id JOIN chat_sessions s ON s.id = m.session_id WHERE a.id = $1",
+            OwnedResource::SuggestedQuestion => "SELECT s.user_id FROM suggested_questions q JOIN chat_sessions s ON s.id = q.session_id WHERE q.id = $1",
+            OwnedResource::Folder => "SELECT user_id FROM session_folders WHERE id = $1",
+            OwnedResource::Tag => "SELECT user_id FROM tags WHERE id = $1",
+            OwnedResource::KnowledgeBase => "SELECT user_id FROM knowledge_bases WHERE id = $1",
+            OwnedResource::KnowledgeDocument => "SELECT kb.user_id FROM kb_documents d JOIN knowledge_bases kb ON kb.id = d.knowledge_base_id WHERE d.id = $1",
+            OwnedResource::CrawlSource => "SELECT kb.user_id FROM crawl_sources c JOIN knowledge_bases kb ON kb.id = c.knowledge_base_id WHERE c.id = $1",
+            OwnedResource::FeedSource => "SELECT kb.user_id FROM feed_sources f JOIN knowledge_bases kb ON kb.id = f.knowledge_base_id WHERE f.id = $1",
+            OwnedResource::IngestionJob => "SELECT kb.user_id FROM ingestion_jobs j JOIN knowledge_bases kb ON kb.id = j.knowledge_base_id WHERE j.id = $1",
+            OwnedResource::Connector => "SELECT user_id FROM connectors WHERE id = $1",
+            OwnedResource::Schedule => "SELECT user_id FROM schedules WHERE id = $1",
+            OwnedResource::Rule => "SELECT user_id FROM rules WHERE id = $1",
+        }
+    }
+}
+
+impl std::fmt::Display for OwnedResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnedResource::Session => write!(f, "Session"),
+            OwnedResource::Message => write!(f, "Message"),
+            OwnedResource::Attachment => write!(f, "Attachment"),
+            OwnedResource::SuggestedQuestion => write!(f, "Suggested question"),
+            OwnedResource::Folder => write!(f, "Folder"),
+            OwnedResource::Tag => write!(f, "Tag"),
+            OwnedResource::KnowledgeBase => write!(f, "Knowledge base"),
+            OwnedResource::KnowledgeDocument => write!(f, "Document"),
+            OwnedResource::CrawlSource => write!(f, "Crawl source"),
+            OwnedResource::FeedSource => write!(f, "Feed source"),
+            OwnedResource::IngestionJob => write!(f, "Ingestion job"),
+            OwnedResource::Connector => write!(f, "Connector"),
+            OwnedResource::Schedule => write!(f, "Schedule"),
+            OwnedResource::Rule => write!(f, "Rule"),
+        }
+    }
+}
+
+// Everything one chat turn writes, saved together by `save_exchange`
+pub struct ChatExchange {
+    pub user_message: Message,
+    pub attachments: Vec<FileAttachment>,
+    pub ai_message: Message,
+    pub suggested_questions: Vec<SuggestedQuestion>,
+    // Memories the user restated, with their boosted confidence
+    pub reinforced_memories: Vec<(UserMemory, f64)>,
+}
+
+const SESSION_COLUMNS: &str = "id, user_id, title, model_provider, model_name, incognito, archived, folder_id, pinned, parent_session_id, branch_message_id, last_message_preview, org_id, settings, deleted_at, created_at, updated_at";
+const MESSAGE_COLUMNS: &str = "id, session_id, role, content, reasoning, model_provider, model_name, tokens_used, citations, deleted_at, created_at";
+const MEMORY_COLUMNS: &str = "id, user_id, memory_key, memory_value, confidence, version, created_at, updated_at";
+const ATTACHMENT_COLUMNS: &str = "id, message_id, file_name, file_path, file_type, file_size, content_hash, created_at, transcript";
+const KB_COLUMNS: &str = "id, user_id, org_id, name, description, created_at, updated_at";
+const ORG_COLUMNS: &str = "id, name, settings, created_at, updated_at";
+const DOCUMENT_COLUMNS: &str = "id, knowledge_base_id, source_type, source_uri, title, content_hash, chunk_count, created_at";
+const JOB_COLUMNS: &str = "id, knowledge_base_id, source_type, source_uri, status, documents_processed, error, created_at, updated_at";
+const CRAWL_SOURCE_COLUMNS: &str = "id, knowledge_base_id, start_url, max_depth, max_pages, same_domain_only, sync_interval_minutes, last_synced_at, last_sync_status, last_sync_error, created_at, updated_at";
+// A batch job with how many of its prompts there are, and how many have run
+const BATCH_JOB_SELECT: &str = "SELECT j.id, j.user_id, j.model_provider, j.model_name, j.concurrency, j.status, j.error, j.created_at, j.finished_at,
+    (SELECT COUNT(*) FROM batch_items i WHERE i.job_id = j.id) AS total,
+    (SELECT COUNT(*) FROM batch_items i WHERE i.job_id = j.id AND i.status = 'completed') AS completed,
+    (SELECT COUNT(*) FROM batch_items i WHERE i.job_id = j.id AND i.status = 'failed') AS failed
+    FROM batch_jobs j";
+const FEED_SOURCE_COLUMNS: &str = "id, knowledge_base_id, url, title, sync_interval_minutes, digest_model_provider, digest_model_name, last_digest_at, last_synced_at, last_sync_status, last_sync_error, created_at, updated_at";
+const FEED_ITEM_COLUMNS: &str = "feed_source_id, guid, title, link, summary, published_at, created_at";
+const SCHEDULE_COLUMNS: &str = "id, user_id, session_id, prompt, fetch_url, interval_minutes, next_run_at, notify, enabled, last_run_at, last_error, created_at";
+const RULE_COLUMNS: &str = "id, user_id, name, trigger_event, condition_json, action_json, enabled, last_fired_at, last_error, created_at";
+const CONNECTOR_COLUMNS: &str = "id, user_id, knowledge_base_id, provider, resource_ids, sync_interval_minutes, access_token, last_synced_at, last_sync_status, last_sync_error, created_at, updated_at";
+
+// An ALTER TABLE adding a column that is already there. Postgres reports this with its own
+// error code; SQLite only in the message.
+fn is_duplicate_column(error: &sqlx::Error) -> bool {
+    error.as_database_error().is_some_and(|e| {
+        e.code().as_deref() == Some("42701") || e.message().contains("duplicate column name")
+    })
+}
+
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+// The newest migration from before applied migrations were recorded
+const LAST_UNTRACKED_MIGRATION: i64 = 13;
+
+// Quotes a passphrase for `PRAGMA key`
+pub fn sqlite_key_literal(key: &str) -> String {
+    format!("'{}'", key.replace('\'', "''"))
+}
+
+// A plain SQLite build silently ignores `PRAGMA key`, which would leave the database unencrypted
+async fn check_sqlcipher(pool: &SqlitePool) -> Result<()> {
+    let cipher_version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(pool)
+        .await?;
+    if cipher_version.is_none() {
+        return Err(anyhow::anyhow!("A SQLCipher key is set but this build has no SQLCipher support; enable the `sqlcipher` feature"));
+    }
+
+    sqlx::query("SELECT count(*) FROM sqlite_master")
+        .execute(pool)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to open the encrypted database, check the key: {}", e))?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    // How long a query waits for a free connection before failing
+    pub acquire_timeout_secs: u64,
+    // The SQLite settings below are ignored on Postgres.
+    // WAL lets readers run alongside the writer while a reply is being saved
+    pub sqlite_wal: bool,
+    // How long a SQLite write waits on a lock before failing with "database is locked"
+    pub sqlite_busy_timeout_ms: u64,
+    pub sqlite_foreign_keys: bool,
+    // Encrypts the SQLite file with SQLCipher; needs a build with the `sqlcipher` feature
+    pub sqlite_key: Option<String>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            acquire_timeout_secs: 30,
+            sqlite_wal: true,
+            sqlite_busy_timeout_ms: 5_000,
+            sqlite_foreign_keys: true,
+            sqlite_key: None,
+        }
+    }
+}
+
+// Connections in the pool right now; `in_use` near `max_connections` means queries
+// are queueing for a connection
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub max_connections: u32,
+    pub size: u32,
+    pub idle: u32,
+    pub in_use: u32,
+}
+
+// Result of a health probe: one connection acquired from the pool and one trivial query
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseHealth {
+    pub backend: &'static str,
+    pub healthy: bool,
+    pub error: Option<String>,
+    // How long the probe waited for a connection; grows with SQLite lock contention
+    // or an exhausted Postgres pool
+    pub acquire_wait_ms: f64,
+    pub query_ms: f64,
+    pub pool: PoolStats,
+}
+
+#[derive(Clone)]
 pub struct Database {
-    pool: SqlitePool,
+    pool: DbPool,
+    url: String,
+    sqlite_key: Option<String>,
 }
 
 impl Database {
-    pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(database_url).await?;
-        Self::run_migrations(&pool).await?;
-        Ok(Self { pool })
+    pub async fn new(database_url: &str, config: &DatabaseConfig) -> Result<Self> {
+        let acquire_timeout = Duration::from_secs(config.acquire_timeout_secs);
+        let pool = if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            DbPool::Postgres(
+                PgPoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .acquire_timeout(acquire_timeout)
+                    .connect(database_url)
+                    .await?,
+            )
+        } else {
+            // sqlite-vec has to be registered before the first connection is opened
+            crate::vector_store::register_sqlite_vec();
+            let mut options = SqliteConnectOptions::from_str(database_url)?
+                .journal_mode(if config.sqlite_wal { SqliteJournalMode::Wal } else { SqliteJournalMode::Delete })
+                .busy_timeout(Duration::from_millis(config.sqlite_busy_timeout_ms))
+                .foreign_keys(config.sqlite_foreign_keys);
+            // sqlx always sends the key pragma first, before anything reads the file
+            if let Some(key) = &config.sqlite_key {
+                options = options.pragma("key", sqlite_key_literal(key));
+            }
+            let pool = SqlitePoolOptions::new()
+                .max_connections(config.max_connections)
+                .acquire_timeout(acquire_timeout)
+                .connect_with(options)
+                .await?;
+            if config.sqlite_key.is_some() {
+                check_sqlcipher(&pool).await?;
+            }
+            DbPool::Sqlite(pool)
+        };
+
+        let db = Self { pool, url: database_url.to_string(), sqlite_key: config.sqlite_key.clone() };
+        db.run_migrations().await?;
+        Ok(db)
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        match &self.pool {
+            DbPool::Sqlite(_) => &SQLITE_MIGRATIONS,
+            DbPool::Postgres(_) => &POSTGRES_MIGRATIONS,
+        }
+    }
+
+    // Migrations are embedded at compile time and tracked, with checksums, in _sqlx_migrations.
+    // Also re-run after a restore, which may bring back an older schema.
+    pub async fn run_migrations(&self) -> Result<()> {
+        let migrator = self.migrator();
+
+        on_pool!(&self.pool, pool => {
+            // Databases from before sqlx migrations re-ran every migration on each start and
+            // kept no record of them. Bring one up to date the old way, then record those
+            // migrations as applied so their ALTER TABLEs aren't run a second time.
+            let mut conn = pool.acquire().await?;
+            let existing = sqlx::query("SELECT 1 FROM chat_sessions LIMIT 1").fetch_optional(&mut *conn).await.is_ok();
+            let tracked = sqlx::query("SELECT 1 FROM _sqlx_migrations LIMIT 1").fetch_optional(&mut *conn).await.is_ok();
+            if existing && !tracked {
+                conn.ensure_migrations_table().await?;
+                for migration in migrator.iter().filter(|migration| migration.version <= LAST_UNTRACKED_MIGRATION) {
+                    match (&mut *conn).execute(&*migration.sql).await {
+                        Err(e) if !is_duplicate_column(&e) => return Err(e.into()),
+                        _ => {}
+                    }
+                    sqlx::query("INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES ($1, $2, TRUE, $3, 0)")
+                        .bind(migration.version)
+                        .bind(&*migration.description)
+                        .bind(&*migration.checksum)
+                        .execute(&mut *conn)
+                        .await?;
+                }
+            }
+            drop(conn);
+
+            migrator.run(pool).await?;
+        });
+        Ok(())
     }
 
-    async fn run_migrations(pool: &SqlitePool) -> Result<()> {
-        let migration_sql = include_str!("../migrations/001_create_tables.sql");
-        sqlx::query(migration_sql).execute(pool).await?;
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        match &self.pool {
+            DbPool::Sqlite(_) => "sqlite",
+            DbPool::Postgres(_) => "postgres",
+        }
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        on_pool!(&self.pool, pool => {
+            let size = pool.size();
+            let idle = pool.num_idle() as u32;
+            PoolStats {
+                max_connections: pool.options().get_max_connections(),
+                size,
+                idle,
+                in_use: size.saturating_sub(idle),
+            }
+        })
+    }
+
+    // Acquires a connection and runs `SELECT 1`, timing both. Failures are reported in
+    // the result rather than returned, so callers always get the pool figures.
+    pub async fn health(&self) -> DatabaseHealth {
+        let started = Instant::now();
+        let mut acquire_wait_ms = 0.0;
+        let probe: Result<()> = on_pool!(&self.pool, pool => {
+            async {
+                let mut conn = pool.acquire().await?;
+                acquire_wait_ms = started.elapsed().as_secs_f64() * 1000.0;
+                sqlx::query("SELECT 1").execute(&mut *conn).await?;
+                Ok::<(), anyhow::Error>(())
+            }.await
+        });
+        let query_ms = (started.elapsed().as_secs_f64() * 1000.0 - acquire_wait_ms).max(0.0);
+
+        DatabaseHealth {
+            backend: self.backend_name(),
+            healthy: probe.is_ok(),
+            error: probe.err().map(|e| e.to_string()),
+            acquire_wait_ms,
+            query_ms,
+            pool: self.pool_stats(),
+        }
+    }
+
+    // Waits for queries in progress and closes every connection. SQLite first folds the
+    // write-ahead log back into the database file, so the file is complete on its own.
+    pub async fn close(&self) -> Result<()> {
+        if let DbPool::Sqlite(pool) = &self.pool {
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(pool).await?;
+        }
+        on_pool!(&self.pool, pool => pool.close().await);
+        Ok(())
+    }
+
+    // The SQLCipher key, for opening the database file outside the pool
+    pub fn sqlite_key(&self) -> Option<&str> {
+        self.sqlite_key.as_deref()
+    }
+
+    // Newest migration applied to this database
+    pub async fn schema_version(&self) -> Result<i64> {
+        let version: Option<i64> = on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = TRUE")
+                .fetch_one(pool)
+                .await?
+        });
+        Ok(version.unwrap_or(0))
+    }
+
+    // Newest migration this build knows about
+    pub fn latest_schema_version(&self) -> i64 {
+        self.migrator().iter().map(|migration| migration.version).max().unwrap_or(0)
+    }
+
+    // Path of the SQLite database file; None on Postgres or for in-memory databases
+    pub fn sqlite_path(&self) -> Option<std::path::PathBuf> {
+        match &self.pool {
+            DbPool::Sqlite(pool) => {
+                let path = pool.connect_options().get_filename().to_path_buf();
+                (path.as_os_str() != ":memory:").then_some(path)
+            }
+            DbPool::Postgres(_) => None,
+        }
+    }
+
+    // Writes a consistent, compacted copy of a SQLite database to `path`, which must not exist yet
+    pub async fn vacuum_into(&self, path: &str) -> Result<()> {
+        match &self.pool {
+            DbPool::Sqlite(pool) => {
+                sqlx::query("VACUUM INTO $1").bind(path).execute(pool).await?;
+                Ok(())
+            }
+            DbPool::Postgres(_) => Err(anyhow::anyhow!("VACUUM INTO is only available on SQLite")),
+        }
+    }
+
+    // Re-reads every message into the SQLite keyword index. The index is keyed on the rowids of
+    // `messages`, which has no INTEGER PRIMARY KEY to pin them, so VACUUM INTO may renumber
+    // them in a snapshot; a restored database needs its index rebuilt. Postgres indexes the
+    // content itself, so there is nothing to do there.
+    pub async fn rebuild_search_index(&self) -> Result<()> {
+        if let DbPool::Sqlite(pool) = &self.pool {
+            sqlx::query("INSERT INTO messages_fts(messages_fts) VALUES ('rebuild')").execute(pool).await?;
+        }
         Ok(())
     }
 
+    // True if `user_id` owns the record; false for records that don't exist
+    pub async fn is_owner(&self, resource: OwnedResource, id: &str, user_id: &str) -> Result<bool> {
+        let owner: Option<String> = on_pool!(&self.pool, pool => {
+            sqlx::query_scalar(resource.owner_sql()).bind(id).fetch_optional(pool).await?
+        });
+        Ok(owner.as_deref() == Some(user_id))
+    }
+
     // User operations
     pub async fn create_user(&self, user: &User) -> Result<()> {
-        sqlx::query!(
-            "INSERT INTO users (id, name, email, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
-            user.id,
-            user.name,
-            user.email,
-            user.created_at,
-            user.updated_at
-        )
-        .execute(&self.pool)
-        .await?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO users (id, name, email, org_id, role, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                .bind(&user.id)
+                .bind(&user.name)
+                .bind(&user.email)
+                .bind(user.org_id.as_deref().unwrap_or(DEFAULT_ORG_ID))
+                .bind(user.role.to_string())
+                .bind(user.created_at)
+                .bind(user.updated_at)
+                .execute(pool)
+                .await?;
+        });
         Ok(())
     }
 
     pub async fn get_user(&self, user_id: &str) -> Result<Option<User>> {
-        let row = sqlx::query!(
-            "SELECT id, name, email, created_at, updated_at FROM users WHERE id = ?",
-            user_id
-        )
-        .fetch_optional(&self.pool)
-        .await?;
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, name, email, org_id, role, created_at, updated_at FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
 
-        Ok(row.map(|r| User {
-            id: r.id,
-            name: r.name,
-            email: r.email,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
+    // Emails are compared case-insensitively
+    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, name, email, org_id, role, created_at, updated_at FROM users WHERE LOWER(email) = LOWER($1)")
+                .bind(email)
+                .fetch_optional(pool)
+                .await?
         }))
     }
 
-    // Chat session operations
-    pub async fn create_session(&self, session: &ChatSession) -> Result<()> {
-        sqlx::query!(
-            "INSERT INTO chat_sessions (id, user_id, title, model_provider, model_name, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
-            session.id,
-            session.user_id,
-            session.title,
-            session.model_provider,
-            session.model_name,
-            session.created_at,
-            session.updated_at
-        )
-        .execute(&self.pool)
-        .await?;
+    pub async fn get_password_hash(&self, user_id: &str) -> Result<Option<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar::<_, Option<String>>("SELECT password_hash FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+                .flatten()
+        }))
+    }
+
+    pub async fn set_password_hash(&self, user_id: &str, password_hash: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET password_hash = $1, updated_at = $2 WHERE id = $3")
+                .bind(password_hash)
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+        });
         Ok(())
     }
 
-    pub async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<ChatSession>> {
-        let rows = sqlx::query!(
-            "SELECT id, user_id, title, model_provider, model_name, created_at, updated_at FROM chat_sessions WHERE user_id = ? ORDER BY updated_at DESC",
-            user_id
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    pub async fn count_password_users(&self) -> Result<i64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE password_hash IS NOT NULL")
+                .fetch_one(pool)
+                .await?
+        }))
+    }
 
-        Ok(rows
-            .into_iter()
-            .map(|r| ChatSession {
-                id: r.id,
-                user_id: r.user_id,
-                title: r.title,
-                model_provider: r.model_provider,
-                model_name: r.model_name,
-                created_at: r.created_at,
-                updated_at: r.updated_at,
-            })
-            .collect())
+    // Gives an existing user the profile and password of a new account
+    pub async fn claim_user(&self, user_id: &str, name: Option<&str>, email: &str, password_hash: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET name = COALESCE($1, name), email = $2, password_hash = $3, updated_at = $4 WHERE id = $5")
+                .bind(name)
+                .bind(email)
+                .bind(password_hash)
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
     }
 
-    pub async fn get_session(&self, session_id: &str) -> Result<Option<ChatSession>> {
-        let row = sqlx::query!(
-            "SELECT id, user_id, title, model_provider, model_name, created_at, updated_at FROM chat_sessions WHERE id = ?",
-            session_id
-        )
-        .fetch_optional(&self.pool)
-        .await?;
+    // Every user with their session count and last activity, for the admin panel
+    pub async fn list_admin_users(&self) -> Result<Vec<AdminUser>> {
+        let sql = "SELECT u.id, u.name, u.email, u.org_id, u.role, u.password_hash IS NOT NULL AS has_password,
+                    COUNT(s.id) AS session_count, MAX(s.updated_at) AS last_active_at, u.created_at
+             FROM users u
+             LEFT JOIN chat_sessions s ON s.user_id = u.id AND s.deleted_at IS NULL
+             GROUP BY u.id, u.name, u.email, u.org_id, u.role, u.password_hash, u.created_at
+             ORDER BY u.created_at ASC";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn set_user_role(&self, user_id: &str, role: Role) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET role = $1, updated_at = $2 WHERE id = $3")
+                .bind(role.to_string())
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
 
-        Ok(row.map(|r| ChatSession {
-            id: r.id,
-            user_id: r.user_id,
-            title: r.title,
-            model_provider: r.model_provider,
-            model_name: r.model_name,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
+    pub async fn count_admins(&self) -> Result<i64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin'")
+                .fetch_one(pool)
+                .await?
         }))
     }
 
-    // Message operations
-    pub async fn create_message(&self, message: &Message) -> Result<()> {
-        sqlx::query!(
-            "INSERT INTO messages (id, session_id, role, content, reasoning, model_provider, model_name, tokens_used, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            message.id,
-            message.session_id,
-            message.role.to_string(),
-            message.content,
-            message.reasoning,
-            message.model_provider,
-            message.model_name,
-            message.tokens_used,
-            message.created_at
-        )
-        .execute(&self.pool)
-        .await?;
+    // Anonymous guests (see `guests`) created before `created_before` with no login session
+    // left. Everything they own goes with them.
+    pub async fn delete_abandoned_guests(&self, created_before: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "DELETE FROM users WHERE role = 'guest' AND email IS NULL AND password_hash IS NULL AND created_at < $1
+                 AND NOT EXISTS (SELECT 1 FROM auth_sessions a WHERE a.user_id = users.id)",
+            )
+            .bind(created_before)
+            .execute(pool)
+            .await?
+            .rows_affected()
+        }))
+    }
+
+    // Account deletion. A requested deletion waits out the grace period before `account::purge`
+    // removes the user.
+    pub async fn set_deletion_requested_at(&self, user_id: &str, requested_at: Option<chrono::DateTime<chrono::Utc>>) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET deletion_requested_at = $1, updated_at = $2 WHERE id = $3")
+                .bind(requested_at)
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    pub async fn get_deletion_requested_at(&self, user_id: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar::<_, Option<chrono::DateTime<chrono::Utc>>>("SELECT deletion_requested_at FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+                .flatten()
+        }))
+    }
+
+    pub async fn get_users_due_for_deletion(&self, requested_before: chrono::DateTime<chrono::Utc>) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT id FROM users WHERE deletion_requested_at IS NOT NULL AND deletion_requested_at < $1")
+                .bind(requested_before)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Removes the user row; everything referencing it goes with it. Usage statistics and
+    // moderation events aren't tied to the users table, so they are removed here too. The
+    // audit log keeps the bare id.
+    pub async fn delete_user(&self, user_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("DELETE FROM usage_daily WHERE user_id = $1").bind(user_id).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM moderation_events WHERE user_id = $1").bind(user_id).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM users WHERE id = $1").bind(user_id).execute(&mut *tx).await?;
+            tx.commit().await?;
+        });
         Ok(())
     }
 
-    pub async fn get_session_messages(&self, session_id: &str) -> Result<Vec<Message>> {
-        let rows = sqlx::query!(
-            "SELECT id, session_id, role, content, reasoning, model_provider, model_name, tokens_used, created_at FROM messages WHERE session_id = ? ORDER BY created_at ASC",
-            session_id
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    // Login session operations. Sessions are looked up by the digest of their cookie token.
+    pub async fn create_auth_session(
+        &self,
+        id: &str,
+        user_id: &str,
+        expires_at: chrono::DateTime<chrono::Utc>,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO auth_sessions (id, user_id, expires_at, user_agent, ip_address, last_seen_at, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                .bind(id)
+                .bind(user_id)
+                .bind(expires_at)
+                .bind(user_agent)
+                .bind(ip_address)
+                .bind(now)
+                .bind(now)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
 
-        Ok(rows
-            .into_iter()
-            .map(|r| Message {
-                id: r.id,
-                session_id: r.session_id,
-                role: MessageRole::from(r.role),
-                content: r.content,
-                reasoning: r.reasoning,
-                model_provider: r.model_provider,
-                model_name: r.model_name,
-                tokens_used: r.tokens_used,
-                created_at: r.created_at,
-            })
-            .collect())
+    // The user signed in with session `id`, unless it has expired, and when it was last seen
+    pub async fn get_auth_session_user(
+        &self,
+        id: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<(String, Option<chrono::DateTime<chrono::Utc>>)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT user_id, last_seen_at FROM auth_sessions WHERE id = $1 AND expires_at > $2")
+                .bind(id)
+                .bind(now)
+                .fetch_optional(pool)
+                .await?
+        }))
     }
 
-    // User memory operations
-    pub async fn save_memory(&self, memory: &UserMemory) -> Result<()> {
-        sqlx::query!(
-            "INSERT OR REPLACE INTO user_memory (id, user_id, memory_key, memory_value, confidence, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
-            memory.id,
-            memory.user_id,
-            memory.memory_key,
-            memory.memory_value,
-            memory.confidence,
-            memory.created_at,
-            memory.updated_at
-        )
-        .execute(&self.pool)
-        .await?;
+    pub async fn touch_auth_session(&self, id: &str, now: chrono::DateTime<chrono::Utc>, ip_address: Option<&str>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE auth_sessions SET last_seen_at = $1, ip_address = COALESCE($2, ip_address) WHERE id = $3")
+                .bind(now)
+                .bind(ip_address)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
         Ok(())
     }
 
-    pub async fn get_user_memory(&self, user_id: &str) -> Result<Vec<UserMemory>> {
-        let rows = sqlx::query!(
-            "SELECT id, user_id, memory_key, memory_value, confidence, created_at, updated_at FROM user_memory WHERE user_id = ? ORDER BY confidence DESC, updated_at DESC",
-            user_id
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    // The user's unexpired login sessions, most recently used first
+    pub async fn get_user_auth_sessions(&self, user_id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<LoginSession>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT id, user_agent, ip_address, created_at, last_seen_at, expires_at FROM auth_sessions
+                 WHERE user_id = $1 AND expires_at > $2
+                 ORDER BY COALESCE(last_seen_at, created_at) DESC",
+            )
+            .bind(user_id)
+            .bind(now)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
 
-        Ok(rows
-            .into_iter()
-            .map(|r| UserMemory {
-                id: r.id,
-                user_id: r.user_id,
-                memory_key: r.memory_key,
-                memory_value: r.memory_value,
-                confidence: r.confidence,
-                created_at: r.created_at,
-                updated_at: r.updated_at,
-            })
-            .collect())
+    // Ends one of the user's login sessions; false when they have no session `id`
+    pub async fn delete_user_auth_session(&self, user_id: &str, id: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM auth_sessions WHERE id = $1 AND user_id = $2")
+                .bind(id)
+                .bind(user_id)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
     }
 
-    pub async fn get_memory_by_key(&self, user_id: &str, memory_key: &str) -> Result<Option<UserMemory>> {
-        let row = sqlx::query!(
-            "SELECT id, user_id, memory_key, memory_value, confidence, created_at, updated_at FROM user_memory WHERE user_id = ? AND memory_key = ?",
-            user_id,
-            memory_key
-        )
-        .fetch_optional(&self.pool)
-        .await?;
+    // Signs a user out everywhere but login session `keep_id`
+    pub async fn delete_other_auth_sessions(&self, user_id: &str, keep_id: &str) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM auth_sessions WHERE user_id = $1 AND id <> $2")
+                .bind(user_id)
+                .bind(keep_id)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        }))
+    }
+
+    pub async fn delete_auth_session(&self, id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM auth_sessions WHERE id = $1").bind(id).execute(pool).await?;
+        });
+        Ok(())
+    }
 
-        Ok(row.map(|r| UserMemory {
-            id: r.id,
-            user_id: r.user_id,
-            memory_key: r.memory_key,
-            memory_value: r.memory_value,
-            confidence: r.confidence,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
+    // Signs a user out everywhere
+    pub async fn delete_user_auth_sessions(&self, user_id: &str) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM auth_sessions WHERE user_id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await?
+                .rows_affected()
         }))
     }
 
-    // File attachment operations
-    pub async fn save_file_attachment(&self, attachment: &FileAttachment) -> Result<()> {
-        sqlx::query!(
-            "INSERT INTO file_attachments (id, message_id, file_name, file_path, file_type, file_size, content_hash, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-            attachment.id,
-            attachment.message_id,
-            attachment.file_name,
-            attachment.file_path,
-            attachment.file_type,
-            attachment.file_size,
-            attachment.content_hash,
-            attachment.created_at
-        )
-        .execute(&self.pool)
-        .await?;
+    // Returns how many expired sessions were removed
+    pub async fn delete_expired_auth_sessions(&self, now: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM auth_sessions WHERE expires_at <= $1")
+                .bind(now)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        }))
+    }
+
+    // Personal access tokens, looked up by the digest of the token
+    pub async fn create_api_token(&self, user_id: &str, token: &ApiToken, token_hash: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO api_tokens (id, user_id, name, token_hash, token_prefix, scope, expires_at, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+                .bind(&token.id)
+                .bind(user_id)
+                .bind(&token.name)
+                .bind(token_hash)
+                .bind(&token.token_prefix)
+                .bind(token.scope.to_string())
+                .bind(token.expires_at)
+                .bind(token.created_at)
+                .execute(pool)
+                .await?;
+        });
         Ok(())
     }
 
-    pub async fn get_message_attachments(&self, message_id: &str) -> Result<Vec<FileAttachment>> {
-        let rows = sqlx::query!(
-            "SELECT id, message_id, file_name, file_path, file_type, file_size, content_hash, created_at FROM file_attachments WHERE message_id = ?",
-            message_id
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    // The owner, id, scope and last use of the token with this digest, unless it has expired
+    pub async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<(String, String, String, Option<chrono::DateTime<chrono::Utc>>)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT user_id, id, scope, last_used_at FROM api_tokens
+                 WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > $2)",
+            )
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(pool)
+            .await?
+        }))
+    }
 
-        Ok(rows
-            .into_iter()
-            .map(|r| FileAttachment {
-                id: r.id,
-                message_id: r.message_id,
-                file_name: r.file_name,
-                file_path: r.file_path,
-                file_type: r.file_type,
-                file_size: r.file_size,
-                content_hash: r.content_hash,
-                created_at: r.created_at,
-            })
-            .collect())
+    pub async fn touch_api_token(&self, id: &str, now: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE api_tokens SET last_used_at = $1 WHERE id = $2")
+                .bind(now)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
     }
 
-    // Suggested questions operations
-    pub async fn save_suggested_questions(&self, questions: &[SuggestedQuestion]) -> Result<()> {
-        for question in questions {
-            sqlx::query!(
-                "INSERT INTO suggested_questions (id, session_id, question, relevance_score, used, created_at) VALUES (?, ?, ?, ?, ?, ?)",
-                question.id,
-                question.session_id,
-                question.question,
-                question.relevance_score,
-                question.used,
-                question.created_at
+    pub async fn get_user_api_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT id, name, token_prefix, scope, last_used_at, expires_at, created_at FROM api_tokens
+                 WHERE user_id = $1 ORDER BY created_at DESC",
             )
-            .execute(&self.pool)
-            .await?;
-        }
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // False when the user has no token `id`
+    pub async fn delete_api_token(&self, user_id: &str, id: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+                .bind(id)
+                .bind(user_id)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    // Single sign-on identities, keyed by the provider's issuer and its id for the user
+    pub async fn get_identity_user_id(&self, issuer: &str, subject: &str) -> Result<Option<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT user_id FROM user_identities WHERE issuer = $1 AND subject = $2")
+                .bind(issuer)
+                .bind(subject)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    pub async fn create_user_identity(&self, issuer: &str, subject: &str, user_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO user_identities (issuer, subject, user_id, created_at) VALUES ($1, $2, $3, $4)")
+                .bind(issuer)
+                .bind(subject)
+                .bind(user_id)
+                .bind(chrono::Utc::now())
+                .execute(pool)
+                .await?;
+        });
         Ok(())
     }
 
-    pub async fn get_session_suggested_questions(&self, session_id: &str, limit: i64) -> Result<Vec<SuggestedQuestion>> {
-        let rows = sqlx::query!(
-            "SELECT id, session_id, question, relevance_score, used, created_at FROM suggested_questions WHERE session_id = ? AND used = FALSE ORDER BY relevance_score DESC, created_at DESC LIMIT ?",
-            session_id,
-            limit
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    // Outbound webhooks. Secrets are stored sealed and only read back to sign deliveries.
+    pub async fn create_webhook(&self, webhook: &Webhook, sealed_secret: &str) -> Result<()> {
+        let events = webhook.events.iter().map(|event| event.to_string()).collect::<Vec<_>>().join(",");
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO webhooks (id, url, events, secret, created_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(&webhook.id)
+                .bind(&webhook.url)
+                .bind(&events)
+                .bind(sealed_secret)
+                .bind(webhook.created_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
 
-        Ok(rows
-            .into_iter()
-            .map(|r| SuggestedQuestion {
-                id: r.id,
-                session_id: r.session_id,
-                question: r.question,
-                relevance_score: r.relevance_score,
-                used: r.used,
-                created_at: r.created_at,
-            })
-            .collect())
+    pub async fn get_webhooks(&self) -> Result<Vec<Webhook>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, url, events, created_at FROM webhooks ORDER BY created_at ASC")
+                .fetch_all(pool)
+                .await?
+        }))
     }
 
-    pub async fn mark_question_used(&self, question_id: &str) -> Result<()> {
-        sqlx::query!(
-            "UPDATE suggested_questions SET used = TRUE WHERE id = ?",
-            question_id
-        )
-        .execute(&self.pool)
-        .await?;
+    // The URL and sealed secret of webhook `id`
+    pub async fn get_webhook_target(&self, id: &str) -> Result<Option<(String, String)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT url, secret FROM webhooks WHERE id = $1")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // Deliveries go with the webhook
+    pub async fn delete_webhook(&self, id: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = $1").bind(id).execute(&mut *tx).await?;
+            let deleted = sqlx::query("DELETE FROM webhooks WHERE id = $1").bind(id).execute(&mut *tx).await?.rows_affected() > 0;
+            tx.commit().await?;
+            deleted
+        }))
+    }
+
+    pub async fn create_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO webhook_deliveries (id, webhook_id, event, payload, status, attempts, next_attempt_at, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(&delivery.id)
+            .bind(&delivery.webhook_id)
+            .bind(&delivery.event)
+            .bind(&delivery.payload)
+            .bind(delivery.status.to_string())
+            .bind(delivery.attempts)
+            .bind(delivery.next_attempt_at)
+            .bind(delivery.created_at)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    // Records the outcome of an attempt
+    pub async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "UPDATE webhook_deliveries SET status = $1, attempts = $2, response_status = $3, error = $4, next_attempt_at = $5, delivered_at = $6
+                 WHERE id = $7",
+            )
+            .bind(delivery.status.to_string())
+            .bind(delivery.attempts)
+            .bind(delivery.response_status)
+            .bind(&delivery.error)
+            .bind(delivery.next_attempt_at)
+            .bind(delivery.delivered_at)
+            .bind(&delivery.id)
+            .execute(pool)
+            .await?;
+        });
         Ok(())
     }
-} 
\ No newline at end of file
+
+    // Pending deliveries whose next attempt is due, oldest first
+    pub async fn get_due_webhook_deliveries(&self, now: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT id, webhook_id, event, payload, status, attempts, response_status, error, next_attempt_at, created_at, delivered_at
+                 FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= $1
+                 ORDER BY next_attempt_at ASC LIMIT $2",
+            )
+            .bind(now)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // The webhook's most recent deliveries, newest first
+    pub async fn get_webhook_deliveries(&self, webhook_id: &str, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT id, webhook_id, event, payload, status, attempts, response_status, error, next_attempt_at, created_at, delivered_at
+                 FROM webhook_deliveries WHERE webhook_id = $1
+                 ORDER BY created_at DESC LIMIT $2",
+            )
+            .bind(webhook_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // Removes finished deliveries created before `cutoff`; pending ones are kept until they finish
+    pub async fn delete_webhook_deliveries_before(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM webhook_deliveries WHERE created_at < $1 AND status <> 'pending'")
+                .bind(cutoff)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        }))
+    }
+
+    // Batch jobs and their prompts; see `batch`
+    pub async fn create_batch_job(&self, job: &BatchJob, items: &[BatchItem]) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "INSERT INTO batch_jobs (id, user_id, model_provider, model_name, concurrency, status, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(&job.id)
+            .bind(&job.user_id)
+            .bind(&job.model_provider)
+            .bind(&job.model_name)
+            .bind(job.concurrency)
+            .bind(job.status.to_string())
+            .bind(job.created_at)
+            .execute(&mut *tx)
+            .await?;
+            for item in items {
+                sqlx::query(
+                    "INSERT INTO batch_items (job_id, line, custom_id, prompt, system_prompt, status)
+                     VALUES ($1, $2, $3, $4, $5, $6)",
+                )
+                .bind(&item.job_id)
+                .bind(item.line)
+                .bind(&item.custom_id)
+                .bind(&item.prompt)
+                .bind(&item.system_prompt)
+                .bind(item.status.to_string())
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_batch_job(&self, id: &str) -> Result<Option<BatchJob>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("{} WHERE j.id = $1", BATCH_JOB_SELECT))
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // The user's batch jobs, newest first
+    pub async fn get_user_batch_jobs(&self, user_id: &str, limit: i64) -> Result<Vec<BatchJob>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("{} WHERE j.user_id = $1 ORDER BY j.created_at DESC LIMIT $2", BATCH_JOB_SELECT))
+                .bind(user_id)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Jobs that were waiting or running when the server last stopped
+    pub async fn get_unfinished_batch_jobs(&self) -> Result<Vec<BatchJob>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("{} WHERE j.status IN ('pending', 'running') ORDER BY j.created_at ASC", BATCH_JOB_SELECT))
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn update_batch_job(&self, id: &str, status: JobStatus, error: Option<&str>, finished_at: Option<chrono::DateTime<chrono::Utc>>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE batch_jobs SET status = $1, error = $2, finished_at = $3 WHERE id = $4")
+                .bind(status.to_string())
+                .bind(error)
+                .bind(finished_at)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // The job's prompts in the order they were submitted, only those still to run if `pending_only`
+    pub async fn get_batch_items(&self, job_id: &str, pending_only: bool) -> Result<Vec<BatchItem>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT job_id, line, custom_id, prompt, system_prompt, status, output, error, tokens_used
+                 FROM batch_items WHERE job_id = $1 AND (status = 'pending' OR NOT $2)
+                 ORDER BY line ASC",
+            )
+            .bind(job_id)
+            .bind(pending_only)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // Records a prompt's result
+    pub async fn update_batch_item(&self, item: &BatchItem) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE batch_items SET status = $1, output = $2, error = $3, tokens_used = $4 WHERE job_id = $5 AND line = $6")
+                .bind(item.status.to_string())
+                .bind(&item.output)
+                .bind(&item.error)
+                .bind(item.tokens_used)
+                .bind(&item.job_id)
+                .bind(item.line)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Prompts sent on a schedule; see `schedules`
+    pub async fn create_schedule(&self, schedule: &Schedule) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(&format!(
+                "INSERT INTO schedules ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+                SCHEDULE_COLUMNS
+            ))
+            .bind(&schedule.id)
+            .bind(&schedule.user_id)
+            .bind(&schedule.session_id)
+            .bind(&schedule.prompt)
+            .bind(&schedule.fetch_url)
+            .bind(schedule.interval_minutes)
+            .bind(schedule.next_run_at)
+            .bind(schedule.notify)
+            .bind(schedule.enabled)
+            .bind(schedule.last_run_at)
+            .bind(&schedule.last_error)
+            .bind(schedule.created_at)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_schedule(&self, id: &str) -> Result<Option<Schedule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("SELECT {} FROM schedules WHERE id = $1", SCHEDULE_COLUMNS))
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // The session's schedules, next to run first
+    pub async fn get_session_schedules(&self, session_id: &str) -> Result<Vec<Schedule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("SELECT {} FROM schedules WHERE session_id = $1 ORDER BY next_run_at ASC", SCHEDULE_COLUMNS))
+                .bind(session_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Enabled schedules whose next run is at or before `now`
+    pub async fn get_due_schedules(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<Schedule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!(
+                "SELECT {} FROM schedules WHERE enabled = TRUE AND next_run_at <= $1 ORDER BY next_run_at ASC",
+                SCHEDULE_COLUMNS
+            ))
+            .bind(now)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // Moves a schedule's next run from `previous` to `next`. False if it had already been
+    // moved, so a run is only started once however many servers share the database.
+    pub async fn advance_schedule(
+        &self,
+        id: &str,
+        previous: chrono::DateTime<chrono::Utc>,
+        next: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE schedules SET next_run_at = $1 WHERE id = $2 AND next_run_at = $3")
+                .bind(next)
+                .bind(id)
+                .bind(previous)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    pub async fn record_schedule_run(&self, id: &str, ran_at: chrono::DateTime<chrono::Utc>, error: Option<&str>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE schedules SET last_run_at = $1, last_error = $2 WHERE id = $3")
+                .bind(ran_at)
+                .bind(error)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Turns a schedule on or off, and sets when it next runs
+    pub async fn set_schedule_enabled(&self, id: &str, enabled: bool, next_run_at: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE schedules SET enabled = $1, next_run_at = $2 WHERE id = $3")
+                .bind(enabled)
+                .bind(next_run_at)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn delete_schedule(&self, id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM schedules WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Users' automations; see `rules`
+    pub async fn create_rule(&self, rule: &Rule) -> Result<()> {
+        let condition = serde_json::to_string(&rule.condition)?;
+        let action = serde_json::to_string(&rule.action)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query(&format!(
+                "INSERT INTO rules ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+                RULE_COLUMNS
+            ))
+            .bind(&rule.id)
+            .bind(&rule.user_id)
+            .bind(&rule.name)
+            .bind(rule.trigger.to_string())
+            .bind(&condition)
+            .bind(&action)
+            .bind(rule.enabled)
+            .bind(rule.last_fired_at)
+            .bind(&rule.last_error)
+            .bind(rule.created_at)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_rule(&self, id: &str) -> Result<Option<Rule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("SELECT {} FROM rules WHERE id = $1", RULE_COLUMNS))
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // The user's rules, oldest first
+    pub async fn get_user_rules(&self, user_id: &str) -> Result<Vec<Rule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!("SELECT {} FROM rules WHERE user_id = $1 ORDER BY created_at ASC", RULE_COLUMNS))
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // The user's enabled rules set off by `trigger`
+    pub async fn get_triggered_rules(&self, user_id: &str, trigger: RuleTrigger) -> Result<Vec<Rule>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&format!(
+                "SELECT {} FROM rules WHERE user_id = $1 AND trigger_event = $2 AND enabled = TRUE ORDER BY created_at ASC",
+                RULE_COLUMNS
+            ))
+            .bind(user_id)
+            .bind(trigger.to_string())
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    // Replaces a rule's name, trigger, condition, action and whether it's enabled
+    pub async fn update_rule(&self, rule: &Rule) -> Result<()> {
+        let condition = serde_json::to_string(&rule.condition)?;
+        let action = serde_json::to_string(&rule.action)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "UPDATE rules SET name = $1, trigger_event = $2, condition_json = $3, action_json = $4, enabled = $5 WHERE id = $6",
+            )
+            .bind(&rule.name)
+            .bind(rule.trigger.to_string())
+            .bind(&condition)
+            .bind(&action)
+            .bind(rule.enabled)
+            .bind(&rule.id)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn record_rule_run(&self, id: &str, fired_at: chrono::DateTime<chrono::Utc>, error: Option<&str>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE rules SET last_fired_at = $1, last_error = $2 WHERE id = $3")
+                .bind(fired_at)
+                .bind(error)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn delete_rule(&self, id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM rules WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // The session a Matrix user is chatting in in a room; see `matrix`
+    pub async fn get_matrix_room_session(&self, room_id: &str, user_id: &str) -> Result<Option<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT session_id FROM matrix_rooms WHERE room_id = $1 AND user_id = $2")
+                .bind(room_id)
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    pub async fn set_matrix_room_session(&self, room_id: &str, user_id: &str, session_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO matrix_rooms (room_id, user_id, session_id, updated_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (room_id, user_id) DO UPDATE SET session_id = excluded.session_id, updated_at = excluded.updated_at")
+                .bind(room_id)
+                .bind(user_id)
+                .bind(session_id)
+                .bind(chrono::Utc::now())
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Sessions carried on by email; see `email_gateway`
+    pub async fn get_email_thread_session(&self, message_ids: &[String]) -> Result<Option<String>> {
+        for message_id in message_ids {
+            let session_id: Option<String> = on_pool!(&self.pool, pool => {
+                sqlx::query_scalar("SELECT session_id FROM email_threads WHERE message_id = $1")
+                    .bind(message_id)
+                    .fetch_optional(pool)
+                    .await?
+            });
+            if session_id.is_some() {
+                return Ok(session_id);
+            }
+        }
+        Ok(None)
+    }
+
+    // Records an email of a session's thread; false when it was already recorded
+    pub async fn add_email_thread_message(&self, message_id: &str, session_id: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO email_threads (message_id, session_id, created_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+                .bind(message_id)
+                .bind(session_id)
+                .bind(chrono::Utc::now())
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    // Failed sign-ins; see `login_throttle`
+    pub async fn record_login_failure(&self, email: &str, ip_address: Option<&str>, at: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO login_failures (id, email, ip_address, created_at) VALUES ($1, $2, $3, $4)")
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(email)
+                .bind(ip_address)
+                .bind(at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // When the newest `limit` failures for an email since `since` happened, newest first
+    pub async fn get_login_failures_for_email(&self, email: &str, since: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<chrono::DateTime<chrono::Utc>>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT created_at FROM login_failures WHERE email = $1 AND created_at > $2 ORDER BY created_at DESC LIMIT $3")
+                .bind(email)
+                .bind(since)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // When the newest `limit` failures from an address since `since` happened, newest first
+    pub async fn get_login_failures_for_ip(&self, ip_address: &str, since: chrono::DateTime<chrono::Utc>, limit: i64) -> Result<Vec<chrono::DateTime<chrono::Utc>>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT created_at FROM login_failures WHERE ip_address = $1 AND created_at > $2 ORDER BY created_at DESC LIMIT $3")
+                .bind(ip_address)
+                .bind(since)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Forgets an email's failures once someone signs in with it
+    pub async fn clear_login_failures(&self, email: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM login_failures WHERE email = $1").bind(email).execute(pool).await?;
+        });
+        Ok(())
+    }
+
+    pub async fn delete_login_failures_before(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM login_failures WHERE created_at <= $1")
+                .bind(cutoff)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        }))
+    }
+
+    // Two-factor operations. Secrets are stored sealed; see `two_factor`.
+    // The sealed secret and whether two-factor is on yet
+    pub async fn get_two_factor(&self, user_id: &str) -> Result<Option<(String, bool)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT encrypted_secret, enabled FROM user_two_factor WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // Starts enrollment over with a new secret; it takes effect once enabled
+    pub async fn set_pending_two_factor(&self, user_id: &str, sealed_secret: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO user_two_factor (user_id, encrypted_secret, enabled, created_at) VALUES ($1, $2, FALSE, $3)
+                 ON CONFLICT(user_id) DO UPDATE SET encrypted_secret = excluded.encrypted_secret, enabled = FALSE,
+                 last_used_step = NULL, created_at = excluded.created_at, enabled_at = NULL",
+            )
+            .bind(user_id)
+            .bind(sealed_secret)
+            .bind(chrono::Utc::now())
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn enable_two_factor(&self, user_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE user_two_factor SET enabled = TRUE, enabled_at = $1 WHERE user_id = $2")
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Records that a code for `step` was used. False if one for this or a later step already
+    // was, so the same code can't be used twice, even by two requests at once.
+    pub async fn use_two_factor_step(&self, user_id: &str, step: i64) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE user_two_factor SET last_used_step = $1 WHERE user_id = $2 AND (last_used_step IS NULL OR last_used_step < $1)")
+                .bind(step)
+                .bind(user_id)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    // Turns two-factor off, removing the secret and backup codes
+    pub async fn delete_two_factor(&self, user_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM two_factor_backup_codes WHERE user_id = $1").bind(user_id).execute(pool).await?;
+            sqlx::query("DELETE FROM user_two_factor WHERE user_id = $1").bind(user_id).execute(pool).await?;
+        });
+        Ok(())
+    }
+
+    // Replaces every backup code of a user, used or not
+    pub async fn replace_backup_codes(&self, user_id: &str, code_hashes: &[String]) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM two_factor_backup_codes WHERE user_id = $1").bind(user_id).execute(pool).await?;
+            for code_hash in code_hashes {
+                sqlx::query("INSERT INTO two_factor_backup_codes (id, user_id, code_hash, created_at) VALUES ($1, $2, $3, $4)")
+                    .bind(uuid::Uuid::new_v4().to_string())
+                    .bind(user_id)
+                    .bind(code_hash)
+                    .bind(now)
+                    .execute(pool)
+                    .await?;
+            }
+        });
+        Ok(())
+    }
+
+    // Marks an unused backup code used; false if there is none with this digest
+    pub async fn use_backup_code(&self, user_id: &str, code_hash: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE two_factor_backup_codes SET used_at = $1 WHERE user_id = $2 AND code_hash = $3 AND used_at IS NULL")
+                .bind(chrono::Utc::now())
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    pub async fn count_unused_backup_codes(&self, user_id: &str) -> Result<i64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM two_factor_backup_codes WHERE user_id = $1 AND used_at IS NULL")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await?
+        }))
+    }
+
+    // Chat session operations
+    pub async fn create_session(&self, session: &ChatSession) -> Result<()> {
+        let settings = serde_json::to_string(&session.settings)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO chat_sessions (id, user_id, title, model_provider, model_name, incognito, archived, folder_id, pinned, parent_session_id, branch_message_id, last_message_preview, created_at, updated_at, settings, org_id)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, COALESCE($16, (SELECT org_id FROM users WHERE id = $2), $17))",
+            )
+                .bind(&session.id)
+                .bind(&session.user_id)
+                .bind(&session.title)
+                .bind(&session.model_provider)
+                .bind(&session.model_name)
+                .bind(session.incognito)
+                .bind(session.archived)
+                .bind(&session.folder_id)
+                .bind(session.pinned)
+                .bind(&session.parent_session_id)
+                .bind(&session.branch_message_id)
+                .bind(&session.last_message_preview)
+                .bind(session.created_at)
+                .bind(session.updated_at)
+                .bind(&settings)
+                .bind(&session.org_id)
+                .bind(DEFAULT_ORG_ID)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Pinned sessions first, then most recently active, optionally limited to sessions
+    // carrying `tag_id`. Pages continue after `after`.
+    // Every session the user owns, including archived and trashed ones
+    pub async fn get_all_user_sessions(&self, user_id: &str) -> Result<Vec<ChatSession>> {
+        let sql = format!("SELECT {} FROM chat_sessions WHERE user_id = $1 ORDER BY created_at ASC", SESSION_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn get_user_sessions(
+        &self,
+        user_id: &str,
+        include_archived: bool,
+        tag_id: Option<&str>,
+        after: Option<&SessionCursor>,
+        limit: i64,
+    ) -> Result<Page<ChatSession, SessionCursor>> {
+        let sql = format!(
+            "SELECT {} FROM chat_sessions
+             WHERE user_id = $1 AND deleted_at IS NULL AND ($2 OR archived = FALSE)
+               AND ($3 IS NULL OR id IN (SELECT session_id FROM session_tags WHERE tag_id = $3))
+               AND ($4 IS NULL OR (pinned, updated_at, id) < ($4, $5, $6))
+             ORDER BY pinned DESC, updated_at DESC, id DESC
+             LIMIT $7",
+            SESSION_COLUMNS
+        );
+        // One extra row tells us whether there is another page
+        let mut sessions: Vec<ChatSession> = on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql)
+                .bind(user_id)
+                .bind(include_archived)
+                .bind(tag_id)
+                .bind(after.map(|cursor| cursor.pinned))
+                .bind(after.map(|cursor| cursor.updated_at))
+                .bind(after.map(|cursor| cursor.id.as_str()))
+                .bind(limit + 1)
+                .fetch_all(pool)
+                .await?
+        });
+
+        let next_cursor = if sessions.len() as i64 > limit {
+            sessions.truncate(limit as usize);
+            sessions.last().map(|session| SessionCursor {
+                pinned: session.pinned,
+                updated_at: session.updated_at,
+                id: session.id.clone(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Page { items: sessions, next_cursor })
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_session(&self, session_id: &str) -> Result<Option<ChatSession>> {
+        let sql = format!("SELECT {} FROM chat_sessions WHERE id = $1", SESSION_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(session_id).fetch_optional(pool).await?
+        }))
+    }
+
+    // The root session and every branch below it, oldest first, leaving out trashed ones
+    pub async fn get_session_branches(&self, root_session_id: &str) -> Result<Vec<ChatSession>> {
+        let sql = format!(
+            "WITH RECURSIVE family(id) AS (
+                SELECT id FROM chat_sessions WHERE id = $1
+                UNION ALL
+                SELECT s.id FROM chat_sessions s JOIN family f ON s.parent_session_id = f.id
+            )
+            SELECT {} FROM chat_sessions WHERE id IN (SELECT id FROM family) AND deleted_at IS NULL ORDER BY created_at ASC",
+            SESSION_COLUMNS
+        );
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(root_session_id).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn update_session_settings(&self, session_id: &str, settings: &SessionSettings) -> Result<()> {
+        let settings = serde_json::to_string(settings)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET settings = $1 WHERE id = $2")
+                .bind(&settings)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn update_session_title(&self, session_id: &str, title: Option<&str>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET title = $1 WHERE id = $2")
+                .bind(title)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn set_session_archived(&self, session_id: &str, archived: bool) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET archived = $1, updated_at = $2 WHERE id = $3")
+                .bind(archived)
+                .bind(now)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn set_session_pinned(&self, session_id: &str, pinned: bool) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET pinned = $1 WHERE id = $2")
+                .bind(pinned)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // None moves the session back to the top level
+    pub async fn move_session_to_folder(&self, session_id: &str, folder_id: Option<&str>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET folder_id = $1 WHERE id = $2")
+                .bind(folder_id)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Trash operations
+    // `None` restores the session from the trash
+    pub async fn set_session_deleted(&self, session_id: &str, deleted_at: Option<chrono::DateTime<chrono::Utc>>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE chat_sessions SET deleted_at = $1 WHERE id = $2")
+                .bind(deleted_at)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // `None` restores the message from the trash
+    pub async fn set_message_deleted(&self, message_id: &str, deleted_at: Option<chrono::DateTime<chrono::Utc>>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE messages SET deleted_at = $1 WHERE id = $2")
+                .bind(deleted_at)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_retention_policy(&self, user_id: &str) -> Result<Option<RetentionPolicy>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT message_retention_days, max_sessions FROM retention_policies WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    pub async fn save_retention_policy(&self, user_id: &str, policy: &RetentionPolicy) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO retention_policies (user_id, message_retention_days, max_sessions, updated_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (user_id) DO UPDATE SET message_retention_days = excluded.message_retention_days, max_sessions = excluded.max_sessions, updated_at = excluded.updated_at")
+                .bind(user_id)
+                .bind(policy.message_retention_days)
+                .bind(policy.max_sessions)
+                .bind(chrono::Utc::now())
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Users with at least one session outside the trash
+    pub async fn get_users_with_sessions(&self) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT DISTINCT user_id FROM chat_sessions WHERE deleted_at IS NULL")
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Every session outside the trash, pinned first, then most recently active
+    pub async fn get_sessions_by_activity(&self, user_id: &str) -> Result<Vec<ChatSession>> {
+        let sql = format!(
+            "SELECT {} FROM chat_sessions WHERE user_id = $1 AND deleted_at IS NULL ORDER BY pinned DESC, updated_at DESC, id DESC",
+            SESSION_COLUMNS
+        );
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Messages created before `cutoff` in sessions that aren't trashed
+    pub async fn count_messages_before(&self, user_id: &str, cutoff: chrono::DateTime<chrono::Utc>) -> Result<i64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM messages
+                 WHERE deleted_at IS NULL AND created_at < $2
+                   AND session_id IN (SELECT id FROM chat_sessions WHERE user_id = $1 AND deleted_at IS NULL)",
+            )
+                .bind(user_id)
+                .bind(cutoff)
+                .fetch_one(pool)
+                .await?
+        }))
+    }
+
+    // Moves the messages counted by `count_messages_before` to the trash
+    pub async fn trash_messages_before(&self, user_id: &str, cutoff: chrono::DateTime<chrono::Utc>, deleted_at: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "UPDATE messages SET deleted_at = $3
+                 WHERE deleted_at IS NULL AND created_at < $2
+                   AND session_id IN (SELECT id FROM chat_sessions WHERE user_id = $1 AND deleted_at IS NULL)",
+            )
+                .bind(user_id)
+                .bind(cutoff)
+                .bind(deleted_at)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        }))
+    }
+
+    pub async fn record_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        let details = entry.details.as_ref().map(serde_json::to_string).transpose()?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO audit_log (id, user_id, action, target_id, details, created_at) VALUES ($1, $2, $3, $4, $5, $6)")
+                .bind(&entry.id)
+                .bind(&entry.user_id)
+                .bind(&entry.action)
+                .bind(&entry.target_id)
+                .bind(&details)
+                .bind(entry.created_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Newest first
+    pub async fn get_audit_log(&self, filter: &AuditLogFilter, limit: i64) -> Result<Vec<AuditEntry>> {
+        let sql = "SELECT id, user_id, action, target_id, details, created_at FROM audit_log
+             WHERE ($1 IS NULL OR user_id = $1)
+               AND ($2 IS NULL OR action = $2)
+               AND ($3 IS NULL OR created_at >= $3)
+               AND ($4 IS NULL OR created_at < $4)
+             ORDER BY created_at DESC, id DESC
+             LIMIT $5";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql)
+                .bind(&filter.user_id)
+                .bind(&filter.action)
+                .bind(filter.after)
+                .bind(filter.before)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn record_moderation_event(&self, event: &ModerationEvent) -> Result<()> {
+        let categories = serde_json::to_string(&event.categories)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO moderation_events (id, user_id, session_id, direction, action, categories, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                .bind(&event.id)
+                .bind(&event.user_id)
+                .bind(&event.session_id)
+                .bind(&event.direction)
+                .bind(&event.action)
+                .bind(&categories)
+                .bind(event.created_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Newest first, optionally for one user
+    pub async fn get_moderation_events(&self, user_id: Option<&str>, limit: i64) -> Result<Vec<ModerationEvent>> {
+        let sql = "SELECT id, user_id, session_id, direction, action, categories, created_at FROM moderation_events
+             WHERE ($1 IS NULL OR user_id = $1)
+             ORDER BY created_at DESC, id DESC
+             LIMIT $2";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(user_id).bind(limit).fetch_all(pool).await?
+        }))
+    }
+
+    // Newest day that has a usage rollup
+    pub async fn get_last_usage_day(&self) -> Result<Option<chrono::NaiveDate>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT MAX(day) FROM usage_daily").fetch_one(pool).await?
+        }))
+    }
+
+    pub async fn get_first_message_at(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT MIN(created_at) FROM messages").fetch_one(pool).await?
+        }))
+    }
+
+    // Usage per user and model for one day, straight from messages. Trashed messages
+    // still count; they were used. Costs are left for the caller to estimate.
+    pub async fn aggregate_usage(&self, day: chrono::NaiveDate) -> Result<Vec<DailyUsage>> {
+        let start = day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        let end = start + chrono::Duration::days(1);
+        let sql = "SELECT $1 AS day, s.user_id,
+                    COALESCE(m.model_provider, s.model_provider) AS model_provider,
+                    COALESCE(m.model_name, s.model_name) AS model_name,
+                    SUM(CASE WHEN m.role = 'user' THEN 1 ELSE 0 END) AS user_messages,
+                    SUM(CASE WHEN m.role = 'assistant' THEN 1 ELSE 0 END) AS assistant_messages,
+                    COALESCE(SUM(m.tokens_used), 0) AS tokens,
+                    CAST(NULL AS DOUBLE PRECISION) AS estimated_cost
+             FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.created_at >= $2 AND m.created_at < $3
+             GROUP BY s.user_id, COALESCE(m.model_provider, s.model_provider), COALESCE(m.model_name, s.model_name)";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(day).bind(start).bind(end).fetch_all(pool).await?
+        }))
+    }
+
+    // Replaces the rollup rows for `day` in one transaction
+    pub async fn replace_usage_day(&self, day: chrono::NaiveDate, rows: &[DailyUsage]) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("DELETE FROM usage_daily WHERE day = $1").bind(day).execute(&mut *tx).await?;
+            for row in rows {
+                sqlx::query("INSERT INTO usage_daily (day, user_id, model_provider, model_name, user_messages, assistant_messages, tokens, estimated_cost) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+                    .bind(row.day)
+                    .bind(&row.user_id)
+                    .bind(&row.model_provider)
+                    .bind(&row.model_name)
+                    .bind(row.user_messages)
+                    .bind(row.assistant_messages)
+                    .bind(row.tokens)
+                    .bind(row.estimated_cost)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    // Rollup rows between `from` and `to` inclusive, for one user or (None) everyone
+    pub async fn get_daily_usage(&self, user_id: Option<&str>, from: chrono::NaiveDate, to: chrono::NaiveDate) -> Result<Vec<DailyUsage>> {
+        let sql = "SELECT day, user_id, model_provider, model_name, user_messages, assistant_messages, tokens, estimated_cost
+             FROM usage_daily
+             WHERE ($1 IS NULL OR user_id = $1) AND day >= $2 AND day <= $3
+             ORDER BY day ASC, user_id ASC, model_provider ASC, model_name ASC";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(user_id).bind(from).bind(to).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn get_trash(&self, user_id: &str) -> Result<Trash> {
+        let session_sql = format!(
+            "SELECT {} FROM chat_sessions WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            SESSION_COLUMNS
+        );
+        let message_sql = "SELECT m.id, m.session_id, m.role, m.content, m.reasoning, m.model_provider, m.model_name, m.tokens_used, m.citations, m.deleted_at, m.created_at
+             FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE s.user_id = $1 AND m.deleted_at IS NOT NULL AND s.deleted_at IS NULL
+             ORDER BY m.deleted_at DESC";
+
+        Ok(on_pool!(&self.pool, pool => {
+            Trash {
+                sessions: sqlx::query_as(&session_sql).bind(user_id).fetch_all(pool).await?,
+                messages: sqlx::query_as(message_sql).bind(user_id).fetch_all(pool).await?,
+            }
+        }))
+    }
+
+    // Everything trashed before `cutoff`, for permanent purging. Messages are paired with
+    // their owner's user id and only listed when their session isn't being purged too.
+    pub async fn get_expired_trash(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<(Vec<ChatSession>, Vec<(String, Message)>)> {
+        let session_sql = format!("SELECT {} FROM chat_sessions WHERE deleted_at < $1", SESSION_COLUMNS);
+        let message_sql = "SELECT m.id, m.session_id, m.role, m.content, m.reasoning, m.model_provider, m.model_name, m.tokens_used, m.citations, m.deleted_at, m.created_at, s.user_id
+             FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.deleted_at < $1 AND (s.deleted_at IS NULL OR s.deleted_at >= $1)";
+
+        Ok(on_pool!(&self.pool, pool => {
+            let sessions = sqlx::query_as(&session_sql).bind(cutoff).fetch_all(pool).await?;
+            let messages = sqlx::query(message_sql)
+                .bind(cutoff)
+                .fetch_all(pool)
+                .await?
+                .iter()
+                .map(|r| Ok((r.try_get("user_id")?, Message::from_row(r)?)))
+                .collect::<Result<Vec<_>, sqlx::Error>>()?;
+            (sessions, messages)
+        }))
+    }
+
+    // Every message id in the session, trashed or not
+    pub async fn get_session_message_ids(&self, session_id: &str) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT id FROM messages WHERE session_id = $1")
+                .bind(session_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Stored file paths for every attachment in the session, including trashed messages
+    pub async fn get_session_attachment_paths(&self, session_id: &str) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar(
+                "SELECT DISTINCT f.file_path FROM file_attachments f
+                 JOIN messages m ON m.id = f.message_id
+                 WHERE m.session_id = $1",
+            )
+            .bind(session_id)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM messages WHERE id = $1")
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Permanent; messages, suggested questions, attachment rows and knowledge base links cascade
+    pub async fn delete_session(&self, session_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM chat_sessions WHERE id = $1")
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Organization operations
+    pub async fn create_organization(&self, organization: &Organization) -> Result<()> {
+        let settings = serde_json::to_string(&organization.settings)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO organizations (id, name, settings, created_at, updated_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(&organization.id)
+                .bind(&organization.name)
+                .bind(&settings)
+                .bind(organization.created_at)
+                .bind(organization.updated_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_organization(&self, org_id: &str) -> Result<Option<Organization>> {
+        let sql = format!("SELECT {} FROM organizations WHERE id = $1", ORG_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(org_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_organizations(&self) -> Result<Vec<Organization>> {
+        let sql = format!("SELECT {} FROM organizations ORDER BY name", ORG_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).fetch_all(pool).await?
+        }))
+    }
+
+    // Returns false if there is no such organization
+    pub async fn update_organization_settings(&self, org_id: &str, settings: &OrganizationSettings) -> Result<bool> {
+        let settings = serde_json::to_string(settings)?;
+        let result = on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE organizations SET settings = $1, updated_at = $2 WHERE id = $3")
+                .bind(&settings)
+                .bind(chrono::Utc::now())
+                .bind(org_id)
+                .execute(pool)
+                .await?
+                .rows_affected()
+        });
+        Ok(result > 0)
+    }
+
+    // Organization keys are stored sealed; see `secrets::SecretBox`. Rows from before keys
+    // were sealed hold the plain key with `sealed` false until `seal_organization_api_key`.
+    pub async fn set_organization_api_key(&self, org_id: &str, provider: &str, sealed_key: &str, fingerprint: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO organization_api_keys (org_id, provider, api_key, sealed, fingerprint, updated_at) VALUES ($1, $2, $3, TRUE, $4, $5)
+                 ON CONFLICT(org_id, provider) DO UPDATE SET api_key = excluded.api_key, sealed = TRUE, fingerprint = excluded.fingerprint, updated_at = excluded.updated_at",
+            )
+            .bind(org_id)
+            .bind(provider)
+            .bind(sealed_key)
+            .bind(fingerprint)
+            .bind(chrono::Utc::now())
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    // Seals a key saved in plain text, unless it was replaced in the meantime
+    pub async fn seal_organization_api_key(&self, org_id: &str, provider: &str, sealed_key: &str, fingerprint: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE organization_api_keys SET api_key = $1, sealed = TRUE, fingerprint = $2 WHERE org_id = $3 AND provider = $4 AND sealed = FALSE")
+                .bind(sealed_key)
+                .bind(fingerprint)
+                .bind(org_id)
+                .bind(provider)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // (org_id, provider, plain key) for every key not sealed yet
+    pub async fn get_unsealed_organization_api_keys(&self) -> Result<Vec<(String, String, String)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT org_id, provider, api_key FROM organization_api_keys WHERE sealed = FALSE")
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn get_organization_api_keys(&self, org_id: &str) -> Result<Vec<StoredApiKey>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT provider, COALESCE(fingerprint, '') AS fingerprint, updated_at FROM organization_api_keys WHERE org_id = $1 ORDER BY provider")
+                .bind(org_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn delete_organization_api_key(&self, org_id: &str, provider: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM organization_api_keys WHERE org_id = $1 AND provider = $2")
+                .bind(org_id)
+                .bind(provider)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // The stored key and whether it is sealed
+    pub async fn get_organization_api_key(&self, org_id: &str, provider: &str) -> Result<Option<(String, bool)>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT api_key, sealed FROM organization_api_keys WHERE org_id = $1 AND provider = $2")
+                .bind(org_id)
+                .bind(provider)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // User API key operations. Keys are stored sealed; see `secrets::SecretBox`.
+    pub async fn set_user_api_key(&self, user_id: &str, provider: &str, encrypted_key: &str, fingerprint: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO user_api_keys (user_id, provider, encrypted_key, fingerprint, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT(user_id, provider) DO UPDATE SET encrypted_key = excluded.encrypted_key, fingerprint = excluded.fingerprint, updated_at = excluded.updated_at",
+            )
+            .bind(user_id)
+            .bind(provider)
+            .bind(encrypted_key)
+            .bind(fingerprint)
+            .bind(now)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn delete_user_api_key(&self, user_id: &str, provider: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM user_api_keys WHERE user_id = $1 AND provider = $2")
+                .bind(user_id)
+                .bind(provider)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_user_api_keys(&self, user_id: &str) -> Result<Vec<StoredApiKey>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT provider, fingerprint, updated_at FROM user_api_keys WHERE user_id = $1 ORDER BY provider")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn get_user_api_key_sealed(&self, user_id: &str, provider: &str) -> Result<Option<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT encrypted_key FROM user_api_keys WHERE user_id = $1 AND provider = $2")
+                .bind(user_id)
+                .bind(provider)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // Moves a user into another organization. Their existing sessions and knowledge bases
+    // move with them, so nothing stays visible to the old organization.
+    pub async fn set_user_organization(&self, user_id: &str, org_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE users SET org_id = $1 WHERE id = $2").bind(org_id).bind(user_id).execute(&mut *tx).await?;
+            sqlx::query("UPDATE chat_sessions SET org_id = $1 WHERE user_id = $2").bind(org_id).bind(user_id).execute(&mut *tx).await?;
+            sqlx::query("UPDATE knowledge_bases SET org_id = $1 WHERE user_id = $2").bind(org_id).bind(user_id).execute(&mut *tx).await?;
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    // Tag operations
+    // Tag names are unique per user, so tagging with an existing name reuses that tag
+    pub async fn get_or_create_tag(&self, user_id: &str, name: &str) -> Result<Tag> {
+        let tag = Tag::new(user_id.to_string(), name.to_string());
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO tags (id, user_id, name, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT(user_id, name) DO NOTHING")
+                .bind(&tag.id)
+                .bind(&tag.user_id)
+                .bind(&tag.name)
+                .bind(tag.created_at)
+                .execute(pool)
+                .await?;
+
+            sqlx::query_as("SELECT id, user_id, name, created_at FROM tags WHERE user_id = $1 AND name = $2")
+                .bind(user_id)
+                .bind(name)
+                .fetch_one(pool)
+                .await?
+        }))
+    }
+
+    pub async fn get_user_tags(&self, user_id: &str) -> Result<Vec<Tag>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, user_id, name, created_at FROM tags WHERE user_id = $1 ORDER BY name ASC")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn get_session_tags(&self, session_id: &str) -> Result<Vec<Tag>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT t.id, t.user_id, t.name, t.created_at FROM tags t
+                 JOIN session_tags st ON st.tag_id = t.id
+                 WHERE st.session_id = $1
+                 ORDER BY t.name ASC",
+            )
+            .bind(session_id)
+            .fetch_all(pool)
+            .await?
+        }))
+    }
+
+    pub async fn add_session_tag(&self, session_id: &str, tag_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO session_tags (session_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(session_id)
+                .bind(tag_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn remove_session_tag(&self, session_id: &str, tag_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM session_tags WHERE session_id = $1 AND tag_id = $2")
+                .bind(session_id)
+                .bind(tag_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Session folder operations
+    pub async fn create_folder(&self, folder: &SessionFolder) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO session_folders (id, user_id, name, created_at) VALUES ($1, $2, $3, $4)")
+                .bind(&folder.id)
+                .bind(&folder.user_id)
+                .bind(&folder.name)
+                .bind(folder.created_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_user_folders(&self, user_id: &str) -> Result<Vec<SessionFolder>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, user_id, name, created_at FROM session_folders WHERE user_id = $1 ORDER BY name ASC")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn rename_folder(&self, folder_id: &str, name: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE session_folders SET name = $1 WHERE id = $2")
+                .bind(name)
+                .bind(folder_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Sessions in the folder are kept and move back to the top level
+    pub async fn delete_folder(&self, folder_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM session_folders WHERE id = $1")
+                .bind(folder_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Message operations
+    pub async fn create_message(&self, message: &Message) -> Result<()> {
+        let citations = citations_json(message)?;
+        on_pool!(&self.pool, pool => {
+            insert_message!(message, &citations).execute(pool).await?;
+        });
+        Ok(())
+    }
+
+    // Saves a chat turn in one transaction: the user message and its attachments, the
+    // reply, suggested questions, reinforced memories and the session's activity.
+    // If any write fails none of them are kept.
+    #[tracing::instrument(skip_all)]
+    pub async fn save_exchange(&self, exchange: &ChatExchange) -> Result<()> {
+        let user_citations = citations_json(&exchange.user_message)?;
+        let ai_citations = citations_json(&exchange.ai_message)?;
+        let ai_message = &exchange.ai_message;
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+
+            insert_message!(exchange.user_message, &user_citations).execute(&mut *tx).await?;
+            for attachment in &exchange.attachments {
+                insert_attachment!(attachment).execute(&mut *tx).await?;
+            }
+            insert_message!(ai_message, &ai_citations).execute(&mut *tx).await?;
+            for question in &exchange.suggested_questions {
+                insert_suggested_question!(question).execute(&mut *tx).await?;
+            }
+
+            for (memory, confidence) in &exchange.reinforced_memories {
+                sqlx::query("UPDATE user_memory SET confidence = $1, updated_at = $2 WHERE id = $3")
+                    .bind(confidence)
+                    .bind(exchange.user_message.created_at)
+                    .bind(&memory.id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            // Moves the session up the session list and records its newest message
+            sqlx::query("UPDATE chat_sessions SET updated_at = $1, last_message_preview = $2 WHERE id = $3")
+                .bind(ai_message.created_at)
+                .bind(message_preview(&ai_message.content))
+                .bind(&ai_message.session_id)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_session_messages(&self, session_id: &str) -> Result<Vec<Message>> {
+        let sql = format!("SELECT {} FROM messages WHERE session_id = $1 AND deleted_at IS NULL ORDER BY created_at ASC", MESSAGE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(session_id).fetch_all(pool).await?
+        }))
+    }
+
+    // The newest `limit` messages older than `before`, returned in chronological order
+    pub async fn get_session_messages_page(
+        &self,
+        session_id: &str,
+        before: Option<&MessageCursor>,
+        limit: i64,
+    ) -> Result<Page<Message, MessageCursor>> {
+        let sql = format!(
+            "SELECT {} FROM messages
+             WHERE session_id = $1 AND deleted_at IS NULL AND ($2 IS NULL OR (created_at, id) < ($2, $3))
+             ORDER BY created_at DESC, id DESC
+             LIMIT $4",
+            MESSAGE_COLUMNS
+        );
+        // One extra row tells us whether there is another page
+        let mut messages: Vec<Message> = on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql)
+                .bind(session_id)
+                .bind(before.map(|cursor| cursor.created_at))
+                .bind(before.map(|cursor| cursor.id.as_str()))
+                .bind(limit + 1)
+                .fetch_all(pool)
+                .await?
+        });
+
+        let next_cursor = if messages.len() as i64 > limit {
+            messages.truncate(limit as usize);
+            messages.last().map(|message| MessageCursor {
+                created_at: message.created_at,
+                id: message.id.clone(),
+            })
+        } else {
+            None
+        };
+        messages.reverse();
+
+        Ok(Page { items: messages, next_cursor })
+    }
+
+    pub async fn get_message(&self, message_id: &str) -> Result<Option<Message>> {
+        let sql = format!("SELECT {} FROM messages WHERE id = $1", MESSAGE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(message_id).fetch_optional(pool).await?
+        }))
+    }
+
+    // Messages not yet in the conversation search index, paired with their owner's user id.
+    // Incognito sessions are never indexed. Messages that failed `max_attempts` times, or last
+    // failed after `retry_before`, are skipped.
+    pub async fn get_unembedded_messages(&self, limit: i64, max_attempts: i64, retry_before: chrono::DateTime<chrono::Utc>) -> Result<Vec<(String, Message)>> {
+        let sql = "SELECT m.id, m.session_id, m.role, m.content, m.reasoning, m.model_provider, m.model_name, m.tokens_used, m.citations, m.deleted_at, m.created_at, s.user_id
+             FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.embedded_at IS NULL AND s.incognito = FALSE AND m.deleted_at IS NULL AND s.deleted_at IS NULL
+               AND m.embed_attempts < $2 AND (m.embed_failed_at IS NULL OR m.embed_failed_at < $3)
+             ORDER BY m.created_at ASC
+             LIMIT $1";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query(sql)
+                .bind(limit)
+                .bind(max_attempts)
+                .bind(retry_before)
+                .fetch_all(pool)
+                .await?
+                .iter()
+                .map(|r| Ok((r.try_get("user_id")?, Message::from_row(r)?)))
+                .collect::<Result<Vec<_>, sqlx::Error>>()?
+        }))
+    }
+
+    // Keyword search over the user's messages; every term must appear. Snippets have
+    // matched terms wrapped in HIGHLIGHT_START/HIGHLIGHT_END. Best match first.
+    pub async fn search_messages(
+        &self,
+        user_id: &str,
+        terms: &[String],
+        filters: &MessageSearchFilters,
+        limit: i64,
+    ) -> Result<Vec<ConversationSearchResult>> {
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let role = filters.role.as_ref().map(|role| role.to_string());
+        let start = HIGHLIGHT_START.to_string();
+        let end = HIGHLIGHT_END.to_string();
+        let filter_sql = "s.user_id = $4
+               AND m.deleted_at IS NULL AND s.deleted_at IS NULL
+               AND ($5 IS NULL OR m.session_id = $5)
+               AND ($6 IS NULL OR m.role = $6)
+               AND ($7 IS NULL OR m.created_at >= $7)
+               AND ($8 IS NULL OR m.created_at < $8)";
+
+        let (sql, query) = match &self.pool {
+            DbPool::Sqlite(_) => (
+                format!(
+                    "SELECT m.id, m.session_id, s.title, m.role, m.created_at,
+                            snippet(messages_fts, 0, $2, $3, '…', 24) AS snippet, -bm25(messages_fts) AS score
+                     FROM messages_fts
+                     JOIN messages m ON m.rowid = messages_fts.rowid
+                     JOIN chat_sessions s ON s.id = m.session_id
+                     WHERE messages_fts MATCH $1 AND {filter_sql}
+                     ORDER BY bm25(messages_fts)
+                     LIMIT $9"
+                ),
+                // Quoted so punctuation in a term can't be parsed as FTS5 syntax
+                terms.iter().map(|term| format!("\"{}\"", term)).collect::<Vec<_>>().join(" "),
+            ),
+            DbPool::Postgres(_) => (
+                format!(
+                    "SELECT m.id, m.session_id, s.title, m.role, m.created_at,
+                            ts_headline('english', m.content, plainto_tsquery('english', $1),
+                                        'MaxFragments=1, MaxWords=24, MinWords=8, StartSel=' || $2 || ', StopSel=' || $3) AS snippet,
+                            ts_rank(to_tsvector('english', m.content), plainto_tsquery('english', $1))::DOUBLE PRECISION AS score
+                     FROM messages m
+                     JOIN chat_sessions s ON s.id = m.session_id
+                     WHERE to_tsvector('english', m.content) @@ plainto_tsquery('english', $1) AND {filter_sql}
+                     ORDER BY score DESC
+                     LIMIT $9"
+                ),
+                terms.join(" "),
+            ),
+        };
+
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query(&sql)
+                .bind(query)
+                .bind(&start)
+                .bind(&end)
+                .bind(user_id)
+                .bind(&filters.session_id)
+                .bind(&role)
+                .bind(filters.after)
+                .bind(filters.before)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+                .iter()
+                .map(|r| {
+                    Ok(ConversationSearchResult {
+                        session_id: r.try_get("session_id")?,
+                        session_title: r.try_get::<Option<String>, _>("title")?.unwrap_or_else(|| "Untitled chat".to_string()),
+                        message_id: r.try_get("id")?,
+                        role: MessageRole::from(r.try_get::<String, _>("role")?),
+                        snippet: r.try_get("snippet")?,
+                        created_at: r.try_get("created_at")?,
+                        score: r.try_get("score")?,
+                    })
+                })
+                .collect::<Result<Vec<_>, sqlx::Error>>()?
+        }))
+    }
+
+    pub async fn record_message_embed_failure(&self, message_id: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE messages SET embed_attempts = embed_attempts + 1, embed_failed_at = $1 WHERE id = $2")
+                .bind(now)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn mark_message_embedded(&self, message_id: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE messages SET embedded_at = $1 WHERE id = $2")
+                .bind(now)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // User memory operations
+
+    // Inserts the memory, or overwrites the one already stored under its key
+    pub async fn save_memory(&self, memory: &UserMemory) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO user_memory (id, user_id, memory_key, memory_value, confidence, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT(user_id, memory_key) DO UPDATE SET memory_value = excluded.memory_value, confidence = excluded.confidence, updated_at = excluded.updated_at, version = user_memory.version + 1",
+            )
+            .bind(&memory.id)
+            .bind(&memory.user_id)
+            .bind(&memory.memory_key)
+            .bind(&memory.memory_value)
+            .bind(memory.confidence)
+            .bind(memory.created_at)
+            .bind(memory.updated_at)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_user_memory(&self, user_id: &str) -> Result<Vec<UserMemory>> {
+        let sql = format!("SELECT {} FROM user_memory WHERE user_id = $1 ORDER BY confidence DESC, updated_at DESC", MEMORY_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn get_memory_by_key(&self, user_id: &str, memory_key: &str) -> Result<Option<UserMemory>> {
+        let sql = format!("SELECT {} FROM user_memory WHERE user_id = $1 AND memory_key = $2", MEMORY_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).bind(memory_key).fetch_optional(pool).await?
+        }))
+    }
+
+    // Replaces a memory's value in place, moving the old value into memory_history.
+    // Fails without changing anything if the memory has moved on from `previous.version`.
+    pub async fn supersede_memory(&self, previous: &UserMemory, replacement: &UserMemory) -> Result<()> {
+        let history_id = uuid::Uuid::new_v4().to_string();
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+
+            sqlx::query("INSERT INTO memory_history (id, user_id, memory_key, memory_value, confidence, recorded_at, superseded_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                .bind(&history_id)
+                .bind(&previous.user_id)
+                .bind(&previous.memory_key)
+                .bind(&previous.memory_value)
+                .bind(previous.confidence)
+                .bind(previous.updated_at)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+
+            let updated = sqlx::query("UPDATE user_memory SET memory_value = $1, confidence = $2, updated_at = $3, version = version + 1 WHERE id = $4 AND version = $5")
+                .bind(&replacement.memory_value)
+                .bind(replacement.confidence)
+                .bind(replacement.updated_at)
+                .bind(&previous.id)
+                .bind(previous.version)
+                .execute(&mut *tx)
+                .await?;
+            if updated.rows_affected() == 0 {
+                tx.rollback().await?;
+                return Err(memory_conflict(&previous.memory_key));
+            }
+
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_memory_history(&self, user_id: &str, memory_key: &str) -> Result<Vec<MemoryHistoryEntry>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, memory_key, memory_value, confidence, recorded_at, superseded_at FROM memory_history WHERE user_id = $1 AND memory_key = $2 ORDER BY superseded_at DESC")
+                .bind(user_id)
+                .bind(memory_key)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn reinforce_memory(&self, memory_id: &str, confidence: f64, updated_at: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE user_memory SET confidence = $1, updated_at = $2 WHERE id = $3")
+                .bind(confidence)
+                .bind(updated_at)
+                .bind(memory_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // File attachment operations
+    pub async fn save_file_attachment(&self, attachment: &FileAttachment) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            insert_attachment!(attachment).execute(pool).await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_message_attachments(&self, message_id: &str) -> Result<Vec<FileAttachment>> {
+        let sql = format!("SELECT {} FROM file_attachments WHERE message_id = $1", ATTACHMENT_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(message_id).fetch_all(pool).await?
+        }))
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_session_attachments(&self, session_id: &str) -> Result<Vec<FileAttachment>> {
+        let sql = "SELECT f.id, f.message_id, f.file_name, f.file_path, f.file_type, f.file_size, f.content_hash, f.created_at, f.transcript
+             FROM file_attachments f
+             JOIN messages m ON m.id = f.message_id
+             WHERE m.session_id = $1 AND m.deleted_at IS NULL
+             ORDER BY f.created_at ASC";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(session_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Every attachment the user has sent, including in archived and trashed chats
+    pub async fn get_all_user_attachments(&self, user_id: &str) -> Result<Vec<FileAttachment>> {
+        let sql = "SELECT f.id, f.message_id, f.file_name, f.file_path, f.file_type, f.file_size, f.content_hash, f.created_at, f.transcript
+             FROM file_attachments f
+             JOIN messages m ON m.id = f.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE s.user_id = $1
+             ORDER BY f.created_at ASC";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn get_attachment(&self, attachment_id: &str) -> Result<Option<FileAttachment>> {
+        let sql = format!("SELECT {} FROM file_attachments WHERE id = $1", ATTACHMENT_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(attachment_id).fetch_optional(pool).await?
+        }))
+    }
+
+    // Attachment rows left behind when their message or session was removed without the
+    // cascade, e.g. with SQLite foreign keys turned off. Trashed messages still exist,
+    // so their attachments are not included.
+    pub async fn get_orphaned_attachments(&self) -> Result<Vec<FileAttachment>> {
+        let sql = format!(
+            "SELECT {} FROM file_attachments f WHERE NOT EXISTS (
+                SELECT 1 FROM messages m JOIN chat_sessions s ON s.id = m.session_id WHERE m.id = f.message_id
+             )",
+            ATTACHMENT_COLUMNS,
+        );
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn delete_attachment(&self, attachment_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM file_attachments WHERE id = $1")
+                .bind(attachment_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Every stored file some attachment row points at
+    pub async fn get_attachment_file_paths(&self) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT DISTINCT file_path FROM file_attachments")
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // Whether any attachment row still points at a stored file; re-attached files share one
+    pub async fn is_attachment_file_referenced(&self, file_path: &str) -> Result<bool> {
+        let count: i64 = on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM file_attachments WHERE file_path = $1")
+                .bind(file_path)
+                .fetch_one(pool)
+                .await?
+        });
+        Ok(count > 0)
+    }
+
+    // Every attachment the user has uploaded, newest first. Re-attached files share a
+    // file_path with the original upload, so only the earliest row per path is returned.
+    pub async fn get_user_attachments(&self, user_id: &str) -> Result<Vec<FileAttachment>> {
+        let sql = "SELECT f.id, f.message_id, f.file_name, f.file_path, f.file_type, f.file_size, f.content_hash, f.created_at, f.transcript
+             FROM file_attachments f
+             JOIN messages m ON m.id = f.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE s.user_id = $1 AND m.deleted_at IS NULL AND s.deleted_at IS NULL
+               AND f.created_at = (SELECT MIN(f2.created_at) FROM file_attachments f2 WHERE f2.file_path = f.file_path)
+             ORDER BY f.created_at DESC";
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Suggested questions operations
+    pub async fn save_suggested_questions(&self, questions: &[SuggestedQuestion]) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            for question in questions {
+                insert_suggested_question!(question).execute(pool).await?;
+            }
+        });
+        Ok(())
+    }
+
+    pub async fn get_session_suggested_questions(&self, session_id: &str, limit: i64) -> Result<Vec<SuggestedQuestion>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, session_id, question, relevance_score, used, created_at FROM suggested_questions WHERE session_id = $1 AND used = FALSE ORDER BY relevance_score DESC, created_at DESC LIMIT $2")
+                .bind(session_id)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn mark_question_used(&self, question_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE suggested_questions SET used = TRUE WHERE id = $1")
+                .bind(question_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Knowledge base operations
+    pub async fn create_knowledge_base(&self, kb: &KnowledgeBase) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO knowledge_bases (id, user_id, name, description, created_at, updated_at, org_id)
+                 VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, (SELECT org_id FROM users WHERE id = $2), $8))",
+            )
+                .bind(&kb.id)
+                .bind(&kb.user_id)
+                .bind(&kb.name)
+                .bind(&kb.description)
+                .bind(kb.created_at)
+                .bind(kb.updated_at)
+                .bind(&kb.org_id)
+                .bind(DEFAULT_ORG_ID)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_knowledge_base(&self, kb_id: &str) -> Result<Option<KnowledgeBase>> {
+        let sql = format!("SELECT {} FROM knowledge_bases WHERE id = $1", KB_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_user_knowledge_bases(&self, user_id: &str) -> Result<Vec<KnowledgeBase>> {
+        let sql = format!("SELECT {} FROM knowledge_bases WHERE user_id = $1 ORDER BY updated_at DESC", KB_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn delete_knowledge_base(&self, kb_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM knowledge_bases WHERE id = $1")
+                .bind(kb_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn create_kb_document(&self, document: &KnowledgeDocument) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO kb_documents (id, knowledge_base_id, source_type, source_uri, title, content_hash, chunk_count, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+                .bind(&document.id)
+                .bind(&document.knowledge_base_id)
+                .bind(&document.source_type)
+                .bind(&document.source_uri)
+                .bind(&document.title)
+                .bind(&document.content_hash)
+                .bind(document.chunk_count)
+                .bind(document.created_at)
+                .execute(pool)
+                .await?;
+
+            sqlx::query("UPDATE knowledge_bases SET updated_at = $1 WHERE id = $2")
+                .bind(document.created_at)
+                .bind(&document.knowledge_base_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_kb_document(&self, document_id: &str) -> Result<Option<KnowledgeDocument>> {
+        let sql = format!("SELECT {} FROM kb_documents WHERE id = $1", DOCUMENT_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(document_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_kb_documents(&self, kb_id: &str) -> Result<Vec<KnowledgeDocument>> {
+        let sql = format!("SELECT {} FROM kb_documents WHERE knowledge_base_id = $1 ORDER BY created_at DESC", DOCUMENT_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).fetch_all(pool).await?
+        }))
+    }
+
+    // The document most recently ingested from `source_uri`, used to skip unchanged pages on re-crawl
+    pub async fn find_kb_document_by_source(&self, kb_id: &str, source_uri: &str) -> Result<Option<KnowledgeDocument>> {
+        let sql = format!(
+            "SELECT {} FROM kb_documents WHERE knowledge_base_id = $1 AND source_uri = $2 ORDER BY created_at DESC LIMIT 1",
+            DOCUMENT_COLUMNS
+        );
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).bind(source_uri).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn delete_kb_document(&self, document_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM kb_documents WHERE id = $1")
+                .bind(document_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn save_kb_chunks(&self, chunks: &[KnowledgeChunk]) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            for chunk in chunks {
+                sqlx::query("INSERT INTO kb_chunks (id, document_id, knowledge_base_id, chunk_index, content, created_at) VALUES ($1, $2, $3, $4, $5, $6)")
+                    .bind(&chunk.id)
+                    .bind(&chunk.document_id)
+                    .bind(&chunk.knowledge_base_id)
+                    .bind(chunk.chunk_index)
+                    .bind(&chunk.content)
+                    .bind(chunk.created_at)
+                    .execute(pool)
+                    .await?;
+            }
+        });
+        Ok(())
+    }
+
+    pub async fn get_kb_chunk(&self, chunk_id: &str) -> Result<Option<KnowledgeChunk>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT id, document_id, knowledge_base_id, chunk_index, content, created_at FROM kb_chunks WHERE id = $1")
+                .bind(chunk_id)
+                .fetch_optional(pool)
+                .await?
+        }))
+    }
+
+    // Keyword search within one knowledge base for chunks containing any of `terms`;
+    // returns chunk ids, best match first. SQLite ranks with FTS5 BM25, Postgres with ts_rank.
+    pub async fn search_kb_chunks(&self, kb_id: &str, terms: &[String], limit: i64) -> Result<Vec<String>> {
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = match &self.pool {
+            DbPool::Sqlite(pool) => {
+                // Quoted so punctuation in a term can't be parsed as FTS5 syntax
+                let fts_query = terms.iter().map(|term| format!("\"{}\"", term)).collect::<Vec<_>>().join(" OR ");
+                sqlx::query(
+                    "SELECT c.id FROM kb_chunks_fts
+                     JOIN kb_chunks c ON c.rowid = kb_chunks_fts.rowid
+                     WHERE kb_chunks_fts MATCH $1 AND c.knowledge_base_id = $2
+                     ORDER BY bm25(kb_chunks_fts)
+                     LIMIT $3",
+                )
+                .bind(fts_query)
+                .bind(kb_id)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+                .iter()
+                .map(|r| r.try_get("id"))
+                .collect::<Result<Vec<String>, sqlx::Error>>()?
+            }
+            DbPool::Postgres(pool) => {
+                // plainto_tsquery per term keeps user text from being parsed as tsquery syntax
+                let tsquery = (0..terms.len())
+                    .map(|i| format!("plainto_tsquery('english', ${})", i + 3))
+                    .collect::<Vec<_>>()
+                    .join(" || ");
+                let sql = format!(
+                    "SELECT id FROM kb_chunks
+                     WHERE knowledge_base_id = $1 AND to_tsvector('english', content) @@ ({tsquery})
+                     ORDER BY ts_rank(to_tsvector('english', content), {tsquery}) DESC
+                     LIMIT $2"
+                );
+                let mut query = sqlx::query(&sql).bind(kb_id).bind(limit);
+                for term in terms {
+                    query = query.bind(term);
+                }
+                query
+                    .fetch_all(pool)
+                    .await?
+                    .iter()
+                    .map(|r| r.try_get("id"))
+                    .collect::<Result<Vec<String>, sqlx::Error>>()?
+            }
+        };
+
+        Ok(rows)
+    }
+
+    pub async fn get_document_chunk_ids(&self, document_id: &str) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT id FROM kb_chunks WHERE document_id = $1 ORDER BY chunk_index ASC")
+                .bind(document_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    pub async fn link_session_knowledge_base(&self, session_id: &str, kb_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO session_knowledge_bases (session_id, knowledge_base_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(session_id)
+                .bind(kb_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn unlink_session_knowledge_base(&self, session_id: &str, kb_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM session_knowledge_bases WHERE session_id = $1 AND knowledge_base_id = $2")
+                .bind(session_id)
+                .bind(kb_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get_session_knowledge_base_ids(&self, session_id: &str) -> Result<Vec<String>> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT knowledge_base_id FROM session_knowledge_bases WHERE session_id = $1")
+                .bind(session_id)
+                .fetch_all(pool)
+                .await?
+        }))
+    }
+
+    // RAG usage operations
+    pub async fn record_embedded_tokens(&self, user_id: &str, tokens: i64) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO rag_usage (user_id, embedded_tokens, updated_at) VALUES ($1, $2, $3)
+                 ON CONFLICT(user_id) DO UPDATE SET embedded_tokens = rag_usage.embedded_tokens + excluded.embedded_tokens, updated_at = excluded.updated_at",
+            )
+            .bind(user_id)
+            .bind(tokens)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn record_retrieval(&self, user_id: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO rag_usage (user_id, retrievals, updated_at) VALUES ($1, 1, $2)
+                 ON CONFLICT(user_id) DO UPDATE SET retrievals = rag_usage.retrievals + 1, updated_at = excluded.updated_at",
+            )
+            .bind(user_id)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+
+    // Chunks stored across all of the user's knowledge bases; each has one vector
+    pub async fn count_user_kb_chunks(&self, user_id: &str) -> Result<i64> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM kb_chunks c JOIN knowledge_bases kb ON kb.id = c.knowledge_base_id WHERE kb.user_id = $1")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await?
+        }))
+    }
+
+    // Usage counters for a user; `max_vectors` is left for the caller to fill in
+    pub async fn get_rag_usage(&self, user_id: &str) -> Result<RagUsage> {
+        let (counters, documents): (Option<(i64, i64)>, i64) = on_pool!(&self.pool, pool => {
+            let counters = sqlx::query_as("SELECT embedded_tokens, retrievals FROM rag_usage WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?;
+            let documents = sqlx::query_scalar("SELECT COUNT(*) FROM kb_documents d JOIN knowledge_bases kb ON kb.id = d.knowledge_base_id WHERE kb.user_id = $1")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await?;
+            (counters, documents)
+        });
+
+        Ok(RagUsage {
+            embedded_tokens: counters.map(|(tokens, _)| tokens).unwrap_or(0),
+            stored_documents: documents,
+            stored_vectors: self.count_user_kb_chunks(user_id).await?,
+            retrievals: counters.map(|(_, retrievals)| retrievals).unwrap_or(0),
+            max_vectors: None,
+        })
+    }
+
+    // Ingestion job operations
+    pub async fn create_ingestion_job(&self, job: &IngestionJob) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO ingestion_jobs (id, knowledge_base_id, source_type, source_uri, status, documents_processed, error, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+                .bind(&job.id)
+                .bind(&job.knowledge_base_id)
+                .bind(&job.source_type)
+                .bind(&job.source_uri)
+                .bind(job.status.to_string())
+                .bind(job.documents_processed)
+                .bind(&job.error)
+                .bind(job.created_at)
+                .bind(job.updated_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn update_ingestion_job(&self, job_id: &str, status: JobStatus, documents_processed: i64, error: Option<&str>) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE ingestion_jobs SET status = $1, documents_processed = $2, error = $3, updated_at = $4 WHERE id = $5")
+                .bind(status.to_string())
+                .bind(documents_processed)
+                .bind(error)
+                .bind(now)
+                .bind(job_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_ingestion_job(&self, job_id: &str) -> Result<Option<IngestionJob>> {
+        let sql = format!("SELECT {} FROM ingestion_jobs WHERE id = $1", JOB_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(job_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_kb_ingestion_jobs(&self, kb_id: &str) -> Result<Vec<IngestionJob>> {
+        let sql = format!("SELECT {} FROM ingestion_jobs WHERE knowledge_base_id = $1 ORDER BY created_at DESC", JOB_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Crawl source operations
+    pub async fn create_crawl_source(&self, source: &CrawlSource) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO crawl_sources (id, knowledge_base_id, start_url, max_depth, max_pages, same_domain_only, sync_interval_minutes, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+                .bind(&source.id)
+                .bind(&source.knowledge_base_id)
+                .bind(&source.start_url)
+                .bind(source.max_depth)
+                .bind(source.max_pages)
+                .bind(source.same_domain_only)
+                .bind(source.sync_interval_minutes)
+                .bind(source.created_at)
+                .bind(source.updated_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_crawl_source(&self, source_id: &str) -> Result<Option<CrawlSource>> {
+        let sql = format!("SELECT {} FROM crawl_sources WHERE id = $1", CRAWL_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(source_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_kb_crawl_sources(&self, kb_id: &str) -> Result<Vec<CrawlSource>> {
+        let sql = format!("SELECT {} FROM crawl_sources WHERE knowledge_base_id = $1 ORDER BY created_at DESC", CRAWL_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Crawl sources with a re-crawl interval; the scheduler decides which are due
+    pub async fn get_scheduled_crawl_sources(&self) -> Result<Vec<CrawlSource>> {
+        let sql = format!("SELECT {} FROM crawl_sources WHERE sync_interval_minutes IS NOT NULL", CRAWL_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn update_crawl_source_interval(&self, source_id: &str, sync_interval_minutes: Option<i64>) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE crawl_sources SET sync_interval_minutes = $1, updated_at = $2 WHERE id = $3")
+                .bind(sync_interval_minutes)
+                .bind(now)
+                .bind(source_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn record_crawl_sync(&self, source_id: &str, status: JobStatus, error: Option<&str>) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE crawl_sources SET last_synced_at = $1, last_sync_status = $2, last_sync_error = $3, updated_at = $4 WHERE id = $5")
+                .bind(now)
+                .bind(status.to_string())
+                .bind(error)
+                .bind(now)
+                .bind(source_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn delete_crawl_source(&self, source_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM crawl_sources WHERE id = $1")
+                .bind(source_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Feed source operations
+    pub async fn create_feed_source(&self, source: &FeedSource) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO feed_sources (id, knowledge_base_id, url, title, sync_interval_minutes, digest_model_provider, digest_model_name, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+                .bind(&source.id)
+                .bind(&source.knowledge_base_id)
+                .bind(&source.url)
+                .bind(&source.title)
+                .bind(source.sync_interval_minutes)
+                .bind(&source.digest_model_provider)
+                .bind(&source.digest_model_name)
+                .bind(source.created_at)
+                .bind(source.updated_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_feed_source(&self, source_id: &str) -> Result<Option<FeedSource>> {
+        let sql = format!("SELECT {} FROM feed_sources WHERE id = $1", FEED_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(source_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_kb_feed_sources(&self, kb_id: &str) -> Result<Vec<FeedSource>> {
+        let sql = format!("SELECT {} FROM feed_sources WHERE knowledge_base_id = $1 ORDER BY created_at DESC", FEED_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(kb_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Every feed source; the scheduler decides which are due for a poll or a digest
+    pub async fn get_feed_sources(&self) -> Result<Vec<FeedSource>> {
+        let sql = format!("SELECT {} FROM feed_sources", FEED_SOURCE_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn update_feed_source(&self, source: &FeedSource) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE feed_sources SET sync_interval_minutes = $1, digest_model_provider = $2, digest_model_name = $3, updated_at = $4 WHERE id = $5")
+                .bind(source.sync_interval_minutes)
+                .bind(&source.digest_model_provider)
+                .bind(&source.digest_model_name)
+                .bind(now)
+                .bind(&source.id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn record_feed_sync(&self, source_id: &str, status: JobStatus, error: Option<&str>) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE feed_sources SET last_synced_at = $1, last_sync_status = $2, last_sync_error = $3, updated_at = $4 WHERE id = $5")
+                .bind(now)
+                .bind(status.to_string())
+                .bind(error)
+                .bind(now)
+                .bind(source_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Moves a feed's digest on from `previous` (its last digest, or its creation before the
+    // first) to `at`. False when another run got there first.
+    pub async fn claim_feed_digest(
+        &self,
+        source_id: &str,
+        previous: chrono::DateTime<chrono::Utc>,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE feed_sources SET last_digest_at = $1 WHERE id = $2 AND COALESCE(last_digest_at, created_at) = $3")
+                .bind(at)
+                .bind(source_id)
+                .bind(previous)
+                .execute(pool)
+                .await?
+                .rows_affected() > 0
+        }))
+    }
+
+    pub async fn delete_feed_source(&self, source_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM feed_sources WHERE id = $1")
+                .bind(source_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn feed_item_exists(&self, source_id: &str, guid: &str) -> Result<bool> {
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query("SELECT 1 FROM feed_items WHERE feed_source_id = $1 AND guid = $2")
+                .bind(source_id)
+                .bind(guid)
+                .fetch_optional(pool)
+                .await?
+                .is_some()
+        }))
+    }
+
+    pub async fn create_feed_item(&self, item: &FeedItem) -> Result<()> {
+        let sql = format!("INSERT INTO feed_items ({}) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING", FEED_ITEM_COLUMNS);
+        on_pool!(&self.pool, pool => {
+            sqlx::query(&sql)
+                .bind(&item.feed_source_id)
+                .bind(&item.guid)
+                .bind(&item.title)
+                .bind(&item.link)
+                .bind(&item.summary)
+                .bind(item.published_at)
+                .bind(item.created_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Items indexed from a feed after `since`, oldest first
+    pub async fn get_feed_items_since(&self, source_id: &str, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<FeedItem>> {
+        let sql = format!("SELECT {} FROM feed_items WHERE feed_source_id = $1 AND created_at > $2 ORDER BY created_at", FEED_ITEM_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(source_id).bind(since).fetch_all(pool).await?
+        }))
+    }
+
+    // Connector operations
+    pub async fn create_connector(&self, connector: &Connector) -> Result<()> {
+        let resource_ids = serde_json::to_string(&connector.resource_ids)?;
+        on_pool!(&self.pool, pool => {
+            sqlx::query("INSERT INTO connectors (id, user_id, knowledge_base_id, provider, resource_ids, sync_interval_minutes, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+                .bind(&connector.id)
+                .bind(&connector.user_id)
+                .bind(&connector.knowledge_base_id)
+                .bind(connector.provider.to_string())
+                .bind(&resource_ids)
+                .bind(connector.sync_interval_minutes)
+                .bind(connector.created_at)
+                .bind(connector.updated_at)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_connector(&self, connector_id: &str) -> Result<Option<Connector>> {
+        let sql = format!("SELECT {} FROM connectors WHERE id = $1", CONNECTOR_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(connector_id).fetch_optional(pool).await?
+        }))
+    }
+
+    pub async fn get_user_connectors(&self, user_id: &str) -> Result<Vec<Connector>> {
+        let sql = format!("SELECT {} FROM connectors WHERE user_id = $1 ORDER BY created_at DESC", CONNECTOR_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).bind(user_id).fetch_all(pool).await?
+        }))
+    }
+
+    // Connectors that have completed OAuth; the scheduler decides which are due
+    pub async fn get_connected_connectors(&self) -> Result<Vec<Connector>> {
+        let sql = format!("SELECT {} FROM connectors WHERE access_token IS NOT NULL", CONNECTOR_COLUMNS);
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql).fetch_all(pool).await?
+        }))
+    }
+
+    pub async fn delete_connector(&self, connector_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM connectors WHERE id = $1")
+                .bind(connector_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn set_connector_tokens(
+        &self,
+        connector_id: &str,
+        access_token: &str,
+        refresh_token: Option<&str>,
+        token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<()> {
+        let now = chrono::Utc::now();
+        // Google only returns a refresh token on first consent, so keep the old one if none is given
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE connectors SET access_token = $1, refresh_token = COALESCE($2, refresh_token), token_expires_at = $3, updated_at = $4 WHERE id = $5")
+                .bind(access_token)
+                .bind(refresh_token)
+                .bind(token_expires_at)
+                .bind(now)
+                .bind(connector_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    pub async fn get_connector_tokens(
+        &self,
+        connector_id: &str,
+    ) -> Result<Option<(String, Option<String>, Option<chrono::DateTime<chrono::Utc>>)>> {
+        let row: Option<(Option<String>, Option<String>, Option<chrono::DateTime<chrono::Utc>>)> = on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT access_token, refresh_token, token_expires_at FROM connectors WHERE id = $1")
+                .bind(connector_id)
+                .fetch_optional(pool)
+                .await?
+        });
+
+        Ok(row.and_then(|(access_token, refresh_token, expires_at)| access_token.map(|token| (token, refresh_token, expires_at))))
+    }
+
+    pub async fn record_connector_sync(&self, connector_id: &str, status: JobStatus, error: Option<&str>) -> Result<()> {
+        let now = chrono::Utc::now();
+        on_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE connectors SET last_synced_at = $1, last_sync_status = $2, last_sync_error = $3, updated_at = $4 WHERE id = $5")
+                .bind(now)
+                .bind(status.to_string())
+                .bind(error)
+                .bind(now)
+                .bind(connector_id)
+                .execute(pool)
+                .await?;
+        });
+        Ok(())
+    }
+
+    // Returns (document_id, external_modified_at) for every item the connector has ingested
+    pub async fn get_connector_documents(&self, connector_id: &str) -> Result<std::collections::HashMap<String, (String, String)>> {
+        let rows: Vec<(String, String, String)> = on_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT external_id, document_id, external_modified_at FROM connector_documents WHERE connector_id = $1")
+                .bind(connector_id)
+                .fetch_all(pool)
+                .await?
+        });
+
+        Ok(rows
+            .into_iter()
+            .map(|(external_id, document_id, external_modified_at)| (external_id, (document_id, external_modified_at)))
+            .collect())
+    }
+
+    pub async fn upsert_connector_document(
+        &self,
+        connector_id: &str,
+        external_id: &str,
+        document_id: &str,
+        external_modified_at: &str,
+    ) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            sqlx::query(
+                "INSERT INTO connector_documents (connector_id, external_id, document_id, external_modified_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT(connector_id, external_id) DO UPDATE SET document_id = excluded.document_id, external_modified_at = excluded.external_modified_at",
+            )
+            .bind(connector_id)
+            .bind(external_id)
+            .bind(document_id)
+            .bind(external_modified_at)
+            .execute(pool)
+            .await?;
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use serde::Deserialize;
+use crate::{api::{self, AppState}, models::*, rate_limit::RateLimiter};
+
+pub const INBOUND_PATH: &str = "/api/email/inbound";
+const MAX_SUBJECT_LENGTH: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct EmailGatewayConfig {
+    // The password the email provider sends with HTTP basic auth when it posts an email
+    pub secret: String,
+    // The model new conversations by email use
+    pub model_provider: AIProvider,
+    pub model_name: String,
+}
+
+// Lets users ask questions by email. The email provider posts each email the instance's
+// address receives to `INBOUND_PATH`; the email starts a session, or carries on the one its
+// thread belongs to, and the reply is emailed back in the same thread. Only emails from the
+// addresses of existing accounts are answered.
+#[derive(Clone)]
+pub struct EmailGateway {
+    config: EmailGatewayConfig,
+    limiter: RateLimiter,
+}
+
+// An email in the JSON Postmark's inbound webhook posts; other providers can be pointed at
+// the endpoint through a small relay that posts the same fields
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InboundEmail {
+    pub from_full: Mailbox,
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub text_body: String,
+    // The new text of a reply, without the quoted email
+    #[serde(default)]
+    pub stripped_text_reply: String,
+    #[serde(default)]
+    pub headers: Vec<EmailHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Mailbox {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl InboundEmail {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.trim())
+    }
+
+    // The Message-IDs in a header like References, which lists them separated by spaces
+    fn message_ids(&self, name: &str) -> Vec<String> {
+        self.header(name)
+            .map(|value| value.split_whitespace().filter(|id| id.starts_with('<')).map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn question(&self) -> &str {
+        let reply = self.stripped_text_reply.trim();
+        if reply.is_empty() { self.text_body.trim() } else { reply }
+    }
+}
+
+impl EmailGateway {
+    pub fn new(config: EmailGatewayConfig, limiter: RateLimiter) -> Self {
+        Self { config, limiter }
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.config.secret
+    }
+
+    // Answers the email, in the background so the provider isn't kept waiting for the model.
+    // Emails that can't be answered are dropped with a log line; replying to a forged sender
+    // would only send mail to a stranger.
+    pub fn receive(&self, state: &AppState, email: InboundEmail) {
+        let (gateway, state) = (self.clone(), state.clone());
+        state.shutdown.clone().spawn(async move {
+            if let Err(e) = gateway.answer(&state, email).await {
+                tracing::warn!("Failed to answer an inbound email: {}", e);
+            }
+        });
+    }
+
+    async fn answer(&self, state: &AppState, email: InboundEmail) -> Result<()> {
+        let mailer = state.mailer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Replying by email needs SMTP_HOST"))?;
+        let message_id = email.header("Message-ID")
+            .ok_or_else(|| anyhow::anyhow!("Email has no Message-ID"))?
+            .to_string();
+        // The provider checks the sender's domain allows its mail server; anything else may
+        // be someone else writing in the user's name
+        if email.header("Received-SPF").is_some_and(|spf| !spf.to_lowercase().starts_with("pass")) {
+            return Err(anyhow::anyhow!("Email from {} failed its SPF check", email.from_full.email));
+        }
+        let user = state.db.get_user_by_email(&email.from_full.email).await?
+            .ok_or_else(|| anyhow::anyhow!("No account for {}", email.from_full.email))?;
+        let question = email.question();
+        // Providers post again when they don't hear back in time; the same email is only
+        // answered once
+        if question.is_empty() || state.db.get_email_thread_session(std::slice::from_ref(&message_id)).await?.is_some() {
+            return Ok(());
+        }
+
+        // Follow-ups answer one of the thread's emails; only the sender's own sessions count
+        let mut thread_ids = email.message_ids("In-Reply-To");
+        thread_ids.extend(email.message_ids("References"));
+        let session = match state.db.get_email_thread_session(&thread_ids).await? {
+            Some(session_id) => state.db.get_session(&session_id).await?
+                .filter(|session| session.user_id == user.id && session.deleted_at.is_none()),
+            None => None,
+        };
+        let session_id = match session {
+            Some(session) => session.id,
+            None => {
+                let title = email.subject.trim().chars().take(MAX_SUBJECT_LENGTH).collect::<String>();
+                let title = (!title.is_empty()).then_some(title);
+                api::create_session_as(state, &user.id, title, self.config.model_provider.clone(), self.config.model_name.clone(), false).await?.id
+            }
+        };
+        if !state.db.add_email_thread_message(&message_id, &session_id).await? {
+            return Ok(());
+        }
+
+        let body = match self.limiter.take_message(&user.id) {
+            Ok(()) => match api::send_message_as(state, &user.id, session_id.clone(), question.to_string(), Vec::new(), Vec::new(), Vec::new(), false).await {
+                Ok(response) => response.content,
+                Err(e) => format!("Sorry, your question couldn't be answered: {}", e),
+            },
+            Err(wait) => format!("Too many messages. Try again in {} seconds.", (wait.as_secs_f64().ceil() as u64).max(1)),
+        };
+        let subject = if email.subject.to_lowercase().starts_with("re:") {
+            email.subject.clone()
+        } else {
+            format!("Re: {}", email.subject)
+        };
+        let reply_id = mailer.send_reply(&email.from_full.email, &subject, &body, &message_id, &email.message_ids("References")).await?;
+        state.db.add_email_thread_message(&reply_id, &session_id).await?;
+        Ok(())
+    }
+}
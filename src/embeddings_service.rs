@@ -0,0 +1,199 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmbeddingProvider {
+    OpenAI,
+    Ollama,
+    Local,
+}
+
+impl std::fmt::Display for EmbeddingProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbeddingProvider::OpenAI => write!(f, "openai"),
+            EmbeddingProvider::Ollama => write!(f, "ollama"),
+            EmbeddingProvider::Local => write!(f, "local"),
+        }
+    }
+}
+
+impl From<String> for EmbeddingProvider {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "openai" => EmbeddingProvider::OpenAI,
+            "ollama" => EmbeddingProvider::Ollama,
+            "local" => EmbeddingProvider::Local,
+            _ => EmbeddingProvider::Ollama,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EmbeddingsServiceConfig {
+    pub provider: EmbeddingProvider,
+    pub model_name: String,
+    pub openai_api_key: Option<String>,
+    pub ollama_base_url: String,
+    // Any server speaking the text-embeddings-inference `/embed` API
+    pub local_base_url: String,
+    pub batch_size: usize,
+}
+
+impl Default for EmbeddingsServiceConfig {
+    fn default() -> Self {
+        Self {
+            provider: EmbeddingProvider::Ollama,
+            model_name: "nomic-embed-text".to_string(),
+            openai_api_key: None,
+            ollama_base_url: "http://localhost:11434".to_string(),
+            local_base_url: "http://localhost:8080".to_string(),
+            batch_size: 32,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EmbeddingsService {
+    config: EmbeddingsServiceConfig,
+    http: reqwest::Client,
+}
+
+impl EmbeddingsService {
+    pub fn new(config: EmbeddingsServiceConfig) -> Result<Self> {
+        if config.provider == EmbeddingProvider::OpenAI && config.openai_api_key.is_none() {
+            return Err(anyhow::anyhow!("OpenAI embeddings require an API key"));
+        }
+
+        Ok(Self {
+            config,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    pub fn provider(&self) -> EmbeddingProvider {
+        self.config.provider
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.config.model_name
+    }
+
+    // Embeds every text, splitting the input into provider-sized batches.
+    // The output is in the same order as the input.
+    #[tracing::instrument(skip_all, fields(texts = texts.len()))]
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(self.config.batch_size.max(1)) {
+            let batch_embeddings = match self.config.provider {
+                EmbeddingProvider::OpenAI => self.embed_openai(batch).await?,
+                EmbeddingProvider::Ollama => self.embed_ollama(batch).await?,
+                EmbeddingProvider::Local => self.embed_local(batch).await?,
+            };
+
+            if batch_embeddings.len() != batch.len() {
+                return Err(anyhow::anyhow!(
+                    "Embedding provider returned {} vectors for {} inputs",
+                    batch_embeddings.len(),
+                    batch.len()
+                ));
+            }
+            embeddings.extend(batch_embeddings);
+        }
+
+        Ok(embeddings)
+    }
+
+    pub async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding provider returned no vectors"))
+    }
+
+    async fn embed_openai(&self, batch: &[String]) -> Result<Vec<Vec<f32>>> {
+        let api_key = self.config.openai_api_key.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("OpenAI API key not configured"))?;
+
+        let response: Value = self.http
+            .post("https://api.openai.com/v1/embeddings")
+            .bearer_auth(api_key)
+            .json(&json!({
+                "model": self.config.model_name,
+                "input": batch,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut data = response["data"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("OpenAI embeddings response missing data"))?
+            .clone();
+        // OpenAI documents `index` on every item; sort on it rather than trusting the order
+        data.sort_by_key(|item| item["index"].as_u64().unwrap_or(0));
+
+        data.iter().map(|item| parse_vector(&item["embedding"])).collect()
+    }
+
+    async fn embed_ollama(&self, batch: &[String]) -> Result<Vec<Vec<f32>>> {
+        // /api/embeddings takes a single prompt per request
+        let url = format!("{}/api/embeddings", self.config.ollama_base_url.trim_end_matches('/'));
+        let mut embeddings = Vec::with_capacity(batch.len());
+
+        for text in batch {
+            let response: Value = self.http
+                .post(&url)
+                .json(&json!({
+                    "model": self.config.model_name,
+                    "prompt": text,
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            embeddings.push(parse_vector(&response["embedding"])?);
+        }
+
+        Ok(embeddings)
+    }
+
+    async fn embed_local(&self, batch: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embed", self.config.local_base_url.trim_end_matches('/'));
+
+        let response: Value = self.http
+            .post(&url)
+            .json(&json!({ "inputs": batch }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        response
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Local embeddings response was not an array"))?
+            .iter()
+            .map(parse_vector)
+            .collect()
+    }
+}
+
+fn parse_vector(value: &Value) -> Result<Vec<f32>> {
+    value
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Embedding was not an array"))?
+        .iter()
+        .map(|v| {
+            v.as_f64()
+                .map(|f| f as f32)
+                .ok_or_else(|| anyhow::anyhow!("Embedding contained a non-numeric value"))
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use chrono::Utc;
+use crate::{api::AppState, models::*};
+
+pub const EXPORT_VERSION: u32 = 1;
+
+// Renders a session as a downloadable JSON document, Markdown transcript or PDF
+pub async fn export_session(state: &AppState, session_id: &str, format: ExportFormat) -> Result<ExportedFile> {
+    let export = build_export(state, session_id).await?;
+    let (content, content_type, extension) = match format {
+        ExportFormat::Json => (serde_json::to_string_pretty(&export)?.into_bytes(), "application/json", "json"),
+        ExportFormat::Markdown => (to_markdown(&export).into_bytes(), "text/markdown; charset=utf-8", "md"),
+        ExportFormat::Pdf => {
+            let images = message_images(state, session_id).await?;
+            (crate::pdf::render(&export, &images)?, "application/pdf", "pdf")
+        }
+    };
+
+    Ok(ExportedFile {
+        file_name: format!("{}.{}", file_stem(&export), extension),
+        content_type: content_type.to_string(),
+        content,
+    })
+}
+
+async fn build_export(state: &AppState, session_id: &str) -> Result<SessionExport> {
+    let session = state.db.get_session(session_id).await?
+        .filter(|session| session.deleted_at.is_none())
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    session_export(state, session).await
+}
+
+// The export of a session already loaded, whether or not it is in the trash
+pub async fn session_export(state: &AppState, session: ChatSession) -> Result<SessionExport> {
+    let now = Utc::now();
+    let mut messages = Vec::new();
+    for message in state.db.get_session_messages(&session.id).await? {
+        let attachments = state.db.get_message_attachments(&message.id).await?
+            .into_iter()
+            .map(|attachment| Ok(SessionExportAttachment {
+                // Exports get shared, so their links expire like the ones in the app
+                url: crate::attachment_urls::sign(state, &attachment.id, now)?,
+                file_name: attachment.file_name,
+                file_type: attachment.file_type,
+                file_size: attachment.file_size,
+            }))
+            .collect::<Result<_>>()?;
+
+        messages.push(SessionExportMessage {
+            role: message.role,
+            content: message.content,
+            reasoning: message.reasoning,
+            model_provider: message.model_provider,
+            model_name: message.model_name,
+            citations: message.citations,
+            attachments,
+            created_at: message.created_at,
+        });
+    }
+
+    Ok(SessionExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        session_id: session.id,
+        title: session.title,
+        model_provider: session.model_provider,
+        model_name: session.model_name,
+        created_at: session.created_at,
+        messages,
+    })
+}
+
+// The image attachments of each of the session's messages, decoded, in the same order as
+// the messages of its export. Images that can't be read are left out.
+async fn message_images(state: &AppState, session_id: &str) -> Result<Vec<Vec<image::DynamicImage>>> {
+    let mut images = Vec::new();
+    for message in state.db.get_session_messages(session_id).await? {
+        let mut message_images = Vec::new();
+        for attachment in state.db.get_message_attachments(&message.id).await? {
+            if !attachment.file_type.starts_with("image/") {
+                continue;
+            }
+            let decoded = tokio::fs::read(&attachment.file_path).await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(image::load_from_memory(&data)?));
+            match decoded {
+                Ok(image) => message_images.push(image),
+                Err(e) => tracing::warn!("Leaving image {} out of the PDF: {}", attachment.file_path, e),
+            }
+        }
+        images.push(message_images);
+    }
+    Ok(images)
+}
+
+pub fn to_markdown(export: &SessionExport) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("# {}\n\n", export.title.as_deref().unwrap_or("Untitled chat")));
+    out.push_str(&format!(
+        "_{} · {} · started {}_\n",
+        export.model_provider,
+        export.model_name,
+        export.created_at.format("%Y-%m-%d %H:%M UTC"),
+    ));
+
+    for message in &export.messages {
+        let speaker = match message.role {
+            MessageRole::User => "User".to_string(),
+            MessageRole::System => "System".to_string(),
+            MessageRole::Assistant => match &message.model_name {
+                Some(model_name) => format!("Assistant ({})", model_name),
+                None => "Assistant".to_string(),
+            },
+        };
+        out.push_str(&format!("\n## {} · {}\n\n", speaker, message.created_at.format("%Y-%m-%d %H:%M")));
+
+        // Collapsed by default where the Markdown renderer supports HTML
+        if let Some(reasoning) = message.reasoning.as_deref().filter(|r| !r.trim().is_empty()) {
+            out.push_str("<details>\n<summary>Reasoning</summary>\n\n");
+            out.push_str(reasoning.trim());
+            out.push_str("\n\n</details>\n\n");
+        }
+
+        out.push_str(message.content.trim());
+        out.push('\n');
+
+        if !message.attachments.is_empty() {
+            out.push_str("\n**Attachments**\n\n");
+            for attachment in &message.attachments {
+                out.push_str(&format!("- [{}]({}) ({})\n", attachment.file_name, attachment.url, attachment.file_type));
+            }
+        }
+
+        if !message.citations.is_empty() {
+            out.push_str("\n**Sources**\n\n");
+            for citation in &message.citations {
+                out.push_str(&format!("{}. [{}]({})\n", citation.index, citation.title, citation.source_uri));
+            }
+        }
+    }
+
+    out
+}
+
+// Titles become file names, so keep only characters that are safe everywhere
+fn file_stem(export: &SessionExport) -> String {
+    let title = export.title.as_deref().unwrap_or("chat");
+    let mut stem: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    while stem.contains("--") {
+        stem = stem.replace("--", "-");
+    }
+    let stem = stem.trim_matches('-');
+    if stem.is_empty() {
+        format!("chat-{}", export.exported_at.format("%Y%m%d"))
+    } else {
+        stem.chars().take(60).collect()
+    }
+}
+
+// An attachment's path in a zip archive: the name it was uploaded with, prefixed with its
+// id so two uploads with the same name don't collide. The name came from the uploader, so
+// only its last component is kept, and nothing in it can lead out of the folder the
+// archive is extracted into.
+pub fn archive_entry_name(attachment: &FileAttachment) -> String {
+    // Ids from imports needn't be uuids, so take up to eight characters
+    let id_prefix: String = attachment.id.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .take(8)
+        .collect();
+    let base_name: String = attachment.file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_control() || c == ':' { '_' } else { c })
+        .collect();
+    let base_name = base_name.trim();
+    if base_name.is_empty() || base_name == "." || base_name == ".." {
+        format!("{}_attachment", id_prefix)
+    } else {
+        format!("{}_{}", id_prefix, base_name)
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use scraper::Html;
+use crate::{api::{self, AppState}, crawler, knowledge_base, models::*, outbound};
+
+const USER_AGENT: &str = "aibot-feeds/0.1";
+const REQUEST_DELAY_MS: u64 = 250;
+// Feeds larger than this aren't read
+const MAX_FEED_BYTES: usize = 10_000_000;
+// The most often a feed may be polled
+pub const MIN_INTERVAL_MINUTES: i64 = 15;
+pub const DEFAULT_INTERVAL_MINUTES: i64 = 60;
+// Feeds one knowledge base may have
+pub const MAX_FEEDS: usize = 50;
+const DIGEST_INTERVAL_HOURS: i64 = 24;
+// Only a feed's newest items are taken in one poll, so adding a feed with a long back
+// catalogue doesn't index all of it
+const MAX_ITEMS_PER_POLL: usize = 50;
+// Items whose own text is shorter than this, usually just a teaser, have their linked page
+// indexed instead
+const MIN_ITEM_CHARS: usize = 500;
+// Length of the text kept with each item for the digest
+const MAX_SUMMARY_CHARS: usize = 1_000;
+// Items beyond this many characters are left out of the digest
+const MAX_DIGEST_CHARS: usize = 50_000;
+
+// Downloads and parses an RSS, Atom or JSON feed
+async fn fetch(url: &str) -> Result<feed_rs::model::Feed> {
+    let response = outbound::client().get(url)
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?
+        .error_for_status()?;
+    let body = outbound::read_body(response, MAX_FEED_BYTES).await?;
+    feed_rs::parser::parse(&body[..]).map_err(|e| anyhow::anyhow!("{} isn't a feed: {}", url, e))
+}
+
+// Plain text of an item's summary or content, which feeds usually send as HTML
+fn html_text(html: &str) -> String {
+    crawler::page_text(&Html::parse_fragment(html)).trim().to_string()
+}
+
+fn check_digest_model(provider: Option<&str>, model_name: Option<&str>) -> Result<()> {
+    match (provider, model_name) {
+        (None, None) => Ok(()),
+        (Some(provider), Some(model_name)) if !model_name.trim().is_empty() => {
+            if AIProvider::from(provider.to_string()).to_string() != provider {
+                return Err(anyhow::anyhow!("Unknown model provider"));
+            }
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!("Pick both a provider and a model to write the digest")),
+    }
+}
+
+// Reads a feed to check it is one, saves it and queues its first poll
+pub async fn create(
+    state: &AppState,
+    kb_id: String,
+    url: String,
+    sync_interval_minutes: Option<i64>,
+    digest_model_provider: Option<String>,
+    digest_model_name: Option<String>,
+) -> Result<(FeedSource, IngestionJob)> {
+    let url = url.trim().to_string();
+    let parsed = Url::parse(&url).map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?;
+    outbound::check_url(&parsed).await?;
+    let sources = state.db.get_kb_feed_sources(&kb_id).await?;
+    if sources.len() >= MAX_FEEDS {
+        return Err(anyhow::anyhow!("A knowledge base can have at most {} feeds", MAX_FEEDS));
+    }
+    if sources.iter().any(|source| source.url == url) {
+        return Err(anyhow::anyhow!("This feed is already added"));
+    }
+    check_digest_model(digest_model_provider.as_deref(), digest_model_name.as_deref())?;
+
+    let feed = fetch(&url).await?;
+    let title = feed.title.map(|title| title.content.trim().to_string()).filter(|title| !title.is_empty());
+    let source = FeedSource::new(
+        kb_id,
+        url,
+        title,
+        sync_interval_minutes.unwrap_or(DEFAULT_INTERVAL_MINUTES).max(MIN_INTERVAL_MINUTES),
+        digest_model_provider,
+        digest_model_name,
+    );
+    state.db.create_feed_source(&source).await?;
+
+    let job = start_poll(state, source.clone()).await?;
+    Ok((source, job))
+}
+
+// Changes how often a feed is polled and the model writing its digest, if any
+pub async fn update(
+    state: &AppState,
+    source: &mut FeedSource,
+    sync_interval_minutes: i64,
+    digest_model_provider: Option<String>,
+    digest_model_name: Option<String>,
+) -> Result<()> {
+    check_digest_model(digest_model_provider.as_deref(), digest_model_name.as_deref())?;
+    source.sync_interval_minutes = sync_interval_minutes.max(MIN_INTERVAL_MINUTES);
+    source.digest_model_provider = digest_model_provider;
+    source.digest_model_name = digest_model_name;
+    state.db.update_feed_source(source).await
+}
+
+// Polls a feed and records the outcome on it. Returns the number of new items indexed.
+pub async fn sync_feed_source(state: AppState, job_id: String, source: FeedSource) -> Result<i64> {
+    // Stamp the poll as started so the scheduler doesn't queue it again meanwhile
+    state.db.record_feed_sync(&source.id, JobStatus::Running, None).await?;
+
+    let result = poll(&state, &job_id, &source).await;
+    match &result {
+        Ok(_) => state.db.record_feed_sync(&source.id, JobStatus::Completed, None).await?,
+        Err(e) => state.db.record_feed_sync(&source.id, JobStatus::Failed, Some(&e.to_string())).await?,
+    }
+    result
+}
+
+// Queues a background poll of a feed and returns the job tracking it
+pub async fn start_poll(state: &AppState, source: FeedSource) -> Result<IngestionJob> {
+    let job = IngestionJob::new(source.knowledge_base_id.clone(), "rss", source.url.clone());
+    state.db.create_ingestion_job(&job).await?;
+
+    let work = sync_feed_source(state.clone(), job.id.clone(), source);
+    knowledge_base::run_in_background(state.clone(), job.id.clone(), work);
+    Ok(job)
+}
+
+// Indexes the feed's items that haven't been seen before into its knowledge base
+async fn poll(state: &AppState, job_id: &str, source: &FeedSource) -> Result<i64> {
+    let mut entries = fetch(&source.url).await?.entries;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.published.or(entry.updated)));
+
+    let mut ingested = 0i64;
+    for entry in entries.into_iter().take(MAX_ITEMS_PER_POLL) {
+        if state.db.feed_item_exists(&source.id, &entry.id).await? {
+            continue;
+        }
+
+        let title = entry.title
+            .map(|title| html_text(&title.content))
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "Untitled item".to_string());
+        let link = entry.links.first().map(|link| link.href.clone());
+        let own_text = entry.content
+            .and_then(|content| content.body)
+            .or(entry.summary.map(|summary| summary.content))
+            .map(|html| html_text(&html))
+            .unwrap_or_default();
+
+        let mut text = own_text.clone();
+        if text.chars().count() < MIN_ITEM_CHARS {
+            if let Some(link) = &link {
+                match crawler::fetch_page_text(link).await {
+                    Ok((_, page)) if !page.trim().is_empty() => text = page,
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Feed reader couldn't read {}: {}", link, e),
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(REQUEST_DELAY_MS)).await;
+            }
+        }
+
+        let source_uri = link.clone().unwrap_or_else(|| format!("{}#{}", source.url, entry.id));
+        knowledge_base::ingest_text(state, &source.knowledge_base_id, "rss", &source_uri, &title, &format!("{}\n\n{}", title, text)).await?;
+
+        let summary = if own_text.is_empty() { &text } else { &own_text };
+        state.db.create_feed_item(&FeedItem {
+            feed_source_id: source.id.clone(),
+            guid: entry.id,
+            title,
+            link,
+            summary: summary.chars().take(MAX_SUMMARY_CHARS).collect(),
+            published_at: entry.published.or(entry.updated),
+            created_at: Utc::now(),
+        }).await?;
+        ingested += 1;
+        state.db.update_ingestion_job(job_id, JobStatus::Running, ingested, None).await?;
+    }
+
+    Ok(ingested)
+}
+
+// Starts the digest of every feed that has one and whose last digest, or creation, was a
+// day ago. Each is moved on first, so one still being written isn't started twice.
+pub async fn send_due_digests(state: &AppState) -> Result<()> {
+    let now = Utc::now();
+    for source in state.db.get_feed_sources().await? {
+        let (Some(provider), Some(model_name)) = (source.digest_model_provider.clone(), source.digest_model_name.clone()) else {
+            continue;
+        };
+        let since = source.last_digest_at.unwrap_or(source.created_at);
+        if since + Duration::hours(DIGEST_INTERVAL_HOURS) > now || !state.db.claim_feed_digest(&source.id, since, now).await? {
+            continue;
+        }
+        let state = state.clone();
+        state.shutdown.clone().spawn(async move {
+            if let Err(e) = digest(&state, &source, AIProvider::from(provider), model_name, since).await {
+                tracing::error!("Digest of feed {} failed: {}", source.id, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Opens a session of the knowledge base owner's and has the model summarize the items
+// indexed since `since` in it. Nothing is written when there are none.
+async fn digest(state: &AppState, source: &FeedSource, provider: AIProvider, model_name: String, since: DateTime<Utc>) -> Result<()> {
+    let items = state.db.get_feed_items_since(&source.id, since).await?;
+    if items.is_empty() {
+        return Ok(());
+    }
+    let kb = state.db.get_knowledge_base(&source.knowledge_base_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Knowledge base not found"))?;
+
+    let mut listing = String::new();
+    let mut listed = 0;
+    for item in &items {
+        let mut entry = format!("## {}\n", item.title);
+        if let Some(link) = &item.link {
+            entry.push_str(&format!("{}\n", link));
+        }
+        if let Some(published_at) = item.published_at {
+            entry.push_str(&format!("Published {}\n", published_at.format("%Y-%m-%d %H:%M UTC")));
+        }
+        entry.push_str(&format!("\n{}\n\n", item.summary));
+        if listed > 0 && listing.len() + entry.len() > MAX_DIGEST_CHARS {
+            break;
+        }
+        listing.push_str(&entry);
+        listed += 1;
+    }
+
+    let name = source.title.clone().unwrap_or_else(|| source.url.clone());
+    let title = format!("{} digest, {}", name, Utc::now().format("%Y-%m-%d"));
+    let session = api::create_session_as(state, &kb.user_id, Some(title), provider, model_name, false).await?;
+    let prompt = format!(
+        "Attached are the {} items new in the feed \"{}\" since {}. Summarize what changed: group related items, lead with the most significant, and link to the items you mention.",
+        listed,
+        name,
+        since.format("%Y-%m-%d %H:%M UTC"),
+    );
+    let files = vec![FileUpload {
+        name: "feed-items.md".to_string(),
+        content_type: "text/markdown".to_string(),
+        data: listing.into_bytes(),
+        transcript: None,
+    }];
+    api::send_message_as(state, &kb.user_id, session.id.clone(), prompt, files, Vec::new(), Vec::new(), false).await?;
+
+    // Follow-up questions in the digest can then draw on the full items
+    state.db.link_session_knowledge_base(&session.id, &kb.id).await
+}
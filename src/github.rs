@@ -0,0 +1,265 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::{api::AppState, knowledge_base, models::*};
+
+// Files larger than this are usually generated or vendored
+const MAX_FILE_BYTES: u64 = 200_000;
+
+const DEFAULT_EXTENSIONS: [&str; 24] = [
+    "md", "mdx", "txt", "rst", "adoc",
+    "rs", "py", "js", "ts", "tsx", "jsx", "go", "java", "kt", "c", "h", "cpp", "cs", "rb", "php", "swift",
+    "toml", "yaml", "yml",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoOptions {
+    // Defaults to the repository's default branch
+    pub branch: Option<String>,
+    // Only ingest files under this directory
+    pub path_prefix: Option<String>,
+    // File extensions to ingest; empty means the built-in list
+    pub extensions: Vec<String>,
+}
+
+// Accepts "owner/repo" or a github.com URL. Both parts go into URL paths, so only the
+// characters GitHub allows in them are accepted.
+pub fn parse_repo(input: &str) -> Result<(String, String)> {
+    let trimmed = input
+        .trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("github.com/")
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+
+    let mut parts = trimmed.split('/');
+    match (parts.next(), parts.next()) {
+        (Some(owner), Some(repo)) if valid_owner(owner) && valid_repo(repo) => Ok((owner.to_string(), repo.to_string())),
+        _ => Err(anyhow::anyhow!("Expected a repository like 'owner/repo', got '{}'", input)),
+    }
+}
+
+fn valid_owner(owner: &str) -> bool {
+    !owner.is_empty() && owner.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn valid_repo(repo: &str) -> bool {
+    !matches!(repo, "" | "." | "..") && repo.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+// Checks a branch name against git's rules for ref names. It goes into URL paths as it
+// is, slashes included, so nothing that could step out of the path or end it is allowed.
+pub fn check_branch(branch: &str) -> Result<()> {
+    let valid = !branch.is_empty()
+        && !branch.starts_with(['/', '-', '.'])
+        && !branch.ends_with(['/', '.'])
+        && !branch.contains("..")
+        && !branch.contains("//")
+        && !branch.contains("@{")
+        && !branch.ends_with(".lock")
+        && branch.split('/').all(|part| !part.starts_with('.'))
+        && branch.chars().all(|c| c.is_ascii_graphic() && !matches!(c, '~' | '^' | ':' | '?' | '*' | '[' | '\\' | '#' | '%'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("'{}' isn't a valid branch name", branch))
+    }
+}
+
+// A file path from the repository's tree, encoded one segment at a time
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| percent_encoding::utf8_percent_encode(segment, percent_encoding::NON_ALPHANUMERIC).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Checks a personal access token with GitHub before it is saved
+pub async fn check_token(token: &str) -> Result<()> {
+    let client = GitHubClient::new(Some(token.to_string()))?;
+    let response = client.get("https://api.github.com/user").send().await?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(anyhow::anyhow!("GitHub rejected this token")),
+        status => Err(anyhow::anyhow!("Couldn't check the token with GitHub: {}", status)),
+    }
+}
+
+// Ingests the text files of a GitHub repository into a knowledge base for the user.
+// Returns the number of files ingested.
+pub async fn ingest_repository(
+    state: AppState,
+    job_id: String,
+    user_id: String,
+    kb_id: String,
+    repo: String,
+    options: RepoOptions,
+) -> Result<i64> {
+    let (owner, name) = parse_repo(&repo)?;
+    if let Some(branch) = &options.branch {
+        check_branch(branch)?;
+    }
+    // The user's own token reaches their private repositories. GITHUB_TOKEN can read
+    // whatever its owner can, so it only raises the rate limit, and without the user's
+    // token the repository must be readable by anyone.
+    let (client, default_branch) = match crate::api_keys::github_token(&state, &user_id).await? {
+        Some(token) => {
+            let client = GitHubClient::new(Some(token))?;
+            let default_branch = client.default_branch(&owner, &name).await?;
+            (client, default_branch)
+        }
+        None => {
+            let client = GitHubClient::new(std::env::var("GITHUB_TOKEN").ok())?;
+            let default_branch = client.public_default_branch(&owner, &name).await?;
+            (client, default_branch)
+        }
+    };
+
+    let branch = match options.branch {
+        Some(branch) => branch,
+        None => {
+            check_branch(&default_branch)?;
+            default_branch
+        }
+    };
+    let extensions: Vec<String> = if options.extensions.is_empty() {
+        DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect()
+    } else {
+        options.extensions.iter().map(|ext| ext.trim_start_matches('.').to_lowercase()).collect()
+    };
+    let prefix = options.path_prefix.unwrap_or_default();
+
+    let tree = client.tree(&owner, &name, &branch).await?;
+    let mut ingested = 0i64;
+
+    for entry in tree {
+        if entry.size > MAX_FILE_BYTES || !entry.path.starts_with(&prefix) {
+            continue;
+        }
+        let extension = entry.path.rsplit('.').next().unwrap_or("").to_lowercase();
+        if !extensions.contains(&extension) {
+            continue;
+        }
+
+        let text = match client.raw_file(&owner, &name, &branch, &entry.path).await {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("Skipping {}/{}:{}: {}", owner, name, entry.path, e);
+                continue;
+            }
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let source_uri = format!("https://github.com/{}/{}/blob/{}/{}", owner, name, branch, encode_path(&entry.path));
+        let chunks = knowledge_base::chunk_code(&text, &extension);
+        knowledge_base::ingest_chunks(&state, None, &kb_id, "github", &source_uri, &entry.path, &text, chunks).await?;
+        ingested += 1;
+        state.db.update_ingestion_job(&job_id, JobStatus::Running, ingested, None).await?;
+    }
+
+    Ok(ingested)
+}
+
+struct TreeEntry {
+    path: String,
+    size: u64,
+}
+
+struct GitHubClient {
+    http: reqwest::Client,
+    token: Option<String>,
+}
+
+impl GitHubClient {
+    fn new(token: Option<String>) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().user_agent("aibot").build()?,
+            token,
+        })
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.http.get(url).header("Accept", "application/vnd.github+json");
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    // Asked with the token, for a repository its owner can read
+    async fn default_branch(&self, owner: &str, repo: &str) -> Result<String> {
+        let response = self.get(&format!("https://api.github.com/repos/{}/{}", owner, repo)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("Repository {}/{} doesn't exist or your GitHub token can't read it", owner, repo));
+        }
+        let response: Value = response.error_for_status()?.json().await?;
+        Self::branch_of(&response, owner, repo)
+    }
+
+    // Asked without the token, so a private repository looks missing
+    async fn public_default_branch(&self, owner: &str, repo: &str) -> Result<String> {
+        let response = self
+            .http
+            .get(format!("https://api.github.com/repos/{}/{}", owner, repo))
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("Repository {}/{} doesn't exist or isn't public; save a GitHub token to ingest private ones", owner, repo));
+        }
+        let response: Value = response.error_for_status()?.json().await?;
+        if response["private"].as_bool() != Some(false) {
+            return Err(anyhow::anyhow!("Only public repositories can be ingested without a GitHub token"));
+        }
+        Self::branch_of(&response, owner, repo)
+    }
+
+    fn branch_of(repository: &Value, owner: &str, repo: &str) -> Result<String> {
+        repository["default_branch"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Repository {}/{} has no default branch", owner, repo))
+    }
+
+    async fn tree(&self, owner: &str, repo: &str, branch: &str) -> Result<Vec<TreeEntry>> {
+        let response: Value = self
+            .get(&format!("https://api.github.com/repos/{}/{}/git/trees/{}?recursive=1", owner, repo, branch))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response["truncated"].as_bool().unwrap_or(false) {
+            tracing::warn!("Tree for {}/{} was truncated by GitHub; some files will be missing", owner, repo);
+        }
+
+        Ok(response["tree"]
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|entry| entry["type"] == "blob")
+                    .filter_map(|entry| {
+                        Some(TreeEntry {
+                            path: entry["path"].as_str()?.to_string(),
+                            size: entry["size"].as_u64().unwrap_or(0),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn raw_file(&self, owner: &str, repo: &str, branch: &str, path: &str) -> Result<String> {
+        let url = format!("https://raw.githubusercontent.com/{}/{}/{}/{}", owner, repo, branch, encode_path(path));
+        let mut request = self.http.get(&url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        Ok(request.send().await?.error_for_status()?.text().await?)
+    }
+}
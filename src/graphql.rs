@@ -0,0 +1,391 @@
+use async_graphql::{
+    ComplexObject, Context, Enum, Object, Result, Schema, SimpleObject, Subscription, Union, ID,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::{
+    extract::{ws::WebSocketUpgrade, Extension},
+    http::Method,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::Stream;
+use crate::{
+    api::{self, AppState},
+    auth::AuthUser,
+    database::OwnedResource,
+    models::{self, AIProvider, MessageCursor, SessionCursor},
+    rate_limit::RateLimiter,
+    rest_api::{encode_cursor, page_size, parse_cursor},
+};
+
+// A GraphQL view of sessions, messages, memories and usage, for frontends that prefer it to
+// the server functions. Queries and mutations are POSTed to `/api/graphql`, or GET for
+// queries alone; subscriptions run over a WebSocket at `/api/graphql/ws`. Both act for the
+// signed-in user, by session cookie or personal access token.
+pub type AibotSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+pub fn schema(state: AppState, limiter: RateLimiter) -> AibotSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(state)
+        .data(limiter)
+        .finish()
+}
+
+// Marks a request that may only read, such as a GET, which cookies are sent on from other
+// sites' links and read-only tokens are allowed to make
+struct ReadOnly;
+
+fn state<'a>(ctx: &Context<'a>) -> &'a AppState {
+    ctx.data_unchecked::<AppState>()
+}
+
+fn user_id<'a>(ctx: &Context<'a>) -> Result<&'a str> {
+    Ok(ctx.data::<AuthUser>()?.user_id.as_str())
+}
+
+fn writable(ctx: &Context<'_>) -> Result<()> {
+    if ctx.data_opt::<ReadOnly>().is_some() {
+        return Err("Mutations must be POSTed".into());
+    }
+    Ok(())
+}
+
+fn cursor<C: serde::de::DeserializeOwned>(cursor: Option<&str>) -> Result<Option<C>> {
+    cursor.map(|cursor| parse_cursor(cursor).ok_or_else(|| "Invalid cursor".into())).transpose()
+}
+
+pub async fn graphql(
+    Extension(schema): Extension<AibotSchema>,
+    user: AuthUser,
+    method: Method,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut request = request.into_inner().data(user);
+    if method == Method::GET {
+        request = request.data(ReadOnly);
+    }
+    schema.execute(request).await.into()
+}
+
+pub async fn graphql_ws(
+    Extension(schema): Extension<AibotSchema>,
+    user: AuthUser,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade
+        .protocols(async_graphql::http::ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            let mut data = async_graphql::Data::default();
+            data.insert(user);
+            GraphQLWebSocket::new(stream, schema, protocol).with_data(data).serve()
+        })
+}
+
+// The schema in SDL, for code generators
+pub async fn sdl(Extension(schema): Extension<AibotSchema>) -> impl IntoResponse {
+    schema.sdl()
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Session {
+    pub id: ID,
+    pub title: Option<String>,
+    pub model_provider: String,
+    pub model_name: String,
+    pub incognito: bool,
+    pub archived: bool,
+    pub pinned: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<models::ChatSession> for Session {
+    fn from(session: models::ChatSession) -> Self {
+        Self {
+            id: ID(session.id),
+            title: session.title,
+            model_provider: session.model_provider,
+            model_name: session.model_name,
+            incognito: session.incognito,
+            archived: session.archived,
+            pinned: session.pinned,
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Session {
+    // The session's history, newest page first, each page oldest first
+    async fn messages(&self, ctx: &Context<'_>, limit: Option<i64>, before: Option<String>) -> Result<MessagePage> {
+        let before: Option<MessageCursor> = cursor(before.as_deref())?;
+        let page = state(ctx).db.get_session_messages_page(&self.id, before.as_ref(), page_size(limit)).await?;
+        Ok(MessagePage {
+            messages: page.items.into_iter().map(Message::from).collect(),
+            next_before: page.next_cursor.as_ref().and_then(encode_cursor),
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SessionPage {
+    pub sessions: Vec<Session>,
+    // Pass as `cursor` to get the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Message {
+    pub id: ID,
+    pub session_id: ID,
+    // user, assistant or system
+    pub role: String,
+    pub content: String,
+    pub model_provider: Option<String>,
+    pub model_name: Option<String>,
+    pub tokens_used: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<models::Message> for Message {
+    fn from(message: models::Message) -> Self {
+        Self {
+            id: ID(message.id),
+            session_id: ID(message.session_id),
+            role: message.role.to_string(),
+            content: message.content,
+            model_provider: message.model_provider,
+            model_name: message.model_name,
+            tokens_used: message.tokens_used,
+            created_at: message.created_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    // Pass as `before` to get the page of earlier messages; null when there are none
+    pub next_before: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct Memory {
+    pub id: ID,
+    pub key: String,
+    pub value: String,
+    // After decay, as the model sees it
+    pub confidence: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(SimpleObject)]
+pub struct Usage {
+    pub day: NaiveDate,
+    pub model_provider: String,
+    pub model_name: String,
+    pub user_messages: i64,
+    pub assistant_messages: i64,
+    pub tokens: i64,
+    // USD estimate; null when the model has no known price
+    pub estimated_cost: Option<f64>,
+}
+
+#[derive(SimpleObject)]
+pub struct MessageReply {
+    // The assistant's reply, as saved in the session
+    pub message: Message,
+    pub suggested_questions: Vec<String>,
+    // Set when the content filter flagged the message or reply but let it through
+    pub moderation_warning: Option<String>,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    // A message was sent and the reply is being written
+    Responding,
+    Idle,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct MessageEvent {
+    pub message: Message,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct StatusEvent {
+    pub status: SessionStatus,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct RenamedEvent {
+    pub title: Option<String>,
+}
+
+// Sent instead of the events a subscriber fell too far behind to get; reload the session
+#[derive(SimpleObject, Clone)]
+pub struct LaggedEvent {
+    pub missed: u64,
+}
+
+#[derive(Union, Clone)]
+pub enum SessionEvent {
+    Message(MessageEvent),
+    Status(StatusEvent),
+    Renamed(RenamedEvent),
+    Lagged(LaggedEvent),
+}
+
+impl From<models::SessionEvent> for SessionEvent {
+    fn from(event: models::SessionEvent) -> Self {
+        match event {
+            models::SessionEvent::Message { message } => SessionEvent::Message(MessageEvent { message: message.into() }),
+            models::SessionEvent::Status { status } => SessionEvent::Status(StatusEvent {
+                status: match status {
+                    models::SessionStatus::Responding => SessionStatus::Responding,
+                    models::SessionStatus::Idle => SessionStatus::Idle,
+                },
+            }),
+            models::SessionEvent::Renamed { title } => SessionEvent::Renamed(RenamedEvent { title }),
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // The user's sessions, pinned first, then by most recent activity
+    async fn sessions(&self, ctx: &Context<'_>, limit: Option<i64>, cursor: Option<String>, #[graphql(default)] include_archived: bool) -> Result<SessionPage> {
+        let after: Option<SessionCursor> = self::cursor(cursor.as_deref())?;
+        let page = state(ctx).db.get_user_sessions(user_id(ctx)?, include_archived, None, after.as_ref(), page_size(limit)).await?;
+        Ok(SessionPage {
+            sessions: page.items.into_iter().map(Session::from).collect(),
+            next_cursor: page.next_cursor.as_ref().and_then(encode_cursor),
+        })
+    }
+
+    async fn session(&self, ctx: &Context<'_>, id: ID) -> Result<Session> {
+        let state = state(ctx);
+        crate::auth::authorize(state, user_id(ctx)?, OwnedResource::Session, &id).await?;
+        let session = state.db.get_session(&id).await?
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        Ok(session.into())
+    }
+
+    // What the assistant remembers about the user, leaving out what has decayed away
+    async fn memories(&self, ctx: &Context<'_>) -> Result<Vec<Memory>> {
+        let state = state(ctx);
+        let now = Utc::now();
+        let memories = state.memory_policy.active_memories(state.db.get_user_memory(user_id(ctx)?).await?, now);
+        Ok(memories.into_iter().map(|memory| Memory {
+            confidence: state.memory_policy.effective_confidence(&memory, now),
+            id: ID(memory.id),
+            key: memory.memory_key,
+            value: memory.memory_value,
+            updated_at: memory.updated_at,
+        }).collect())
+    }
+
+    // The user's daily usage per model between two days, inclusive. Figures come from the
+    // hourly rollup, so today's are up to an hour behind.
+    async fn usage(&self, ctx: &Context<'_>, from: NaiveDate, to: NaiveDate) -> Result<Vec<Usage>> {
+        let usage = state(ctx).db.get_daily_usage(Some(user_id(ctx)?), from, to).await?;
+        Ok(usage.into_iter().map(|usage| Usage {
+            day: usage.day,
+            model_provider: usage.model_provider,
+            model_name: usage.model_name,
+            user_messages: usage.user_messages,
+            assistant_messages: usage.assistant_messages,
+            tokens: usage.tokens,
+            estimated_cost: usage.estimated_cost,
+        }).collect())
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_session(
+        &self,
+        ctx: &Context<'_>,
+        // ollama, openai, anthropic, gemini or openrouter
+        model_provider: String,
+        model_name: String,
+        title: Option<String>,
+        #[graphql(default)] incognito: bool,
+    ) -> Result<Session> {
+        writable(ctx)?;
+        let provider = AIProvider::from(model_provider.clone());
+        if provider.to_string() != model_provider {
+            return Err("Unknown model provider".into());
+        }
+        Ok(api::create_session_as(state(ctx), user_id(ctx)?, title, provider, model_name, incognito).await?.into())
+    }
+
+    // An empty title clears it
+    async fn rename_session(&self, ctx: &Context<'_>, id: ID, title: String) -> Result<Session> {
+        writable(ctx)?;
+        let state = state(ctx);
+        api::rename_session_as(state, user_id(ctx)?, &id, &title).await?;
+        let session = state.db.get_session(&id).await?
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        Ok(session.into())
+    }
+
+    // Moves a session to the trash, where it can be restored from the app until it is purged
+    async fn delete_session(&self, ctx: &Context<'_>, id: ID) -> Result<bool> {
+        writable(ctx)?;
+        let (state, user_id) = (state(ctx), user_id(ctx)?);
+        api::delete_session_as(state, user_id, &id).await?;
+        Ok(true)
+    }
+
+    // Sends a message and waits for the reply. Counts toward the same message rate limit as
+    // chatting in the app; subscribe to the session's events to see it arrive.
+    async fn send_message(&self, ctx: &Context<'_>, session_id: ID, content: String) -> Result<MessageReply> {
+        writable(ctx)?;
+        let (state, user_id) = (state(ctx), user_id(ctx)?);
+        if content.trim().is_empty() {
+            return Err("Message content is empty".into());
+        }
+        if let Err(wait) = ctx.data_unchecked::<RateLimiter>().take_message(user_id) {
+            let retry_after_secs = (wait.as_secs_f64().ceil() as u64).max(1);
+            return Err(format!("Too many messages. Try again in {} seconds.", retry_after_secs).into());
+        }
+        let reply = api::send_message_as(state, user_id, session_id.0, content, Vec::new(), Vec::new(), Vec::new(), false).await?;
+        let message = state.db.get_message(&reply.message_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+        Ok(MessageReply {
+            message: message.into(),
+            suggested_questions: reply.suggested_questions,
+            moderation_warning: reply.moderation_warning,
+        })
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    // What happens in one of the user's sessions from now on: messages as they are saved,
+    // whether a reply is being written, and renames
+    async fn session_events(&self, ctx: &Context<'_>, session_id: ID) -> Result<impl Stream<Item = SessionEvent>> {
+        let state = state(ctx);
+        crate::auth::authorize(state, user_id(ctx)?, OwnedResource::Session, &session_id).await?;
+        let mut events = state.session_events.subscribe(&session_id);
+        Ok(async_stream::stream! {
+            loop {
+                match events.recv().await {
+                    Ok(event) => yield SessionEvent::from(event),
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => yield SessionEvent::Lagged(LaggedEvent { missed }),
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
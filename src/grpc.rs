@@ -0,0 +1,275 @@
+use axum::http::{Method, StatusCode};
+use futures::Stream;
+use std::{net::SocketAddr, pin::Pin};
+use tokio::sync::broadcast::error::TryRecvError;
+use tonic::{Request, Response, Status};
+use crate::{
+    api::{self, AppState},
+    database::OwnedResource,
+    models::{AIProvider, ChatSession, MessageCursor, SessionCursor, SessionEvent},
+    rate_limit::RateLimiter,
+    rest_api::{encode_cursor, page_size, parse_cursor},
+};
+
+pub mod proto {
+    tonic::include_proto!("aibot.v1");
+}
+
+use proto::{
+    chat_event::Event,
+    chat_server::{Chat, ChatServer},
+    sessions_server::{Sessions, SessionsServer},
+};
+
+// The gRPC API, for integrations where REST or server-sent events are awkward. It shares
+// the app's state, so it sees the same sessions, limits and live events, but listens on its
+// own address.
+pub async fn serve(state: AppState, limiter: RateLimiter, addr: SocketAddr) -> anyhow::Result<()> {
+    let service = GrpcService { state, limiter };
+    tonic::transport::Server::builder()
+        .add_service(ChatServer::new(service.clone()))
+        .add_service(SessionsServer::new(service))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[derive(Clone)]
+struct GrpcService {
+    state: AppState,
+    limiter: RateLimiter,
+}
+
+// Records that are missing or belong to someone else are NOT_FOUND, as in the REST API
+fn status(e: anyhow::Error) -> Status {
+    let message = e.to_string();
+    if message.ends_with("not found") {
+        Status::not_found(message)
+    } else {
+        tracing::warn!("gRPC request failed: {}", message);
+        Status::invalid_argument(message)
+    }
+}
+
+fn timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.to_rfc3339()
+}
+
+impl From<ChatSession> for proto::Session {
+    fn from(session: ChatSession) -> Self {
+        Self {
+            id: session.id,
+            title: session.title,
+            model_provider: session.model_provider,
+            model_name: session.model_name,
+            incognito: session.incognito,
+            archived: session.archived,
+            pinned: session.pinned,
+            created_at: timestamp(session.created_at),
+            updated_at: timestamp(session.updated_at),
+        }
+    }
+}
+
+impl From<crate::models::Message> for proto::Message {
+    fn from(message: crate::models::Message) -> Self {
+        Self {
+            id: message.id,
+            session_id: message.session_id,
+            role: message.role.to_string(),
+            content: message.content,
+            model_provider: message.model_provider,
+            model_name: message.model_name,
+            tokens_used: message.tokens_used,
+            created_at: timestamp(message.created_at),
+        }
+    }
+}
+
+impl GrpcService {
+    // The user the call's personal access token acts for. Read tokens can only make the
+    // calls that change nothing, as with GET requests to the REST API.
+    async fn user_id<T>(&self, request: &Request<T>, rpc: &str, read_only: bool) -> Result<String, Status> {
+        let token = request.metadata().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| Status::unauthenticated("Missing API token"))?;
+        let method = if read_only { Method::GET } else { Method::POST };
+        crate::api_tokens::authenticate(&self.state, token, &method, rpc).await
+            .map_err(|(code, message)| match code {
+                StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+                StatusCode::FORBIDDEN => Status::permission_denied(message),
+                _ => Status::internal(message),
+            })
+    }
+
+    // Checks a message may be sent, counting it toward the same rate limit as the app
+    async fn start_chat(&self, request: &Request<proto::ChatRequest>, rpc: &str) -> Result<String, Status> {
+        let user_id = self.user_id(request, rpc, false).await?;
+        if request.get_ref().content.trim().is_empty() {
+            return Err(Status::invalid_argument("Message content is empty"));
+        }
+        if let Err(wait) = self.limiter.take_message(&user_id) {
+            let retry_after_secs = (wait.as_secs_f64().ceil() as u64).max(1);
+            return Err(Status::resource_exhausted(format!("Too many messages. Try again in {} seconds.", retry_after_secs)));
+        }
+        Ok(user_id)
+    }
+
+    async fn reply(state: &AppState, response: crate::models::ChatResponse) -> Result<proto::ChatReply, Status> {
+        let message = state.db.get_message(&response.message_id).await.map_err(status)?
+            .ok_or_else(|| Status::not_found("Message not found"))?;
+        Ok(proto::ChatReply {
+            message: Some(message.into()),
+            suggested_questions: response.suggested_questions,
+            moderation_warning: response.moderation_warning,
+        })
+    }
+}
+
+fn chat_event(event: SessionEvent) -> Option<proto::ChatEvent> {
+    let event = match event {
+        SessionEvent::Status { status } => Event::Status(match status {
+            crate::models::SessionStatus::Responding => proto::SessionStatus::Responding,
+            crate::models::SessionStatus::Idle => proto::SessionStatus::Idle,
+        } as i32),
+        SessionEvent::Message { message } => Event::Message(message.into()),
+        SessionEvent::Renamed { .. } => return None,
+    };
+    Some(proto::ChatEvent { event: Some(event) })
+}
+
+type ChatEventStream = Pin<Box<dyn Stream<Item = Result<proto::ChatEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Chat for GrpcService {
+    async fn chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<proto::ChatReply>, Status> {
+        let user_id = self.start_chat(&request, "/aibot.v1.Chat/Chat").await?;
+        let proto::ChatRequest { session_id, content } = request.into_inner();
+        let response = api::send_message_as(&self.state, &user_id, session_id, content, Vec::new(), Vec::new(), Vec::new(), false).await
+            .map_err(status)?;
+        Ok(Response::new(Self::reply(&self.state, response).await?))
+    }
+
+    type StreamChatStream = ChatEventStream;
+
+    async fn stream_chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<ChatEventStream>, Status> {
+        let user_id = self.start_chat(&request, "/aibot.v1.Chat/StreamChat").await?;
+        let proto::ChatRequest { session_id, content } = request.into_inner();
+        crate::auth::authorize(&self.state, &user_id, OwnedResource::Session, &session_id).await.map_err(status)?;
+
+        // Subscribe before sending so no event of the exchange is missed. The exchange runs
+        // on its own, so it is still saved if the caller goes away before the reply.
+        let mut events = self.state.session_events.subscribe(&session_id);
+        let state = self.state.clone();
+        let mut exchange = tokio::spawn({
+            let state = state.clone();
+            async move {
+                api::send_message_as(&state, &user_id, session_id, content, Vec::new(), Vec::new(), Vec::new(), false).await
+            }
+        });
+
+        let stream = async_stream::stream! {
+            let result = loop {
+                let event = tokio::select! {
+                    result = &mut exchange => break result,
+                    event = events.recv() => event.ok().and_then(chat_event),
+                };
+                if let Some(event) = event {
+                    yield Ok(event);
+                }
+            };
+            // Events published as the exchange finished are still waiting
+            loop {
+                match events.try_recv() {
+                    Ok(event) => {
+                        if let Some(event) = chat_event(event) {
+                            yield Ok(event);
+                        }
+                    }
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+            match result {
+                Ok(Ok(response)) => match Self::reply(&state, response).await {
+                    Ok(reply) => yield Ok(proto::ChatEvent { event: Some(Event::Reply(reply)) }),
+                    Err(e) => yield Err(e),
+                },
+                Ok(Err(e)) => yield Err(status(e)),
+                Err(e) => yield Err(Status::internal(e.to_string())),
+            }
+        };
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+#[tonic::async_trait]
+impl Sessions for GrpcService {
+    async fn list_sessions(&self, request: Request<proto::ListSessionsRequest>) -> Result<Response<proto::ListSessionsResponse>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/ListSessions", true).await?;
+        let request = request.into_inner();
+        let after: Option<SessionCursor> = request.cursor.as_deref()
+            .map(|cursor| parse_cursor(cursor).ok_or_else(|| Status::invalid_argument("Invalid cursor")))
+            .transpose()?;
+        let page = self.state.db.get_user_sessions(&user_id, request.include_archived, None, after.as_ref(), page_size(request.limit)).await
+            .map_err(status)?;
+        Ok(Response::new(proto::ListSessionsResponse {
+            sessions: page.items.into_iter().map(proto::Session::from).collect(),
+            next_cursor: page.next_cursor.as_ref().and_then(encode_cursor),
+        }))
+    }
+
+    async fn get_session(&self, request: Request<proto::GetSessionRequest>) -> Result<Response<proto::Session>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/GetSession", true).await?;
+        let session_id = request.into_inner().session_id;
+        crate::auth::authorize(&self.state, &user_id, OwnedResource::Session, &session_id).await.map_err(status)?;
+        let session = self.state.db.get_session(&session_id).await.map_err(status)?
+            .ok_or_else(|| Status::not_found("Session not found"))?;
+        Ok(Response::new(session.into()))
+    }
+
+    async fn create_session(&self, request: Request<proto::CreateSessionRequest>) -> Result<Response<proto::Session>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/CreateSession", false).await?;
+        let request = request.into_inner();
+        let model_provider = AIProvider::from(request.model_provider.clone());
+        if model_provider.to_string() != request.model_provider {
+            return Err(Status::invalid_argument("Unknown model provider"));
+        }
+        let session = api::create_session_as(&self.state, &user_id, request.title, model_provider, request.model_name, request.incognito).await
+            .map_err(status)?;
+        Ok(Response::new(session.into()))
+    }
+
+    async fn rename_session(&self, request: Request<proto::RenameSessionRequest>) -> Result<Response<proto::Session>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/RenameSession", false).await?;
+        let request = request.into_inner();
+        api::rename_session_as(&self.state, &user_id, &request.session_id, &request.title).await.map_err(status)?;
+        let session = self.state.db.get_session(&request.session_id).await.map_err(status)?
+            .ok_or_else(|| Status::not_found("Session not found"))?;
+        Ok(Response::new(session.into()))
+    }
+
+    async fn delete_session(&self, request: Request<proto::DeleteSessionRequest>) -> Result<Response<proto::DeleteSessionResponse>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/DeleteSession", false).await?;
+        let session_id = request.into_inner().session_id;
+        api::delete_session_as(&self.state, &user_id, &session_id).await.map_err(status)?;
+        Ok(Response::new(proto::DeleteSessionResponse {}))
+    }
+
+    async fn list_messages(&self, request: Request<proto::ListMessagesRequest>) -> Result<Response<proto::ListMessagesResponse>, Status> {
+        let user_id = self.user_id(&request, "/aibot.v1.Sessions/ListMessages", true).await?;
+        let request = request.into_inner();
+        crate::auth::authorize(&self.state, &user_id, OwnedResource::Session, &request.session_id).await.map_err(status)?;
+        let before: Option<MessageCursor> = request.before.as_deref()
+            .map(|cursor| parse_cursor(cursor).ok_or_else(|| Status::invalid_argument("Invalid cursor")))
+            .transpose()?;
+        let page = self.state.db.get_session_messages_page(&request.session_id, before.as_ref(), page_size(request.limit)).await
+            .map_err(status)?;
+        Ok(Response::new(proto::ListMessagesResponse {
+            messages: page.items.into_iter().map(proto::Message::from).collect(),
+            next_before: page.next_cursor.as_ref().and_then(encode_cursor),
+        }))
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use crate::{api::AppState, models::*};
+
+// Guests only chat with the instance's local models, so they cost nothing per message
+pub const GUEST_PROVIDER: AIProvider = AIProvider::Ollama;
+// A guest whose login sessions have all expired can't come back, so they are removed. This
+// keeps guests created moments ago, before their login session is stored.
+const ABANDONED_AFTER_HOURS: i64 = 1;
+
+// The model guests chat with, failing when guest mode is off
+pub fn guest_model(state: &AppState) -> Result<String> {
+    state.guest_model.read().unwrap().clone()
+        .ok_or_else(|| anyhow::anyhow!("Guest access is not enabled on this server"))
+}
+
+// Creates a guest identity for this browser and signs it in. It lives in the login cookie
+// alone; signing up while signed in as it turns it into the new account.
+pub async fn start(state: &AppState) -> Result<User> {
+    guest_model(state)?;
+    if crate::auth::signed_in_user_id().is_some() {
+        return Err(anyhow::anyhow!("Already signed in"));
+    }
+
+    let mut user = User::new(Some("Guest".to_string()), None);
+    user.role = Role::Guest;
+    state.db.create_user(&user).await?;
+    crate::auth::sign_in(state, &user.id).await?;
+    Ok(user)
+}
+
+// Fails when a guest's session isn't on the guest model, such as one created before guest
+// mode changed models
+pub async fn check_session(state: &AppState, user_id: &str, session: &ChatSession) -> Result<()> {
+    let Some(user) = state.db.get_user(user_id).await? else { return Ok(()) };
+    if !user.is_anonymous_guest() {
+        return Ok(());
+    }
+    let model = guest_model(state)?;
+    if session.model_provider != GUEST_PROVIDER.to_string() || session.model_name != model {
+        return Err(anyhow::anyhow!("Guests can only chat with {}; create an account to use other models", model));
+    }
+    Ok(())
+}
+
+// Removes guests who can no longer sign in, with everything they created
+pub async fn remove_abandoned(state: &AppState) -> Result<u64> {
+    state.db.delete_abandoned_guests(Utc::now() - Duration::hours(ABANDONED_AFTER_HOURS)).await
+}
@@ -0,0 +1,842 @@
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message as WsMessage, WebSocketUpgrade},
+        Extension, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        AppendHeaders, IntoResponse, Redirect, Response,
+    },
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use crate::{
+    api::AppState,
+    audit::ADMIN_ACTOR,
+    auth::AuthUser,
+    database::OwnedResource,
+    email_gateway::InboundEmail,
+    models::{AIProvider, AuditAction, AuditLogFilter, Capability, ExportFormat, Organization, OrganizationSettings, SyncEvent},
+    rate_limit::{ClientIp, RateLimiter},
+};
+
+type HandlerError = (StatusCode, String);
+
+fn internal_error(e: impl std::fmt::Display) -> HandlerError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Content types an attachment may be shown inline as. Anything else, HTML and SVG included,
+// is downloaded, so an upload can never run as a page on this origin.
+const INLINE_ATTACHMENT_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp", "application/pdf"];
+
+// A `Content-Disposition` value for a user-supplied file name, per RFC 6266: a plain ASCII
+// fallback for old clients, and the exact name percent-encoded in `filename*`
+pub(crate) fn content_disposition(disposition: &str, file_name: &str) -> String {
+    let fallback: String = file_name.chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let mut encoded = String::new();
+    for byte in file_name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded)
+}
+
+// Other users' records are reported as missing, the same as records that don't exist
+async fn require_owner(state: &AppState, user: &AuthUser, resource: OwnedResource, id: &str) -> Result<(), HandlerError> {
+    if !state.db.is_owner(resource, id, &user.user_id).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, format!("{} not found", resource)));
+    }
+    Ok(())
+}
+
+// Bundles every attachment of a session into a single zip download
+pub async fn export_session_attachments(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_owner(&state, &user, OwnedResource::Session, &session_id).await?;
+    state.db.get_session(&session_id).await.map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Session not found".to_string()))?;
+
+    let attachments = state.db.get_session_attachments(&session_id).await.map_err(internal_error)?;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let mut used_names = HashSet::new();
+
+    for attachment in &attachments {
+        // Files that were re-attached share a path; only archive them once
+        if !used_names.insert(attachment.file_path.clone()) {
+            continue;
+        }
+        let data = match tokio::fs::read(&attachment.file_path).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Skipping missing attachment {}: {}", attachment.file_path, e);
+                continue;
+            }
+        };
+
+        zip.start_file(crate::export::archive_entry_name(attachment), options).map_err(internal_error)?;
+        zip.write_all(&data).map_err(internal_error)?;
+    }
+
+    let bytes = zip.finish().map_err(internal_error)?.into_inner();
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"session-{}-attachments.zip\"", session_id),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+// Streams what happens in a session as server-sent events: each event's data is a
+// `SessionEvent` as JSON. A subscriber that falls too far behind gets a `lagged` event and
+// should reload the session to catch up.
+pub async fn session_events(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_owner(&state, &user, OwnedResource::Session, &session_id).await?;
+    let mut events = state.session_events.subscribe(&session_id);
+
+    // The stream ends when the server stops, so it doesn't hold up the shutdown
+    let stream = async_stream::stream! {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = state.shutdown.triggered() => break,
+            };
+            match event {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(data) => yield Ok::<_, std::convert::Infallible>(Event::default().data(data)),
+                    Err(e) => tracing::error!("Failed to serialize session event: {}", e),
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                    yield Ok(Event::default().event("lagged").data(missed.to_string()));
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Keeps the user's open tabs and devices in step over a WebSocket: each text message is a
+// `SyncEvent` as JSON. Clients send nothing; the socket closes when they go away.
+pub async fn sync(
+    State(state): State<AppState>,
+    user: AuthUser,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let mut events = state.session_events.subscribe_user(&user.user_id);
+
+    // Clients reconnect when the socket closes, so it closes when the server stops
+    ws.on_upgrade(|mut socket| async move {
+        loop {
+            let event = tokio::select! {
+                _ = state.shutdown.triggered() => break,
+                event = events.recv() => match event {
+                    Ok(event) => event,
+                    // Too far behind to catch up event by event, so the client reloads instead
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => SyncEvent::Lagged,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                },
+                message = socket.recv() => match message {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+            };
+            let data = match serde_json::to_string(&event) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::error!("Failed to serialize sync event: {}", e);
+                    continue;
+                }
+            };
+            if socket.send(WsMessage::Text(data.into())).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+// Receives an email posted by the email provider, which authenticates with HTTP basic auth
+// and INBOUND_EMAIL_SECRET as the password. The email is answered in the background.
+pub async fn inbound_email(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::Json(email): axum::Json<InboundEmail>,
+) -> Result<StatusCode, HandlerError> {
+    let gateway = state.email_gateway.as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Email gateway is disabled".to_string()))?;
+    let password = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|credentials| STANDARD.decode(credentials.trim()).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .and_then(|credentials| credentials.split_once(':').map(|(_, password)| password.to_string()));
+    if password.as_deref() != Some(gateway.secret()) {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid inbound email secret".to_string()));
+    }
+
+    gateway.receive(&state, email);
+    Ok(StatusCode::OK)
+}
+
+// Downloads a session as JSON (the default) or, with `?format=markdown`, a Markdown transcript
+pub async fn export_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_owner(&state, &user, OwnedResource::Session, &session_id).await?;
+    state.db.get_session(&session_id).await.map_err(internal_error)?
+        .filter(|session| session.deleted_at.is_none())
+        .ok_or((StatusCode::NOT_FOUND, "Session not found".to_string()))?;
+
+    let format = query.format.map(ExportFormat::from).unwrap_or(ExportFormat::Json);
+    let file = crate::export::export_session(&state, &session_id, format).await.map_err(internal_error)?;
+    crate::audit::record(&state, &user.user_id, AuditAction::SessionExported, Some(&session_id), Some(serde_json::json!({ "format": format.to_string() })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, file.content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                content_disposition("attachment", &file.file_name),
+            ),
+        ],
+        file.content,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct SignedAttachmentQuery {
+    expires: i64,
+    sig: String,
+}
+
+// Streams a stored attachment back. The link must be signed by `attachment_urls::sign` and
+// unexpired; it doesn't need a signed-in user. The content type came from the uploader, so
+// only a few safe types are shown inline, the browser may not sniff another type, and the
+// response is sandboxed in case it is opened as a page anyway. PDFs aren't sandboxed, as
+// browsers won't show them in a sandboxed document; their viewers don't run the file's
+// scripts with the site's origin.
+pub async fn serve_attachment(
+    State(state): State<AppState>,
+    Path(attachment_id): Path<String>,
+    Query(query): Query<SignedAttachmentQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    if !crate::attachment_urls::verify(&state, &attachment_id, query.expires, &query.sig, chrono::Utc::now()) {
+        return Err((StatusCode::FORBIDDEN, "This link is invalid or has expired".to_string()));
+    }
+    let attachment = state.db.get_attachment(&attachment_id).await.map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Attachment not found".to_string()))?;
+
+    let data = tokio::fs::read(&attachment.file_path).await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Attachment file missing".to_string()))?;
+
+    let disposition = if INLINE_ATTACHMENT_TYPES.contains(&attachment.file_type.as_str()) { "inline" } else { "attachment" };
+    let content_type = header::HeaderValue::from_str(&attachment.file_type)
+        .unwrap_or(header::HeaderValue::from_static("application/octet-stream"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        header::HeaderValue::from_str(&content_disposition(disposition, &attachment.file_name)).map_err(internal_error)?,
+    );
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, header::HeaderValue::from_static("nosniff"));
+    if attachment.file_type != "application/pdf" {
+        headers.insert(header::CONTENT_SECURITY_POLICY, header::HeaderValue::from_static("sandbox"));
+    }
+    // The signature is a bearer credential: keep it out of shared caches and out of the
+    // Referer of anything the file links to
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private, no-store"));
+    headers.insert(header::REFERRER_POLICY, header::HeaderValue::from_static("no-referrer"));
+
+    Ok((headers, data))
+}
+
+#[derive(Deserialize)]
+pub struct OAuthCallback {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+}
+
+// OAuth redirect target for connectors; stores the tokens and kicks off the first sync
+pub async fn connector_oauth_callback(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(provider): Path<String>,
+    Query(callback): Query<OAuthCallback>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, HandlerError> {
+    if let Some(error) = callback.error {
+        return Err((StatusCode::BAD_REQUEST, format!("Authorization was declined: {}", error)));
+    }
+    let code = callback.code.ok_or((StatusCode::BAD_REQUEST, "Missing authorization code".to_string()))?;
+
+    let flow = crate::auth::cookie_value(&headers, crate::connectors::FLOW_COOKIE);
+    let connector_id = crate::connectors::flow_connector_id(&callback.state, flow.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let connector = state.db.get_connector(&connector_id).await.map_err(internal_error)?
+        .filter(|connector| connector.provider.to_string() == provider && connector.user_id == user.user_id)
+        .ok_or((StatusCode::NOT_FOUND, "Connector not found".to_string()))?;
+
+    crate::connectors::complete_authorization(&state, &connector, &code).await.map_err(internal_error)?;
+    crate::audit::record(&state, &connector.user_id, AuditAction::ConnectorAuthorized, Some(&connector.id), None).await.map_err(internal_error)?;
+    crate::connectors::start_sync(&state, connector).await.map_err(internal_error)?;
+
+    Ok(([(header::SET_COOKIE, crate::connectors::clear_flow_cookie())], Redirect::to("/")))
+}
+
+// Sends the browser to the single sign-on provider to sign in
+pub async fn oidc_login(State(state): State<AppState>) -> Result<impl IntoResponse, HandlerError> {
+    let oidc = state.oidc.as_ref().ok_or((StatusCode::NOT_FOUND, "Single sign-on is not enabled".to_string()))?;
+    let (url, cookie) = oidc.authorization_redirect().await.map_err(internal_error)?;
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(&url)))
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+// Redirect target of the single sign-on provider. Signs the browser in and goes to the chat,
+// or back to the sign-in page with an error; the reason is only logged.
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    Query(callback): Query<OidcCallback>,
+    request: Request,
+) -> Response {
+    let clear_flow = (header::SET_COOKIE, crate::oidc::clear_flow_cookie());
+    // The cookie to set and where to go: signed in, or on to the sign-in page for a two-factor code
+    let signed_in: anyhow::Result<(String, &str)> = async {
+        let oidc = state.oidc.as_ref().ok_or_else(|| anyhow::anyhow!("Single sign-on is not enabled"))?;
+        if let Some(error) = callback.error {
+            return Err(anyhow::anyhow!("Provider returned {}", error));
+        }
+        let (Some(code), Some(returned_state)) = (callback.code, callback.state) else {
+            return Err(anyhow::anyhow!("Missing authorization code or state"));
+        };
+        let flow = crate::auth::cookie_value(request.headers(), crate::oidc::FLOW_COOKIE);
+        let user = oidc.complete(&state, &code, &returned_state, flow.as_deref()).await?;
+        if crate::two_factor::is_enabled(&state, &user.id).await? {
+            return Ok((crate::oidc::two_factor_pending_cookie(&state, &user.id)?, "/login?sso_code=1"));
+        }
+
+        let ip = request.extensions().get::<ClientIp>().map(|ClientIp(ip)| ip.to_string());
+        let cookie = crate::auth::create_login_session(&state, &user.id, request.headers(), ip.as_deref()).await?;
+        Ok((cookie, "/"))
+    }.await;
+
+    match signed_in {
+        Ok((cookie, to)) => (AppendHeaders([clear_flow, (header::SET_COOKIE, cookie)]), Redirect::to(to)).into_response(),
+        Err(e) => {
+            tracing::warn!("Single sign-on failed: {}", e);
+            (AppendHeaders([clear_flow]), Redirect::to("/login?sso_error=1")).into_response()
+        }
+    }
+}
+
+// Downloads the user's memory as a JSON file
+pub async fn export_memory(State(state): State<AppState>, user: AuthUser) -> Result<impl IntoResponse, HandlerError> {
+    let memories = state.db.get_user_memory(&user.user_id).await.map_err(internal_error)?;
+    crate::audit::record(&state, &user.user_id, AuditAction::MemoryExported, None, None).await.map_err(internal_error)?;
+    let export = crate::memory::export_memories(&memories);
+    let body = serde_json::to_vec_pretty(&export).map_err(internal_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"memory-{}.json\"", export.exported_at.format("%Y%m%d")),
+            ),
+        ],
+        body,
+    ))
+}
+
+// Downloads everything stored for the signed-in user as a zip; see `account::export_archive`
+pub async fn export_account(State(state): State<AppState>, user: AuthUser) -> Result<impl IntoResponse, HandlerError> {
+    let bytes = crate::account::export_archive(&state, &user.user_id).await.map_err(internal_error)?;
+    crate::audit::record(&state, &user.user_id, AuditAction::AccountExported, None, None).await.map_err(internal_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"my-data-{}.zip\"", chrono::Utc::now().format("%Y%m%d")),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+// Who is calling an admin endpoint: `ADMIN_ACTOR` for `Authorization: Bearer <ADMIN_TOKEN>`,
+// otherwise the user signed in or sending an API token, whose role must allow `capability`
+async fn require_admin(
+    state: &AppState,
+    headers: &HeaderMap,
+    user: Option<AuthUser>,
+    capability: Capability,
+) -> Result<String, HandlerError> {
+    let provided = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if let (Some(provided), Some(expected)) = (provided, state.admin_token.as_deref()) {
+        if crate::auth::secrets_match(provided, expected) {
+            return Ok(ADMIN_ACTOR.to_string());
+        }
+    }
+
+    let user = user.ok_or((StatusCode::UNAUTHORIZED, crate::auth::NOT_SIGNED_IN.to_string()))?;
+    crate::permissions::check(state, &user.user_id, capability).await
+        .map_err(|e| (StatusCode::FORBIDDEN, e.to_string()))?;
+    Ok(user.user_id)
+}
+
+// Downloads a backup of the database and uploads as a zip archive
+pub async fn download_backup(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let (manifest, bytes) = crate::backup::create_backup(&state).await.map_err(internal_error)?;
+    crate::audit::record(&state, &actor, AuditAction::BackupCreated, None, None).await.map_err(internal_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"aibot-backup-{}.zip\"", manifest.created_at.format("%Y%m%d-%H%M%S")),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+// Reports what the retention policies would move to the trash right now, per user
+pub async fn preview_retention(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ViewUsage).await?;
+    let reports = crate::retention::apply_all(&state, true).await.map_err(internal_error)?;
+
+    Ok(axum::Json(reports))
+}
+
+// Restores a backup archive uploaded as the request body
+pub async fn restore_backup(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    body: Bytes,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let manifest = crate::backup::restore_backup(&state, &body).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    // Recorded into the restored log, so the restore itself isn't lost
+    crate::audit::record(&state, &actor, AuditAction::BackupRestored, None, Some(serde_json::json!({ "backup_created_at": manifest.created_at })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(axum::Json(manifest))
+}
+
+// Most audit entries returned by one query
+const MAX_AUDIT_ENTRIES: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    user_id: Option<String>,
+    action: Option<String>,
+    after: Option<chrono::DateTime<chrono::Utc>>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<i64>,
+}
+
+// Lists audit log entries, newest first, filtered by user, action and time range
+pub async fn audit_log(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ViewUsage).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_ENTRIES);
+    let filter = AuditLogFilter {
+        user_id: query.user_id,
+        action: query.action,
+        after: query.after,
+        before: query.before,
+    };
+    let entries = state.db.get_audit_log(&filter, limit).await.map_err(internal_error)?;
+
+    Ok(axum::Json(entries))
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    user_id: Option<String>,
+    from: chrono::NaiveDate,
+    to: chrono::NaiveDate,
+}
+
+// Daily usage rollups for every user, or one with `user_id`, between two days inclusive
+pub async fn usage_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Query(query): Query<UsageQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ViewUsage).await?;
+    let rows = state.db.get_daily_usage(query.user_id.as_deref(), query.from, query.to).await.map_err(internal_error)?;
+
+    Ok(axum::Json(rows))
+}
+
+#[derive(Deserialize)]
+pub struct ModerationEventsQuery {
+    user_id: Option<String>,
+    limit: Option<i64>,
+}
+
+// Lists what the content filter flagged, newest first
+pub async fn moderation_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Query(query): Query<ModerationEventsQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ViewUsage).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_ENTRIES);
+    let events = state.db.get_moderation_events(query.user_id.as_deref(), limit).await.map_err(internal_error)?;
+
+    Ok(axum::Json(events))
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrganizationRequest {
+    name: String,
+    #[serde(default)]
+    settings: OrganizationSettings,
+}
+
+// Lists every organization
+pub async fn list_organizations(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let organizations = state.db.get_organizations().await.map_err(internal_error)?;
+
+    Ok(axum::Json(organizations))
+}
+
+pub async fn create_organization(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    axum::Json(request): axum::Json<CreateOrganizationRequest>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Organization name is required".to_string()));
+    }
+
+    let organization = Organization::new(name.to_string(), request.settings);
+    state.db.create_organization(&organization).await.map_err(internal_error)?;
+    crate::audit::record(&state, &actor, AuditAction::OrganizationCreated, Some(&organization.id), Some(serde_json::json!({ "name": organization.name })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, axum::Json(organization)))
+}
+
+pub async fn update_organization_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path(org_id): Path<String>,
+    axum::Json(settings): axum::Json<OrganizationSettings>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    if !state.db.update_organization_settings(&org_id, &settings).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, "Organization not found".to_string()));
+    }
+    crate::audit::record(&state, &actor, AuditAction::OrganizationSettingsChanged, Some(&org_id), Some(serde_json::to_value(&settings).map_err(internal_error)?))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct OrganizationKeyRequest {
+    api_key: String,
+}
+
+// The organization's keys by provider, as fingerprints only
+pub async fn list_organization_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path(org_id): Path<String>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let keys = state.db.get_organization_api_keys(&org_id).await.map_err(internal_error)?;
+    Ok(axum::Json(keys))
+}
+
+// Sets the organization's own key for a provider, used instead of the instance key. The
+// key is stored sealed and only its fingerprint is returned.
+pub async fn set_organization_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path((org_id, provider)): Path<(String, String)>,
+    axum::Json(request): axum::Json<OrganizationKeyRequest>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    state.db.get_organization(&org_id).await.map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Organization not found".to_string()))?;
+    // Normalizes the name, so "OpenAI" and "openai" store the same key
+    let provider = AIProvider::from(provider.to_lowercase()).to_string();
+
+    if state.secrets.is_none() {
+        return Err((StatusCode::CONFLICT, "Storing API keys requires SECRETS_KEY to be set".to_string()));
+    }
+    let key = crate::organizations::save_key(&state, &org_id, &provider, &request.api_key)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    // The key itself is never logged
+    crate::audit::record(&state, &actor, AuditAction::OrganizationKeyChanged, Some(&org_id), Some(serde_json::json!({ "provider": provider, "fingerprint": key.fingerprint, "removed": false })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(axum::Json(key))
+}
+
+// Removes the organization's key for a provider, falling back to the instance key
+pub async fn delete_organization_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path((org_id, provider)): Path<(String, String)>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let provider = AIProvider::from(provider.to_lowercase()).to_string();
+
+    state.db.delete_organization_api_key(&org_id, &provider).await.map_err(internal_error)?;
+    crate::audit::record(&state, &actor, AuditAction::OrganizationKeyChanged, Some(&org_id), Some(serde_json::json!({ "provider": provider, "removed": true })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct UserOrganizationRequest {
+    org_id: String,
+}
+
+// Moves a user, with their sessions and knowledge bases, into another organization
+pub async fn set_user_organization(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path(user_id): Path<String>,
+    axum::Json(request): axum::Json<UserOrganizationRequest>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageUsers).await?;
+    state.db.get_organization(&request.org_id).await.map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Organization not found".to_string()))?;
+    state.db.get_user(&user_id).await.map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    state.db.set_user_organization(&user_id, &request.org_id).await.map_err(internal_error)?;
+    crate::audit::record(&state, &actor, AuditAction::UserOrganizationChanged, Some(&user_id), Some(serde_json::json!({ "org_id": request.org_id })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Lists every registered webhook
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let webhooks = state.db.get_webhooks().await.map_err(internal_error)?;
+
+    Ok(axum::Json(webhooks))
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    url: String,
+    events: Vec<String>,
+}
+
+// Registers a webhook; the response holds its signing secret, which isn't shown again
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    axum::Json(request): axum::Json<CreateWebhookRequest>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let created = crate::webhooks::create(&state, &request.url, &request.events).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let events: Vec<String> = created.webhook.events.iter().map(|event| event.to_string()).collect();
+    crate::audit::record(&state, &actor, AuditAction::WebhookCreated, Some(&created.webhook.id), Some(serde_json::json!({ "url": created.webhook.url, "events": events })))
+        .await
+        .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, axum::Json(created)))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path(webhook_id): Path<String>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    if !state.db.delete_webhook(&webhook_id).await.map_err(internal_error)? {
+        return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string()));
+    }
+    crate::audit::record(&state, &actor, AuditAction::WebhookDeleted, Some(&webhook_id), None)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveriesQuery {
+    limit: Option<i64>,
+}
+
+// The webhook's delivery log, newest first, with each attempt's outcome
+pub async fn webhook_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+    Path(webhook_id): Path<String>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<impl IntoResponse, HandlerError> {
+    require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_ENTRIES);
+    let deliveries = state.db.get_webhook_deliveries(&webhook_id, limit).await.map_err(internal_error)?;
+
+    Ok(axum::Json(deliveries))
+}
+
+// Re-reads the configuration and applies the provider keys, rate limits and guest model
+// without a restart. A file that can't be read leaves the running configuration as it was.
+pub async fn reload_config(
+    State(state): State<AppState>,
+    Extension(limiter): Extension<RateLimiter>,
+    headers: HeaderMap,
+    user: Option<AuthUser>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let actor = require_admin(&state, &headers, user, Capability::ManageInstance).await?;
+    crate::config::reload(&state, &limiter)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid configuration: {}", e)))?;
+    crate::audit::record(&state, &actor, AuditAction::ConfigReloaded, None, None)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Liveness probe: answers as long as the process is serving requests. Checks nothing else,
+// so an unreachable database doesn't get the server restarted.
+pub async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+// Readiness probe for load balancers and orchestrators: 503 until the database answers,
+// every migration this build knows about is applied and a model provider is set up
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let database = state.db.health().await;
+    let schema_version = state.db.schema_version().await.unwrap_or(0);
+    let latest_schema_version = state.db.latest_schema_version();
+    let provider_configured = state.ai_service.has_provider().await;
+
+    let ready = database.healthy && schema_version >= latest_schema_version && provider_configured;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, axum::Json(serde_json::json!({
+        "ready": ready,
+        "database": database,
+        "schema_version": schema_version,
+        "latest_schema_version": latest_schema_version,
+        "provider_configured": provider_configured,
+    })))
+}
+
+// Database pool gauges in the Prometheus text format. The wait and query times come
+// from a probe taken on each scrape.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let health = state.db.health().await;
+    let backend = health.backend;
+    let gauges = [
+        ("db_up", "Whether the database answered the health probe", if health.healthy { 1.0 } else { 0.0 }),
+        ("db_pool_max_connections", "Configured maximum pool size", health.pool.max_connections as f64),
+        ("db_pool_connections_in_use", "Connections checked out of the pool", health.pool.in_use as f64),
+        ("db_pool_connections_idle", "Open connections waiting in the pool", health.pool.idle as f64),
+        ("db_pool_acquire_wait_seconds", "Time the probe waited for a pool connection", health.acquire_wait_ms / 1000.0),
+        ("db_probe_query_seconds", "Time the probe's SELECT 1 took", health.query_ms / 1000.0),
+    ];
+
+    let mut body = String::new();
+    for (name, help, value) in gauges {
+        body.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{}{{backend=\"{}\"}} {}\n", name, help, name, name, backend, value));
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -0,0 +1,63 @@
+use std::sync::OnceLock;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    html::{highlighted_html_for_string, styled_line_to_highlighted_html, IncludeBackground},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+// Code blocks in chat sit on a dark background
+const CODE_BLOCK_THEME: &str = "base16-ocean.dark";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, hint: &str) -> &'a syntect::parsing::SyntaxReference {
+    syntaxes
+        .find_syntax_by_token(hint)
+        .or_else(|| syntaxes.find_syntax_by_extension(hint))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+// Renders source text as highlighted HTML. `hint` may be a language token ("rust")
+// or a file extension ("rs"); unknown hints fall back to plain text.
+pub fn highlight_to_html(code: &str, hint: &str) -> String {
+    let syntaxes = syntax_set();
+    let syntax = find_syntax(syntaxes, hint);
+    let theme = &theme_set().themes["InspiredGitHub"];
+
+    highlighted_html_for_string(code, syntaxes, syntax, theme)
+        .unwrap_or_else(|_| format!("<pre>{}</pre>", escape_html(code)))
+}
+
+// Renders a chat code block as highlighted HTML, one string per line and without the
+// background, so the block can number and wrap its lines itself
+pub fn highlight_lines(code: &str, hint: &str) -> Vec<String> {
+    let syntaxes = syntax_set();
+    let mut highlighter = HighlightLines::new(find_syntax(syntaxes, hint), &theme_set().themes[CODE_BLOCK_THEME]);
+    LinesWithEndings::from(code)
+        .map(|line| {
+            highlighter.highlight_line(line, syntaxes)
+                .ok()
+                .and_then(|ranges| styled_line_to_highlighted_html(&ranges, IncludeBackground::No).ok())
+                .unwrap_or_else(|| escape_html(line))
+                .trim_end_matches('\n')
+                .to_string()
+        })
+        .collect()
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,291 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use crate::{api::AppState, models::*};
+
+// Used when an export doesn't say which model produced a conversation
+const CHATGPT_DEFAULT_MODEL: &str = "gpt-4";
+const CLAUDE_DEFAULT_MODEL: &str = "claude-3-sonnet-20240229";
+
+// Both services ship conversations.json inside a zip; the bare file is accepted too
+const CONVERSATIONS_FILE: &str = "conversations.json";
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    title: Option<String>,
+    create_time: Option<f64>,
+    update_time: Option<f64>,
+    mapping: HashMap<String, ChatGptNode>,
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    message: Option<ChatGptMessage>,
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    content: ChatGptContent,
+    create_time: Option<f64>,
+    #[serde(default)]
+    metadata: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+    content_type: String,
+    #[serde(default)]
+    parts: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeConversation {
+    name: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    chat_messages: Vec<ClaudeMessage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeMessage {
+    sender: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    content: Vec<ClaudeContent>,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    attachments: Vec<serde_json::Value>,
+    #[serde(default)]
+    files: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeContent {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+    thinking: Option<String>,
+}
+
+// A conversation mapped into rows, before it is saved
+struct ParsedConversation {
+    session: ChatSession,
+    messages: Vec<Message>,
+    // Messages (or parts of them) that had nothing importable, such as tool output or images
+    skipped_messages: usize,
+}
+
+// Imports a ChatGPT or Claude data export (the zip or its conversations.json) as new
+// sessions for `user_id`. Conversations that can't be read are reported and skipped.
+pub async fn import_conversations(state: &AppState, user_id: &str, data: &[u8]) -> Result<ImportReport> {
+    let json = conversations_json(data)?;
+    let items: Vec<serde_json::Value> = serde_json::from_slice(&json)
+        .map_err(|_| anyhow::anyhow!("Expected a conversations.json export"))?;
+
+    let mut report = ImportReport::default();
+    for (i, item) in items.into_iter().enumerate() {
+        let parsed = if item.get("mapping").is_some() {
+            serde_json::from_value::<ChatGptConversation>(item).map(|c| parse_chatgpt(user_id, c))
+        } else if item.get("chat_messages").is_some() {
+            serde_json::from_value::<ClaudeConversation>(item).map(|c| parse_claude(user_id, c))
+        } else {
+            report.skipped.push(format!("Conversation {}: unrecognized format", i + 1));
+            continue;
+        };
+
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report.skipped.push(format!("Conversation {}: {}", i + 1, e));
+                continue;
+            }
+        };
+
+        let title = parsed.session.title.clone().unwrap_or_else(|| format!("Conversation {}", i + 1));
+        if parsed.messages.is_empty() {
+            report.skipped.push(format!("\"{}\": no text messages", title));
+            continue;
+        }
+        if parsed.skipped_messages > 0 {
+            report.skipped.push(format!(
+                "\"{}\": skipped {} message(s) without text (tool output, images or files)",
+                title, parsed.skipped_messages,
+            ));
+        }
+
+        state.db.create_session(&parsed.session).await?;
+        for message in &parsed.messages {
+            state.db.create_message(message).await?;
+        }
+        report.sessions_imported += 1;
+        report.messages_imported += parsed.messages.len();
+    }
+
+    Ok(report)
+}
+
+fn conversations_json(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.starts_with(b"PK") {
+        return Ok(data.to_vec());
+    }
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|e| anyhow::anyhow!("Not a valid export archive: {}", e))?;
+    let name = zip.file_names()
+        .find(|name| name.rsplit('/').next() == Some(CONVERSATIONS_FILE))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("The archive has no {}", CONVERSATIONS_FILE))?;
+
+    let mut file = zip.by_name(&name)?;
+    let mut json = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut json)?;
+    Ok(json)
+}
+
+// ChatGPT stores every edit and regeneration as a tree; only the branch ending at
+// `current_node`, the one shown in ChatGPT, is imported
+fn parse_chatgpt(user_id: &str, conversation: ChatGptConversation) -> ParsedConversation {
+    let started = conversation.create_time.and_then(timestamp).unwrap_or_else(Utc::now);
+    let mut session = ChatSession::new(user_id.to_string(), AIProvider::OpenAI, CHATGPT_DEFAULT_MODEL.to_string());
+    session.title = conversation.title.filter(|title| !title.trim().is_empty());
+    session.created_at = started;
+    session.updated_at = conversation.update_time.and_then(timestamp).unwrap_or(started);
+
+    let mut branch = Vec::new();
+    let mut node_id = conversation.current_node;
+    while let Some(node) = node_id.as_ref().and_then(|id| conversation.mapping.get(id)) {
+        if let Some(message) = &node.message {
+            branch.push(message);
+        }
+        node_id = node.parent.clone();
+    }
+    branch.reverse();
+
+    let mut messages: Vec<Message> = Vec::new();
+    let mut skipped_messages = 0;
+    for message in branch {
+        let hidden = message.metadata.get("is_visually_hidden_from_conversation")
+            .and_then(|hidden| hidden.as_bool())
+            .unwrap_or(false);
+        let role = match message.author.role.as_str() {
+            "user" => MessageRole::User,
+            "assistant" => MessageRole::Assistant,
+            // System prompts are usually empty or hidden; keep only ones the user could see
+            "system" if !hidden => MessageRole::System,
+            "system" => continue,
+            _ => {
+                skipped_messages += 1;
+                continue;
+            }
+        };
+
+        // Images and file references come through as objects among the parts
+        let text = match message.content.content_type.as_str() {
+            "text" | "multimodal_text" => message.content.parts.iter()
+                .filter_map(|part| part.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        };
+        if text.trim().is_empty() {
+            // Every conversation starts from an empty system node, which isn't worth reporting
+            if !hidden && !matches!(role, MessageRole::System) {
+                skipped_messages += 1;
+            }
+            continue;
+        }
+
+        let mut imported = Message::new(session.id.clone(), role.clone(), text);
+        imported.created_at = next_timestamp(&messages, message.create_time.and_then(timestamp).unwrap_or(started));
+        if let MessageRole::Assistant = role {
+            let model_slug = message.metadata.get("model_slug").and_then(|slug| slug.as_str());
+            if let Some(model_slug) = model_slug {
+                session.model_name = model_slug.to_string();
+            }
+            imported.model_provider = Some(session.model_provider.clone());
+            imported.model_name = Some(session.model_name.clone());
+        }
+        messages.push(imported);
+    }
+
+    session.last_message_preview = messages.last().map(|message| message_preview(&message.content));
+    ParsedConversation { session, messages, skipped_messages }
+}
+
+fn parse_claude(user_id: &str, conversation: ClaudeConversation) -> ParsedConversation {
+    let mut session = ChatSession::new(user_id.to_string(), AIProvider::Anthropic, CLAUDE_DEFAULT_MODEL.to_string());
+    session.title = conversation.name.filter(|name| !name.trim().is_empty());
+    session.created_at = conversation.created_at;
+    session.updated_at = conversation.updated_at.unwrap_or(conversation.created_at);
+
+    let mut messages: Vec<Message> = Vec::new();
+    let mut skipped_messages = 0;
+    for message in conversation.chat_messages {
+        let role = match message.sender.as_str() {
+            "human" => MessageRole::User,
+            "assistant" => MessageRole::Assistant,
+            _ => {
+                skipped_messages += 1;
+                continue;
+            }
+        };
+
+        // Newer exports split messages into typed blocks; older ones only have `text`
+        let text = if message.content.is_empty() {
+            message.text
+        } else {
+            blocks_text(&message.content, "text")
+        };
+        let reasoning = blocks_text(&message.content, "thinking");
+        // Uploaded files aren't part of the export, only their names
+        skipped_messages += message.attachments.len() + message.files.len();
+        if text.trim().is_empty() {
+            skipped_messages += 1;
+            continue;
+        }
+
+        let mut imported = Message::new(session.id.clone(), role.clone(), text);
+        imported.created_at = next_timestamp(&messages, message.created_at);
+        if let MessageRole::Assistant = role {
+            imported.reasoning = (!reasoning.trim().is_empty()).then_some(reasoning);
+            imported.model_provider = Some(session.model_provider.clone());
+            imported.model_name = Some(session.model_name.clone());
+        }
+        messages.push(imported);
+    }
+
+    session.last_message_preview = messages.last().map(|message| message_preview(&message.content));
+    ParsedConversation { session, messages, skipped_messages }
+}
+
+fn blocks_text(blocks: &[ClaudeContent], kind: &str) -> String {
+    blocks.iter()
+        .filter(|block| block.kind == kind)
+        .filter_map(|block| block.text.as_deref().or(block.thinking.as_deref()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn timestamp(seconds: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+}
+
+// Messages are ordered by created_at, so ties and clock skew in the export are
+// nudged forward to keep the original order
+fn next_timestamp(previous: &[Message], at: DateTime<Utc>) -> DateTime<Utc> {
+    match previous.last() {
+        Some(last) if at <= last.created_at => last.created_at + Duration::milliseconds(1),
+        _ => at,
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::Result;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use crate::{api::AppState, models::*, usage::estimate_tokens};
+
+// Target chunk size and overlap, in characters
+const CHUNK_SIZE: usize = 1500;
+const CHUNK_OVERLAP: usize = 200;
+
+// Each knowledge base gets its own partition of the vector index
+pub fn kb_collection(kb_id: &str) -> String {
+    format!("kb:{}", kb_id)
+}
+
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+// Pulls plain text out of an uploaded document
+pub fn extract_text(file: &FileUpload) -> Result<String> {
+    if file.content_type == "application/pdf" {
+        let document = lopdf::Document::load_mem(&file.data)?;
+        let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+        Ok(document.extract_text(&pages)?)
+    } else if file.content_type.starts_with("text/") || file.content_type == "application/json" {
+        Ok(String::from_utf8_lossy(&file.data).into_owned())
+    } else {
+        Err(anyhow::anyhow!("Unsupported document type: {}", file.content_type))
+    }
+}
+
+// Splits text on paragraph boundaries into chunks of roughly CHUNK_SIZE characters.
+// Consecutive chunks share CHUNK_OVERLAP characters so context isn't cut mid-thought.
+pub fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > CHUNK_SIZE {
+            chunks.push(current.clone());
+            current = tail_chars(&current, CHUNK_OVERLAP);
+        }
+
+        // Paragraphs longer than a whole chunk are split hard
+        let mut remaining: Vec<char> = paragraph.chars().collect();
+        while current.chars().count() + remaining.len() > CHUNK_SIZE {
+            let take = CHUNK_SIZE.saturating_sub(current.chars().count()).max(1);
+            current.extend(remaining.drain(..take.min(remaining.len())));
+            chunks.push(current.clone());
+            current = tail_chars(&current, CHUNK_OVERLAP);
+        }
+
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.extend(remaining);
+    }
+
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// Splits source code before each function, class or similar definition, keeping the comments
+// and attributes above it, and packs whole definitions into chunks of up to CHUNK_SIZE
+// characters. Definitions too long for one chunk fall back to `chunk_text`, as do files that
+// aren't code.
+pub fn chunk_code(text: &str, extension: &str) -> Vec<String> {
+    if !is_code(extension) {
+        return chunk_text(text);
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut starts = vec![0];
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        if !starts_definition(extension, line) {
+            continue;
+        }
+        let last = *starts.last().unwrap();
+        let mut start = i;
+        while start > last + 1 && is_annotation(lines[start - 1]) {
+            start -= 1;
+        }
+        if start > last {
+            starts.push(start);
+        }
+    }
+    starts.push(lines.len());
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for bounds in starts.windows(2) {
+        let section = lines[bounds[0]..bounds[1]].join("\n");
+        let section = section.trim_matches('\n');
+        if section.trim().is_empty() {
+            continue;
+        }
+
+        let length = section.chars().count();
+        if !current.is_empty() && current.chars().count() + length + 2 > CHUNK_SIZE {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if length > CHUNK_SIZE {
+            chunks.extend(chunk_text(section));
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(section);
+    }
+
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn is_code(extension: &str) -> bool {
+    matches!(
+        extension,
+        "rs" | "py" | "rb" | "js" | "jsx" | "ts" | "tsx" | "go" | "java" | "kt" | "cs" | "swift" | "php" | "c" | "h" | "cpp"
+    )
+}
+
+// Whether a line of code starts a definition worth splitting before
+fn starts_definition(extension: &str, line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let keywords: &[&str] = match extension {
+        "rs" => {
+            let item = trimmed.trim_start_matches("pub(crate) ").trim_start_matches("pub ");
+            let item = item.trim_start_matches("async ").trim_start_matches("unsafe ");
+            return ["fn ", "impl ", "impl<", "struct ", "enum ", "trait ", "mod ", "macro_rules!"]
+                .iter()
+                .any(|keyword| item.starts_with(keyword));
+        }
+        "py" => &["def ", "async def ", "class "],
+        "rb" => &["def ", "class ", "module "],
+        "js" | "jsx" | "ts" | "tsx" => &["function ", "async function ", "export ", "class ", "interface ", "type "],
+        "go" => &["func ", "type "],
+        "java" | "kt" | "cs" | "swift" | "php" => &[
+            "class ", "interface ", "enum ", "struct ", "public ", "private ", "protected ", "internal ",
+            "fun ", "func ", "function ", "override ", "namespace ",
+        ],
+        // C and C++ definitions open with a type, so take unindented lines with a parameter list
+        "c" | "h" | "cpp" => {
+            return (line == trimmed && trimmed.contains('(') && !trimmed.starts_with(['#', '/', '*', '}']))
+                || ["struct ", "class ", "namespace ", "typedef ", "template"].iter().any(|keyword| line.starts_with(keyword));
+        }
+        _ => &[],
+    };
+    keywords.iter().any(|keyword| trimmed.starts_with(keyword))
+}
+
+// Comments, attributes and decorators belong with the definition below them
+fn is_annotation(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    ["//", "/*", "*", "#", "@"].iter().any(|prefix| trimmed.starts_with(prefix))
+}
+
+fn tail_chars(text: &str, count: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    chars[chars.len().saturating_sub(count)..].iter().collect()
+}
+
+// Chunks, embeds and stores a document in a knowledge base
+pub async fn ingest_text(
+    state: &AppState,
+    kb_id: &str,
+    source_type: &str,
+    source_uri: &str,
+    title: &str,
+    text: &str,
+) -> Result<KnowledgeDocument> {
+    ingest_chunks(state, None, kb_id, source_type, source_uri, title, text, chunk_text(text)).await
+}
+
+// Like `ingest_text`, for a new version of `previous`. The chunks being replaced don't count
+// against the quota, and `previous` is only deleted once the new version is stored.
+pub async fn replace_text(
+    state: &AppState,
+    previous: Option<&KnowledgeDocument>,
+    kb_id: &str,
+    source_type: &str,
+    source_uri: &str,
+    title: &str,
+    text: &str,
+) -> Result<KnowledgeDocument> {
+    ingest_chunks(state, previous, kb_id, source_type, source_uri, title, text, chunk_text(text)).await
+}
+
+// Embeds and stores a document already split into chunks, in place of `previous` if given
+#[allow(clippy::too_many_arguments)]
+pub async fn ingest_chunks(
+    state: &AppState,
+    previous: Option<&KnowledgeDocument>,
+    kb_id: &str,
+    source_type: &str,
+    source_uri: &str,
+    title: &str,
+    text: &str,
+    chunks: Vec<String>,
+) -> Result<KnowledgeDocument> {
+    if chunks.is_empty() {
+        return Err(anyhow::anyhow!("Document '{}' contains no text", title));
+    }
+
+    let kb = state.db.get_knowledge_base(kb_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Knowledge base not found"))?;
+    let replaced_chunks = previous.map(|document| document.chunk_count).unwrap_or(0);
+    let stored_chunks = (state.db.count_user_kb_chunks(&kb.user_id).await? - replaced_chunks).max(0);
+    if let Err(e) = state.usage_limits.check_kb_chunks(stored_chunks, chunks.len() as i64) {
+        let data = json!({
+            "user_id": kb.user_id,
+            "limit": "knowledge_base_chunks",
+            "used": stored_chunks,
+            "max": state.usage_limits.max_kb_chunks_per_user,
+            "requested": chunks.len(),
+        });
+        crate::webhooks::emit(state, WebhookEvent::BudgetExceeded, data).await;
+        return Err(e);
+    }
+
+    let document = KnowledgeDocument {
+        id: uuid::Uuid::new_v4().to_string(),
+        knowledge_base_id: kb_id.to_string(),
+        source_type: source_type.to_string(),
+        source_uri: source_uri.to_string(),
+        title: title.to_string(),
+        content_hash: Some(content_hash(text)),
+        chunk_count: chunks.len() as i64,
+        created_at: chrono::Utc::now(),
+    };
+
+    // Embed before writing anything so a provider failure leaves no partial document
+    let embeddings = state.embeddings.embed(&chunks).await?;
+    state.db.record_embedded_tokens(&kb.user_id, estimate_tokens(&chunks)).await?;
+
+    let chunk_rows: Vec<KnowledgeChunk> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, content)| KnowledgeChunk {
+            id: uuid::Uuid::new_v4().to_string(),
+            document_id: document.id.clone(),
+            knowledge_base_id: kb_id.to_string(),
+            chunk_index: i as i64,
+            content,
+            created_at: chrono::Utc::now(),
+        })
+        .collect();
+
+    state.db.create_kb_document(&document).await?;
+    state.db.save_kb_chunks(&chunk_rows).await?;
+
+    let collection = kb_collection(kb_id);
+    for (chunk, embedding) in chunk_rows.iter().zip(&embeddings) {
+        let metadata = json!({
+            "knowledge_base_id": kb_id,
+            "document_id": document.id,
+            "chunk_index": chunk.chunk_index,
+            "title": document.title,
+            "source_uri": document.source_uri,
+        });
+        state.vectors.upsert(&collection, &chunk.id, embedding, &metadata).await?;
+    }
+
+    if let Some(previous) = previous {
+        delete_document(state, previous).await?;
+    }
+    Ok(document)
+}
+
+pub async fn delete_document(state: &AppState, document: &KnowledgeDocument) -> Result<()> {
+    let collection = kb_collection(&document.knowledge_base_id);
+    for chunk_id in state.db.get_document_chunk_ids(&document.id).await? {
+        state.vectors.delete(&collection, &chunk_id).await?;
+    }
+    state.db.delete_kb_document(&document.id).await
+}
+
+pub async fn delete_knowledge_base(state: &AppState, kb_id: &str) -> Result<()> {
+    for document in state.db.get_kb_documents(kb_id).await? {
+        delete_document(state, &document).await?;
+    }
+    state.db.delete_knowledge_base(kb_id).await
+}
+
+// Runs an ingestion job on the Tokio runtime, recording its status as it goes.
+// `work` resolves to the number of documents ingested. A job still running when the server
+// stops is recorded as failed with the documents it got through.
+pub fn run_in_background<Fut>(state: AppState, job_id: String, work: Fut)
+where
+    Fut: Future<Output = Result<i64>> + Send + 'static,
+{
+    let shutdown = state.shutdown.clone();
+    shutdown.clone().spawn(async move {
+        if let Err(e) = state.db.update_ingestion_job(&job_id, JobStatus::Running, 0, None).await {
+            tracing::error!("Failed to start ingestion job {}: {}", job_id, e);
+            return;
+        }
+
+        let result = tokio::select! {
+            result = work => result,
+            _ = shutdown.triggered() => Err(anyhow::anyhow!("Interrupted by a server shutdown")),
+        };
+        let outcome = match result {
+            Ok(processed) => state.db.update_ingestion_job(&job_id, JobStatus::Completed, processed, None).await,
+            Err(e) => {
+                tracing::error!("Ingestion job {} failed: {}", job_id, e);
+                let processed = state.db.get_ingestion_job(&job_id).await
+                    .ok()
+                    .flatten()
+                    .map(|job| job.documents_processed)
+                    .unwrap_or(0);
+                state.db.update_ingestion_job(&job_id, JobStatus::Failed, processed, Some(&e.to_string())).await
+            }
+        };
+        if let Err(e) = outcome {
+            tracing::error!("Failed to record outcome of ingestion job {}: {}", job_id, e);
+        }
+    });
+}
@@ -2,13 +2,139 @@ pub mod app;
 pub mod models;
 pub mod database;
 pub mod ai_service;
+pub mod embeddings_service;
+pub mod vector_store;
+pub mod reranker;
+pub mod memory;
+pub mod usage;
 pub mod api;
 pub mod components;
+#[cfg(feature = "ssr")]
+pub mod handlers;
+#[cfg(feature = "ssr")]
+pub mod highlight;
+#[cfg(feature = "ssr")]
+pub mod knowledge_base;
+#[cfg(feature = "ssr")]
+pub mod rag;
+#[cfg(feature = "ssr")]
+pub mod outbound;
+#[cfg(feature = "ssr")]
+pub mod crawler;
+#[cfg(feature = "ssr")]
+pub mod github;
+#[cfg(feature = "ssr")]
+pub mod connectors;
+#[cfg(feature = "ssr")]
+pub mod scheduler;
+#[cfg(feature = "ssr")]
+pub mod conversation_search;
+#[cfg(feature = "ssr")]
+pub mod trash;
+#[cfg(feature = "ssr")]
+pub mod backup;
+#[cfg(feature = "ssr")]
+pub mod export;
+#[cfg(feature = "ssr")]
+pub mod import;
+#[cfg(feature = "ssr")]
+pub mod retention;
+#[cfg(feature = "ssr")]
+pub mod audit;
+#[cfg(feature = "ssr")]
+pub mod usage_stats;
+#[cfg(feature = "ssr")]
+pub mod seed;
+#[cfg(feature = "ssr")]
+pub mod organizations;
+#[cfg(feature = "ssr")]
+pub mod attachment_gc;
+#[cfg(feature = "ssr")]
+pub mod auth;
+#[cfg(feature = "ssr")]
+pub mod secrets;
+#[cfg(feature = "ssr")]
+pub mod api_keys;
+#[cfg(feature = "ssr")]
+pub mod rate_limit;
+#[cfg(feature = "ssr")]
+pub mod csrf;
+#[cfg(feature = "ssr")]
+pub mod moderation;
+#[cfg(feature = "ssr")]
+pub mod redaction;
+#[cfg(feature = "ssr")]
+pub mod injection_guard;
+#[cfg(feature = "ssr")]
+pub mod permissions;
+#[cfg(feature = "ssr")]
+pub mod two_factor;
+#[cfg(feature = "ssr")]
+pub mod guests;
+#[cfg(feature = "ssr")]
+pub mod mailer;
+#[cfg(feature = "ssr")]
+pub mod password_reset;
+#[cfg(feature = "ssr")]
+pub mod account;
+#[cfg(feature = "ssr")]
+pub mod attachment_urls;
+#[cfg(feature = "ssr")]
+pub mod login_throttle;
+#[cfg(feature = "ssr")]
+pub mod api_tokens;
+#[cfg(feature = "ssr")]
+pub mod oidc;
+#[cfg(feature = "ssr")]
+pub mod rest_api;
+#[cfg(feature = "ssr")]
+pub mod api_versions;
+#[cfg(feature = "ssr")]
+pub mod webhooks;
+#[cfg(feature = "ssr")]
+pub mod session_events;
+#[cfg(feature = "ssr")]
+pub mod email_gateway;
+#[cfg(feature = "ssr")]
+pub mod graphql;
+#[cfg(feature = "ssr")]
+pub mod config;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
+#[cfg(feature = "ssr")]
+pub mod shutdown;
+#[cfg(feature = "ssr")]
+pub mod batch;
+#[cfg(feature = "ssr")]
+pub mod pdf;
+#[cfg(feature = "ssr")]
+pub mod schedules;
+#[cfg(feature = "ssr")]
+pub mod mcp;
+#[cfg(feature = "ssr")]
+pub mod rules;
+#[cfg(feature = "ssr")]
+pub mod feeds;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "matrix")]
+pub mod matrix;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
     use crate::app::*;
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
     console_error_panic_hook::set_once();
+    // Logs go to the browser console; wasm has no clock for timestamps
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .without_time()
+                .with_writer(tracing_web::MakeWebConsoleWriter::new()),
+        )
+        .init();
     leptos::mount::hydrate_body(App);
 }
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use crate::{api::AppState, audit::ANONYMOUS_ACTOR, models::*};
+
+// Failures count for this long. An email or address with too many of them in that time is
+// locked out until the oldest of those ages out.
+const WINDOW_MINUTES: i64 = 15;
+// Failures an email may have before it is locked out, whether or not it has an account, so
+// lockouts don't give away which addresses are registered
+const MAX_EMAIL_FAILURES: i64 = 5;
+// Failures one client address may have across every email it tries
+const MAX_ADDRESS_FAILURES: i64 = 20;
+
+fn normalize(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// When a lockout ends, given the newest failures up to the limit, newest first
+fn locked_until(failures: &[DateTime<Utc>], max_failures: i64) -> Option<DateTime<Utc>> {
+    if (failures.len() as i64) < max_failures {
+        return None;
+    }
+    failures.last().map(|oldest| *oldest + Duration::minutes(WINDOW_MINUTES))
+}
+
+async fn lockouts(state: &AppState, email: &str, ip: Option<&str>, now: DateTime<Utc>) -> Result<(Option<DateTime<Utc>>, Option<DateTime<Utc>>)> {
+    let since = now - Duration::minutes(WINDOW_MINUTES);
+    let email_failures = state.db.get_login_failures_for_email(email, since, MAX_EMAIL_FAILURES).await?;
+    let address_failures = match ip {
+        Some(ip) => state.db.get_login_failures_for_ip(ip, since, MAX_ADDRESS_FAILURES).await?,
+        None => Vec::new(),
+    };
+    Ok((locked_until(&email_failures, MAX_EMAIL_FAILURES), locked_until(&address_failures, MAX_ADDRESS_FAILURES)))
+}
+
+// Fails, before the password is looked at, while the email or the client address is locked out
+pub async fn check(state: &AppState, email: &str, ip: Option<&str>) -> Result<()> {
+    let now = Utc::now();
+    let (email_until, address_until) = lockouts(state, &normalize(email), ip, now).await?;
+    if let Some(until) = email_until.into_iter().chain(address_until).max() {
+        let minutes = ((until - now).num_seconds() as f64 / 60.0).ceil().max(1.0);
+        return Err(anyhow::anyhow!("Too many failed sign-ins. Try again in {} minutes, or reset your password.", minutes));
+    }
+    Ok(())
+}
+
+// Counts a wrong password or two-factor code, and audits it along with any lockout it starts.
+// Entries name the account, if there is one, but never the email that was typed.
+pub async fn record_failure(state: &AppState, email: &str, ip: Option<&str>) -> Result<()> {
+    let email = normalize(email);
+    let now = Utc::now();
+    state.db.record_login_failure(&email, ip, now).await?;
+
+    let account_id = state.db.get_user_by_email(&email).await?.map(|user| user.id);
+    let details = serde_json::json!({ "ip_address": ip });
+    crate::audit::record(state, ANONYMOUS_ACTOR, AuditAction::LoginFailed, account_id.as_deref(), Some(details)).await?;
+
+    // Only the failure that reaches a limit starts a lockout; later attempts are refused
+    // by `check` without being counted
+    let since = now - Duration::minutes(WINDOW_MINUTES);
+    let email_failures = state.db.get_login_failures_for_email(&email, since, MAX_EMAIL_FAILURES + 1).await?.len() as i64;
+    let address_failures = match ip {
+        Some(ip) => state.db.get_login_failures_for_ip(ip, since, MAX_ADDRESS_FAILURES + 1).await?.len() as i64,
+        None => 0,
+    };
+    for (scope, failures, max_failures) in [("email", email_failures, MAX_EMAIL_FAILURES), ("address", address_failures, MAX_ADDRESS_FAILURES)] {
+        if failures == max_failures {
+            tracing::warn!("Locked out sign-ins by {} after {} failures", scope, failures);
+            let details = serde_json::json!({ "scope": scope, "ip_address": ip, "minutes": WINDOW_MINUTES });
+            crate::audit::record(state, ANONYMOUS_ACTOR, AuditAction::LoginLockedOut, account_id.as_deref(), Some(details)).await?;
+        }
+    }
+    Ok(())
+}
+
+// Clears the email's failures once someone signs in with it
+pub async fn record_success(state: &AppState, email: &str) -> Result<()> {
+    state.db.clear_login_failures(&normalize(email)).await
+}
+
+// Removes failures too old to count toward a lockout
+pub async fn remove_expired(state: &AppState) -> Result<u64> {
+    state.db.delete_login_failures_before(Utc::now() - Duration::minutes(WINDOW_MINUTES)).await
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    // Plain connection upgraded with STARTTLS, usually on port 587
+    StartTls,
+    // TLS from the start, usually on port 465
+    Tls,
+    // No encryption, for a relay on the same host or network
+    None,
+}
+
+impl From<String> for SmtpSecurity {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "tls" => SmtpSecurity::Tls,
+            "none" => SmtpSecurity::None,
+            _ => SmtpSecurity::StartTls,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MailerConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    // Both or neither
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sender of every email, like `aibot <noreply@example.com>`
+    pub from: String,
+}
+
+// Sends the instance's emails, such as password reset links, through an SMTP server
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: MailerConfig) -> Result<Self> {
+        let mut builder = match config.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        }
+        .port(config.port);
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse().map_err(|e| anyhow::anyhow!("Invalid SMTP_FROM: {}", e))?,
+        })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().map_err(|e| anyhow::anyhow!("Invalid email address: {}", e))?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    // Sends a reply in the thread of the email with `in_reply_to` as its Message-ID, whose
+    // References were `references`. Returns the reply's own Message-ID.
+    pub async fn send_reply(&self, to: &str, subject: &str, body: &str, in_reply_to: &str, references: &[String]) -> Result<String> {
+        let domain = self.from.email.domain();
+        let message_id = format!("<{}@{}>", uuid::Uuid::new_v4(), domain);
+        let mut thread = references.to_vec();
+        thread.push(in_reply_to.to_string());
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().map_err(|e| anyhow::anyhow!("Invalid email address: {}", e))?)
+            .subject(subject)
+            .message_id(Some(message_id.clone()))
+            .in_reply_to(in_reply_to.to_string())
+            .references(thread.join(" "))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())?;
+        self.transport.send(message).await?;
+        Ok(message_id)
+    }
+}
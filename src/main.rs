@@ -1,34 +1,388 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
-    use axum::Router;
-    use leptos::logging::log;
+    use axum::{extract::{DefaultBodyLimit, Extension}, middleware, routing::{delete, get, post, put}, Router};
     use leptos::prelude::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
     use aibot::app::*;
-    use aibot::{database::Database, ai_service::{AIService, AIServiceConfig}, api::AppState};
+    use aibot::{
+        database::{Database, DatabaseConfig},
+        ai_service::AIService,
+        embeddings_service::{EmbeddingsService, EmbeddingsServiceConfig},
+        vector_store::VectorStore,
+        reranker::{Reranker, RerankerConfig},
+        moderation::{Moderator, ModeratorConfig},
+        memory::MemoryPolicy,
+        usage::UsageLimits,
+        api::AppState,
+        models::RetentionPolicy,
+        secrets::SecretBox,
+        rate_limit::RateLimiter,
+        csrf::TrustedOrigins,
+        mailer::{Mailer, MailerConfig},
+        email_gateway::{EmailGateway, EmailGatewayConfig},
+        oidc::{self, Oidc, OidcConfig},
+        handlers,
+        rest_api,
+        graphql,
+    };
+    use aibot::telemetry::REQUEST_ID_HEADER;
+    use aibot::config::Settings;
     use dotenvy::dotenv;
+    use tower_http::{
+        request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+        trace::TraceLayer,
+    };
     use std::env;
 
-    // Load environment variables
+    // Load environment variables, then the config file they are layered over
     dotenv().ok();
+    let settings = Settings::load().expect("Invalid configuration");
+
+    // Logs go to stderr, as text or JSON; with an OTLP endpoint, traces are exported as well
+    let _telemetry = aibot::telemetry::init(aibot::telemetry::TelemetryConfig {
+        log_format: settings.logging.format.as_deref()
+            .and_then(aibot::telemetry::LogFormat::parse)
+            .unwrap_or_default(),
+        otlp_endpoint: settings.logging.otlp_endpoint.clone(),
+        service_name: settings.logging.service_name.clone().unwrap_or_else(|| "aibot".to_string()),
+    });
 
     // Initialize database
-    let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:./aibot.db".to_string());
-    let db = Database::new(&database_url).await.expect("Failed to initialize database");
+    let storage = &settings.storage;
+    let database_url = storage.database_url.clone().unwrap_or_else(|| "sqlite:./aibot.db".to_string());
+    // SQLCipher key, given directly or as a file holding it
+    let sqlite_key = storage.sqlcipher_key.clone()
+        .or_else(|| {
+            storage.sqlcipher_key_file.as_ref().map(|path| {
+                std::fs::read_to_string(path).expect("Failed to read SQLCIPHER_KEY_FILE").trim().to_string()
+            })
+        })
+        .filter(|key| !key.is_empty());
+
+    let database_defaults = DatabaseConfig::default();
+    let database_config = DatabaseConfig {
+        max_connections: storage.max_connections.unwrap_or(database_defaults.max_connections),
+        acquire_timeout_secs: storage.acquire_timeout_secs.unwrap_or(database_defaults.acquire_timeout_secs),
+        sqlite_wal: storage.sqlite_wal.unwrap_or(database_defaults.sqlite_wal),
+        sqlite_busy_timeout_ms: storage.sqlite_busy_timeout_ms.unwrap_or(database_defaults.sqlite_busy_timeout_ms),
+        sqlite_foreign_keys: storage.sqlite_foreign_keys.unwrap_or(database_defaults.sqlite_foreign_keys),
+        sqlite_key,
+    };
+    let db = Database::new(&database_url, &database_config).await.expect("Failed to initialize database");
+
+    // `--seed-demo` (or SEED_DEMO=true, for `cargo leptos watch`) fills the database with
+    // demo chats and memories before serving
+    let seed_demo = env::args().any(|arg| arg == "--seed-demo")
+        || settings.features.seed_demo.unwrap_or(false);
+    if seed_demo {
+        aibot::seed::seed_demo(&db).await.expect("Failed to seed demo data");
+        tracing::info!("Seeded demo data; sign in as {} / {}", aibot::seed::DEMO_EMAIL, aibot::seed::DEMO_PASSWORD);
+    }
 
     // Initialize AI service
-    let ai_config = AIServiceConfig {
-        openai_api_key: env::var("OPENAI_API_KEY").ok(),
-        anthropic_api_key: env::var("ANTHROPIC_API_KEY").ok(),
-        gemini_api_key: env::var("GEMINI_API_KEY").ok(),
-        openrouter_api_key: env::var("OPENROUTER_API_KEY").ok(),
-        ollama_base_url: env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string()),
+    let providers = &settings.providers;
+    let ai_service = AIService::new(settings.ai_service_config()).await.expect("Failed to initialize AI service");
+
+    // Initialize embeddings service
+    let embeddings_defaults = EmbeddingsServiceConfig::default();
+    let embeddings_config = EmbeddingsServiceConfig {
+        provider: settings.embeddings.provider.clone().map(Into::into).unwrap_or(embeddings_defaults.provider),
+        model_name: settings.embeddings.model.clone().unwrap_or(embeddings_defaults.model_name),
+        openai_api_key: providers.openai_api_key.clone(),
+        ollama_base_url: providers.ollama_base_url.clone().unwrap_or(embeddings_defaults.ollama_base_url),
+        local_base_url: settings.embeddings.local_url.clone().unwrap_or(embeddings_defaults.local_base_url),
+        batch_size: settings.embeddings.batch_size.unwrap_or(embeddings_defaults.batch_size),
+    };
+    let embeddings = EmbeddingsService::new(embeddings_config).expect("Failed to initialize embeddings service");
+
+    // Initialize vector index; the dimension must match the embedding model
+    let vector_dimensions = storage.vector_dimensions.unwrap_or(768);
+    let vectors = VectorStore::new(db.pool().clone(), vector_dimensions).await.expect("Failed to initialize vector store");
+
+    // Optional reranker, enabled by setting a rerank provider
+    let rerank = &settings.rerank;
+    let reranker = rerank.provider.clone().map(|provider| {
+        let config = RerankerConfig {
+            provider: provider.into(),
+            model_name: rerank.model.clone().unwrap_or_else(|| "rerank-english-v3.0".to_string()),
+            api_key: rerank.cohere_api_key.clone(),
+            base_url: rerank.local_url.clone().unwrap_or_else(|| "http://localhost:8081".to_string()),
+        };
+        Reranker::new(config).expect("Failed to initialize reranker")
+    });
+
+    // Optional content filter on messages and replies, enabled by setting a moderation provider
+    let moderation = &settings.moderation;
+    let moderator = moderation.provider.clone().map(|provider| {
+        let config = ModeratorConfig {
+            provider: provider.into(),
+            model_name: moderation.model.clone().unwrap_or_else(|| "omni-moderation-latest".to_string()),
+            api_key: providers.openai_api_key.clone(),
+            base_url: moderation.local_url.clone().unwrap_or_else(|| "http://localhost:8082".to_string()),
+            action: moderation.action.clone().unwrap_or_else(|| "block".to_string()).into(),
+            threshold: moderation.threshold.unwrap_or(0.5),
+        };
+        Moderator::new(config).expect("Failed to initialize content filter")
+    });
+
+    // Memory decay policy
+    let memory_defaults = MemoryPolicy::default();
+    let memory_policy = MemoryPolicy {
+        half_life_days: settings.memory.half_life_days.unwrap_or(memory_defaults.half_life_days),
+        min_confidence: settings.memory.min_confidence.unwrap_or(memory_defaults.min_confidence),
+    };
+
+    // Per-user knowledge base cap; unset means unlimited
+    let usage_limits = UsageLimits {
+        max_kb_chunks_per_user: settings.limits.kb_max_chunks_per_user,
+    };
+
+    // How long deleted sessions and messages stay restorable
+    let trash_retention_days = settings.retention.trash_days.unwrap_or(30);
+    // How long a user can change their mind after asking for their account to be deleted
+    let account_deletion_grace_days = settings.retention.account_deletion_grace_days.unwrap_or(14);
+
+    // Instance-wide retention; unset limits keep history forever
+    let retention_policy = RetentionPolicy {
+        message_retention_days: settings.retention.message_days,
+        max_sessions: settings.retention.max_sessions,
+    };
+    let retention_dry_run = settings.retention.dry_run.unwrap_or(false);
+
+    // Admin endpoints (backup, restore and retention reports) are only served when a token is set
+    let admin_token = settings.server.admin_token.clone();
+
+    // Master key sealing provider keys stored in the database, given directly or as a file holding it
+    let security = &settings.security;
+    let secrets = security.secrets_key.clone()
+        .or_else(|| {
+            security.secrets_key_file.as_ref().map(|path| {
+                std::fs::read_to_string(path).expect("Failed to read SECRETS_KEY_FILE").trim().to_string()
+            })
+        })
+        .filter(|key| !key.is_empty())
+        .map(|key| SecretBox::from_hex(&key).expect("Invalid SECRETS_KEY"));
+    // Organization keys saved before they were sealed are sealed now, or left readable as they are
+    match &secrets {
+        Some(secret_box) => {
+            let sealed = aibot::organizations::seal_legacy_keys(&db, secret_box).await.expect("Failed to seal organization keys");
+            if sealed > 0 {
+                tracing::info!("Sealed {} organization API keys stored in plain text", sealed);
+            }
+        }
+        None => {
+            let unsealed = db.get_unsealed_organization_api_keys().await.map(|keys| keys.len()).unwrap_or(0);
+            if unsealed > 0 {
+                tracing::warn!("{} organization API keys are stored in plain text; set SECRETS_KEY to seal them", unsealed);
+            }
+        }
+    }
+
+    // Hold admins to a member's capabilities until they turn on two-factor authentication
+    let require_admin_two_factor = security.require_admin_2fa.unwrap_or(false);
+    if require_admin_two_factor && secrets.is_none() {
+        panic!("REQUIRE_ADMIN_2FA needs SECRETS_KEY, which two-factor secrets are sealed with");
+    }
+
+    // Let visitors chat as a guest with a local Ollama model before signing up
+    let default_model = settings.default_model();
+    let guest_model = std::sync::Arc::new(std::sync::RwLock::new(settings.guest_model()));
+
+    // SMTP server for password reset emails; resetting by email is off without SMTP_HOST
+    let smtp = &settings.smtp;
+    let mailer = smtp.host.clone().map(|host| {
+        Mailer::new(MailerConfig {
+            host,
+            port: smtp.port.unwrap_or(587),
+            security: smtp.security.clone().unwrap_or_default().into(),
+            username: smtp.username.clone(),
+            password: smtp.password.clone(),
+            from: smtp.from.clone().expect("SMTP_FROM must be set with SMTP_HOST"),
+        }).expect("Invalid SMTP configuration")
+    });
+    // Single sign-on with an OpenID Connect provider such as Okta, Keycloak or Azure AD; off
+    // without OIDC_ISSUER_URL
+    let public_base_url = settings.server.public_base_url.clone().unwrap_or_else(|| "http://127.0.0.1:3000".to_string());
+    let oidc_settings = &settings.oidc;
+    let oidc = oidc_settings.issuer_url.clone().map(|issuer| {
+        Oidc::new(OidcConfig {
+            issuer,
+            client_id: oidc_settings.client_id.clone().expect("OIDC_CLIENT_ID must be set with OIDC_ISSUER_URL"),
+            client_secret: oidc_settings.client_secret.clone().expect("OIDC_CLIENT_SECRET must be set with OIDC_ISSUER_URL"),
+            redirect_url: format!("{}{}", public_base_url.trim_end_matches('/'), oidc::CALLBACK_PATH),
+            scopes: oidc_settings.scopes.clone().unwrap_or_else(|| "openid email profile".to_string()),
+            groups_claim: oidc_settings.groups_claim.clone().unwrap_or_else(|| "groups".to_string()),
+            role_mapping: oidc::parse_role_mapping(oidc_settings.role_mapping.as_deref().unwrap_or_default())
+                .expect("Invalid OIDC_ROLE_MAPPING"),
+            // "none" turns away users in none of the mapped groups
+            default_role: match oidc_settings.default_role.as_deref().unwrap_or("member") {
+                "none" => None,
+                role => Some(oidc::parse_role(role).expect("Invalid OIDC_DEFAULT_ROLE")),
+            },
+            display_name: oidc_settings.display_name.clone().unwrap_or_else(|| "SSO".to_string()),
+        })
+    });
+    // Keys signing password reset tokens and attachment links. Without one a random key is
+    // used, and what it signed stops working when the server restarts.
+    let signing_key = |secret: &Option<String>| secret.clone()
+        .map(String::into_bytes)
+        .unwrap_or_else(|| {
+            use argon2::password_hash::rand_core::{OsRng, RngCore};
+            let mut key = vec![0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            key
+        });
+    let reset_token_key = signing_key(&security.password_reset_secret);
+    let attachment_url_key = signing_key(&security.attachment_url_secret);
+    // How long attachment links given to the browser or written into exports keep working
+    let attachment_url_ttl_hours = security.attachment_url_ttl_hours.unwrap_or(24);
+
+    // Per-user message and upload limits
+    let rate_limiter = RateLimiter::new(settings.rate_limits());
+
+    // Questions by email, posted by the email provider with INBOUND_EMAIL_SECRET; replies go
+    // out through the SMTP server
+    let email_gateway = settings.email.inbound_secret.clone().map(|secret| {
+        if mailer.is_none() {
+            panic!("INBOUND_EMAIL_SECRET needs SMTP_HOST to send the replies");
+        }
+        EmailGateway::new(EmailGatewayConfig {
+            secret,
+            model_provider: aibot::models::AIProvider::from(
+                settings.email.model_provider.clone().or_else(|| providers.default_provider.clone()).unwrap_or_default(),
+            ),
+            model_name: settings.email.model.clone().unwrap_or_else(|| default_model.clone()),
+        }, rate_limiter.clone())
+    });
+
+    // Origins allowed to make cookie-authenticated API calls besides the server's own
+    let trusted_origins = TrustedOrigins::new(
+        settings.server.public_base_url.iter()
+            .chain(&settings.server.trusted_origins)
+            .cloned()
+            .collect(),
+    );
+    // Other frontends allowed to call the API from the browser with a personal access token.
+    // Credentials aren't allowed, so the browser never sends them the user's cookie.
+    let cors = {
+        use axum::http::{header, HeaderValue, Method};
+        use tower_http::cors::{AllowOrigin, CorsLayer};
+        let origins: Vec<HeaderValue> = settings.server.cors_origins.iter()
+            .map(|origin| origin.trim_end_matches('/'))
+            .map(|origin| HeaderValue::from_str(origin).expect("Invalid origin in CORS_ORIGINS"))
+            .collect();
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT])
+            .expose_headers([header::CONTENT_DISPOSITION, header::RETRY_AFTER])
+            .max_age(std::time::Duration::from_secs(60 * 60))
     };
-    let ai_service = AIService::new(ai_config).await.expect("Failed to initialize AI service");
 
     // Create app state
-    let app_state = AppState { db, ai_service };
+    let app_state = AppState {
+        db,
+        ai_service: std::sync::Arc::new(ai_service),
+        embeddings,
+        vectors,
+        reranker,
+        moderator,
+        memory_policy,
+        usage_limits,
+        trash_retention_days,
+        account_deletion_grace_days,
+        retention_policy,
+        retention_dry_run,
+        admin_token,
+        secrets,
+        require_admin_two_factor,
+        guest_model,
+        mailer,
+        oidc,
+        email_gateway,
+        session_events: Default::default(),
+        shutdown: Default::default(),
+        public_base_url,
+        reset_token_key,
+        attachment_url_key,
+        attachment_url_ttl_hours,
+    };
+
+    // Background re-sync of connectors and crawled websites, message indexing and trash purging
+    aibot::scheduler::spawn(app_state.clone());
+    // Batch jobs a restart interrupted carry on where they were
+    if let Err(e) = aibot::batch::resume(&app_state).await {
+        tracing::error!("Failed to resume batch jobs: {}", e);
+    }
+
+    // Kept for stopping the server once the state has been handed to the router
+    let shutdown = app_state.shutdown.clone();
+    let shutdown_grace = std::time::Duration::from_secs(settings.server.shutdown_grace_secs.unwrap_or(30));
+    let db = app_state.db.clone();
+
+    // The gRPC API listens on its own address, and only when one is given
+    match settings.server.grpc_addr.clone() {
+        #[cfg(feature = "grpc")]
+        Some(grpc_addr) => {
+            let grpc_addr: std::net::SocketAddr = grpc_addr.parse().expect("Invalid GRPC_ADDR");
+            let (state, limiter) = (app_state.clone(), rate_limiter.clone());
+            tracing::info!("gRPC listening on {}", grpc_addr);
+            tokio::spawn(async move {
+                if let Err(e) = aibot::grpc::serve(state, limiter, grpc_addr).await {
+                    tracing::error!("gRPC server stopped: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "grpc"))]
+        Some(_) => tracing::warn!("GRPC_ADDR is set but this build doesn't include the grpc feature"),
+        None => {}
+    }
+
+    // Matrix bot, when a homeserver is given
+    match settings.matrix.homeserver_url.clone() {
+        #[cfg(feature = "matrix")]
+        Some(homeserver_url) => {
+            let matrix = &settings.matrix;
+            let config = aibot::matrix::MatrixConfig {
+                homeserver_url,
+                user_id: matrix.user_id.clone().expect("MATRIX_USER_ID must be set with MATRIX_HOMESERVER_URL"),
+                password: matrix.password.clone().expect("MATRIX_PASSWORD must be set with MATRIX_HOMESERVER_URL"),
+                store_path: matrix.store_path.clone().unwrap_or_else(|| "matrix-store".to_string()).into(),
+                store_passphrase: matrix.store_passphrase.clone(),
+                allowed: matrix.allowed_users.clone(),
+                model_provider: aibot::models::AIProvider::from(
+                    matrix.model_provider.clone().or_else(|| providers.default_provider.clone()).unwrap_or_default(),
+                ),
+                model_name: matrix.model.clone().unwrap_or_else(|| default_model.clone()),
+            };
+            let (state, limiter) = (app_state.clone(), rate_limiter.clone());
+            tokio::spawn(async move {
+                if let Err(e) = aibot::matrix::run(state, limiter, config).await {
+                    tracing::error!("Matrix bot stopped: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "matrix"))]
+        Some(_) => tracing::warn!("MATRIX_HOMESERVER_URL is set but this build doesn't include the matrix feature"),
+        None => {}
+    }
+
+    // SIGHUP reloads the configuration, like the admin endpoint does
+    #[cfg(unix)]
+    {
+        let (state, limiter) = (app_state.clone(), rate_limiter.clone());
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangups = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+            while hangups.recv().await.is_some() {
+                match aibot::config::reload(&state, &limiter).await {
+                    Ok(()) => tracing::info!("Reloaded configuration"),
+                    Err(e) => tracing::error!("Failed to reload configuration, keeping the current one: {}", e),
+                }
+            }
+        });
+    }
 
     let conf = get_configuration(None).unwrap();
     let addr = conf.leptos_options.site_addr;
@@ -36,22 +390,121 @@ async fn main() {
     // Generate the list of routes in your Leptos App
     let routes = generate_route_list(App);
 
+    // The GraphQL schema resolves against the same state and limits as everything else
+    let graphql_schema = graphql::schema(app_state.clone(), rate_limiter.clone());
+
+    // Plain HTTP endpoints that sit alongside the server functions
+    let api_routes = Router::new()
+        .route("/healthz", get(handlers::healthz))
+        .route("/readyz", get(handlers::readyz))
+        .route("/metrics", get(handlers::metrics))
+        .route("/api/sessions/{session_id}/attachments.zip", get(handlers::export_session_attachments))
+        .route("/api/sessions/{session_id}/export", get(handlers::export_session))
+        .route("/api/sessions/{session_id}/events", get(handlers::session_events))
+        .route("/api/sync", get(handlers::sync))
+        .route("/api/attachments/{attachment_id}", get(handlers::serve_attachment))
+        .route("/api/connectors/{provider}/callback", get(handlers::connector_oauth_callback))
+        .route("/api/v1/sessions", get(rest_api::list_sessions).post(rest_api::create_session))
+        .route("/api/v1/sessions/{session_id}", delete(rest_api::delete_session))
+        .route("/api/v1/sessions/{session_id}/export", get(rest_api::export_session))
+        .route("/api/v1/sessions/{session_id}/messages", get(rest_api::list_messages).post(rest_api::post_message))
+        .route("/api/v1/embeddings", post(rest_api::create_embeddings))
+        .route("/api/v1/batches", get(rest_api::list_batches).post(rest_api::create_batch).layer(DefaultBodyLimit::max(rest_api::MAX_BATCH_BYTES)))
+        .route("/api/v1/batches/{batch_id}", get(rest_api::get_batch))
+        .route("/api/v1/batches/{batch_id}/results", get(rest_api::batch_results))
+        .route("/api/v1/rules", get(rest_api::list_rules).post(rest_api::create_rule))
+        .route("/api/v1/rules/{rule_id}", put(rest_api::update_rule).delete(rest_api::delete_rule))
+        .route(rest_api::TRANSCRIPTIONS_PATH, post(rest_api::create_transcription).layer(DefaultBodyLimit::max(rest_api::MAX_AUDIO_BYTES)))
+        .route(rest_api::OPENAPI_PATH, get(rest_api::openapi))
+        .route(aibot::api_versions::VERSIONS_PATH, get(aibot::api_versions::list_versions))
+        .route(aibot::mcp::PATH, post(aibot::mcp::handle))
+        .route("/api/graphql", get(graphql::graphql).post(graphql::graphql))
+        .route("/api/graphql/ws", get(graphql::graphql_ws))
+        .route("/api/graphql/schema.graphql", get(graphql::sdl))
+        .route("/api/auth/oidc/login", get(handlers::oidc_login))
+        .route("/api/auth/oidc/callback", get(handlers::oidc_callback))
+        .route(aibot::email_gateway::INBOUND_PATH, post(handlers::inbound_email))
+        .route("/api/memory/export", get(handlers::export_memory))
+        .route("/api/account/export", get(handlers::export_account))
+        .route("/api/admin/backup", get(handlers::download_backup))
+        .route("/api/admin/retention/preview", get(handlers::preview_retention))
+        .route("/api/admin/audit", get(handlers::audit_log))
+        .route("/api/admin/usage", get(handlers::usage_stats))
+        .route("/api/admin/moderation", get(handlers::moderation_events))
+        .route("/api/admin/organizations", get(handlers::list_organizations).post(handlers::create_organization))
+        .route("/api/admin/organizations/{org_id}/settings", put(handlers::update_organization_settings))
+        .route("/api/admin/organizations/{org_id}/keys", get(handlers::list_organization_keys))
+        .route("/api/admin/organizations/{org_id}/keys/{provider}", put(handlers::set_organization_key).delete(handlers::delete_organization_key))
+        .route("/api/admin/users/{user_id}/organization", put(handlers::set_user_organization))
+        .route("/api/admin/webhooks", get(handlers::list_webhooks).post(handlers::create_webhook))
+        .route("/api/admin/webhooks/{webhook_id}", delete(handlers::delete_webhook))
+        .route("/api/admin/webhooks/{webhook_id}/deliveries", get(handlers::webhook_deliveries))
+        .route("/api/admin/config/reload", post(handlers::reload_config))
+        // Backups include every upload, so they easily exceed the default body limit
+        .route("/api/admin/restore", post(handlers::restore_backup).layer(DefaultBodyLimit::disable()))
+        .layer(Extension(graphql_schema))
+        .layer(Extension(rate_limiter.clone()))
+        .with_state(app_state.clone());
+
     let app = Router::new()
+        .merge(api_routes)
         .leptos_routes(&leptos_options, routes, {
             let leptos_options = leptos_options.clone();
             move || shell(leptos_options.clone())
         })
         .fallback(leptos_axum::file_and_error_handler(shell))
+        // Layers run bottom-up: each request gets an ID and a trace span carrying it, CORS
+        // preflights are answered and cross-site calls are blocked first, then the client address and the session cookie or API token are resolved to
+        // the signed-in user before signed-out API calls are rejected, the rest are rate
+        // limited, and REST API calls are checked against the versions still served
+        .layer(middleware::from_fn(aibot::api_versions::versioning))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), aibot::rate_limit::rate_limit))
+        .layer(middleware::from_fn(aibot::auth::require_sign_in))
+        .layer(middleware::from_fn_with_state(app_state.clone(), aibot::auth::session_middleware))
+        .layer(middleware::from_fn_with_state(rate_limiter, aibot::rate_limit::resolve_client_ip))
+        .layer(middleware::from_fn_with_state(trusted_origins, aibot::csrf::require_same_origin))
+        .layer(cors)
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http().make_span_with(aibot::telemetry::request_span))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
         .with_state(leptos_options)
         .with_state(app_state);
 
     // run our app with hyper
     // `axum::Server` is a re-export of `hyper::Server`
-    log!("listening on http://{}", &addr);
+    tracing::info!("listening on http://{}", &addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    // Connection info gives the rate limiter each client's address. On SIGTERM or Ctrl+C no
+    // new connections are accepted, and requests in progress, like replies being written,
+    // get until the grace period ends to finish.
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            aibot::shutdown::signal().await;
+            tracing::info!("Shutting down; waiting up to {}s for requests and background jobs", shutdown_grace.as_secs());
+            shutdown.trigger();
+        }
+    });
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async { shutdown.triggered().await; tokio::time::sleep(shutdown_grace).await } => {
+            tracing::warn!("Requests still in progress after {}s were cut off", shutdown_grace.as_secs());
+        }
+    }
+
+    // Background jobs record how far they got before the database closes
+    if !shutdown.wait_for_tasks(shutdown_grace).await {
+        tracing::warn!("Background tasks still running after {}s were cut off", shutdown_grace.as_secs());
+    }
+    if let Err(e) = db.close().await {
+        tracing::error!("Failed to close the database cleanly: {}", e);
+    }
+    tracing::info!("Stopped");
 }
 
 #[cfg(not(feature = "ssr"))]
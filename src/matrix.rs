@@ -0,0 +1,226 @@
+use anyhow::Result;
+use matrix_sdk::{
+    config::SyncSettings,
+    matrix_auth::MatrixSession,
+    ruma::{
+        events::room::{
+            member::StrippedRoomMemberEvent,
+            message::{MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        },
+        OwnedUserId, UserId,
+    },
+    Client, Room, RoomState,
+};
+use std::{path::PathBuf, sync::Arc};
+use crate::{api::{self, AppState}, models::*, rate_limit::RateLimiter};
+
+// Matrix users get accounts of their own, found again by this issuer and their Matrix ID
+pub const MATRIX_ISSUER: &str = "matrix";
+// Sent on its own, starts a new conversation in the room
+const NEW_SESSION_COMMAND: &str = "!new";
+// Where the login is kept in the store directory, so the bot stays the same device across
+// restarts and keeps its encryption keys
+const SESSION_FILE: &str = "session.json";
+
+#[derive(Debug, Clone)]
+pub struct MatrixConfig {
+    pub homeserver_url: String,
+    // The bot's account, like `@aibot:example.org`
+    pub user_id: String,
+    pub password: String,
+    // Holds the login and the encryption keys; keep it private and don't share it between
+    // servers
+    pub store_path: PathBuf,
+    // Encrypts the store; None leaves it in plain text
+    pub store_passphrase: Option<String>,
+    // Who may chat with the bot: Matrix IDs like `@alice:example.org`, or whole servers like
+    // `example.org`. Empty allows the bot's own server.
+    pub allowed: Vec<String>,
+    // The model new conversations use
+    pub model_provider: AIProvider,
+    pub model_name: String,
+}
+
+// A bot that answers in Matrix rooms, encrypted ones included. It joins rooms it is invited
+// to by allowed users, answers every message in a direct chat and, in group rooms, the
+// messages that mention it. Each user's conversation in a room is a session of their own.
+struct Bot {
+    state: AppState,
+    limiter: RateLimiter,
+    config: MatrixConfig,
+    user_id: OwnedUserId,
+}
+
+// Signs the bot in, reusing its device when it has signed in before
+async fn login(config: &MatrixConfig) -> Result<Client> {
+    let client = Client::builder()
+        .homeserver_url(&config.homeserver_url)
+        .sqlite_store(&config.store_path, config.store_passphrase.as_deref())
+        .build()
+        .await?;
+
+    let session_file = config.store_path.join(SESSION_FILE);
+    match tokio::fs::read_to_string(&session_file).await {
+        Ok(saved) => {
+            let session: MatrixSession = serde_json::from_str(&saved)?;
+            client.restore_session(session).await?;
+        }
+        Err(_) => {
+            client.matrix_auth()
+                .login_username(&config.user_id, &config.password)
+                .initial_device_display_name("aibot")
+                .await?;
+            let session = client.matrix_auth().session()
+                .ok_or_else(|| anyhow::anyhow!("Matrix login returned no session"))?;
+            tokio::fs::write(&session_file, serde_json::to_string(&session)?).await?;
+        }
+    }
+    Ok(client)
+}
+
+// Runs the bot until the connection to the homeserver fails for good
+pub async fn run(state: AppState, limiter: RateLimiter, config: MatrixConfig) -> Result<()> {
+    tokio::fs::create_dir_all(&config.store_path).await?;
+    let client = login(&config).await?;
+    let user_id = client.user_id()
+        .ok_or_else(|| anyhow::anyhow!("Matrix client isn't signed in"))?
+        .to_owned();
+    let bot = Arc::new(Bot { state, limiter, config, user_id });
+
+    // Messages sent while the bot was away aren't answered
+    let response = client.sync_once(SyncSettings::default()).await?;
+
+    client.add_event_handler({
+        let bot = bot.clone();
+        move |event: StrippedRoomMemberEvent, room: Room| {
+            let bot = bot.clone();
+            async move {
+                if let Err(e) = bot.on_invite(event, room).await {
+                    tracing::warn!("Failed to join Matrix room: {}", e);
+                }
+            }
+        }
+    });
+    client.add_event_handler({
+        let bot = bot.clone();
+        move |event: OriginalSyncRoomMessageEvent, room: Room| {
+            let bot = bot.clone();
+            async move {
+                if let Err(e) = bot.on_message(event, room).await {
+                    tracing::error!("Failed to answer Matrix message: {}", e);
+                }
+            }
+        }
+    });
+
+    client.sync(SyncSettings::default().token(response.next_batch)).await?;
+    Ok(())
+}
+
+impl Bot {
+    fn allowed(&self, sender: &UserId) -> bool {
+        if self.config.allowed.is_empty() {
+            return sender.server_name() == self.user_id.server_name();
+        }
+        self.config.allowed.iter().any(|allowed| {
+            if allowed.starts_with('@') {
+                allowed == sender.as_str()
+            } else {
+                allowed == sender.server_name().as_str()
+            }
+        })
+    }
+
+    // Clients put the bot's name or ID in the body of a message that mentions it
+    fn mentioned(&self, body: &str) -> bool {
+        let body = body.to_lowercase();
+        body.contains(&self.user_id.as_str().to_lowercase()) || body.contains(&self.user_id.localpart().to_lowercase())
+    }
+
+    async fn on_invite(&self, event: StrippedRoomMemberEvent, room: Room) -> Result<()> {
+        if event.state_key != self.user_id || room.state() != RoomState::Invited {
+            return Ok(());
+        }
+        if !self.allowed(&event.sender) {
+            tracing::info!("Declined a Matrix invite from {}", event.sender);
+            return Ok(room.leave().await?);
+        }
+        room.join().await?;
+        Ok(())
+    }
+
+    async fn on_message(&self, event: OriginalSyncRoomMessageEvent, room: Room) -> Result<()> {
+        if room.state() != RoomState::Joined || event.sender == self.user_id || !self.allowed(&event.sender) {
+            return Ok(());
+        }
+        let MessageType::Text(text) = event.content.msgtype else {
+            return Ok(());
+        };
+        if room.joined_members_count() > 2 && !self.mentioned(&text.body) {
+            return Ok(());
+        }
+
+        let user_id = self.user_for(&room, &event.sender).await?;
+        let room_id = room.room_id().as_str();
+        if text.body.trim() == NEW_SESSION_COMMAND {
+            self.new_session(room_id, &user_id).await?;
+            room.send(RoomMessageEventContent::text_plain("Started a new conversation.")).await?;
+            return Ok(());
+        }
+        if let Err(wait) = self.limiter.take_message(&user_id) {
+            let notice = format!("Too many messages. Try again in {} seconds.", (wait.as_secs_f64().ceil() as u64).max(1));
+            room.send(RoomMessageEventContent::notice_plain(notice)).await?;
+            return Ok(());
+        }
+
+        let session = match self.state.db.get_matrix_room_session(room_id, &user_id).await? {
+            Some(session_id) => self.state.db.get_session(&session_id).await?,
+            None => None,
+        };
+        let session_id = match session {
+            Some(session) if session.deleted_at.is_none() => session.id,
+            // A trashed session starts over rather than being brought back
+            _ => self.new_session(room_id, &user_id).await?,
+        };
+
+        let _ = room.typing_notice(true).await;
+        let result = api::send_message_as(&self.state, &user_id, session_id, text.body, Vec::new(), Vec::new(), Vec::new(), false).await;
+        let _ = room.typing_notice(false).await;
+
+        let reply = match result {
+            Ok(response) => match response.moderation_warning {
+                Some(warning) => format!("{}\n\n_{}_", response.content, warning),
+                None => response.content,
+            },
+            Err(e) => {
+                tracing::warn!("Matrix message in {} failed: {}", room_id, e);
+                room.send(RoomMessageEventContent::notice_plain(format!("Sorry, that didn't work: {}", e))).await?;
+                return Ok(());
+            }
+        };
+        room.send(RoomMessageEventContent::text_markdown(reply)).await?;
+        Ok(())
+    }
+
+    // The account of the Matrix user, created without a password or email the first time
+    // they write to the bot
+    async fn user_for(&self, room: &Room, sender: &UserId) -> Result<String> {
+        if let Some(user_id) = self.state.db.get_identity_user_id(MATRIX_ISSUER, sender.as_str()).await? {
+            return Ok(user_id);
+        }
+        let name = match room.get_member(sender).await? {
+            Some(member) => member.display_name().map(str::to_string),
+            None => None,
+        };
+        let user = User::new(name.or_else(|| Some(sender.to_string())), None);
+        self.state.db.create_user(&user).await?;
+        self.state.db.create_user_identity(MATRIX_ISSUER, sender.as_str(), &user.id).await?;
+        Ok(user.id)
+    }
+
+    async fn new_session(&self, room_id: &str, user_id: &str) -> Result<String> {
+        let session = api::create_session_as(&self.state, user_id, None, self.config.model_provider.clone(), self.config.model_name.clone(), false).await?;
+        self.state.db.set_matrix_room_session(room_id, user_id, &session.id).await?;
+        Ok(session.id)
+    }
+}
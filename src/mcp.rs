@@ -0,0 +1,435 @@
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Extension, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::{api::{self, AppState}, database::OwnedResource, models::*, rate_limit::RateLimiter, rest_api::page_size};
+
+// A Model Context Protocol server, so MCP clients such as desktop assistants and editors
+// can use this instance's sessions, memories and knowledge bases. It speaks JSON-RPC over
+// the Streamable HTTP transport without server-sent events: each POST carries one message
+// and gets one response. As with the gRPC API, calls authenticate with a personal access
+// token, and read tokens can only use what changes nothing.
+pub const PATH: &str = "/mcp";
+// Newest first; a client asking for another version is offered the newest
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+const SESSION_URI_PREFIX: &str = "aibot://sessions/";
+const MEMORIES_URI: &str = "aibot://memories";
+// Sessions listed as resources, most recently updated first
+const LISTED_SESSIONS: i64 = 50;
+// Knowledge base excerpts a search returns unless asked for fewer
+const MAX_SEARCH_RESULTS: usize = 20;
+// Tools that change something, which read tokens can't call
+const WRITE_TOOLS: [&str; 2] = ["create_session", "send_message"];
+
+// JSON-RPC error codes, and MCP's own for unknown resources
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    // Absent on notifications, which get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+fn rpc_response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": error.code, "message": error.message } }),
+    };
+    Json(body).into_response()
+}
+
+fn params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Clients may leave out params altogether when every field is optional
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+// Whether a call only reads, and so may be made with a read token
+fn is_read_only(request: &RpcRequest) -> bool {
+    match request.method.as_str() {
+        "tools/call" => request.params.get("name")
+            .and_then(Value::as_str)
+            .is_some_and(|name| !WRITE_TOOLS.contains(&name)),
+        _ => true,
+    }
+}
+
+pub async fn handle(
+    State(state): State<AppState>,
+    Extension(limiter): Extension<RateLimiter>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request: RpcRequest = match serde_json::from_slice::<Value>(&body) {
+        Err(e) => return rpc_response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+        Ok(message) => match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => return rpc_response(Value::Null, Err(RpcError::new(INVALID_REQUEST, e.to_string()))),
+        },
+    };
+    if request.jsonrpc != "2.0" {
+        return rpc_response(request.id.unwrap_or(Value::Null), Err(RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported")));
+    }
+
+    let Some(token) = crate::api_tokens::bearer_token(&headers) else {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "Missing API token").into_response();
+    };
+    let method = if is_read_only(&request) { Method::GET } else { Method::POST };
+    let user_id = match crate::api_tokens::authenticate(&state, token, &method, PATH).await {
+        Ok(user_id) => user_id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    // Notifications, such as `notifications/initialized`, need nothing from the server
+    let Some(id) = request.id else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let result = match request.method.as_str() {
+        "initialize" => Ok(initialize(&request.params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call_tool(&state, &limiter, &user_id, request.params).await,
+        "resources/list" => list_resources(&state, &user_id).await,
+        "resources/templates/list" => Ok(json!({ "resourceTemplates": [{
+            "uriTemplate": format!("{}{{session_id}}", SESSION_URI_PREFIX),
+            "name": "Chat session",
+            "description": "A chat session's transcript as Markdown",
+            "mimeType": "text/markdown",
+        }] })),
+        "resources/read" => read_resource(&state, &user_id, request.params).await,
+        method => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+    };
+    rpc_response(id, result)
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = PROTOCOL_VERSIONS.iter()
+        .find(|version| Some(**version) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {}, "resources": {} },
+        "serverInfo": { "name": "aibot", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Tools and resources act for the owner of the API token: their chat sessions, what the assistant remembers about them and their knowledge bases.",
+    })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "list_sessions",
+            "description": "List the user's chat sessions, pinned first, then most recently updated",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "limit": { "type": "integer", "minimum": 1, "maximum": 200, "description": "Sessions to return; 50 by default" },
+                    "include_archived": { "type": "boolean" },
+                },
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "read_session",
+            "description": "Read a chat session's whole transcript as Markdown",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "session_id": { "type": "string" } },
+                "required": ["session_id"],
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "create_session",
+            "description": "Start a new chat session with a model",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "model_provider": { "type": "string", "description": "ollama, openai, anthropic, gemini or openrouter" },
+                    "model_name": { "type": "string" },
+                    "title": { "type": "string" },
+                },
+                "required": ["model_provider", "model_name"],
+            },
+        },
+        {
+            "name": "send_message",
+            "description": "Send a message in a chat session and wait for the assistant's reply",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": { "type": "string" },
+                    "content": { "type": "string" },
+                },
+                "required": ["session_id", "content"],
+            },
+        },
+        {
+            "name": "search_memories",
+            "description": "Find what the assistant remembers about the user, by words in a memory's key or value. Without a query, every memory is returned.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "query": { "type": "string" } },
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "list_knowledge_bases",
+            "description": "List the user's knowledge bases",
+            "inputSchema": { "type": "object", "properties": {} },
+            "annotations": { "readOnlyHint": true },
+        },
+        {
+            "name": "search_knowledge",
+            "description": "Search the user's knowledge bases, or one of them, for the excerpts most relevant to a query",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "knowledge_base_id": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_RESULTS, "description": "Excerpts to return; 5 by default" },
+                },
+                "required": ["query"],
+            },
+            "annotations": { "readOnlyHint": true },
+        },
+    ])
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct ListSessionsArgs {
+    limit: Option<i64>,
+    #[serde(default)]
+    include_archived: bool,
+}
+
+#[derive(Deserialize)]
+struct SessionArgs {
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+struct CreateSessionArgs {
+    model_provider: String,
+    model_name: String,
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SendMessageArgs {
+    session_id: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct SearchMemoriesArgs {
+    query: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchKnowledgeArgs {
+    query: String,
+    knowledge_base_id: Option<String>,
+    limit: Option<usize>,
+}
+
+// Runs a tool. Arguments that don't fit the tool are a protocol error; anything that goes
+// wrong running it is reported in the result, so the model calling it can see why.
+async fn call_tool(state: &AppState, limiter: &RateLimiter, user_id: &str, call: Value) -> Result<Value, RpcError> {
+    let call: ToolCall = params(call)?;
+    let arguments = call.arguments;
+    let output = match call.name.as_str() {
+        "list_sessions" => list_sessions(state, user_id, params(arguments)?).await,
+        "read_session" => read_session(state, user_id, &params::<SessionArgs>(arguments)?.session_id).await,
+        "create_session" => create_session(state, user_id, params(arguments)?).await,
+        "send_message" => send_message(state, limiter, user_id, params(arguments)?).await,
+        "search_memories" => search_memories(state, user_id, params(arguments)?).await,
+        "list_knowledge_bases" => list_knowledge_bases(state, user_id).await,
+        "search_knowledge" => search_knowledge(state, user_id, params(arguments)?).await,
+        name => return Err(RpcError::new(INVALID_PARAMS, format!("Unknown tool {}", name))),
+    };
+    Ok(match output {
+        Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+        Err(e) => {
+            tracing::warn!("MCP tool {} failed: {}", call.name, e);
+            json!({ "content": [{ "type": "text", "text": e.to_string() }], "isError": true })
+        }
+    })
+}
+
+fn session_json(session: &ChatSession) -> Value {
+    json!({
+        "id": session.id,
+        "title": session.title,
+        "model_provider": session.model_provider,
+        "model_name": session.model_name,
+        "archived": session.archived,
+        "pinned": session.pinned,
+        "updated_at": session.updated_at,
+    })
+}
+
+async fn list_sessions(state: &AppState, user_id: &str, args: ListSessionsArgs) -> Result<String> {
+    let page = state.db.get_user_sessions(user_id, args.include_archived, None, None, page_size(args.limit)).await?;
+    let sessions: Vec<Value> = page.items.iter().map(session_json).collect();
+    Ok(serde_json::to_string_pretty(&sessions)?)
+}
+
+async fn read_session(state: &AppState, user_id: &str, session_id: &str) -> Result<String> {
+    crate::auth::authorize(state, user_id, OwnedResource::Session, session_id).await?;
+    let file = crate::export::export_session(state, session_id, ExportFormat::Markdown).await?;
+    Ok(String::from_utf8(file.content)?)
+}
+
+async fn create_session(state: &AppState, user_id: &str, args: CreateSessionArgs) -> Result<String> {
+    let model_provider = AIProvider::from(args.model_provider.clone());
+    if model_provider.to_string() != args.model_provider {
+        return Err(anyhow::anyhow!("Unknown model provider"));
+    }
+    let session = api::create_session_as(state, user_id, args.title, model_provider, args.model_name, false).await?;
+    Ok(serde_json::to_string_pretty(&session_json(&session))?)
+}
+
+// Counts toward the same message rate limit as chatting in the app
+async fn send_message(state: &AppState, limiter: &RateLimiter, user_id: &str, args: SendMessageArgs) -> Result<String> {
+    if args.content.trim().is_empty() {
+        return Err(anyhow::anyhow!("Message content is empty"));
+    }
+    if let Err(wait) = limiter.take_message(user_id) {
+        let retry_after_secs = (wait.as_secs_f64().ceil() as u64).max(1);
+        return Err(anyhow::anyhow!("Too many messages. Try again in {} seconds.", retry_after_secs));
+    }
+    let reply = api::send_message_as(state, user_id, args.session_id, args.content, Vec::new(), Vec::new(), Vec::new(), false).await?;
+    Ok(reply.content)
+}
+
+async fn search_memories(state: &AppState, user_id: &str, args: SearchMemoriesArgs) -> Result<String> {
+    let query = args.query.unwrap_or_default().to_lowercase();
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let memories: Vec<String> = state.db.get_user_memory(user_id).await?
+        .into_iter()
+        .filter(|memory| {
+            let text = format!("{} {}", memory.memory_key, memory.memory_value).to_lowercase();
+            words.iter().all(|word| text.contains(word))
+        })
+        .map(|memory| format!("{}: {}", memory.memory_key, memory.memory_value))
+        .collect();
+    if memories.is_empty() {
+        return Ok("No memories found".to_string());
+    }
+    Ok(memories.join("\n"))
+}
+
+async fn list_knowledge_bases(state: &AppState, user_id: &str) -> Result<String> {
+    let knowledge_bases: Vec<Value> = state.db.get_user_knowledge_bases(user_id).await?
+        .into_iter()
+        .map(|kb| json!({ "id": kb.id, "name": kb.name, "description": kb.description }))
+        .collect();
+    Ok(serde_json::to_string_pretty(&knowledge_bases)?)
+}
+
+// Searched like the knowledge bases linked to a session, and counted toward the user's
+// retrieval usage the same way
+async fn search_knowledge(state: &AppState, user_id: &str, args: SearchKnowledgeArgs) -> Result<String> {
+    let kb_ids = match args.knowledge_base_id {
+        Some(kb_id) => {
+            crate::auth::authorize(state, user_id, OwnedResource::KnowledgeBase, &kb_id).await?;
+            vec![kb_id]
+        }
+        None => state.db.get_user_knowledge_bases(user_id).await?.into_iter().map(|kb| kb.id).collect(),
+    };
+    if kb_ids.is_empty() {
+        return Ok("There are no knowledge bases to search".to_string());
+    }
+    let limit = args.limit.unwrap_or(crate::rag::TOP_K).clamp(1, MAX_SEARCH_RESULTS);
+    let chunks = crate::rag::retrieve(state, &kb_ids, &args.query, limit).await?;
+    state.db.record_retrieval(user_id).await?;
+    if chunks.is_empty() {
+        return Ok("Nothing relevant was found".to_string());
+    }
+    Ok(chunks.iter()
+        .map(|chunk| format!("[{}] {} ({})\n{}", chunk.citation.index, chunk.citation.title, chunk.citation.source_uri, chunk.content))
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+// The user's memories, and their most recently updated sessions
+async fn list_resources(state: &AppState, user_id: &str) -> Result<Value, RpcError> {
+    let page = state.db.get_user_sessions(user_id, false, None, None, LISTED_SESSIONS).await.map_err(internal)?;
+    let mut resources = vec![json!({
+        "uri": MEMORIES_URI,
+        "name": "Memories",
+        "description": "What the assistant remembers about the user",
+        "mimeType": "application/json",
+    })];
+    resources.extend(page.items.iter().map(|session| json!({
+        "uri": format!("{}{}", SESSION_URI_PREFIX, session.id),
+        "name": session.title.clone().unwrap_or_else(|| "Untitled chat".to_string()),
+        "mimeType": "text/markdown",
+    })));
+    Ok(json!({ "resources": resources }))
+}
+
+#[derive(Deserialize)]
+struct ReadResourceParams {
+    uri: String,
+}
+
+async fn read_resource(state: &AppState, user_id: &str, request: Value) -> Result<Value, RpcError> {
+    let uri = params::<ReadResourceParams>(request)?.uri;
+    let (mime_type, text) = if uri == MEMORIES_URI {
+        let memories = state.db.get_user_memory(user_id).await.map_err(internal)?;
+        let export = crate::memory::export_memories(&memories);
+        ("application/json", serde_json::to_string_pretty(&export).map_err(|e| internal(e.into()))?)
+    } else if let Some(session_id) = uri.strip_prefix(SESSION_URI_PREFIX) {
+        let transcript = read_session(state, user_id, session_id).await.map_err(|e| {
+            if e.to_string().ends_with("not found") {
+                RpcError::new(RESOURCE_NOT_FOUND, format!("Resource not found: {}", uri))
+            } else {
+                internal(e)
+            }
+        })?;
+        ("text/markdown", transcript)
+    } else {
+        return Err(RpcError::new(RESOURCE_NOT_FOUND, format!("Resource not found: {}", uri)));
+    };
+    Ok(json!({ "contents": [{ "uri": uri, "mimeType": mime_type, "text": text }] }))
+}
+
+fn internal(e: anyhow::Error) -> RpcError {
+    tracing::error!("MCP request failed: {}", e);
+    RpcError::new(INTERNAL_ERROR, "Internal error")
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crate::models::*;
+
+pub const EXPORT_VERSION: u32 = 1;
+
+// How much a re-confirmation in conversation adds back to a memory's confidence
+const REINFORCEMENT_BOOST: f64 = 0.2;
+// Values shorter than this match too much ordinary text to count as a confirmation
+const MIN_MATCH_LEN: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct MemoryPolicy {
+    // Days for an unconfirmed memory to lose half its confidence
+    pub half_life_days: f64,
+    // Memories that decay below this are left out of the system prompt
+    pub min_confidence: f64,
+}
+
+impl Default for MemoryPolicy {
+    fn default() -> Self {
+        Self {
+            half_life_days: 30.0,
+            min_confidence: 0.3,
+        }
+    }
+}
+
+impl MemoryPolicy {
+    // Stored confidence is the value as of `updated_at`; decay is applied on read
+    pub fn effective_confidence(&self, memory: &UserMemory, now: DateTime<Utc>) -> f64 {
+        let age_days = (now - memory.updated_at).num_seconds().max(0) as f64 / 86_400.0;
+        memory.confidence * 0.5_f64.powf(age_days / self.half_life_days)
+    }
+
+    // Memories still above the threshold, with decayed confidence, strongest first
+    pub fn active_memories(&self, memories: Vec<UserMemory>, now: DateTime<Utc>) -> Vec<UserMemory> {
+        let mut active: Vec<UserMemory> = memories
+            .into_iter()
+            .map(|mut memory| {
+                memory.confidence = self.effective_confidence(&memory, now);
+                memory
+            })
+            .filter(|memory| memory.confidence >= self.min_confidence)
+            .collect();
+        active.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        active
+    }
+
+    // Memories whose value the user restated in `message`, with their boosted confidence
+    pub fn reinforced(&self, memories: &[UserMemory], message: &str, now: DateTime<Utc>) -> Vec<(UserMemory, f64)> {
+        let message = message.to_lowercase();
+        memories
+            .iter()
+            .filter(|memory| {
+                let value = memory.memory_value.trim().to_lowercase();
+                value.chars().count() >= MIN_MATCH_LEN && message.contains(&value)
+            })
+            .map(|memory| {
+                let boosted = (self.effective_confidence(memory, now) + REINFORCEMENT_BOOST).min(1.0);
+                (memory.clone(), boosted)
+            })
+            .collect()
+    }
+}
+
+// What to do with a newly learned fact given the memory already stored under its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryResolution {
+    // Nothing stored under this key yet
+    Insert,
+    // Same value restated; top up the existing memory
+    Reinforce,
+    // A different value that replaces the current one, which moves to history
+    Supersede,
+    // A weak contradiction of a memory that is still trusted; ignored
+    Keep,
+}
+
+impl MemoryPolicy {
+    // The newest value wins a conflict unless it is below the confidence threshold
+    // while the current value is still above it
+    pub fn resolve(&self, existing: Option<&UserMemory>, incoming: &UserMemory, now: DateTime<Utc>) -> MemoryResolution {
+        let Some(existing) = existing else {
+            return MemoryResolution::Insert;
+        };
+
+        if same_value(&existing.memory_value, &incoming.memory_value) {
+            return MemoryResolution::Reinforce;
+        }
+
+        let current_trusted = self.effective_confidence(existing, now) >= self.min_confidence;
+        if incoming.confidence < self.min_confidence && current_trusted {
+            MemoryResolution::Keep
+        } else {
+            MemoryResolution::Supersede
+        }
+    }
+}
+
+pub fn same_value(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+pub fn export_memories(memories: &[UserMemory]) -> MemoryExport {
+    MemoryExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        memories: memories
+            .iter()
+            .map(|memory| MemoryExportEntry {
+                key: memory.memory_key.clone(),
+                value: memory.memory_value.clone(),
+                confidence: memory.confidence,
+                created_at: memory.created_at,
+                updated_at: memory.updated_at,
+            })
+            .collect(),
+    }
+}
+
+// Merges an export into the user's memories. An imported entry only replaces an
+// existing memory with the same key when it is newer. Returns the memories to save.
+pub fn merge_import(user_id: &str, existing: &[UserMemory], export: MemoryExport) -> Result<Vec<UserMemory>> {
+    if export.version > EXPORT_VERSION {
+        return Err(anyhow::anyhow!("Unsupported memory export version {}", export.version));
+    }
+
+    Ok(export
+        .memories
+        .into_iter()
+        .filter(|entry| !entry.key.trim().is_empty())
+        .filter_map(|entry| {
+            let current = existing.iter().find(|memory| memory.memory_key == entry.key);
+            if current.map(|memory| memory.updated_at >= entry.updated_at).unwrap_or(false) {
+                return None;
+            }
+
+            Some(UserMemory {
+                id: current
+                    .map(|memory| memory.id.clone())
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                user_id: user_id.to_string(),
+                memory_key: entry.key,
+                memory_value: entry.value,
+                confidence: entry.confidence.clamp(0.0, 1.0),
+                version: current.map(|memory| memory.version).unwrap_or(1),
+                created_at: entry.created_at,
+                updated_at: entry.updated_at,
+            })
+        })
+        .collect())
+}
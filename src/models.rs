@@ -7,10 +7,316 @@ pub struct User {
     pub id: String,
     pub name: Option<String>,
     pub email: Option<String>,
+    // None for users saved without one; they belong to the default organization
+    pub org_id: Option<String>,
+    // What the user may do beyond chatting; see `Role::can`
+    pub role: Role,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// Members are the default role. Guests can chat but can't bring keys, knowledge or data of
+// their own, and admins run the instance.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    Admin,
+    Member,
+    Guest,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Member, Role::Guest];
+
+    // The one place roles are mapped to what they allow
+    pub fn can(self, capability: Capability) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Member => matches!(
+                capability,
+                Capability::ManageKeys | Capability::ManageKnowledge | Capability::ImportData | Capability::UseApiTokens
+            ),
+            Role::Guest => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Admin => write!(f, "admin"),
+            Role::Member => write!(f, "member"),
+            Role::Guest => write!(f, "guest"),
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "admin" => Role::Admin,
+            "member" => Role::Member,
+            // Anything unrecognized gets the least access
+            _ => Role::Guest,
+        }
+    }
+}
+
+// Something a role may allow besides chatting in one's own sessions, which everyone can do
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Capability {
+    // Save and remove one's own provider API keys
+    ManageKeys,
+    // Create knowledge bases and add documents, websites, repositories and connectors to them
+    ManageKnowledge,
+    // Import conversations and memory from exports
+    ImportData,
+    // See every user's usage, provider health and the instance's configuration
+    ViewUsage,
+    // Change users' roles and sign them out
+    ManageUsers,
+    // Move anyone's sessions to the trash, not just one's own
+    DeleteAnySession,
+    // Create personal access tokens for scripts and other clients
+    UseApiTokens,
+    // Take and restore backups, reload the configuration, and manage organizations and webhooks
+    ManageInstance,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::ManageKeys => write!(f, "manage_keys"),
+            Capability::ManageKnowledge => write!(f, "manage_knowledge"),
+            Capability::ImportData => write!(f, "import_data"),
+            Capability::ViewUsage => write!(f, "view_usage"),
+            Capability::ManageUsers => write!(f, "manage_users"),
+            Capability::DeleteAnySession => write!(f, "delete_any_session"),
+            Capability::UseApiTokens => write!(f, "use_api_tokens"),
+            Capability::ManageInstance => write!(f, "manage_instance"),
+        }
+    }
+}
+
+// Owner of everything created before accounts existed. The first account registered
+// takes it over.
+pub const DEFAULT_USER_ID: &str = "default_user";
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+// What a correct email and password lead to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LoginResult {
+    SignedIn(User),
+    // The account has two-factor authentication; sign in again with a code
+    CodeRequired,
+}
+
+// What a personal access token may do. Read tokens can only make GET requests, like the
+// exports and attachment downloads; write tokens can also call server functions.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TokenScope {
+    Read,
+    Write,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 2] = [TokenScope::Read, TokenScope::Write];
+}
+
+impl std::fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenScope::Read => write!(f, "read"),
+            TokenScope::Write => write!(f, "write"),
+        }
+    }
+}
+
+impl From<String> for TokenScope {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "write" => TokenScope::Write,
+            // Anything unrecognized gets the least access
+            _ => TokenScope::Read,
+        }
+    }
+}
+
+// A personal access token, without the token itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    // The start of the token, to tell tokens apart
+    pub token_prefix: String,
+    pub scope: TokenScope,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// A token just created; `secret` is only ever shown this once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewApiToken {
+    pub token: ApiToken,
+    pub secret: String,
+}
+
+// What an outbound webhook can be sent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    // A message was sent in a chat, or a reply to one saved
+    MessageCreated,
+    SessionCreated,
+    // A user was refused for going over a usage limit
+    BudgetExceeded,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 3] = [WebhookEvent::MessageCreated, WebhookEvent::SessionCreated, WebhookEvent::BudgetExceeded];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.to_string() == s.trim())
+    }
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::MessageCreated => write!(f, "message.created"),
+            WebhookEvent::SessionCreated => write!(f, "session.created"),
+            WebhookEvent::BudgetExceeded => write!(f, "budget.exceeded"),
+        }
+    }
+}
+
+// A registered webhook, without its signing secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: DateTime<Utc>,
+}
+
+// A webhook as just registered; the signing secret is only returned this once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewWebhook {
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    // Waiting for its first attempt or a retry
+    Pending,
+    Delivered,
+    // Out of attempts
+    Failed,
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryStatus::Pending => write!(f, "pending"),
+            DeliveryStatus::Delivered => write!(f, "delivered"),
+            DeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl From<String> for DeliveryStatus {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "delivered" => DeliveryStatus::Delivered,
+            "failed" => DeliveryStatus::Failed,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+// One event sent, or being sent, to a webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    // HTTP status of the last attempt, if the endpoint answered
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+// A signed-in browser, as listed on the security page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginSession {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    // The session making the request
+    pub current: bool,
+}
+
+impl LoginSession {
+    // Browser and operating system, as far as the user agent tells them, like "Firefox on Linux"
+    pub fn device(&self) -> String {
+        let Some(user_agent) = self.user_agent.as_deref() else {
+            return "Unknown device".to_string();
+        };
+        // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+        let browser = [("Edg/", "Edge"), ("OPR/", "Opera"), ("Firefox/", "Firefox"), ("Chrome/", "Chrome"), ("Safari/", "Safari")]
+            .into_iter()
+            .find(|(token, _)| user_agent.contains(token))
+            .map(|(_, name)| name);
+        let os = [("Android", "Android"), ("iPhone", "iOS"), ("iPad", "iPadOS"), ("Windows", "Windows"), ("Mac OS X", "macOS"), ("Linux", "Linux")]
+            .into_iter()
+            .find(|(token, _)| user_agent.contains(token))
+            .map(|(_, name)| name);
+        match (browser, os) {
+            (Some(browser), Some(os)) => format!("{} on {}", browser, os),
+            (Some(name), None) | (None, Some(name)) => name.to_string(),
+            (None, None) => user_agent.chars().take(60).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub backup_codes_left: i64,
+    // The user is an admin on an instance that requires two-factor for admins
+    pub required: bool,
+}
+
+// The account.json entry of a "download my data" archive; the rest of the archive holds
+// one export per session, the user's memory and their attachment files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    pub knowledge_bases: Vec<KnowledgeBase>,
+}
+
+// What an authenticator app needs to start generating codes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorSetup {
+    // Base32, for typing into the app when the QR code can't be scanned
+    pub secret: String,
+    pub otpauth_url: String,
+    // PNG of the otpauth URL, base64-encoded
+    pub qr_code_png: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatSession {
     pub id: String,
@@ -18,10 +324,41 @@ pub struct ChatSession {
     pub title: Option<String>,
     pub model_provider: String,
     pub model_name: String,
+    // Incognito sessions neither read nor write user memory
+    pub incognito: bool,
+    // Hidden from the session list unless archived sessions are requested
+    pub archived: bool,
+    pub folder_id: Option<String>,
+    pub pinned: bool,
+    // Set on branches: the session they were forked from and the message they continue after
+    pub parent_session_id: Option<String>,
+    pub branch_message_id: Option<String>,
+    pub last_message_preview: Option<String>,
+    // Owning organization; filled from the user's organization when the session is saved
+    pub org_id: Option<String>,
+    pub settings: SessionSettings,
+    // Set while the session is in the trash
+    pub deleted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFolder {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub id: String,
@@ -32,6 +369,11 @@ pub struct Message {
     pub model_provider: Option<String>,
     pub model_name: Option<String>,
     pub tokens_used: Option<i32>,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    // Set while the message is in the trash
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -64,36 +406,457 @@ impl From<String> for MessageRole {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UserMemory {
+pub struct UserMemory {
+    pub id: String,
+    pub user_id: String,
+    pub memory_key: String,
+    pub memory_value: String,
+    pub confidence: f64,
+    // Bumped whenever the value changes; edits say which version they started from
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Retrieval-augmented generation usage for one user, with their configured cap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagUsage {
+    pub embedded_tokens: i64,
+    pub stored_documents: i64,
+    pub stored_vectors: i64,
+    pub retrievals: i64,
+    pub max_vectors: Option<i64>,
+}
+
+// A value a memory held before a conflicting fact replaced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryHistoryEntry {
+    pub id: String,
+    pub memory_key: String,
+    pub memory_value: String,
+    pub confidence: f64,
+    pub recorded_at: DateTime<Utc>,
+    pub superseded_at: DateTime<Utc>,
+}
+
+// Portable memory backup; ids and user ids are left out so it can be imported anywhere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub memories: Vec<MemoryExportEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryExportEntry {
+    pub key: String,
+    pub value: String,
+    pub confidence: f64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+    Pdf,
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Json => write!(f, "json"),
+            ExportFormat::Markdown => write!(f, "markdown"),
+            ExportFormat::Pdf => write!(f, "pdf"),
+        }
+    }
+}
+
+impl From<String> for ExportFormat {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "markdown" | "md" => ExportFormat::Markdown,
+            "pdf" => ExportFormat::Pdf,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+// A whole conversation in a self-contained, versioned document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub session_id: String,
+    pub title: Option<String>,
+    pub model_provider: String,
+    pub model_name: String,
+    pub created_at: DateTime<Utc>,
+    pub messages: Vec<SessionExportMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExportMessage {
+    pub role: MessageRole,
+    pub content: String,
+    pub reasoning: Option<String>,
+    pub model_provider: Option<String>,
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    // Referenced by name and download URL; file contents aren't embedded
+    #[serde(default)]
+    pub attachments: Vec<SessionExportAttachment>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExportAttachment {
+    pub file_name: String,
+    pub file_type: String,
+    pub file_size: i64,
+    pub url: String,
+}
+
+// Outcome of importing conversations from another chat service
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub sessions_imported: usize,
+    pub messages_imported: usize,
+    // One human-readable line per conversation that was skipped or only partly imported
+    pub skipped: Vec<String>,
+}
+
+// A rendered export, ready to be offered as a download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileAttachment {
+    pub id: String,
+    pub message_id: String,
+    pub file_name: String,
+    pub file_path: String,
+    pub file_type: String,
+    pub file_size: i64,
+    pub content_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    // What was said in a voice note, when it was transcribed
+    #[serde(default)]
+    pub transcript: Option<String>,
+    // Signed, expiring link to the file; filled in by `attachment_urls::sign_all` before
+    // attachments are sent to the browser
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestedQuestion {
+    pub id: String,
+    pub session_id: String,
+    pub question: String,
+    pub relevance_score: f64,
+    pub used: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBase {
+    pub id: String,
+    pub user_id: String,
+    // Owning organization; filled from the user's organization when the knowledge base is saved
+    pub org_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeDocument {
+    pub id: String,
+    pub knowledge_base_id: String,
+    pub source_type: String,
+    pub source_uri: String,
+    pub title: String,
+    pub content_hash: Option<String>,
+    pub chunk_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeChunk {
+    pub id: String,
+    pub document_id: String,
+    pub knowledge_base_id: String,
+    pub chunk_index: i64,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestionJob {
+    pub id: String,
+    pub knowledge_base_id: String,
+    pub source_type: String,
+    pub source_uri: String,
+    pub status: JobStatus,
+    pub documents_processed: i64,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Pending => write!(f, "pending"),
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Completed => write!(f, "completed"),
+            JobStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl From<String> for JobStatus {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "pending" => JobStatus::Pending,
+            "running" => JobStatus::Running,
+            "completed" => JobStatus::Completed,
+            "failed" => JobStatus::Failed,
+            _ => JobStatus::Pending,
+        }
+    }
+}
+
+// Prompts run in the background against one model; see `batch`. The counts are of the
+// job's prompts so far.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: String,
+    pub user_id: String,
+    pub model_provider: String,
+    pub model_name: String,
+    pub concurrency: i32,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub total: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+// One prompt of a batch and, once it has run, its result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub job_id: String,
+    pub line: i32,
+    pub custom_id: String,
+    pub prompt: String,
+    pub system_prompt: Option<String>,
+    // Pending until the model has answered, then completed or failed
+    pub status: JobStatus,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub tokens_used: Option<i32>,
+}
+
+// A prompt sent to a session every `interval_minutes`, starting at `next_run_at`; see
+// `schedules`. The replies are added to the session like any other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub user_id: String,
+    pub session_id: String,
+    pub prompt: String,
+    // A page fetched each run and attached to the prompt, for prompts about what's on it now
+    pub fetch_url: Option<String>,
+    pub interval_minutes: i64,
+    pub next_run_at: DateTime<Utc>,
+    // Email each reply to the user
+    pub notify: bool,
+    pub enabled: bool,
+    pub last_run_at: Option<DateTime<Utc>>,
+    // Why the last run failed, if it did
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// What sets a rule off; see `rules`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTrigger {
+    // A message was saved in one of the user's sessions, theirs or a reply
+    MessageCreated,
+    SessionCreated,
+}
+
+impl RuleTrigger {
+    pub const ALL: [RuleTrigger; 2] = [RuleTrigger::MessageCreated, RuleTrigger::SessionCreated];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|trigger| trigger.to_string() == s.trim())
+    }
+}
+
+impl std::fmt::Display for RuleTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleTrigger::MessageCreated => write!(f, "message_created"),
+            RuleTrigger::SessionCreated => write!(f, "session_created"),
+        }
+    }
+}
+
+// Narrows down which events fire a rule; every part that is set must match. The text
+// matched is the message's content, or the session's title for new sessions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleCondition {
+    // "user", "assistant" or "system"; only for messages
+    #[serde(default)]
+    pub role: Option<String>,
+    // Text that must appear, ignoring case
+    #[serde(default)]
+    pub contains: Option<String>,
+    // A regular expression that must match
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+// What a rule does when it fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    // POSTs the event as JSON
+    Webhook { url: String },
+    // Emails the event to the user
+    Email,
+    // Tags the session, creating the tag if the user doesn't have it yet
+    TagSession { tag: String },
+    // Sends a prompt in the session the event happened in
+    RunPrompt { prompt: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
     pub id: String,
     pub user_id: String,
-    pub memory_key: String,
-    pub memory_value: String,
-    pub confidence: f64,
+    pub name: String,
+    pub trigger: RuleTrigger,
+    pub condition: RuleCondition,
+    pub action: RuleAction,
+    pub enabled: bool,
+    pub last_fired_at: Option<DateTime<Utc>>,
+    // Why the action failed the last time it ran, if it did
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// A website crawled into a knowledge base; re-crawled every `sync_interval_minutes` if set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlSource {
+    pub id: String,
+    pub knowledge_base_id: String,
+    pub start_url: String,
+    pub max_depth: i64,
+    pub max_pages: i64,
+    pub same_domain_only: bool,
+    pub sync_interval_minutes: Option<i64>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_sync_status: Option<String>,
+    pub last_sync_error: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// An RSS or Atom feed polled into a knowledge base every `sync_interval_minutes`; see `feeds`
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FileAttachment {
+pub struct FeedSource {
     pub id: String,
-    pub message_id: String,
-    pub file_name: String,
-    pub file_path: String,
-    pub file_type: String,
-    pub file_size: i64,
-    pub content_hash: Option<String>,
+    pub knowledge_base_id: String,
+    pub url: String,
+    // The feed's own title, read when it is added
+    pub title: Option<String>,
+    pub sync_interval_minutes: i64,
+    // The model that writes a daily digest of new items in a session of its own; no digest
+    // is written when unset
+    pub digest_model_provider: Option<String>,
+    pub digest_model_name: Option<String>,
+    pub last_digest_at: Option<DateTime<Utc>>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_sync_status: Option<String>,
+    pub last_sync_error: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
+// An item indexed from a feed, kept for the digest
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SuggestedQuestion {
+pub struct FeedItem {
+    pub feed_source_id: String,
+    pub guid: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub summary: String,
+    pub published_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Credentials are deliberately not part of this struct so it can be sent to the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Connector {
     pub id: String,
-    pub session_id: String,
-    pub question: String,
-    pub relevance_score: f64,
-    pub used: bool,
+    pub user_id: String,
+    pub knowledge_base_id: String,
+    pub provider: ConnectorProvider,
+    pub resource_ids: Vec<String>,
+    pub sync_interval_minutes: i64,
+    pub connected: bool,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_sync_status: Option<String>,
+    pub last_sync_error: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectorProvider {
+    Notion,
+    GoogleDrive,
+}
+
+impl std::fmt::Display for ConnectorProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectorProvider::Notion => write!(f, "notion"),
+            ConnectorProvider::GoogleDrive => write!(f, "google_drive"),
+        }
+    }
+}
+
+impl From<String> for ConnectorProvider {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "notion" => ConnectorProvider::Notion,
+            "google_drive" => ConnectorProvider::GoogleDrive,
+            _ => ConnectorProvider::Notion,
+        }
+    }
 }
 
 // AI Provider Models
@@ -151,6 +914,477 @@ pub struct ChatResponse {
     pub model_provider: String,
     pub model_name: String,
     pub tokens_used: Option<i32>,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    // Set when the content filter flagged the message or reply but let it through
+    #[serde(default)]
+    pub moderation_warning: Option<String>,
+}
+
+// Something that happened in a session, streamed to its subscribers as JSON tagged by `type`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    // A message was saved, the user's or the reply
+    Message { message: Message },
+    Status { status: SessionStatus },
+    Renamed { title: Option<String> },
+}
+
+// Sent to each of a user's connected tabs and devices so they stay in step, as JSON tagged
+// by `type`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncEvent {
+    // A message was saved in one of the user's sessions
+    Message { message: Message },
+    // More of a reply still being written in one of the user's sessions; the saved
+    // `Message` follows once it is done
+    Delta { session_id: String, text: String },
+    // The reply being written failed or was withheld, and no `Message` will follow
+    ReplyAbandoned { session_id: String },
+    // A session or folder was created, renamed, moved, pinned, archived or deleted
+    SessionsChanged,
+    // Events were missed; reload everything shown
+    Lagged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    // A message was sent and the reply is being written
+    Responding,
+    Idle,
+}
+
+// A knowledge base chunk that was injected into the prompt; `index` matches the
+// [n] marker the model is asked to cite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub index: usize,
+    pub document_id: String,
+    pub chunk_id: String,
+    pub title: String,
+    pub source_uri: String,
+    pub snippet: String,
+    pub score: f64,
+}
+
+// A past message matching a conversation search, with enough context to link to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSearchResult {
+    pub session_id: String,
+    pub session_title: String,
+    pub message_id: String,
+    pub role: MessageRole,
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+    pub score: f64,
+}
+
+// Sessions and messages currently in the trash. Messages are only listed here when
+// their session is not itself in the trash.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trash {
+    pub sessions: Vec<ChatSession>,
+    pub messages: Vec<Message>,
+}
+
+// Users and data saved without an organization belong to this one
+pub const DEFAULT_ORG_ID: &str = "default_org";
+
+// A tenant. Users only ever see sessions and knowledge bases of their own organization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub settings: OrganizationSettings,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrganizationSettings {
+    // Providers the organization's chats may use, by name; None allows every provider
+    #[serde(default)]
+    pub allowed_providers: Option<Vec<String>>,
+}
+
+impl OrganizationSettings {
+    pub fn allows_provider(&self, provider: &str) -> bool {
+        self.allowed_providers
+            .as_ref()
+            .map(|allowed| allowed.iter().any(|name| name == provider))
+            .unwrap_or(true)
+    }
+}
+
+impl Organization {
+    pub fn new(name: String, settings: OrganizationSettings) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            settings,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+// A provider key stored in the database for a user or an organization. Only its
+// fingerprint is ever shown; the key itself never leaves the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredApiKey {
+    pub provider: String,
+    pub fingerprint: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+// A user as listed in the admin panel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminUser {
+    pub id: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub org_id: String,
+    pub role: Role,
+    // False for users from before accounts existed that nobody has claimed
+    pub has_password: bool,
+    pub session_count: i64,
+    // When one of their sessions was last updated
+    pub last_active_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Whether a provider answers when called with the instance's own credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderHealth {
+    pub provider: String,
+    // False when the instance has no key for the provider; it isn't called then
+    pub configured: bool,
+    pub healthy: bool,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+// The instance's settings as shown to admins. Secrets are only reported as set or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceConfig {
+    pub database_backend: String,
+    pub reranking_enabled: bool,
+    // What the content filter does with flagged text; None when there is no filter
+    pub moderation_action: Option<String>,
+    pub memory_half_life_days: f64,
+    pub memory_min_confidence: f64,
+    pub max_kb_chunks_per_user: Option<i64>,
+    pub trash_retention_days: i64,
+    pub account_deletion_grace_days: i64,
+    pub retention_policy: RetentionPolicy,
+    pub retention_dry_run: bool,
+    pub admin_token_set: bool,
+    pub secrets_key_set: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminOverview {
+    pub users: Vec<AdminUser>,
+    pub providers: Vec<ProviderHealth>,
+    pub config: InstanceConfig,
+}
+
+// Body of a 429 from the rate limiter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimited {
+    pub error: String,
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+impl RateLimited {
+    // Server function errors carry the response body in their text, so the UI can find
+    // the limit in a failed call and count down to the retry
+    pub fn from_error(error: &str) -> Option<Self> {
+        let start = error.find('{')?;
+        let end = error.rfind('}')?;
+        serde_json::from_str(error.get(start..=end)?).ok()
+    }
+}
+
+// How long chat history is kept. None means no limit. The instance-wide policy is a
+// ceiling: a user's own policy can only make it stricter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    // Messages older than this many days are moved to the trash
+    pub message_retention_days: Option<i64>,
+    // Only the most recently active sessions are kept; pinned sessions are never removed
+    pub max_sessions: Option<i64>,
+}
+
+impl RetentionPolicy {
+    // The stricter of the two policies, field by field
+    pub fn stricter(self, other: RetentionPolicy) -> RetentionPolicy {
+        fn min(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        RetentionPolicy {
+            message_retention_days: min(self.message_retention_days, other.message_retention_days),
+            max_sessions: min(self.max_sessions, other.max_sessions),
+        }
+    }
+}
+
+// What a retention run moved, or in a dry run would move, to the trash for one user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub user_id: String,
+    pub dry_run: bool,
+    // The policy that was applied, after combining the user's and the instance's
+    pub policy: RetentionPolicy,
+    pub expired_messages: i64,
+    // Sessions over the session limit, least recently active last
+    pub excess_sessions: Vec<ChatSession>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    SessionDeleted,
+    SessionRestored,
+    SessionPurged,
+    MessageDeleted,
+    MessageRestored,
+    MessagePurged,
+    SessionExported,
+    ConversationsImported,
+    MemorySaved,
+    MemoryExported,
+    MemoryImported,
+    ConnectorCreated,
+    ConnectorAuthorized,
+    ConnectorDeleted,
+    RetentionPolicyChanged,
+    BackupCreated,
+    BackupRestored,
+    OrganizationCreated,
+    OrganizationSettingsChanged,
+    OrganizationKeyChanged,
+    UserOrganizationChanged,
+    UserApiKeyChanged,
+    UserRoleChanged,
+    UserSignedOut,
+    TwoFactorChanged,
+    PasswordReset,
+    AccountExported,
+    AccountDeletionRequested,
+    AccountDeletionCancelled,
+    AccountDeleted,
+    LoginSessionRevoked,
+    LoginFailed,
+    LoginLockedOut,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    WebhookCreated,
+    WebhookDeleted,
+    ConfigReloaded,
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditAction::SessionDeleted => write!(f, "session_deleted"),
+            AuditAction::SessionRestored => write!(f, "session_restored"),
+            AuditAction::SessionPurged => write!(f, "session_purged"),
+            AuditAction::MessageDeleted => write!(f, "message_deleted"),
+            AuditAction::MessageRestored => write!(f, "message_restored"),
+            AuditAction::MessagePurged => write!(f, "message_purged"),
+            AuditAction::SessionExported => write!(f, "session_exported"),
+            AuditAction::ConversationsImported => write!(f, "conversations_imported"),
+            AuditAction::MemorySaved => write!(f, "memory_saved"),
+            AuditAction::MemoryExported => write!(f, "memory_exported"),
+            AuditAction::MemoryImported => write!(f, "memory_imported"),
+            AuditAction::ConnectorCreated => write!(f, "connector_created"),
+            AuditAction::ConnectorAuthorized => write!(f, "connector_authorized"),
+            AuditAction::ConnectorDeleted => write!(f, "connector_deleted"),
+            AuditAction::RetentionPolicyChanged => write!(f, "retention_policy_changed"),
+            AuditAction::BackupCreated => write!(f, "backup_created"),
+            AuditAction::BackupRestored => write!(f, "backup_restored"),
+            AuditAction::OrganizationCreated => write!(f, "organization_created"),
+            AuditAction::OrganizationSettingsChanged => write!(f, "organization_settings_changed"),
+            AuditAction::OrganizationKeyChanged => write!(f, "organization_key_changed"),
+            AuditAction::UserOrganizationChanged => write!(f, "user_organization_changed"),
+            AuditAction::UserApiKeyChanged => write!(f, "user_api_key_changed"),
+            AuditAction::UserRoleChanged => write!(f, "user_role_changed"),
+            AuditAction::UserSignedOut => write!(f, "user_signed_out"),
+            AuditAction::TwoFactorChanged => write!(f, "two_factor_changed"),
+            AuditAction::PasswordReset => write!(f, "password_reset"),
+            AuditAction::AccountExported => write!(f, "account_exported"),
+            AuditAction::AccountDeletionRequested => write!(f, "account_deletion_requested"),
+            AuditAction::AccountDeletionCancelled => write!(f, "account_deletion_cancelled"),
+            AuditAction::AccountDeleted => write!(f, "account_deleted"),
+            AuditAction::LoginSessionRevoked => write!(f, "login_session_revoked"),
+            AuditAction::LoginFailed => write!(f, "login_failed"),
+            AuditAction::LoginLockedOut => write!(f, "login_locked_out"),
+            AuditAction::ApiTokenCreated => write!(f, "api_token_created"),
+            AuditAction::ApiTokenRevoked => write!(f, "api_token_revoked"),
+            AuditAction::WebhookCreated => write!(f, "webhook_created"),
+            AuditAction::WebhookDeleted => write!(f, "webhook_deleted"),
+            AuditAction::ConfigReloaded => write!(f, "config_reloaded"),
+        }
+    }
+}
+
+// Text the content filter flagged, without the text itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationEvent {
+    pub id: String,
+    pub user_id: String,
+    pub session_id: String,
+    // "input" or "output"
+    pub direction: String,
+    // "block", "warn" or "log"
+    pub action: String,
+    pub categories: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// One row of the append-only audit log. `action` is kept as stored so entries
+// written by newer versions still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub user_id: String,
+    pub action: String,
+    pub target_id: Option<String>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Optional constraints on an audit log query
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLogFilter {
+    pub user_id: Option<String>,
+    pub action: Option<String>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+// One day of a user's usage of one model, from the usage_daily rollup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub day: chrono::NaiveDate,
+    pub user_id: String,
+    pub model_provider: String,
+    pub model_name: String,
+    pub user_messages: i64,
+    pub assistant_messages: i64,
+    pub tokens: i64,
+    // USD estimate; None when the model has no known price
+    pub estimated_cost: Option<f64>,
+}
+
+// Keyset position in a message listing: the (created_at, id) of the oldest message returned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: String,
+}
+
+// Keyset position in a session listing, which is ordered pinned first, then by recent activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionCursor {
+    pub pinned: bool,
+    pub updated_at: DateTime<Utc>,
+    pub id: String,
+}
+
+// One page of a listing; `next_cursor` is None on the last page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T, C> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<C>,
+}
+
+// Keyword search results wrap matched terms in these markers so the client can
+// highlight them without rendering stored text as HTML
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+// Highest temperature any supported provider accepts
+pub const MAX_TEMPERATURE: f32 = 2.0;
+
+// How a session's earlier messages are fitted into the prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    // The whole conversation
+    #[default]
+    Full,
+    // Only the most recent messages
+    RecentMessages { count: usize },
+    // As many recent messages as fit in roughly this many tokens
+    TokenBudget { tokens: usize },
+}
+
+impl ContextStrategy {
+    // Drops the oldest messages the strategy leaves out
+    pub fn select(&self, mut messages: Vec<Message>) -> Vec<Message> {
+        let keep = match *self {
+            ContextStrategy::Full => messages.len(),
+            ContextStrategy::RecentMessages { count } => count.min(messages.len()),
+            ContextStrategy::TokenBudget { tokens } => {
+                // Estimated at ~4 characters per token, like the usage counters
+                let mut used = 0;
+                messages
+                    .iter()
+                    .rev()
+                    .take_while(|message| {
+                        used += message.content.chars().count().div_ceil(4);
+                        used <= tokens
+                    })
+                    .count()
+            }
+        };
+        messages.split_off(messages.len() - keep)
+    }
+}
+
+// Generation settings kept per session. Missing fields take their defaults, so settings
+// saved by older versions still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    // None uses the provider's default
+    pub temperature: Option<f32>,
+    // Replaces the default assistant instructions; memory and knowledge are still added
+    pub system_prompt: Option<String>,
+    // Read the user's memories into the prompt and reinforce them
+    pub memory_enabled: bool,
+    // Retrieve excerpts from the session's linked knowledge bases
+    pub knowledge_enabled: bool,
+    pub context_strategy: ContextStrategy,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            temperature: None,
+            system_prompt: None,
+            memory_enabled: true,
+            knowledge_enabled: true,
+            context_strategy: ContextStrategy::Full,
+        }
+    }
+}
+
+// Optional constraints on a keyword message search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageSearchFilters {
+    pub session_id: Option<String>,
+    pub role: Option<MessageRole>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -158,6 +1392,9 @@ pub struct FileUpload {
     pub name: String,
     pub content_type: String,
     pub data: Vec<u8>,
+    // Filled in server-side for voice notes when auto-transcription is enabled
+    #[serde(default)]
+    pub transcript: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -169,11 +1406,18 @@ pub struct CreateSessionRequest {
 
 // Utility functions
 impl User {
+    // A browser-local identity from guest mode, as opposed to an account given the guest role
+    pub fn is_anonymous_guest(&self) -> bool {
+        self.role == Role::Guest && self.email.is_none()
+    }
+
     pub fn new(name: Option<String>, email: Option<String>) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             name,
             email,
+            org_id: None,
+            role: Role::Member,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -188,12 +1432,56 @@ impl ChatSession {
             title: None,
             model_provider: model_provider.to_string(),
             model_name,
+            incognito: false,
+            archived: false,
+            folder_id: None,
+            pinned: false,
+            parent_session_id: None,
+            branch_message_id: None,
+            last_message_preview: None,
+            org_id: None,
+            settings: SessionSettings::default(),
+            deleted_at: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
     }
 }
 
+// Length of the message preview stored on a session for the session list
+const MESSAGE_PREVIEW_CHARS: usize = 100;
+
+pub fn message_preview(content: &str) -> String {
+    let flattened = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flattened.chars().count() > MESSAGE_PREVIEW_CHARS {
+        format!("{}…", flattened.chars().take(MESSAGE_PREVIEW_CHARS).collect::<String>())
+    } else {
+        flattened
+    }
+}
+
+impl Tag {
+    pub fn new(user_id: String, name: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            name,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+impl SessionFolder {
+    pub fn new(user_id: String, name: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            name,
+            created_at: Utc::now(),
+        }
+    }
+}
+
 impl Message {
     pub fn new(session_id: String, role: MessageRole, content: String) -> Self {
         Self {
@@ -205,7 +1493,144 @@ impl Message {
             model_provider: None,
             model_name: None,
             tokens_used: None,
+            citations: Vec::new(),
+            deleted_at: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+impl KnowledgeBase {
+    pub fn new(user_id: String, name: String, description: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            org_id: None,
+            name,
+            description,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl IngestionJob {
+    pub fn new(knowledge_base_id: String, source_type: &str, source_uri: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            knowledge_base_id,
+            source_type: source_type.to_string(),
+            source_uri,
+            status: JobStatus::Pending,
+            documents_processed: 0,
+            error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new(
+        user_id: String,
+        session_id: String,
+        prompt: String,
+        fetch_url: Option<String>,
+        interval_minutes: i64,
+        next_run_at: DateTime<Utc>,
+        notify: bool,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            session_id,
+            prompt,
+            fetch_url,
+            interval_minutes,
+            next_run_at,
+            notify,
+            enabled: true,
+            last_run_at: None,
+            last_error: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+impl FeedSource {
+    pub fn new(
+        knowledge_base_id: String,
+        url: String,
+        title: Option<String>,
+        sync_interval_minutes: i64,
+        digest_model_provider: Option<String>,
+        digest_model_name: Option<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            knowledge_base_id,
+            url,
+            title,
+            sync_interval_minutes,
+            digest_model_provider,
+            digest_model_name,
+            last_digest_at: None,
+            last_synced_at: None,
+            last_sync_status: None,
+            last_sync_error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl CrawlSource {
+    pub fn new(
+        knowledge_base_id: String,
+        start_url: String,
+        max_depth: i64,
+        max_pages: i64,
+        same_domain_only: bool,
+        sync_interval_minutes: Option<i64>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            knowledge_base_id,
+            start_url,
+            max_depth,
+            max_pages,
+            same_domain_only,
+            sync_interval_minutes,
+            last_synced_at: None,
+            last_sync_status: None,
+            last_sync_error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl Connector {
+    pub fn new(
+        user_id: String,
+        knowledge_base_id: String,
+        provider: ConnectorProvider,
+        resource_ids: Vec<String>,
+        sync_interval_minutes: i64,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            knowledge_base_id,
+            provider,
+            resource_ids,
+            sync_interval_minutes,
+            connected: false,
+            last_synced_at: None,
+            last_sync_status: None,
+            last_sync_error: None,
             created_at: Utc::now(),
+            updated_at: Utc::now(),
         }
     }
 }
@@ -218,6 +1643,7 @@ impl UserMemory {
             memory_key,
             memory_value,
             confidence: 1.0,
+            version: 1,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::{api::AppState, models::*};
+
+// Labels a local classifier may use for text that is fine
+const SAFE_LABELS: [&str; 4] = ["safe", "neutral", "ok", "non-toxic"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModerationProvider {
+    OpenAI,
+    // Any text classifier served with the text-embeddings-inference `/predict` API
+    Local,
+}
+
+impl From<String> for ModerationProvider {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "openai" => ModerationProvider::OpenAI,
+            "local" => ModerationProvider::Local,
+            _ => ModerationProvider::Local,
+        }
+    }
+}
+
+// What happens to flagged text. Every flag is recorded as a moderation event either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModerationAction {
+    // Refuse the message, or withhold the reply
+    Block,
+    // Go ahead, but tell the user
+    Warn,
+    // Go ahead silently
+    Log,
+}
+
+impl From<String> for ModerationAction {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "block" => ModerationAction::Block,
+            "warn" => ModerationAction::Warn,
+            "log" => ModerationAction::Log,
+            _ => ModerationAction::Block,
+        }
+    }
+}
+
+impl std::fmt::Display for ModerationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModerationAction::Block => write!(f, "block"),
+            ModerationAction::Warn => write!(f, "warn"),
+            ModerationAction::Log => write!(f, "log"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationDirection {
+    Input,
+    Output,
+}
+
+impl std::fmt::Display for ModerationDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModerationDirection::Input => write!(f, "input"),
+            ModerationDirection::Output => write!(f, "output"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ModeratorConfig {
+    pub provider: ModerationProvider,
+    pub model_name: String,
+    pub api_key: Option<String>,
+    pub base_url: String,
+    pub action: ModerationAction,
+    // Local classifier labels scoring at least this are flagged
+    pub threshold: f64,
+}
+
+#[derive(Clone)]
+pub struct Moderator {
+    config: ModeratorConfig,
+    http: reqwest::Client,
+}
+
+impl Moderator {
+    pub fn new(config: ModeratorConfig) -> Result<Self> {
+        if config.provider == ModerationProvider::OpenAI && config.api_key.is_none() {
+            return Err(anyhow::anyhow!("OpenAI moderation requires an API key"));
+        }
+
+        Ok(Self {
+            config,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    pub fn action(&self) -> ModerationAction {
+        self.config.action
+    }
+
+    // The categories the text was flagged for; empty when it's fine
+    pub async fn check(&self, text: &str) -> Result<Vec<String>> {
+        match self.config.provider {
+            ModerationProvider::OpenAI => {
+                let response: Value = self.http
+                    .post("https://api.openai.com/v1/moderations")
+                    .bearer_auth(self.config.api_key.as_deref().unwrap_or_default())
+                    .json(&json!({ "model": self.config.model_name, "input": text }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                let categories = response["results"][0]["categories"].as_object()
+                    .ok_or_else(|| anyhow::anyhow!("Unexpected moderation response"))?;
+                Ok(categories.iter()
+                    .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                    .map(|(category, _)| category.clone())
+                    .collect())
+            }
+            ModerationProvider::Local => {
+                let response: Value = self.http
+                    .post(format!("{}/predict", self.config.base_url))
+                    .json(&json!({ "inputs": text }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                let predictions = response.as_array()
+                    .ok_or_else(|| anyhow::anyhow!("Unexpected moderation response"))?;
+                Ok(predictions.iter()
+                    .filter_map(|prediction| Some((prediction["label"].as_str()?, prediction["score"].as_f64()?)))
+                    .filter(|(label, score)| *score >= self.config.threshold && !SAFE_LABELS.contains(&label.to_lowercase().as_str()))
+                    .map(|(label, _)| label.to_string())
+                    .collect())
+            }
+        }
+    }
+}
+
+// Whether a reply may be withheld once it is written, in which case none of it should be
+// shown before the filter has seen the whole of it
+pub fn may_withhold_replies(state: &AppState) -> bool {
+    state.moderator.as_ref().is_some_and(|moderator| moderator.action() == ModerationAction::Block)
+}
+
+// Runs one side of an exchange past the content filter, if there is one. Flagged text is
+// recorded as a moderation event and then blocked with an error, or let through with a
+// warning for the user, or let through silently, as configured. The filter being
+// unreachable doesn't stop the chat.
+#[tracing::instrument(skip_all, fields(direction = ?direction))]
+pub async fn moderate(
+    state: &AppState,
+    user_id: &str,
+    session_id: &str,
+    direction: ModerationDirection,
+    text: &str,
+) -> Result<Option<String>> {
+    let Some(moderator) = &state.moderator else {
+        return Ok(None);
+    };
+    let categories = match moderator.check(text).await {
+        Ok(categories) => categories,
+        Err(e) => {
+            tracing::warn!("Content filter failed, letting {} through: {}", direction, e);
+            return Ok(None);
+        }
+    };
+    if categories.is_empty() {
+        return Ok(None);
+    }
+
+    let action = moderator.action();
+    state.db.record_moderation_event(&ModerationEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        session_id: session_id.to_string(),
+        direction: direction.to_string(),
+        action: action.to_string(),
+        categories: categories.clone(),
+        created_at: chrono::Utc::now(),
+    }).await?;
+
+    let subject = match direction {
+        ModerationDirection::Input => "Your message",
+        ModerationDirection::Output => "The reply",
+    };
+    match action {
+        ModerationAction::Block => Err(anyhow::anyhow!("{} was blocked by the content filter ({})", subject, categories.join(", "))),
+        ModerationAction::Warn => Ok(Some(format!("{} was flagged by the content filter ({})", subject, categories.join(", ")))),
+        ModerationAction::Log => Ok(None),
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use crate::{api::AppState, audit::SSO_ACTOR, models::*, secrets::secret_box};
+
+pub const CALLBACK_PATH: &str = "/api/auth/oidc/callback";
+// Holds the state, nonce and PKCE verifier of a sign-in in progress for the callback to check
+pub const FLOW_COOKIE: &str = "aibot_oidc";
+// How long the user has to finish signing in at the provider
+const FLOW_TTL_SECS: i64 = 10 * 60;
+// Holds, sealed, the user who signed in at the provider until they enter their two-factor code
+pub const PENDING_COOKIE: &str = "aibot_sso_pending";
+// How long they have to enter it
+const PENDING_TTL_SECS: i64 = 5 * 60;
+// Leeway for the provider's clock when checking an ID token's expiry
+const CLOCK_SKEW_SECS: i64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    // Issuer URL of the provider, like `https://example.okta.com` or a Keycloak realm
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    // Where the provider sends users back to; PUBLIC_BASE_URL followed by `CALLBACK_PATH`
+    pub redirect_url: String,
+    pub scopes: String,
+    // The ID token or userinfo claim listing the user's groups
+    pub groups_claim: String,
+    // Groups and the role each gives. Users get the highest role of their groups, and their
+    // role follows their groups at every sign-in. Empty leaves roles to the admin panel.
+    pub role_mapping: Vec<(String, Role)>,
+    // Role of users in none of the mapped groups; None turns them away
+    pub default_role: Option<Role>,
+    // Shown on the sign-in button, like "Okta"
+    pub display_name: String,
+}
+
+// Reads `group:role,group:role`, failing on a role that doesn't exist
+pub fn parse_role_mapping(mapping: &str) -> Result<Vec<(String, Role)>> {
+    mapping.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (group, role) = entry.rsplit_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Expected group:role, got {}", entry))?;
+            Ok((group.trim().to_string(), parse_role(role)?))
+        })
+        .collect()
+}
+
+pub fn parse_role(role: &str) -> Result<Role> {
+    Role::ALL.into_iter()
+        .find(|candidate| candidate.to_string() == role.trim().to_lowercase())
+        .ok_or_else(|| anyhow::anyhow!("Unknown role {}", role))
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: Option<String>,
+}
+
+// Signs users in with an OpenID Connect provider such as Okta, Keycloak or Azure AD, using
+// the authorization code flow with PKCE
+#[derive(Clone)]
+pub struct Oidc {
+    config: Arc<OidcConfig>,
+    client: reqwest::Client,
+    // Fetched from the provider on first use, so the server starts while it is unreachable
+    discovery: Arc<tokio::sync::OnceCell<Discovery>>,
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn flow_cookie(value: &str, max_age_secs: i64) -> String {
+    format!("{}={}; Path=/api/auth/oidc/; HttpOnly; SameSite=Lax; Max-Age={}", FLOW_COOKIE, value, max_age_secs)
+}
+
+// Clears the cookie of a sign-in once it is finished, whether or not it worked
+pub fn clear_flow_cookie() -> String {
+    flow_cookie("", 0)
+}
+
+fn pending_cookie(value: &str, max_age_secs: i64) -> String {
+    format!("{}={}; Path=/api/; HttpOnly; SameSite=Strict; Max-Age={}", PENDING_COOKIE, value, max_age_secs)
+}
+
+// Holds a user who signed in at the provider but has two-factor authentication, until the
+// code from their app is checked. Two-factor needs SECRETS_KEY, so the cookie can be sealed.
+pub fn two_factor_pending_cookie(state: &AppState, user_id: &str) -> Result<String> {
+    let expires_at = (Utc::now() + Duration::seconds(PENDING_TTL_SECS)).timestamp();
+    let sealed = secret_box(state)?.seal(&format!("{}.{}", user_id, expires_at))?;
+    Ok(pending_cookie(&sealed, PENDING_TTL_SECS))
+}
+
+pub fn clear_pending_cookie() -> String {
+    pending_cookie("", 0)
+}
+
+// The user a `two_factor_pending_cookie` is for, while it is still valid
+pub fn pending_user_id(state: &AppState, cookie: Option<&str>) -> Result<String> {
+    let expired = || anyhow::anyhow!("Single sign-on expired; sign in again");
+    let value = secret_box(state)?.open(cookie.ok_or_else(expired)?).map_err(|_| expired())?;
+    let (user_id, expires_at) = value.rsplit_once('.').ok_or_else(expired)?;
+    if expires_at.parse::<i64>().map_err(|_| expired())? < Utc::now().timestamp() {
+        return Err(expired());
+    }
+    Ok(user_id.to_string())
+}
+
+// The claim as a list of strings, whether the provider sent a list or a single value
+fn string_list(claim: Option<&Value>) -> Vec<String> {
+    match claim {
+        Some(Value::Array(values)) => values.iter().filter_map(|value| value.as_str().map(str::to_string)).collect(),
+        Some(Value::String(value)) => vec![value.clone()],
+        _ => Vec::new(),
+    }
+}
+
+impl Oidc {
+    pub fn new(mut config: OidcConfig) -> Self {
+        config.issuer = config.issuer.trim_end_matches('/').to_string();
+        Self {
+            config: Arc::new(config),
+            client: reqwest::Client::new(),
+            discovery: Arc::new(tokio::sync::OnceCell::new()),
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.config.display_name
+    }
+
+    async fn discovery(&self) -> Result<&Discovery> {
+        self.discovery.get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+            let discovery: Discovery = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+            if discovery.issuer.trim_end_matches('/') != self.config.issuer {
+                return Err(anyhow::anyhow!("Provider reports issuer {}, expected {}", discovery.issuer, self.config.issuer));
+            }
+            Ok::<_, anyhow::Error>(discovery)
+        }).await
+    }
+
+    // The provider URL to send the browser to, and the cookie to set on that redirect
+    pub async fn authorization_redirect(&self) -> Result<(String, String)> {
+        let discovery = self.discovery().await?;
+        let (state, nonce, verifier) = (random_token(), random_token(), random_token());
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        let cookie = flow_cookie(&format!("{}.{}.{}", state, nonce, verifier), FLOW_TTL_SECS);
+        Ok((url.to_string(), cookie))
+    }
+
+    // The claims about the user, from the ID token and, for what it leaves out, the userinfo
+    // endpoint. The ID token comes straight from the token endpoint over TLS, so its
+    // signature isn't checked again here; its issuer, audience, expiry and nonce are.
+    async fn claims(&self, code: &str, nonce: &str, verifier: &str) -> Result<serde_json::Map<String, Value>> {
+        let discovery = self.discovery().await?;
+        let tokens: TokenResponse = self.client
+            .post(&discovery.token_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("code_verifier", verifier),
+            ])
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        let payload = tokens.id_token.split('.').nth(1)
+            .ok_or_else(|| anyhow::anyhow!("Malformed ID token"))?;
+        let mut claims: serde_json::Map<String, Value> = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))?)?;
+
+        if claims.get("iss").and_then(Value::as_str) != Some(discovery.issuer.as_str()) {
+            return Err(anyhow::anyhow!("ID token is from another issuer"));
+        }
+        if !string_list(claims.get("aud")).contains(&self.config.client_id) {
+            return Err(anyhow::anyhow!("ID token is for another client"));
+        }
+        let expires_at = claims.get("exp").and_then(Value::as_i64).unwrap_or(0);
+        if expires_at + CLOCK_SKEW_SECS < Utc::now().timestamp() {
+            return Err(anyhow::anyhow!("ID token has expired"));
+        }
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(anyhow::anyhow!("ID token nonce doesn't match"));
+        }
+
+        let incomplete = !claims.contains_key("email") || !claims.contains_key(&self.config.groups_claim);
+        if let (true, Some(endpoint), Some(access_token)) = (incomplete, &discovery.userinfo_endpoint, &tokens.access_token) {
+            let userinfo: serde_json::Map<String, Value> = self.client.get(endpoint)
+                .bearer_auth(access_token)
+                .send().await?
+                .error_for_status()?
+                .json().await?;
+            // Userinfo about someone else must not fill in the token's claims
+            if userinfo.get("sub") == claims.get("sub") {
+                for (name, value) in userinfo {
+                    claims.entry(name).or_insert(value);
+                }
+            }
+        }
+        Ok(claims)
+    }
+
+    // The role the user's groups give them, or the default role when they are in none of the
+    // mapped groups
+    fn role_for(&self, groups: &[String]) -> Option<Role> {
+        let granted: Vec<Role> = self.config.role_mapping.iter()
+            .filter(|(group, _)| groups.contains(group))
+            .map(|(_, role)| *role)
+            .collect();
+        Role::ALL.into_iter().find(|role| granted.contains(role)).or(self.config.default_role)
+    }
+
+    // Completes a sign-in when the provider redirects back, and returns the user to sign in.
+    // Users are matched by their identity at the provider, then by verified email, and
+    // otherwise get a new account without a password. Users with two-factor authentication
+    // still have to enter a code before they are signed in.
+    pub async fn complete(&self, state: &AppState, code: &str, returned_state: &str, flow: Option<&str>) -> Result<User> {
+        let flow = flow.ok_or_else(|| anyhow::anyhow!("Sign-in cookie missing or expired"))?;
+        let mut parts = flow.splitn(3, '.');
+        let (Some(expected_state), Some(nonce), Some(verifier)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(anyhow::anyhow!("Malformed sign-in cookie"));
+        };
+        if expected_state != returned_state {
+            return Err(anyhow::anyhow!("Sign-in state doesn't match"));
+        }
+
+        let claims = self.claims(code, nonce, verifier).await?;
+        let subject = claims.get("sub").and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("ID token has no subject"))?;
+        // Only an email the provider says it verified is used, since anyone can put any address
+        // on an account at some providers. Users of providers that don't send `email_verified`,
+        // such as Azure AD, get accounts of their own rather than being linked by email.
+        let email = claims.get("email").and_then(Value::as_str)
+            .filter(|_| claims.get("email_verified").and_then(Value::as_bool) == Some(true));
+        let name = claims.get("name").and_then(Value::as_str).map(str::to_string);
+        let groups = string_list(claims.get(&self.config.groups_claim));
+        let role = self.role_for(&groups)
+            .ok_or_else(|| anyhow::anyhow!("User {} is in none of the groups allowed to sign in", subject))?;
+
+        let issuer = &self.config.issuer;
+        let mut user = match state.db.get_identity_user_id(issuer, subject).await? {
+            Some(user_id) => state.db.get_user(&user_id).await?
+                .ok_or_else(|| anyhow::anyhow!("User not found"))?,
+            None => {
+                let user = match email {
+                    Some(email) => state.db.get_user_by_email(email).await?,
+                    None => None,
+                };
+                let user = match user {
+                    Some(user) => user,
+                    None => {
+                        let mut user = User::new(name, email.map(str::to_string));
+                        user.role = role;
+                        state.db.create_user(&user).await?;
+                        user
+                    }
+                };
+                state.db.create_user_identity(issuer, subject, &user.id).await?;
+                user
+            }
+        };
+
+        if !self.config.role_mapping.is_empty() && user.role != role {
+            // The provider can't demote the last admin, so the instance keeps someone who can
+            // manage it
+            if user.role == Role::Admin && state.db.count_admins().await? <= 1 {
+                tracing::warn!("Kept the last admin's role despite their single sign-on groups");
+            } else {
+                state.db.set_user_role(&user.id, role).await?;
+                let details = serde_json::json!({ "from": user.role.to_string(), "to": role.to_string() });
+                crate::audit::record(state, SSO_ACTOR, AuditAction::UserRoleChanged, Some(&user.id), Some(details)).await?;
+                user.role = role;
+            }
+        }
+        Ok(user)
+    }
+}
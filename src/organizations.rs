@@ -0,0 +1,57 @@
+use anyhow::Result;
+use crate::{api::AppState, database::Database, models::*, secrets::{secret_box, SecretBox}};
+
+// The organization a session belongs to; sessions saved before organizations existed
+// belong to the default one
+pub fn session_org_id(session: &ChatSession) -> &str {
+    session.org_id.as_deref().unwrap_or(DEFAULT_ORG_ID)
+}
+
+// Checks the session's organization may use the session's provider, and returns the
+// organization's own key for it, if it has one. Without one the instance key is used.
+pub async fn provider_key(state: &AppState, session: &ChatSession) -> Result<Option<String>> {
+    let org_id = session_org_id(session);
+    if let Some(organization) = state.db.get_organization(org_id).await? {
+        if !organization.settings.allows_provider(&session.model_provider) {
+            return Err(anyhow::anyhow!(
+                "{} is not enabled for the {} organization",
+                session.model_provider,
+                organization.name
+            ));
+        }
+    }
+    match state.db.get_organization_api_key(org_id, &session.model_provider).await? {
+        Some((sealed, true)) => Ok(Some(secret_box(state)?.open(&sealed)?)),
+        // Saved before keys were sealed, and not sealed yet because SECRETS_KEY isn't set
+        Some((api_key, false)) => Ok(Some(api_key)),
+        None => Ok(None),
+    }
+}
+
+// Stores the organization's key for a provider sealed. Unlike a user's own key it isn't
+// checked with the provider, since an admin may set it up before the provider is reachable.
+pub async fn save_key(state: &AppState, org_id: &str, provider: &str, api_key: &str) -> Result<StoredApiKey> {
+    let secret_box = secret_box(state)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return Err(anyhow::anyhow!("API key can't be empty"));
+    }
+
+    let fingerprint = crate::secrets::fingerprint(api_key);
+    state.db.set_organization_api_key(org_id, provider, &secret_box.seal(api_key)?, &fingerprint).await?;
+    Ok(StoredApiKey {
+        provider: provider.to_string(),
+        fingerprint,
+        updated_at: chrono::Utc::now(),
+    })
+}
+
+// Seals organization keys saved in plain text before keys were sealed. Returns how many.
+pub async fn seal_legacy_keys(db: &Database, secret_box: &SecretBox) -> Result<usize> {
+    let unsealed = db.get_unsealed_organization_api_keys().await?;
+    for (org_id, provider, api_key) in &unsealed {
+        let fingerprint = crate::secrets::fingerprint(api_key);
+        db.seal_organization_api_key(org_id, provider, &secret_box.seal(api_key)?, &fingerprint).await?;
+    }
+    Ok(unsealed.len())
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect, Url,
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+// Requests to URLs users give us (crawled sites, feeds, webhook and rule targets, pages
+// fetched for scheduled prompts) go through `client`, which only ever connects to public
+// addresses. Otherwise a user could point one at localhost, the LAN or a cloud metadata
+// endpoint and read the response back out of their knowledge base or a delivery log.
+const MAX_REDIRECTS: usize = 10;
+// A slow server can hold up a background job or the scheduler for at most this long
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+// Resolves host names like the system resolver, keeping only public addresses. Checking at
+// connection time, rather than only when a URL is saved, also covers redirects and a DNS
+// record that changes after the check.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                let error = std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{} has no public address", host));
+                return Err(Box::new(error) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+// The shared client for user-supplied URLs. URLs with a literal IP skip the resolver, so
+// redirects to one are checked here, and callers check the URLs they request with
+// `literal_host_is_public` unless `check_url` already has.
+pub fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicOnly))
+            // A proxy would do the resolving, out of reach of the check
+            .no_proxy()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .redirect(redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if !literal_host_is_public(attempt.url()) {
+                    attempt.error("redirected to a private address")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()
+            .expect("the outbound HTTP client settings are valid")
+    })
+}
+
+// Checks a URL before it is saved: http or https, and a host that is public. The client
+// checks again on every request.
+pub async fn check_url(url: &Url) -> Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("Only http and https URLs are allowed"));
+    }
+    let host = url.host_str().ok_or_else(|| anyhow::anyhow!("The URL has no host"))?;
+    if !literal_host_is_public(url) {
+        return Err(anyhow::anyhow!("{} is a private address", host));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port)).await
+        .map_err(|e| anyhow::anyhow!("Couldn't resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(anyhow::anyhow!("{} resolves to a private address", host));
+    }
+    Ok(())
+}
+
+// Reads a response body, giving up once it is longer than `limit` bytes
+pub async fn read_body(mut response: reqwest::Response, limit: usize) -> Result<Vec<u8>> {
+    if response.content_length().is_some_and(|length| length > limit as u64) {
+        return Err(anyhow::anyhow!("{} is larger than {} bytes", response.url(), limit));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(anyhow::anyhow!("{} is larger than {} bytes", response.url(), limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// `read_body` as text, with anything that isn't UTF-8 replaced
+pub async fn read_text(response: reqwest::Response, limit: usize) -> Result<String> {
+    Ok(String::from_utf8_lossy(&read_body(response, limit).await?).into_owned())
+}
+
+// False when the URL's host is an IP address that isn't public; names are left to the resolver
+pub(crate) fn literal_host_is_public(url: &Url) -> bool {
+    match url.host_str() {
+        // IPv6 hosts come bracketed, as in `http://[::1]/`
+        Some(host) => !matches!(host.trim_matches(['[', ']']).parse::<IpAddr>(), Ok(ip) if !is_public(ip)),
+        None => false,
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // "This network", carrier-grade NAT, IETF protocol assignments, benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped and NAT64 addresses reach the IPv4 address inside them
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, link-local and the old site-local ranges
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // Documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn public_addresses_pass() {
+        assert!(public("93.184.216.34"));
+        assert!(public("1.1.1.1"));
+        assert!(public("2606:4700:4700::1111"));
+    }
+
+    #[test]
+    fn private_and_loopback_addresses_are_refused() {
+        assert!(!public("10.0.0.1"));
+        assert!(!public("172.16.5.4"));
+        assert!(!public("192.168.1.1"));
+        assert!(!public("127.0.0.1"));
+        assert!(!public("0.0.0.0"));
+        assert!(!public("::1"));
+        assert!(!public("::"));
+    }
+
+    #[test]
+    fn link_local_addresses_are_refused() {
+        // Cloud metadata endpoints live here
+        assert!(!public("169.254.169.254"));
+        assert!(!public("fe80::1"));
+    }
+
+    #[test]
+    fn carrier_grade_nat_is_refused() {
+        assert!(!public("100.64.0.1"));
+        assert!(!public("100.127.255.254"));
+        assert!(public("100.128.0.1"));
+    }
+
+    #[test]
+    fn unique_local_ipv6_is_refused() {
+        assert!(!public("fc00::1"));
+        assert!(!public("fd12:3456:789a::1"));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_judged_by_the_ipv4_inside() {
+        assert!(!public("::ffff:127.0.0.1"));
+        assert!(!public("::ffff:169.254.169.254"));
+        assert!(public("::ffff:93.184.216.34"));
+    }
+
+    #[test]
+    fn nat64_addresses_are_judged_by_the_ipv4_inside() {
+        assert!(!public("64:ff9b::7f00:1"));
+        assert!(!public("64:ff9b::a9fe:a9fe"));
+        assert!(public("64:ff9b::5db8:d822"));
+    }
+
+    #[test]
+    fn literal_hosts_are_checked_and_names_left_to_the_resolver() {
+        assert!(!literal_host_is_public(&url("http://127.0.0.1:8080/")));
+        assert!(!literal_host_is_public(&url("http://169.254.169.254/latest/meta-data/")));
+        assert!(!literal_host_is_public(&url("http://[::1]/")));
+        assert!(!literal_host_is_public(&url("http://[::ffff:10.0.0.1]/")));
+        assert!(literal_host_is_public(&url("http://93.184.216.34/")));
+        assert!(literal_host_is_public(&url("https://example.com/")));
+    }
+}
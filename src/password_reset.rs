@@ -0,0 +1,85 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::{api::AppState, models::*};
+
+const TOKEN_TTL_MINUTES: i64 = 60;
+const INVALID_TOKEN: &str = "This reset link is invalid or has already been used";
+
+// Reset tokens are `<user id>.<expiry>.<signature>`. The signature also covers the user's
+// current password hash, so a token stops working once the password changes, by this
+// token or any other way, without the server keeping track of tokens.
+fn mac(key: &[u8], user_id: &str, expires_at: i64, password_hash: &str) -> Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| anyhow::anyhow!("Invalid reset token key: {}", e))?;
+    mac.update(format!("{}.{}.{}", user_id, expires_at, password_hash).as_bytes());
+    Ok(mac)
+}
+
+fn create_token(key: &[u8], user_id: &str, password_hash: &str, now: DateTime<Utc>) -> Result<String> {
+    let expires_at = (now + Duration::minutes(TOKEN_TTL_MINUTES)).timestamp();
+    let signature = mac(key, user_id, expires_at, password_hash)?.finalize().into_bytes();
+    Ok(format!("{}.{}.{}", user_id, expires_at, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+// The user a token was issued for, if it is genuine, unexpired and unused
+async fn verify_token(state: &AppState, token: &str, now: DateTime<Utc>) -> Result<String> {
+    let invalid = || anyhow::anyhow!(INVALID_TOKEN);
+    let mut parts = token.trim().splitn(3, '.');
+    let (Some(user_id), Some(expires_at), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let expires_at: i64 = expires_at.parse().map_err(|_| invalid())?;
+    if expires_at < now.timestamp() {
+        return Err(anyhow::anyhow!("This reset link has expired; ask for a new one"));
+    }
+
+    let password_hash = state.db.get_password_hash(user_id).await?.ok_or_else(invalid)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+    mac(&state.reset_token_key, user_id, expires_at, &password_hash)?
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+    Ok(user_id.to_string())
+}
+
+// Emails a reset link if an account with a password has this address. Whether one does
+// isn't revealed, by the result or by how long it takes, since the email is sent in the
+// background.
+pub async fn request(state: &AppState, email: &str) -> Result<()> {
+    let mailer = state.mailer.clone()
+        .ok_or_else(|| anyhow::anyhow!("Resetting passwords by email is not set up on this server"))?;
+    let Some(user) = state.db.get_user_by_email(email).await? else { return Ok(()) };
+    let (Some(address), Some(password_hash)) = (user.email, state.db.get_password_hash(&user.id).await?) else {
+        return Ok(());
+    };
+
+    let token = create_token(&state.reset_token_key, &user.id, &password_hash, Utc::now())?;
+    let base_url = &state.public_base_url;
+    let body = format!(
+        "Someone asked to reset the password for your account. If it was you, open this link within {} minutes to choose a new one:\n\n{}/reset-password?token={}\n\nIf it wasn't you, you can ignore this email; your password hasn't changed.\n",
+        TOKEN_TTL_MINUTES,
+        base_url.trim_end_matches('/'),
+        token,
+    );
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&address, "Reset your password", &body).await {
+            tracing::error!("Failed to send password reset email: {}", e);
+        }
+    });
+    Ok(())
+}
+
+// Sets a new password with a reset token and signs the user out everywhere. They sign in
+// again themselves, so two-factor authentication still applies.
+pub async fn reset(state: &AppState, token: &str, new_password: &str) -> Result<String> {
+    let user_id = verify_token(state, token, Utc::now()).await?;
+    if new_password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(anyhow::anyhow!("Passwords must be at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+
+    state.db.set_password_hash(&user_id, &crate::auth::hash_password(new_password)?).await?;
+    state.db.delete_user_auth_sessions(&user_id).await?;
+    Ok(user_id)
+}
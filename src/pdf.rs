@@ -0,0 +1,302 @@
+use anyhow::Result;
+use image::DynamicImage;
+use printpdf::{BuiltinFont, Image, ImageTransform, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use pulldown_cmark::{Event, Parser, Tag};
+use crate::models::*;
+
+// A4, in millimetres
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const CODE_INDENT: f32 = 4.0;
+const MM_PER_POINT: f32 = 0.3528;
+const LINE_SPACING: f32 = 1.4;
+const TITLE_SIZE: f32 = 16.0;
+const SPEAKER_SIZE: f32 = 11.0;
+const BODY_SIZE: f32 = 10.0;
+const CODE_SIZE: f32 = 8.5;
+const NOTE_SIZE: f32 = 8.0;
+// Average glyph width of Helvetica as a fraction of the font size, a little generous so
+// lines of wide letters still fit; Courier's is exact
+const PROSE_GLYPH_WIDTH: f32 = 0.55;
+const MONO_GLYPH_WIDTH: f32 = 0.6;
+// Images are shrunk to fit in this box, and never enlarged
+const MAX_IMAGE_WIDTH: f32 = 120.0;
+const MAX_IMAGE_HEIGHT: f32 = 120.0;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+// A block of a message's Markdown as it is laid out
+enum Block {
+    Text(String),
+    Heading(String),
+    Code(String),
+}
+
+// Lays text and images out top to bottom, starting a new page when one fills up
+struct Writer {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    fonts: [IndirectFontRef; 4],
+    // Distance of the next line from the bottom of the page
+    y: f32,
+}
+
+impl Writer {
+    fn new(title: &str) -> Result<Self> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        let fonts = [
+            doc.add_builtin_font(BuiltinFont::Helvetica)?,
+            doc.add_builtin_font(BuiltinFont::HelveticaBold)?,
+            doc.add_builtin_font(BuiltinFont::HelveticaOblique)?,
+            doc.add_builtin_font(BuiltinFont::Courier)?,
+        ];
+        let layer = doc.get_page(page).get_layer(layer);
+        Ok(Self { doc, layer, fonts, y: PAGE_HEIGHT - MARGIN })
+    }
+
+    // Starts a new page unless `height` still fits on this one
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn line(&mut self, text: &str, size: f32, font: Font, indent: f32) {
+        let height = size * MM_PER_POINT * LINE_SPACING;
+        self.reserve(height);
+        self.y -= height;
+        let font = &self.fonts[font as usize];
+        self.layer.use_text(latin1(text), size, Mm(MARGIN + indent), Mm(self.y), font);
+    }
+
+    // Wraps prose at word boundaries to the width of the page
+    fn paragraph(&mut self, text: &str, size: f32, font: Font) {
+        let width = line_width(size, PROSE_GLYPH_WIDTH, 0.0);
+        for line in text.lines() {
+            for wrapped in wrap_words(line, width) {
+                self.line(&wrapped, size, font, 0.0);
+            }
+        }
+    }
+
+    // Code keeps its indentation and is broken wherever a line runs out of room
+    fn code(&mut self, code: &str) {
+        let width = line_width(CODE_SIZE, MONO_GLYPH_WIDTH, CODE_INDENT);
+        for line in code.trim_end().lines() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                self.line("", CODE_SIZE, Font::Mono, CODE_INDENT);
+            }
+            for chunk in chars.chunks(width) {
+                self.line(&chunk.iter().collect::<String>(), CODE_SIZE, Font::Mono, CODE_INDENT);
+            }
+        }
+    }
+
+    fn image(&mut self, image: &DynamicImage) {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        if width == 0.0 || height == 0.0 {
+            return;
+        }
+        // Millimetres per pixel at 300 dpi, reduced further for images too big for the box
+        let scale = (25.4 / 300.0_f32).min(MAX_IMAGE_WIDTH / width).min(MAX_IMAGE_HEIGHT / height);
+        let dpi = 25.4 / scale;
+        self.reserve(height * scale);
+        self.y -= height * scale;
+        // PDF images here have no transparency, so it's flattened away first
+        let image = Image::from_dynamic_image(&DynamicImage::ImageRgb8(image.to_rgb8()));
+        image.add_to_layer(self.layer.clone(), ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(self.y)),
+            dpi: Some(dpi),
+            ..Default::default()
+        });
+        self.gap(2.0);
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        Ok(self.doc.save_to_bytes()?)
+    }
+}
+
+// How many glyphs fit across the page
+fn line_width(size: f32, glyph_width: f32, indent: f32) -> usize {
+    ((PAGE_WIDTH - 2.0 * MARGIN - indent) / (size * MM_PER_POINT * glyph_width)).max(1.0) as usize
+}
+
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        // Words longer than a line, like URLs, are broken up
+        while word.len() > width {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            lines.push(word.drain(..width).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+// The built-in PDF fonts only cover Latin-1, so common punctuation beyond it is swapped for
+// its nearest match and anything else for a question mark
+fn latin1(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{2018}' | '\u{2019}' => "'".to_string(),
+            '\u{201C}' | '\u{201D}' => "\"".to_string(),
+            '\u{2013}' | '\u{2014}' => "-".to_string(),
+            '\u{2022}' => "*".to_string(),
+            '\u{2026}' => "...".to_string(),
+            '\t' => "    ".to_string(),
+            c if (c as u32) < 0x20 => String::new(),
+            c if (c as u32) < 0x100 => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+// Splits Markdown into what gets laid out differently: prose, headings and code blocks.
+// Inline formatting is dropped, except that inline code keeps its backticks.
+fn blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut code: Option<String> = None;
+    let mut list_depth = 0usize;
+
+    let flush = |text: &mut String, blocks: &mut Vec<Block>| {
+        if !text.trim().is_empty() {
+            blocks.push(Block::Text(text.trim().to_string()));
+        }
+        text.clear();
+    };
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                flush(&mut text, &mut blocks);
+                code = Some(String::new());
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some(code) = code.take() {
+                    blocks.push(Block::Code(code));
+                }
+            }
+            Event::Text(content) => match code.as_mut() {
+                Some(code) => code.push_str(&content),
+                None => text.push_str(&content),
+            },
+            Event::Code(content) => text.push_str(&format!("`{}`", content)),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Start(Tag::Heading(..)) => flush(&mut text, &mut blocks),
+            Event::End(Tag::Heading(..)) => {
+                if !text.trim().is_empty() {
+                    blocks.push(Block::Heading(text.trim().to_string()));
+                }
+                text.clear();
+            }
+            Event::Start(Tag::List(_)) => {
+                flush(&mut text, &mut blocks);
+                list_depth += 1;
+            }
+            Event::End(Tag::List(_)) => list_depth = list_depth.saturating_sub(1),
+            Event::Start(Tag::Item) => {
+                flush(&mut text, &mut blocks);
+                text.push_str(&"    ".repeat(list_depth.saturating_sub(1)));
+                text.push_str("- ");
+            }
+            Event::End(Tag::Paragraph) | Event::End(Tag::Item) | Event::End(Tag::BlockQuote) => {
+                flush(&mut text, &mut blocks);
+            }
+            Event::Rule => flush(&mut text, &mut blocks),
+            _ => {}
+        }
+    }
+    flush(&mut text, &mut blocks);
+    blocks
+}
+
+// Renders a session transcript as a PDF. `images` holds the decoded image attachments of
+// each message, in the same order as `export.messages`.
+pub fn render(export: &SessionExport, images: &[Vec<DynamicImage>]) -> Result<Vec<u8>> {
+    let title = export.title.as_deref().unwrap_or("Untitled chat");
+    let mut writer = Writer::new(title)?;
+    writer.paragraph(title, TITLE_SIZE, Font::Bold);
+    writer.paragraph(
+        &format!("{} - {} - started {}", export.model_provider, export.model_name, export.created_at.format("%Y-%m-%d %H:%M UTC")),
+        NOTE_SIZE,
+        Font::Italic,
+    );
+
+    for (index, message) in export.messages.iter().enumerate() {
+        let speaker = match message.role {
+            MessageRole::User => "User".to_string(),
+            MessageRole::System => "System".to_string(),
+            MessageRole::Assistant => match &message.model_name {
+                Some(model_name) => format!("Assistant ({})", model_name),
+                None => "Assistant".to_string(),
+            },
+        };
+        writer.gap(4.0);
+        writer.paragraph(&format!("{} - {}", speaker, message.created_at.format("%Y-%m-%d %H:%M")), SPEAKER_SIZE, Font::Bold);
+
+        for block in blocks(&message.content) {
+            writer.gap(1.5);
+            match block {
+                Block::Text(text) => writer.paragraph(&text, BODY_SIZE, Font::Regular),
+                Block::Heading(text) => writer.paragraph(&text, BODY_SIZE, Font::Bold),
+                Block::Code(code) => writer.code(&code),
+            }
+        }
+
+        for image in images.get(index).into_iter().flatten() {
+            writer.gap(2.0);
+            writer.image(image);
+        }
+        let other_attachments: Vec<&str> = message.attachments.iter()
+            .filter(|attachment| !attachment.file_type.starts_with("image/"))
+            .map(|attachment| attachment.file_name.as_str())
+            .collect();
+        if !other_attachments.is_empty() {
+            writer.gap(1.5);
+            writer.paragraph(&format!("Attachments: {}", other_attachments.join(", ")), NOTE_SIZE, Font::Italic);
+        }
+
+        if !message.citations.is_empty() {
+            writer.gap(1.5);
+            writer.paragraph("Sources", NOTE_SIZE, Font::Bold);
+            for citation in &message.citations {
+                writer.paragraph(&format!("{}. {} ({})", citation.index, citation.title, citation.source_uri), NOTE_SIZE, Font::Regular);
+            }
+        }
+    }
+
+    writer.finish()
+}
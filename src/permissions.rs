@@ -0,0 +1,98 @@
+use anyhow::Result;
+use crate::{api::AppState, database::OwnedResource, models::*};
+
+// Whether an admin is held back to a member's capabilities until they turn on two-factor
+async fn needs_two_factor(state: &AppState, user: &User) -> Result<bool> {
+    Ok(user.role == Role::Admin
+        && state.require_admin_two_factor
+        && !crate::two_factor::is_enabled(state, &user.id).await?)
+}
+
+// The role a signed-in user acts with. A user deleted since signing in has no access at
+// all, and an admin who must set up two-factor first acts as a member.
+pub async fn role(state: &AppState, user_id: &str) -> Result<Role> {
+    match state.db.get_user(user_id).await? {
+        Some(user) => Ok(acting_role(user.role, needs_two_factor(state, &user).await?)),
+        None => Ok(Role::Guest),
+    }
+}
+
+fn acting_role(role: Role, needs_two_factor: bool) -> Role {
+    if needs_two_factor { Role::Member } else { role }
+}
+
+// Fails unless the user's role allows `capability`
+pub async fn check(state: &AppState, user_id: &str, capability: Capability) -> Result<()> {
+    if role(state, user_id).await?.can(capability) {
+        return Ok(());
+    }
+    // Only an admin held back by `needs_two_factor` can be refused as an admin
+    if matches!(state.db.get_user(user_id).await?, Some(user) if user.role == Role::Admin) {
+        return Err(anyhow::anyhow!("Turn on two-factor authentication to use admin features"));
+    }
+    Err(anyhow::anyhow!("Your role doesn't allow this ({})", capability))
+}
+
+// The user a server function acts for, failing unless their role allows `capability`.
+// Server functions that need more than a signed-in user get their user from here.
+pub async fn require(state: &AppState, capability: Capability) -> Result<String> {
+    let user_id = crate::auth::current_user_id()?;
+    check(state, &user_id, capability).await?;
+    Ok(user_id)
+}
+
+// Like `auth::authorize`, but a role with `capability` may act on anyone's record
+pub async fn authorize_any(
+    state: &AppState,
+    user_id: &str,
+    capability: Capability,
+    resource: OwnedResource,
+    id: &str,
+) -> Result<()> {
+    if role(state, user_id).await?.can(capability) {
+        return Ok(());
+    }
+    crate::auth::authorize(state, user_id, resource, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADMIN_ONLY: [Capability; 4] = [
+        Capability::ViewUsage,
+        Capability::ManageUsers,
+        Capability::DeleteAnySession,
+        Capability::ManageInstance,
+    ];
+    const MEMBER: [Capability; 4] = [
+        Capability::ManageKeys,
+        Capability::ManageKnowledge,
+        Capability::ImportData,
+        Capability::UseApiTokens,
+    ];
+
+    #[test]
+    fn admins_can_do_everything() {
+        assert!(ADMIN_ONLY.iter().chain(&MEMBER).all(|&capability| Role::Admin.can(capability)));
+    }
+
+    #[test]
+    fn members_can_only_bring_their_own_things() {
+        assert!(MEMBER.iter().all(|&capability| Role::Member.can(capability)));
+        assert!(!ADMIN_ONLY.iter().any(|&capability| Role::Member.can(capability)));
+    }
+
+    #[test]
+    fn guests_can_only_chat() {
+        assert!(!ADMIN_ONLY.iter().chain(&MEMBER).any(|&capability| Role::Guest.can(capability)));
+    }
+
+    #[test]
+    fn admins_without_two_factor_act_as_members() {
+        assert_eq!(acting_role(Role::Admin, true), Role::Member);
+        assert!(!acting_role(Role::Admin, true).can(Capability::ManageInstance));
+        assert_eq!(acting_role(Role::Admin, false), Role::Admin);
+        assert_eq!(acting_role(Role::Guest, false), Role::Guest);
+    }
+}
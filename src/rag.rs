@@ -0,0 +1,99 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use crate::{api::AppState, knowledge_base::kb_collection, models::*};
+
+// Number of chunks injected into the prompt per user message
+pub const TOP_K: usize = 5;
+// Candidates taken from each retriever, per knowledge base, before fusion
+const CANDIDATES: usize = 50;
+// Fused candidates handed to the reranker, when one is configured
+const RERANK_CANDIDATES: usize = 50;
+// Standard reciprocal rank fusion constant; dampens the weight of top ranks
+const RRF_K: f64 = 60.0;
+const SNIPPET_CHARS: usize = 280;
+
+pub struct RetrievedChunk {
+    pub citation: Citation,
+    pub content: String,
+}
+
+// Finds the chunks most relevant to `query` across the given knowledge bases by fusing
+// vector similarity and BM25 keyword rankings. Citations are numbered from 1 in rank order.
+#[tracing::instrument(skip_all, fields(knowledge_bases = kb_ids.len()))]
+pub async fn retrieve(state: &AppState, kb_ids: &[String], query: &str, k: usize) -> Result<Vec<RetrievedChunk>> {
+    if kb_ids.is_empty() || query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query_embedding = state.embeddings.embed_one(query).await?;
+    let terms = search_terms(query);
+
+    let mut fused: HashMap<String, f64> = HashMap::new();
+    for kb_id in kb_ids {
+        let vector_ranked = state.vectors.query(&kb_collection(kb_id), &query_embedding, CANDIDATES).await?;
+        for (rank, m) in vector_ranked.into_iter().enumerate() {
+            *fused.entry(m.item_id).or_default() += rrf(rank);
+        }
+
+        let keyword_ranked = state.db.search_kb_chunks(kb_id, &terms, CANDIDATES as i64).await?;
+        for (rank, chunk_id) in keyword_ranked.into_iter().enumerate() {
+            *fused.entry(chunk_id).or_default() += rrf(rank);
+        }
+    }
+
+    let mut ranked: Vec<(String, f64)> = fused.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(if state.reranker.is_some() { RERANK_CANDIDATES } else { k });
+
+    let mut candidates = Vec::with_capacity(ranked.len());
+    for (chunk_id, score) in ranked {
+        let Some(chunk) = state.db.get_kb_chunk(&chunk_id).await? else {
+            continue;
+        };
+        let Some(document) = state.db.get_kb_document(&chunk.document_id).await? else {
+            continue;
+        };
+        candidates.push((chunk, document, score));
+    }
+
+    if let Some(reranker) = &state.reranker {
+        let passages: Vec<String> = candidates.iter().map(|(chunk, _, _)| chunk.content.clone()).collect();
+        let scores = reranker.rerank(query, &passages).await?;
+        for (candidate, score) in candidates.iter_mut().zip(scores) {
+            candidate.2 = score;
+        }
+        candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+    }
+    candidates.truncate(k);
+
+    let mut retrieved = Vec::with_capacity(candidates.len());
+    for (chunk, document, score) in candidates {
+        retrieved.push(RetrievedChunk {
+            citation: Citation {
+                index: retrieved.len() + 1,
+                document_id: document.id,
+                chunk_id: chunk.id,
+                title: document.title,
+                source_uri: document.source_uri,
+                snippet: chunk.content.chars().take(SNIPPET_CHARS).collect(),
+                score,
+            },
+            content: chunk.content,
+        });
+    }
+
+    Ok(retrieved)
+}
+
+fn rrf(rank: usize) -> f64 {
+    1.0 / (RRF_K + rank as f64 + 1.0)
+}
+
+// Splits free text into the words used for keyword search; the database layer
+// escapes them for its own full-text syntax
+pub fn search_terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| term.chars().count() > 1)
+        .map(|term| term.to_string())
+        .collect()
+}
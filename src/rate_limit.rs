@@ -0,0 +1,239 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use leptos::server_fn::ServerFn;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use crate::{api, auth::AuthUser, models::RateLimited};
+
+// An address gets this many times a user's allowance, so a few users behind one NAT or
+// proxy don't throttle each other
+const IP_ALLOWANCE_FACTOR: f64 = 4.0;
+// Once this many buckets are tracked the full ones are dropped; a full bucket is the same
+// as no bucket
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+// Sign-in attempts per minute, counting guest sign-ins and password resets, so passwords and
+// two-factor codes can't be guessed quickly or inboxes flooded with reset links. Attempts
+// come from signed-out clients, so only the per-address bucket applies.
+const SIGN_INS_PER_MINUTE: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimits {
+    // Chat messages a user may send per minute; None is unlimited
+    pub messages_per_minute: Option<u32>,
+    // Documents, imports and voice notes a user may upload per hour; None is unlimited
+    pub uploads_per_hour: Option<u32>,
+    // Take the client address from X-Forwarded-For. Only safe behind a proxy that sets it.
+    pub trust_forwarded_for: bool,
+}
+
+// The client's address, added to the request extensions by `resolve_client_ip`
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    Messages,
+    Uploads,
+    SignIns,
+}
+
+impl Limit {
+    fn for_request(method: &Method, path: &str) -> Option<Self> {
+        if path == api::SendMessage::PATH || (*method == Method::POST && crate::rest_api::is_message_post(path)) {
+            Some(Limit::Messages)
+        } else if [
+            api::Login::PATH,
+            api::CompleteSingleSignOn::PATH,
+            api::StartGuestSession::PATH,
+            api::RequestPasswordReset::PATH,
+            api::ResetPassword::PATH,
+        ].contains(&path) {
+            Some(Limit::SignIns)
+        } else if [
+            api::AddKnowledgeDocuments::PATH,
+            api::ImportConversations::PATH,
+            api::ImportMemory::PATH,
+            api::ProcessVoiceInput::PATH,
+            crate::rest_api::TRANSCRIPTIONS_PATH,
+        ].contains(&path) {
+            Some(Limit::Uploads)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Limit::Messages => "messages",
+            Limit::Uploads => "uploads",
+            Limit::SignIns => "sign-in attempts",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, refill_per_sec: f64, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_per_sec).min(self.capacity);
+        self.refilled_at = now;
+    }
+}
+
+// Token buckets per user and per client address. They live in memory, so each server
+// process enforces the limits on its own.
+#[derive(Clone)]
+pub struct RateLimiter {
+    // Shared by every clone, so reloaded limits apply everywhere at once
+    limits: Arc<RwLock<RateLimits>>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits: Arc::new(RwLock::new(limits)),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn limits(&self) -> RateLimits {
+        *self.limits.read().unwrap()
+    }
+
+    // Applies new limits from the next request on; each bucket is resized the next time
+    // it's used
+    pub fn set_limits(&self, limits: RateLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    // A user's allowance and the period it refills over
+    fn allowance(&self, limit: Limit) -> Option<(f64, Duration)> {
+        match limit {
+            Limit::Messages => self.limits().messages_per_minute.map(|n| (n as f64, Duration::from_secs(60))),
+            Limit::Uploads => self.limits().uploads_per_hour.map(|n| (n as f64, Duration::from_secs(60 * 60))),
+            Limit::SignIns => Some((SIGN_INS_PER_MINUTE, Duration::from_secs(60))),
+        }
+    }
+
+    fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        if self.limits().trust_forwarded_for {
+            let forwarded = request.headers().get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip())
+    }
+
+    // Takes a token from every bucket, or from none of them if any is empty, in which case
+    // it returns how long until they all have one
+    fn take(&self, buckets: &[(String, f64)], period: Duration, now: Instant) -> Result<(), Duration> {
+        let mut tracked = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if tracked.len() >= MAX_TRACKED_BUCKETS {
+            tracked.retain(|_, bucket| {
+                bucket.refill(bucket.capacity / period.as_secs_f64(), now);
+                bucket.tokens < bucket.capacity
+            });
+        }
+
+        let mut wait = Duration::ZERO;
+        for (key, capacity) in buckets {
+            let refill_per_sec = capacity / period.as_secs_f64();
+            let bucket = tracked.entry(key.clone()).or_insert(Bucket {
+                tokens: *capacity,
+                capacity: *capacity,
+                refilled_at: now,
+            });
+            // The limit may have been changed since the bucket was made
+            bucket.capacity = *capacity;
+            bucket.refill(refill_per_sec, now);
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64((1.0 - bucket.tokens) / refill_per_sec));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for (key, _) in buckets {
+            if let Some(bucket) = tracked.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    // Takes one of the user's messages for requests that don't go through `rate_limit`, such
+    // as gRPC calls. Fails with how long until they can send another.
+    pub fn take_message(&self, user_id: &str) -> Result<(), Duration> {
+        let Some((allowance, period)) = self.allowance(Limit::Messages) else {
+            return Ok(());
+        };
+        let key = format!("{}:user:{}", Limit::Messages.name(), user_id);
+        self.take(&[(key, allowance)], period, Instant::now())
+    }
+}
+
+// Works out the client's address once for everything after it: login sessions record it,
+// and `rate_limit` keeps a bucket per address
+pub async fn resolve_client_ip(State(limiter): State<RateLimiter>, mut request: Request, next: Next) -> Response {
+    if let Some(ip) = limiter.client_ip(&request) {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+// Limits how often a user, and a client address, can send messages and upload. Runs after
+// `session_middleware` so it knows who is signed in.
+pub async fn rate_limit(State(limiter): State<RateLimiter>, request: Request, next: Next) -> Response {
+    let Some(limit) = Limit::for_request(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let Some((allowance, period)) = limiter.allowance(limit) else {
+        return next.run(request).await;
+    };
+
+    let mut buckets = Vec::new();
+    if let Some(user) = request.extensions().get::<AuthUser>() {
+        buckets.push((format!("{}:user:{}", limit.name(), user.user_id), allowance));
+    }
+    if let Some(ClientIp(ip)) = request.extensions().get::<ClientIp>().copied() {
+        buckets.push((format!("{}:ip:{}", limit.name(), ip), allowance * IP_ALLOWANCE_FACTOR));
+    }
+
+    match limiter.take(&buckets, period, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => too_many_requests(limit, wait),
+    }
+}
+
+fn too_many_requests(limit: Limit, wait: Duration) -> Response {
+    let retry_after_secs = (wait.as_secs_f64().ceil() as u64).max(1);
+    let body = RateLimited {
+        error: "rate_limited".to_string(),
+        message: format!("Too many {}. Try again in {} seconds.", limit.name(), retry_after_secs),
+        retry_after_secs,
+    };
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.to_string())],
+        axum::Json(body),
+    ).into_response()
+}
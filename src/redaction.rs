@@ -0,0 +1,110 @@
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+// Personal data and secrets that are swapped for placeholders, matched in this order so
+// that, say, a key containing digits isn't taken for a phone number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PiiKind {
+    ApiKey,
+    Email,
+    CreditCard,
+    Phone,
+}
+
+impl PiiKind {
+    fn label(self) -> &'static str {
+        match self {
+            PiiKind::ApiKey => "API_KEY",
+            PiiKind::Email => "EMAIL",
+            PiiKind::CreditCard => "CARD",
+            PiiKind::Phone => "PHONE",
+        }
+    }
+}
+
+fn patterns() -> &'static [(PiiKind, Regex)] {
+    static PATTERNS: OnceLock<Vec<(PiiKind, Regex)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // OpenAI, Anthropic, AWS, GitHub, Google and Slack key formats
+            (PiiKind::ApiKey, r"\b(?:sk-(?:ant-|proj-)?[A-Za-z0-9_-]{20,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36,}|AIza[0-9A-Za-z_-]{35}|xox[abpr]-[A-Za-z0-9-]{10,})"),
+            (PiiKind::Email, r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b"),
+            // 13 to 19 digits, optionally grouped; only those passing the Luhn check count
+            (PiiKind::CreditCard, r"\b(?:\d[ -]?){12,18}\d\b"),
+            (PiiKind::Phone, r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)[ .-]?|\b\d{2,4}[ .-])\d{3,4}[ .-]?\d{3,4}\b"),
+        ]
+        .into_iter()
+        .map(|(kind, pattern)| (kind, Regex::new(pattern).expect("Invalid redaction pattern")))
+        .collect()
+    })
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits.iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| if i % 2 == 1 { if digit * 2 > 9 { digit * 2 - 9 } else { digit * 2 } } else { digit })
+        .sum();
+    sum % 10 == 0
+}
+
+// Swaps personal data and secrets in an outgoing prompt for numbered placeholders such as
+// `[EMAIL_1]`, and puts the originals back in the reply. One redactor covers one request,
+// so the same value gets the same placeholder wherever it appears.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    // (placeholder, original)
+    replaced: Vec<(String, String)>,
+}
+
+impl Redactor {
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (kind, pattern) in patterns() {
+            text = pattern.replace_all(&text, |captures: &Captures| {
+                let found = &captures[0];
+                if *kind == PiiKind::CreditCard && !luhn_valid(found) {
+                    return found.to_string();
+                }
+                self.placeholder(*kind, found)
+            }).into_owned();
+        }
+        text
+    }
+
+    pub fn restore(&self, text: &str) -> String {
+        self.replaced.iter().fold(text.to_string(), |text, (placeholder, original)| text.replace(placeholder, original))
+    }
+
+    // How many distinct values were replaced
+    pub fn len(&self) -> usize {
+        self.replaced.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replaced.is_empty()
+    }
+
+    fn placeholder(&mut self, kind: PiiKind, original: &str) -> String {
+        if let Some((placeholder, _)) = self.replaced.iter().find(|(_, seen)| seen == original) {
+            return placeholder.clone();
+        }
+        let prefix = format!("[{}_", kind.label());
+        let number = self.replaced.iter().filter(|(placeholder, _)| placeholder.starts_with(&prefix)).count() + 1;
+        let placeholder = format!("{}{}]", prefix, number);
+        self.replaced.push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+}
+
+// Masks secrets in text bound for the logs: the given credentials wherever they appear, and
+// anything shaped like a known API key. Unlike `Redactor`, nothing can be put back.
+pub fn redact_secrets(text: &str, secrets: &[&str]) -> String {
+    let text = secrets.iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| text.replace(secret, "[REDACTED]"));
+    patterns().iter()
+        .filter(|(kind, _)| *kind == PiiKind::ApiKey)
+        .fold(text, |text, (_, pattern)| pattern.replace_all(&text, "[REDACTED]").into_owned())
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RerankProvider {
+    Cohere,
+    // Any server speaking the text-embeddings-inference `/rerank` API
+    Local,
+}
+
+impl From<String> for RerankProvider {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "cohere" => RerankProvider::Cohere,
+            "local" => RerankProvider::Local,
+            _ => RerankProvider::Local,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RerankerConfig {
+    pub provider: RerankProvider,
+    pub model_name: String,
+    pub api_key: Option<String>,
+    pub base_url: String,
+}
+
+// Cross-encoder reranking: scores each (query, passage) pair jointly, which is slower
+// than vector search but much better at ordering a short candidate list
+#[derive(Clone)]
+pub struct Reranker {
+    config: RerankerConfig,
+    http: reqwest::Client,
+}
+
+impl Reranker {
+    pub fn new(config: RerankerConfig) -> Result<Self> {
+        if config.provider == RerankProvider::Cohere && config.api_key.is_none() {
+            return Err(anyhow::anyhow!("Cohere reranking requires an API key"));
+        }
+
+        Ok(Self {
+            config,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    // Returns one relevance score per passage, in input order; higher is better
+    pub async fn rerank(&self, query: &str, passages: &[String]) -> Result<Vec<f64>> {
+        if passages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let results: Vec<(usize, f64)> = match self.config.provider {
+            RerankProvider::Cohere => {
+                let response: Value = self.http
+                    .post("https://api.cohere.com/v1/rerank")
+                    .bearer_auth(self.config.api_key.as_deref().unwrap_or_default())
+                    .json(&json!({
+                        "model": self.config.model_name,
+                        "query": query,
+                        "documents": passages,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                parse_scores(&response["results"], "relevance_score")?
+            }
+            RerankProvider::Local => {
+                let url = format!("{}/rerank", self.config.base_url.trim_end_matches('/'));
+                let response: Value = self.http
+                    .post(&url)
+                    .json(&json!({
+                        "query": query,
+                        "texts": passages,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                parse_scores(&response, "score")?
+            }
+        };
+
+        let mut scores = vec![f64::MIN; passages.len()];
+        for (index, score) in results {
+            if let Some(slot) = scores.get_mut(index) {
+                *slot = score;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+fn parse_scores(results: &Value, score_field: &str) -> Result<Vec<(usize, f64)>> {
+    results
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Rerank response was not a list of results"))?
+        .iter()
+        .map(|result| {
+            let index = result["index"].as_u64().ok_or_else(|| anyhow::anyhow!("Rerank result missing index"))?;
+            let score = result[score_field].as_f64().ok_or_else(|| anyhow::anyhow!("Rerank result missing score"))?;
+            Ok((index as usize, score))
+        })
+        .collect()
+}
@@ -0,0 +1,843 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+use crate::{api::{self, AppState}, auth::AuthUser, database::OwnedResource, models::*};
+
+// Version 1 of the REST API under `/api/v1`. Unlike the server functions, its requests and
+// responses are part of the public contract: fields are only ever added, and anything else
+// goes in a new version. Callers authenticate with a personal access token.
+pub const OPENAPI_PATH: &str = "/api/v1/openapi.json";
+pub const TRANSCRIPTIONS_PATH: &str = "/api/v1/audio/transcriptions";
+// The largest recording OpenAI's transcription API takes
+pub const MAX_AUDIO_BYTES: usize = 25 * 1024 * 1024;
+pub const MAX_BATCH_BYTES: usize = 20 * 1024 * 1024;
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+// Texts one embeddings request may carry
+const MAX_EMBEDDING_INPUTS: usize = 256;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "aibot API", version = "1"),
+    paths(list_sessions, create_session, delete_session, export_session, list_messages, post_message, create_embeddings, create_transcription,
+        create_batch, list_batches, get_batch, batch_results, list_rules, create_rule, update_rule, delete_rule),
+    components(schemas(
+        SessionV1, SessionList, CreateSessionV1, MessageV1, MessageList, PostMessageV1, MessageReply,
+        EmbeddingsRequestV1, EmbeddingInput, EmbeddingList, EmbeddingV1, EmbeddingVector, EmbeddingUsage,
+        TranscriptionForm, TranscriptionV1, BatchV1, BatchList, BatchResultV1, RuleV1, RuleList, RuleRequestV1, ErrorBody,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+)]
+struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+// The OpenAPI document describing this version of the API
+pub async fn openapi() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+// Errors are sent as `{"error": "..."}`. Records that are missing or belong to someone
+// else are 404, like everywhere else in the API.
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorBody { error: self.1 })).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let message = e.to_string();
+        if message.ends_with("not found") {
+            ApiError(StatusCode::NOT_FOUND, message)
+        } else {
+            tracing::warn!("REST API request failed: {}", message);
+            ApiError(StatusCode::BAD_REQUEST, message)
+        }
+    }
+}
+
+fn bad_request(message: &str) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message.to_string())
+}
+
+// Cursors are opaque to callers: the listing's keyset position as base64 JSON. The gRPC
+// API uses the same ones.
+pub(crate) fn encode_cursor<C: Serialize>(cursor: &C) -> Option<String> {
+    serde_json::to_vec(cursor).ok().map(|json| URL_SAFE_NO_PAD.encode(json))
+}
+
+pub(crate) fn parse_cursor<C: DeserializeOwned>(cursor: &str) -> Option<C> {
+    URL_SAFE_NO_PAD.decode(cursor).ok().and_then(|json| serde_json::from_slice(&json).ok())
+}
+
+fn decode_cursor<C: DeserializeOwned>(cursor: Option<&str>) -> Result<Option<C>, ApiError> {
+    cursor.map(|cursor| parse_cursor(cursor).ok_or_else(|| bad_request("Invalid cursor"))).transpose()
+}
+
+// True for `/api/v1/sessions/<id>/messages`, which sends a message when POSTed to
+pub fn is_message_post(path: &str) -> bool {
+    path.strip_prefix("/api/v1/sessions/")
+        .and_then(|rest| rest.strip_suffix("/messages"))
+        .is_some_and(|session_id| !session_id.is_empty() && !session_id.contains('/'))
+}
+
+pub(crate) fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionV1 {
+    pub id: String,
+    pub title: Option<String>,
+    pub model_provider: String,
+    pub model_name: String,
+    pub incognito: bool,
+    pub archived: bool,
+    pub pinned: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<ChatSession> for SessionV1 {
+    fn from(session: ChatSession) -> Self {
+        Self {
+            id: session.id,
+            title: session.title,
+            model_provider: session.model_provider,
+            model_name: session.model_name,
+            incognito: session.incognito,
+            archived: session.archived,
+            pinned: session.pinned,
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionList {
+    pub sessions: Vec<SessionV1>,
+    // Pass as `cursor` to get the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListSessionsQuery {
+    // Sessions per page, 1 to 200; 50 by default
+    limit: Option<i64>,
+    cursor: Option<String>,
+    #[serde(default)]
+    include_archived: bool,
+}
+
+// Lists the user's sessions, pinned first, then by most recent activity
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions",
+    params(ListSessionsQuery),
+    responses((status = 200, body = SessionList), (status = 400, body = ErrorBody)),
+)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<ListSessionsQuery>,
+) -> Result<Json<SessionList>, ApiError> {
+    let after: Option<SessionCursor> = decode_cursor(query.cursor.as_deref())?;
+    let page = state.db.get_user_sessions(&user.user_id, query.include_archived, None, after.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(SessionList {
+        sessions: page.items.into_iter().map(SessionV1::from).collect(),
+        next_cursor: page.next_cursor.as_ref().and_then(encode_cursor),
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateSessionV1 {
+    pub title: Option<String>,
+    // ollama, openai, anthropic, gemini or openrouter
+    pub model_provider: String,
+    pub model_name: String,
+    #[serde(default)]
+    pub incognito: bool,
+}
+
+// Starts a session
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions",
+    request_body = CreateSessionV1,
+    responses((status = 201, body = SessionV1), (status = 400, body = ErrorBody)),
+)]
+pub async fn create_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(request): Json<CreateSessionV1>,
+) -> Result<(StatusCode, Json<SessionV1>), ApiError> {
+    let model_provider = AIProvider::from(request.model_provider.clone());
+    if model_provider.to_string() != request.model_provider {
+        return Err(bad_request("Unknown model provider"));
+    }
+    let session = api::create_session_as(&state, &user.user_id, request.title, model_provider, request.model_name, request.incognito).await?;
+    Ok((StatusCode::CREATED, Json(SessionV1::from(session))))
+}
+
+// Moves a session to the trash, where it can be restored from the app until it is purged
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sessions/{session_id}",
+    params(("session_id" = String, Path)),
+    responses((status = 204), (status = 404, body = ErrorBody)),
+)]
+pub async fn delete_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    api::delete_session_as(&state, &user.user_id, &session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportSessionQuery {
+    // json, the default, markdown or pdf
+    format: Option<String>,
+}
+
+// Downloads a session's transcript
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{session_id}/export",
+    params(("session_id" = String, Path), ExportSessionQuery),
+    responses((status = 200, description = "The transcript as a file"), (status = 404, body = ErrorBody)),
+)]
+pub async fn export_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+    Query(query): Query<ExportSessionQuery>,
+) -> Result<Response, ApiError> {
+    crate::auth::authorize(&state, &user.user_id, OwnedResource::Session, &session_id).await?;
+    let format = match query.format.as_deref() {
+        None | Some("json") => ExportFormat::Json,
+        Some("markdown") => ExportFormat::Markdown,
+        Some("pdf") => ExportFormat::Pdf,
+        Some(_) => return Err(bad_request("format must be json, markdown or pdf")),
+    };
+    let file = crate::export::export_session(&state, &session_id, format).await?;
+    crate::audit::record(&state, &user.user_id, AuditAction::SessionExported, Some(&session_id), Some(serde_json::json!({ "format": format.to_string() }))).await?;
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, file.content_type),
+            (axum::http::header::CONTENT_DISPOSITION, crate::handlers::content_disposition("attachment", &file.file_name)),
+        ],
+        file.content,
+    ).into_response())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessageV1 {
+    pub id: String,
+    pub session_id: String,
+    // user, assistant or system
+    pub role: String,
+    pub content: String,
+    pub model_provider: Option<String>,
+    pub model_name: Option<String>,
+    pub tokens_used: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Message> for MessageV1 {
+    fn from(message: Message) -> Self {
+        Self {
+            id: message.id,
+            session_id: message.session_id,
+            role: message.role.to_string(),
+            content: message.content,
+            model_provider: message.model_provider,
+            model_name: message.model_name,
+            tokens_used: message.tokens_used,
+            created_at: message.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessageList {
+    // Oldest first
+    pub messages: Vec<MessageV1>,
+    // Pass as `before` to get the page of earlier messages; null when there are none
+    pub next_before: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListMessagesQuery {
+    // Messages per page, 1 to 200; 50 by default
+    limit: Option<i64>,
+    before: Option<String>,
+}
+
+// Gets a session's history, newest page first
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{session_id}/messages",
+    params(("session_id" = String, Path), ListMessagesQuery),
+    responses((status = 200, body = MessageList), (status = 404, body = ErrorBody)),
+)]
+pub async fn list_messages(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+    Query(query): Query<ListMessagesQuery>,
+) -> Result<Json<MessageList>, ApiError> {
+    crate::auth::authorize(&state, &user.user_id, OwnedResource::Session, &session_id).await?;
+    let before: Option<MessageCursor> = decode_cursor(query.before.as_deref())?;
+    let page = state.db.get_session_messages_page(&session_id, before.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(MessageList {
+        messages: page.items.into_iter().map(MessageV1::from).collect(),
+        next_before: page.next_cursor.as_ref().and_then(encode_cursor),
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostMessageV1 {
+    pub content: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessageReply {
+    // The assistant's reply, as saved in the session
+    pub message: MessageV1,
+    pub suggested_questions: Vec<String>,
+    // Set when the content filter flagged the message or reply but let it through
+    pub moderation_warning: Option<String>,
+}
+
+// Sends a message and waits for the reply. Counts toward the same message rate limit as
+// chatting in the app.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions/{session_id}/messages",
+    params(("session_id" = String, Path)),
+    request_body = PostMessageV1,
+    responses((status = 201, body = MessageReply), (status = 400, body = ErrorBody), (status = 404, body = ErrorBody)),
+)]
+pub async fn post_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+    Json(request): Json<PostMessageV1>,
+) -> Result<(StatusCode, Json<MessageReply>), ApiError> {
+    if request.content.trim().is_empty() {
+        return Err(bad_request("Message content is empty"));
+    }
+    let reply = api::send_message_as(&state, &user.user_id, session_id, request.content, Vec::new(), Vec::new(), Vec::new(), false).await?;
+    let message = state.db.get_message(&reply.message_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    Ok((StatusCode::CREATED, Json(MessageReply {
+        message: MessageV1::from(message),
+        suggested_questions: reply.suggested_questions,
+        moderation_warning: reply.moderation_warning,
+    })))
+}
+
+// One text or a list of them, as OpenAI's API takes
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EmbeddingsRequestV1 {
+    pub input: EmbeddingInput,
+    // Accepted for compatibility; texts are always embedded with the server's configured
+    // model, which the response names
+    pub model: Option<String>,
+    // float, the default, or base64 for little-endian 32-bit floats
+    pub encoding_format: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum EmbeddingVector {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EmbeddingV1 {
+    // Always "embedding"
+    pub object: &'static str,
+    // The input's position in the request
+    pub index: usize,
+    pub embedding: EmbeddingVector,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EmbeddingUsage {
+    // Estimated at ~4 characters a token
+    pub prompt_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EmbeddingList {
+    // Always "list"
+    pub object: &'static str,
+    pub data: Vec<EmbeddingV1>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+// Embeds texts with the server's embedding provider, in the shape of OpenAI's embeddings
+// API, so other tools can share the provider the server is set up with
+#[utoipa::path(
+    post,
+    path = "/api/v1/embeddings",
+    request_body = EmbeddingsRequestV1,
+    responses((status = 200, body = EmbeddingList), (status = 400, body = ErrorBody)),
+)]
+pub async fn create_embeddings(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Json(request): Json<EmbeddingsRequestV1>,
+) -> Result<Json<EmbeddingList>, ApiError> {
+    let as_base64 = match request.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(_) => return Err(bad_request("encoding_format must be float or base64")),
+    };
+    let texts = match request.input {
+        EmbeddingInput::One(text) => vec![text],
+        EmbeddingInput::Many(texts) => texts,
+    };
+    if texts.is_empty() || texts.iter().any(|text| text.is_empty()) {
+        return Err(bad_request("Input is empty"));
+    }
+    if texts.len() > MAX_EMBEDDING_INPUTS {
+        return Err(bad_request(&format!("At most {} inputs can be embedded at once", MAX_EMBEDDING_INPUTS)));
+    }
+
+    let embeddings = state.embeddings.embed(&texts).await?;
+    let tokens = crate::usage::estimate_tokens(&texts);
+    let data = embeddings.into_iter().enumerate().map(|(index, embedding)| {
+        let embedding = if as_base64 {
+            let bytes: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+            EmbeddingVector::Base64(STANDARD.encode(bytes))
+        } else {
+            EmbeddingVector::Float(embedding)
+        };
+        EmbeddingV1 { object: "embedding", index, embedding }
+    }).collect();
+
+    Ok(Json(EmbeddingList {
+        object: "list",
+        data,
+        model: state.embeddings.model_name().to_string(),
+        usage: EmbeddingUsage { prompt_tokens: tokens, total_tokens: tokens },
+    }))
+}
+
+// The multipart form a recording is sent in, as OpenAI's transcription API takes it
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct TranscriptionForm {
+    // The recording, in any format Whisper reads, like webm, mp3, m4a or wav
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+    // Accepted for compatibility; recordings are always transcribed with whisper-1
+    model: Option<String>,
+    // json, the default, or text for the transcript alone
+    response_format: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TranscriptionV1 {
+    pub text: String,
+}
+
+// Transcribes a recording with Whisper, the same way voice input in the app is, in the
+// shape of OpenAI's transcription API. Counts toward the upload rate limit.
+#[utoipa::path(
+    post,
+    path = "/api/v1/audio/transcriptions",
+    request_body(content = TranscriptionForm, content_type = "multipart/form-data"),
+    responses((status = 200, body = TranscriptionV1), (status = 400, body = ErrorBody)),
+)]
+pub async fn create_transcription(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    mut form: Multipart,
+) -> Result<Response, ApiError> {
+    let mut audio = None;
+    let mut as_text = false;
+    while let Some(field) = form.next_field().await.map_err(|e| bad_request(&e.body_text()))? {
+        match field.name() {
+            Some("file") => {
+                // Clients that don't know the format send it as octet-stream; the file's
+                // extension says more
+                let content_type = field.content_type()
+                    .filter(|content_type| *content_type != "application/octet-stream")
+                    .map(str::to_string)
+                    .or_else(|| field.file_name().and_then(|name| mime_guess::from_path(name).first_raw()).map(str::to_string))
+                    .unwrap_or_else(|| "audio/webm".to_string());
+                let data = field.bytes().await.map_err(|e| bad_request(&e.body_text()))?;
+                audio = Some((data, content_type));
+            }
+            Some("response_format") => {
+                as_text = match field.text().await.map_err(|e| bad_request(&e.body_text()))?.as_str() {
+                    "json" => false,
+                    "text" => true,
+                    _ => return Err(bad_request("response_format must be json or text")),
+                };
+            }
+            _ => {}
+        }
+    }
+    let (data, content_type) = audio.ok_or_else(|| bad_request("No file in the form"))?;
+    if data.is_empty() {
+        return Err(bad_request("The file is empty"));
+    }
+
+    let text = state.ai_service.transcribe(&data, &content_type, None).await?;
+    Ok(if as_text { text.into_response() } else { Json(TranscriptionV1 { text }).into_response() })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchV1 {
+    pub id: String,
+    pub model_provider: String,
+    pub model_name: String,
+    pub concurrency: i32,
+    // pending, running, completed or failed
+    pub status: String,
+    // Set when the whole batch failed, like when the provider isn't allowed any more
+    pub error: Option<String>,
+    pub total: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl From<BatchJob> for BatchV1 {
+    fn from(job: BatchJob) -> Self {
+        Self {
+            id: job.id,
+            model_provider: job.model_provider,
+            model_name: job.model_name,
+            concurrency: job.concurrency,
+            status: job.status.to_string(),
+            error: job.error,
+            total: job.total,
+            completed: job.completed,
+            failed: job.failed,
+            created_at: job.created_at,
+            finished_at: job.finished_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchList {
+    // Newest first
+    pub batches: Vec<BatchV1>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreateBatchQuery {
+    // ollama, openai, anthropic, gemini or openrouter
+    model_provider: String,
+    model_name: String,
+    // Prompts sent to the model at once, 1 to 16; 4 by default
+    concurrency: Option<i32>,
+}
+
+// Submits a JSONL file of prompts, one `{"custom_id": "...", "prompt": "...",
+// "system_prompt": "..."}` per line, to run against the model in the background
+#[utoipa::path(
+    post,
+    path = "/api/v1/batches",
+    params(CreateBatchQuery),
+    request_body(content = String, content_type = "application/jsonl"),
+    responses((status = 202, body = BatchV1), (status = 400, body = ErrorBody)),
+)]
+pub async fn create_batch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<CreateBatchQuery>,
+    body: String,
+) -> Result<(StatusCode, Json<BatchV1>), ApiError> {
+    let model_provider = AIProvider::from(query.model_provider.clone());
+    if model_provider.to_string() != query.model_provider {
+        return Err(bad_request("Unknown model provider"));
+    }
+    let job = crate::batch::submit(&state, &user.user_id, model_provider, query.model_name, query.concurrency, &body).await?;
+    Ok((StatusCode::ACCEPTED, Json(BatchV1::from(job))))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListBatchesQuery {
+    // Batches to list, 1 to 200; 50 by default
+    limit: Option<i64>,
+}
+
+// Lists the user's batches
+#[utoipa::path(
+    get,
+    path = "/api/v1/batches",
+    params(ListBatchesQuery),
+    responses((status = 200, body = BatchList)),
+)]
+pub async fn list_batches(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<ListBatchesQuery>,
+) -> Result<Json<BatchList>, ApiError> {
+    let jobs = state.db.get_user_batch_jobs(&user.user_id, page_size(query.limit)).await?;
+    Ok(Json(BatchList { batches: jobs.into_iter().map(BatchV1::from).collect() }))
+}
+
+async fn owned_batch(state: &AppState, user: &AuthUser, batch_id: &str) -> Result<BatchJob, ApiError> {
+    Ok(state.db.get_batch_job(batch_id).await?
+        .filter(|job| job.user_id == user.user_id)
+        .ok_or_else(|| anyhow::anyhow!("Batch not found"))?)
+}
+
+// Gets a batch's progress
+#[utoipa::path(
+    get,
+    path = "/api/v1/batches/{batch_id}",
+    params(("batch_id" = String, Path)),
+    responses((status = 200, body = BatchV1), (status = 404, body = ErrorBody)),
+)]
+pub async fn get_batch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(batch_id): Path<String>,
+) -> Result<Json<BatchV1>, ApiError> {
+    Ok(Json(BatchV1::from(owned_batch(&state, &user, &batch_id).await?)))
+}
+
+// A line of a batch's results file
+#[derive(Serialize, ToSchema)]
+pub struct BatchResultV1 {
+    pub custom_id: String,
+    // pending, completed or failed
+    pub status: String,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub tokens_used: Option<i32>,
+}
+
+// Downloads the results as JSONL, a line per prompt in the order they were submitted.
+// Prompts that haven't run yet are listed as pending, so results can be fetched early.
+#[utoipa::path(
+    get,
+    path = "/api/v1/batches/{batch_id}/results",
+    params(("batch_id" = String, Path)),
+    responses((status = 200, body = BatchResultV1, content_type = "application/jsonl"), (status = 404, body = ErrorBody)),
+)]
+pub async fn batch_results(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(batch_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let job = owned_batch(&state, &user, &batch_id).await?;
+    let mut body = String::new();
+    for item in state.db.get_batch_items(&job.id, false).await? {
+        let result = BatchResultV1 {
+            custom_id: item.custom_id,
+            status: item.status.to_string(),
+            output: item.output,
+            error: item.error,
+            tokens_used: item.tokens_used,
+        };
+        body.push_str(&serde_json::to_string(&result).map_err(anyhow::Error::from)?);
+        body.push('\n');
+    }
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "application/jsonl".to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"batch-{}.jsonl\"", job.id)),
+        ],
+        body,
+    ).into_response())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RuleV1 {
+    pub id: String,
+    pub name: String,
+    // message_created or session_created
+    pub trigger: String,
+    // `{"role": "user", "contains": "TODO", "pattern": "..."}`; every part is optional
+    #[schema(value_type = Object)]
+    pub condition: RuleCondition,
+    // `{"type": "webhook", "url": "..."}`, `{"type": "email"}`, `{"type": "tag_session", "tag": "..."}`
+    // or `{"type": "run_prompt", "prompt": "..."}`
+    #[schema(value_type = Object)]
+    pub action: RuleAction,
+    pub enabled: bool,
+    pub last_fired_at: Option<DateTime<Utc>>,
+    // Why the action failed the last time it ran, if it did
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Rule> for RuleV1 {
+    fn from(rule: Rule) -> Self {
+        Self {
+            id: rule.id,
+            name: rule.name,
+            trigger: rule.trigger.to_string(),
+            condition: rule.condition,
+            action: rule.action,
+            enabled: rule.enabled,
+            last_fired_at: rule.last_fired_at,
+            last_error: rule.last_error,
+            created_at: rule.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RuleList {
+    // Oldest first
+    pub rules: Vec<RuleV1>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RuleRequestV1 {
+    pub name: String,
+    pub trigger: String,
+    // Matches every event when left out
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub condition: RuleCondition,
+    #[schema(value_type = Object)]
+    pub action: RuleAction,
+    // True when left out
+    pub enabled: Option<bool>,
+}
+
+fn rule_trigger(trigger: &str) -> Result<RuleTrigger, ApiError> {
+    RuleTrigger::parse(trigger).ok_or_else(|| bad_request("Trigger must be message_created or session_created"))
+}
+
+// Lists the user's rules
+#[utoipa::path(
+    get,
+    path = "/api/v1/rules",
+    responses((status = 200, body = RuleList)),
+)]
+pub async fn list_rules(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RuleList>, ApiError> {
+    let rules = state.db.get_user_rules(&user.user_id).await?;
+    Ok(Json(RuleList { rules: rules.into_iter().map(RuleV1::from).collect() }))
+}
+
+// Adds a rule that runs its action whenever its trigger happens and the condition matches
+#[utoipa::path(
+    post,
+    path = "/api/v1/rules",
+    request_body = RuleRequestV1,
+    responses((status = 201, body = RuleV1), (status = 400, body = ErrorBody)),
+)]
+pub async fn create_rule(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(request): Json<RuleRequestV1>,
+) -> Result<(StatusCode, Json<RuleV1>), ApiError> {
+    let rule = Rule {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user.user_id,
+        name: request.name.trim().to_string(),
+        trigger: rule_trigger(&request.trigger)?,
+        condition: request.condition,
+        action: request.action,
+        enabled: request.enabled.unwrap_or(true),
+        last_fired_at: None,
+        last_error: None,
+        created_at: Utc::now(),
+    };
+    let rule = crate::rules::create(&state, rule).await?;
+    Ok((StatusCode::CREATED, Json(RuleV1::from(rule))))
+}
+
+// Replaces a rule's name, trigger, condition, action and whether it's enabled
+#[utoipa::path(
+    put,
+    path = "/api/v1/rules/{rule_id}",
+    params(("rule_id" = String, Path)),
+    request_body = RuleRequestV1,
+    responses((status = 200, body = RuleV1), (status = 400, body = ErrorBody), (status = 404, body = ErrorBody)),
+)]
+pub async fn update_rule(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(rule_id): Path<String>,
+    Json(request): Json<RuleRequestV1>,
+) -> Result<Json<RuleV1>, ApiError> {
+    crate::auth::authorize(&state, &user.user_id, OwnedResource::Rule, &rule_id).await?;
+    let mut rule = state.db.get_rule(&rule_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Rule not found"))?;
+    rule.name = request.name.trim().to_string();
+    rule.trigger = rule_trigger(&request.trigger)?;
+    rule.condition = request.condition;
+    rule.action = request.action;
+    rule.enabled = request.enabled.unwrap_or(true);
+    crate::rules::update(&state, &rule).await?;
+    Ok(Json(RuleV1::from(rule)))
+}
+
+// Deletes a rule
+#[utoipa::path(
+    delete,
+    path = "/api/v1/rules/{rule_id}",
+    params(("rule_id" = String, Path)),
+    responses((status = 204), (status = 404, body = ErrorBody)),
+)]
+pub async fn delete_rule(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(rule_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    crate::auth::authorize(&state, &user.user_id, OwnedResource::Rule, &rule_id).await?;
+    state.db.delete_rule(&rule_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
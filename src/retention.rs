@@ -0,0 +1,63 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use crate::{api::AppState, models::*};
+
+// The instance policy tightened by the user's own settings, if they have any
+pub async fn effective_policy(state: &AppState, user_id: &str) -> Result<RetentionPolicy> {
+    Ok(match state.db.get_retention_policy(user_id).await? {
+        Some(user_policy) => state.retention_policy.stricter(user_policy),
+        None => state.retention_policy,
+    })
+}
+
+// Applies the user's retention policy. Expired messages and excess sessions are moved to
+// the trash, where the trash retention period takes over. A dry run only reports them.
+pub async fn apply(state: &AppState, user_id: &str, dry_run: bool) -> Result<RetentionReport> {
+    let policy = effective_policy(state, user_id).await?;
+    let now = Utc::now();
+
+    let mut report = RetentionReport {
+        user_id: user_id.to_string(),
+        dry_run,
+        policy,
+        ..Default::default()
+    };
+
+    if let Some(max_sessions) = policy.max_sessions {
+        report.excess_sessions = state.db.get_sessions_by_activity(user_id).await?
+            .into_iter()
+            .skip(max_sessions.max(0) as usize)
+            .filter(|session| !session.pinned)
+            .collect();
+        if !dry_run {
+            for session in &report.excess_sessions {
+                state.db.set_session_deleted(&session.id, Some(now)).await?;
+            }
+        }
+    }
+
+    // In a real run excess sessions are already trashed here, so their messages aren't
+    // counted again; a dry run counts them under both
+    if let Some(days) = policy.message_retention_days {
+        let cutoff = now - Duration::days(days);
+        report.expired_messages = if dry_run {
+            state.db.count_messages_before(user_id, cutoff).await?
+        } else {
+            state.db.trash_messages_before(user_id, cutoff, now).await? as i64
+        };
+    }
+
+    Ok(report)
+}
+
+// Runs `apply` for every user with sessions. Reports with nothing to remove are left out.
+pub async fn apply_all(state: &AppState, dry_run: bool) -> Result<Vec<RetentionReport>> {
+    let mut reports = Vec::new();
+    for user_id in state.db.get_users_with_sessions().await? {
+        let report = apply(state, &user_id, dry_run).await?;
+        if report.expired_messages > 0 || !report.excess_sessions.is_empty() {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use chrono::Utc;
+use regex::RegexBuilder;
+use crate::{api::{self, AppState}, models::*};
+
+// Rules one user may have
+pub const MAX_RULES: usize = 50;
+const MAX_NAME_LENGTH: usize = 100;
+// Compiled size allowed for a condition's regular expression
+const MAX_PATTERN_BYTES: usize = 256 * 1024;
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+// Longest error kept on a rule
+const MAX_ERROR_LENGTH: usize = 500;
+
+pub const RULE_HEADER: &str = "X-Aibot-Rule";
+
+tokio::task_local! {
+    // Set while a rule's action runs, so what it does, like the reply to a prompt it sends,
+    // doesn't set rules off in turn
+    static RUNNING_RULE: ();
+}
+
+// What happened, as rules see it
+pub struct RuleEvent<'a> {
+    pub trigger: RuleTrigger,
+    pub session: &'a ChatSession,
+    // The message saved, for `MessageCreated`
+    pub message: Option<&'a Message>,
+}
+
+impl RuleEvent<'_> {
+    // The text a condition is matched against
+    fn text(&self) -> &str {
+        match self.message {
+            Some(message) => &message.content,
+            None => self.session.title.as_deref().unwrap_or(""),
+        }
+    }
+
+    // What the user is emailed
+    fn summary(&self) -> String {
+        match self.message {
+            Some(message) => message.content.clone(),
+            None => format!("A new session was started: {}", self.session.title.as_deref().unwrap_or("Untitled chat")),
+        }
+    }
+
+    fn payload(&self, rule: &Rule) -> serde_json::Value {
+        serde_json::json!({
+            "rule": { "id": rule.id, "name": rule.name },
+            "trigger": self.trigger.to_string(),
+            "session": { "id": self.session.id, "title": self.session.title },
+            "message": self.message,
+            "created_at": Utc::now(),
+        })
+    }
+}
+
+// Checks a rule before it's saved, so a broken one is turned down rather than failing
+// every time it fires
+async fn validate(state: &AppState, rule: &Rule) -> Result<()> {
+    let name = rule.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(anyhow::anyhow!("Rule names must be 1 to {} characters", MAX_NAME_LENGTH));
+    }
+
+    let condition = &rule.condition;
+    if let Some(role) = &condition.role {
+        if rule.trigger != RuleTrigger::MessageCreated {
+            return Err(anyhow::anyhow!("Only message_created rules can match a role"));
+        }
+        if !["user", "assistant", "system"].contains(&role.as_str()) {
+            return Err(anyhow::anyhow!("Role must be user, assistant or system"));
+        }
+    }
+    if condition.contains.as_ref().is_some_and(|text| text.trim().is_empty()) {
+        return Err(anyhow::anyhow!("The text to look for is empty"));
+    }
+    if let Some(pattern) = &condition.pattern {
+        RegexBuilder::new(pattern).size_limit(MAX_PATTERN_BYTES).build()
+            .map_err(|e| anyhow::anyhow!("Invalid pattern: {}", e))?;
+    }
+
+    match &rule.action {
+        RuleAction::Webhook { url } => {
+            let parsed = reqwest::Url::parse(url).map_err(|_| anyhow::anyhow!("Invalid webhook URL"))?;
+            crate::outbound::check_url(&parsed).await?;
+        }
+        RuleAction::Email => {
+            if state.mailer.is_none() {
+                return Err(anyhow::anyhow!("Emailing needs SMTP_HOST"));
+            }
+            let user = state.db.get_user(&rule.user_id).await?
+                .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+            if user.email.is_none() {
+                return Err(anyhow::anyhow!("Add an email address to your account to be emailed"));
+            }
+        }
+        RuleAction::TagSession { tag } => {
+            if tag.trim().is_empty() {
+                return Err(anyhow::anyhow!("Tag name can't be empty"));
+            }
+        }
+        RuleAction::RunPrompt { prompt } => {
+            if prompt.trim().is_empty() {
+                return Err(anyhow::anyhow!("The prompt is empty"));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks and saves a new rule
+pub async fn create(state: &AppState, rule: Rule) -> Result<Rule> {
+    if state.db.get_user_rules(&rule.user_id).await?.len() >= MAX_RULES {
+        return Err(anyhow::anyhow!("You can have at most {} rules", MAX_RULES));
+    }
+    validate(state, &rule).await?;
+    state.db.create_rule(&rule).await?;
+    Ok(rule)
+}
+
+// Checks and saves changes to a rule
+pub async fn update(state: &AppState, rule: &Rule) -> Result<()> {
+    validate(state, rule).await?;
+    state.db.update_rule(rule).await
+}
+
+fn matches(condition: &RuleCondition, event: &RuleEvent<'_>) -> bool {
+    if let Some(role) = &condition.role {
+        if !event.message.is_some_and(|message| message.role.to_string() == *role) {
+            return false;
+        }
+    }
+    let text = event.text();
+    if let Some(contains) = &condition.contains {
+        if !text.to_lowercase().contains(&contains.to_lowercase()) {
+            return false;
+        }
+    }
+    if let Some(pattern) = &condition.pattern {
+        match RegexBuilder::new(pattern).size_limit(MAX_PATTERN_BYTES).build() {
+            Ok(pattern) if pattern.is_match(text) => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+// Runs the actions of the user's rules the event matches, in the background. Like webhooks,
+// a rule never fails the request that set it off; failures are recorded on the rule.
+pub async fn fire(state: &AppState, event: RuleEvent<'_>) {
+    if RUNNING_RULE.try_with(|_| ()).is_ok() {
+        return;
+    }
+    let rules = match state.db.get_triggered_rules(&event.session.user_id, event.trigger).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            tracing::error!("Failed to load rules: {}", e);
+            return;
+        }
+    };
+    for rule in rules.into_iter().filter(|rule| matches(&rule.condition, &event)) {
+        let (payload, summary) = (event.payload(&rule), event.summary());
+        let session_id = event.session.id.clone();
+        let state = state.clone();
+        state.shutdown.clone().spawn(RUNNING_RULE.scope((), async move {
+            let error = run(&state, &rule, &session_id, &payload, &summary).await.err()
+                .map(|e| e.to_string().chars().take(MAX_ERROR_LENGTH).collect::<String>());
+            if let Some(error) = &error {
+                tracing::warn!("Rule {} failed: {}", rule.id, error);
+            }
+            if let Err(e) = state.db.record_rule_run(&rule.id, Utc::now(), error.as_deref()).await {
+                tracing::error!("Failed to record run of rule {}: {}", rule.id, e);
+            }
+        }));
+    }
+}
+
+async fn run(state: &AppState, rule: &Rule, session_id: &str, payload: &serde_json::Value, summary: &str) -> Result<()> {
+    match &rule.action {
+        RuleAction::Webhook { url } => {
+            let response = crate::outbound::client()
+                .post(url)
+                .timeout(std::time::Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+                .header(RULE_HEADER, &rule.id)
+                .json(payload)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(anyhow::anyhow!("Endpoint answered {}", response.status().as_u16()));
+            }
+        }
+        RuleAction::Email => {
+            let mailer = state.mailer.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Emailing needs SMTP_HOST"))?;
+            let user = state.db.get_user(&rule.user_id).await?
+                .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+            let email = user.email
+                .ok_or_else(|| anyhow::anyhow!("The user has no email address"))?;
+            mailer.send(&email, &format!("Rule \"{}\" fired", rule.name), summary).await?;
+        }
+        RuleAction::TagSession { tag } => {
+            let tag = state.db.get_or_create_tag(&rule.user_id, &tag.trim().to_lowercase()).await?;
+            state.db.add_session_tag(session_id, &tag.id).await?;
+            api::sessions_changed(state, &rule.user_id);
+        }
+        RuleAction::RunPrompt { prompt } => {
+            api::send_message_as(state, &rule.user_id, session_id.to_string(), prompt.clone(), Vec::new(), Vec::new(), Vec::new(), false).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::{account, api::AppState, attachment_gc, connectors, conversation_search, crawler, feeds, guests, login_throttle, retention, schedules, trash, usage_stats, webhooks};
+
+const SCHEDULER_TICK_SECS: u64 = 60;
+// Retention works in days, so checking hourly is plenty
+const RETENTION_INTERVAL_MINUTES: i64 = 60;
+const USAGE_ROLLUP_INTERVAL_MINUTES: i64 = 60;
+// Orphans only appear when something went wrong, so a daily sweep is enough
+const ATTACHMENT_GC_INTERVAL_MINUTES: i64 = 24 * 60;
+
+// Periodically sends scheduled prompts that are due, re-syncs every connector, crawl
+// source and feed whose interval has elapsed, writes daily feed digests, indexes new
+// messages for conversation search, rolls up usage statistics, applies retention policies,
+// purges expired trash, login sessions, old failed sign-ins, abandoned guests and accounts
+// past their deletion grace period, retries webhook deliveries and removes orphaned
+// attachments. On shutdown, the round under way is finished first.
+pub fn spawn(state: AppState) {
+    state.shutdown.clone().spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(SCHEDULER_TICK_SECS));
+        let mut last_retention_run = None;
+        let mut last_usage_rollup = None;
+        let mut last_attachment_gc = None;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.triggered() => break,
+            }
+
+            if let Err(e) = schedules::run_due(&state).await {
+                tracing::error!("Failed to start scheduled prompts: {}", e);
+            }
+
+            if let Err(e) = conversation_search::index_pending_messages(&state).await {
+                tracing::error!("Failed to index messages for search: {}", e);
+            }
+
+            match state.db.get_connected_connectors().await {
+                Ok(connectors) => {
+                    for connector in connectors {
+                        if is_due(connector.last_synced_at, connector.sync_interval_minutes) {
+                            if let Err(e) = connectors::start_sync(&state, connector).await {
+                                tracing::error!("Failed to queue connector sync: {}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Scheduler failed to load connectors: {}", e),
+            }
+
+            match state.db.get_scheduled_crawl_sources().await {
+                Ok(sources) => {
+                    for source in sources {
+                        let Some(minutes) = source.sync_interval_minutes else { continue };
+                        if is_due(source.last_synced_at, minutes) {
+                            if let Err(e) = crawler::start_crawl(&state, source).await {
+                                tracing::error!("Failed to queue re-crawl: {}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Scheduler failed to load crawl sources: {}", e),
+            }
+
+            match state.db.get_feed_sources().await {
+                Ok(sources) => {
+                    for source in sources {
+                        if is_due(source.last_synced_at, source.sync_interval_minutes) {
+                            if let Err(e) = feeds::start_poll(&state, source).await {
+                                tracing::error!("Failed to queue feed poll: {}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Scheduler failed to load feed sources: {}", e),
+            }
+            if let Err(e) = feeds::send_due_digests(&state).await {
+                tracing::error!("Failed to start feed digests: {}", e);
+            }
+
+            if is_due(last_usage_rollup, USAGE_ROLLUP_INTERVAL_MINUTES) {
+                last_usage_rollup = Some(Utc::now());
+                if let Err(e) = usage_stats::roll_up(&state).await {
+                    tracing::error!("Failed to roll up usage statistics: {}", e);
+                }
+            }
+
+            if is_due(last_retention_run, RETENTION_INTERVAL_MINUTES) {
+                last_retention_run = Some(Utc::now());
+                match retention::apply_all(&state, state.retention_dry_run).await {
+                    Ok(reports) => {
+                        for report in reports {
+                            tracing::info!(
+                                "Retention{} for {}: {} expired messages, {} excess sessions",
+                                if report.dry_run { " (dry run)" } else { "" },
+                                report.user_id,
+                                report.expired_messages,
+                                report.excess_sessions.len(),
+                            );
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to apply retention policies: {}", e),
+                }
+            }
+
+            if let Err(e) = trash::purge_expired(&state, state.trash_retention_days).await {
+                tracing::error!("Failed to purge expired trash: {}", e);
+            }
+
+            if let Err(e) = state.db.delete_expired_auth_sessions(Utc::now()).await {
+                tracing::error!("Failed to remove expired login sessions: {}", e);
+            }
+            if let Err(e) = login_throttle::remove_expired(&state).await {
+                tracing::error!("Failed to remove old failed sign-ins: {}", e);
+            }
+            match guests::remove_abandoned(&state).await {
+                Ok(removed) if removed > 0 => tracing::info!("Removed {} abandoned guests", removed),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to remove abandoned guests: {}", e),
+            }
+            match account::purge_due(&state).await {
+                Ok(purged) if purged > 0 => tracing::info!("Deleted {} accounts past their grace period", purged),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to delete accounts: {}", e),
+            }
+
+            if let Err(e) = webhooks::retry_due(&state).await {
+                tracing::error!("Failed to retry webhook deliveries: {}", e);
+            }
+
+            if is_due(last_attachment_gc, ATTACHMENT_GC_INTERVAL_MINUTES) {
+                last_attachment_gc = Some(Utc::now());
+                match attachment_gc::collect(&state).await {
+                    Ok(report) if report.orphaned_attachments > 0 || report.removed_files > 0 => {
+                        tracing::info!(
+                            "Removed {} orphaned attachments and {} unreferenced files, reclaiming {} bytes",
+                            report.orphaned_attachments,
+                            report.removed_files,
+                            report.reclaimed_bytes,
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to collect orphaned attachments: {}", e),
+                }
+            }
+        }
+    });
+}
+
+fn is_due(last_synced_at: Option<DateTime<Utc>>, interval_minutes: i64) -> bool {
+    last_synced_at
+        .map(|at| at + Duration::minutes(interval_minutes) <= Utc::now())
+        .unwrap_or(true)
+}
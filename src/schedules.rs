@@ -0,0 +1,125 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use crate::{api::{self, AppState}, crawler, models::*};
+
+// The most often a schedule may run
+pub const MIN_INTERVAL_MINUTES: i64 = 15;
+// Text of a fetched page beyond this many characters is left out of the prompt
+const MAX_PAGE_CHARS: usize = 50_000;
+// Session titles are cut to this length for the notification's subject
+const MAX_SUBJECT_LENGTH: usize = 80;
+
+// Checks and saves a new schedule for one of the user's sessions
+#[allow(clippy::too_many_arguments)]
+pub async fn create(
+    state: &AppState,
+    user_id: &str,
+    session_id: String,
+    prompt: String,
+    fetch_url: Option<String>,
+    interval_minutes: i64,
+    first_run_at: DateTime<Utc>,
+    notify: bool,
+) -> Result<Schedule> {
+    let user = state.db.get_user(user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    if user.is_anonymous_guest() {
+        return Err(anyhow::anyhow!("Guests can't schedule prompts; create an account first"));
+    }
+    if prompt.trim().is_empty() {
+        return Err(anyhow::anyhow!("The prompt is empty"));
+    }
+    if interval_minutes < MIN_INTERVAL_MINUTES {
+        return Err(anyhow::anyhow!("A schedule can run at most every {} minutes", MIN_INTERVAL_MINUTES));
+    }
+    let fetch_url = fetch_url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+    if let Some(url) = &fetch_url {
+        let parsed = Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?;
+        crate::outbound::check_url(&parsed).await?;
+    }
+    if notify {
+        if state.mailer.is_none() {
+            return Err(anyhow::anyhow!("Emailing replies needs SMTP_HOST"));
+        }
+        if user.email.is_none() {
+            return Err(anyhow::anyhow!("Add an email address to your account to be emailed replies"));
+        }
+    }
+
+    let schedule = Schedule::new(user_id.to_string(), session_id, prompt, fetch_url, interval_minutes, first_run_at, notify);
+    state.db.create_schedule(&schedule).await?;
+    Ok(schedule)
+}
+
+// The first run after `now` on the schedule's interval. Runs missed while the server was
+// down are skipped rather than all sent at once.
+pub fn next_run(schedule: &Schedule, now: DateTime<Utc>) -> DateTime<Utc> {
+    let interval = Duration::minutes(schedule.interval_minutes.max(MIN_INTERVAL_MINUTES));
+    let mut next = schedule.next_run_at;
+    while next <= now {
+        next += interval;
+    }
+    next
+}
+
+// Starts every schedule that is due. Each is moved on to its next run first, so one still
+// running on the next tick isn't started twice.
+pub async fn run_due(state: &AppState) -> Result<()> {
+    let now = Utc::now();
+    for schedule in state.db.get_due_schedules(now).await? {
+        if !state.db.advance_schedule(&schedule.id, schedule.next_run_at, next_run(&schedule, now)).await? {
+            continue;
+        }
+        let state = state.clone();
+        state.shutdown.clone().spawn(async move {
+            let error = run(&state, &schedule).await.err().map(|e| e.to_string());
+            if let Some(error) = &error {
+                tracing::error!("Scheduled prompt {} failed: {}", schedule.id, error);
+            }
+            if let Err(e) = state.db.record_schedule_run(&schedule.id, Utc::now(), error.as_deref()).await {
+                tracing::error!("Failed to record run of scheduled prompt {}: {}", schedule.id, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Sends the prompt to its session, with the page it reads attached, and emails the reply
+// if asked to
+async fn run(state: &AppState, schedule: &Schedule) -> Result<()> {
+    let mut files = Vec::new();
+    if let Some(url) = &schedule.fetch_url {
+        let (title, text) = crawler::fetch_page_text(url).await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch {}: {}", url, e))?;
+        let text: String = text.chars().take(MAX_PAGE_CHARS).collect();
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_else(|| "page".to_string());
+        files.push(FileUpload {
+            name: format!("{}.txt", host),
+            content_type: "text/plain".to_string(),
+            data: format!("{}\n{}\n\n{}", title, url, text).into_bytes(),
+            transcript: None,
+        });
+    }
+
+    let response = api::send_message_as(state, &schedule.user_id, schedule.session_id.clone(), schedule.prompt.clone(), files, Vec::new(), Vec::new(), false).await?;
+
+    if schedule.notify {
+        notify(state, schedule, &response.content).await?;
+    }
+    Ok(())
+}
+
+async fn notify(state: &AppState, schedule: &Schedule, reply: &str) -> Result<()> {
+    let mailer = state.mailer.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Emailing replies needs SMTP_HOST"))?;
+    let user = state.db.get_user(&schedule.user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("User not found"))?;
+    let email = user.email
+        .ok_or_else(|| anyhow::anyhow!("The user has no email address to notify"))?;
+    let title = state.db.get_session(&schedule.session_id).await?
+        .and_then(|session| session.title)
+        .unwrap_or_else(|| "Scheduled prompt".to_string());
+    let subject = title.chars().take(MAX_SUBJECT_LENGTH).collect::<String>();
+    mailer.send(&email, &subject, reply).await
+}
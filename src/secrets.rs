@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use sha2::{Digest, Sha256};
+use crate::api::AppState;
+
+const NONCE_LEN: usize = 24;
+
+// Seals secrets such as provider API keys before they are written to the database, with
+// the instance's master key (SECRETS_KEY, 32 bytes as 64 hex characters)
+#[derive(Clone)]
+pub struct SecretBox {
+    cipher: XChaCha20Poly1305,
+}
+
+impl SecretBox {
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key = decode_hex(key.trim())
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| anyhow::anyhow!("SECRETS_KEY must be 64 hex characters"))?;
+        Ok(Self { cipher: XChaCha20Poly1305::new_from_slice(&key)? })
+    }
+
+    // Hex of a random nonce followed by the ciphertext
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt secret"))?;
+        Ok(encode_hex(&[nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<String> {
+        let bytes = decode_hex(sealed)
+            .filter(|bytes| bytes.len() > NONCE_LEN)
+            .ok_or_else(|| anyhow::anyhow!("Stored secret is corrupt"))?;
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt secret; was SECRETS_KEY changed?"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+// The instance's SecretBox, failing when no SECRETS_KEY is set and nothing can be sealed
+pub fn secret_box(state: &AppState) -> Result<&SecretBox> {
+    state.secrets.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Storing API keys is not enabled on this server; SECRETS_KEY must be set"))
+}
+
+// A short, stable identifier for a secret that reveals nothing about it
+pub fn fingerprint(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))[..12].to_string()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use crate::{database::Database, models::*};
+
+// The UI always acts as the default user, so the demo data belongs to it
+pub const DEMO_USER_ID: &str = DEFAULT_USER_ID;
+pub const DEMO_EMAIL: &str = "demo@example.com";
+pub const DEMO_PASSWORD: &str = "demo-password";
+
+// Every row is dated from here and keyed by a fixed id, so each run produces the same data
+const DEMO_EPOCH: (i32, u32, u32) = (2025, 1, 6);
+
+struct DemoSession {
+    id: &'static str,
+    title: &'static str,
+    provider: AIProvider,
+    model_name: &'static str,
+    pinned: bool,
+    // Alternating user and assistant turns, starting with the user
+    turns: &'static [&'static str],
+    suggested_questions: &'static [&'static str],
+}
+
+const DEMO_SESSIONS: &[DemoSession] = &[
+    DemoSession {
+        id: "demo-session-lisbon",
+        title: "Long weekend in Lisbon",
+        provider: AIProvider::OpenAI,
+        model_name: "gpt-4",
+        pinned: true,
+        turns: &[
+            "I have three days in Lisbon in April. What should I not miss?",
+            "Three days is a good fit for Lisbon. A loose plan:\n\n\
+             1. **Day 1: Alfama and Baixa.** Walk up to the Castelo de São Jorge early, then wander down through Alfama. Catch a fado show in the evening.\n\
+             2. **Day 2: Belém.** Jerónimos Monastery, the Belém Tower and a pastel de nata at Pastéis de Belém. Finish at the LX Factory.\n\
+             3. **Day 3: Sintra.** Take the train from Rossio (about 40 minutes) for Pena Palace and Quinta da Regaleira.\n\n\
+             April is mild, but the hills are steep, so bring comfortable shoes.",
+            "Is tram 28 worth it or just a tourist trap?",
+            "Both, honestly. The route through Alfama and Graça is beautiful, but the trams are packed from mid-morning. \
+             Board at Martim Moniz before 9am and you'll usually get a seat. A 24-hour transit pass covers it.",
+        ],
+        suggested_questions: &[
+            "Where should I stay in Lisbon?",
+            "What are good day trips besides Sintra?",
+            "Which fado venues are less touristy?",
+        ],
+    },
+    DemoSession {
+        id: "demo-session-rust",
+        title: "Rust lifetimes explained",
+        provider: AIProvider::Anthropic,
+        model_name: "claude-3-sonnet-20240229",
+        pinned: false,
+        turns: &[
+            "Why does this not compile?\n\n```rust\nfn longest(a: &str, b: &str) -> &str {\n    if a.len() > b.len() { a } else { b }\n}\n```",
+            "The compiler can't tell which input the returned reference borrows from, so it can't check that the result \
+             outlives its use. Name the relationship with a lifetime parameter:\n\n\
+             ```rust\nfn longest<'a>(a: &'a str, b: &'a str) -> &'a str {\n    if a.len() > b.len() { a } else { b }\n}\n```\n\n\
+             This says the result lives at least as long as the shorter of the two inputs.",
+            "And when can I leave lifetimes out?",
+            "When the elision rules make them unambiguous:\n\n\
+             - Each reference parameter gets its own lifetime.\n\
+             - With exactly one input lifetime, it is used for every output.\n\
+             - In methods taking `&self` or `&mut self`, outputs borrow from `self`.\n\n\
+             `longest` has two inputs and no `self`, so none of the rules apply.",
+        ],
+        suggested_questions: &[
+            "What does 'static mean?",
+            "How do lifetimes work in structs?",
+        ],
+    },
+    DemoSession {
+        id: "demo-session-meals",
+        title: "Vegetarian meal prep",
+        provider: AIProvider::Ollama,
+        model_name: "llama2",
+        pinned: false,
+        turns: &[
+            "Give me a vegetarian meal prep plan for the work week, under an hour of cooking on Sunday.",
+            "Here's a plan built from three batch-cooked bases:\n\n\
+             | Base | Used in |\n|---|---|\n\
+             | Roasted chickpeas and vegetables | Grain bowls (Mon, Wed) |\n\
+             | Red lentil dal | With rice (Tue, Thu) |\n\
+             | Overnight oats | Breakfast every day |\n\n\
+             Roast the vegetables while the dal simmers and you'll be done in about 50 minutes. Friday is left free for eating out.",
+        ],
+        suggested_questions: &[
+            "Can you make a shopping list for this?",
+            "How long does the dal keep in the fridge?",
+        ],
+    },
+];
+
+const DEMO_MEMORIES: &[(&str, &str)] = &[
+    ("name", "Alex"),
+    ("diet", "vegetarian"),
+    ("programming_language", "Rust"),
+    ("home_city", "Berlin"),
+];
+
+fn demo_epoch() -> DateTime<Utc> {
+    let (year, month, day) = DEMO_EPOCH;
+    Utc.with_ymd_and_hms(year, month, day, 9, 0, 0).unwrap()
+}
+
+// Fills the database with a demo user, sessions, memories and suggested questions for local
+// development and screenshots, without calling any provider. Existing demo sessions are
+// replaced, so running it again resets them.
+pub async fn seed_demo(db: &Database) -> Result<()> {
+    let epoch = demo_epoch();
+
+    if db.get_user(DEMO_USER_ID).await?.is_none() {
+        db.create_user(&User {
+            id: DEMO_USER_ID.to_string(),
+            name: Some("Demo User".to_string()),
+            email: Some(DEMO_EMAIL.to_string()),
+            org_id: Some(DEFAULT_ORG_ID.to_string()),
+            role: Role::Member,
+            created_at: epoch,
+            updated_at: epoch,
+        }).await?;
+    }
+    // Keep a password someone already set for the user
+    if db.get_password_hash(DEMO_USER_ID).await?.is_none() {
+        db.set_password_hash(DEMO_USER_ID, &crate::auth::hash_password(DEMO_PASSWORD)?).await?;
+    }
+
+    for (i, demo) in DEMO_SESSIONS.iter().enumerate() {
+        db.delete_session(demo.id).await?;
+
+        let started = epoch + Duration::days(i as i64);
+        let mut session = ChatSession::new(DEMO_USER_ID.to_string(), demo.provider.clone(), demo.model_name.to_string());
+        session.id = demo.id.to_string();
+        session.title = Some(demo.title.to_string());
+        session.pinned = demo.pinned;
+        session.created_at = started;
+
+        let mut messages = Vec::new();
+        for (turn, content) in demo.turns.iter().enumerate() {
+            let role = if turn % 2 == 0 { MessageRole::User } else { MessageRole::Assistant };
+            let mut message = Message::new(demo.id.to_string(), role.clone(), content.to_string());
+            message.id = format!("{}-message-{}", demo.id, turn + 1);
+            message.created_at = started + Duration::minutes(turn as i64 * 2);
+            if let MessageRole::Assistant = role {
+                message.model_provider = Some(session.model_provider.clone());
+                message.model_name = Some(session.model_name.clone());
+                // Roughly four characters per token
+                message.tokens_used = Some((content.len() / 4) as i32);
+            }
+            messages.push(message);
+        }
+
+        let last = messages.last().expect("demo sessions have messages");
+        session.updated_at = last.created_at;
+        session.last_message_preview = Some(message_preview(&last.content));
+
+        db.create_session(&session).await?;
+        for message in &messages {
+            db.create_message(message).await?;
+        }
+
+        let questions: Vec<SuggestedQuestion> = demo.suggested_questions
+            .iter()
+            .enumerate()
+            .map(|(rank, question)| SuggestedQuestion {
+                id: format!("{}-question-{}", demo.id, rank + 1),
+                session_id: demo.id.to_string(),
+                question: question.to_string(),
+                relevance_score: 1.0 - (rank as f64 * 0.1),
+                used: false,
+                created_at: session.updated_at,
+            })
+            .collect();
+        db.save_suggested_questions(&questions).await?;
+    }
+
+    for (key, value) in DEMO_MEMORIES {
+        let mut memory = UserMemory::new(DEMO_USER_ID.to_string(), key.to_string(), value.to_string());
+        memory.id = format!("demo-memory-{}", key);
+        memory.created_at = epoch;
+        memory.updated_at = epoch;
+        db.save_memory(&memory).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+use crate::models::{SessionEvent, SyncEvent};
+
+// Events a subscriber may fall behind by before it misses some
+const CHANNEL_CAPACITY: usize = 64;
+
+// Broadcast channels by key, made when the first subscriber arrives and dropped once the
+// last one has gone
+struct Channels<E> {
+    senders: Mutex<HashMap<String, broadcast::Sender<E>>>,
+}
+
+impl<E: Clone> Default for Channels<E> {
+    fn default() -> Self {
+        Self { senders: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<E: Clone> Channels<E> {
+    fn publish(&self, key: &str, event: E) {
+        let mut senders = self.senders.lock().unwrap();
+        if let Some(sender) = senders.get(key) {
+            // Sending only fails once the last subscriber has gone, so the channel goes too
+            if sender.send(event).is_err() {
+                senders.remove(key);
+            }
+        }
+    }
+
+    fn subscribe(&self, key: &str) -> broadcast::Receiver<E> {
+        let mut senders = self.senders.lock().unwrap();
+        // Channels whose subscribers have all gone aren't kept around
+        senders.retain(|_, sender| sender.receiver_count() > 0);
+        senders.entry(key.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+}
+
+// Fans out what happens to everyone following along: a session's events to its subscribers,
+// such as a dashboard, and a user's sync events to each of their open tabs and devices.
+// Events only reach subscribers of this server process.
+#[derive(Clone, Default)]
+pub struct SessionEvents {
+    sessions: Arc<Channels<SessionEvent>>,
+    users: Arc<Channels<SyncEvent>>,
+}
+
+impl SessionEvents {
+    // Sends the event to the session's subscribers, if it has any
+    pub fn publish(&self, session_id: &str, event: SessionEvent) {
+        self.sessions.publish(session_id, event);
+    }
+
+    pub fn subscribe(&self, session_id: &str) -> broadcast::Receiver<SessionEvent> {
+        self.sessions.subscribe(session_id)
+    }
+
+    // Sends the event to every client the user has connected to the sync channel
+    pub fn publish_to_user(&self, user_id: &str, event: SyncEvent) {
+        self.users.publish(user_id, event);
+    }
+
+    pub fn subscribe_user(&self, user_id: &str) -> broadcast::Receiver<SyncEvent> {
+        self.users.subscribe(user_id)
+    }
+}
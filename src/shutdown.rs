@@ -0,0 +1,60 @@
+use std::{future::Future, time::Duration};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+// Stops the server in order: once triggered, new connections are refused, live update
+// streams end, the scheduler finishes the round it is on and background jobs record how far
+// they got. The server waits for the tasks spawned through it before the database closes.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.token.cancel();
+        self.tasks.close();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    // Resolves once shutdown has been triggered
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    // Runs a background task the server lets finish before it exits
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    // Waits for the background tasks to finish; false when some were still running at the
+    // deadline
+    pub async fn wait_for_tasks(&self, deadline: Duration) -> bool {
+        tokio::time::timeout(deadline, self.tasks.wait()).await.is_ok()
+    }
+}
+
+// Resolves when the process is asked to stop, with Ctrl+C or, on Unix, SIGTERM
+pub async fn signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM").recv().await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
@@ -0,0 +1,125 @@
+use axum::http::{HeaderName, Request};
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+// Filter used when RUST_LOG isn't set
+const DEFAULT_FILTER: &str = "info";
+// Header carrying each request's ID, taken from the client or proxy when it sends one
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    // Human-readable lines, for a terminal
+    #[default]
+    Pretty,
+    // One JSON object per line, for log collectors
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "pretty" | "text" => Some(LogFormat::Pretty),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub log_format: LogFormat,
+    // Where spans are exported over OTLP/gRPC, like `http://localhost:4317`; None keeps
+    // them to the log
+    pub otlp_endpoint: Option<String>,
+    // How the server appears in the tracing backend
+    pub service_name: String,
+}
+
+// Holds the exporter while the server runs. Dropping it at shutdown flushes the spans that
+// haven't been sent yet.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+// Installs the global subscriber: `tracing` output goes to stderr in the configured format,
+// filtered by RUST_LOG, and with an OTLP endpoint every span is exported too. A request then
+// shows as one trace, from the HTTP request through the server function down to the database
+// queries and the model provider, so a slow reply can be put down to the part that was slow.
+pub fn init(config: TelemetryConfig) -> Telemetry {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (pretty, json) = match config.log_format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer().with_writer(std::io::stderr)), None),
+        LogFormat::Json => (None, Some(tracing_subscriber::fmt::layer().json().with_writer(std::io::stderr))),
+    };
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(pretty)
+        .with(json);
+
+    #[cfg(feature = "otel")]
+    {
+        let provider = match config.otlp_endpoint.as_deref().map(|endpoint| tracer_provider(endpoint, &config.service_name)) {
+            Some(Ok(provider)) => Some(provider),
+            Some(Err(e)) => {
+                eprintln!("Warning: not exporting traces: {}", e);
+                None
+            }
+            None => None,
+        };
+        let layer = provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider as _;
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("aibot"))
+        });
+        registry.with(layer).init();
+        Telemetry { provider }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        if config.otlp_endpoint.is_some() {
+            tracing::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set but this build has no OpenTelemetry support; rebuild with the `otel` feature");
+        }
+        Telemetry {}
+    }
+}
+
+// The span each HTTP request runs in. Every log line written while handling the request
+// carries its fields, the request ID among them.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request.headers().get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!("request", method = %request.method(), uri = %request.uri().path(), request_id)
+}
+
+#[cfg(feature = "otel")]
+fn tracer_provider(endpoint: &str, service_name: &str) -> anyhow::Result<opentelemetry_sdk::trace::TracerProvider> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(opentelemetry_sdk::Resource::new([
+            opentelemetry::KeyValue::new("service.name", service_name.to_string()),
+        ]))
+        .build();
+    Ok(provider)
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                // The stderr layer of the global subscriber is still installed here
+                tracing::warn!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use crate::{api::AppState, conversation_search::message_collection, models::*};
+
+// Permanently deletes a session with its messages, search index entries and stored files
+pub async fn purge_session(state: &AppState, session: &ChatSession) -> Result<()> {
+    let message_ids = state.db.get_session_message_ids(&session.id).await?;
+    let file_paths = state.db.get_session_attachment_paths(&session.id).await?;
+
+    state.db.delete_session(&session.id).await?;
+
+    let collection = message_collection(&session.user_id);
+    for message_id in &message_ids {
+        state.vectors.delete(&collection, message_id).await?;
+    }
+    remove_unreferenced_files(state, file_paths).await
+}
+
+// Permanently deletes a single message with its search index entry and stored files
+pub async fn purge_message(state: &AppState, user_id: &str, message: &Message) -> Result<()> {
+    let file_paths = state.db.get_message_attachments(&message.id).await?
+        .into_iter()
+        .map(|attachment| attachment.file_path)
+        .collect();
+
+    state.db.delete_message(&message.id).await?;
+    state.vectors.delete(&message_collection(user_id), &message.id).await?;
+    remove_unreferenced_files(state, file_paths).await
+}
+
+// Purges everything that has been in the trash longer than `retention_days`. Returns how many items were purged.
+pub async fn purge_expired(state: &AppState, retention_days: i64) -> Result<usize> {
+    let cutoff = Utc::now() - Duration::days(retention_days);
+    let (sessions, messages) = state.db.get_expired_trash(cutoff).await?;
+
+    for session in &sessions {
+        purge_session(state, session).await?;
+    }
+    for (user_id, message) in &messages {
+        purge_message(state, user_id, message).await?;
+    }
+    Ok(sessions.len() + messages.len())
+}
+
+// A file re-attached in another message is still in use there
+async fn remove_unreferenced_files(state: &AppState, mut file_paths: Vec<String>) -> Result<()> {
+    file_paths.sort();
+    file_paths.dedup();
+    for file_path in file_paths {
+        if !state.db.is_attachment_file_referenced(&file_path).await? {
+            if let Err(e) = tokio::fs::remove_file(&file_path).await {
+                tracing::warn!("Failed to remove stored file {}: {}", file_path, e);
+            }
+        }
+    }
+    Ok(())
+}
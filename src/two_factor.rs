@@ -0,0 +1,189 @@
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use crate::{api::AppState, models::*, secrets::secret_box};
+
+const ISSUER: &str = "aibot";
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+const BACKUP_CODE_COUNT: usize = 10;
+// Backup codes are written as two groups of this many characters, like `k3vq9-x7mwa`
+const BACKUP_CODE_GROUP: usize = 5;
+const BACKUP_CODE_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+fn totp(secret: Secret, account: &str) -> Result<TOTP> {
+    let secret = secret.to_bytes().map_err(|e| anyhow::anyhow!("Invalid two-factor secret: {:?}", e))?;
+    TOTP::new(Algorithm::SHA1, DIGITS, 1, STEP_SECS, secret, Some(ISSUER.to_string()), account.to_string())
+        .map_err(|e| anyhow::anyhow!("Invalid two-factor secret: {}", e))
+}
+
+// The time step `code` is valid for, allowing a step of clock drift either way
+fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<u64> {
+    let step = now / STEP_SECS;
+    [step.saturating_sub(1), step, step + 1]
+        .into_iter()
+        .find(|step| totp.generate(step * STEP_SECS) == code)
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+fn backup_code_hash(code: &str) -> String {
+    let normalized: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+fn generate_backup_codes() -> Vec<String> {
+    (0..BACKUP_CODE_COUNT).map(|_| {
+        let mut bytes = [0u8; BACKUP_CODE_GROUP * 2];
+        OsRng.fill_bytes(&mut bytes);
+        let chars: String = bytes.iter().map(|byte| BACKUP_CODE_ALPHABET[(byte % 32) as usize] as char).collect();
+        format!("{}-{}", &chars[..BACKUP_CODE_GROUP], &chars[BACKUP_CODE_GROUP..])
+    }).collect()
+}
+
+// Stores a fresh set of backup codes and returns them; this is the only time they are shown
+async fn replace_backup_codes(state: &AppState, user_id: &str) -> Result<Vec<String>> {
+    let codes = generate_backup_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| backup_code_hash(code)).collect();
+    state.db.replace_backup_codes(user_id, &hashes).await?;
+    Ok(codes)
+}
+
+// Checks a code from the authenticator app against the user's secret. Each code is only
+// accepted once.
+async fn check_totp(state: &AppState, user_id: &str, sealed_secret: &str, code: &str) -> Result<bool> {
+    let secret = secret_box(state)?.open(sealed_secret)?;
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    match matching_step(&totp(Secret::Encoded(secret), user_id)?, code, now) {
+        Some(step) => state.db.use_two_factor_step(user_id, step as i64).await,
+        None => Ok(false),
+    }
+}
+
+pub async fn is_enabled(state: &AppState, user_id: &str) -> Result<bool> {
+    Ok(matches!(state.db.get_two_factor(user_id).await?, Some((_, true))))
+}
+
+pub async fn status(state: &AppState, user: &User) -> Result<TwoFactorStatus> {
+    Ok(TwoFactorStatus {
+        enabled: is_enabled(state, &user.id).await?,
+        backup_codes_left: state.db.count_unused_backup_codes(&user.id).await?,
+        required: user.role == Role::Admin && state.require_admin_two_factor,
+    })
+}
+
+// Creates a new secret for the user to add to their authenticator app. It isn't asked for
+// at login until `confirm_setup` sees a code generated from it.
+pub async fn start_setup(state: &AppState, user: &User) -> Result<TwoFactorSetup> {
+    let secret_box = secret_box(state)?;
+    if is_enabled(state, &user.id).await? {
+        return Err(anyhow::anyhow!("Two-factor authentication is already on"));
+    }
+
+    // Apps show the account name next to the codes; otpauth URLs can't have ':' in it
+    let account = user.email.clone().unwrap_or_else(|| user.id.clone()).replace(':', "");
+    let totp = totp(Secret::generate_secret(), &account)?;
+    state.db.set_pending_two_factor(&user.id, &secret_box.seal(&totp.get_secret_base32())?).await?;
+    Ok(TwoFactorSetup {
+        secret: totp.get_secret_base32(),
+        otpauth_url: totp.get_url(),
+        qr_code_png: totp.get_qr_base64().map_err(|e| anyhow::anyhow!("Failed to draw QR code: {}", e))?,
+    })
+}
+
+// Turns two-factor on once the user proves their app generates the right codes, and
+// returns their backup codes
+pub async fn confirm_setup(state: &AppState, user_id: &str, code: &str) -> Result<Vec<String>> {
+    let (sealed_secret, enabled) = state.db.get_two_factor(user_id).await?
+        .ok_or_else(|| anyhow::anyhow!("Start setting up two-factor authentication first"))?;
+    if enabled {
+        return Err(anyhow::anyhow!("Two-factor authentication is already on"));
+    }
+    if !check_totp(state, user_id, &sealed_secret, code.trim()).await? {
+        return Err(anyhow::anyhow!("Incorrect code"));
+    }
+
+    state.db.enable_two_factor(user_id).await?;
+    replace_backup_codes(state, user_id).await
+}
+
+// Checks a code from the authenticator app, or uses up a backup code. False when two-factor
+// isn't on, so callers must check `is_enabled` first.
+pub async fn verify(state: &AppState, user_id: &str, code: &str) -> Result<bool> {
+    let Some((sealed_secret, true)) = state.db.get_two_factor(user_id).await? else {
+        return Ok(false);
+    };
+    let code = code.trim();
+    if is_totp_code(code) {
+        check_totp(state, user_id, &sealed_secret, code).await
+    } else {
+        state.db.use_backup_code(user_id, &backup_code_hash(code)).await
+    }
+}
+
+// Turns two-factor off. Takes a current code so a stolen login session can't do it.
+pub async fn disable(state: &AppState, user_id: &str, code: &str) -> Result<()> {
+    if !verify(state, user_id, code).await? {
+        return Err(anyhow::anyhow!("Incorrect code"));
+    }
+    state.db.delete_two_factor(user_id).await
+}
+
+// Replaces the backup codes, used or not, for a current code
+pub async fn regenerate_backup_codes(state: &AppState, user_id: &str, code: &str) -> Result<Vec<String>> {
+    if !verify(state, user_id, code).await? {
+        return Err(anyhow::anyhow!("Incorrect code"));
+    }
+    replace_backup_codes(state, user_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_totp() -> TOTP {
+        totp(Secret::Raw(b"12345678901234567890".to_vec()), "user-1").unwrap()
+    }
+
+    #[test]
+    fn accepts_a_step_of_drift_either_way() {
+        let totp = test_totp();
+        let now = 1_700_000_000;
+        let step = now / STEP_SECS;
+        assert_eq!(matching_step(&totp, &totp.generate(now), now), Some(step));
+        assert_eq!(matching_step(&totp, &totp.generate(now - STEP_SECS), now), Some(step - 1));
+        assert_eq!(matching_step(&totp, &totp.generate(now + STEP_SECS), now), Some(step + 1));
+        assert_eq!(matching_step(&totp, &totp.generate(now + 3 * STEP_SECS), now), None);
+    }
+
+    #[test]
+    fn tells_app_codes_from_backup_codes() {
+        assert!(is_totp_code("123456"));
+        assert!(!is_totp_code("12345"));
+        assert!(!is_totp_code("12345a"));
+        assert!(!is_totp_code("k3vq9-x7mwa"));
+    }
+
+    #[test]
+    fn backup_codes_match_however_they_are_typed() {
+        assert_eq!(backup_code_hash("k3vq9-x7mwa"), backup_code_hash("K3VQ9 X7MWA"));
+        assert_eq!(backup_code_hash("k3vq9-x7mwa"), backup_code_hash("k3vq9x7mwa"));
+        assert_ne!(backup_code_hash("k3vq9-x7mwa"), backup_code_hash("k3vq9-x7mwb"));
+    }
+
+    #[test]
+    fn generates_distinct_backup_codes() {
+        let codes = generate_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        for code in &codes {
+            let (first, second) = code.split_once('-').unwrap();
+            assert_eq!((first.len(), second.len()), (BACKUP_CODE_GROUP, BACKUP_CODE_GROUP));
+            assert!(code.bytes().all(|byte| byte == b'-' || BACKUP_CODE_ALPHABET.contains(&byte)));
+        }
+        let distinct: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(distinct.len(), codes.len());
+    }
+}
@@ -0,0 +1,27 @@
+use anyhow::Result;
+
+#[derive(Debug, Clone, Default)]
+pub struct UsageLimits {
+    // Most knowledge base chunks (one vector each) a user may store; None is unlimited
+    pub max_kb_chunks_per_user: Option<i64>,
+}
+
+impl UsageLimits {
+    // Fails if adding `new_chunks` would take the user past their cap
+    pub fn check_kb_chunks(&self, stored_chunks: i64, new_chunks: i64) -> Result<()> {
+        match self.max_kb_chunks_per_user {
+            Some(max) if stored_chunks + new_chunks > max => Err(anyhow::anyhow!(
+                "Knowledge base quota exceeded: {} of {} chunks used, this document needs {} more",
+                stored_chunks,
+                max,
+                new_chunks
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Providers don't all report usage, so tokens are estimated at ~4 characters each
+pub fn estimate_tokens(texts: &[String]) -> i64 {
+    texts.iter().map(|text| (text.chars().count() as i64 + 3) / 4).sum()
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, Utc};
+use crate::{api::AppState, models::*};
+
+// Blended USD per million tokens. Only totals are stored per message, not the input/output
+// split providers bill separately, so costs are estimates. Matched by model name prefix.
+const PRICES_PER_MILLION_TOKENS: &[(&str, f64)] = &[
+    ("gpt-4-turbo", 20.0),
+    ("gpt-4", 45.0),
+    ("gpt-3.5-turbo", 1.0),
+    ("claude-3-opus", 45.0),
+    ("claude-3-sonnet", 9.0),
+    ("claude-3-haiku", 0.75),
+    ("gemini-pro", 1.0),
+];
+
+pub fn estimate_cost(model_provider: &str, model_name: &str, tokens: i64) -> Option<f64> {
+    // Local models cost nothing per token
+    if model_provider == AIProvider::Ollama.to_string() {
+        return Some(0.0);
+    }
+    // OpenRouter names carry a vendor prefix, e.g. "openai/gpt-4"
+    let model_name = model_name.rsplit('/').next().unwrap_or(model_name);
+    PRICES_PER_MILLION_TOKENS
+        .iter()
+        .find(|(prefix, _)| model_name.starts_with(prefix))
+        .map(|(_, price)| tokens as f64 * price / 1_000_000.0)
+}
+
+// Brings the daily rollups up to date. The newest rolled-up day may have been partial,
+// so it is rebuilt along with every day after it; earlier days are final.
+// Returns how many days were rebuilt.
+pub async fn roll_up(state: &AppState) -> Result<usize> {
+    let today = Utc::now().date_naive();
+    let start = match state.db.get_last_usage_day().await? {
+        Some(day) => day,
+        None => match state.db.get_first_message_at().await? {
+            Some(first) => first.date_naive(),
+            None => return Ok(0),
+        },
+    };
+
+    let mut day = start;
+    let mut rebuilt = 0;
+    while day <= today {
+        roll_up_day(state, day).await?;
+        rebuilt += 1;
+        day += Duration::days(1);
+    }
+    Ok(rebuilt)
+}
+
+async fn roll_up_day(state: &AppState, day: NaiveDate) -> Result<()> {
+    let rows: Vec<DailyUsage> = state.db.aggregate_usage(day).await?
+        .into_iter()
+        .map(|mut row| {
+            row.estimated_cost = estimate_cost(&row.model_provider, &row.model_name, row.tokens);
+            row
+        })
+        .collect();
+    state.db.replace_usage_day(day, &rows).await
+}
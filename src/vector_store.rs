@@ -0,0 +1,237 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::Row;
+use std::sync::Once;
+use crate::database::{on_pool, DbPool};
+
+// Collections kept in the shared index
+pub const DOCUMENTS: &str = "documents";
+pub const MESSAGES: &str = "messages";
+pub const MEMORIES: &str = "memories";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorMatch {
+    pub item_id: String,
+    pub metadata: Value,
+    // Cosine distance: 0.0 is identical, 2.0 is opposite
+    pub distance: f64,
+}
+
+// Registers sqlite-vec as an auto extension so every pooled connection can use vec0.
+// Safe to call more than once.
+pub fn register_sqlite_vec() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        rusqlite::ffi::sqlite3_auto_extension(Some(std::mem::transmute(
+            sqlite_vec::sqlite3_vec_init as *const (),
+        )));
+    });
+}
+
+// Backed by sqlite-vec on SQLite and pgvector on Postgres. Both accept vectors as a
+// JSON-style array literal, so only the index DDL and the KNN query differ.
+#[derive(Clone)]
+pub struct VectorStore {
+    pool: DbPool,
+    dimensions: usize,
+}
+
+impl VectorStore {
+    pub async fn new(pool: DbPool, dimensions: usize) -> Result<Self> {
+        let store = Self { pool, dimensions };
+        store.ensure_index().await?;
+        Ok(store)
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn ensure_index(&self) -> Result<()> {
+        let existing: Option<String> = on_pool!(&self.pool, pool => {
+            sqlx::query("SELECT value FROM vector_store_meta WHERE key = 'dimensions'")
+                .fetch_optional(pool)
+                .await?
+                .map(|row| row.get("value"))
+        });
+
+        match existing {
+            Some(value) if value != self.dimensions.to_string() => {
+                return Err(anyhow::anyhow!(
+                    "Vector index was built with {} dimensions but {} are configured; re-index before switching embedding models",
+                    value,
+                    self.dimensions
+                ));
+            }
+            Some(_) => {}
+            None => {
+                on_pool!(&self.pool, pool => {
+                    sqlx::query("INSERT INTO vector_store_meta (key, value) VALUES ('dimensions', $1)")
+                        .bind(self.dimensions.to_string())
+                        .execute(pool)
+                        .await?;
+                });
+            }
+        }
+
+        match &self.pool {
+            DbPool::Sqlite(pool) => {
+                sqlx::query(&format!(
+                    "CREATE VIRTUAL TABLE IF NOT EXISTS vec_embeddings USING vec0(collection TEXT PARTITION KEY, embedding FLOAT[{}] distance_metric=cosine)",
+                    self.dimensions
+                ))
+                .execute(pool)
+                .await?;
+            }
+            DbPool::Postgres(pool) => {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS vec_embeddings (rowid BIGINT PRIMARY KEY REFERENCES vector_entries(rowid), collection TEXT NOT NULL, embedding vector({}) NOT NULL)",
+                    self.dimensions
+                ))
+                .execute(pool)
+                .await?;
+                sqlx::query("CREATE INDEX IF NOT EXISTS idx_vec_embeddings_hnsw ON vec_embeddings USING hnsw (embedding vector_cosine_ops)")
+                    .execute(pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn upsert(&self, collection: &str, item_id: &str, embedding: &[f32], metadata: &Value) -> Result<()> {
+        self.check_dimensions(embedding)?;
+        let insert_vector = match &self.pool {
+            DbPool::Sqlite(_) => "INSERT INTO vec_embeddings (rowid, collection, embedding) VALUES ($1, $2, $3)",
+            DbPool::Postgres(_) => "INSERT INTO vec_embeddings (rowid, collection, embedding) VALUES ($1, $2, $3::vector)",
+        };
+
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+
+            let existing: Option<i64> = sqlx::query("SELECT rowid FROM vector_entries WHERE collection = $1 AND item_id = $2")
+                .bind(collection)
+                .bind(item_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(|row| row.get("rowid"));
+
+            let rowid: i64 = match existing {
+                Some(rowid) => {
+                    // vec0 rows can't be updated in place, so replace the vector
+                    sqlx::query("DELETE FROM vec_embeddings WHERE rowid = $1")
+                        .bind(rowid)
+                        .execute(&mut *tx)
+                        .await?;
+                    sqlx::query("UPDATE vector_entries SET metadata = $1, updated_at = CURRENT_TIMESTAMP WHERE rowid = $2")
+                        .bind(metadata.to_string())
+                        .bind(rowid)
+                        .execute(&mut *tx)
+                        .await?;
+                    rowid
+                }
+                None => {
+                    sqlx::query("INSERT INTO vector_entries (collection, item_id, metadata) VALUES ($1, $2, $3) RETURNING rowid")
+                        .bind(collection)
+                        .bind(item_id)
+                        .bind(metadata.to_string())
+                        .fetch_one(&mut *tx)
+                        .await?
+                        .get("rowid")
+                }
+            };
+
+            sqlx::query(insert_vector)
+                .bind(rowid)
+                .bind(collection)
+                .bind(to_vector_literal(embedding))
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    pub async fn query(&self, collection: &str, embedding: &[f32], k: usize) -> Result<Vec<VectorMatch>> {
+        self.check_dimensions(embedding)?;
+        let sql = match &self.pool {
+            DbPool::Sqlite(_) => {
+                "SELECT e.item_id, e.metadata, v.distance
+                 FROM vec_embeddings v
+                 JOIN vector_entries e ON e.rowid = v.rowid
+                 WHERE v.embedding MATCH $1 AND v.k = $2 AND v.collection = $3
+                 ORDER BY v.distance"
+            }
+            DbPool::Postgres(_) => {
+                "SELECT e.item_id, e.metadata, v.embedding <=> $1::vector AS distance
+                 FROM vec_embeddings v
+                 JOIN vector_entries e ON e.rowid = v.rowid
+                 WHERE v.collection = $3
+                 ORDER BY distance
+                 LIMIT $2"
+            }
+        };
+
+        Ok(on_pool!(&self.pool, pool => {
+            sqlx::query(sql)
+                .bind(to_vector_literal(embedding))
+                .bind(k as i64)
+                .bind(collection)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| VectorMatch {
+                    item_id: row.get("item_id"),
+                    metadata: serde_json::from_str(row.get::<&str, _>("metadata")).unwrap_or(Value::Null),
+                    distance: row.get("distance"),
+                })
+                .collect()
+        }))
+    }
+
+    pub async fn delete(&self, collection: &str, item_id: &str) -> Result<()> {
+        on_pool!(&self.pool, pool => {
+            let mut tx = pool.begin().await?;
+
+            let rowid: Option<i64> = sqlx::query("SELECT rowid FROM vector_entries WHERE collection = $1 AND item_id = $2")
+                .bind(collection)
+                .bind(item_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(|row| row.get("rowid"));
+
+            if let Some(rowid) = rowid {
+                sqlx::query("DELETE FROM vec_embeddings WHERE rowid = $1")
+                    .bind(rowid)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("DELETE FROM vector_entries WHERE rowid = $1")
+                    .bind(rowid)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+        });
+        Ok(())
+    }
+
+    fn check_dimensions(&self, embedding: &[f32]) -> Result<()> {
+        if embedding.len() != self.dimensions {
+            return Err(anyhow::anyhow!(
+                "Expected a {}-dimensional embedding, got {}",
+                self.dimensions,
+                embedding.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// "[0.1,0.2,...]", which sqlite-vec parses as JSON and pgvector casts to `vector`
+fn to_vector_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::{api::AppState, models::*, secrets::secret_box};
+
+// Minutes to wait before each retry of a failed delivery. A delivery that still fails after
+// the last one is marked failed.
+const RETRY_DELAYS_MINUTES: [i64; 5] = [1, 5, 30, 120, 720];
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+// Deliveries retried per scheduler tick
+const RETRY_BATCH_SIZE: i64 = 50;
+// Finished deliveries are kept in the log this long
+const DELIVERY_LOG_DAYS: i64 = 30;
+// Longest error kept in the delivery log
+const MAX_ERROR_LENGTH: usize = 500;
+
+pub const SIGNATURE_HEADER: &str = "X-Aibot-Signature";
+pub const EVENT_HEADER: &str = "X-Aibot-Event";
+pub const DELIVERY_HEADER: &str = "X-Aibot-Delivery";
+
+// Registers a webhook for the given events. Its signing secret is returned only this once.
+pub async fn create(state: &AppState, url: &str, events: &[String]) -> Result<NewWebhook> {
+    let url = url.trim();
+    let parsed = reqwest::Url::parse(url).map_err(|_| anyhow::anyhow!("Invalid webhook URL"))?;
+    crate::outbound::check_url(&parsed).await?;
+    let events = events.iter()
+        .map(|event| WebhookEvent::parse(event).ok_or_else(|| anyhow::anyhow!("Unknown webhook event {}", event)))
+        .collect::<Result<Vec<_>>>()?;
+    if events.is_empty() {
+        return Err(anyhow::anyhow!("Choose at least one event"));
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let secret = format!("whsec_{}", bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let webhook = Webhook {
+        id: uuid::Uuid::new_v4().to_string(),
+        url: url.to_string(),
+        events,
+        created_at: Utc::now(),
+    };
+    state.db.create_webhook(&webhook, &secret_box(state)?.seal(&secret)?).await?;
+    Ok(NewWebhook { webhook, secret })
+}
+
+// `t=<unix time>,v1=<hex HMAC-SHA256 of "<unix time>.<body>">`; receivers check it with the
+// webhook's secret and reject old timestamps to stop replays
+fn signature(secret: &str, timestamp: i64, body: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Invalid webhook secret: {}", e))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!("t={},v1={}", timestamp, digest))
+}
+
+// Queues `event` for every webhook subscribed to it and makes the first attempts in the
+// background. Failing to queue is only logged, so a webhook problem never fails the request
+// that caused the event.
+pub async fn emit(state: &AppState, event: WebhookEvent, data: serde_json::Value) {
+    if let Err(e) = enqueue(state, event, data).await {
+        tracing::error!("Failed to queue {} webhooks: {}", event, e);
+    }
+}
+
+async fn enqueue(state: &AppState, event: WebhookEvent, data: serde_json::Value) -> Result<()> {
+    let webhooks: Vec<Webhook> = state.db.get_webhooks().await?
+        .into_iter()
+        .filter(|webhook| webhook.events.contains(&event))
+        .collect();
+    let now = Utc::now();
+    for webhook in webhooks {
+        let id = uuid::Uuid::new_v4().to_string();
+        let payload = serde_json::json!({
+            "id": id,
+            "event": event.to_string(),
+            "created_at": now,
+            "data": data,
+        });
+        let delivery = WebhookDelivery {
+            id,
+            webhook_id: webhook.id,
+            event: event.to_string(),
+            payload: payload.to_string(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            response_status: None,
+            error: None,
+            // The scheduler picks the delivery up from here if the first attempt never
+            // finishes, say because the server stopped
+            next_attempt_at: Some(now + Duration::minutes(RETRY_DELAYS_MINUTES[0])),
+            created_at: now,
+            delivered_at: None,
+        };
+        state.db.create_webhook_delivery(&delivery).await?;
+
+        let state = state.clone();
+        state.shutdown.clone().spawn(async move {
+            if let Err(e) = attempt(&state, delivery).await {
+                tracing::error!("Failed to record webhook delivery: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// POSTs the delivery once and records how it went, scheduling the next retry on failure
+async fn attempt(state: &AppState, mut delivery: WebhookDelivery) -> Result<()> {
+    let Some((url, sealed_secret)) = state.db.get_webhook_target(&delivery.webhook_id).await? else {
+        // The webhook was deleted, and its deliveries with it
+        return Ok(());
+    };
+    let now = Utc::now();
+    let outcome = send(state, &url, &sealed_secret, &delivery, now).await;
+
+    delivery.attempts += 1;
+    match outcome {
+        Ok(status) if (200..300).contains(&status) => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.response_status = Some(status as i32);
+            delivery.error = None;
+            delivery.next_attempt_at = None;
+            delivery.delivered_at = Some(now);
+        }
+        result => {
+            let (response_status, error) = match result {
+                Ok(status) => (Some(status as i32), format!("Endpoint answered {}", status)),
+                Err(e) => (None, e.to_string().chars().take(MAX_ERROR_LENGTH).collect()),
+            };
+            delivery.response_status = response_status;
+            delivery.error = Some(error);
+            delivery.next_attempt_at = next_attempt_at(delivery.attempts, now);
+            if delivery.next_attempt_at.is_none() {
+                delivery.status = DeliveryStatus::Failed;
+                tracing::warn!("Gave up on webhook delivery {} after {} attempts", delivery.id, delivery.attempts);
+            }
+        }
+    }
+    state.db.update_webhook_delivery(&delivery).await
+}
+
+// When to retry after `attempts` failures; None once the retries are used up
+fn next_attempt_at(attempts: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    RETRY_DELAYS_MINUTES.get(attempts as usize - 1).map(|minutes| now + Duration::minutes(*minutes))
+}
+
+async fn send(state: &AppState, url: &str, sealed_secret: &str, delivery: &WebhookDelivery, now: DateTime<Utc>) -> Result<u16> {
+    let secret = secret_box(state)?.open(sealed_secret)?;
+    let response = crate::outbound::client()
+        .post(url)
+        .timeout(std::time::Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature(&secret, now.timestamp(), &delivery.payload)?)
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, &delivery.id)
+        .body(delivery.payload.clone())
+        .send()
+        .await?;
+    Ok(response.status().as_u16())
+}
+
+// Retries deliveries that are due, and trims the delivery log. Called by the scheduler.
+pub async fn retry_due(state: &AppState) -> Result<()> {
+    for delivery in state.db.get_due_webhook_deliveries(Utc::now(), RETRY_BATCH_SIZE).await? {
+        attempt(state, delivery).await?;
+    }
+    state.db.delete_webhook_deliveries_before(Utc::now() - Duration::days(DELIVERY_LOG_DAYS)).await?;
+    Ok(())
+}